@@ -0,0 +1,68 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use merx::{
+    order::{Order, OrderPrice, OrderSide},
+    orderbook::Orderbook,
+};
+
+const LEVELS: u64 = 10_000;
+
+// the book alone, without the engine around it: asks one order per level 10,000 levels deep, each bid sweeps the next
+// 10 of them
+fn deep_book() -> Orderbook {
+    let mut orderbook = Orderbook::default();
+    for order_id in 1..=LEVELS {
+        let price = OrderPrice::new(100_000 + order_id, 2);
+        let ask = Order::limit_order(order_id.into(), OrderSide::Ask, 10.into(), price);
+        orderbook.handle_create(ask).unwrap();
+    }
+    orderbook
+}
+
+fn sweeps() -> Vec<Order> {
+    (1..=LEVELS / 10)
+        .map(|i| {
+            let price = OrderPrice::new(100_000 + i * 10, 2);
+            Order::limit_order((LEVELS + i).into(), OrderSide::Bid, 100.into(), price)
+        })
+        .collect()
+}
+
+// limit orders resting deep in the book, then walking down to it (every level is compared on the way)
+fn deep_inserts() -> Vec<Order> {
+    (1..=LEVELS)
+        .map(|i| {
+            let price = OrderPrice::new(100_000 - i % 5_000, 2);
+            Order::limit_order((LEVELS + i).into(), OrderSide::Bid, 10.into(), price)
+        })
+        .collect()
+}
+
+fn bench_orders(c: &mut Criterion, name: &str, orders: Vec<Order>) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(orders.len() as u64));
+    group.sample_size(20);
+    group.bench_function("handle_create", |b| {
+        b.iter_batched(
+            || (deep_book(), orders.clone()),
+            |(mut orderbook, orders)| {
+                for order in orders {
+                    let _ = black_box(orderbook.handle_create(order));
+                }
+                orderbook
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+pub fn sweep_deep_book(c: &mut Criterion) {
+    bench_orders(c, "sweep_deep_book", sweeps());
+}
+
+pub fn rest_in_deep_book(c: &mut Criterion) {
+    bench_orders(c, "rest_in_deep_book", deep_inserts());
+}
+
+criterion_group!(benches, sweep_deep_book, rest_in_deep_book);
+criterion_main!(benches);
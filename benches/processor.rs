@@ -1,8 +1,15 @@
-use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use compact_str::format_compact;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
 use merx::{
     engine::Engine,
-    order::util::{generate, DEFAULT_PAIR},
+    order::{
+        util::{generate, generate_with, GeneratorConfig, DEFAULT_SYMBOL},
+        OrderRequest, OrderSide,
+    },
 };
+use rust_decimal::Decimal;
+
+const WORKLOAD: u64 = 10_000;
 
 pub fn process(c: &mut Criterion) {
     let range = 1..;
@@ -10,7 +17,7 @@ pub fn process(c: &mut Criterion) {
 
     c.bench_function("process", |b| {
         b.iter_batched(
-            || Engine::new(DEFAULT_PAIR),
+            || Engine::new(DEFAULT_SYMBOL),
             |mut engine| {
                 let order_request = black_box(orders.next().unwrap());
                 black_box(engine.process(order_request))
@@ -20,5 +27,136 @@ pub fn process(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, process);
+// bids and asks 100 levels deep each side of a 10 wide spread, nothing trades
+fn inserts() -> Vec<OrderRequest> {
+    (1..=WORKLOAD)
+        .map(|order_id| {
+            let (side, price) = match order_id % 2 {
+                0 => (OrderSide::Bid, 1_000 - (order_id % 100) as u32),
+                _ => (OrderSide::Ask, 1_010 + (order_id % 100) as u32),
+            };
+            OrderRequest::Create {
+                account_id: format_compact!("{}", order_id % 9),
+                order_id,
+                pair: DEFAULT_SYMBOL,
+                side,
+                limit_price: Some(price.into()),
+                quantity: 10.into(),
+                time_in_force: None,
+                short_sell: false,
+                priority_fee: Decimal::ZERO,
+                client_order_id: None,
+                price_protection: None,
+                reduce_only: false,
+            }
+        })
+        .collect()
+}
+
+fn cancels() -> Vec<OrderRequest> {
+    (1..=WORKLOAD)
+        .map(|order_id| OrderRequest::Cancel {
+            account_id: format_compact!("{}", order_id % 9),
+            order_id,
+        })
+        .collect()
+}
+
+// a single price level, the newest orders are cancelled first (the ones furthest from the front of the queue)
+fn deep_level() -> (Vec<OrderRequest>, Vec<OrderRequest>) {
+    let inserts = (1..=WORKLOAD)
+        .map(|order_id| OrderRequest::Create {
+            account_id: format_compact!("{order_id}"),
+            order_id,
+            pair: DEFAULT_SYMBOL,
+            side: OrderSide::Ask,
+            limit_price: Some(1_000.into()),
+            quantity: 10.into(),
+            time_in_force: None,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: None,
+            price_protection: None,
+            reduce_only: false,
+        })
+        .collect();
+    let cancels = (1..=WORKLOAD)
+        .rev()
+        .map(|order_id| OrderRequest::Cancel {
+            account_id: format_compact!("{order_id}"),
+            order_id,
+        })
+        .collect();
+    (inserts, cancels)
+}
+
+// a narrow price range and no cancels, most orders trade on arrival
+fn matches() -> Vec<OrderRequest> {
+    let config = GeneratorConfig {
+        cancel_ratio: 0.0,
+        price_range: 10_000..10_100,
+        ..Default::default()
+    };
+    generate_with(config, 1..=WORKLOAD as usize).collect()
+}
+
+// every scenario is run request by request and as a single batch, throughput is reported in requests per second
+fn bench_workload(c: &mut Criterion, name: &str, setup: impl Fn() -> Engine, order_requests: Vec<OrderRequest>) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(order_requests.len() as u64));
+    group.sample_size(20);
+
+    group.bench_function("process", |b| {
+        b.iter_batched(
+            || (setup(), order_requests.clone()),
+            |(mut engine, order_requests)| {
+                for order_request in order_requests {
+                    let _ = black_box(engine.process(order_request));
+                }
+                engine
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.bench_function("process_batch", |b| {
+        b.iter_batched(
+            || (setup(), order_requests.clone()),
+            |(mut engine, order_requests)| {
+                let _ = black_box(engine.process_batch(order_requests));
+                engine
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+pub fn insert(c: &mut Criterion) {
+    bench_workload(c, "insert", || Engine::new(DEFAULT_SYMBOL), inserts());
+}
+
+pub fn cancel(c: &mut Criterion) {
+    let setup = || {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        engine.process_batch(inserts()).unwrap();
+        engine
+    };
+    bench_workload(c, "cancel", setup, cancels());
+}
+
+pub fn cancel_deep_level(c: &mut Criterion) {
+    let (inserts, cancels) = deep_level();
+    let setup = || {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        engine.process_batch(inserts.clone()).unwrap();
+        engine
+    };
+    bench_workload(c, "cancel_deep_level", setup, cancels);
+}
+
+pub fn match_heavy(c: &mut Criterion) {
+    bench_workload(c, "match_heavy", || Engine::new(DEFAULT_SYMBOL), matches());
+}
+
+criterion_group!(benches, process, insert, cancel, cancel_deep_level, match_heavy);
 criterion_main!(benches);
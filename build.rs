@@ -0,0 +1,8 @@
+// the gRPC service is generated from its proto definition, with a vendored protoc so that none has to be installed
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_prost_build::compile_protos("proto/merx.proto").unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ledger::EXCHANGE_ACCOUNT,
+    order::{OrderId, OrderPrice, OrderQuantity, OrderSide},
+};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Balance {
+    pub available: Decimal,
+    pub locked: Decimal, // reserved by open orders
+}
+
+impl Balance {
+    #[inline]
+    pub fn total(&self) -> Decimal {
+        self.available + self.locked
+    }
+}
+
+// funds reserved by an order: base for asks, quote for bids
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Lock {
+    account_id: CompactString,
+    side: OrderSide,
+    base: CompactString,
+    quote: CompactString,
+    amount: Decimal,
+}
+
+impl Lock {
+    #[inline]
+    fn asset(&self) -> &CompactString {
+        match self.side {
+            OrderSide::Ask => &self.base,
+            OrderSide::Bid => &self.quote,
+        }
+    }
+}
+
+// balances by account and asset, funds move from available to locked while they back an open order
+#[derive(Debug, Default)]
+pub struct AccountManager {
+    balances: IndexMap<(CompactString, CompactString), Balance>,
+    locks: IndexMap<OrderId, Lock>,
+}
+
+impl AccountManager {
+    pub fn deposit(&mut self, account_id: &str, asset: &str, amount: Decimal) -> Result<(), AccountError> {
+        if amount <= Decimal::ZERO {
+            return Err(AccountError::NonPositiveAmount(amount));
+        }
+
+        self.balance_mut(account_id, asset).available += amount;
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, account_id: &str, asset: &str, amount: Decimal) -> Result<(), AccountError> {
+        if amount <= Decimal::ZERO {
+            return Err(AccountError::NonPositiveAmount(amount));
+        }
+
+        let balance = self.balance_mut(account_id, asset);
+        if balance.available < amount {
+            return Err(AccountError::InsufficientFunds {
+                account_id: account_id.into(),
+                asset: asset.into(),
+                required: amount,
+                available: balance.available,
+            });
+        }
+        balance.available -= amount;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn balance(&self, account_id: &str, asset: &str) -> Balance {
+        self.balances
+            .get(&(account_id.into(), asset.into()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn balance_mut(&mut self, account_id: &str, asset: &str) -> &mut Balance {
+        self.balances.entry((account_id.into(), asset.into())).or_default()
+    }
+
+    // asks lock the base quantity, bids lock the quote amount (quantity x price)
+    pub fn lock(
+        &mut self,
+        order_id: OrderId,
+        account_id: &str,
+        side: OrderSide,
+        (base, quote): (&str, &str),
+        amount: Decimal,
+    ) -> Result<(), AccountError> {
+        if self.locks.contains_key(&order_id) {
+            return Err(AccountError::LockDuplicated(order_id));
+        }
+
+        let asset = match side {
+            OrderSide::Ask => base,
+            OrderSide::Bid => quote,
+        };
+        let balance = self.balance_mut(account_id, asset);
+        if balance.available < amount {
+            return Err(AccountError::InsufficientFunds {
+                account_id: account_id.into(),
+                asset: asset.into(),
+                required: amount,
+                available: balance.available,
+            });
+        }
+        balance.available -= amount;
+        balance.locked += amount;
+
+        self.locks.insert(
+            order_id,
+            Lock {
+                account_id: account_id.into(),
+                side,
+                base: base.into(),
+                quote: quote.into(),
+                amount,
+            },
+        );
+        Ok(())
+    }
+
+    // the locked funds pay for the fill, the other asset is credited
+    pub fn fill(&mut self, order_id: OrderId, quantity: OrderQuantity, price: OrderPrice) -> Result<(), AccountError> {
+        let lock = self
+            .locks
+            .get_mut(&order_id)
+            .ok_or(AccountError::LockNotFound(order_id))?;
+        let (paid, received) = match lock.side {
+            OrderSide::Ask => (quantity.value(), quantity * price),
+            OrderSide::Bid => (quantity * price, quantity.value()),
+        };
+        lock.amount -= paid;
+
+        let lock = lock.clone();
+        let (credited, debited) = match lock.side {
+            OrderSide::Ask => (&lock.quote, &lock.base),
+            OrderSide::Bid => (&lock.base, &lock.quote),
+        };
+        self.balance_mut(&lock.account_id, debited).locked -= paid;
+        self.balance_mut(&lock.account_id, credited).available += received;
+        Ok(())
+    }
+
+    // what is left of the lock (e.g. cancel, or a bid filled below its limit price) is available again
+    pub fn release(&mut self, order_id: OrderId) -> Option<Decimal> {
+        let lock = self.locks.swap_remove(&order_id)?;
+        let balance = self.balance_mut(&lock.account_id, lock.asset());
+        balance.locked -= lock.amount;
+        balance.available += lock.amount;
+        Some(lock.amount)
+    }
+
+    // delivery versus payment of a trade agreed outside the book: both legs are checked before anything moves
+    pub fn settle(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        (base, quote): (&str, &str),
+        quantity: OrderQuantity,
+        price: OrderPrice,
+    ) -> Result<(), AccountError> {
+        let notional = quantity * price;
+        for (account_id, asset, required) in [(buyer, quote, notional), (seller, base, quantity.value())] {
+            let available = self.balance(account_id, asset).available;
+            if available < required {
+                return Err(AccountError::InsufficientFunds {
+                    account_id: account_id.into(),
+                    asset: asset.into(),
+                    required,
+                    available,
+                });
+            }
+        }
+
+        self.balance_mut(buyer, quote).available -= notional;
+        self.balance_mut(seller, quote).available += notional;
+        self.balance_mut(seller, base).available -= quantity.value();
+        self.balance_mut(buyer, base).available += quantity.value();
+        Ok(())
+    }
+
+    // undoes a settled trade (e.g. busted), like fees it may leave a balance negative
+    pub fn reverse(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        (base, quote): (&str, &str),
+        quantity: OrderQuantity,
+        price: OrderPrice,
+    ) {
+        let notional = quantity * price;
+        self.balance_mut(buyer, base).available -= quantity.value();
+        self.balance_mut(seller, base).available += quantity.value();
+        self.balance_mut(seller, quote).available -= notional;
+        self.balance_mut(buyer, quote).available += notional;
+    }
+
+    // fees are taken from the available balance even if it goes negative (it is already traded), rebates are credited
+    pub fn charge(&mut self, account_id: &str, asset: &str, fee: Decimal) {
+        self.balance_mut(account_id, asset).available -= fee;
+        self.balance_mut(EXCHANGE_ACCOUNT, asset).available += fee;
+    }
+
+    #[inline]
+    pub fn locked(&self, order_id: OrderId) -> Option<Decimal> {
+        self.locks.get(&order_id).map(|lock| lock.amount)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AccountError {
+    #[error("amount must be positive! {0}")]
+    NonPositiveAmount(Decimal),
+    #[error("insufficient funds (account_id={}, asset={}, required={}, available={})", .account_id, .asset, .required, .available)]
+    InsufficientFunds {
+        account_id: CompactString,
+        asset: CompactString,
+        required: Decimal,
+        available: Decimal,
+    },
+    #[error("funds already locked for the order! {0}")]
+    LockDuplicated(OrderId),
+    #[error("no funds locked for the order! {0}")]
+    LockNotFound(OrderId),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    const PAIR: (&str, &str) = ("ETH", "USDT");
+
+    #[fixture]
+    fn accounts() -> AccountManager {
+        let mut accounts = AccountManager::default();
+        accounts.deposit("1", "USDT", 1_000.into()).unwrap();
+        accounts.deposit("2", "ETH", 10.into()).unwrap();
+        accounts
+    }
+
+    #[rstest]
+    fn lock_fill_and_release(mut accounts: AccountManager) {
+        // a bid for 5 at 150 and an ask for 5, filled for 4 at 140
+        accounts.lock(1.into(), "1", OrderSide::Bid, PAIR, 750.into()).unwrap();
+        accounts.lock(2.into(), "2", OrderSide::Ask, PAIR, 5.into()).unwrap();
+        assert_eq!(
+            accounts.balance("1", "USDT"),
+            Balance {
+                available: 250.into(),
+                locked: 750.into()
+            }
+        );
+
+        accounts.fill(1.into(), 4.into(), 140.into()).unwrap();
+        accounts.fill(2.into(), 4.into(), 140.into()).unwrap();
+        assert_eq!(accounts.balance("1", "ETH").available, Decimal::from(4));
+        assert_eq!(accounts.balance("2", "USDT").available, Decimal::from(560));
+        assert_eq!(accounts.locked(1.into()), Some(190.into()));
+
+        // the rest of both locks is available again once the orders are gone
+        assert_eq!(accounts.release(1.into()), Some(190.into()));
+        assert_eq!(accounts.release(2.into()), Some(1.into()));
+        assert_eq!(accounts.balance("1", "USDT").total(), Decimal::from(440));
+        assert_eq!(accounts.balance("2", "ETH").available, Decimal::from(6));
+        assert_eq!(accounts.release(2.into()), None);
+    }
+
+    #[rstest]
+    fn reject_insufficient_funds(mut accounts: AccountManager) {
+        assert_eq!(
+            accounts.lock(1.into(), "2", OrderSide::Ask, PAIR, 11.into()),
+            Err(AccountError::InsufficientFunds {
+                account_id: "2".into(),
+                asset: "ETH".into(),
+                required: 11.into(),
+                available: 10.into()
+            })
+        );
+        assert_eq!(accounts.balance("2", "ETH").locked, Decimal::ZERO);
+        assert!(accounts.withdraw("1", "USDT", 1_001.into()).is_err());
+    }
+}
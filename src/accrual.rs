@@ -0,0 +1,219 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::clock::{Clock, Timestamp};
+
+// a period is identified by its schedule and its index (0 = the period starting at the anchor)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccrualPeriod {
+    pub schedule_id: CompactString,
+    pub index: u64,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct AccrualSchedule {
+    anchor: Timestamp,
+    interval: u64,
+}
+
+impl AccrualSchedule {
+    #[inline]
+    fn period(&self, schedule_id: &str, index: u64) -> AccrualPeriod {
+        let start = self.anchor + index * self.interval;
+        AccrualPeriod {
+            schedule_id: schedule_id.into(),
+            index,
+            start,
+            end: start + self.interval,
+        }
+    }
+
+    // number of periods already finished at `now`
+    #[inline]
+    fn elapsed(&self, now: Timestamp) -> u64 {
+        now.saturating_sub(self.anchor) / self.interval
+    }
+}
+
+// keeps track of the periods already applied so a period is never applied twice (e.g. after a restart)
+pub trait AccrualLog {
+    // index of the next period to apply for the schedule (0 if none has been applied yet)
+    fn next_index(&self, schedule_id: &str) -> u64;
+
+    fn record(&mut self, period: &AccrualPeriod);
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryAccrualLog(IndexMap<CompactString, u64>);
+
+impl AccrualLog for InMemoryAccrualLog {
+    fn next_index(&self, schedule_id: &str) -> u64 {
+        self.0.get(schedule_id).copied().unwrap_or_default()
+    }
+
+    fn record(&mut self, period: &AccrualPeriod) {
+        let next_index = self.0.entry(period.schedule_id.clone()).or_default();
+        *next_index = (*next_index).max(period.index + 1);
+    }
+}
+
+#[derive(Default)]
+pub struct AccrualScheduler {
+    schedules: IndexMap<CompactString, AccrualSchedule>,
+}
+
+impl AccrualScheduler {
+    pub fn add(&mut self, schedule_id: &str, anchor: Timestamp, interval: u64) -> Result<(), AccrualError> {
+        if interval == 0 {
+            return Err(AccrualError::InvalidInterval(schedule_id.into()));
+        }
+        if self.schedules.contains_key(schedule_id) {
+            return Err(AccrualError::ScheduleDuplicated(schedule_id.into()));
+        }
+
+        self.schedules
+            .insert(schedule_id.into(), AccrualSchedule { anchor, interval });
+        Ok(())
+    }
+
+    #[inline]
+    pub fn remove(&mut self, schedule_id: &str) -> bool {
+        self.schedules.shift_remove(schedule_id).is_some()
+    }
+
+    // all the finished periods not applied yet, oldest first (after a downtime every missed period is returned)
+    pub fn due(&self, now: Timestamp, log: &impl AccrualLog) -> Vec<AccrualPeriod> {
+        let mut due: Vec<AccrualPeriod> = self
+            .schedules
+            .iter()
+            .flat_map(|(schedule_id, schedule)| {
+                (log.next_index(schedule_id)..schedule.elapsed(now)).map(|index| schedule.period(schedule_id, index))
+            })
+            .collect();
+        due.sort_by_key(|period| period.end);
+        due
+    }
+
+    // apply every due period in order, a period is recorded only once it has been applied successfully
+    pub fn run<E>(
+        &self,
+        clock: &dyn Clock,
+        log: &mut impl AccrualLog,
+        mut apply: impl FnMut(&AccrualPeriod) -> Result<(), E>,
+    ) -> Result<usize, E> {
+        let due = self.due(clock.now(), log);
+        for period in &due {
+            apply(period)?;
+            log.record(period);
+        }
+
+        Ok(due.len())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AccrualError {
+    #[error("accrual interval must be greater than zero! {0}")]
+    InvalidInterval(CompactString),
+    #[error("a schedule with the same ID already exists! {0}")]
+    ScheduleDuplicated(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::clock::{ManualClock, HOUR};
+
+    #[fixture]
+    fn scheduler() -> AccrualScheduler {
+        let mut scheduler = AccrualScheduler::default();
+        scheduler.add("funding", 0, 8 * HOUR).unwrap();
+        scheduler.add("interest", 0, HOUR).unwrap();
+        scheduler
+    }
+
+    fn applied(scheduler: &AccrualScheduler, clock: &ManualClock, log: &mut InMemoryAccrualLog) -> Vec<AccrualPeriod> {
+        let mut applied = vec![];
+        scheduler
+            .run(clock, log, |period| -> Result<(), ()> {
+                applied.push(period.clone());
+                Ok(())
+            })
+            .unwrap();
+        applied
+    }
+
+    #[rstest]
+    fn reject_invalid_schedules(mut scheduler: AccrualScheduler) {
+        assert_eq!(
+            scheduler.add("other", 0, 0),
+            Err(AccrualError::InvalidInterval("other".into()))
+        );
+        assert_eq!(
+            scheduler.add("funding", 0, HOUR),
+            Err(AccrualError::ScheduleDuplicated("funding".into()))
+        );
+    }
+
+    #[rstest]
+    fn catch_up_after_downtime(scheduler: AccrualScheduler) {
+        let clock = ManualClock::new(0);
+        let mut log = InMemoryAccrualLog::default();
+
+        // nothing is due before the end of the first period
+        assert!(applied(&scheduler, &clock, &mut log).is_empty());
+
+        clock.set(HOUR);
+        let periods = applied(&scheduler, &clock, &mut log);
+        assert_eq!(periods.len(), 1);
+        assert_eq!((periods[0].schedule_id.as_str(), periods[0].index), ("interest", 0));
+
+        // after a downtime of 16 hours, every missed period is applied in order
+        clock.advance(16 * HOUR);
+        let periods = applied(&scheduler, &clock, &mut log);
+        assert_eq!(periods.len(), 16 + 2);
+        assert!(periods.windows(2).all(|pair| pair[0].end <= pair[1].end));
+        assert_eq!(
+            periods.iter().filter(|period| period.schedule_id == "funding").count(),
+            2
+        );
+    }
+
+    #[rstest]
+    fn apply_each_period_once(scheduler: AccrualScheduler) {
+        let clock = ManualClock::new(24 * HOUR);
+        let mut log = InMemoryAccrualLog::default();
+
+        assert_eq!(applied(&scheduler, &clock, &mut log).len(), 24 + 3);
+
+        // running again (or with a new scheduler, e.g. after a restart) does not apply anything again
+        assert!(applied(&scheduler, &clock, &mut log).is_empty());
+        let restarted = self::scheduler();
+        assert!(applied(&restarted, &clock, &mut log).is_empty());
+    }
+
+    #[rstest]
+    fn failed_period_is_retried(scheduler: AccrualScheduler) {
+        let clock = ManualClock::new(2 * HOUR);
+        let mut log = InMemoryAccrualLog::default();
+
+        // the second period fails, hence only the first one is recorded
+        let result = scheduler.run(
+            &clock,
+            &mut log,
+            |period| if period.index == 1 { Err(()) } else { Ok(()) },
+        );
+        assert_eq!(result, Err(()));
+        assert_eq!(log.next_index("interest"), 1);
+
+        let periods = applied(&scheduler, &clock, &mut log);
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].index, 1);
+    }
+}
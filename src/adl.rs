@@ -0,0 +1,216 @@
+use std::cmp::Reverse;
+
+use compact_str::CompactString;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    insurance::Waterfall,
+    order::{OrderPrice, OrderQuantity, OrderSide},
+    position::{Position, PositionBook},
+};
+
+// sent to every account whose position has been (partially) closed by auto-deleveraging
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdlEvent {
+    pub account_id: CompactString,
+    pub side: OrderSide, // side of the position reduced
+    pub quantity: OrderQuantity,
+    pub price: OrderPrice,
+    pub realized_pnl: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdlRank {
+    pub account_id: CompactString,
+    pub score: Decimal,
+}
+
+// profitable and highly leveraged positions first; positions without margin count as not leveraged
+fn score(position: &Position, mark_price: OrderPrice) -> Decimal {
+    let cost = position.size.abs() * position.entry_price;
+    if cost.is_zero() {
+        return Decimal::ZERO;
+    }
+
+    let pnl_ratio = position.unrealized_pnl(mark_price) / cost;
+    let leverage = position.leverage(mark_price).unwrap_or(Decimal::ONE);
+    if pnl_ratio > Decimal::ZERO {
+        pnl_ratio * leverage
+    } else if leverage > Decimal::ZERO {
+        pnl_ratio / leverage
+    } else {
+        pnl_ratio
+    }
+}
+
+pub fn rank(positions: &PositionBook, side: OrderSide, mark_price: OrderPrice) -> Vec<AdlRank> {
+    let mut queue: Vec<AdlRank> = positions
+        .iter()
+        .filter(|(_, position)| position.side() == Some(side))
+        .map(|(account_id, position)| AdlRank {
+            account_id: account_id.clone(),
+            score: score(position, mark_price),
+        })
+        .collect();
+    queue.sort_by_key(|rank| Reverse(rank.score));
+    queue
+}
+
+// close the position of the bankrupt account at its bankruptcy price against the top of the opposite ADL queue
+pub fn execute(
+    positions: &mut PositionBook,
+    bankrupt: &str,
+    bankruptcy_price: OrderPrice,
+    mark_price: OrderPrice,
+) -> Result<Vec<AdlEvent>, AdlError> {
+    let (bankrupt_side, bankrupt_size) = match positions.get(bankrupt) {
+        Some(position) if !position.is_flat() => (position.side().unwrap(), position.quantity()),
+        _ => return Err(AdlError::NothingToDeleverage(bankrupt.into())),
+    };
+
+    let queue = rank(positions, !bankrupt_side, mark_price);
+    let capacity: OrderQuantity = queue
+        .iter()
+        .filter_map(|rank| positions.get(&rank.account_id))
+        .map(|position| position.quantity())
+        .sum();
+    if capacity < bankrupt_size {
+        return Err(AdlError::InsufficientCounterparties {
+            required: bankrupt_size,
+            available: capacity,
+        });
+    }
+
+    let mut events = vec![];
+    let mut remaining = bankrupt_size;
+    for rank in queue {
+        if remaining.is_zero() {
+            break;
+        }
+
+        let available = positions.get(&rank.account_id).map(|position| position.quantity());
+        let quantity = remaining.min(available.unwrap_or_default());
+        remaining -= quantity;
+
+        for (account_id, side) in [(rank.account_id.as_str(), !bankrupt_side), (bankrupt, bankrupt_side)] {
+            // the position is reduced with a fill on the opposite side
+            let realized_pnl = positions.apply_fill(account_id, !side, quantity, bankruptcy_price);
+            events.push(AdlEvent {
+                account_id: account_id.into(),
+                side,
+                quantity,
+                price: bankruptcy_price,
+                realized_pnl,
+            });
+        }
+    }
+
+    Ok(events)
+}
+
+// last step of the waterfall: only deleverage when the insurance fund could not cover the shortfall
+pub fn resolve(
+    waterfall: &Waterfall,
+    positions: &mut PositionBook,
+    bankrupt: &str,
+    bankruptcy_price: OrderPrice,
+    mark_price: OrderPrice,
+) -> Result<Vec<AdlEvent>, AdlError> {
+    if !waterfall.requires_deleveraging() {
+        return Ok(vec![]);
+    }
+
+    execute(positions, bankrupt, bankruptcy_price, mark_price)
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AdlError {
+    #[error("account has no position to deleverage! {0}")]
+    NothingToDeleverage(CompactString),
+    #[error("not enough opposite positions to deleverage (required={}, available={})", .required, .available)]
+    InsufficientCounterparties {
+        required: OrderQuantity,
+        available: OrderQuantity,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // account 1 is long and bankrupt, accounts 2-4 are short with different profit and leverage
+    #[fixture]
+    fn positions() -> PositionBook {
+        let mut positions = PositionBook::default();
+        positions.apply_fill("1", OrderSide::Bid, 10.into(), 100.into());
+        positions.apply_fill("2", OrderSide::Ask, 4.into(), 100.into());
+        positions.apply_fill("3", OrderSide::Ask, 4.into(), 120.into());
+        positions.apply_fill("4", OrderSide::Ask, 6.into(), 120.into());
+        positions.add_margin("3", 96.into());
+        positions.add_margin("4", 360.into());
+        positions
+    }
+
+    #[rstest]
+    fn rank_by_profit_and_leverage(positions: PositionBook) {
+        let queue = rank(&positions, OrderSide::Ask, 80.into());
+        let accounts: Vec<&str> = queue.iter().map(|rank| rank.account_id.as_str()).collect();
+
+        // same profit for 3 and 4 but 3 is more leveraged, then 2 with less profit and no leverage
+        assert_eq!(accounts, vec!["3", "4", "2"]);
+        assert!(rank(&positions, OrderSide::Bid, 80.into()).len() == 1);
+    }
+
+    #[rstest]
+    fn deleverage_against_the_queue(mut positions: PositionBook) {
+        let events = execute(&mut positions, "1", 70.into(), 80.into()).unwrap();
+
+        // 4 from account 3 and then 6 from account 4, account 2 is untouched
+        let reduced: Vec<(&str, OrderQuantity)> = events
+            .iter()
+            .map(|event| (event.account_id.as_str(), event.quantity))
+            .collect();
+        assert_eq!(
+            reduced,
+            vec![("3", 4.into()), ("1", 4.into()), ("4", 6.into()), ("1", 6.into())]
+        );
+
+        assert!(positions.get("1").unwrap().is_flat());
+        assert!(positions.get("3").unwrap().is_flat());
+        assert!(positions.get("4").unwrap().is_flat());
+        assert_eq!(positions.get("2").unwrap().size, Decimal::from(-4));
+        assert_eq!(events[0].realized_pnl, Decimal::from(4 * 50));
+    }
+
+    #[rstest]
+    fn resolve_only_residual_shortfalls(mut positions: PositionBook) {
+        let covered = Waterfall {
+            shortfall: 10.into(),
+            covered_by_fund: 10.into(),
+            residual: Decimal::ZERO,
+        };
+        assert_eq!(resolve(&covered, &mut positions, "1", 70.into(), 80.into()), Ok(vec![]));
+        assert_eq!(positions.get("1").unwrap().size, Decimal::from(10));
+    }
+
+    #[rstest]
+    fn reject_when_not_possible(mut positions: PositionBook) {
+        assert_eq!(
+            execute(&mut positions, "5", 70.into(), 80.into()),
+            Err(AdlError::NothingToDeleverage("5".into()))
+        );
+
+        positions.apply_fill("1", OrderSide::Bid, 10.into(), 100.into());
+        assert_eq!(
+            execute(&mut positions, "1", 70.into(), 80.into()),
+            Err(AdlError::InsufficientCounterparties {
+                required: 20.into(),
+                available: 14.into(),
+            })
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use compact_str::CompactString;
+use indexmap::IndexSet;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ledger::{Ledger, LedgerError, PostingKind},
+    order::{OrderPrice, OrderQuantity, OrderSide},
+    position::PositionBook,
+    trade::TradeId,
+};
+
+// a fill of the executing account, as seen from its side
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Fill {
+    pub trade_id: TradeId,
+    pub side: OrderSide,
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+}
+
+// audit trail of a give-up: which fills went to which sub-accounts, at which price
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AllocationRecord {
+    pub allocation_id: u64,
+    pub account_id: CompactString,
+    pub side: OrderSide,
+    pub fills: Vec<TradeId>,
+    pub average_price: OrderPrice,
+    pub allocations: Vec<(CompactString, OrderQuantity)>,
+    pub entries: Vec<u64>, // ledger entries of the cash legs
+}
+
+// fills are given up once at most
+#[derive(Debug, Default)]
+pub struct Allocator {
+    records: Vec<AllocationRecord>,
+    allocated: IndexSet<TradeId>,
+}
+
+impl Allocator {
+    // the executing account gives the fills up at their average price: the position moves to the sub-accounts, which
+    // pay for it (or get paid for a sale) in the ledger
+    pub fn allocate(
+        &mut self,
+        positions: &mut PositionBook,
+        ledger: &mut Ledger,
+        (account_id, asset): (&str, &str),
+        fills: &[Fill],
+        allocations: &[(&str, OrderQuantity)],
+    ) -> Result<&AllocationRecord, AllocationError> {
+        let side = fills.first().ok_or(AllocationError::NoFills)?.side;
+        for fill in fills {
+            if fill.side != side {
+                return Err(AllocationError::MixedSides(fill.trade_id));
+            }
+            if self.allocated.contains(&fill.trade_id) {
+                return Err(AllocationError::AlreadyAllocated(fill.trade_id));
+            }
+        }
+        for (sub_account, quantity) in allocations {
+            if *quantity <= OrderQuantity::ZERO || *sub_account == account_id {
+                return Err(AllocationError::InvalidAllocation {
+                    sub_account: (*sub_account).into(),
+                    quantity: *quantity,
+                });
+            }
+        }
+        let filled: OrderQuantity = fills.iter().map(|fill| fill.quantity).sum();
+        let allocated: OrderQuantity = allocations.iter().map(|(_, quantity)| *quantity).sum();
+        if filled != allocated {
+            return Err(AllocationError::QuantityMismatch { filled, allocated });
+        }
+
+        let notional: Decimal = fills.iter().map(|fill| fill.quantity * fill.price).sum();
+        let average_price = OrderPrice::from_decimal(notional / filled);
+        let opposite = match side {
+            OrderSide::Ask => OrderSide::Bid,
+            OrderSide::Bid => OrderSide::Ask,
+        };
+        let mut entries = vec![];
+        for (sub_account, quantity) in allocations {
+            let (from, to) = match side {
+                OrderSide::Bid => (*sub_account, account_id),
+                OrderSide::Ask => (account_id, *sub_account),
+            };
+            entries.push(ledger.transfer(from, to, asset, *quantity * average_price, PostingKind::Allocation)?);
+            positions.apply_fill(account_id, opposite, *quantity, average_price);
+            positions.apply_fill(sub_account, side, *quantity, average_price);
+        }
+
+        self.allocated.extend(fills.iter().map(|fill| fill.trade_id));
+        self.records.push(AllocationRecord {
+            allocation_id: self.records.len() as u64,
+            account_id: account_id.into(),
+            side,
+            fills: fills.iter().map(|fill| fill.trade_id).collect(),
+            average_price,
+            allocations: allocations
+                .iter()
+                .map(|(sub_account, quantity)| ((*sub_account).into(), *quantity))
+                .collect(),
+            entries,
+        });
+        Ok(self.records.last().unwrap())
+    }
+
+    #[inline]
+    pub fn records(&self) -> &[AllocationRecord] {
+        &self.records
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AllocationError {
+    #[error("nothing to allocate without fills")]
+    NoFills,
+    #[error("fills to allocate together must be on the same side! {0:?}")]
+    MixedSides(TradeId),
+    #[error("fill already allocated! {0:?}")]
+    AlreadyAllocated(TradeId),
+    #[error("invalid allocation (sub_account={}, quantity={})", .sub_account, .quantity)]
+    InvalidAllocation {
+        sub_account: CompactString,
+        quantity: OrderQuantity,
+    },
+    #[error("allocations must add up to the fills (filled={}, allocated={})", .filled, .allocated)]
+    QuantityMismatch {
+        filled: OrderQuantity,
+        allocated: OrderQuantity,
+    },
+    #[error("ledger error: {0}")]
+    LedgerError(#[from] LedgerError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // the executing account "1" bought 10 at 100 and 10 at 103
+    #[fixture]
+    fn fills() -> Vec<Fill> {
+        vec![
+            Fill {
+                trade_id: 1.into(),
+                side: OrderSide::Bid,
+                price: 100.into(),
+                quantity: 10.into(),
+            },
+            Fill {
+                trade_id: 2.into(),
+                side: OrderSide::Bid,
+                price: 103.into(),
+                quantity: 10.into(),
+            },
+        ]
+    }
+
+    #[rstest]
+    fn allocate_at_average_price(fills: Vec<Fill>) {
+        let mut positions = PositionBook::default();
+        for fill in &fills {
+            positions.apply_fill("1", fill.side, fill.quantity, fill.price);
+        }
+        let mut ledger = Ledger::default();
+        let mut allocator = Allocator::default();
+
+        let record = allocator
+            .allocate(
+                &mut positions,
+                &mut ledger,
+                ("1", "USDT"),
+                &fills,
+                &[("1a", 15.into()), ("1b", 5.into())],
+            )
+            .unwrap();
+        assert_eq!(record.average_price, OrderPrice::new(1015, 1));
+        assert_eq!(record.entries.len(), 2);
+
+        assert!(positions.get("1").unwrap().is_flat());
+        assert_eq!(positions.get("1a").unwrap().size, Decimal::from(15));
+        assert_eq!(positions.get("1b").unwrap().entry_price, OrderPrice::new(1015, 1));
+        assert_eq!(ledger.balance("1", "USDT"), Decimal::from(2030));
+        assert_eq!(ledger.balance("1b", "USDT"), Decimal::new(-5075, 1));
+
+        // fills are allocated once, in full
+        assert_eq!(
+            allocator.allocate(&mut positions, &mut ledger, ("1", "USDT"), &fills, &[("1a", 20.into())]),
+            Err(AllocationError::AlreadyAllocated(1.into()))
+        );
+        assert_eq!(allocator.records().len(), 1);
+    }
+
+    #[rstest]
+    fn reject_partial_allocations(fills: Vec<Fill>) {
+        let mut allocator = Allocator::default();
+        assert_eq!(
+            allocator.allocate(
+                &mut PositionBook::default(),
+                &mut Ledger::default(),
+                ("1", "USDT"),
+                &fills,
+                &[("1a", 15.into())]
+            ),
+            Err(AllocationError::QuantityMismatch {
+                filled: 20.into(),
+                allocated: 15.into()
+            })
+        );
+        assert!(allocator.records().is_empty());
+    }
+}
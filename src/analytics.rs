@@ -0,0 +1,303 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Timestamp,
+    market_data::{BookEvent, MarketDataEvent},
+    order::{OrderId, OrderPrice, OrderQuantity, OrderSide},
+};
+
+// means over the stream observed, none when there was nothing to measure
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BookQualityReport {
+    pub quoted_spread: Option<Decimal>, // after every change of the book quoted on both sides
+    pub effective_spread: Option<Decimal>, // quantity weighted, against the mid price on arrival of the taker
+    pub realized_spread: Option<Decimal>, // quantity weighted, against the mid price at the horizon
+    pub bid_depth_at_touch: Option<OrderQuantity>, // like the quoted spread
+    pub ask_depth_at_touch: Option<OrderQuantity>,
+    pub resiliency: Option<Timestamp>, // time for the spread to be back to where it was before a trade
+    pub trades: u64,
+    pub unrecovered: u64, // trades the spread did not come back from (yet)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Arrival {
+    taker: OrderId, // its trades all take the book as it was before the first one
+    mid_price: Option<OrderPrice>,
+    spread: Option<Decimal>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PendingTrade {
+    at: Timestamp,
+    side: OrderSide,
+    price: OrderPrice,
+    quantity: OrderQuantity,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Mean {
+    total: Decimal,
+    weight: Decimal,
+}
+
+impl Mean {
+    #[inline]
+    fn add(&mut self, value: Decimal, weight: Decimal) {
+        self.total += value * weight;
+        self.weight += weight;
+    }
+
+    #[inline]
+    fn get(&self) -> Option<Decimal> {
+        self.total.checked_div(self.weight)
+    }
+}
+
+// microstructure metrics of a market, simulated or not, out of its level stream (Granularity::Level, order events are
+// ignored); `at` is any monotonic measure of time, the sequence of the events will do (see observe_event)
+#[derive(Clone, Debug)]
+pub struct BookQuality {
+    horizon: Timestamp, // of the realized spread
+    bids: BTreeMap<Reverse<OrderPrice>, OrderQuantity>,
+    asks: BTreeMap<OrderPrice, OrderQuantity>,
+    arrival: Option<Arrival>,
+    pending: VecDeque<PendingTrade>,       // waiting for the horizon
+    recovering: Vec<(Timestamp, Decimal)>, // when each trade happened and the spread before it
+    quoted_spread: Mean,
+    bid_depth: Mean,
+    ask_depth: Mean,
+    effective_spread: Mean,
+    realized_spread: Mean,
+    resiliency: Mean,
+    trades: u64,
+}
+
+impl BookQuality {
+    pub fn new(horizon: Timestamp) -> Self {
+        Self {
+            horizon,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            arrival: None,
+            pending: VecDeque::new(),
+            recovering: Vec::new(),
+            quoted_spread: Mean::default(),
+            bid_depth: Mean::default(),
+            ask_depth: Mean::default(),
+            effective_spread: Mean::default(),
+            realized_spread: Mean::default(),
+            resiliency: Mean::default(),
+            trades: 0,
+        }
+    }
+
+    #[inline]
+    pub fn observe_event(&mut self, event: &MarketDataEvent) {
+        self.observe(event.sequence, &event.event);
+    }
+
+    pub fn observe(&mut self, at: Timestamp, event: &BookEvent) {
+        // the book as it was up to now is the one at the horizon of the trades reaching it
+        while let Some(trade) = self.pending.front().filter(|trade| trade.at + self.horizon <= at) {
+            if let Some(mid_price) = self.mid_price() {
+                let distance = signed(trade.side, trade.price - mid_price);
+                self.realized_spread
+                    .add(Decimal::TWO * distance, trade.quantity.value());
+            }
+            self.pending.pop_front();
+        }
+
+        match *event {
+            BookEvent::Add {
+                order_id: None,
+                side,
+                price,
+                quantity,
+            }
+            | BookEvent::Modify {
+                order_id: None,
+                side,
+                price,
+                quantity,
+            } => {
+                match side {
+                    OrderSide::Bid => self.bids.insert(Reverse(price), quantity),
+                    OrderSide::Ask => self.asks.insert(price, quantity),
+                };
+                self.sample(at);
+            }
+            BookEvent::Delete {
+                order_id: None,
+                side,
+                price,
+            } => {
+                match side {
+                    OrderSide::Bid => self.bids.remove(&Reverse(price)),
+                    OrderSide::Ask => self.asks.remove(&price),
+                };
+                self.sample(at);
+            }
+            BookEvent::Trade {
+                taker,
+                side,
+                price,
+                quantity,
+                ..
+            } => self.trade(at, taker, side, price, quantity),
+            _ => {}
+        }
+    }
+
+    pub fn report(&self) -> BookQualityReport {
+        BookQualityReport {
+            quoted_spread: self.quoted_spread.get(),
+            effective_spread: self.effective_spread.get(),
+            realized_spread: self.realized_spread.get(),
+            bid_depth_at_touch: self.bid_depth.get().map(OrderQuantity::from_decimal),
+            ask_depth_at_touch: self.ask_depth.get().map(OrderQuantity::from_decimal),
+            resiliency: self.resiliency.get().and_then(|resiliency| resiliency.try_into().ok()),
+            trades: self.trades,
+            unrecovered: self.recovering.len() as u64,
+        }
+    }
+
+    #[inline]
+    fn best_bid(&self) -> Option<(OrderPrice, OrderQuantity)> {
+        self.bids
+            .first_key_value()
+            .map(|(Reverse(price), quantity)| (*price, *quantity))
+    }
+
+    #[inline]
+    fn best_ask(&self) -> Option<(OrderPrice, OrderQuantity)> {
+        self.asks.first_key_value().map(|(price, quantity)| (*price, *quantity))
+    }
+
+    #[inline]
+    fn mid_price(&self) -> Option<OrderPrice> {
+        Some((self.best_bid()?.0 + self.best_ask()?.0) / Decimal::TWO)
+    }
+
+    #[inline]
+    fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()?.0 - self.best_bid()?.0)
+    }
+
+    fn trade(&mut self, at: Timestamp, taker: OrderId, side: OrderSide, price: OrderPrice, quantity: OrderQuantity) {
+        // the levels the taker already went through are gone from the book by its next trades
+        let arrival = match self.arrival {
+            Some(arrival) if arrival.taker == taker => arrival,
+            _ => Arrival {
+                taker,
+                mid_price: self.mid_price(),
+                spread: self.spread(),
+            },
+        };
+        self.arrival = Some(arrival);
+
+        self.trades += 1;
+        if let Some(mid_price) = arrival.mid_price {
+            let distance = signed(side, price - mid_price);
+            self.effective_spread.add(Decimal::TWO * distance, quantity.value());
+        }
+        self.pending.push_back(PendingTrade {
+            at,
+            side,
+            price,
+            quantity,
+        });
+        if let Some(spread) = arrival.spread {
+            self.recovering.push((at, spread));
+        }
+    }
+
+    fn sample(&mut self, at: Timestamp) {
+        let (Some((bid_price, bid_quantity)), Some((ask_price, ask_quantity))) = (self.best_bid(), self.best_ask())
+        else {
+            return;
+        };
+        let spread = ask_price - bid_price;
+        self.quoted_spread.add(spread, Decimal::ONE);
+        self.bid_depth.add(bid_quantity.value(), Decimal::ONE);
+        self.ask_depth.add(ask_quantity.value(), Decimal::ONE);
+
+        let resiliency = &mut self.resiliency;
+        self.recovering.retain(|(traded_at, before)| {
+            if spread > *before {
+                return true;
+            }
+            resiliency.add((at - traded_at).into(), Decimal::ONE);
+            false
+        });
+    }
+}
+
+// positive when the taker paid it
+#[inline]
+fn signed(side: OrderSide, distance: Decimal) -> Decimal {
+    match side {
+        OrderSide::Bid => distance,
+        OrderSide::Ask => -distance,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        engine::Engine,
+        market_data::Granularity,
+        order::{
+            util::{self, DEFAULT_SYMBOL},
+            OrderRequest,
+        },
+    };
+
+    fn create(order_id: u64, side: OrderSide, quantity: u32, limit_price: u32) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(Some(limit_price.into()))
+            .quantity(quantity)
+            .build()
+    }
+
+    #[rstest]
+    fn measure_a_sweep_and_the_recovery() {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        let levels = engine.subscribe(Granularity::Level);
+        // a bid sweeps the ask at 11 and half of the one at 12, a new ask at 11 brings the spread back to 2
+        for order_request in [
+            create(1, OrderSide::Bid, 10, 9),
+            create(2, OrderSide::Ask, 10, 11),
+            create(3, OrderSide::Ask, 20, 12),
+            create(4, OrderSide::Bid, 20, 15),
+            create(5, OrderSide::Ask, 10, 11),
+        ] {
+            engine.process(order_request).unwrap();
+        }
+
+        let mut quality = BookQuality::new(2);
+        for event in levels.try_iter() {
+            quality.observe_event(&event);
+        }
+        let report = quality.report();
+        assert_eq!(report.trades, 2);
+        // quoted 2, 2, 3, 3, 2 with 10, 10, 20, 10, 10 at the ask
+        assert_eq!(report.quoted_spread, Some(Decimal::new(24, 1)));
+        assert_eq!(report.bid_depth_at_touch, Some(10.into()));
+        assert_eq!(report.ask_depth_at_touch, Some(12.into()));
+        // 10 at 11 and 10 at 12 against a mid of 10, then of 10.5 two events later
+        assert_eq!(report.effective_spread, Some(3.into()));
+        assert_eq!(report.realized_spread, Some(2.into()));
+        // trades as the 4th and 6th events, the spread is back on the 8th
+        assert_eq!(report.resiliency, Some(3));
+        assert_eq!(report.unrecovered, 0);
+    }
+}
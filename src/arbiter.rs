@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Timestamp;
+
+// time spent by the messages of one session in its input queue (milliseconds)
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueueLatency {
+    pub messages: u64,
+    pub total: Timestamp,
+    pub max: Timestamp,
+}
+
+impl QueueLatency {
+    #[inline]
+    pub fn mean(&self) -> Timestamp {
+        self.total.checked_div(self.messages).unwrap_or_default()
+    }
+
+    fn record(&mut self, latency: Timestamp) {
+        self.messages += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct InputQueue<T> {
+    weight: u32,
+    pending: VecDeque<(Timestamp, T)>, // by arrival time
+    latency: QueueLatency,
+}
+
+// weighted round robin over the input queues of the sessions feeding one matching thread: each session takes up to
+// its weight (1 by default, plain round robin) of messages per turn, so a noisy one cannot starve the others
+#[derive(Clone, Debug)]
+pub struct Arbiter<T> {
+    queues: IndexMap<u64, InputQueue<T>>,
+    turn: usize, // index of the session whose turn it is
+    taken: u32,  // messages taken during the current turn
+    pending: usize,
+}
+
+impl<T> Default for Arbiter<T> {
+    fn default() -> Self {
+        Self {
+            queues: IndexMap::new(),
+            turn: 0,
+            taken: 0,
+            pending: 0,
+        }
+    }
+}
+
+impl<T> Arbiter<T> {
+    // takes effect from the next turn of the session, a weight of 0 counts as 1
+    pub fn set_weight(&mut self, session_id: u64, weight: u32) {
+        self.queue_mut(session_id).weight = weight.max(1);
+    }
+
+    #[inline]
+    pub fn push(&mut self, session_id: u64, now: Timestamp, message: T) {
+        self.queue_mut(session_id).pending.push_back((now, message));
+        self.pending += 1;
+    }
+
+    pub fn pop(&mut self, now: Timestamp) -> Option<(u64, T)> {
+        if self.pending == 0 {
+            return None;
+        }
+
+        loop {
+            let (&session_id, queue) = self.queues.get_index_mut(self.turn)?;
+            if self.taken < queue.weight {
+                if let Some((arrived_at, message)) = queue.pending.pop_front() {
+                    queue.latency.record(now.saturating_sub(arrived_at));
+                    self.taken += 1;
+                    self.pending -= 1;
+                    return Some((session_id, message));
+                }
+            }
+            self.turn = (self.turn + 1) % self.queues.len();
+            self.taken = 0;
+        }
+    }
+
+    // forgets a session gone, returns the messages it still had queued with its latency
+    pub fn remove(&mut self, session_id: u64) -> Option<(Vec<T>, QueueLatency)> {
+        let (index, _, queue) = self.queues.shift_remove_full(&session_id)?;
+        if index < self.turn {
+            self.turn -= 1;
+        } else if index == self.turn {
+            self.taken = 0;
+        }
+        if self.turn >= self.queues.len() {
+            self.turn = 0;
+        }
+        self.pending -= queue.pending.len();
+        Some((
+            queue.pending.into_iter().map(|(_, message)| message).collect(),
+            queue.latency,
+        ))
+    }
+
+    #[inline]
+    pub fn latency(&self, session_id: u64) -> Option<QueueLatency> {
+        self.queues.get(&session_id).map(|queue| queue.latency)
+    }
+
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    fn queue_mut(&mut self, session_id: u64) -> &mut InputQueue<T> {
+        self.queues.entry(session_id).or_insert_with(|| InputQueue {
+            weight: 1,
+            pending: VecDeque::new(),
+            latency: QueueLatency::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn drain(arbiter: &mut Arbiter<u64>, now: Timestamp) -> Vec<(u64, u64)> {
+        std::iter::from_fn(|| arbiter.pop(now)).collect()
+    }
+
+    #[rstest]
+    fn noisy_sessions_do_not_starve_the_others() {
+        let mut arbiter = Arbiter::default();
+        for message in 1..=4 {
+            arbiter.push(1, 0, message);
+        }
+        arbiter.push(2, 1, 5);
+        arbiter.push(3, 2, 6);
+        assert_eq!(arbiter.pending(), 6);
+        assert_eq!(
+            drain(&mut arbiter, 2),
+            vec![(1, 1), (2, 5), (3, 6), (1, 2), (1, 3), (1, 4)]
+        );
+        assert!(arbiter.is_empty());
+        assert_eq!(
+            arbiter.latency(1),
+            Some(QueueLatency {
+                messages: 4,
+                total: 8,
+                max: 2
+            })
+        );
+        assert_eq!(arbiter.latency(2).unwrap().mean(), 1);
+    }
+
+    #[rstest]
+    fn weighted_turns() {
+        let mut arbiter = Arbiter::default();
+        arbiter.set_weight(1, 2);
+        for message in 1..=3 {
+            arbiter.push(1, 0, message);
+            arbiter.push(2, 0, message + 10);
+        }
+        assert_eq!(
+            drain(&mut arbiter, 0),
+            vec![(1, 1), (1, 2), (2, 11), (1, 3), (2, 12), (2, 13)]
+        );
+
+        // session 2 just had its turn, it stays that way once session 1 is gone
+        arbiter.push(1, 0, 4);
+        arbiter.push(2, 0, 14);
+        arbiter.push(3, 0, 24);
+        let (dropped, latency) = arbiter.remove(1).unwrap();
+        assert_eq!((dropped, latency.messages), (vec![4], 3));
+        assert_eq!(drain(&mut arbiter, 0), vec![(3, 24), (2, 14)]);
+        assert!(arbiter.remove(1).is_none());
+    }
+}
@@ -0,0 +1,187 @@
+use std::cmp::Reverse;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Timestamp,
+    order::{OrderRequest, OrderSide},
+};
+
+// how long a batch collects the incoming requests, from the first one
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "interval")]
+pub enum BatchInterval {
+    Fixed { length: Timestamp },
+    Random { max_length: Timestamp }, // drawn anew for every batch, from 0 to max_length
+}
+
+// in which order the requests of a batch are matched
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum BatchOrdering {
+    #[default]
+    Random,
+    // cancels first, then the most aggressive orders of each side (market orders, then by price) taking turns, the
+    // arrival order only breaks ties
+    PricePriority,
+}
+
+// speed bump: the requests wait for the end of their batch, arriving first within a batch is no advantage
+#[derive(Clone, Debug)]
+pub struct Batcher {
+    interval: BatchInterval,
+    ordering: BatchOrdering,
+    rng: StdRng, // seeded, so that a run can be reproduced
+    closes_at: Option<Timestamp>,
+    pending: Vec<OrderRequest>,
+}
+
+impl Batcher {
+    #[inline]
+    pub fn new(interval: BatchInterval, ordering: BatchOrdering, seed: u64) -> Self {
+        Self {
+            interval,
+            ordering,
+            rng: StdRng::seed_from_u64(seed),
+            closes_at: None,
+            pending: vec![],
+        }
+    }
+
+    // the first request opens a batch
+    pub fn push(&mut self, now: Timestamp, order_request: OrderRequest) {
+        if self.closes_at.is_none() {
+            let length = match self.interval {
+                BatchInterval::Fixed { length } => length,
+                BatchInterval::Random { max_length } => self.rng.gen_range(0..=max_length),
+            };
+            self.closes_at = Some(now + length);
+        }
+        self.pending.push(order_request);
+    }
+
+    #[inline]
+    pub fn closes_at(&self) -> Option<Timestamp> {
+        self.closes_at
+    }
+
+    #[inline]
+    pub fn is_due(&self, now: Timestamp) -> bool {
+        self.closes_at.is_some_and(|closes_at| closes_at <= now)
+    }
+
+    #[inline]
+    pub fn pending(&self) -> &[OrderRequest] {
+        &self.pending
+    }
+
+    // ends the batch, returns its requests in matching order
+    pub fn close(&mut self) -> Vec<OrderRequest> {
+        self.closes_at = None;
+        let mut batch = std::mem::take(&mut self.pending);
+        match self.ordering {
+            BatchOrdering::Random => {
+                batch.shuffle(&mut self.rng);
+                batch
+            }
+            BatchOrdering::PricePriority => by_price_priority(batch),
+        }
+    }
+}
+
+fn by_price_priority(batch: Vec<OrderRequest>) -> Vec<OrderRequest> {
+    let (mut ordered, mut bids, mut asks) = (vec![], vec![], vec![]);
+    for order_request in batch {
+        match &order_request {
+            OrderRequest::Create {
+                side: OrderSide::Bid, ..
+            } => bids.push(order_request),
+            OrderRequest::Create {
+                side: OrderSide::Ask, ..
+            } => asks.push(order_request),
+            // a mass quote pulls the previous quotes of the account first
+            OrderRequest::Cancel { .. }
+            | OrderRequest::CancelByClientId { .. }
+            | OrderRequest::CancelAll { .. }
+            | OrderRequest::MassQuote { .. } => ordered.push(order_request),
+        }
+    }
+
+    // stable sorts: market orders first (no limit price), then the best prices
+    let limit_price = |order_request: &OrderRequest| match order_request {
+        OrderRequest::Create { limit_price, .. } => *limit_price,
+        _ => None,
+    };
+    bids.sort_by_key(|bid| limit_price(bid).map(Reverse));
+    asks.sort_by_key(|ask| (limit_price(ask).is_some(), limit_price(ask)));
+
+    let (mut bids, mut asks) = (bids.into_iter(), asks.into_iter());
+    loop {
+        match (bids.next(), asks.next()) {
+            (None, None) => break,
+            (bid, ask) => ordered.extend(bid.into_iter().chain(ask)),
+        }
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::util;
+
+    fn create(order_id: u64, side: OrderSide, limit_price: Option<u32>) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(limit_price.map(Into::into))
+            .build()
+    }
+
+    fn order_id(order_request: &OrderRequest) -> u64 {
+        match order_request {
+            OrderRequest::Create { order_id, .. } | OrderRequest::Cancel { order_id, .. } => *order_id,
+            OrderRequest::CancelByClientId { .. } | OrderRequest::CancelAll { .. } | OrderRequest::MassQuote { .. } => {
+                0
+            }
+        }
+    }
+
+    #[rstest]
+    fn close_batches_in_price_priority() {
+        let mut batcher = Batcher::new(BatchInterval::Fixed { length: 1 }, BatchOrdering::PricePriority, 0);
+        assert!(!batcher.is_due(0));
+        batcher.push(10, create(1, OrderSide::Bid, Some(14)));
+        batcher.push(10, create(2, OrderSide::Ask, Some(16)));
+        batcher.push(10, create(3, OrderSide::Bid, Some(15)));
+        batcher.push(10, create(4, OrderSide::Ask, None));
+        batcher.push(10, util::cancel(9));
+        batcher.push(10, create(5, OrderSide::Bid, Some(15)));
+
+        // a millisecond from the first request
+        assert!(!batcher.is_due(10));
+        assert!(batcher.is_due(11));
+        let batch: Vec<u64> = batcher.close().iter().map(order_id).collect();
+        assert_eq!(batch, vec![9, 3, 4, 5, 2, 1]);
+        assert_eq!(batcher.closes_at(), None);
+    }
+
+    #[rstest]
+    fn draw_random_intervals_and_orders() {
+        let mut batcher = Batcher::new(BatchInterval::Random { max_length: 3 }, BatchOrdering::Random, 7);
+        for order_id in 1..=20 {
+            batcher.push(0, create(order_id, OrderSide::Bid, Some(15)));
+        }
+        assert!(batcher.closes_at().unwrap() <= 3);
+
+        let batch: Vec<u64> = batcher.close().iter().map(order_id).collect();
+        assert_ne!(batch, (1..=20).collect::<Vec<u64>>());
+        // the same seed gives the same batches
+        let mut replay = Batcher::new(BatchInterval::Random { max_length: 3 }, BatchOrdering::Random, 7);
+        for order_id in 1..=20 {
+            replay.push(0, create(order_id, OrderSide::Bid, Some(15)));
+        }
+        assert_eq!(replay.close().iter().map(order_id).collect::<Vec<u64>>(), batch);
+    }
+}
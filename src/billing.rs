@@ -0,0 +1,239 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fx::FxRate,
+    ledger::{Ledger, LedgerError, PostingKind, EXCHANGE_ACCOUNT},
+};
+
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MessageKind {
+    Order,
+    Cancel,
+    Amend,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub orders: u64,
+    pub cancels: u64,
+    pub amends: u64,
+}
+
+impl MessageCounts {
+    #[inline]
+    pub fn get(&self, kind: MessageKind) -> u64 {
+        match kind {
+            MessageKind::Order => self.orders,
+            MessageKind::Cancel => self.cancels,
+            MessageKind::Amend => self.amends,
+        }
+    }
+
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.orders + self.cancels + self.amends
+    }
+
+    #[inline]
+    fn increment(&mut self, kind: MessageKind) {
+        match kind {
+            MessageKind::Order => self.orders += 1,
+            MessageKind::Cancel => self.cancels += 1,
+            MessageKind::Amend => self.amends += 1,
+        }
+    }
+}
+
+// pricing hook: `counts` are the messages already sent by the account (excluding this one) so tiered pricing is possible
+pub trait MessagePricing: Send {
+    fn price(&self, account_id: &str, kind: MessageKind, counts: &MessageCounts) -> Decimal;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlatMessagePricing {
+    pub order: Decimal,
+    pub cancel: Decimal,
+    pub amend: Decimal,
+}
+
+impl MessagePricing for FlatMessagePricing {
+    fn price(&self, _account_id: &str, kind: MessageKind, _counts: &MessageCounts) -> Decimal {
+        match kind {
+            MessageKind::Order => self.order,
+            MessageKind::Cancel => self.cancel,
+            MessageKind::Amend => self.amend,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MessageAccount {
+    pub counts: MessageCounts,
+    pub fees: Decimal,    // all the fees charged so far
+    pub accrued: Decimal, // fees not yet posted to the ledger
+}
+
+pub struct MessageBilling {
+    pricing: Box<dyn MessagePricing>,
+    accounts: IndexMap<CompactString, MessageAccount>,
+}
+
+impl Default for MessageBilling {
+    fn default() -> Self {
+        Self::new(FlatMessagePricing::default())
+    }
+}
+
+impl MessageBilling {
+    pub fn new(pricing: impl MessagePricing + 'static) -> Self {
+        Self {
+            pricing: Box::new(pricing),
+            accounts: IndexMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, account_id: &str, kind: MessageKind) -> Decimal {
+        let account = self.accounts.entry(account_id.into()).or_default();
+        let fee = self.pricing.price(account_id, kind, &account.counts);
+
+        account.counts.increment(kind);
+        account.fees += fee;
+        account.accrued += fee;
+
+        fee
+    }
+
+    #[inline]
+    pub fn account(&self, account_id: &str) -> Option<&MessageAccount> {
+        self.accounts.get(account_id)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = (&CompactString, &MessageAccount)> {
+        self.accounts.iter()
+    }
+
+    // post the accrued fees of every account to the ledger (one entry per account)
+    pub fn settle(&mut self, ledger: &mut Ledger, asset: &str) -> Result<(), LedgerError> {
+        self.settle_with_fx(ledger, asset, None)
+    }
+
+    // fees accrue in the base of the rate and are charged in its quote (the asset)
+    pub fn settle_with_fx(&mut self, ledger: &mut Ledger, asset: &str, fx: Option<&FxRate>) -> Result<(), LedgerError> {
+        for (account_id, account) in self.accounts.iter_mut() {
+            if account.accrued > Decimal::ZERO {
+                let amount = fx.map_or(account.accrued, |fx| fx.convert(account.accrued));
+                ledger.transfer_with_fx(
+                    account_id,
+                    EXCHANGE_ACCOUNT,
+                    asset,
+                    amount,
+                    PostingKind::MessageFee,
+                    fx.cloned(),
+                )?;
+                account.accrued = Decimal::ZERO;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // first 2 orders are free, then every order costs 1
+    struct TieredPricing;
+
+    impl MessagePricing for TieredPricing {
+        fn price(&self, _account_id: &str, kind: MessageKind, counts: &MessageCounts) -> Decimal {
+            match kind {
+                MessageKind::Order if counts.orders >= 2 => Decimal::ONE,
+                _ => Decimal::ZERO,
+            }
+        }
+    }
+
+    #[fixture]
+    fn flat() -> MessageBilling {
+        MessageBilling::new(FlatMessagePricing {
+            order: Decimal::new(10, 2),
+            cancel: Decimal::new(5, 2),
+            amend: Decimal::new(1, 2),
+        })
+    }
+
+    #[rstest]
+    fn count_messages_per_account(mut flat: MessageBilling) {
+        flat.record("1", MessageKind::Order);
+        flat.record("1", MessageKind::Order);
+        flat.record("1", MessageKind::Cancel);
+        flat.record("2", MessageKind::Amend);
+
+        let account = flat.account("1").unwrap();
+        assert_eq!(
+            account.counts,
+            MessageCounts {
+                orders: 2,
+                cancels: 1,
+                amends: 0
+            }
+        );
+        assert_eq!(account.fees, Decimal::new(25, 2));
+        assert_eq!(flat.account("2").unwrap().counts.total(), 1);
+        assert_eq!(flat.account("3"), None);
+    }
+
+    #[rstest]
+    fn tiered_pricing_hook() {
+        let mut billing = MessageBilling::new(TieredPricing);
+        let fees: Vec<Decimal> = (0..4).map(|_| billing.record("1", MessageKind::Order)).collect();
+        assert_eq!(fees, vec![0.into(), 0.into(), 1.into(), 1.into()]);
+    }
+
+    #[rstest]
+    fn settle_to_ledger(mut flat: MessageBilling) {
+        let mut ledger = Ledger::default();
+        flat.record("1", MessageKind::Order);
+        flat.record("2", MessageKind::Cancel);
+        flat.settle(&mut ledger, "USDT").unwrap();
+
+        assert_eq!(ledger.balance("1", "USDT"), Decimal::new(-10, 2));
+        assert_eq!(ledger.balance("2", "USDT"), Decimal::new(-5, 2));
+        assert_eq!(ledger.balance(EXCHANGE_ACCOUNT, "USDT"), Decimal::new(15, 2));
+
+        // settling twice does not post the same fees again
+        flat.settle(&mut ledger, "USDT").unwrap();
+        assert_eq!(ledger.postings().len(), 4);
+        assert_eq!(flat.account("1").unwrap().accrued, Decimal::ZERO);
+        assert_eq!(flat.account("1").unwrap().fees, Decimal::new(10, 2));
+
+        let statement = ledger.statement("1");
+        assert_eq!(statement.total(PostingKind::MessageFee), Decimal::new(-10, 2));
+    }
+
+    #[rstest]
+    fn settle_in_another_asset(mut flat: MessageBilling) {
+        let mut ledger = Ledger::default();
+        let fx = FxRate {
+            base: "USDT".into(),
+            quote: "EUR".into(),
+            rate: Decimal::new(9, 1),
+            timestamp: 0,
+            source: "manual".into(),
+        };
+        flat.record("1", MessageKind::Order);
+        flat.settle_with_fx(&mut ledger, "EUR", Some(&fx)).unwrap();
+
+        // the fee is converted and the rate is kept on both postings
+        assert_eq!(ledger.balance("1", "EUR"), Decimal::new(-9, 2));
+        assert!(ledger.postings().iter().all(|posting| posting.fx.as_ref() == Some(&fx)));
+    }
+}
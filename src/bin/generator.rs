@@ -2,12 +2,47 @@ use std::io;
 use std::io::Write;
 
 use anyhow::Result;
-use merx::order::util::generate;
+use clap::Parser;
+use merx::{order::util::generate_with, scenario::Scenario, simulation::flow::MarketRegime};
+
+#[derive(Parser)]
+#[clap(author, version, about)]
+struct Args {
+    #[clap(
+        short,
+        long,
+        help = "Stress scenario (flash-crash, one-sided-flow, cancel-storm, quote-stuffing, thin-book)"
+    )]
+    scenario: Option<Scenario>,
+    #[clap(
+        short,
+        long,
+        conflicts_with = "scenario",
+        help = "Market regime of a timed flow (calm, trending, volatile, bursty)"
+    )]
+    regime: Option<MarketRegime>,
+    #[clap(long, help = "Seed of the generator, the same flow for the same seed")]
+    seed: Option<u64>,
+    #[clap(short, long, default_value_t = 10_000_000, help = "Number of order requests")]
+    count: usize,
+}
 
 fn main() -> Result<()> {
+    let args = Args::parse();
+
     let mut stdout = io::stdout();
-    let range = 1..=10_000_000;
-    for order in generate(range) {
+    if let Some(regime) = args.regime {
+        for request in regime.generate(args.seed.unwrap_or_default()).take(args.count) {
+            let request = serde_json::to_string(&request)?;
+            writeln!(stdout, "{}", request)?;
+        }
+        return Ok(());
+    }
+
+    let mut config = args.scenario.map(|scenario| scenario.config()).unwrap_or_default();
+    config.seed = args.seed;
+    let range = 1..=args.count;
+    for order in generate_with(config, range) {
         let order = serde_json::to_string(&order).ok().unwrap();
         writeln!(stdout, "{}", order)?;
     }
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use merx::{market_data::VerbosityConfig, order::util::DEFAULT_SYMBOL, symbol::Symbol, websocket::WebSocketServer};
+use tracing::info;
+
+#[derive(Parser)]
+#[clap(author, version, about)]
+struct Args {
+    #[clap(short, long, default_value = "127.0.0.1:8080", help = "Address to listen on")]
+    listen: String,
+    #[clap(short, long, default_values_t = [DEFAULT_SYMBOL], help = "Pairs to trade")]
+    pair: Vec<Symbol>,
+    #[clap(
+        short,
+        long,
+        help = "JSON file of the market data events generated per pair (all of them by default)"
+    )]
+    verbosity: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt().json().init();
+
+    let args = Args::parse();
+    let verbosity = match args.verbosity {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => VerbosityConfig::default(),
+    };
+
+    let server = WebSocketServer::bind_with_verbosity(&args.listen, &args.pair, &verbosity).await?;
+    info!("WebSocket server listening on {}", server.local_addr()?);
+    server.run().await?;
+
+    Ok(())
+}
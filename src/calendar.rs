@@ -0,0 +1,251 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::clock::{next_time_of_day, Timestamp, DAY};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SessionState {
+    #[default]
+    Open,
+    PreOpen, // orders rest without matching until the opening uncross
+    Closed,
+    Maintenance,
+    Auction, // call phase of an auction started on demand, until its uncross
+    Halted,  // by the circuit breaker or by hand, nothing reaches the book
+    CancelOnly,
+    PostOnly, // only orders that cannot take liquidity, the book rebuilds its depth before trading resumes
+}
+
+impl Display for SessionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionState::Open => write!(f, "OPEN"),
+            SessionState::PreOpen => write!(f, "PREOPEN"),
+            SessionState::Closed => write!(f, "CLOSED"),
+            SessionState::Maintenance => write!(f, "MAINTENANCE"),
+            SessionState::Auction => write!(f, "AUCTION"),
+            SessionState::Halted => write!(f, "HALTED"),
+            SessionState::CancelOnly => write!(f, "CANCELONLY"),
+            SessionState::PostOnly => write!(f, "POSTONLY"),
+        }
+    }
+}
+
+impl SessionState {
+    // orders are collected without matching
+    #[inline]
+    pub fn is_call_phase(&self) -> bool {
+        matches!(self, SessionState::PreOpen | SessionState::Auction)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    // the UNIX epoch was a thursday
+    #[inline]
+    pub fn of(timestamp: Timestamp) -> Self {
+        Self::ALL[((timestamp / DAY + 3) % 7) as usize]
+    }
+}
+
+// weekly window, times of the day in UTC
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub weekday: Weekday,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+impl MaintenanceWindow {
+    #[inline]
+    fn contains(&self, now: Timestamp) -> bool {
+        let time = now % DAY;
+        Weekday::of(now) == self.weekday && self.start <= time && time < self.end
+    }
+}
+
+// trading hours of an instrument (times of the day in UTC), the default one trades around the clock
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TradingCalendar {
+    open: Timestamp,
+    close: Timestamp,
+    pre_open: Timestamp, // duration before the open
+    closed_weekdays: BTreeSet<Weekday>,
+    holidays: BTreeSet<u64>, // days since the UNIX epoch
+    maintenance: Vec<MaintenanceWindow>,
+}
+
+impl Default for TradingCalendar {
+    fn default() -> Self {
+        Self {
+            open: 0,
+            close: DAY,
+            pre_open: 0,
+            closed_weekdays: BTreeSet::new(),
+            holidays: BTreeSet::new(),
+            maintenance: vec![],
+        }
+    }
+}
+
+impl TradingCalendar {
+    pub fn new(open: Timestamp, close: Timestamp) -> Result<Self, CalendarError> {
+        if open >= close || close > DAY {
+            return Err(CalendarError::InvalidHours { open, close });
+        }
+
+        Ok(Self {
+            open,
+            close,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_pre_open(mut self, duration: Timestamp) -> Result<Self, CalendarError> {
+        if duration > self.open {
+            return Err(CalendarError::InvalidHours {
+                open: self.open.saturating_sub(duration),
+                close: self.close,
+            });
+        }
+
+        self.pre_open = duration;
+        Ok(self)
+    }
+
+    pub fn with_closed_weekday(mut self, weekday: Weekday) -> Self {
+        self.closed_weekdays.insert(weekday);
+        self
+    }
+
+    // any time of the day closes the whole day
+    pub fn with_holiday(mut self, day: Timestamp) -> Self {
+        self.holidays.insert(day / DAY);
+        self
+    }
+
+    pub fn with_maintenance(
+        mut self,
+        weekday: Weekday,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Self, CalendarError> {
+        if start >= end || end > DAY {
+            return Err(CalendarError::InvalidHours {
+                open: start,
+                close: end,
+            });
+        }
+
+        self.maintenance.push(MaintenanceWindow { weekday, start, end });
+        Ok(self)
+    }
+
+    // the next close after now, closed days included
+    #[inline]
+    pub fn next_close(&self, now: Timestamp) -> Timestamp {
+        next_time_of_day(now, self.close)
+    }
+
+    pub fn state(&self, now: Timestamp) -> SessionState {
+        let time = now % DAY;
+        if self.holidays.contains(&(now / DAY)) || self.closed_weekdays.contains(&Weekday::of(now)) {
+            SessionState::Closed
+        } else if self.maintenance.iter().any(|window| window.contains(now)) {
+            SessionState::Maintenance
+        } else if self.open <= time && time < self.close {
+            SessionState::Open
+        } else if self.open - self.pre_open <= time && time < self.open {
+            SessionState::PreOpen
+        } else {
+            SessionState::Closed
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CalendarError {
+    #[error("invalid trading hours (open={}, close={})", .open, .close)]
+    InvalidHours { open: Timestamp, close: Timestamp },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::clock::HOUR;
+
+    // monday 2024-01-01 00:00 UTC
+    const MONDAY: Timestamp = 19_723 * DAY;
+
+    // 08:00-16:00 on weekdays, maintenance on wednesdays 12:00-13:00
+    #[fixture]
+    fn calendar() -> TradingCalendar {
+        TradingCalendar::new(8 * HOUR, 16 * HOUR)
+            .unwrap()
+            .with_closed_weekday(Weekday::Saturday)
+            .with_closed_weekday(Weekday::Sunday)
+            .with_holiday(MONDAY + 7 * DAY)
+            .with_maintenance(Weekday::Wednesday, 12 * HOUR, 13 * HOUR)
+            .unwrap()
+            .with_pre_open(HOUR)
+            .unwrap()
+    }
+
+    #[rstest]
+    fn weekday_of_timestamp() {
+        assert_eq!(Weekday::of(0), Weekday::Thursday);
+        assert_eq!(Weekday::of(MONDAY + 23 * HOUR), Weekday::Monday);
+        assert_eq!(Weekday::of(MONDAY + 6 * DAY), Weekday::Sunday);
+    }
+
+    #[rstest]
+    fn session_states(calendar: TradingCalendar) {
+        assert_eq!(calendar.state(MONDAY + 6 * HOUR), SessionState::Closed);
+        assert_eq!(calendar.state(MONDAY + 7 * HOUR), SessionState::PreOpen);
+        assert_eq!(calendar.state(MONDAY + 8 * HOUR), SessionState::Open);
+        assert_eq!(calendar.state(MONDAY + 16 * HOUR), SessionState::Closed);
+
+        let wednesday = MONDAY + 2 * DAY;
+        assert_eq!(calendar.state(wednesday + 12 * HOUR), SessionState::Maintenance);
+        assert_eq!(calendar.state(wednesday + 13 * HOUR), SessionState::Open);
+
+        // weekends and holidays are closed during the trading hours too
+        assert_eq!(calendar.state(MONDAY + 5 * DAY + 10 * HOUR), SessionState::Closed);
+        assert_eq!(calendar.state(MONDAY + 7 * DAY + 10 * HOUR), SessionState::Closed);
+        assert_eq!(calendar.state(MONDAY + 8 * DAY + 10 * HOUR), SessionState::Open);
+
+        assert_eq!(
+            TradingCalendar::new(16 * HOUR, 8 * HOUR),
+            Err(CalendarError::InvalidHours {
+                open: 16 * HOUR,
+                close: 8 * HOUR
+            })
+        );
+    }
+}
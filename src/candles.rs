@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::{Timestamp, HOUR, MINUTE, SECOND},
+    market_data::BookEvent,
+    order::{OrderPrice, OrderQuantity},
+};
+
+pub const DEFAULT_INTERVALS: [Timestamp; 4] = [SECOND, MINUTE, 5 * MINUTE, HOUR];
+
+// the trades of one interval, aligned on multiples of it (e.g. 1m candles open on the minute)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Candle {
+    pub open_time: Timestamp,
+    pub interval: Timestamp,
+    pub open: OrderPrice,
+    pub high: OrderPrice,
+    pub low: OrderPrice,
+    pub close: OrderPrice,
+    pub volume: OrderQuantity,
+    pub notional: Decimal,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(open_time: Timestamp, interval: Timestamp, price: OrderPrice, quantity: OrderQuantity) -> Self {
+        Self {
+            open_time,
+            interval,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: quantity,
+            notional: price * quantity,
+            trades: 1,
+        }
+    }
+
+    fn add(&mut self, price: OrderPrice, quantity: OrderQuantity) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += quantity;
+        self.notional += price * quantity;
+        self.trades += 1;
+    }
+
+    #[inline]
+    pub fn close_time(&self) -> Timestamp {
+        self.open_time + self.interval
+    }
+
+    #[inline]
+    pub fn vwap(&self) -> OrderPrice {
+        OrderPrice::from_decimal(self.notional / self.volume)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Series {
+    current: Option<Candle>,
+    history: VecDeque<Candle>, // closed, oldest first
+}
+
+// OHLCV candles per pair out of the trades of the feed, for every interval configured; only the last candles of each
+// are kept (capacity), intervals without trades get no candle
+#[derive(Clone, Debug)]
+pub struct CandleAggregator {
+    intervals: Vec<Timestamp>,
+    capacity: usize,
+    series: IndexMap<CompactString, Vec<Series>>, // by pair, then in the order of the intervals
+}
+
+impl CandleAggregator {
+    pub fn new(intervals: impl IntoIterator<Item = Timestamp>, capacity: usize) -> Result<Self, CandleError> {
+        let intervals: Vec<Timestamp> = intervals.into_iter().collect();
+        if let Some(interval) = intervals.iter().find(|interval| **interval == 0) {
+            return Err(CandleError::InvalidInterval(*interval));
+        }
+        Ok(Self {
+            intervals,
+            capacity,
+            series: IndexMap::new(),
+        })
+    }
+
+    #[inline]
+    pub fn intervals(&self) -> &[Timestamp] {
+        &self.intervals
+    }
+
+    // only the trades of the book make candles: off-book trades are no price discovery, and a bust cannot be taken
+    // out of the candles already closed
+    pub fn observe(&mut self, pair: &str, at: Timestamp, event: &BookEvent) {
+        if let BookEvent::Trade { price, quantity, .. } = *event {
+            self.record(pair, at, price, quantity);
+        }
+    }
+
+    // a trade older than the current candle (e.g. released late) goes to the current one all the same
+    pub fn record(&mut self, pair: &str, at: Timestamp, price: OrderPrice, quantity: OrderQuantity) {
+        let intervals = self.intervals.len();
+        let series = self
+            .series
+            .entry(pair.into())
+            .or_insert_with(|| vec![Series::default(); intervals]);
+        for (interval, series) in self.intervals.iter().zip(series.iter_mut()) {
+            match series.current.as_mut() {
+                Some(candle) if at < candle.close_time() => candle.add(price, quantity),
+                _ => {
+                    let open_time = at - at % interval;
+                    let candle = Candle::new(open_time, *interval, price, quantity);
+                    if let Some(closed) = series.current.replace(candle) {
+                        Self::close(&mut series.history, closed, self.capacity);
+                    }
+                }
+            }
+        }
+    }
+
+    // tick driven: the candles whose interval is over by now are closed even if no trade came after them
+    pub fn roll(&mut self, now: Timestamp) {
+        for series in self.series.values_mut().flatten() {
+            if let Some(candle) = series.current.take_if(|candle| candle.close_time() <= now) {
+                Self::close(&mut series.history, candle, self.capacity);
+            }
+        }
+    }
+
+    fn close(history: &mut VecDeque<Candle>, candle: Candle, capacity: usize) {
+        history.push_back(candle);
+        while history.len() > capacity {
+            history.pop_front();
+        }
+    }
+
+    // the candle still open, none for an interval not configured
+    pub fn current(&self, pair: &str, interval: Timestamp) -> Option<&Candle> {
+        self.series(pair, interval)?.current.as_ref()
+    }
+
+    // the closed candles, oldest first
+    pub fn history(&self, pair: &str, interval: Timestamp) -> impl Iterator<Item = &Candle> {
+        self.series(pair, interval)
+            .into_iter()
+            .flat_map(|series| series.history.iter())
+    }
+
+    fn series(&self, pair: &str, interval: Timestamp) -> Option<&Series> {
+        let index = self.intervals.iter().position(|candidate| *candidate == interval)?;
+        self.series.get(pair).map(|series| &series[index])
+    }
+}
+
+impl Default for CandleAggregator {
+    fn default() -> Self {
+        Self {
+            intervals: DEFAULT_INTERVALS.to_vec(),
+            capacity: 1_000,
+            series: IndexMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CandleError {
+    #[error("invalid candle interval! {0}")]
+    InvalidInterval(Timestamp),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        engine::Engine,
+        market_data::Granularity,
+        order::{
+            util::{self, DEFAULT_PAIR, DEFAULT_SYMBOL},
+            OrderSide,
+        },
+    };
+
+    #[rstest]
+    fn aggregate_trades_per_interval() {
+        let mut candles = CandleAggregator::new([SECOND, MINUTE], 2).unwrap();
+        for (at, price, quantity) in [(100, 15, 1), (900, 17, 2), (1_500, 14, 1), (61_000, 16, 3)] {
+            candles.record(DEFAULT_PAIR, at, price.into(), quantity.into());
+        }
+
+        let minute: Vec<&Candle> = candles.history(DEFAULT_PAIR, MINUTE).collect();
+        assert_eq!(minute.len(), 1);
+        assert_eq!(
+            (minute[0].open, minute[0].high, minute[0].low, minute[0].close),
+            (15.into(), 17.into(), 14.into(), 14.into())
+        );
+        assert_eq!(minute[0].volume, 4.into());
+        assert_eq!(minute[0].vwap(), OrderPrice::new(1575, 2));
+        assert_eq!(candles.current(DEFAULT_PAIR, MINUTE).unwrap().open_time, MINUTE);
+
+        // only the last two seconds are kept, the gap has no candle
+        let seconds: Vec<Timestamp> = candles
+            .history(DEFAULT_PAIR, SECOND)
+            .map(|candle| candle.open_time)
+            .collect();
+        assert_eq!(seconds, vec![0, SECOND]);
+        candles.roll(62_000);
+        assert!(candles.current(DEFAULT_PAIR, SECOND).is_none());
+        assert_eq!(candles.history(DEFAULT_PAIR, SECOND).last().unwrap().open_time, 61_000);
+        assert!(candles.current(DEFAULT_PAIR, HOUR).is_none());
+        assert_eq!(
+            CandleAggregator::new([0], 1).unwrap_err(),
+            CandleError::InvalidInterval(0)
+        );
+    }
+
+    #[rstest]
+    fn aggregate_the_feed() {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        let market_data = engine.subscribe(Granularity::Level);
+        for (order_id, side) in [(1, OrderSide::Ask), (2, OrderSide::Bid)] {
+            engine
+                .process(util::create(order_id, side).quantity(2).build())
+                .unwrap();
+        }
+
+        let mut candles = CandleAggregator::default();
+        for event in market_data.try_iter() {
+            candles.observe(engine.pair().as_str(), 5 * SECOND, &event.event);
+        }
+        let candle = candles.current(DEFAULT_PAIR, 5 * MINUTE).unwrap();
+        assert_eq!((candle.open_time, candle.close, candle.trades), (0, 15.into(), 1));
+    }
+}
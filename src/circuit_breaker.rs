@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::Timestamp, order::OrderPrice};
+
+// what becomes of the requests received while trading is halted
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HaltPolicy {
+    #[default]
+    Reject,
+    Queue, // processed in order once trading resumes
+}
+
+// trips when the price moves more than max_move (relative, e.g. 0.1) away from any price traded within the window
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    max_move: Decimal,
+    window: Timestamp,
+    halt: Timestamp, // how long trading stays halted
+    prices: VecDeque<(Timestamp, OrderPrice)>,
+}
+
+impl CircuitBreaker {
+    #[inline]
+    pub fn new(max_move: Decimal, window: Timestamp, halt: Timestamp) -> Self {
+        Self {
+            max_move,
+            window,
+            halt,
+            prices: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub fn halt(&self) -> Timestamp {
+        self.halt
+    }
+
+    // returns whether the trade tripped the breaker, the window starts over after a trip
+    pub fn record(&mut self, now: Timestamp, price: OrderPrice) -> bool {
+        while let Some(&(traded_at, _)) = self.prices.front() {
+            if traded_at + self.window > now {
+                break;
+            }
+            self.prices.pop_front();
+        }
+
+        let tripped = self
+            .prices
+            .iter()
+            .any(|&(_, reference)| (price - reference).abs() > reference.value() * self.max_move);
+        if tripped {
+            self.prices.clear();
+        } else {
+            self.prices.push_back((now, price));
+        }
+        tripped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::clock::{MINUTE, SECOND};
+
+    #[rstest]
+    fn trip_within_the_window() {
+        // 10% within a minute
+        let mut circuit_breaker = CircuitBreaker::new(Decimal::new(1, 1), MINUTE, 5 * MINUTE);
+        assert!(!circuit_breaker.record(0, 100.into()));
+        assert!(!circuit_breaker.record(10 * SECOND, 109.into()));
+        assert!(circuit_breaker.record(20 * SECOND, 111.into()));
+
+        // the moves older than the window are forgotten
+        assert!(!circuit_breaker.record(30 * SECOND, 100.into()));
+        assert!(!circuit_breaker.record(2 * MINUTE, 120.into()));
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// milliseconds since the UNIX epoch
+pub type Timestamp = u64;
+
+pub const SECOND: u64 = 1_000;
+pub const MINUTE: u64 = 60 * SECOND;
+pub const HOUR: u64 = 60 * MINUTE;
+pub const DAY: u64 = 24 * HOUR;
+
+// the first time at that time of the day (UTC) after now
+#[inline]
+pub fn next_time_of_day(now: Timestamp, time_of_day: Timestamp) -> Timestamp {
+    let midnight = now - now % DAY;
+    match midnight + time_of_day > now {
+        true => midnight + time_of_day,
+        false => midnight + DAY + time_of_day,
+    }
+}
+
+pub trait Clock: Send {
+    fn now(&self) -> Timestamp;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Timestamp {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as Timestamp)
+            .unwrap_or_default()
+    }
+}
+
+// clones share the same time so one handle can be given away (e.g. to the engine) while another one moves the time
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock(Arc<AtomicU64>);
+
+impl ManualClock {
+    pub fn new(now: Timestamp) -> Self {
+        Self(Arc::new(AtomicU64::new(now)))
+    }
+
+    #[inline]
+    pub fn set(&self, now: Timestamp) {
+        self.0.store(now, Relaxed);
+    }
+
+    #[inline]
+    pub fn advance(&self, elapsed: u64) -> Timestamp {
+        self.0.fetch_add(elapsed, Relaxed) + elapsed
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now(&self) -> Timestamp {
+        self.0.load(Relaxed)
+    }
+}
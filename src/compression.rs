@@ -0,0 +1,183 @@
+use std::cmp::Reverse;
+
+use compact_str::CompactString;
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    order::{OrderPrice, OrderQuantity, OrderSide},
+    position::PositionBook,
+};
+
+// only accounts that opted in take part in a compression run
+#[derive(Clone, Debug, Default)]
+pub struct Consents {
+    accounts: IndexSet<CompactString>,
+}
+
+impl Consents {
+    #[inline]
+    pub fn grant(&mut self, account_id: &str) {
+        self.accounts.insert(account_id.into());
+    }
+
+    #[inline]
+    pub fn revoke(&mut self, account_id: &str) {
+        self.accounts.shift_remove(account_id);
+    }
+
+    #[inline]
+    pub fn has_consented(&self, account_id: &str) -> bool {
+        self.accounts.contains(account_id)
+    }
+}
+
+// the long account sells and the short account buys, both at the mark price
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompressionTrade {
+    pub long: CompactString,
+    pub short: CompactString,
+    pub quantity: OrderQuantity,
+    pub price: OrderPrice,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompressionRun {
+    pub trades: Vec<CompressionTrade>,
+    pub open_interest_before: OrderQuantity,
+    pub open_interest_after: OrderQuantity,
+}
+
+impl CompressionRun {
+    #[inline]
+    pub fn compressed(&self) -> OrderQuantity {
+        self.open_interest_before - self.open_interest_after
+    }
+}
+
+// consenting accounts on opposite sides, biggest positions first
+fn candidates(positions: &PositionBook, consents: &Consents, side: OrderSide) -> Vec<(CompactString, OrderQuantity)> {
+    let mut candidates: Vec<(CompactString, OrderQuantity)> = positions
+        .iter()
+        .filter(|(account_id, position)| position.side() == Some(side) && consents.has_consented(account_id))
+        .map(|(account_id, position)| (account_id.clone(), position.quantity()))
+        .collect();
+    candidates.sort_by_key(|(_, size)| Reverse(*size));
+    candidates
+}
+
+// admin triggered: net the positions of consenting longs against consenting shorts at the mark price
+pub fn compress(
+    positions: &mut PositionBook,
+    consents: &Consents,
+    mark_price: OrderPrice,
+) -> Result<CompressionRun, CompressionError> {
+    if mark_price.is_zero() {
+        return Err(CompressionError::InvalidMarkPrice(mark_price));
+    }
+
+    let open_interest_before = positions.open_interest();
+    let mut longs = candidates(positions, consents, OrderSide::Bid).into_iter();
+    let mut shorts = candidates(positions, consents, OrderSide::Ask).into_iter();
+
+    let mut trades = vec![];
+    let (mut long, mut short) = (longs.next(), shorts.next());
+    while let (Some((long_id, long_size)), Some((short_id, short_size))) = (long.as_mut(), short.as_mut()) {
+        let quantity = (*long_size).min(*short_size);
+        positions.apply_fill(long_id, OrderSide::Ask, quantity, mark_price);
+        positions.apply_fill(short_id, OrderSide::Bid, quantity, mark_price);
+        trades.push(CompressionTrade {
+            long: long_id.clone(),
+            short: short_id.clone(),
+            quantity,
+            price: mark_price,
+        });
+
+        *long_size -= quantity;
+        *short_size -= quantity;
+        if long_size.is_zero() {
+            long = longs.next();
+        }
+        if short_size.is_zero() {
+            short = shorts.next();
+        }
+    }
+
+    Ok(CompressionRun {
+        trades,
+        open_interest_before,
+        open_interest_after: positions.open_interest(),
+    })
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CompressionError {
+    #[error("compression needs a positive mark price! {0}")]
+    InvalidMarkPrice(OrderPrice),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    // longs: 1 (10), 2 (4); shorts: 3 (6), 4 (5), 5 (3)
+    #[fixture]
+    fn positions() -> PositionBook {
+        let mut positions = PositionBook::default();
+        positions.apply_fill("1", OrderSide::Bid, 10.into(), 100.into());
+        positions.apply_fill("2", OrderSide::Bid, 4.into(), 100.into());
+        positions.apply_fill("3", OrderSide::Ask, 6.into(), 100.into());
+        positions.apply_fill("4", OrderSide::Ask, 5.into(), 100.into());
+        positions.apply_fill("5", OrderSide::Ask, 3.into(), 100.into());
+        positions
+    }
+
+    #[rstest]
+    fn compress_consenting_accounts(mut positions: PositionBook) {
+        let mut consents = Consents::default();
+        for account_id in ["1", "3", "4", "5"] {
+            consents.grant(account_id);
+        }
+        consents.revoke("5");
+
+        let run = compress(&mut positions, &consents, 110.into()).unwrap();
+
+        // the biggest long is netted against the biggest shorts until one side runs out
+        assert_eq!(run.trades.len(), 2);
+        assert_eq!(
+            run.trades[1],
+            CompressionTrade {
+                long: "1".into(),
+                short: "4".into(),
+                quantity: 4.into(),
+                price: 110.into()
+            }
+        );
+        assert_eq!(run.open_interest_before, OrderQuantity::from(14));
+        assert_eq!(run.open_interest_after, OrderQuantity::from(4));
+        assert_eq!(run.compressed(), OrderQuantity::from(10));
+
+        // closed at the mark price, accounts without consent are untouched
+        assert!(positions.get("3").unwrap().is_flat());
+        assert_eq!(positions.get("1").unwrap().realized_pnl, Decimal::from(100));
+        assert_eq!(positions.get("4").unwrap().size, Decimal::from(-1));
+        assert_eq!(positions.get("2").unwrap().size, Decimal::from(4));
+        assert_eq!(positions.get("5").unwrap().size, Decimal::from(-3));
+    }
+
+    #[rstest]
+    fn nothing_to_compress_without_consents(mut positions: PositionBook) {
+        let run = compress(&mut positions, &Consents::default(), 110.into()).unwrap();
+        assert!(run.trades.is_empty());
+        assert_eq!(run.compressed(), OrderQuantity::ZERO);
+
+        assert_eq!(
+            compress(&mut positions, &Consents::default(), OrderPrice::ZERO),
+            Err(CompressionError::InvalidMarkPrice(OrderPrice::ZERO))
+        );
+    }
+}
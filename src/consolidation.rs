@@ -0,0 +1,252 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::{Timestamp, SECOND},
+    engine::Engine,
+    order::{OrderPrice, OrderQuantity, OrderSide},
+};
+
+// top of the book of one source on one side
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Quote {
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SourceQuote {
+    pub bid: Option<Quote>,
+    pub ask: Option<Quote>,
+    pub updated_at: Timestamp,
+}
+
+// the best price of one side across the sources, with the quantity each of them shows there (first updated first)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConsolidatedLevel {
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+    pub sources: Vec<(CompactString, OrderQuantity)>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConsolidatedBbo {
+    pub bid: Option<ConsolidatedLevel>,
+    pub ask: Option<ConsolidatedLevel>,
+    pub timestamp: Timestamp,
+    pub stale: Vec<CompactString>, // left out of the consolidation
+}
+
+impl ConsolidatedBbo {
+    // the best bid at or above the best ask, as can happen between separate books
+    #[inline]
+    pub fn is_crossed(&self) -> bool {
+        matches!((&self.bid, &self.ask), (Some(bid), Some(ask)) if bid.price >= ask.price)
+    }
+}
+
+// merged top of the book of several engines trading the same instrument (e.g. regional books), fed with the quote of
+// each source as it changes; for routing experiments, nothing is routed from here
+#[derive(Clone, Debug)]
+pub struct BboConsolidator {
+    sources: IndexMap<CompactString, Option<SourceQuote>>, // none until the first update
+    max_age: Timestamp,
+}
+
+impl Default for BboConsolidator {
+    fn default() -> Self {
+        Self {
+            sources: IndexMap::new(),
+            max_age: 10 * SECOND,
+        }
+    }
+}
+
+impl BboConsolidator {
+    pub fn with_max_age(mut self, max_age: Timestamp) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn add_source(&mut self, source: &str) -> Result<(), ConsolidationError> {
+        if self.sources.contains_key(source) {
+            return Err(ConsolidationError::SourceDuplicated(source.into()));
+        }
+        self.sources.insert(source.into(), None);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn remove_source(&mut self, source: &str) -> Option<SourceQuote> {
+        self.sources.shift_remove(source).flatten()
+    }
+
+    #[inline]
+    pub fn source(&self, source: &str) -> Option<&SourceQuote> {
+        self.sources.get(source)?.as_ref()
+    }
+
+    pub fn update(
+        &mut self,
+        source: &str,
+        bid: Option<Quote>,
+        ask: Option<Quote>,
+        now: Timestamp,
+    ) -> Result<(), ConsolidationError> {
+        let quote = self
+            .sources
+            .get_mut(source)
+            .ok_or_else(|| ConsolidationError::SourceNotFound(source.into()))?;
+        *quote = Some(SourceQuote {
+            bid,
+            ask,
+            updated_at: now,
+        });
+        Ok(())
+    }
+
+    // the round-lot book of the engine, the odd lots do not make the quote
+    pub fn observe(&mut self, source: &str, engine: &Engine, now: Timestamp) -> Result<(), ConsolidationError> {
+        let depth = engine.orderbook().depth(1);
+        let quote = |level: Option<&(OrderPrice, OrderQuantity, usize)>| {
+            level.map(|(price, quantity, _)| Quote {
+                price: *price,
+                quantity: *quantity,
+            })
+        };
+        self.update(source, quote(depth.bids.first()), quote(depth.asks.first()), now)
+    }
+
+    // the sources not updated within the max age (or never) are left out, an empty side has no level
+    pub fn bbo(&self, now: Timestamp) -> ConsolidatedBbo {
+        let mut fresh = vec![];
+        let mut stale = vec![];
+        for (source, quote) in &self.sources {
+            match quote {
+                Some(quote) if now.saturating_sub(quote.updated_at) <= self.max_age => fresh.push((source, quote)),
+                _ => stale.push(source.clone()),
+            }
+        }
+        fresh.sort_by_key(|(_, quote)| quote.updated_at);
+
+        ConsolidatedBbo {
+            bid: Self::consolidate(&fresh, OrderSide::Bid),
+            ask: Self::consolidate(&fresh, OrderSide::Ask),
+            timestamp: now,
+            stale,
+        }
+    }
+
+    fn consolidate(sources: &[(&CompactString, &SourceQuote)], side: OrderSide) -> Option<ConsolidatedLevel> {
+        let quotes = sources.iter().filter_map(|(source, quote)| {
+            let quote = match side {
+                OrderSide::Bid => quote.bid,
+                OrderSide::Ask => quote.ask,
+            };
+            quote.map(|quote| (*source, quote))
+        });
+        let best = match side {
+            OrderSide::Bid => quotes.clone().map(|(_, quote)| quote.price).max(),
+            OrderSide::Ask => quotes.clone().map(|(_, quote)| quote.price).min(),
+        }?;
+        let sources: Vec<(CompactString, OrderQuantity)> = quotes
+            .filter(|(_, quote)| quote.price == best)
+            .map(|(source, quote)| (source.clone(), quote.quantity))
+            .collect();
+        Some(ConsolidatedLevel {
+            price: best,
+            quantity: sources.iter().map(|(_, quantity)| *quantity).sum(),
+            sources,
+        })
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ConsolidationError {
+    #[error("quote source already exists! {0}")]
+    SourceDuplicated(CompactString),
+    #[error("quote source not found! {0}")]
+    SourceNotFound(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::order::util::{self, DEFAULT_SYMBOL};
+
+    #[fixture]
+    fn consolidator() -> BboConsolidator {
+        let mut consolidator = BboConsolidator::default();
+        for source in ["east", "west", "north"] {
+            consolidator.add_source(source).unwrap();
+        }
+        consolidator
+    }
+
+    fn quote(price: u32, quantity: u32) -> Option<Quote> {
+        Some(Quote {
+            price: price.into(),
+            quantity: quantity.into(),
+        })
+    }
+
+    #[rstest]
+    fn attribute_the_best_prices(mut consolidator: BboConsolidator) {
+        consolidator
+            .update("west", quote(99, 2), quote(102, 1), SECOND)
+            .unwrap();
+        consolidator.update("east", quote(100, 3), quote(102, 4), 0).unwrap();
+        consolidator.update("north", quote(100, 1), None, 2 * SECOND).unwrap();
+
+        let bbo = consolidator.bbo(2 * SECOND);
+        assert!(!bbo.is_crossed());
+        let bid = bbo.bid.unwrap();
+        assert_eq!((bid.price, bid.quantity), (100.into(), 4.into()));
+        assert_eq!(bid.sources, vec![("east".into(), 3.into()), ("north".into(), 1.into())]);
+        let ask = bbo.ask.unwrap();
+        assert_eq!(ask.sources, vec![("east".into(), 4.into()), ("west".into(), 1.into())]);
+
+        // east goes stale, west crosses north
+        consolidator
+            .update("west", quote(101, 2), quote(103, 1), 20 * SECOND)
+            .unwrap();
+        consolidator
+            .update("north", quote(100, 1), quote(101, 5), 20 * SECOND)
+            .unwrap();
+        let bbo = consolidator.bbo(20 * SECOND);
+        assert_eq!(bbo.stale, vec!["east"]);
+        assert!(bbo.is_crossed());
+        assert_eq!(bbo.bid.unwrap().sources, vec![("west".into(), 2.into())]);
+
+        assert_eq!(
+            consolidator.update("south", None, None, 0),
+            Err(ConsolidationError::SourceNotFound("south".into()))
+        );
+        assert!(consolidator.add_source("east").is_err());
+    }
+
+    #[rstest]
+    fn observe_the_engines(mut consolidator: BboConsolidator) {
+        let mut engines = [Engine::new(DEFAULT_SYMBOL), Engine::new(DEFAULT_SYMBOL)];
+        for (engine, price) in engines.iter_mut().zip([15, 14]) {
+            engine
+                .process(
+                    util::create(1, OrderSide::Bid)
+                        .limit_price(Some(price.into()))
+                        .quantity(2)
+                        .build(),
+                )
+                .unwrap();
+        }
+        consolidator.observe("east", &engines[0], 0).unwrap();
+        consolidator.observe("west", &engines[1], 0).unwrap();
+
+        let bbo = consolidator.bbo(0);
+        assert_eq!(bbo.bid.unwrap().sources, vec![("east".into(), 2.into())]);
+        assert_eq!((bbo.ask, bbo.stale), (None, vec!["north".into()]));
+    }
+}
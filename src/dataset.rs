@@ -0,0 +1,494 @@
+use std::{io::Write, path::Path};
+
+use compact_str::{format_compact, CompactString};
+use crossbeam_channel::Receiver;
+use indexmap::IndexMap;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::Timestamp,
+    engine::{
+        journal::{Journal, JournalEntry, JournalError, JournalEvent},
+        Engine, EngineError,
+    },
+    market_data::{BookEvent, Granularity, MarketDataEvent},
+    order::{id::decompose, OrderId, OrderRequest, QuoteLevel, QuoteSide, TimeInForce},
+    replay::Fnv,
+};
+
+// how the timestamps of a dataset are disguised
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "policy")]
+pub enum JitterPolicy {
+    #[default]
+    Keep,
+    // the first timestamp becomes `to`, the spacing is kept
+    Shift {
+        to: Timestamp,
+    },
+    // each one moved by up to `max` either way
+    Uniform {
+        max: Timestamp,
+    },
+    // down to a multiple of `to`
+    Round {
+        to: Timestamp,
+    },
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DatasetConfig {
+    pub salt: CompactString, // the secret of the pseudonyms: the same salt gives the same ones across datasets
+    pub jitter: JitterPolicy,
+    pub seed: u64, // of the uniform jitter, so that an export can be reproduced
+    // order ids given by an IdGenerator of this epoch carry the time of their request, which is then exported jittered
+    pub id_epoch: Option<Timestamp>,
+}
+
+// one journal entry with the book events it caused, one JSON line each
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DatasetRecord {
+    pub sequence: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>, // of the creates, when their order id tells it
+    pub order_request: OrderRequest,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+    pub book: Vec<MarketDataEvent>,
+}
+
+// accounts and client order ids become salted hashes, order ids are numbered again in order of appearance (the ids of
+// an IdGenerator tell the time of the request) and the timestamps are jittered; prices, quantities and the order of
+// the requests are left as they are
+struct Anonymizer {
+    config: DatasetConfig,
+    rng: StdRng,
+    order_ids: IndexMap<u64, u64>,
+    offset: Option<i128>, // of the shift
+    last: Option<Timestamp>,
+}
+
+impl Anonymizer {
+    fn new(config: DatasetConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            order_ids: IndexMap::new(),
+            offset: None,
+            last: None,
+        }
+    }
+
+    // no account stays no account
+    fn pseudonym(&self, id: &str) -> CompactString {
+        if id.is_empty() {
+            return CompactString::default();
+        }
+        let mut fnv = Fnv::default();
+        fnv.write(&self.config.salt);
+        fnv.write(id);
+        format_compact!("{:016x}", fnv.0)
+    }
+
+    fn order_id(&mut self, order_id: u64) -> u64 {
+        let next = self.order_ids.len() as u64 + 1;
+        *self.order_ids.entry(order_id).or_insert(next)
+    }
+
+    // the requests keep their order
+    fn timestamp(&mut self, at: Timestamp) -> Timestamp {
+        let moved = self.jitter(at);
+        let moved = self.last.map_or(moved, |last| moved.max(last));
+        self.last = Some(moved);
+        moved
+    }
+
+    fn jitter(&mut self, at: Timestamp) -> Timestamp {
+        let moved = match self.config.jitter {
+            JitterPolicy::Keep => return at,
+            JitterPolicy::Shift { to } => at as i128 + *self.offset.get_or_insert(to as i128 - at as i128),
+            JitterPolicy::Uniform { max } => at as i128 + self.rng.gen_range(-(max as i128)..=max as i128),
+            JitterPolicy::Round { to } if to > 0 => return at - at % to,
+            JitterPolicy::Round { .. } => return at,
+        };
+        moved.clamp(0, Timestamp::MAX as i128) as Timestamp
+    }
+
+    fn order_request(&mut self, order_request: &OrderRequest) -> OrderRequest {
+        match order_request.clone() {
+            OrderRequest::Create {
+                account_id,
+                order_id,
+                pair,
+                side,
+                limit_price,
+                quantity,
+                time_in_force,
+                short_sell,
+                priority_fee,
+                client_order_id,
+                price_protection,
+                reduce_only,
+            } => OrderRequest::Create {
+                account_id: self.pseudonym(&account_id),
+                order_id: self.order_id(order_id),
+                pair,
+                side,
+                limit_price,
+                quantity,
+                time_in_force: time_in_force.map(|time_in_force| match time_in_force {
+                    TimeInForce::GoodTilDate { expires_at, post_only } => TimeInForce::GoodTilDate {
+                        expires_at: self.jitter(expires_at),
+                        post_only,
+                    },
+                    time_in_force => time_in_force,
+                }),
+                short_sell,
+                priority_fee,
+                client_order_id: client_order_id.map(|client_order_id| self.pseudonym(&client_order_id)),
+                price_protection,
+                reduce_only,
+            },
+            OrderRequest::Cancel { account_id, order_id } => OrderRequest::Cancel {
+                account_id: self.pseudonym(&account_id),
+                order_id: self.order_id(order_id),
+            },
+            OrderRequest::CancelByClientId {
+                account_id,
+                client_order_id,
+            } => OrderRequest::CancelByClientId {
+                account_id: self.pseudonym(&account_id),
+                client_order_id: self.pseudonym(&client_order_id),
+            },
+            OrderRequest::CancelAll { account_id } => OrderRequest::CancelAll {
+                account_id: self.pseudonym(&account_id),
+            },
+            OrderRequest::MassQuote {
+                account_id,
+                pair,
+                levels,
+            } => OrderRequest::MassQuote {
+                account_id: self.pseudonym(&account_id),
+                pair,
+                levels: levels
+                    .into_iter()
+                    .map(|level| {
+                        let mut quote = |side: QuoteSide| QuoteSide {
+                            order_id: self.order_id(side.order_id),
+                            ..side
+                        };
+                        QuoteLevel {
+                            bid: level.bid.map(&mut quote),
+                            ask: level.ask.map(&mut quote),
+                        }
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    fn event(&mut self, event: &JournalEvent) -> JournalEvent {
+        match event.clone() {
+            JournalEvent::Created { order_id, matched } => JournalEvent::Created {
+                order_id: self.order_id(order_id),
+                matched,
+            },
+            JournalEvent::Cancelled { order_id } => JournalEvent::Cancelled {
+                order_id: self.order_id(order_id),
+            },
+            JournalEvent::CancelledAll { account_id, order_ids } => JournalEvent::CancelledAll {
+                account_id: self.pseudonym(&account_id),
+                order_ids: order_ids.into_iter().map(|order_id| self.order_id(order_id)).collect(),
+            },
+            JournalEvent::Expired { order_id } => JournalEvent::Expired {
+                order_id: self.order_id(order_id),
+            },
+            JournalEvent::Busted { trade_id, maker, taker } => JournalEvent::Busted {
+                trade_id,
+                maker: self.order_id(maker),
+                taker: self.order_id(taker),
+            },
+            JournalEvent::Rejected { order_id, reason } => JournalEvent::Rejected {
+                order_id: self.order_id(order_id),
+                reason: self.reason(&reason),
+            },
+            JournalEvent::Quoted {
+                account_id,
+                cancelled,
+                created,
+                matched,
+            } => JournalEvent::Quoted {
+                account_id: self.pseudonym(&account_id),
+                cancelled: cancelled.into_iter().map(|order_id| self.order_id(order_id)).collect(),
+                created: created.into_iter().map(|order_id| self.order_id(order_id)).collect(),
+                matched,
+            },
+        }
+    }
+
+    // the ids a reason names as key=value (e.g. insufficient funds (account_id=1, ...)) are replaced too
+    fn reason(&mut self, reason: &str) -> CompactString {
+        let mut scrubbed = CompactString::default();
+        for token in reason.split_inclusive([' ', '(', ',']) {
+            let value = token.trim_end_matches([' ', '(', ',', ')']);
+            let end = &token[value.len()..];
+            match value.split_once('=') {
+                Some((key @ ("account_id" | "client_order_id"), id)) => {
+                    scrubbed.push_str(&format_compact!("{key}={}{end}", self.pseudonym(id)));
+                }
+                Some(("order_id", order_id)) if order_id.parse::<u64>().is_ok() => {
+                    let order_id = self.order_id(order_id.parse().unwrap_or_default());
+                    scrubbed.push_str(&format_compact!("order_id={order_id}{end}"));
+                }
+                _ => scrubbed.push_str(token),
+            }
+        }
+        scrubbed
+    }
+
+    fn book_event(&mut self, MarketDataEvent { sequence, event }: MarketDataEvent) -> MarketDataEvent {
+        let mut order_id = |order_id: OrderId| OrderId::new(self.order_id(order_id.into()));
+        let event = match event {
+            BookEvent::Add {
+                order_id: id,
+                side,
+                price,
+                quantity,
+            } => BookEvent::Add {
+                order_id: id.map(&mut order_id),
+                side,
+                price,
+                quantity,
+            },
+            BookEvent::Modify {
+                order_id: id,
+                side,
+                price,
+                quantity,
+            } => BookEvent::Modify {
+                order_id: id.map(&mut order_id),
+                side,
+                price,
+                quantity,
+            },
+            BookEvent::Delete {
+                order_id: id,
+                side,
+                price,
+            } => BookEvent::Delete {
+                order_id: id.map(&mut order_id),
+                side,
+                price,
+            },
+            BookEvent::Trade {
+                trade_id,
+                maker,
+                taker,
+                side,
+                price,
+                quantity,
+            } => BookEvent::Trade {
+                trade_id,
+                maker: order_id(maker),
+                taker: order_id(taker),
+                side,
+                price,
+                quantity,
+            },
+            event => event,
+        };
+        MarketDataEvent { sequence, event }
+    }
+}
+
+// research datasets out of journals: every entry anonymized, with the book events (order by order) it caused when
+// replayed through a fresh engine set up like the one that wrote the journal
+pub struct DatasetExporter {
+    engine: Engine,
+    market_data: Receiver<MarketDataEvent>,
+    anonymizer: Anonymizer,
+}
+
+impl DatasetExporter {
+    pub fn new(mut engine: Engine, config: DatasetConfig) -> Self {
+        let market_data = engine.subscribe(Granularity::Order);
+        Self {
+            engine,
+            market_data,
+            anonymizer: Anonymizer::new(config),
+        }
+    }
+
+    // the entries in journal order, the replay must reach the outcome journaled
+    pub fn record(&mut self, entry: JournalEntry) -> Result<DatasetRecord, DatasetError> {
+        let timestamp = match (&entry.order_request, self.anonymizer.config.id_epoch) {
+            (OrderRequest::Create { order_id, .. }, Some(epoch)) => {
+                let (at, _, _) = decompose(OrderId::new(*order_id), epoch);
+                Some(self.anonymizer.timestamp(at))
+            }
+            _ => None,
+        };
+        let sequence = entry.sequence;
+        let order_request = self.anonymizer.order_request(&entry.order_request);
+        let event = self.anonymizer.event(&entry.event);
+        self.engine.apply(entry)?;
+        let book = self
+            .market_data
+            .try_iter()
+            .map(|event| self.anonymizer.book_event(event))
+            .collect();
+        Ok(DatasetRecord {
+            sequence,
+            timestamp,
+            order_request,
+            event,
+            book,
+        })
+    }
+
+    // one record per line, returns how many were written
+    pub fn export(&mut self, journal: impl AsRef<Path>, mut out: impl Write) -> Result<u64, DatasetError> {
+        let mut records = 0;
+        for entry in Journal::read(journal)? {
+            let record = self.record(entry?)?;
+            serde_json::to_writer(&mut out, &record)?;
+            out.write_all(b"\n")?;
+            records += 1;
+        }
+        out.flush()?;
+        Ok(records)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DatasetError {
+    #[error("dataset io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("dataset format error: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("journal error: {0}")]
+    JournalError(#[from] JournalError),
+    #[error("engine error: {0}")]
+    EngineError(#[from] EngineError),
+}
+
+#[cfg(test)]
+mod test {
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        clock::{ManualClock, SECOND},
+        order::{
+            id::{IdGenerator, EPOCH},
+            util::{self, DEFAULT_SYMBOL},
+            OrderSide,
+        },
+    };
+
+    fn create(account_id: &str, order_id: OrderId, side: OrderSide) -> OrderRequest {
+        util::create(order_id.into(), side)
+            .account_id(account_id)
+            .client_order_id(Some("secret".into()))
+            .build()
+    }
+
+    #[rstest]
+    fn export_an_anonymized_journal() {
+        let path = util::TempPath::new("dataset.jsonl");
+        let clock = ManualClock::new(EPOCH + 1_000 * SECOND);
+        let mut ids = IdGenerator::new(clock.clone(), EPOCH, 0).unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_journal(Journal::open(&path).unwrap());
+        engine
+            .process(create("alice", ids.next_id().unwrap(), OrderSide::Ask))
+            .unwrap();
+        clock.advance(SECOND);
+        engine
+            .process(create("bob", ids.next_id().unwrap(), OrderSide::Bid))
+            .unwrap();
+        drop(engine);
+
+        let config = DatasetConfig {
+            salt: "pepper".into(),
+            jitter: JitterPolicy::Shift { to: 0 },
+            seed: 0,
+            id_epoch: Some(EPOCH),
+        };
+        let mut out = vec![];
+        let mut exporter = DatasetExporter::new(Engine::new(DEFAULT_SYMBOL), config);
+        assert_eq!(exporter.export(&path, &mut out).unwrap(), 2);
+
+        let records: Vec<DatasetRecord> = out
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(
+            records.iter().map(|record| record.timestamp).collect::<Vec<_>>(),
+            vec![Some(0), Some(SECOND)]
+        );
+        let text = String::from_utf8(out).unwrap();
+        assert!(!text.contains("alice") && !text.contains("bob") && !text.contains("secret"));
+
+        let OrderRequest::Create {
+            account_id,
+            order_id,
+            client_order_id,
+            ..
+        } = &records[0].order_request
+        else {
+            unreachable!()
+        };
+        assert_eq!(account_id.len(), 16);
+        assert_eq!(
+            (*order_id, client_order_id.as_ref().map(CompactString::len)),
+            (1, Some(16))
+        );
+
+        // the trade between the two, on the ids numbered again
+        assert_eq!(
+            records[1].event,
+            JournalEvent::Created {
+                order_id: 2,
+                matched: true
+            }
+        );
+        assert!(records[1].book.iter().any(|event| matches!(
+            event.event,
+            BookEvent::Trade { maker, taker, .. } if (u64::from(maker), u64::from(taker)) == (1, 2)
+        )));
+    }
+
+    #[rstest]
+    #[case(JitterPolicy::Keep, vec![1_500, 1_500, 3_700])]
+    #[case(JitterPolicy::Shift { to: 100 }, vec![100, 100, 2_300])]
+    #[case(JitterPolicy::Round { to: 1_000 }, vec![1_000, 1_000, 3_000])]
+    fn jitter_the_timestamps(#[case] jitter: JitterPolicy, #[case] expected: Vec<Timestamp>) {
+        let mut anonymizer = Anonymizer::new(DatasetConfig {
+            jitter,
+            ..Default::default()
+        });
+        // never before the previous one
+        let timestamps: Vec<Timestamp> = [1_500, 1_200, 3_700]
+            .into_iter()
+            .map(|at| anonymizer.timestamp(at))
+            .collect();
+        assert_eq!(timestamps, expected);
+    }
+
+    #[rstest]
+    fn jitter_within_bounds_and_scrub_the_reasons() {
+        let mut anonymizer = Anonymizer::new(DatasetConfig {
+            jitter: JitterPolicy::Uniform { max: 100 },
+            ..Default::default()
+        });
+        assert!((900..=1_100).contains(&anonymizer.jitter(1_000)));
+        assert_eq!(
+            anonymizer.reason("no such order (order_id=42, account_id=1)"),
+            format_compact!("no such order (order_id=1, account_id={})", anonymizer.pseudonym("1"))
+        );
+    }
+}
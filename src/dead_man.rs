@@ -0,0 +1,118 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::clock::Timestamp;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Countdown {
+    pub timeout: Timestamp,
+    pub deadline: Timestamp,
+}
+
+// dead man's switch per account: once armed, the countdown must be refreshed before its deadline or every order of the
+// account is cancelled (e.g. an algo that hangs with orders in the book)
+#[derive(Clone, Debug)]
+pub struct DeadManSwitch {
+    min_timeout: Timestamp,
+    countdowns: IndexMap<CompactString, Countdown>,
+}
+
+impl DeadManSwitch {
+    #[inline]
+    pub fn new(min_timeout: Timestamp) -> Self {
+        Self {
+            min_timeout,
+            countdowns: IndexMap::new(),
+        }
+    }
+
+    // arming again restarts the countdown with the new timeout, returns the deadline
+    pub fn arm(&mut self, account_id: &str, timeout: Timestamp, now: Timestamp) -> Result<Timestamp, DeadManError> {
+        if timeout < self.min_timeout {
+            return Err(DeadManError::TimeoutTooShort {
+                timeout,
+                min_timeout: self.min_timeout,
+            });
+        }
+
+        let deadline = now + timeout;
+        self.countdowns
+            .insert(account_id.into(), Countdown { timeout, deadline });
+        Ok(deadline)
+    }
+
+    // restarts the countdown with the timeout it was armed with, returns the deadline
+    pub fn refresh(&mut self, account_id: &str, now: Timestamp) -> Result<Timestamp, DeadManError> {
+        let countdown = self
+            .countdowns
+            .get_mut(account_id)
+            .ok_or_else(|| DeadManError::NotArmed(account_id.into()))?;
+        countdown.deadline = countdown.deadline.max(now + countdown.timeout);
+        Ok(countdown.deadline)
+    }
+
+    #[inline]
+    pub fn disarm(&mut self, account_id: &str) -> Option<Countdown> {
+        self.countdowns.shift_remove(account_id)
+    }
+
+    #[inline]
+    pub fn countdown(&self, account_id: &str) -> Option<&Countdown> {
+        self.countdowns.get(account_id)
+    }
+
+    // disarms the countdowns past their deadline, returns their accounts by deadline
+    pub fn trip(&mut self, now: Timestamp) -> Vec<CompactString> {
+        let mut tripped: Vec<(Timestamp, CompactString)> = self
+            .countdowns
+            .iter()
+            .filter(|(_, countdown)| countdown.deadline <= now)
+            .map(|(account_id, countdown)| (countdown.deadline, account_id.clone()))
+            .collect();
+        tripped.sort();
+        for (_, account_id) in &tripped {
+            self.countdowns.shift_remove(account_id);
+        }
+        tripped.into_iter().map(|(_, account_id)| account_id).collect()
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DeadManError {
+    #[error("dead man's switch timeout too short (timeout={}, min_timeout={})", .timeout, .min_timeout)]
+    TimeoutTooShort { timeout: Timestamp, min_timeout: Timestamp },
+    #[error("dead man's switch not armed! {0}")]
+    NotArmed(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::clock::SECOND;
+
+    #[rstest]
+    fn trip_unless_refreshed() {
+        let mut switch = DeadManSwitch::new(SECOND);
+        assert_eq!(
+            switch.arm("1", 500, 0),
+            Err(DeadManError::TimeoutTooShort {
+                timeout: 500,
+                min_timeout: SECOND
+            })
+        );
+        assert_eq!(switch.arm("1", 5 * SECOND, 0), Ok(5 * SECOND));
+        assert_eq!(switch.arm("2", 3 * SECOND, 0), Ok(3 * SECOND));
+        assert_eq!(switch.arm("3", 4 * SECOND, 0), Ok(4 * SECOND));
+
+        assert_eq!(switch.refresh("1", 4 * SECOND), Ok(9 * SECOND));
+        assert_eq!(switch.refresh("4", 4 * SECOND), Err(DeadManError::NotArmed("4".into())));
+        assert!(switch.disarm("3").is_some());
+        assert!(switch.trip(2 * SECOND).is_empty());
+        assert_eq!(switch.trip(10 * SECOND), vec!["2", "1"]);
+        assert!(switch.countdown("1").is_none());
+    }
+}
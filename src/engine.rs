@@ -1,64 +1,4310 @@
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+use std::{collections::BTreeSet, path::Path};
+
 use anyhow::Result;
-use compact_str::CompactString;
+use compact_str::{format_compact, CompactString};
+use crossbeam_channel::Receiver;
+use indexmap::{IndexMap, IndexSet};
+use rust_decimal::{Decimal, RoundingStrategy};
 use thiserror::Error;
+use tracing::{info, warn};
 
 use crate::{
-    order::{Order, OrderRequest},
-    orderbook::Orderbook,
+    accounts::{AccountError, AccountManager},
+    batching::Batcher,
+    billing::{MessageBilling, MessageKind, MessagePricing},
+    calendar::{SessionState, TradingCalendar},
+    circuit_breaker::{CircuitBreaker, HaltPolicy},
+    clock::{next_time_of_day, Clock, Timestamp, DAY},
+    dead_man::{DeadManError, DeadManSwitch},
+    fees::{FeeReport, FeeSchedule},
+    fx::{FxError, FxRates},
+    ledger::{Ledger, LedgerError, PostingKind, Statement},
+    locate::{Locate, LocateError},
+    lots::{LotRules, OddLotHandling},
+    market_data::{
+        BboUpdate, BookEvent, ConflatedBbo, EventVerbosity, Granularity, MarketData, MarketDataEvent, TradeDeferral,
+    },
+    memory::{MemoryAlert, MemoryMonitor, Subsystem},
+    metrics::{Metric, MetricsStore},
+    order::{Order, OrderId, OrderPrice, OrderQuantity, OrderRequest, OrderSide, QuoteLevel, QuoteSide, TimeInForce},
+    orderbook::{MatchingPolicy, Orderbook, OrderbookError, QueuePriority, SweepLimits, Uncross, YieldHandler},
+    position::PositionBook,
+    review::{FlaggedTrade, ReviewDecision, ReviewError, ReviewQueue},
+    risk::{RiskError, RiskLimits},
+    session::{Session, SessionError, SessionEvent, SessionManager},
+    speed_bump::SpeedBump,
+    symbol::Symbol,
+    ticker::{Ticker, TickerStats},
+    trade::{OffBookTrade, Trade, TradeId},
+};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::prometheus::EngineCounters;
+#[cfg(feature = "scripting")]
+use crate::scripting::{ScriptError, ScriptRules};
+#[cfg(feature = "storage")]
+use crate::storage::{StorageError, StoredTrade, TradeStore};
+
+use self::{
+    history::{OrderHistory, OrderStatusReport},
+    journal::{Journal, JournalEntry, JournalError, JournalEvent},
+    listener::ExecutionListener,
+    recovery::{EngineSnapshot, Recovery, RecoveryError, RestingOrder, SnapshotConfig},
+    watermark::{IdWatermark, WatermarkError},
 };
 
+pub mod history;
+pub mod journal;
+pub mod listener;
+pub mod recovery;
+pub mod watermark;
+
 pub struct Engine {
-    _pair: CompactString,
+    pair: Symbol,
     orderbook: Orderbook,
+    billing: MessageBilling,
+    ledger: Ledger,
+    journal: Option<Journal>,
+    listener: Option<Box<dyn ExecutionListener>>,
+    watermark: Option<IdWatermark>,
+    market_data: MarketData,
+    fee_asset: Option<CompactString>,
+    fx_rates: FxRates,
+    expiries: BTreeSet<(Timestamp, u64)>, // good-til-date orders by expiry, they may be gone from the book already
+    calendar: Option<(TradingCalendar, Box<dyn Clock>)>,
+    session_end: Option<(Timestamp, Box<dyn Clock>)>, // time of the day the day orders expire at
+    price_protection: Option<Decimal>,                // band of the market orders through the opposite best
+    session: SessionState,
+    indicative: Option<Uncross>, // last preview of the opening uncross published
+    accounts: Option<AccountManager>,
+    owners: IndexMap<OrderId, CompactString>,      // account of every open order
+    quotes: IndexMap<CompactString, Vec<OrderId>>, // of the last mass quote of every account, some may be gone
+    histories: IndexMap<OrderId, OrderHistory>,    // every order accepted, with its fills
+    fee_schedule: Option<FeeSchedule>,
+    fee_reports: IndexMap<CompactString, FeeReport>,
+    locate: Option<Box<dyn Locate>>,
+    positions: Option<PositionBook>, // of every account, from its fills
+    lot_rules: Option<LotRules>,
+    odd_lots: Orderbook,                      // odd lots matched apart from the round lots
+    held_trades: Vec<(Timestamp, BookEvent)>, // trades not on the feed yet, by release time
+    deferral: Option<(TradeDeferral, Box<dyn Clock>)>,
+    risk_limits: Option<RiskLimits>,
+    off_book_trades: Vec<OffBookTrade>,
+    circuit_breaker: Option<(CircuitBreaker, Box<dyn Clock>)>,
+    halted_until: Option<Timestamp>, // halts of the circuit breaker are lifted on their own
+    post_only_reopen: Option<(Timestamp, Box<dyn Clock>)>, // how long a resumed book only takes post-only orders
+    post_only_until: Option<Timestamp>,
+    halt_policy: HaltPolicy,
+    queued: Vec<OrderRequest>, // received during a halt
+    review: Option<(ReviewQueue, Box<dyn Clock>)>,
+    batching: Option<(Batcher, Box<dyn Clock>)>,
+    sessions: Option<(SessionManager, Box<dyn Clock>)>,
+    dead_man: Option<(DeadManSwitch, Box<dyn Clock>)>,
+    speed_bump: Option<(SpeedBump, Box<dyn Clock>)>,
+    ticker: Option<(TickerStats, Box<dyn Clock>)>,
+    metrics: Option<(MetricsStore, Box<dyn Clock>)>,
+    memory: Option<(MemoryMonitor, Box<dyn Clock>)>,
+    snapshots: Option<(SnapshotConfig, Box<dyn Clock>)>,
+    recovery: Option<Recovery>,
+    #[cfg(feature = "metrics")]
+    counters: EngineCounters,
+    #[cfg(feature = "scripting")]
+    script_rules: Option<ScriptRules>,
+    #[cfg(feature = "storage")]
+    trade_store: Option<(TradeStore, Box<dyn Clock>)>,
+    in_batch: bool,               // book events are published once at the end of process_batch
+    ticked_at: Option<Timestamp>, // time of the tick running, every clock of the engine reads it meanwhile
 }
 
 impl Engine {
     #[inline]
-    pub fn new(pair: &str) -> Self {
+    pub fn new(pair: Symbol) -> Self {
         Self {
-            _pair: CompactString::new_inline(pair),
+            pair,
             orderbook: Orderbook::default(),
+            billing: MessageBilling::default(),
+            ledger: Ledger::default(),
+            journal: None,
+            listener: None,
+            watermark: None,
+            market_data: MarketData::default(),
+            fee_asset: None,
+            fx_rates: FxRates::default(),
+            expiries: BTreeSet::new(),
+            calendar: None,
+            session_end: None,
+            price_protection: None,
+            session: SessionState::Open,
+            indicative: None,
+            accounts: None,
+            owners: IndexMap::new(),
+            quotes: IndexMap::new(),
+            histories: IndexMap::new(),
+            fee_schedule: None,
+            fee_reports: IndexMap::new(),
+            locate: None,
+            positions: None,
+            lot_rules: None,
+            odd_lots: Orderbook::default(),
+            held_trades: vec![],
+            deferral: None,
+            risk_limits: None,
+            off_book_trades: vec![],
+            circuit_breaker: None,
+            halted_until: None,
+            post_only_reopen: None,
+            post_only_until: None,
+            halt_policy: HaltPolicy::default(),
+            queued: vec![],
+            review: None,
+            batching: None,
+            sessions: None,
+            dead_man: None,
+            speed_bump: None,
+            ticker: None,
+            metrics: None,
+            memory: None,
+            snapshots: None,
+            recovery: None,
+            #[cfg(feature = "metrics")]
+            counters: EngineCounters::default(),
+            #[cfg(feature = "scripting")]
+            script_rules: None,
+            #[cfg(feature = "storage")]
+            trade_store: None,
+            in_batch: false,
+            ticked_at: None,
+        }
+    }
+
+    pub fn with_message_pricing(mut self, pricing: impl MessagePricing + 'static) -> Self {
+        self.billing = MessageBilling::new(pricing);
+        self
+    }
+
+    // fees are charged in the quote asset of the pair unless another asset is set (converted with the FX rates)
+    pub fn with_fee_asset(mut self, asset: &str) -> Self {
+        self.fee_asset = Some(asset.into());
+        self
+    }
+
+    // the classes of market data events generated for the pair (see VerbosityConfig)
+    pub fn with_verbosity(mut self, verbosity: EventVerbosity) -> Self {
+        self.market_data.set_verbosity(verbosity);
+        self
+    }
+
+    pub fn with_fx_rates(mut self, fx_rates: FxRates) -> Self {
+        self.fx_rates = fx_rates;
+        self
+    }
+
+    // the session follows the calendar on every request, without a calendar the engine is always open
+    pub fn with_calendar(mut self, calendar: TradingCalendar, clock: impl Clock + 'static) -> Self {
+        self.session = calendar.state(clock.now());
+        self.calendar = Some((calendar, Box::new(clock)));
+        self
+    }
+
+    // day orders expire at that time of the day (UTC), without it at the close of the calendar
+    pub fn with_session_end(mut self, time_of_day: Timestamp, clock: impl Clock + 'static) -> Self {
+        self.session_end = Some((time_of_day % DAY, Box::new(clock)));
+        self
+    }
+
+    // limit prices are whole ticks of the pair, the others are rejected
+    pub fn with_tick_size(mut self, tick_size: OrderPrice) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_tick_size(tick_size);
+        self.odd_lots = std::mem::take(&mut self.odd_lots).with_tick_size(tick_size);
+        self
+    }
+
+    // post-only orders crossing the spread rest one tick behind the best opposite price instead of being rejected
+    pub fn with_post_only_repricing(mut self, tick_size: OrderPrice) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_post_only_repricing(tick_size);
+        self.odd_lots = std::mem::take(&mut self.odd_lots).with_post_only_repricing(tick_size);
+        self
+    }
+
+    // times the orders as they join the book, see Priority
+    pub fn with_priority_clock(mut self, clock: impl Clock + Clone + 'static) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_clock(clock.clone());
+        self.odd_lots = std::mem::take(&mut self.odd_lots).with_clock(clock);
+        self
+    }
+
+    // experimental: orders may pay a priority fee to rest ahead of the others at their price, they pay it once resting
+    pub fn with_queue_priority(mut self, queue_priority: QueuePriority) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_queue_priority(queue_priority);
+        self.odd_lots = std::mem::take(&mut self.odd_lots).with_queue_priority(queue_priority);
+        self
+    }
+
+    // e.g. pro-rata for futures, see MatchingPolicy
+    pub fn with_matching_policy(mut self, matching_policy: MatchingPolicy) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_matching_policy(matching_policy);
+        self.odd_lots = std::mem::take(&mut self.odd_lots).with_matching_policy(matching_policy);
+        self
+    }
+
+    // evaluated before the other pre-trade checks, see ScriptRules
+    #[cfg(feature = "scripting")]
+    pub fn with_script_rules(mut self, script_rules: ScriptRules) -> Self {
+        self.script_rules = Some(script_rules);
+        self
+    }
+
+    // tick driven: picks up the changes of the script file, if any
+    #[cfg(feature = "scripting")]
+    pub fn reload_script_rules(&mut self) -> Result<bool, EngineError> {
+        match self.script_rules.as_mut() {
+            Some(script_rules) => Ok(script_rules.reload()?),
+            None => Ok(false),
+        }
+    }
+
+    // every trade is persisted as it settles, timed by the clock (see TradeStore)
+    #[cfg(feature = "storage")]
+    pub fn with_trade_store(mut self, trade_store: TradeStore, clock: impl Clock + 'static) -> Self {
+        self.trade_store = Some((trade_store, Box::new(clock)));
+        self
+    }
+
+    #[cfg(feature = "storage")]
+    #[inline]
+    pub fn trade_store(&self) -> Option<&TradeStore> {
+        self.trade_store.as_ref().map(|(trade_store, _)| trade_store)
+    }
+
+    // see YieldHandler, for the sweeps of the main book (the odd lots hardly sweep)
+    pub fn with_yield_point(mut self, fills: usize, handler: impl YieldHandler + 'static) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_yield_point(fills, handler);
+        self
+    }
+
+    // market orders trade at most that far through the opposite best (e.g. 0.05 for 5%), what is left beyond is
+    // cancelled; a request may carry its own band
+    pub fn with_price_protection(mut self, price_protection: Decimal) -> Self {
+        self.price_protection = Some(price_protection);
+        self
+    }
+
+    // bounds how many levels or how much notional one aggressive order sweeps, see SweepLimits
+    pub fn with_sweep_limits(mut self, sweep_limits: SweepLimits) -> Self {
+        self.orderbook = std::mem::take(&mut self.orderbook).with_sweep_limits(sweep_limits);
+        self.odd_lots = std::mem::take(&mut self.odd_lots).with_sweep_limits(sweep_limits);
+        self
+    }
+
+    // orders must be backed by the balances of their account, without accounts there is no check at all
+    pub fn with_accounts(mut self, accounts: AccountManager) -> Self {
+        self.accounts = Some(accounts);
+        self
+    }
+
+    // maker and taker fees of every trade, in the quote asset
+    pub fn with_fee_schedule(mut self, schedule: FeeSchedule) -> Self {
+        self.fee_schedule = Some(schedule);
+        self
+    }
+
+    pub fn with_execution_listener(mut self, listener: impl ExecutionListener + 'static) -> Self {
+        self.listener = Some(Box::new(listener));
+        self
+    }
+
+    pub fn with_locate(mut self, locate: impl Locate + 'static) -> Self {
+        self.locate = Some(Box::new(locate));
+        self
+    }
+
+    // tracks the position of every account from its fills, needed by the reduce-only orders
+    pub fn with_positions(mut self) -> Self {
+        self.positions = Some(PositionBook::default());
+        self
+    }
+
+    #[inline]
+    pub fn positions(&self) -> Option<&PositionBook> {
+        self.positions.as_ref()
+    }
+
+    pub fn with_lot_rules(mut self, lot_rules: LotRules) -> Self {
+        self.lot_rules = Some(lot_rules);
+        self
+    }
+
+    // the trades are in the book (and the journal) right away, only their publication on the feed is deferred
+    pub fn with_trade_deferral(mut self, deferral: TradeDeferral, clock: impl Clock + 'static) -> Self {
+        self.deferral = Some((deferral, Box::new(clock)));
+        self
+    }
+
+    pub fn with_risk_limits(mut self, risk_limits: RiskLimits) -> Self {
+        self.risk_limits = Some(risk_limits);
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreaker, clock: impl Clock + 'static) -> Self {
+        self.circuit_breaker = Some((circuit_breaker, Box::new(clock)));
+        self
+    }
+
+    // a book resumed after a halt takes only post-only orders for the period so that depth builds up again, then
+    // continuous trading (and the queued requests) resume on their own
+    pub fn with_post_only_reopen(mut self, period: Timestamp, clock: impl Clock + 'static) -> Self {
+        self.post_only_reopen = Some((period, Box::new(clock)));
+        self
+    }
+
+    pub fn with_halt_policy(mut self, halt_policy: HaltPolicy) -> Self {
+        self.halt_policy = halt_policy;
+        self
+    }
+
+    // trades too far from the last price are flagged for review, they can be busted until the window closes
+    pub fn with_trade_review(mut self, review: ReviewQueue, clock: impl Clock + 'static) -> Self {
+        self.review = Some((review, Box::new(clock)));
+        self
+    }
+
+    // requests are matched per batch rather than on arrival, see flush_batch
+    pub fn with_batching(mut self, batcher: Batcher, clock: impl Clock + 'static) -> Self {
+        self.batching = Some((batcher, Box::new(clock)));
+        self
+    }
+
+    // gateway clients open a session for their account, its orders are cancelled once the account has no session left
+    pub fn with_sessions(mut self, sessions: SessionManager, clock: impl Clock + 'static) -> Self {
+        self.sessions = Some((sessions, Box::new(clock)));
+        self
+    }
+
+    // accounts arm a countdown to be refreshed, their orders are cancelled once it runs out, see check_dead_man
+    pub fn with_dead_man_switch(mut self, dead_man: DeadManSwitch, clock: impl Clock + 'static) -> Self {
+        self.dead_man = Some((dead_man, Box::new(clock)));
+        self
+    }
+
+    // orders taking liquidity on arrival wait for the delay of the speed bump, cancels and passive orders do not
+    pub fn with_speed_bump(mut self, speed_bump: SpeedBump, clock: impl Clock + 'static) -> Self {
+        self.speed_bump = Some((speed_bump, Box::new(clock)));
+        self
+    }
+
+    // new orders cannot reuse the ids of a previous run, see IdWatermark
+    pub fn with_id_watermark(mut self, watermark: IdWatermark) -> Self {
+        self.watermark = Some(watermark);
+        self
+    }
+
+    #[inline]
+    pub fn id_watermark(&self) -> Option<&IdWatermark> {
+        self.watermark.as_ref()
+    }
+
+    // rolling statistics of the trades (see TickerStats), timed by the clock
+    pub fn with_ticker(mut self, ticker: TickerStats, clock: impl Clock + 'static) -> Self {
+        self.ticker = Some((ticker, Box::new(clock)));
+        self
+    }
+
+    // none for another pair or without ticker statistics
+    pub fn ticker(&self, pair: &str) -> Option<Ticker> {
+        let (ticker, clock) = self.ticker.as_ref().filter(|_| self.pair == pair)?;
+        Some(ticker.ticker(
+            now_of(self.ticked_at, clock.as_ref()),
+            self.orderbook.best_bid(),
+            self.orderbook.best_ask(),
+        ))
+    }
+
+    // throughput and the top of the book sampled after every request (or batch), see MetricsStore
+    pub fn with_metrics(mut self, metrics: MetricsStore, clock: impl Clock + 'static) -> Self {
+        self.metrics = Some((metrics, Box::new(clock)));
+        self
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> Option<&MetricsStore> {
+        self.metrics.as_ref().map(|(metrics, _)| metrics)
+    }
+
+    // what the subsystems retain sampled on the ticks, see MemoryMonitor
+    pub fn with_memory_monitor(mut self, monitor: MemoryMonitor, clock: impl Clock + 'static) -> Self {
+        self.memory = Some((monitor, Box::new(clock)));
+        self
+    }
+
+    #[inline]
+    pub fn memory_monitor(&self) -> Option<&MemoryMonitor> {
+        self.memory.as_ref().map(|(monitor, _)| monitor)
+    }
+
+    // number of objects retained by every subsystem, both books together
+    pub fn retained(&self) -> [(Subsystem, usize); 8] {
+        [
+            (
+                Subsystem::Orders,
+                self.orderbook.resting_count() + self.odd_lots.resting_count(),
+            ),
+            (Subsystem::Histories, self.histories.len()),
+            (
+                Subsystem::Trades,
+                self.orderbook.trade_count() + self.odd_lots.trade_count(),
+            ),
+            (Subsystem::HeldTrades, self.held_trades.len()),
+            (Subsystem::Expiries, self.expiries.len()),
+            (Subsystem::Queued, self.queued.len()),
+            (Subsystem::JournalEntries, self.journal.as_ref().map_or(0, Journal::len)),
+            (Subsystem::Subscriptions, self.market_data.subscriber_count()),
+        ]
+    }
+
+    // tick driven: samples what is retained once the interval of the monitor is over, returns the alerts raised
+    pub fn check_memory(&mut self) -> Vec<MemoryAlert> {
+        let retained = self.retained();
+        let Some((monitor, clock)) = self.memory.as_mut() else {
+            return vec![];
+        };
+        let alerts = monitor.sample(now_of(self.ticked_at, clock.as_ref()), &retained);
+        for alert in &alerts {
+            warn!(
+                "memory alert: {} {:?} (count={}, {})",
+                alert.subsystem, alert.kind, alert.count, self.pair
+            );
+        }
+        alerts
+    }
+
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub fn counters(&self) -> &EngineCounters {
+        &self.counters
+    }
+
+    fn sample_metrics(&mut self, requests: u64) {
+        let Some((metrics, clock)) = self.metrics.as_mut() else {
+            return;
+        };
+        let now = now_of(self.ticked_at, clock.as_ref());
+        metrics.record(Metric::Throughput, now, requests.into());
+        let depth = self.orderbook.depth(1);
+        let touch = [
+            (Metric::BestBid, Metric::BidDepth, depth.bids.first()),
+            (Metric::BestAsk, Metric::AskDepth, depth.asks.first()),
+        ];
+        for (price, quantity, level) in touch {
+            if let Some((best, at_touch, _)) = level {
+                metrics.record(price, now, best.value());
+                metrics.record(quantity, now, at_touch.value());
+            }
+        }
+        if let Some(spread) = self.orderbook.spread() {
+            metrics.record(Metric::Spread, now, spread);
+        }
+    }
+
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    // rebuild the state of a fresh engine from the journal (if any), then keep appending to it
+    pub fn replay(mut self, path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        if path.as_ref().exists() {
+            for entry in Journal::read(&path)? {
+                self.apply(entry?)?;
+            }
+        }
+
+        self.journal = Some(Journal::open(path)?);
+        Ok(self)
+    }
+
+    // snapshots of the books are taken on the ticks once the interval is over, the journal is compacted after each,
+    // see Engine::recover; none while the engine keeps state they do not hold (see Engine::take_snapshot)
+    pub fn with_snapshots(mut self, config: SnapshotConfig, clock: impl Clock + 'static) -> Self {
+        self.snapshots = Some((config, Box::new(clock)));
+        self
+    }
+
+    // rebuild the state of a fresh engine from the latest snapshot in the directory (if any) and the journal entries
+    // after it, then keep appending to the journal
+    pub fn recover(mut self, dir: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let mut recovery = Recovery::open(dir)?;
+        let mut sequence = 0;
+        if let Some(snapshot) = recovery.latest()? {
+            sequence = snapshot.sequence;
+            self.restore(snapshot)?;
+        }
+
+        let path = recovery.journal_path();
+        if path.exists() {
+            for entry in Journal::read(&path)? {
+                let entry = entry?;
+                if entry.sequence >= sequence {
+                    self.apply(entry)?;
+                }
+            }
         }
+
+        let mut journal = Journal::open(path)?;
+        journal.resume_at(sequence);
+        self.journal = Some(journal);
+        if let Some((_, clock)) = self.snapshots.as_ref() {
+            recovery.set_last_at(now_of(self.ticked_at, clock.as_ref()));
+        }
+        self.recovery = Some(recovery);
+        Ok(self)
+    }
+
+    // the resting orders of both books and the ids they took, as of the next journal entry
+    pub fn snapshot(&self) -> EngineSnapshot {
+        let mut orders = vec![];
+        for (book, odd_lot) in [(&self.orderbook, false), (&self.odd_lots, true)] {
+            let resting = book
+                .iter_side(OrderSide::Bid)
+                .chain(book.iter_side(OrderSide::Ask))
+                .chain(book.auction_orders());
+            for order in resting {
+                let account_id = book
+                    .owner(order.id())
+                    .or_else(|| self.owners.get(&order.id()).map(CompactString::as_str))
+                    .unwrap_or_default();
+                orders.push(RestingOrder {
+                    order: *order,
+                    account_id: account_id.into(),
+                    client_order_id: book.client_order_id(order.id()).map(CompactString::from),
+                    odd_lot,
+                    priority: book.priority(order.id()),
+                    quote: self
+                        .quotes
+                        .get(account_id)
+                        .is_some_and(|quotes| quotes.contains(&order.id())),
+                });
+            }
+        }
+        EngineSnapshot {
+            pair: self.pair.clone(),
+            sequence: self.journal.as_ref().map_or(0, Journal::next_sequence),
+            session: self.session,
+            orders,
+            handled: self.orderbook.handled().collect(),
+            odd_lots_handled: self.odd_lots.handled().collect(),
+        }
+    }
+
+    // into a fresh engine, the histories of the orders start over from their remaining quantity
+    pub fn restore(&mut self, snapshot: EngineSnapshot) -> Result<(), EngineError> {
+        if snapshot.pair != self.pair {
+            return Err(EngineError::InvalidPair {
+                expected: self.pair.as_str().into(),
+                found: snapshot.pair.as_str().into(),
+            });
+        }
+
+        self.session = snapshot.session;
+        for resting in snapshot.orders {
+            let order = resting.order;
+            let book = if resting.odd_lot {
+                &mut self.odd_lots
+            } else {
+                &mut self.orderbook
+            };
+            book.rest(order)?;
+            if let Some(priority) = resting.priority {
+                book.set_priority(order.id(), priority);
+            }
+            book.assign_account(order.id(), &resting.account_id);
+            if let Some(client_order_id) = &resting.client_order_id {
+                book.assign_client_order_id(order.id(), client_order_id)?;
+            }
+            self.histories.insert(order.id(), OrderHistory::new(order.remaining()));
+            if let Some(expires_at) = order.expires_at() {
+                self.expiries.insert((expires_at, order.id().into()));
+            }
+            if resting.quote {
+                self.quotes
+                    .entry(resting.account_id.clone())
+                    .or_default()
+                    .push(order.id());
+            }
+            self.owners.insert(order.id(), resting.account_id);
+        }
+        for order_id in snapshot.handled {
+            self.orderbook.mark_handled(order_id);
+        }
+        for order_id in snapshot.odd_lots_handled {
+            self.odd_lots.mark_handled(order_id);
+        }
+        self.publish_book_events();
+        Ok(())
+    }
+
+    // the journal is synced before the snapshot is saved, and compacted after; returns the sequence of the snapshot.
+    // refused while the engine keeps state the snapshot does not hold, it would be lost with the entries compacted
+    pub fn take_snapshot(&mut self) -> Result<u64, EngineError> {
+        if self.recovery.is_none() {
+            return Err(RecoveryError::NotRecovered.into());
+        }
+        if let Some(state) = self.unsnapshotted() {
+            return Err(RecoveryError::NotInSnapshot(state).into());
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal.sync()?;
+        }
+
+        let snapshot = self.snapshot();
+        let keep = self
+            .snapshots
+            .as_ref()
+            .map_or(SnapshotConfig::default().keep, |(config, _)| config.keep);
+        let recovery = self.recovery.as_mut().unwrap();
+        recovery.save(&snapshot, keep)?;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.compact(snapshot.sequence)?;
+        }
+        if let Some((_, clock)) = self.snapshots.as_ref() {
+            recovery.set_last_at(now_of(self.ticked_at, clock.as_ref()));
+        }
+        info!(
+            "snapshot taken (sequence={}, orders={}, {})",
+            snapshot.sequence,
+            snapshot.orders.len(),
+            self.pair
+        );
+        Ok(snapshot.sequence)
+    }
+
+    // tick driven: takes a snapshot once the interval is over, returns its sequence
+    pub fn check_snapshot(&mut self) -> Result<Option<u64>, EngineError> {
+        let (Some((config, clock)), Some(recovery)) = (self.snapshots.as_ref(), self.recovery.as_ref()) else {
+            return Ok(None);
+        };
+        if !recovery.is_due(now_of(self.ticked_at, clock.as_ref()), config.interval) || self.unsnapshotted().is_some() {
+            return Ok(None);
+        }
+        self.take_snapshot().map(Some)
+    }
+
+    // the first state kept by the engine that a snapshot does not hold (the locked funds are in the accounts)
+    fn unsnapshotted(&self) -> Option<&'static str> {
+        if self.accounts.is_some() {
+            Some("accounts")
+        } else if self.positions.is_some() {
+            Some("positions")
+        } else if self.circuit_breaker.is_some() {
+            Some("circuit breaker")
+        } else if !self.ledger.postings().is_empty() {
+            Some("ledger")
+        } else {
+            None
+        }
+    }
+
+    // one entry of a journal replayed, it must have the same outcome
+    pub(crate) fn apply(&mut self, entry: JournalEntry) -> Result<(), EngineError> {
+        let event = match entry.event {
+            JournalEvent::Expired { order_id } => self.expire_order(order_id),
+            // the trade of an order filled before the snapshot the engine was restored from is not in the book
+            JournalEvent::Busted { maker, taker, .. } => {
+                let (maker, taker) = (maker.into(), taker.into());
+                if let Some(trade_id) = self.mark_busted(|trade| trade.maker() == maker && trade.taker() == taker) {
+                    self.market_data.publish(BookEvent::Bust { trade_id });
+                }
+                entry.event.clone()
+            }
+            _ => self.execute(entry.order_request).0,
+        };
+        if event != entry.event {
+            return Err(JournalError::Divergence(entry.sequence).into());
+        }
+        Ok(())
     }
 
     #[inline]
     pub fn process(&mut self, order_request: OrderRequest) -> Result<(), EngineError> {
         //info!("{order_request}");
-        match order_request {
-            OrderRequest::Create {
-                account_id: _,
-                order_id,
-                pair: _,
-                side,
-                limit_price,
-                quantity,
-            } => {
-                let order = if let Some(limit_price) = limit_price {
-                    Order::limit_order(order_id.into(), side, limit_price, quantity)
-                } else {
-                    Order::market_order(order_id.into(), side, quantity)
-                };
-                let _ = self.orderbook.handle_create(order);
+        // new orders outside the session never reach the book (nor the journal), cancels are always accepted
+        self.update_session()?;
+        self.publish_deferred_trades();
+        let result = self.route(order_request);
+        self.sample_metrics(1);
+        result
+    }
+
+    // same as processing the requests one by one, but the session and the deferred trades are checked once up front
+    // and the book events published once at the end, the results are in request order
+    pub fn process_batch(
+        &mut self,
+        order_requests: impl IntoIterator<Item = OrderRequest>,
+    ) -> Result<Vec<Result<(), EngineError>>, EngineError> {
+        self.update_session()?;
+        self.publish_deferred_trades();
+        self.in_batch = true;
+        let results: Vec<Result<(), EngineError>> = order_requests
+            .into_iter()
+            .map(|order_request| self.route(order_request))
+            .collect();
+        self.in_batch = false;
+        self.sample_metrics(results.len() as u64);
+
+        self.publish_book_events();
+        if self.session.is_call_phase() {
+            self.publish_indicative();
+        }
+        Ok(results)
+    }
+
+    // one span per request (batched, delayed or queued ones included) when traced
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(pair = %self.pair, account_id = %order_request.account_id(), request = %order_request)
+        )
+    )]
+    fn route(&mut self, order_request: OrderRequest) -> Result<(), EngineError> {
+        if self.session == SessionState::Halted {
+            return match self.halt_policy {
+                HaltPolicy::Reject => Err(EngineError::SessionNotOpen(self.session)),
+                HaltPolicy::Queue => {
+                    self.queued.push(order_request);
+                    Ok(())
+                }
+            };
+        }
+        for result in self.release_delayed_orders()? {
+            if let Err(error) = result {
+                info!("delayed order failed: {error} ({})", self.pair);
             }
-            OrderRequest::Cancel { order_id } => {
-                let _ = self.orderbook.handle_cancel(order_id.into());
+        }
+        if self.takes_liquidity(&order_request) {
+            if let Some((speed_bump, clock)) = self.speed_bump.as_mut() {
+                speed_bump.hold(now_of(self.ticked_at, clock.as_ref()), order_request);
+                return Ok(());
             }
+        }
+        self.admit(order_request)
+    }
+
+    // tick driven: the orders past the delay of the speed bump go on in arrival order, their results are returned
+    pub fn release_delayed_orders(&mut self) -> Result<Vec<Result<(), EngineError>>, EngineError> {
+        let Some((speed_bump, clock)) = self.speed_bump.as_mut() else {
+            return Ok(vec![]);
         };
+        let released = speed_bump.release(now_of(self.ticked_at, clock.as_ref()));
+        Ok(released
+            .into_iter()
+            .map(|order_request| self.admit(order_request))
+            .collect())
+    }
 
-        Ok(())
+    #[inline]
+    pub fn speed_bump(&self) -> Option<&SpeedBump> {
+        self.speed_bump.as_ref().map(|(speed_bump, _)| speed_bump)
+    }
+
+    // whether the order would trade on arrival: market orders always do, passive orders never
+    fn takes_liquidity(&self, order_request: &OrderRequest) -> bool {
+        let OrderRequest::Create {
+            order_id,
+            side,
+            limit_price,
+            quantity,
+            time_in_force,
+            ..
+        } = order_request
+        else {
+            return false;
+        };
+        let passive = matches!(
+            time_in_force,
+            Some(
+                TimeInForce::GoodTilCancel { post_only: true }
+                    | TimeInForce::GoodTilDate { post_only: true, .. }
+                    | TimeInForce::Day { post_only: true }
+                    | TimeInForce::ImbalanceOnly
+                    | TimeInForce::ImbalanceOffset
+            )
+        );
+        if passive || self.session.is_call_phase() {
+            return false;
+        }
+
+        let book = match self.routes_to_odd_lots(OrderId::new(*order_id), *quantity) {
+            true => &self.odd_lots,
+            false => &self.orderbook,
+        };
+        match (side, limit_price) {
+            (_, None) => true,
+            (OrderSide::Bid, Some(limit_price)) => book.best_ask().is_some_and(|best_ask| *limit_price >= best_ask),
+            (OrderSide::Ask, Some(limit_price)) => book.best_bid().is_some_and(|best_bid| *limit_price <= best_bid),
+        }
+    }
+
+    fn admit(&mut self, order_request: OrderRequest) -> Result<(), EngineError> {
+        if self.batching.is_some() {
+            // a request arriving after the end of the batch goes to the next one
+            for result in self.flush_batch()? {
+                if let Err(error) = result {
+                    info!("batched request failed: {error} ({})", self.pair);
+                }
+            }
+            if let Some((batcher, clock)) = self.batching.as_mut() {
+                batcher.push(now_of(self.ticked_at, clock.as_ref()), order_request);
+            }
+            return Ok(());
+        }
+        self.match_request(order_request)
+    }
+
+    // tick driven: once the batch is over, its requests are matched in the order of the batcher and their results
+    // returned
+    pub fn flush_batch(&mut self) -> Result<Vec<Result<(), EngineError>>, EngineError> {
+        let Some((batcher, clock)) = self.batching.as_mut() else {
+            return Ok(vec![]);
+        };
+        if !batcher.is_due(now_of(self.ticked_at, clock.as_ref())) {
+            return Ok(vec![]);
+        }
+
+        let batch = batcher.close();
+        self.update_session()?;
+        Ok(batch
+            .into_iter()
+            .map(|order_request| self.match_request(order_request))
+            .collect())
     }
 
     #[inline]
-    pub fn orderbook(&self) -> &Orderbook {
-        &self.orderbook
+    pub fn batcher(&self) -> Option<&Batcher> {
+        self.batching.as_ref().map(|(batcher, _)| batcher)
     }
-}
 
-#[derive(Debug, Error)]
-pub enum EngineError {
-    #[error("invalid pair (expected={}, found={})", .expected, .found)]
-    InvalidPair {
-        expected: CompactString,
-        found: CompactString,
-    },
+    fn match_request(&mut self, mut order_request: OrderRequest) -> Result<(), EngineError> {
+        if let Err(error) = self.resolve_client_order_id(&mut order_request) {
+            return Err(self.reject(&order_request, error));
+        }
+        let order_request = self.resolve_day_order(order_request)?;
+        let order_request = self.resolve_price_protection(order_request)?;
+        let mut order_request = self.resolve_reduce_only(order_request)?;
+        if let Err(error) = self.check_order_id(&mut order_request) {
+            return Err(self.reject(&order_request, error.into()));
+        }
+        if let Err(error) = self.check_owner(&order_request) {
+            return Err(self.reject(&order_request, error));
+        }
+        let accepting = match self.session {
+            SessionState::Open => true,
+            SessionState::PostOnly => is_post_only(&order_request),
+            session => session.is_call_phase(),
+        };
+        if !accepting
+            && matches!(
+                order_request,
+                OrderRequest::Create { .. } | OrderRequest::MassQuote { .. }
+            )
+        {
+            return Err(self.reject(&order_request, EngineError::SessionNotOpen(self.session)));
+        }
+
+        let trades_from = self.trade_counts();
+        let order_ids: Vec<OrderId> = match &order_request {
+            OrderRequest::Create { order_id, .. } | OrderRequest::Cancel { order_id, .. } => {
+                vec![OrderId::new(*order_id)]
+            }
+            OrderRequest::CancelAll { account_id } => self.open_orders(account_id).map(Order::id).collect(),
+            OrderRequest::CancelByClientId { .. } => unreachable!("resolved to a cancel"),
+            // the quotes replaced then the new ones
+            OrderRequest::MassQuote { account_id, .. } => self
+                .quotes
+                .get(account_id)
+                .into_iter()
+                .flatten()
+                .copied()
+                .chain(order_request.new_order_ids().into_iter().map(OrderId::new))
+                .collect(),
+        };
+        #[cfg(feature = "scripting")]
+        let script_fee = match self.check_script(&order_request) {
+            Ok(script_fee) => script_fee,
+            Err(error) => return Err(self.reject(&order_request, error)),
+        };
+        if let Err(error) = self.check_pre_trade(&order_request) {
+            return Err(self.reject(&order_request, error));
+        }
+        for order_id in order_request.new_order_ids() {
+            self.owners
+                .entry(OrderId::new(order_id))
+                .or_insert_with(|| order_request.account_id().into());
+        }
+
+        let result = if self.journal.is_none() && self.listener.is_none() {
+            self.execute(order_request).1
+        } else {
+            let (event, result) = self.execute(order_request.clone());
+            if let Some(journal) = self.journal.as_mut() {
+                journal.append(&order_request, &event)?;
+            }
+            if let Some(listener) = self.listener.as_mut() {
+                listener::notify(listener.as_mut(), &order_request, &event);
+            }
+            result
+        };
+
+        // on the accepted orders only
+        #[cfg(feature = "scripting")]
+        if let Some((account_id, fee)) = script_fee.filter(|_| self.histories.contains_key(&order_ids[0])) {
+            self.charge_order_fee(&account_id, fee);
+        }
+        // before the owners of the orders filled are forgotten
+        let traded = self.traded_accounts(trades_from);
+        self.settle_trades(trades_from, order_ids)?;
+        self.trim_reduce_only(traded)?;
+        self.check_circuit_breaker(trades_from);
+        result
+    }
+
+    // a reduce-only order goes on (and to the journal) with at most what is left of the position once the other
+    // reduce-only orders of the account on the same side are done, it is rejected when nothing is left
+    fn resolve_reduce_only(&self, mut order_request: OrderRequest) -> Result<OrderRequest, EngineError> {
+        let OrderRequest::Create {
+            account_id,
+            order_id,
+            side,
+            quantity,
+            reduce_only: true,
+            ..
+        } = &mut order_request
+        else {
+            return Ok(order_request);
+        };
+        let positions = self.positions.as_ref().ok_or(EngineError::NoPositionTracking)?;
+
+        let reducible = self.reducible(positions, account_id, *side);
+        if reducible.is_zero() {
+            return Err(EngineError::ReduceOnlyWouldIncrease {
+                order_id: *order_id,
+                account_id: account_id.clone(),
+            });
+        }
+        *quantity = (*quantity).min(reducible);
+        Ok(order_request)
+    }
+
+    // what orders on that side may still take off the position of the account, net of its resting reduce-only orders
+    fn reducible(&self, positions: &PositionBook, account_id: &str, side: OrderSide) -> OrderQuantity {
+        let position = positions.get(account_id).copied().unwrap_or_default();
+        if position.side().is_none_or(|position_side| position_side == side) {
+            return OrderQuantity::ZERO;
+        }
+        let closing: OrderQuantity = self
+            .open_orders(account_id)
+            .filter(|order| order.is_reduce_only() && order.side() == side)
+            .map(Order::remaining)
+            .sum();
+        position.quantity() - closing
+    }
+
+    // only needed with the positions tracked
+    fn traded_accounts(&self, (trades_from, odd_lot_trades_from): (usize, usize)) -> IndexSet<CompactString> {
+        if self.positions.is_none() {
+            return IndexSet::new();
+        }
+        self.orderbook
+            .trades_from(trades_from)
+            .chain(self.odd_lots.trades_from(odd_lot_trades_from))
+            .flat_map(|trade| [trade.maker(), trade.taker()])
+            .filter_map(|order_id| self.owners.get(&order_id).cloned())
+            .collect()
+    }
+
+    // once fills shrank or flipped positions, the resting reduce-only orders of these accounts that would now increase
+    // them are cancelled, latest first
+    fn trim_reduce_only(&mut self, account_ids: IndexSet<CompactString>) -> Result<(), EngineError> {
+        let Some(positions) = self.positions.as_ref() else {
+            return Ok(());
+        };
+
+        let mut cancelled = vec![];
+        for account_id in &account_ids {
+            let position = positions.get(account_id).copied().unwrap_or_default();
+            for side in [OrderSide::Bid, OrderSide::Ask] {
+                let mut reducible = match position.side() {
+                    Some(position_side) if position_side != side => position.quantity(),
+                    _ => OrderQuantity::ZERO,
+                };
+                let resting: Vec<&Order> = self
+                    .open_orders(account_id)
+                    .filter(|order| order.is_reduce_only() && order.side() == side)
+                    .collect();
+                for order in resting {
+                    if order.remaining() <= reducible {
+                        reducible -= order.remaining();
+                    } else {
+                        cancelled.push(u64::from(order.id()));
+                    }
+                }
+            }
+        }
+
+        for &order_id in &cancelled {
+            let event = match self.book_mut(order_id.into()).handle_cancel(order_id.into()) {
+                Ok(_) => JournalEvent::Cancelled { order_id },
+                Err(error) => JournalEvent::Rejected {
+                    order_id,
+                    reason: format_compact!("{error}"),
+                },
+            };
+            let order_request = OrderRequest::Cancel {
+                account_id: CompactString::default(),
+                order_id,
+            };
+            if let Some(journal) = self.journal.as_mut() {
+                journal.append(&order_request, &event)?;
+            }
+            if let Some(listener) = self.listener.as_mut() {
+                listener::notify(listener.as_mut(), &order_request, &event);
+            }
+        }
+        if !cancelled.is_empty() {
+            self.settle_trades(self.trade_counts(), cancelled.into_iter().map(OrderId::new))?;
+            if !self.in_batch {
+                self.publish_book_events();
+            }
+        }
+        Ok(())
+    }
+
+    // the fee the script rules charge for the order, if any
+    #[cfg(feature = "scripting")]
+    fn check_script(&self, order_request: &OrderRequest) -> Result<Option<(CompactString, Decimal)>, EngineError> {
+        let (
+            Some(script_rules),
+            OrderRequest::Create {
+                account_id, order_id, ..
+            },
+        ) = (self.script_rules.as_ref(), order_request)
+        else {
+            return Ok(None);
+        };
+        if let Some(reason) = script_rules.validate(order_request)? {
+            return Err(EngineError::RejectedByScript {
+                order_id: *order_id,
+                reason,
+            });
+        }
+        let fee = script_rules.fee(order_request)?;
+        Ok((!fee.is_zero()).then(|| (account_id.clone(), fee)))
+    }
+
+    fn check_pre_trade(&mut self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        if let OrderRequest::MassQuote {
+            account_id,
+            pair,
+            levels,
+        } = order_request
+        {
+            return self.check_mass_quote(account_id, pair, levels);
+        }
+        self.check_risk(order_request)?;
+        self.check_priority_fee(order_request)?;
+        self.locate(order_request)?;
+        self.lock_funds(order_request)
+    }
+
+    // all or nothing: the whole mass quote is refused when one of its orders would be, the quotes it replaces give
+    // their funds back first (they are locked again when it is refused)
+    fn check_mass_quote(&mut self, account_id: &str, pair: &Symbol, levels: &[QuoteLevel]) -> Result<(), EngineError> {
+        let invalid = |reason: CompactString| EngineError::InvalidMassQuote {
+            account_id: account_id.into(),
+            reason,
+        };
+        let mut order_ids = IndexSet::new();
+        let (mut best_bid, mut best_ask) = (None, None);
+        for (side, quote) in levels.iter().flat_map(QuoteLevel::sides) {
+            let order_id = OrderId::new(quote.order_id);
+            if !order_ids.insert(order_id)
+                || self.orderbook.has_handled(order_id)
+                || self.odd_lots.has_handled(order_id)
+            {
+                return Err(OrderbookError::DuplicateOrderId(order_id).into());
+            }
+            if quote.quantity <= OrderQuantity::ZERO || quote.price <= OrderPrice::ZERO {
+                return Err(invalid(format_compact!("{order_id} not positive")));
+            }
+            if self.orderbook.tick_size().to_ticks(quote.price).is_none() {
+                return Err(invalid(format_compact!("{order_id} price not on the tick")));
+            }
+            match side {
+                OrderSide::Bid => best_bid = best_bid.max(Some(quote.price)),
+                OrderSide::Ask => best_ask = best_ask.map_or(Some(quote.price), |best| Some(quote.price.min(best))),
+            }
+        }
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask) {
+            if best_bid >= best_ask {
+                return Err(invalid(format_compact!("bids cross asks at {best_bid}")));
+            }
+        }
+
+        let orders = quote_orders(account_id, pair, levels);
+        for create in &orders {
+            self.check_risk(create)?;
+        }
+        let replaced = self.release_quotes(account_id);
+        for (locked, create) in orders.iter().enumerate() {
+            if let Err(error) = self.lock_funds(create) {
+                if let Some(accounts) = self.accounts.as_mut() {
+                    for order_id in order_ids.iter().take(locked) {
+                        accounts.release(*order_id);
+                    }
+                    // the very funds just released
+                    let assets = (self.pair.base().as_str(), self.pair.quote().as_str());
+                    for (order_id, side, amount) in replaced {
+                        accounts.lock(order_id, account_id, side, assets, amount)?;
+                    }
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    // what is left of the locks of the resting quotes of the account
+    fn release_quotes(&mut self, account_id: &str) -> Vec<(OrderId, OrderSide, Decimal)> {
+        let Some(accounts) = self.accounts.as_mut() else {
+            return vec![];
+        };
+        self.quotes
+            .get(account_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|order_id| {
+                let order = self
+                    .orderbook
+                    .get_order(*order_id)
+                    .or_else(|| self.odd_lots.get_order(*order_id))?;
+                Some((*order_id, order.side(), accounts.release(*order_id)?))
+            })
+            .collect()
+    }
+
+    // told to the listener before it goes back to the caller
+    fn reject(&mut self, order_request: &OrderRequest, error: EngineError) -> EngineError {
+        #[cfg(feature = "metrics")]
+        {
+            self.counters.rejected += 1;
+        }
+        if let Some(listener) = self.listener.as_mut() {
+            listener.on_reject(order_request, &format_compact!("{error}"));
+        }
+        error
+    }
+
+    // an order is cancelled by the account holding it only, the others are neither obeyed nor billed
+    fn check_owner(&self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        let OrderRequest::Cancel { account_id, order_id } = order_request else {
+            return Ok(());
+        };
+        match self.owner((*order_id).into()) {
+            Some(owner) if owner != account_id.as_str() => Err(EngineError::NotOrderOwner {
+                order_id: *order_id,
+                account_id: account_id.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // against the watermark (if any): a new order may go on with another id, the cancel of a remapped one follows it
+    fn check_order_id(&mut self, order_request: &mut OrderRequest) -> Result<(), WatermarkError> {
+        let Some(watermark) = self.watermark.as_mut() else {
+            return Ok(());
+        };
+        match order_request {
+            OrderRequest::Create { order_id, .. } => *order_id = watermark.take(*order_id)?,
+            // all or nothing, like the mass quote itself
+            OrderRequest::MassQuote { levels, .. } => {
+                let quotes: Vec<&mut QuoteSide> = levels
+                    .iter_mut()
+                    .flat_map(|level| level.bid.iter_mut().chain(level.ask.iter_mut()))
+                    .collect();
+                let order_ids: Vec<u64> = quotes.iter().map(|quote| quote.order_id).collect();
+                for (quote, order_id) in quotes.into_iter().zip(watermark.take_all(&order_ids)?) {
+                    quote.order_id = order_id;
+                }
+            }
+            OrderRequest::Cancel { order_id, .. } => {
+                if let Some(remapped) = watermark.remapped(*order_id) {
+                    *order_id = remapped;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // a cancel by client order id goes on (and to the journal) as the cancel of the order found when it is matched
+    fn resolve_client_order_id(&self, order_request: &mut OrderRequest) -> Result<(), EngineError> {
+        let OrderRequest::CancelByClientId {
+            account_id,
+            client_order_id,
+        } = order_request
+        else {
+            return Ok(());
+        };
+        let order_id = self
+            .order_by_client_id(account_id, client_order_id)
+            .ok_or_else(|| EngineError::ClientOrderIdNotFound {
+                account_id: account_id.clone(),
+                client_order_id: client_order_id.clone(),
+            })?
+            .id()
+            .into();
+        let account_id = std::mem::take(account_id);
+        *order_request = OrderRequest::Cancel { account_id, order_id };
+        Ok(())
+    }
+
+    // a day order goes on as a good-til-date one expiring at the next session end, as journaled
+    fn resolve_day_order(&self, mut order_request: OrderRequest) -> Result<OrderRequest, EngineError> {
+        if let OrderRequest::Create {
+            time_in_force: Some(time_in_force),
+            ..
+        } = &mut order_request
+        {
+            let TimeInForce::Day { post_only } = *time_in_force else {
+                return Ok(order_request);
+            };
+            let expires_at = match (&self.session_end, &self.calendar) {
+                (Some((time_of_day, clock)), _) => {
+                    next_time_of_day(now_of(self.ticked_at, clock.as_ref()), *time_of_day)
+                }
+                (None, Some((calendar, clock))) => calendar.next_close(now_of(self.ticked_at, clock.as_ref())),
+                (None, None) => return Err(EngineError::NoSessionEnd),
+            };
+            *time_in_force = TimeInForce::GoodTilDate { expires_at, post_only };
+        }
+        Ok(order_request)
+    }
+
+    // a protected market order goes on as an immediate-or-cancel limit order at the edge of its band, as journaled; not
+    // in the call phases where market orders wait for the uncross
+    fn resolve_price_protection(&self, mut order_request: OrderRequest) -> Result<OrderRequest, EngineError> {
+        let OrderRequest::Create {
+            order_id,
+            side,
+            limit_price: limit_price @ None,
+            time_in_force,
+            price_protection,
+            ..
+        } = &mut order_request
+        else {
+            return Ok(order_request);
+        };
+        let Some(band) = price_protection.or(self.price_protection) else {
+            return Ok(order_request);
+        };
+        if band.is_sign_negative() {
+            return Err(EngineError::InvalidPriceProtection {
+                order_id: *order_id,
+                price_protection: band,
+            });
+        }
+        let (best_price, through, strategy) = match side {
+            OrderSide::Bid => (self.orderbook.best_ask(), Decimal::ONE + band, RoundingStrategy::ToZero),
+            OrderSide::Ask => (
+                self.orderbook.best_bid(),
+                Decimal::ONE - band,
+                RoundingStrategy::AwayFromZero,
+            ),
+        };
+        let Some(best_price) = best_price.filter(|_| !self.session.is_call_phase()) else {
+            return Ok(order_request);
+        };
+
+        *limit_price = Some(self.orderbook.tick_size().round(best_price * through, strategy));
+        let fill_or_kill = matches!(
+            time_in_force,
+            Some(TimeInForce::ImmediateOrCancel { fill_or_kill: true })
+        );
+        *time_in_force = Some(TimeInForce::ImmediateOrCancel { fill_or_kill });
+        Ok(order_request)
+    }
+
+    pub fn open_session(&mut self, session_id: u64, account_id: &str) -> Result<(), EngineError> {
+        let (sessions, clock) = self.sessions.as_mut().ok_or(EngineError::NoSessionLayer)?;
+        sessions.open(session_id, account_id, now_of(self.ticked_at, clock.as_ref()))?;
+        info!("session {session_id} opened for account {account_id} ({})", self.pair);
+        Ok(())
+    }
+
+    pub fn heartbeat(&mut self, session_id: u64) -> Result<(), EngineError> {
+        let (sessions, clock) = self.sessions.as_mut().ok_or(EngineError::NoSessionLayer)?;
+        Ok(sessions.heartbeat(session_id, now_of(self.ticked_at, clock.as_ref()))?)
+    }
+
+    // the request goes through the session (audited, for its own account only) then on as with process
+    pub fn submit(&mut self, session_id: u64, order_request: OrderRequest) -> Result<(), EngineError> {
+        let (sessions, clock) = self.sessions.as_mut().ok_or(EngineError::NoSessionLayer)?;
+        sessions.submit(session_id, &order_request, now_of(self.ticked_at, clock.as_ref()))?;
+        self.process(order_request)
+    }
+
+    pub fn subscribe_session(
+        &mut self,
+        session_id: u64,
+        granularity: Granularity,
+    ) -> Result<Receiver<MarketDataEvent>, EngineError> {
+        let (sessions, clock) = self.sessions.as_mut().ok_or(EngineError::NoSessionLayer)?;
+        sessions.subscribe(session_id, self.pair.as_str(), now_of(self.ticked_at, clock.as_ref()))?;
+        Ok(self.subscribe(granularity))
+    }
+
+    #[inline]
+    pub fn sessions(&self) -> Option<&SessionManager> {
+        self.sessions.as_ref().map(|(sessions, _)| sessions)
+    }
+
+    // on disconnect, returns the ids of the orders cancelled
+    pub fn close_session(&mut self, session_id: u64) -> Result<Vec<u64>, EngineError> {
+        let (sessions, clock) = self.sessions.as_mut().ok_or(EngineError::NoSessionLayer)?;
+        let session = sessions.close(session_id, now_of(self.ticked_at, clock.as_ref()))?;
+        self.drop_session(session)
+    }
+
+    // tick driven: drops the sessions past their heartbeat timeout, returns them with the ids of the orders cancelled
+    pub fn check_heartbeats(&mut self) -> Result<Vec<(Session, Vec<u64>)>, EngineError> {
+        let Some((sessions, clock)) = self.sessions.as_mut() else {
+            return Ok(vec![]);
+        };
+        let timed_out = sessions.time_out(now_of(self.ticked_at, clock.as_ref()));
+        timed_out
+            .into_iter()
+            .map(|session| {
+                let cancelled = self.drop_session(session.clone())?;
+                Ok((session, cancelled))
+            })
+            .collect()
+    }
+
+    // returns the deadline
+    pub fn arm_dead_man(&mut self, account_id: &str, timeout: Timestamp) -> Result<Timestamp, EngineError> {
+        let (dead_man, clock) = self.dead_man.as_mut().ok_or(EngineError::NoDeadManSwitch)?;
+        let deadline = dead_man.arm(account_id, timeout, now_of(self.ticked_at, clock.as_ref()))?;
+        info!(
+            "dead man's switch armed for account {account_id} until {deadline} ({})",
+            self.pair
+        );
+        Ok(deadline)
+    }
+
+    // returns the new deadline
+    pub fn refresh_dead_man(&mut self, account_id: &str) -> Result<Timestamp, EngineError> {
+        let (dead_man, clock) = self.dead_man.as_mut().ok_or(EngineError::NoDeadManSwitch)?;
+        Ok(dead_man.refresh(account_id, now_of(self.ticked_at, clock.as_ref()))?)
+    }
+
+    pub fn disarm_dead_man(&mut self, account_id: &str) -> Result<(), EngineError> {
+        let (dead_man, _) = self.dead_man.as_mut().ok_or(EngineError::NoDeadManSwitch)?;
+        dead_man
+            .disarm(account_id)
+            .ok_or_else(|| DeadManError::NotArmed(account_id.into()))?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn dead_man_switch(&self) -> Option<&DeadManSwitch> {
+        self.dead_man.as_ref().map(|(dead_man, _)| dead_man)
+    }
+
+    // tick driven: cancels the orders of the accounts whose countdown ran out, returns them with the ids cancelled
+    pub fn check_dead_man(&mut self) -> Result<Vec<(CompactString, Vec<u64>)>, EngineError> {
+        let Some((dead_man, clock)) = self.dead_man.as_mut() else {
+            return Ok(vec![]);
+        };
+        let tripped = dead_man.trip(now_of(self.ticked_at, clock.as_ref()));
+        tripped
+            .into_iter()
+            .map(|account_id| {
+                info!("dead man's switch tripped for account {account_id} ({})", self.pair);
+                let cancelled = self.cancel_all_for_account(&account_id)?;
+                Ok((account_id, cancelled))
+            })
+            .collect()
+    }
+
+    // the orders of an account belong to all its sessions, they stay while one is left
+    fn drop_session(&mut self, session: Session) -> Result<Vec<u64>, EngineError> {
+        info!(
+            "session {} closed for account {} ({})",
+            session.session_id, session.account_id, self.pair
+        );
+        let connected = self
+            .sessions
+            .as_ref()
+            .is_some_and(|(sessions, _)| sessions.is_connected(&session.account_id));
+        if connected {
+            return Ok(vec![]);
+        }
+        let cancelled = self.cancel_all_for_account(&session.account_id)?;
+        if let Some((sessions, clock)) = self.sessions.as_mut() {
+            sessions.record(
+                now_of(self.ticked_at, clock.as_ref()),
+                session.session_id,
+                SessionEvent::CancelledOnDisconnect {
+                    order_ids: cancelled.clone(),
+                },
+            );
+        }
+        Ok(cancelled)
+    }
+
+    // resting orders of the account in both books, in time priority within each book
+    pub fn open_orders<'a>(&'a self, account_id: &'a str) -> impl Iterator<Item = &'a Order> {
+        self.orderbook
+            .open_orders(account_id)
+            .chain(self.odd_lots.open_orders(account_id))
+    }
+
+    // cancels every resting order of both books whatever the session (e.g. the kill switch), returns their ids
+    pub fn mass_cancel(&mut self) -> Result<Vec<u64>, EngineError> {
+        let account_ids: IndexSet<CompactString> = self
+            .orderbook
+            .account_ids()
+            .chain(self.odd_lots.account_ids())
+            .map(CompactString::from)
+            .collect();
+        let mut cancelled = vec![];
+        for account_id in account_ids {
+            cancelled.extend(self.open_orders(&account_id).map(|order| u64::from(order.id())));
+            self.match_request(OrderRequest::CancelAll { account_id })?;
+        }
+        Ok(cancelled)
+    }
+
+    // cancels every resting order of the account in one request, returns their ids
+    pub fn cancel_all_for_account(&mut self, account_id: &str) -> Result<Vec<u64>, EngineError> {
+        let order_ids = self.open_orders(account_id).map(|order| order.id().into()).collect();
+        self.process(OrderRequest::CancelAll {
+            account_id: account_id.into(),
+        })?;
+        Ok(order_ids)
+    }
+
+    // every trade of both books goes through the breaker (odd lots print prices too), trading is halted as soon as one
+    // trips it
+    fn check_circuit_breaker(&mut self, (trades_from, odd_lot_trades_from): (usize, usize)) {
+        let Some((circuit_breaker, clock)) = self.circuit_breaker.as_mut() else {
+            return;
+        };
+        let now = now_of(self.ticked_at, clock.as_ref());
+        let tripped = self
+            .orderbook
+            .trades_from(trades_from)
+            .chain(self.odd_lots.trades_from(odd_lot_trades_from))
+            .any(|trade| circuit_breaker.record(now, trade.price()));
+        if tripped && self.session == SessionState::Open {
+            self.halted_until = Some(now + circuit_breaker.halt());
+            self.set_session(SessionState::Halted);
+        }
+    }
+
+    // until resumed
+    pub fn halt(&mut self) -> Result<(), EngineError> {
+        self.hold(SessionState::Halted)
+    }
+
+    pub fn cancel_only(&mut self) -> Result<(), EngineError> {
+        self.hold(SessionState::CancelOnly)
+    }
+
+    pub fn post_only(&mut self) -> Result<(), EngineError> {
+        self.hold(SessionState::PostOnly)
+    }
+
+    // halts whatever the session (e.g. the kill switch), the calendar is held off until resumed
+    pub fn kill(&mut self) {
+        self.halted_until = None;
+        self.post_only_until = None;
+        if self.session != SessionState::Halted {
+            self.set_session(SessionState::Halted);
+        }
+    }
+
+    fn hold(&mut self, state: SessionState) -> Result<(), EngineError> {
+        self.update_session()?;
+        if !matches!(
+            self.session,
+            SessionState::Open | SessionState::Halted | SessionState::CancelOnly | SessionState::PostOnly
+        ) {
+            return Err(EngineError::SessionNotOpen(self.session));
+        }
+
+        self.halted_until = None;
+        self.post_only_until = None;
+        if self.session != state {
+            self.set_session(state);
+        }
+        Ok(())
+    }
+
+    // back to continuous trading (or to what the calendar says), the requests queued during the halt are processed in
+    // order and their results returned; with a post-only reopen, a halted book goes through it first and the requests
+    // stay queued until it is over
+    pub fn resume(&mut self) -> Result<Vec<Result<(), EngineError>>, EngineError> {
+        if !matches!(
+            self.session,
+            SessionState::Halted | SessionState::CancelOnly | SessionState::PostOnly
+        ) {
+            return Err(EngineError::NotHalted(self.session));
+        }
+
+        self.halted_until = None;
+        if let Some((period, clock)) = self.post_only_reopen.as_ref() {
+            if self.session != SessionState::PostOnly {
+                self.post_only_until = Some(now_of(self.ticked_at, clock.as_ref()) + period);
+                self.set_session(SessionState::PostOnly);
+                return Ok(vec![]);
+            }
+        }
+        self.post_only_until = None;
+        self.set_session(SessionState::Open);
+        self.update_session()?;
+        // the orders of a call phase cut short by a kill are left crossed
+        if self.session == SessionState::Open && self.orderbook.spread().is_some_and(|spread| spread <= Decimal::ZERO) {
+            let trades = self.uncross_book()?;
+            info!("uncross on resume: {} trades ({})", trades.len(), self.pair);
+        }
+        let queued = std::mem::take(&mut self.queued);
+        Ok(queued
+            .into_iter()
+            .map(|order_request| self.process(order_request))
+            .collect())
+    }
+
+    // returns the previous state, every change is published
+    fn set_session(&mut self, state: SessionState) -> SessionState {
+        info!("session {} -> {state} ({})", self.session, self.pair);
+        self.market_data.publish(BookEvent::Session { state });
+        std::mem::replace(&mut self.session, state)
+    }
+
+    // priority fees are rejected unless the queue priority is bought
+    fn check_priority_fee(&self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        let OrderRequest::Create {
+            order_id, priority_fee, ..
+        } = order_request
+        else {
+            return Ok(());
+        };
+        let allowed = self.orderbook.queue_priority() == QueuePriority::PriorityFee;
+        if priority_fee.is_sign_negative() || (!priority_fee.is_zero() && !allowed) {
+            return Err(EngineError::PriorityFeeNotAllowed {
+                order_id: *order_id,
+                priority_fee: *priority_fee,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_risk(&self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        let (
+            Some(risk_limits),
+            OrderRequest::Create {
+                side,
+                limit_price,
+                quantity,
+                ..
+            },
+        ) = (self.risk_limits.as_ref(), order_request)
+        else {
+            return Ok(());
+        };
+
+        let opposite = match side {
+            OrderSide::Ask => OrderSide::Bid,
+            OrderSide::Bid => OrderSide::Ask,
+        };
+        let reference_price = self
+            .last_price()
+            .or_else(|| self.orderbook.peek_top(&opposite).and_then(Order::limit_price));
+        Ok(risk_limits.check(*limit_price, *quantity, reference_price)?)
+    }
+
+    #[inline]
+    fn last_price(&self) -> Option<OrderPrice> {
+        self.orderbook
+            .trades_from(self.orderbook.trade_count().saturating_sub(1))
+            .last()
+            .map(Trade::price)
+    }
+
+    // the book is left alone: the price is checked against the collar around the last trade, both legs are settled
+    // between the accounts (when backed by balances) and recorded in the ledger, then the trade is published
+    pub fn report_trade(
+        &mut self,
+        buyer: &str,
+        seller: &str,
+        price: OrderPrice,
+        quantity: OrderQuantity,
+    ) -> Result<TradeId, EngineError> {
+        let trade = OffBookTrade::new(buyer, seller, price, quantity);
+        if price <= OrderPrice::ZERO || quantity <= OrderQuantity::ZERO || buyer == seller {
+            return Err(EngineError::InvalidOffBookTrade(Box::new(trade)));
+        }
+        if let Some(risk_limits) = self.risk_limits.as_ref() {
+            risk_limits.check_price(price, self.last_price())?;
+        }
+
+        let (base, quote) = (self.pair.base().as_str(), self.pair.quote().as_str());
+        if let Some(accounts) = self.accounts.as_mut() {
+            accounts.settle(buyer, seller, (base, quote), quantity, price)?;
+        }
+        self.ledger
+            .transfer(seller, buyer, base, quantity.value(), PostingKind::OffBookTrade)?;
+        self.ledger
+            .transfer(buyer, seller, quote, trade.notional(), PostingKind::OffBookTrade)?;
+
+        info!("{trade} ({})", self.pair);
+        self.market_data.publish(BookEvent::OffBookTrade {
+            trade_id: trade.id(),
+            price,
+            quantity,
+        });
+        let trade_id = trade.id();
+        self.off_book_trades.push(trade);
+        Ok(trade_id)
+    }
+
+    #[inline]
+    pub fn review_queue(&self) -> Option<&ReviewQueue> {
+        self.review.as_ref().map(|(review, _)| review)
+    }
+
+    pub fn confirm_trade(&mut self, trade_id: TradeId) -> Result<(), EngineError> {
+        self.review_trade(trade_id, ReviewDecision::Confirmed).map(|_| ())
+    }
+
+    // the trade is marked as busted and published as such, its settlement (fees, positions, fills of both orders and
+    // ticker included) is undone and the bust journaled
+    pub fn bust_trade(&mut self, trade_id: TradeId) -> Result<(), EngineError> {
+        let flagged = self.review_trade(trade_id, ReviewDecision::Busted)?;
+        let trade = flagged.trade;
+        let (base, quote) = (self.pair.base().as_str(), self.pair.quote().as_str());
+        if let Some(accounts) = self.accounts.as_mut() {
+            accounts.reverse(
+                &flagged.buyer,
+                &flagged.seller,
+                (base, quote),
+                trade.quantity(),
+                trade.price(),
+            );
+        }
+        let (maker, taker) = match trade.side() {
+            OrderSide::Bid => (&flagged.seller, &flagged.buyer),
+            OrderSide::Ask => (&flagged.buyer, &flagged.seller),
+        };
+        for (account_id, fee) in [(maker, trade.maker_fee()), (taker, trade.taker_fee())] {
+            if let Some(accounts) = self.accounts.as_mut() {
+                accounts.charge(account_id, quote, -fee);
+            }
+            if let Some(report) = self.fee_reports.get_mut(account_id) {
+                report.volume -= trade.notional();
+            }
+        }
+        if let Some(report) = self.fee_reports.get_mut(maker) {
+            report.maker_fees -= trade.maker_fee();
+        }
+        if let Some(report) = self.fee_reports.get_mut(taker) {
+            report.taker_fees -= trade.taker_fee();
+        }
+
+        if let Some(positions) = self.positions.as_mut() {
+            positions.reverse_fill(&flagged.buyer, OrderSide::Bid, trade.quantity(), trade.price());
+            positions.reverse_fill(&flagged.seller, OrderSide::Ask, trade.quantity(), trade.price());
+        }
+        for order_id in [trade.taker(), trade.maker()] {
+            if let Some(history) = self.histories.get_mut(&order_id) {
+                history.bust(trade_id);
+            }
+        }
+        if let Some((ticker, _)) = self.ticker.as_mut() {
+            ticker.bust(trade.price(), trade.quantity());
+        }
+
+        self.mark_busted(|busted| busted.id() == trade_id);
+        // journaled as a cancel of the taker by the exchange, like the expirations
+        let event = JournalEvent::Busted {
+            trade_id: trade_id.into(),
+            maker: trade.maker().into(),
+            taker: trade.taker().into(),
+        };
+        if let Some(journal) = self.journal.as_mut() {
+            let order_request = OrderRequest::Cancel {
+                account_id: CompactString::default(),
+                order_id: trade.taker().into(),
+            };
+            journal.append(&order_request, &event)?;
+        }
+        #[cfg(feature = "storage")]
+        if let Some((trade_store, _)) = self.trade_store.as_mut() {
+            trade_store.mark_busted(trade_id)?;
+        }
+        info!("busted {trade} ({})", self.pair);
+        self.market_data.publish(BookEvent::Bust { trade_id });
+        Ok(())
+    }
+
+    // in the book or in the one of the odd lots
+    fn mark_busted(&mut self, mut predicate: impl FnMut(&Trade) -> bool) -> Option<TradeId> {
+        let trade = self
+            .orderbook
+            .trades_from_mut(0)
+            .chain(self.odd_lots.trades_from_mut(0))
+            .find(|trade| predicate(trade))?;
+        trade.bust();
+        Some(trade.id())
+    }
+
+    fn review_trade(&mut self, trade_id: TradeId, decision: ReviewDecision) -> Result<FlaggedTrade, EngineError> {
+        let (review, clock) = self.review.as_mut().ok_or(ReviewError::NotUnderReview(trade_id))?;
+        Ok(review.decide(trade_id, decision, now_of(self.ticked_at, clock.as_ref()))?)
+    }
+
+    #[inline]
+    pub fn off_book_trades(&self) -> &[OffBookTrade] {
+        &self.off_book_trades
+    }
+
+    // short sales must be located before they reach the book, there is nothing to check without a locate hook
+    fn locate(&mut self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        let OrderRequest::Create {
+            account_id,
+            order_id,
+            side,
+            quantity,
+            short_sell: true,
+            ..
+        } = order_request
+        else {
+            return Ok(());
+        };
+
+        if *side != OrderSide::Ask {
+            return Err(EngineError::ShortSellOnBid(*order_id));
+        }
+        match self.locate.as_mut() {
+            Some(locate) => Ok(locate.locate(account_id, self.pair.as_str(), *quantity)?),
+            None => Ok(()),
+        }
+    }
+
+    // the funds of a new order are locked before it reaches the book, market bids lock what the asks would cost
+    fn lock_funds(&mut self, order_request: &OrderRequest) -> Result<(), EngineError> {
+        let (
+            Some(_),
+            OrderRequest::Create {
+                account_id,
+                order_id,
+                side,
+                limit_price,
+                quantity,
+                ..
+            },
+        ) = (self.accounts.as_ref(), order_request)
+        else {
+            return Ok(());
+        };
+
+        let amount = match (side, limit_price) {
+            (OrderSide::Ask, _) => quantity.value(),
+            (OrderSide::Bid, Some(limit_price)) => *quantity * *limit_price,
+            (OrderSide::Bid, None) => {
+                let mut remaining = *quantity;
+                let mut cost = Decimal::ZERO;
+                let book = if self.routes_to_odd_lots(OrderId::new(*order_id), *quantity) {
+                    &self.odd_lots
+                } else {
+                    &self.orderbook
+                };
+                for (price, available, _) in book.depth(usize::MAX).asks {
+                    let traded = remaining.min(available);
+                    cost += traded * price;
+                    remaining -= traded;
+                    if remaining.is_zero() {
+                        break;
+                    }
+                }
+                cost
+            }
+        };
+
+        let (base, quote) = (self.pair.base().as_str(), self.pair.quote().as_str());
+        self.accounts
+            .as_mut()
+            .map_or(Ok(()), |accounts| {
+                accounts.lock(OrderId::new(*order_id), account_id, *side, (base, quote), amount)
+            })
+            .map_err(|error| match error {
+                AccountError::InsufficientFunds {
+                    account_id,
+                    asset,
+                    required,
+                    available,
+                } => EngineError::InsufficientFunds {
+                    account_id,
+                    asset,
+                    required,
+                    available,
+                },
+                error => error.into(),
+            })
+    }
+
+    // the new trades pay their fees and move the locked funds, orders gone from the book release what is left of
+    // their lock and are forgotten
+    fn settle_trades(
+        &mut self,
+        (trades_from, odd_lot_trades_from): (usize, usize),
+        order_ids: impl IntoIterator<Item = OrderId>,
+    ) -> Result<(), EngineError> {
+        let mut orders: Vec<OrderId> = order_ids.into_iter().collect();
+        #[cfg(feature = "storage")]
+        let mut stored = vec![];
+        let quote = self.pair.quote().as_str();
+        let reference_price = trades_from
+            .checked_sub(1)
+            .and_then(|index| self.orderbook.trades_from(index).next())
+            .map(Trade::price);
+        let trades = self
+            .orderbook
+            .trades_from_mut(trades_from)
+            .chain(self.odd_lots.trades_from_mut(odd_lot_trades_from));
+        for trade in trades {
+            if let Some(lot_rules) = self.lot_rules.as_ref() {
+                trade.set_odd_lot(lot_rules.is_odd_lot(trade.quantity()));
+            }
+            let maker = self.owners.get(&trade.maker()).cloned().unwrap_or_default();
+            let taker = self.owners.get(&trade.taker()).cloned().unwrap_or_default();
+            #[cfg(feature = "trace")]
+            for (order_id, account_id) in [(trade.taker(), &taker), (trade.maker(), &maker)] {
+                tracing::debug!(
+                    order_id = u64::from(order_id),
+                    %account_id,
+                    price = %trade.price(),
+                    quantity = %trade.quantity(),
+                    "fill"
+                );
+            }
+            if let Some(schedule) = self.fee_schedule.as_ref() {
+                let notional = trade.notional();
+                let volume = |account_id| {
+                    self.fee_reports
+                        .get(account_id)
+                        .map_or(Decimal::ZERO, |report| report.volume)
+                };
+                let maker_rates = schedule.rates(self.pair.as_str(), volume(&maker));
+                let taker_rates = schedule.rates(self.pair.as_str(), volume(&taker));
+                trade.set_fees(maker_rates.maker_fee(notional), taker_rates.taker_fee(notional));
+
+                let report = self.fee_reports.entry(maker.clone()).or_default();
+                report.volume += notional;
+                report.maker_fees += trade.maker_fee();
+                let report = self.fee_reports.entry(taker.clone()).or_default();
+                report.volume += notional;
+                report.taker_fees += trade.taker_fee();
+            }
+
+            if let Some(positions) = self.positions.as_mut() {
+                let maker_side = match trade.side() {
+                    OrderSide::Ask => OrderSide::Bid,
+                    OrderSide::Bid => OrderSide::Ask,
+                };
+                positions.apply_fill(&taker, trade.side(), trade.quantity(), trade.price());
+                positions.apply_fill(&maker, maker_side, trade.quantity(), trade.price());
+            }
+            if let Some(accounts) = self.accounts.as_mut() {
+                for order_id in [trade.taker(), trade.maker()] {
+                    accounts.fill(order_id, trade.quantity(), trade.price())?;
+                }
+                accounts.charge(&maker, quote, trade.maker_fee());
+                accounts.charge(&taker, quote, trade.taker_fee());
+            }
+            if let Some((review, clock)) = self.review.as_mut() {
+                if let Some(evaluation) = review.check(trade.price(), reference_price) {
+                    let (buyer, seller) = match trade.side() {
+                        OrderSide::Bid => (taker.clone(), maker.clone()),
+                        OrderSide::Ask => (maker.clone(), taker.clone()),
+                    };
+                    review.flag(FlaggedTrade {
+                        trade: *trade,
+                        buyer,
+                        seller,
+                        flagged_at: now_of(self.ticked_at, clock.as_ref()),
+                        evaluation,
+                    });
+                }
+            }
+            if let Some((ticker, clock)) = self.ticker.as_mut() {
+                ticker.record(now_of(self.ticked_at, clock.as_ref()), trade.price(), trade.quantity());
+            }
+            #[cfg(feature = "metrics")]
+            {
+                self.counters.trades += 1;
+            }
+            for order_id in [trade.taker(), trade.maker()] {
+                if let Some(history) = self.histories.get_mut(&order_id) {
+                    history.fill(trade);
+                }
+            }
+            if let Some(listener) = self.listener.as_mut() {
+                listener.on_fill(trade, &maker, &taker);
+            }
+            #[cfg(feature = "storage")]
+            if let Some((_, clock)) = self.trade_store.as_ref() {
+                stored.push(StoredTrade {
+                    pair: self.pair.as_str().into(),
+                    executed_at: now_of(self.ticked_at, clock.as_ref()),
+                    maker: maker.clone(),
+                    taker: taker.clone(),
+                    trade: *trade,
+                });
+            }
+            orders.extend([trade.taker(), trade.maker()]);
+        }
+        #[cfg(feature = "storage")]
+        if let Some((trade_store, _)) = self.trade_store.as_mut().filter(|_| !stored.is_empty()) {
+            trade_store.insert(&stored)?;
+        }
+
+        for order_id in orders {
+            if self.get_order(order_id).is_none() {
+                self.owners.swap_remove(&order_id);
+                if let Some(accounts) = self.accounts.as_mut() {
+                    accounts.release(order_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // trades so far in the book of the round lots and in the one of the odd lots
+    #[inline]
+    fn trade_counts(&self) -> (usize, usize) {
+        (self.orderbook.trade_count(), self.odd_lots.trade_count())
+    }
+
+    // odd lots are matched apart only when the book is open (they join the opening auction), an order id already taken
+    // by a book always goes back to it so that it is rejected as a duplicate
+    fn routes_to_odd_lots(&self, order_id: OrderId, quantity: OrderQuantity) -> bool {
+        if self.odd_lots.has_handled(order_id) {
+            return true;
+        }
+        matches!(self.session, SessionState::Open | SessionState::PostOnly)
+            && !self.orderbook.has_handled(order_id)
+            && self.lot_rules.is_some_and(|lot_rules| {
+                lot_rules.odd_lots() == OddLotHandling::Separate && lot_rules.is_odd_lot(quantity)
+            })
+    }
+
+    #[inline]
+    pub fn get_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.orderbook
+            .get_order(order_id)
+            .or_else(|| self.odd_lots.get_order(order_id))
+    }
+
+    // the account holding the open order
+    pub fn owner(&self, order_id: OrderId) -> Option<&str> {
+        self.orderbook.owner(order_id).or_else(|| self.odd_lots.owner(order_id))
+    }
+
+    // any order accepted so far, none for an unknown or rejected order id
+    pub fn order_status(&self, order_id: OrderId) -> Option<OrderStatusReport> {
+        let history = self.histories.get(&order_id)?;
+        let priority = self
+            .orderbook
+            .priority(order_id)
+            .or_else(|| self.odd_lots.priority(order_id));
+        Some(history.report(self.get_order(order_id), priority))
+    }
+
+    // the fills of the order, in the order they happened
+    #[inline]
+    pub fn executions(&self, order_id: OrderId) -> &[Trade] {
+        self.histories.get(&order_id).map_or(&[], OrderHistory::fills)
+    }
+
+    #[inline]
+    pub fn order_by_client_id(&self, account_id: &str, client_order_id: &str) -> Option<&Order> {
+        self.orderbook
+            .order_by_client_id(account_id, client_order_id)
+            .or_else(|| self.odd_lots.order_by_client_id(account_id, client_order_id))
+    }
+
+    #[inline]
+    fn book_mut(&mut self, order_id: OrderId) -> &mut Orderbook {
+        match self.odd_lots.get_order(order_id) {
+            Some(_) => &mut self.odd_lots,
+            None => &mut self.orderbook,
+        }
+    }
+
+    // block and large in scale trades are held back for the deferral delay (until released without a deferral),
+    // everything else goes to the feed right away
+    fn publish_book_events(&mut self) {
+        let events: Vec<BookEvent> = self
+            .orderbook
+            .drain_events()
+            .chain(self.odd_lots.drain_events())
+            .collect();
+        for book_event in events {
+            let BookEvent::Trade { price, quantity, .. } = book_event else {
+                self.market_data.publish(book_event);
+                continue;
+            };
+
+            let block = self.lot_rules.is_some_and(|rules| rules.is_block(quantity));
+            let large_in_scale = self
+                .deferral
+                .as_ref()
+                .is_some_and(|(deferral, _)| deferral.is_deferred(price, quantity));
+            if block || large_in_scale {
+                let release_at = self.deferral.as_ref().map_or(Timestamp::MAX, |(deferral, clock)| {
+                    now_of(self.ticked_at, clock.as_ref()) + deferral.delay
+                });
+                self.held_trades.push((release_at, book_event));
+            } else {
+                self.market_data.publish(book_event);
+            }
+        }
+        if self.market_data.has_bbo_subscribers() {
+            self.market_data.publish_bbo(self.orderbook.bbo());
+        }
+    }
+
+    // scheduler of the deferred trades, runs on every request too: publishes those due, returns how many
+    pub fn publish_deferred_trades(&mut self) -> usize {
+        let Some((_, clock)) = self.deferral.as_ref() else {
+            return 0;
+        };
+        let now = now_of(self.ticked_at, clock.as_ref());
+        let (due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held_trades)
+            .into_iter()
+            .partition(|(release_at, _)| *release_at <= now);
+        self.held_trades = held;
+        for (_, book_event) in &due {
+            self.market_data.publish(*book_event);
+        }
+        due.len()
+    }
+
+    // publishes all the trades held back so far whatever their release time, returns how many
+    pub fn release_block_trades(&mut self) -> usize {
+        let held_trades = std::mem::take(&mut self.held_trades);
+        let released = held_trades.len();
+        for (_, book_event) in held_trades {
+            self.market_data.publish(book_event);
+        }
+        released
+    }
+
+    #[inline]
+    pub fn held_trades(&self) -> &[(Timestamp, BookEvent)] {
+        &self.held_trades
+    }
+
+    // in the quote asset, like the trading fees
+    #[cfg(feature = "scripting")]
+    fn charge_order_fee(&mut self, account_id: &str, fee: Decimal) {
+        let quote = self.pair.quote().as_str();
+        if let Some(accounts) = self.accounts.as_mut() {
+            accounts.charge(account_id, quote, fee);
+        }
+        self.fee_reports.entry(account_id.into()).or_default().order_fees += fee;
+    }
+
+    fn charge_priority_fee(&mut self, account_id: &str, priority_fee: Decimal) {
+        if priority_fee.is_zero() {
+            return;
+        }
+        let quote = self.pair.quote().as_str();
+        if let Some(accounts) = self.accounts.as_mut() {
+            accounts.charge(account_id, quote, priority_fee);
+        }
+        self.fee_reports.entry(account_id.into()).or_default().priority_fees += priority_fee;
+    }
+
+    #[inline]
+    pub fn fee_report(&self, account_id: &str) -> FeeReport {
+        self.fee_reports.get(account_id).copied().unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn accounts(&self) -> Option<&AccountManager> {
+        self.accounts.as_ref()
+    }
+
+    #[inline]
+    pub fn accounts_mut(&mut self) -> Option<&mut AccountManager> {
+        self.accounts.as_mut()
+    }
+
+    // the book only records its changes once someone subscribed
+    pub fn subscribe(&mut self, granularity: Granularity) -> Receiver<MarketDataEvent> {
+        self.orderbook.record_events();
+        self.odd_lots.record_events();
+        self.market_data.subscribe(granularity)
+    }
+
+    // top of book only, updated once the book settles after each request rather than on every order event
+    pub fn subscribe_bbo(&mut self) -> Receiver<BboUpdate> {
+        self.market_data.subscribe_bbo(self.orderbook.bbo())
+    }
+
+    // for the consumers that may fall behind: they only ever see the latest update
+    pub fn subscribe_bbo_conflated(&mut self) -> ConflatedBbo {
+        self.market_data.subscribe_bbo_conflated(self.orderbook.bbo())
+    }
+
+    // rejections are journaled like any other outcome, those the submitter must act on are also returned as errors
+    #[inline]
+    fn execute(&mut self, order_request: OrderRequest) -> (JournalEvent, Result<(), EngineError>) {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let mut result = Ok(());
+        let event = match order_request {
+            OrderRequest::Create {
+                account_id,
+                order_id,
+                pair: _,
+                side,
+                limit_price,
+                quantity,
+                time_in_force,
+                short_sell,
+                priority_fee,
+                client_order_id,
+                price_protection: _, // resolved on arrival
+                reduce_only,
+            } => {
+                self.billing.record(&account_id, MessageKind::Order);
+                let mut order = if let Some(limit_price) = limit_price {
+                    Order::limit_order(order_id.into(), side, quantity, limit_price)
+                } else {
+                    Order::market_order(order_id.into(), side, quantity)
+                }
+                .with_short_sell(short_sell)
+                .with_priority_fee(priority_fee)
+                .with_reduce_only(reduce_only);
+                if let Some(time_in_force) = time_in_force {
+                    order = order.with_time_in_force(time_in_force);
+                }
+                let taken = client_order_id
+                    .as_ref()
+                    .is_some_and(|client_order_id| self.order_by_client_id(&account_id, client_order_id).is_some());
+                let created = match taken {
+                    true => Err(OrderbookError::DuplicateClientOrderId(
+                        client_order_id.clone().unwrap_or_default(),
+                    )),
+                    false => self.create(order),
+                };
+                match created {
+                    Ok(matched) => {
+                        self.histories.insert(order.id(), OrderHistory::new(quantity));
+                        let book = self.book_mut(order.id());
+                        book.assign_account(order.id(), &account_id);
+                        if let Some(client_order_id) = &client_order_id {
+                            if let Err(error) = book.assign_client_order_id(order.id(), client_order_id) {
+                                result = Err(error.into());
+                            }
+                        }
+                        if self.get_order(order.id()).is_some() {
+                            self.charge_priority_fee(&account_id, priority_fee);
+                        }
+                        if let Some(expires_at) = order.expires_at() {
+                            if self.get_order(order.id()).is_some() {
+                                self.expiries.insert((expires_at, order_id));
+                            }
+                        }
+                        JournalEvent::Created { order_id, matched }
+                    }
+                    Err(error) => {
+                        let reason = format_compact!("{error}");
+                        match error {
+                            OrderbookError::PostOnlyWouldCross {
+                                limit_price,
+                                best_price,
+                                ..
+                            } => {
+                                result = Err(EngineError::PostOnlyWouldCross {
+                                    order_id,
+                                    limit_price,
+                                    best_price,
+                                });
+                            }
+                            OrderbookError::PriceNotOnTick { .. } | OrderbookError::DuplicateClientOrderId(_) => {
+                                result = Err(error.into())
+                            }
+                            _ => {}
+                        }
+                        JournalEvent::Rejected { order_id, reason }
+                    }
+                }
+            }
+            OrderRequest::Cancel { account_id, order_id } => {
+                self.billing.record(&account_id, MessageKind::Cancel);
+                match self.book_mut(order_id.into()).handle_cancel(order_id.into()) {
+                    Ok(_) => JournalEvent::Cancelled { order_id },
+                    Err(error) => JournalEvent::Rejected {
+                        order_id,
+                        reason: format_compact!("{error}"),
+                    },
+                }
+            }
+            OrderRequest::CancelAll { account_id } => {
+                self.billing.record(&account_id, MessageKind::Cancel);
+                let mut order_ids = vec![];
+                for book in [&mut self.orderbook, &mut self.odd_lots] {
+                    match book.cancel_all(&account_id) {
+                        Ok(cancelled) => order_ids.extend(cancelled.iter().map(|order| u64::from(order.id()))),
+                        Err(error) => result = Err(error.into()),
+                    }
+                }
+                JournalEvent::CancelledAll { account_id, order_ids }
+            }
+            OrderRequest::CancelByClientId { .. } => unreachable!("resolved to a cancel"),
+            OrderRequest::MassQuote { account_id, levels, .. } => {
+                self.billing.record(&account_id, MessageKind::Order);
+                let cancelled: Vec<u64> = self
+                    .quotes
+                    .swap_remove(&account_id)
+                    .into_iter()
+                    .flatten()
+                    .filter(|order_id| self.book_mut(*order_id).handle_cancel(*order_id).is_ok())
+                    .map(u64::from)
+                    .collect();
+
+                let (mut created, mut matched) = (vec![], false);
+                for (side, quote) in levels.iter().flat_map(QuoteLevel::sides) {
+                    let order = Order::limit_order(quote.order_id.into(), side, quote.quantity, quote.price);
+                    match self.create(order) {
+                        Ok(quote_matched) => {
+                            matched |= quote_matched;
+                            self.histories.insert(order.id(), OrderHistory::new(quote.quantity));
+                            self.book_mut(order.id()).assign_account(order.id(), &account_id);
+                            created.push(quote.order_id);
+                        }
+                        Err(error) => result = Err(error.into()),
+                    }
+                }
+                let resting: Vec<OrderId> = created
+                    .iter()
+                    .map(|order_id| OrderId::new(*order_id))
+                    .filter(|order_id| self.get_order(*order_id).is_some())
+                    .collect();
+                if !resting.is_empty() {
+                    self.quotes.insert(account_id.clone(), resting);
+                }
+                JournalEvent::Quoted {
+                    account_id,
+                    cancelled,
+                    created,
+                    matched,
+                }
+            }
+        };
+
+        if !self.in_batch {
+            self.publish_book_events();
+            if self.session.is_call_phase() {
+                self.publish_indicative();
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            self.counters.record(&event);
+            let latency = started.elapsed().as_micros() as u64;
+            self.counters.match_latency.observe(latency);
+        }
+        (event, result)
+    }
+
+    // the call phases only rest the orders, the odd lots are matched apart
+    fn create(&mut self, order: Order) -> Result<bool, OrderbookError> {
+        match self.session {
+            session if session.is_call_phase() => self.orderbook.rest(order).map(|_| false),
+            _ if self.routes_to_odd_lots(order.id(), order.remaining()) => self.odd_lots.handle_create(order),
+            _ => self.orderbook.handle_create(order),
+        }
+    }
+
+    // returns the previous state when the session changed since the last update, leaving the pre-open uncrosses the book,
+    // an auction started on demand holds the calendar off until its own uncross
+    pub fn update_session(&mut self) -> Result<Option<SessionState>, EngineError> {
+        if let (Some(halted_until), Some((_, clock))) = (self.halted_until, self.circuit_breaker.as_ref()) {
+            if now_of(self.ticked_at, clock.as_ref()) >= halted_until {
+                self.resume_on_time()?;
+                return Ok(Some(SessionState::Halted));
+            }
+        }
+        if let (Some(post_only_until), Some((_, clock))) = (self.post_only_until, self.post_only_reopen.as_ref()) {
+            if now_of(self.ticked_at, clock.as_ref()) >= post_only_until {
+                self.resume_on_time()?;
+                return Ok(Some(SessionState::PostOnly));
+            }
+        }
+
+        let Some((calendar, clock)) = self.calendar.as_ref() else {
+            return Ok(None);
+        };
+        let state = calendar.state(now_of(self.ticked_at, clock.as_ref()));
+        let held = matches!(
+            self.session,
+            SessionState::Auction | SessionState::Halted | SessionState::CancelOnly | SessionState::PostOnly
+        );
+        if state == self.session || held {
+            return Ok(None);
+        }
+
+        let previous = self.set_session(state);
+        if state == SessionState::PreOpen {
+            self.publish_indicative();
+        } else if previous == SessionState::PreOpen {
+            let trades = self.uncross_book()?;
+            info!("opening uncross: {} trades ({})", trades.len(), self.pair);
+        }
+        Ok(Some(previous))
+    }
+
+    // nobody to return the results of the queued requests to
+    fn resume_on_time(&mut self) -> Result<(), EngineError> {
+        for result in self.resume()? {
+            if let Err(error) = result {
+                info!("queued request failed: {error} ({})", self.pair);
+            }
+        }
+        Ok(())
+    }
+
+    // the call phase of an auction can only start from a continuous trading session
+    pub fn start_auction(&mut self) -> Result<(), EngineError> {
+        self.update_session()?;
+        if self.session != SessionState::Open {
+            return Err(EngineError::SessionNotOpen(self.session));
+        }
+
+        self.set_session(SessionState::Auction);
+        self.publish_indicative();
+        Ok(())
+    }
+
+    // ends the call phase at the equilibrium price in one go, then continuous trading resumes
+    pub fn uncross(&mut self) -> Result<Vec<Trade>, EngineError> {
+        if self.session != SessionState::Auction {
+            return Err(EngineError::AuctionNotStarted(self.session));
+        }
+
+        self.set_session(SessionState::Open);
+        let trades = self.uncross_book()?;
+        info!("auction uncross: {} trades ({})", trades.len(), self.pair);
+        self.update_session()?;
+        Ok(trades)
+    }
+
+    // once the call phase is over
+    fn uncross_book(&mut self) -> Result<Vec<Trade>, EngineError> {
+        let trades_from = self.trade_counts();
+        let auction_orders: Vec<OrderId> = self.orderbook.auction_orders().iter().map(Order::id).collect();
+        let trades = self.orderbook.uncross()?;
+        self.settle_trades(trades_from, auction_orders)?;
+        self.publish_book_events();
+        self.publish_indicative();
+        Ok(trades)
+    }
+
+    // only when it changed, the preview is cleared once the call phase is over
+    fn publish_indicative(&mut self) {
+        let uncross = self
+            .session
+            .is_call_phase()
+            .then(|| self.orderbook.indicative_uncross())
+            .flatten();
+        if uncross != self.indicative {
+            self.indicative = uncross;
+            self.market_data.publish(BookEvent::Indicative { uncross });
+        }
+    }
+
+    #[inline]
+    pub fn indicative(&self) -> Option<Uncross> {
+        self.indicative
+    }
+
+    #[inline]
+    pub fn session(&self) -> SessionState {
+        self.session
+    }
+
+    // tick driven: cancels the good-til-date orders resting past their expiry, returns their ids
+    pub fn expire(&mut self, now: Timestamp) -> Result<Vec<u64>, EngineError> {
+        let mut expired = vec![];
+        while let Some(&(expires_at, order_id)) = self.expiries.first() {
+            if expires_at > now {
+                break;
+            }
+            self.expiries.pop_first();
+            // filled or cancelled in the meantime
+            if self.get_order(order_id.into()).is_none() {
+                continue;
+            }
+
+            let event = self.expire_order(order_id);
+            self.settle_trades(self.trade_counts(), Some(order_id.into()))?;
+            if let Some(journal) = self.journal.as_mut() {
+                let order_request = OrderRequest::Cancel {
+                    account_id: CompactString::default(),
+                    order_id,
+                };
+                journal.append(&order_request, &event)?;
+            }
+            if let Some(listener) = self.listener.as_mut() {
+                let order_request = OrderRequest::Cancel {
+                    account_id: CompactString::default(),
+                    order_id,
+                };
+                listener::notify(listener.as_mut(), &order_request, &event);
+            }
+            expired.push(order_id);
+        }
+        Ok(expired)
+    }
+
+    // the next time a good-til-date order may expire, it may be gone from the book by then
+    #[inline]
+    pub fn next_expiry(&self) -> Option<Timestamp> {
+        self.expiries.first().map(|(expires_at, _)| *expires_at)
+    }
+
+    // runs all the tick driven work at once, in the order a scheduler would: the session first so that orders released
+    // or batched meet the right state, then the timeouts, the expirations, the deferred trades, the memory check and
+    // the snapshot; all of it at `now`, whatever the clocks the subsystems were given say
+    pub fn tick(&mut self, now: Timestamp) -> Result<Tick, EngineError> {
+        self.ticked_at = Some(now);
+        let tick = self.run_tick(now);
+        self.ticked_at = None;
+        tick
+    }
+
+    fn run_tick(&mut self, now: Timestamp) -> Result<Tick, EngineError> {
+        let session = self.update_session()?;
+        let released = self.release_delayed_orders()?;
+        let batched = self.flush_batch()?;
+        let timed_out = self.check_heartbeats()?;
+        let tripped = self.check_dead_man()?;
+        let expired = self.expire(now)?;
+        let published = self.publish_deferred_trades();
+        let memory = self.check_memory();
+        let snapshot = self.check_snapshot()?;
+        Ok(Tick {
+            session,
+            released,
+            batched,
+            timed_out,
+            tripped,
+            expired,
+            published,
+            memory,
+            snapshot,
+        })
+    }
+
+    // not billed, the exchange cancels it on behalf of the account
+    fn expire_order(&mut self, order_id: u64) -> JournalEvent {
+        let event = match self.book_mut(order_id.into()).handle_cancel(order_id.into()) {
+            Ok(_) => JournalEvent::Expired { order_id },
+            Err(error) => JournalEvent::Rejected {
+                order_id,
+                reason: format_compact!("{error}"),
+            },
+        };
+
+        #[cfg(feature = "metrics")]
+        self.counters.record(&event);
+        self.publish_book_events();
+        event
+    }
+
+    #[inline]
+    pub fn pair(&self) -> &Symbol {
+        &self.pair
+    }
+
+    #[inline]
+    pub fn orderbook(&self) -> &Orderbook {
+        &self.orderbook
+    }
+
+    #[inline]
+    pub fn odd_lots(&self) -> &Orderbook {
+        &self.odd_lots
+    }
+
+    #[inline]
+    pub fn market_data(&self) -> &MarketData {
+        &self.market_data
+    }
+
+    #[inline]
+    pub fn billing(&self) -> &MessageBilling {
+        &self.billing
+    }
+
+    #[inline]
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    #[inline]
+    pub fn fx_rates_mut(&mut self) -> &mut FxRates {
+        &mut self.fx_rates
+    }
+
+    // message fees are priced in the quote asset of the pair (e.g. USDT for ETH/USDT)
+    pub fn settle_message_fees(&mut self, now: Timestamp) -> Result<(), EngineError> {
+        let quote = self.pair.quote().as_str();
+        match self.fee_asset.as_deref() {
+            Some(fee_asset) if fee_asset != quote => {
+                let fx = self.fx_rates.rate(quote, fee_asset, now)?;
+                self.billing.settle_with_fx(&mut self.ledger, fee_asset, Some(&fx))?;
+            }
+            _ => self.billing.settle(&mut self.ledger, quote)?,
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn statement(&self, account_id: &str) -> Statement {
+        self.ledger.statement(account_id)
+    }
+}
+
+// what the post-only phase accepts: they never take liquidity, crossing ones are rejected or re-priced by the book
+fn is_post_only(order_request: &OrderRequest) -> bool {
+    matches!(
+        order_request,
+        OrderRequest::Create {
+            time_in_force: Some(
+                TimeInForce::GoodTilCancel { post_only: true }
+                    | TimeInForce::GoodTilDate { post_only: true, .. }
+                    | TimeInForce::Day { post_only: true }
+            ),
+            ..
+        }
+    )
+}
+
+// the orders of a mass quote as plain good-til-cancel creates, for the pre-trade checks
+fn quote_orders(account_id: &str, pair: &Symbol, levels: &[QuoteLevel]) -> Vec<OrderRequest> {
+    levels
+        .iter()
+        .flat_map(QuoteLevel::sides)
+        .map(|(side, quote)| OrderRequest::Create {
+            account_id: account_id.into(),
+            order_id: quote.order_id,
+            pair: pair.clone(),
+            side,
+            limit_price: Some(quote.price),
+            quantity: quote.quantity,
+            time_in_force: None,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: None,
+            price_protection: None,
+            reduce_only: false,
+        })
+        .collect()
+}
+
+// what a tick did, see Engine::tick
+#[derive(Debug, Default)]
+pub struct Tick {
+    pub session: Option<SessionState>, // the previous state when the session changed
+    pub released: Vec<Result<(), EngineError>>,
+    pub batched: Vec<Result<(), EngineError>>,
+    pub timed_out: Vec<(Session, Vec<u64>)>,
+    pub tripped: Vec<(CompactString, Vec<u64>)>,
+    pub expired: Vec<u64>,
+    pub published: usize,
+    pub memory: Vec<MemoryAlert>,
+    pub snapshot: Option<u64>, // sequence of the snapshot taken
+}
+
+impl Tick {
+    // whether nothing happened
+    pub fn is_idle(&self) -> bool {
+        self.session.is_none()
+            && self.released.is_empty()
+            && self.batched.is_empty()
+            && self.timed_out.is_empty()
+            && self.tripped.is_empty()
+            && self.expired.is_empty()
+            && self.published == 0
+            && self.memory.is_empty()
+            && self.snapshot.is_none()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EngineError {
+    #[error("invalid pair (expected={}, found={})", .expected, .found)]
+    InvalidPair {
+        expected: CompactString,
+        found: CompactString,
+    },
+    #[error("ledger error: {0}")]
+    LedgerError(#[from] LedgerError),
+    #[error("journal error: {0}")]
+    JournalError(#[from] JournalError),
+    #[error("recovery error: {0}")]
+    RecoveryError(#[from] RecoveryError),
+    #[error("fx error: {0}")]
+    FxError(#[from] FxError),
+    #[error("orderbook error: {0}")]
+    OrderbookError(#[from] OrderbookError),
+    #[error("post only order would cross the spread (order_id={}, limit_price={}, best_price={})", .order_id, .limit_price, .best_price)]
+    PostOnlyWouldCross {
+        order_id: u64,
+        limit_price: OrderPrice,
+        best_price: OrderPrice,
+    },
+    #[error("account error: {0}")]
+    AccountError(#[from] AccountError),
+    #[error("insufficient funds (account_id={}, asset={}, required={}, available={})", .account_id, .asset, .required, .available)]
+    InsufficientFunds {
+        account_id: CompactString,
+        asset: CompactString,
+        required: Decimal,
+        available: Decimal,
+    },
+    #[error("only asks can be short sales! {0}")]
+    ShortSellOnBid(u64),
+    #[error("locate error: {0}")]
+    LocateError(#[from] LocateError),
+    #[error("invalid off-book trade! {0}")]
+    InvalidOffBookTrade(Box<OffBookTrade>),
+    #[error("review error: {0}")]
+    ReviewError(#[from] ReviewError),
+    #[error("risk error: {0}")]
+    RiskError(#[from] RiskError),
+    #[error("session error: {0}")]
+    SessionError(#[from] SessionError),
+    #[error("no session layer, see Engine::with_sessions")]
+    NoSessionLayer,
+    #[error("dead man's switch error: {0}")]
+    DeadManError(#[from] DeadManError),
+    #[error("no dead man's switch, see Engine::with_dead_man_switch")]
+    NoDeadManSwitch,
+    #[error("no session end for day orders, see Engine::with_session_end")]
+    NoSessionEnd,
+    #[error("no position tracking for reduce-only orders, see Engine::with_positions")]
+    NoPositionTracking,
+    #[error("reduce-only order would increase the position (order_id={}, account_id={})", .order_id, .account_id)]
+    ReduceOnlyWouldIncrease { order_id: u64, account_id: CompactString },
+    #[error("invalid price protection (order_id={}, price_protection={})", .order_id, .price_protection)]
+    InvalidPriceProtection { order_id: u64, price_protection: Decimal },
+    #[cfg(feature = "scripting")]
+    #[error("script error: {0}")]
+    ScriptError(#[from] ScriptError),
+    #[cfg(feature = "storage")]
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+    #[cfg(feature = "scripting")]
+    #[error("rejected by the script rules (order_id={}, reason={})", .order_id, .reason)]
+    RejectedByScript { order_id: u64, reason: CompactString },
+    #[error("priority fee not allowed (order_id={}, priority_fee={})", .order_id, .priority_fee)]
+    PriorityFeeNotAllowed { order_id: u64, priority_fee: Decimal },
+    #[error("trading session not open! {0}")]
+    SessionNotOpen(SessionState),
+    #[error("watermark error: {0}")]
+    WatermarkError(#[from] WatermarkError),
+    #[error("trading not halted! {0}")]
+    NotHalted(SessionState),
+    #[error("no auction to uncross! {0}")]
+    AuctionNotStarted(SessionState),
+    #[error("invalid mass quote (account_id={}, reason={})", .account_id, .reason)]
+    InvalidMassQuote {
+        account_id: CompactString,
+        reason: CompactString,
+    },
+    #[error("order of another account (order_id={}, account_id={})", .order_id, .account_id)]
+    NotOrderOwner { order_id: u64, account_id: CompactString },
+    #[error("no open order with this client order id (account_id={}, client_order_id={})", .account_id, .client_order_id)]
+    ClientOrderIdNotFound {
+        account_id: CompactString,
+        client_order_id: CompactString,
+    },
+}
+
+// the time of the tick running if any, that of the clock of the subsystem otherwise
+#[inline]
+fn now_of(ticked_at: Option<Timestamp>, clock: &dyn Clock) -> Timestamp {
+    ticked_at.unwrap_or_else(|| clock.now())
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        batching::{BatchInterval, BatchOrdering},
+        calendar::Weekday,
+        clock::{ManualClock, DAY, HOUR, MINUTE, SECOND},
+        locate::BorrowInventory,
+        metrics::MetricsQuery,
+        order::{
+            util::{self, DEFAULT_PAIR, DEFAULT_SYMBOL},
+            OrderQuantity, OrderSide, OrderStatus, QuoteSide, TimeInForce,
+        },
+    };
+
+    fn good_til(order_id: u64, side: OrderSide, expires_at: Timestamp) -> OrderRequest {
+        util::create(order_id, side)
+            .time_in_force(Some(TimeInForce::GoodTilDate {
+                expires_at,
+                post_only: false,
+            }))
+            .build()
+    }
+
+    #[fixture]
+    fn engine() -> Engine {
+        Engine::new(DEFAULT_SYMBOL)
+    }
+
+    #[rstest]
+    fn expire_good_til_date_orders(mut engine: Engine) {
+        let market_data = engine.subscribe(Granularity::Order);
+        engine.process(good_til(3, OrderSide::Ask, 50)).unwrap();
+        engine.process(good_til(1, OrderSide::Ask, 100)).unwrap();
+        engine.process(good_til(2, OrderSide::Ask, 200)).unwrap();
+        // order 3 is first in the queue and filled before it expires
+        engine.process(good_til(4, OrderSide::Bid, 300)).unwrap();
+
+        assert_eq!(engine.expire(99).unwrap(), Vec::<u64>::new());
+        assert_eq!(engine.expire(100).unwrap(), vec![1]);
+        assert_eq!(engine.orderbook().get_order(2.into()).unwrap().remaining(), 10.into());
+
+        let last = market_data.try_iter().last().unwrap();
+        assert_eq!(
+            last.event,
+            BookEvent::Delete {
+                order_id: Some(1.into()),
+                side: OrderSide::Ask,
+                price: 15.into()
+            }
+        );
+
+        assert_eq!(engine.expire(1_000).unwrap(), vec![2]);
+        assert!(engine.orderbook().peek_top(&OrderSide::Ask).is_none());
+        // expirations are not billed messages
+        assert_eq!(engine.billing().account("1").unwrap().counts.cancels, 0);
+    }
+
+    #[rstest]
+    fn expire_day_orders_at_the_session_end() {
+        let day = |order_id: u64| {
+            let mut day = good_til(order_id, OrderSide::Ask, 0);
+            if let OrderRequest::Create { time_in_force, .. } = &mut day {
+                *time_in_force = Some(TimeInForce::Day { post_only: false });
+            }
+            day
+        };
+        assert!(matches!(
+            Engine::new(DEFAULT_SYMBOL).process(day(1)),
+            Err(EngineError::NoSessionEnd)
+        ));
+
+        let clock = ManualClock::new(2 * DAY + 10 * HOUR);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_session_end(16 * HOUR, clock.clone());
+        engine.process(day(1)).unwrap();
+        clock.set(2 * DAY + 17 * HOUR);
+        engine.process(day(2)).unwrap();
+        let expires_at = |engine: &Engine, order_id: u64| engine.get_order(order_id.into()).unwrap().expires_at();
+        assert_eq!(expires_at(&engine, 1), Some(2 * DAY + 16 * HOUR));
+        assert_eq!(expires_at(&engine, 2), Some(3 * DAY + 16 * HOUR));
+        assert_eq!(engine.expire(2 * DAY + 16 * HOUR).unwrap(), vec![1]);
+
+        // the close of the calendar otherwise
+        let calendar = TradingCalendar::new(9 * HOUR, 17 * HOUR).unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_calendar(calendar, ManualClock::new(2 * DAY + 10 * HOUR));
+        engine.process(day(1)).unwrap();
+        assert_eq!(expires_at(&engine, 1), Some(2 * DAY + 17 * HOUR));
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<CompactString>>>);
+
+    impl ExecutionListener for Recorder {
+        fn on_accept(&mut self, order_request: &OrderRequest) {
+            match order_request {
+                OrderRequest::Create { order_id, .. } => {
+                    self.0.lock().unwrap().push(format_compact!("accept {order_id}"));
+                }
+                OrderRequest::MassQuote { account_id, .. } => {
+                    self.0
+                        .lock()
+                        .unwrap()
+                        .push(format_compact!("accept quotes {account_id}"));
+                }
+                _ => {}
+            }
+        }
+
+        fn on_fill(&mut self, trade: &Trade, maker: &str, taker: &str) {
+            let fill = format_compact!("fill {} {maker}->{taker}", trade.quantity());
+            self.0.lock().unwrap().push(fill);
+        }
+
+        fn on_cancel(&mut self, order_id: u64) {
+            self.0.lock().unwrap().push(format_compact!("cancel {order_id}"));
+        }
+
+        fn on_reject(&mut self, _order_request: &OrderRequest, reason: &str) {
+            self.0.lock().unwrap().push(format_compact!("reject {reason}"));
+        }
+
+        fn on_expire(&mut self, order_id: u64) {
+            self.0.lock().unwrap().push(format_compact!("expire {order_id}"));
+        }
+    }
+
+    #[rstest]
+    fn notify_the_execution_listener() {
+        let recorder = Recorder::default();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_execution_listener(recorder.clone());
+        engine.process(good_til(1, OrderSide::Ask, 100)).unwrap();
+        engine.process(good_til(2, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.process(util::cancel(2)).unwrap();
+        engine.process(util::cancel(2)).unwrap();
+        engine.expire(100).unwrap();
+        engine.process(good_til(3, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.process(good_til(4, OrderSide::Bid, Timestamp::MAX)).unwrap();
+
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events[..3], ["accept 1", "accept 2", "cancel 2"]);
+        assert!(events[3].starts_with("reject "));
+        assert_eq!(events[4..], ["expire 1", "accept 3", "accept 4", "fill 10 1->1"]);
+    }
+
+    fn mass_quote(account_id: &str, levels: &[(u64, u32, u32)]) -> OrderRequest {
+        // (first order id, bid price, ask price) of every level, 5 on both sides
+        let quote = |order_id: u64, price: u32| QuoteSide {
+            order_id,
+            price: price.into(),
+            quantity: 5.into(),
+        };
+        OrderRequest::MassQuote {
+            account_id: account_id.into(),
+            pair: DEFAULT_SYMBOL,
+            levels: levels
+                .iter()
+                .map(|&(order_id, bid, ask)| QuoteLevel::new(quote(order_id, bid), quote(order_id + 1, ask)))
+                .collect(),
+        }
+    }
+
+    #[rstest]
+    fn replace_the_quotes_of_an_account() {
+        let recorder = Recorder::default();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_execution_listener(recorder.clone());
+        let mut order = good_til(1, OrderSide::Bid, Timestamp::MAX);
+        if let OrderRequest::Create { account_id, .. } = &mut order {
+            *account_id = "mm".into();
+        }
+        engine.process(order).unwrap();
+        engine.process(mass_quote("mm", &[(10, 14, 16), (12, 13, 17)])).unwrap();
+        assert_eq!(engine.orderbook().resting_count(), 5);
+
+        // a bid takes the best ask quote, the next mass quote pulls what is left of the quotes but not the order
+        let mut bid = good_til(2, OrderSide::Bid, Timestamp::MAX);
+        if let OrderRequest::Create { limit_price, .. } = &mut bid {
+            *limit_price = Some(16.into());
+        }
+        engine.process(bid).unwrap();
+        engine.process(mass_quote("mm", &[(20, 15, 17)])).unwrap();
+        let resting: Vec<u64> = engine.open_orders("mm").map(|order| u64::from(order.id())).collect();
+        assert_eq!(resting, vec![1, 20, 21]);
+        assert_eq!(engine.order_status(11.into()).unwrap().status, OrderStatus::Completed);
+        assert_eq!(engine.order_status(12.into()).unwrap().status, OrderStatus::Cancelled);
+
+        // one ack per mass quote, no levels pulls them all
+        engine.process(mass_quote("mm", &[])).unwrap();
+        assert_eq!(engine.open_orders("mm").count(), 1);
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(
+            events[..],
+            [
+                "accept 1",
+                "accept quotes mm",
+                "accept 2",
+                "fill 5 mm->1",
+                "accept quotes mm",
+                "accept quotes mm"
+            ]
+        );
+    }
+
+    #[rstest]
+    fn refuse_a_mass_quote_as_a_whole(engine: Engine) {
+        let mut engine = engine.with_tick_size(2.into());
+        engine.process(mass_quote("mm", &[(10, 14, 16)])).unwrap();
+
+        // the quotes in place stay whatever is wrong with the next ones
+        assert!(matches!(
+            engine.process(mass_quote("mm", &[(20, 14, 16), (22, 18, 20)])),
+            Err(EngineError::InvalidMassQuote { reason, .. }) if reason == "bids cross asks at 18"
+        ));
+        assert!(matches!(
+            engine.process(mass_quote("mm", &[(11, 12, 18)])),
+            Err(EngineError::OrderbookError(OrderbookError::DuplicateOrderId(order_id))) if order_id == 11.into()
+        ));
+        assert!(matches!(
+            engine.process(mass_quote("mm", &[(20, 12, 18), (21, 10, 20)])),
+            Err(EngineError::OrderbookError(OrderbookError::DuplicateOrderId(order_id))) if order_id == 21.into()
+        ));
+        assert!(matches!(
+            engine.process(mass_quote("mm", &[(20, 13, 17)])),
+            Err(EngineError::InvalidMassQuote { .. })
+        ));
+        let resting: Vec<u64> = engine.open_orders("mm").map(|order| u64::from(order.id())).collect();
+        assert_eq!(resting, vec![10, 11]);
+    }
+
+    #[rstest]
+    fn re_quote_with_the_funds_of_the_replaced_quotes() {
+        let mut accounts = AccountManager::default();
+        accounts.deposit("mm", "USDT", 70.into()).unwrap();
+        accounts.deposit("mm", "ETH", 5.into()).unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_accounts(accounts);
+        let balances = |engine: &Engine| {
+            let accounts = engine.accounts().unwrap();
+            (accounts.balance("mm", "USDT"), accounts.balance("mm", "ETH"))
+        };
+
+        // every fund in use, by the quotes replaced then by the new ones
+        engine.process(mass_quote("mm", &[(10, 14, 16)])).unwrap();
+        engine.process(mass_quote("mm", &[(20, 14, 17)])).unwrap();
+        let (usdt, eth) = balances(&engine);
+        assert_eq!((usdt.locked, eth.locked), (70.into(), 5.into()));
+        assert_eq!((usdt.available, eth.available), (Decimal::ZERO, Decimal::ZERO));
+
+        // 5 x 15 = 75 USDT is more than the account has, the quotes in place keep their funds
+        assert!(matches!(
+            engine.process(mass_quote("mm", &[(30, 15, 17)])),
+            Err(EngineError::InsufficientFunds { required, .. }) if required == 75.into()
+        ));
+        assert_eq!(balances(&engine), (usdt, eth));
+        assert_eq!(engine.accounts().unwrap().locked(20.into()), Some(70.into()));
+        let resting: Vec<u64> = engine.open_orders("mm").map(|order| u64::from(order.id())).collect();
+        assert_eq!(resting, vec![20, 21]);
+    }
+
+    #[cfg(feature = "trace")]
+    #[rstest]
+    fn trace_the_order_lifecycle() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let mut engine = Engine::new(DEFAULT_SYMBOL);
+            engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+            engine.process(good_til(2, OrderSide::Ask, Timestamp::MAX)).unwrap();
+            engine.process(util::cancel(2)).unwrap();
+            engine.process(good_til(3, OrderSide::Bid, Timestamp::MAX)).unwrap();
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.iter().any(
+            |line| line.contains("route{pair=ETH/USDT account_id=1 request=[CANCEL]")
+                && line.contains("cancel order_id=2 account_id=1 price=15 quantity=10")
+        ));
+        for order_id in [3, 1] {
+            let fill = format!("fill order_id={order_id} account_id=1 price=15 quantity=10");
+            assert!(lines
+                .iter()
+                .any(|line| line.contains("request=ORDER[3]") && line.contains(&fill)));
+        }
+    }
+
+    #[rstest]
+    fn query_the_status_and_fills_of_an_order(mut engine: Engine) {
+        let mut ask = |order_id, price: u32, quantity: u32| {
+            let mut order_request = good_til(order_id, OrderSide::Ask, Timestamp::MAX);
+            if let OrderRequest::Create {
+                limit_price,
+                quantity: order_quantity,
+                ..
+            } = &mut order_request
+            {
+                *limit_price = Some(price.into());
+                *order_quantity = quantity.into();
+            }
+            engine.process(order_request).unwrap();
+        };
+        ask(1, 14, 4);
+        ask(2, 15, 4);
+        engine.process(good_til(3, OrderSide::Bid, Timestamp::MAX)).unwrap();
+
+        let status = engine.order_status(3.into()).unwrap();
+        assert_eq!(status.status, OrderStatus::Partial);
+        assert_eq!(status.filled_quantity, 8.into());
+        assert_eq!(status.average_price, Some(OrderPrice::new(145, 1)));
+        assert_eq!(status.remaining, 2.into());
+        assert_eq!(status.priority.map(|priority| priority.sequence), Some(3));
+        assert_eq!(engine.executions(3.into()).len(), 2);
+        assert_eq!(engine.executions(1.into())[0].price(), 14.into());
+        assert_eq!(engine.order_status(1.into()).unwrap().status, OrderStatus::Completed);
+
+        engine.process(util::cancel(3)).unwrap();
+        let status = engine.order_status(3.into()).unwrap();
+        assert_eq!(
+            (status.status, status.remaining),
+            (OrderStatus::Closed, OrderQuantity::ZERO)
+        );
+        assert!(status.priority.is_none());
+        assert!(engine.order_status(9.into()).is_none());
+        assert!(engine.executions(9.into()).is_empty());
+    }
+
+    #[rstest]
+    fn keep_ticker_statistics() {
+        let clock = ManualClock::new(0);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_ticker(TickerStats::default(), clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.process(good_til(2, OrderSide::Bid, Timestamp::MAX)).unwrap();
+        engine.process(good_til(3, OrderSide::Ask, Timestamp::MAX)).unwrap();
+
+        let ticker = engine.ticker(DEFAULT_PAIR).unwrap();
+        assert_eq!(
+            (ticker.last_price, ticker.best_ask, ticker.best_bid),
+            (Some(15.into()), Some(15.into()), None)
+        );
+        assert_eq!((ticker.volume, ticker.trades), (10.into(), 1));
+        clock.advance(DAY);
+        let ticker = engine.ticker(DEFAULT_PAIR).unwrap();
+        assert_eq!((ticker.volume, ticker.high), (OrderQuantity::ZERO, None));
+        assert!(engine.ticker("BTC/USDT").is_none());
+    }
+
+    #[rstest]
+    fn sample_metrics() {
+        let clock = ManualClock::new(0);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_metrics(MetricsStore::default(), clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        clock.advance(SECOND);
+        engine
+            .process_batch([good_til(2, OrderSide::Ask, Timestamp::MAX), util::cancel(1)])
+            .unwrap();
+
+        let query = |metric| MetricsQuery {
+            metric,
+            from: 0,
+            to: MINUTE,
+            resolution: None,
+        };
+        let metrics = engine.metrics().unwrap();
+        let throughput = metrics.query(&query(Metric::Throughput)).unwrap();
+        let sums: Vec<Decimal> = throughput.iter().map(|bucket| bucket.sum).collect();
+        assert_eq!(sums, vec![Decimal::ONE, Decimal::TWO]);
+        assert_eq!(metrics.query(&query(Metric::AskDepth)).unwrap()[1].last, Decimal::TEN);
+        assert!(metrics.query(&query(Metric::Spread)).unwrap().is_empty());
+    }
+
+    #[rstest]
+    fn monitor_what_is_retained() {
+        let clock = ManualClock::new(0);
+        let monitor = MemoryMonitor::new(HOUR, 2, 2).with_limit(Subsystem::Subscriptions, 1);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_memory_monitor(monitor, clock.clone());
+        let _market_data = [
+            engine.subscribe(Granularity::Order),
+            engine.subscribe(Granularity::Level),
+        ];
+
+        // orders resting for good, their histories with them
+        let mut alerts = vec![];
+        for order_id in 1..=3 {
+            engine
+                .process(good_til(order_id, OrderSide::Ask, Timestamp::MAX))
+                .unwrap();
+            alerts.extend(engine.tick(clock.now()).unwrap().memory);
+            clock.advance(HOUR);
+        }
+        let subsystems: Vec<Subsystem> = alerts.iter().map(|alert| alert.subsystem).collect();
+        assert_eq!(
+            subsystems,
+            vec![
+                Subsystem::Subscriptions,
+                Subsystem::Orders,
+                Subsystem::Histories,
+                Subsystem::Expiries
+            ]
+        );
+        assert_eq!(engine.retained()[0], (Subsystem::Orders, 3));
+        assert_eq!(engine.memory_monitor().unwrap().last(Subsystem::Trades), Some(0));
+    }
+
+    #[rstest]
+    fn process_a_batch(mut engine: Engine) {
+        let order_requests = || {
+            [
+                good_til(1, OrderSide::Ask, Timestamp::MAX),
+                good_til(2, OrderSide::Ask, Timestamp::MAX),
+                good_til(3, OrderSide::Bid, Timestamp::MAX),
+                util::cancel(2),
+                util::cancel(9),
+            ]
+        };
+        let mut one_by_one = Engine::new(DEFAULT_SYMBOL);
+        let expected = one_by_one.subscribe(Granularity::Order);
+        for order_request in order_requests() {
+            one_by_one.process(order_request).unwrap();
+        }
+
+        let market_data = engine.subscribe(Granularity::Order);
+        let results = engine.process_batch(order_requests()).unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(engine.orderbook().trade_count(), 1);
+        assert!(engine.orderbook().peek_top(&OrderSide::Ask).is_none());
+
+        // the same feed, trade ids aside
+        let book_events = |receiver: &Receiver<MarketDataEvent>| -> Vec<BookEvent> {
+            receiver
+                .try_iter()
+                .map(|event| event.event)
+                .filter(|event| !matches!(event, BookEvent::Trade { .. }))
+                .collect()
+        };
+        assert_eq!(book_events(&market_data), book_events(&expected));
+    }
+
+    #[rstest]
+    fn cancel_all_orders_of_an_account(mut engine: Engine) {
+        let mut other = good_til(3, OrderSide::Ask, Timestamp::MAX);
+        if let OrderRequest::Create { account_id, .. } = &mut other {
+            *account_id = "2".into();
+        }
+        for order_request in [
+            good_til(1, OrderSide::Ask, Timestamp::MAX),
+            good_til(2, OrderSide::Ask, Timestamp::MAX),
+            other,
+        ] {
+            engine.process(order_request).unwrap();
+        }
+        // order 1 is filled by order 4, which does not rest
+        engine.process(good_til(4, OrderSide::Bid, Timestamp::MAX)).unwrap();
+        assert_eq!(engine.open_orders("1").count(), 1);
+
+        assert_eq!(engine.cancel_all_for_account("1").unwrap(), vec![2]);
+        assert_eq!(engine.open_orders("1").count(), 0);
+        assert_eq!(engine.orderbook().peek_top(&OrderSide::Ask).unwrap().id(), 3.into());
+        // one message for the whole batch
+        assert_eq!(engine.billing().account("1").unwrap().counts.cancels, 1);
+        assert_eq!(engine.cancel_all_for_account("1").unwrap(), Vec::<u64>::new());
+    }
+
+    #[rstest]
+    fn cancel_the_orders_of_the_account_only(mut engine: Engine) {
+        engine.process(util::create(1, OrderSide::Ask).build()).unwrap();
+        let other = OrderRequest::Cancel {
+            account_id: "2".into(),
+            order_id: 1,
+        };
+        assert!(matches!(
+            engine.process(other),
+            Err(EngineError::NotOrderOwner { order_id: 1, account_id }) if account_id == "2"
+        ));
+        assert!(engine.get_order(1.into()).is_some());
+        assert!(engine.billing().account("2").is_none());
+
+        engine.process(util::cancel(1)).unwrap();
+        assert!(engine.get_order(1.into()).is_none());
+        assert_eq!(engine.billing().account("1").unwrap().counts.cancels, 1);
+    }
+
+    #[rstest]
+    fn cancel_by_client_order_id() {
+        let recorder = Recorder::default();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_execution_listener(recorder.clone());
+        let with_client_id = |order_id: u64, account: &str| {
+            let mut order_request = good_til(order_id, OrderSide::Ask, Timestamp::MAX);
+            if let OrderRequest::Create {
+                account_id,
+                client_order_id,
+                ..
+            } = &mut order_request
+            {
+                *account_id = account.into();
+                *client_order_id = Some("A-1".into());
+            }
+            order_request
+        };
+        let cancel = |account: &str| OrderRequest::CancelByClientId {
+            account_id: account.into(),
+            client_order_id: "A-1".into(),
+        };
+        engine.process(with_client_id(1, "1")).unwrap();
+        // the same client order id for another account is fine, not for the same one while the order is open
+        engine.process(with_client_id(2, "2")).unwrap();
+        assert!(matches!(
+            engine.process(with_client_id(3, "1")),
+            Err(EngineError::OrderbookError(OrderbookError::DuplicateClientOrderId(_)))
+        ));
+        assert_eq!(engine.order_by_client_id("1", "A-1").unwrap().id(), 1.into());
+        assert_eq!(engine.orderbook().client_order_id(1.into()), Some("A-1"));
+
+        engine.process(cancel("1")).unwrap();
+        assert!(engine.get_order(1.into()).is_none() && engine.get_order(2.into()).is_some());
+        assert!(matches!(
+            engine.process(cancel("1")),
+            Err(EngineError::ClientOrderIdNotFound { .. })
+        ));
+        engine.process(with_client_id(4, "1")).unwrap();
+        assert_eq!(engine.order_by_client_id("1", "A-1").unwrap().id(), 4.into());
+
+        // both refusals reach the listener
+        let rejects = recorder.0.lock().unwrap();
+        let rejects: Vec<_> = rejects.iter().filter(|event| event.starts_with("reject ")).collect();
+        assert_eq!(rejects.len(), 2);
+        assert!(rejects[1].contains("A-1"));
+    }
+
+    #[cfg(feature = "storage")]
+    #[rstest]
+    fn persist_the_trades() {
+        let clock = ManualClock::new(HOUR);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_trade_store(TradeStore::in_memory().unwrap(), clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        let mut bid = good_til(2, OrderSide::Bid, Timestamp::MAX);
+        if let OrderRequest::Create {
+            account_id, quantity, ..
+        } = &mut bid
+        {
+            *account_id = "2".into();
+            *quantity = 4.into();
+        }
+        engine.process(bid.clone()).unwrap();
+        clock.set(2 * HOUR);
+        if let OrderRequest::Create { order_id, .. } = &mut bid {
+            *order_id = 3;
+        }
+        engine.process(bid).unwrap();
+
+        let trade_store = engine.trade_store().unwrap();
+        assert_eq!(trade_store.count().unwrap(), 2);
+        let trades = trade_store.trades_by_account("2").unwrap();
+        let executed: Vec<(Timestamp, &str, &str)> = trades
+            .iter()
+            .map(|stored| (stored.executed_at, stored.maker.as_str(), stored.taker.as_str()))
+            .collect();
+        assert_eq!(executed, vec![(HOUR, "1", "2"), (2 * HOUR, "1", "2")]);
+        assert_eq!(trades[1].trade.taker(), 3.into());
+        let in_range = trade_store.trades_in_range(DEFAULT_PAIR, 2 * HOUR, DAY).unwrap();
+        assert_eq!(in_range.len(), 1);
+        assert!(trade_store.trades_in_range("BTC/USDT", 0, DAY).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "scripting")]
+    #[rstest]
+    fn check_orders_against_the_script_rules() {
+        let script_rules = ScriptRules::compile(
+            r#"
+            fn validate(order) { if order.side == "SELL" && order.limit_price < 15 { "too cheap" } }
+            fn fee(order) { 0.5 }
+            "#,
+        )
+        .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_script_rules(script_rules);
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        let mut cheap = good_til(2, OrderSide::Ask, DAY);
+        if let OrderRequest::Create { limit_price, .. } = &mut cheap {
+            *limit_price = Some(10.into());
+        }
+        assert!(matches!(
+            engine.process(cheap),
+            Err(EngineError::RejectedByScript { order_id: 2, reason }) if reason == "too cheap"
+        ));
+        assert!(engine.get_order(2.into()).is_none());
+        assert_eq!(engine.fee_report("1").order_fees, Decimal::new(5, 1));
+        assert!(!engine.reload_script_rules().unwrap());
+    }
+
+    #[rstest]
+    fn never_increase_a_position_with_reduce_only_orders() {
+        let order = |order_id: u64, account_id: &str, side: OrderSide, price: u32, quantity: u32, reduce_only: bool| {
+            util::create(order_id, side)
+                .account_id(account_id)
+                .limit_price(Some(price.into()))
+                .quantity(quantity)
+                .reduce_only(reduce_only)
+                .build()
+        };
+        assert!(matches!(
+            Engine::new(DEFAULT_SYMBOL).process(order(1, "2", OrderSide::Ask, 20, 15, true)),
+            Err(EngineError::NoPositionTracking)
+        ));
+
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_positions();
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        engine.process(order(2, "2", OrderSide::Bid, 15, 10, false)).unwrap();
+        assert_eq!(engine.positions().unwrap().get("2").unwrap().size, Decimal::from(10));
+
+        // resized to the position, then nothing is left to reduce
+        engine.process(order(3, "2", OrderSide::Ask, 20, 15, true)).unwrap();
+        assert_eq!(engine.get_order(3.into()).unwrap().remaining(), 10.into());
+        for (order_id, side) in [(4, OrderSide::Ask), (5, OrderSide::Bid)] {
+            assert!(matches!(
+                engine.process(order(order_id, "2", side, 18, 1, true)),
+                Err(EngineError::ReduceOnlyWouldIncrease { order_id: id, .. }) if id == order_id
+            ));
+        }
+
+        // the position shrinks under the resting reduce-only order, which goes
+        engine.process(order(6, "3", OrderSide::Bid, 14, 4, false)).unwrap();
+        engine.process(order(7, "2", OrderSide::Ask, 14, 4, false)).unwrap();
+        assert_eq!(engine.positions().unwrap().get("2").unwrap().size, Decimal::from(6));
+        assert!(engine.get_order(3.into()).is_none());
+        assert!(engine.positions().unwrap().verify().is_ok());
+    }
+
+    #[rstest]
+    fn protect_market_orders_in_a_thin_book() {
+        let market = |order_id: u64, price_protection: Option<Decimal>| {
+            util::create(order_id, OrderSide::Bid)
+                .account_id("2")
+                .limit_price(None)
+                .quantity(30)
+                .price_protection(price_protection)
+                .build()
+        };
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_price_protection(Decimal::new(5, 2));
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        let mut far = good_til(2, OrderSide::Ask, Timestamp::MAX);
+        if let OrderRequest::Create { limit_price, .. } = &mut far {
+            *limit_price = Some(20.into());
+        }
+        engine.process(far).unwrap();
+
+        // up to 15.75, the order at 20 is out of the band
+        engine.process(market(3, None)).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 1);
+        assert!(engine.get_order(3.into()).is_none());
+        assert_eq!(engine.get_order(2.into()).unwrap().remaining(), 10.into());
+
+        // a wider band on the request
+        engine.process(market(4, Some(Decimal::new(50, 2)))).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 2);
+        assert!(engine.orderbook().peek_top(&OrderSide::Ask).is_none());
+        assert!(matches!(
+            engine.process(market(5, Some(Decimal::new(-1, 2)))),
+            Err(EngineError::InvalidPriceProtection { order_id: 5, .. })
+        ));
+    }
+
+    #[rstest]
+    fn cancel_when_the_dead_man_switch_runs_out() {
+        assert!(matches!(
+            Engine::new(DEFAULT_SYMBOL).arm_dead_man("1", 5 * SECOND),
+            Err(EngineError::NoDeadManSwitch)
+        ));
+        let clock = ManualClock::new(0);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_dead_man_switch(DeadManSwitch::new(SECOND), clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.process(good_til(2, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        assert_eq!(engine.arm_dead_man("1", 5 * SECOND).unwrap(), 5 * SECOND);
+        engine.arm_dead_man("2", 5 * SECOND).unwrap();
+        engine.disarm_dead_man("2").unwrap();
+
+        clock.advance(4 * SECOND);
+        assert_eq!(engine.refresh_dead_man("1").unwrap(), 9 * SECOND);
+        clock.advance(4 * SECOND);
+        assert!(engine.check_dead_man().unwrap().is_empty());
+        clock.advance(SECOND);
+        assert_eq!(engine.check_dead_man().unwrap(), vec![("1".into(), vec![1, 2])]);
+        assert_eq!(engine.open_orders("1").count(), 0);
+        assert!(matches!(
+            engine.refresh_dead_man("1"),
+            Err(EngineError::DeadManError(DeadManError::NotArmed(_)))
+        ));
+    }
+
+    #[rstest]
+    fn cancel_on_disconnect() {
+        let clock = ManualClock::new(0);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_sessions(SessionManager::new(30 * SECOND), clock.clone());
+        engine.open_session(1, "1").unwrap();
+        engine.open_session(2, "1").unwrap();
+        let market_data = engine.subscribe_session(1, Granularity::Order).unwrap();
+        engine.submit(1, good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.submit(2, good_til(2, OrderSide::Ask, Timestamp::MAX)).unwrap();
+
+        // the other session of the account is still there
+        assert_eq!(engine.close_session(1).unwrap(), Vec::<u64>::new());
+        assert!(matches!(
+            engine.close_session(1),
+            Err(EngineError::SessionError(SessionError::UnknownSession(1)))
+        ));
+
+        clock.advance(20 * SECOND);
+        engine.heartbeat(2).unwrap();
+        clock.advance(20 * SECOND);
+        assert!(engine.check_heartbeats().unwrap().is_empty());
+        clock.advance(10 * SECOND);
+        let timed_out = engine.check_heartbeats().unwrap();
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].1, vec![1, 2]);
+        assert!(engine.orderbook().peek_top(&OrderSide::Ask).is_none());
+        let deletes = market_data
+            .try_iter()
+            .filter(|event| matches!(event.event, BookEvent::Delete { .. }))
+            .count();
+        assert_eq!(deletes, 2);
+
+        // the sweep is audited against the last session of the account, the orders stay linked to theirs
+        let sessions = engine.sessions().unwrap();
+        assert_eq!((sessions.submitted_by(1), sessions.submitted_by(2)), (Some(1), Some(2)));
+        let last = sessions.audit().last().unwrap();
+        assert_eq!(last.session_id, 2);
+        assert_eq!(
+            last.event,
+            SessionEvent::CancelledOnDisconnect { order_ids: vec![1, 2] }
+        );
+    }
+
+    #[rstest]
+    fn delay_orders_taking_liquidity() {
+        let clock = ManualClock::new(0);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_speed_bump(SpeedBump::new(5), clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        assert!(engine.orderbook().get_order(1.into()).is_some());
+
+        // the bid would cross, the maker cancels before it arrives
+        engine.process(good_til(2, OrderSide::Bid, Timestamp::MAX)).unwrap();
+        assert_eq!(engine.speed_bump().unwrap().delayed().count(), 1);
+        engine.process(util::cancel(1)).unwrap();
+        clock.advance(4);
+        assert!(engine.release_delayed_orders().unwrap().is_empty());
+        clock.advance(1);
+        assert_eq!(engine.release_delayed_orders().unwrap().len(), 1);
+        assert_eq!(engine.orderbook().trade_count(), 0);
+        assert_eq!(engine.orderbook().best_bid(), Some(15.into()));
+
+        // the ask crosses the bid now resting, it is released by the next request
+        engine.process(good_til(3, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        clock.advance(5);
+        engine.process(util::cancel(4)).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 1);
+    }
+
+    #[rstest]
+    fn match_per_batch() {
+        let clock = ManualClock::new(0);
+        let batcher = Batcher::new(BatchInterval::Fixed { length: 1 }, BatchOrdering::PricePriority, 0);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_batching(batcher, clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.process(good_til(2, OrderSide::Bid, Timestamp::MAX)).unwrap();
+        assert_eq!(engine.batcher().unwrap().pending().len(), 2);
+        assert!(engine.orderbook().get_order(1.into()).is_none());
+        assert!(engine.flush_batch().unwrap().is_empty());
+
+        // the bid takes its turn first, the ask crosses it
+        clock.advance(1);
+        assert_eq!(engine.flush_batch().unwrap().len(), 2);
+        let trade = *engine.orderbook().trades_from(0).next().unwrap();
+        assert_eq!((trade.taker(), trade.maker()), (1.into(), 2.into()));
+
+        // the batch over, the next request flushes it before opening another one
+        engine.process(good_til(3, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        clock.advance(5);
+        engine.process(good_til(4, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        assert!(engine.orderbook().get_order(3.into()).is_some());
+        assert_eq!(engine.batcher().unwrap().closes_at(), Some(7));
+    }
+
+    #[rstest]
+    fn follow_the_calendar() {
+        // thursday 1970-01-01 00:00, trading 08:00-16:00 with maintenance on fridays 10:00-11:00
+        let clock = ManualClock::new(0);
+        let calendar = TradingCalendar::new(8 * HOUR, 16 * HOUR)
+            .unwrap()
+            .with_maintenance(Weekday::Friday, 10 * HOUR, 11 * HOUR)
+            .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_calendar(calendar, clock.clone());
+        assert_eq!(engine.session(), SessionState::Closed);
+
+        assert!(matches!(
+            engine.process(good_til(1, OrderSide::Ask, DAY)),
+            Err(EngineError::SessionNotOpen(SessionState::Closed))
+        ));
+
+        clock.set(8 * HOUR);
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        assert_eq!(engine.session(), SessionState::Open);
+
+        clock.set(DAY + 10 * HOUR);
+        assert_eq!(engine.update_session().unwrap(), Some(SessionState::Open));
+        assert_eq!(engine.session(), SessionState::Maintenance);
+        assert_eq!(engine.update_session().unwrap(), None);
+
+        // resting orders can still be cancelled
+        engine.process(util::cancel(1)).unwrap();
+        assert!(engine.orderbook().get_order(1.into()).is_none());
+    }
+
+    #[rstest]
+    fn tick_the_time_driven_work() {
+        let clock = ManualClock::new(7 * HOUR);
+        let calendar = TradingCalendar::new(8 * HOUR, 16 * HOUR)
+            .unwrap()
+            .with_pre_open(HOUR)
+            .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_calendar(calendar, clock.clone())
+            .with_dead_man_switch(DeadManSwitch::new(SECOND), clock.clone());
+        engine.process(good_til(1, OrderSide::Ask, 9 * HOUR)).unwrap();
+        engine.process(good_til(2, OrderSide::Ask, 10 * HOUR)).unwrap();
+        engine.arm_dead_man("1", 2 * HOUR + 30 * MINUTE).unwrap();
+        assert!(engine.tick(clock.now()).unwrap().is_idle());
+        assert_eq!(engine.next_expiry(), Some(9 * HOUR));
+
+        clock.set(8 * HOUR);
+        let tick = engine.tick(clock.now()).unwrap();
+        assert_eq!(tick.session, Some(SessionState::PreOpen));
+        assert_eq!(engine.session(), SessionState::Open);
+
+        clock.set(9 * HOUR);
+        assert_eq!(engine.tick(clock.now()).unwrap().expired, vec![1]);
+
+        // the countdown runs out before order 2 expires
+        clock.set(10 * HOUR);
+        let tick = engine.tick(clock.now()).unwrap();
+        assert_eq!(tick.tripped, vec![("1".into(), vec![2])]);
+        assert!(tick.expired.is_empty());
+        assert_eq!(engine.next_expiry(), None);
+    }
+
+    #[rstest]
+    fn tick_every_subsystem_at_the_same_time() {
+        // clocks of their own, left behind
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_dead_man_switch(DeadManSwitch::new(SECOND), ManualClock::new(0))
+            .with_speed_bump(SpeedBump::new(SECOND), ManualClock::new(0));
+        engine.process(good_til(1, OrderSide::Ask, Timestamp::MAX)).unwrap();
+        engine.arm_dead_man("1", MINUTE).unwrap();
+        let market = util::create(2, OrderSide::Bid)
+            .account_id("2")
+            .limit_price(None)
+            .quantity(1);
+        engine.process(market.build()).unwrap();
+        assert_eq!(engine.speed_bump().unwrap().delayed().count(), 1);
+
+        let tick = engine.tick(SECOND).unwrap();
+        assert_eq!(tick.released.len(), 1);
+        assert!(tick.tripped.is_empty());
+        assert_eq!(engine.orderbook().trade_count(), 1);
+
+        let tick = engine.tick(MINUTE).unwrap();
+        assert_eq!(tick.tripped, vec![("1".into(), vec![1])]);
+    }
+
+    #[rstest]
+    fn reject_crossing_post_only(mut engine: Engine) {
+        let post_only = |order_id: u64, side: OrderSide| {
+            let mut order_request = good_til(order_id, side, DAY);
+            if let OrderRequest::Create { time_in_force, .. } = &mut order_request {
+                *time_in_force = Some(TimeInForce::GoodTilCancel { post_only: true });
+            }
+            order_request
+        };
+
+        engine.process(post_only(1, OrderSide::Ask)).unwrap();
+        assert!(matches!(
+            engine.process(post_only(2, OrderSide::Bid)),
+            Err(EngineError::PostOnlyWouldCross { order_id: 2, .. })
+        ));
+        assert!(engine.orderbook().get_order(2.into()).is_none());
+
+        // or re-priced one tick below the ask
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_post_only_repricing(1.into());
+        engine.process(post_only(1, OrderSide::Ask)).unwrap();
+        engine.process(post_only(2, OrderSide::Bid)).unwrap();
+        assert_eq!(
+            engine.orderbook().get_order(2.into()).unwrap().limit_price(),
+            Some(14.into())
+        );
+    }
+
+    #[rstest]
+    fn preview_and_uncross_the_pre_open() {
+        let limit = |order_id: u64, side: OrderSide, quantity: u32, limit_price: u32| {
+            util::create(order_id, side)
+                .limit_price(Some(limit_price.into()))
+                .quantity(quantity)
+                .build()
+        };
+
+        // pre-open from 07:00, open at 08:00
+        let clock = ManualClock::new(7 * HOUR);
+        let calendar = TradingCalendar::new(8 * HOUR, 16 * HOUR)
+            .unwrap()
+            .with_pre_open(HOUR)
+            .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_calendar(calendar, clock.clone());
+        let market_data = engine.subscribe(Granularity::Level);
+        assert_eq!(engine.session(), SessionState::PreOpen);
+
+        // crossing orders rest without trading
+        engine.process(limit(1, OrderSide::Ask, 10, 14)).unwrap();
+        engine.process(limit(2, OrderSide::Ask, 10, 15)).unwrap();
+        engine.process(limit(3, OrderSide::Bid, 15, 15)).unwrap();
+        assert_eq!(
+            engine.indicative(),
+            Some(Uncross {
+                price: 15.into(),
+                matched: 15.into(),
+                imbalance_side: Some(OrderSide::Ask),
+                imbalance: 5.into()
+            })
+        );
+
+        engine.process(limit(4, OrderSide::Bid, 5, 16)).unwrap();
+        let previews: Vec<BookEvent> = market_data
+            .try_iter()
+            .map(|event| event.event)
+            .filter(|event| matches!(event, BookEvent::Indicative { .. }))
+            .collect();
+        // nothing to publish until the book crosses
+        assert_eq!(previews.len(), 2);
+        assert_eq!(
+            previews[1],
+            BookEvent::Indicative {
+                uncross: Some(Uncross {
+                    price: 15.into(),
+                    matched: 20.into(),
+                    imbalance_side: None,
+                    imbalance: 0.into()
+                })
+            }
+        );
+
+        // everything trades at the uncross price once open
+        clock.set(8 * HOUR);
+        assert_eq!(engine.update_session().unwrap(), Some(SessionState::PreOpen));
+        let trades: Vec<BookEvent> = market_data
+            .try_iter()
+            .map(|event| event.event)
+            .filter(|event| matches!(event, BookEvent::Trade { .. }))
+            .collect();
+        assert_eq!(trades.len(), 3);
+        assert!(trades
+            .iter()
+            .all(|trade| matches!(trade, BookEvent::Trade { price, .. } if *price == 15.into())));
+        assert_eq!(engine.orderbook().depth(5), Default::default());
+        assert_eq!(engine.indicative(), None);
+    }
+
+    #[rstest]
+    fn back_orders_with_funds() {
+        let mut accounts = AccountManager::default();
+        accounts.deposit("1", "ETH", 10.into()).unwrap();
+        accounts.deposit("2", "USDT", 200.into()).unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_accounts(accounts);
+        let create = |account_id: &str, order_id: u64, side: OrderSide, quantity: u32, limit_price: Option<u32>| {
+            util::create(order_id, side)
+                .account_id(account_id)
+                .limit_price(limit_price.map(OrderPrice::from))
+                .quantity(quantity)
+                .build()
+        };
+
+        // 14 x 15 = 210 USDT is more than the buyer has
+        engine.process(create("1", 1, OrderSide::Ask, 10, Some(15))).unwrap();
+        assert!(matches!(
+            engine.process(create("2", 2, OrderSide::Bid, 14, Some(15))),
+            Err(EngineError::InsufficientFunds { required, .. }) if required == 210.into()
+        ));
+        assert_eq!(
+            engine.orderbook().get_order(1.into()).unwrap().remaining(),
+            OrderQuantity::from(10)
+        );
+
+        // a market bid only locks what the asks cost
+        engine.process(create("2", 3, OrderSide::Bid, 4, None)).unwrap();
+        let accounts = engine.accounts().unwrap();
+        assert_eq!(accounts.balance("2", "ETH").available, Decimal::from(4));
+        assert_eq!(accounts.balance("2", "USDT").available, Decimal::from(140));
+        assert_eq!(accounts.balance("1", "USDT").available, Decimal::from(60));
+        assert_eq!(accounts.balance("1", "ETH").locked, Decimal::from(6));
+
+        // cancelling releases the rest of the lock
+        engine.process(util::cancel(1)).unwrap();
+        assert_eq!(
+            engine.accounts().unwrap().balance("1", "ETH"),
+            crate::accounts::Balance {
+                available: 6.into(),
+                locked: 0.into()
+            }
+        );
+    }
+
+    #[rstest]
+    fn charge_maker_and_taker_fees() {
+        let mut accounts = AccountManager::default();
+        accounts.deposit("1", "ETH", 10.into()).unwrap();
+        accounts.deposit("2", "USDT", 1_000.into()).unwrap();
+        let schedule = FeeSchedule::builder()
+            .maker_bps((-2).into())
+            .taker_bps(10.into())
+            .build()
+            .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_accounts(accounts)
+            .with_fee_schedule(schedule);
+
+        // 4 x 15 = 60 USDT: 0.06 for the taker, a rebate of 0.012 for the maker
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        let mut bid = good_til(2, OrderSide::Bid, DAY);
+        if let OrderRequest::Create {
+            quantity, account_id, ..
+        } = &mut bid
+        {
+            *quantity = 4.into();
+            *account_id = "2".into();
+        }
+        engine.process(bid).unwrap();
+
+        let trade = engine.orderbook().trades_from(0).last().unwrap();
+        assert_eq!(trade.taker_fee(), Decimal::new(6, 2));
+        assert_eq!(trade.maker_fee(), Decimal::new(-12, 3));
+        assert_eq!(engine.fee_report("2").taker_fees, Decimal::new(6, 2));
+        assert_eq!(engine.fee_report("1").volume, Decimal::from(60));
+
+        let accounts = engine.accounts().unwrap();
+        assert_eq!(accounts.balance("2", "USDT").available, Decimal::new(93994, 2));
+        assert_eq!(accounts.balance("1", "USDT").available, Decimal::new(60012, 3));
+        assert_eq!(
+            accounts.balance(crate::ledger::EXCHANGE_ACCOUNT, "USDT").available,
+            Decimal::new(48, 3)
+        );
+    }
+
+    #[rstest]
+    fn locate_short_sales() {
+        let mut inventory = BorrowInventory::default();
+        inventory.supply(DEFAULT_PAIR, 5.into());
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_locate(inventory);
+        let short_sell = |order_id, quantity: u32| {
+            let mut ask = good_til(order_id, OrderSide::Ask, DAY);
+            if let OrderRequest::Create {
+                quantity: q,
+                short_sell,
+                ..
+            } = &mut ask
+            {
+                *q = quantity.into();
+                *short_sell = true;
+            }
+            ask
+        };
+
+        assert!(matches!(
+            engine.process(short_sell(1, 10)),
+            Err(EngineError::LocateError(LocateError::NotLocated { available, .. })) if available == 5.into()
+        ));
+        assert!(engine.orderbook().get_order(1.into()).is_none());
+
+        // only the located short sale reaches the book, its trades are tagged
+        engine.process(short_sell(2, 4)).unwrap();
+        engine.process(good_til(3, OrderSide::Bid, DAY)).unwrap();
+        let trade = engine.orderbook().trades_from(0).last().unwrap();
+        assert!(trade.is_short_sell());
+        assert_eq!(trade.quantity(), OrderQuantity::from(4));
+
+        let mut bid = short_sell(4, 1);
+        if let OrderRequest::Create { side, .. } = &mut bid {
+            *side = OrderSide::Bid;
+        }
+        assert!(matches!(engine.process(bid), Err(EngineError::ShortSellOnBid(4))));
+    }
+
+    #[rstest]
+    fn route_odd_lots_and_hold_blocks() {
+        let lot_rules = LotRules::new(5.into())
+            .unwrap()
+            .with_odd_lots(OddLotHandling::Separate)
+            .with_block_size(20.into())
+            .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_lot_rules(lot_rules);
+        let market_data = engine.subscribe(Granularity::Order);
+        let order = |order_id, side, quantity: u32| {
+            let mut order = good_til(order_id, side, DAY);
+            if let OrderRequest::Create { quantity: q, .. } = &mut order {
+                *q = quantity.into();
+            }
+            order
+        };
+
+        // odd lots only match each other
+        engine.process(order(1, OrderSide::Bid, 3)).unwrap();
+        engine.process(order(2, OrderSide::Ask, 30)).unwrap();
+        assert!(engine.odd_lots().get_order(1.into()).is_some());
+        assert!(engine.orderbook().get_order(1.into()).is_none());
+        assert_eq!(engine.orderbook().trade_count(), 0);
+
+        engine.process(order(3, OrderSide::Ask, 2)).unwrap();
+        let trade = engine.odd_lots().trades_from(0).last().unwrap();
+        assert!(trade.is_odd_lot());
+        assert_eq!(trade.quantity(), OrderQuantity::from(2));
+
+        // the block trade stays off the feed until released
+        engine.process(order(4, OrderSide::Bid, 20)).unwrap();
+        let trades = |market_data: &Receiver<MarketDataEvent>| {
+            market_data
+                .try_iter()
+                .filter(|event| matches!(event.event, BookEvent::Trade { .. }))
+                .count()
+        };
+        assert_eq!(trades(&market_data), 1);
+        assert_eq!(engine.held_trades().len(), 1);
+        assert_eq!(engine.release_block_trades(), 1);
+        assert_eq!(trades(&market_data), 1);
+        assert!(engine.held_trades().is_empty());
+    }
+
+    #[rstest]
+    fn pay_priority_fees() {
+        let with_fee = |order_id, priority_fee: Decimal| {
+            let mut order_request = good_til(order_id, OrderSide::Ask, Timestamp::MAX);
+            if let OrderRequest::Create { priority_fee: fee, .. } = &mut order_request {
+                *fee = priority_fee;
+            }
+            order_request
+        };
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        assert!(matches!(
+            engine.process(with_fee(1, Decimal::ONE)),
+            Err(EngineError::PriorityFeeNotAllowed { order_id: 1, .. })
+        ));
+
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_queue_priority(QueuePriority::PriorityFee);
+        engine.process(with_fee(1, Decimal::ZERO)).unwrap();
+        engine.process(with_fee(2, Decimal::ONE)).unwrap();
+        assert_eq!(engine.orderbook().peek_top(&OrderSide::Ask).unwrap().id(), 2.into());
+        assert_eq!(engine.fee_report("1").priority_fees, Decimal::ONE);
+        assert!(matches!(
+            engine.process(with_fee(3, Decimal::NEGATIVE_ONE)),
+            Err(EngineError::PriorityFeeNotAllowed { order_id: 3, .. })
+        ));
+    }
+
+    #[rstest]
+    fn check_risk_limits() {
+        let risk_limits = RiskLimits::default()
+            .with_max_quantity(50.into())
+            .with_price_collar(Decimal::new(1, 1));
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_risk_limits(risk_limits);
+
+        // the best opposite price is the reference until something trades
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        let mut bid = good_til(2, OrderSide::Bid, DAY);
+        if let OrderRequest::Create { limit_price, .. } = &mut bid {
+            *limit_price = Some(10.into());
+        }
+        assert!(matches!(
+            engine.process(bid),
+            Err(EngineError::RiskError(RiskError::OutsidePriceCollar { .. }))
+        ));
+        assert!(engine.orderbook().get_order(2.into()).is_none());
+
+        let mut bid = good_til(3, OrderSide::Bid, DAY);
+        if let OrderRequest::Create { quantity, .. } = &mut bid {
+            *quantity = 51.into();
+        }
+        assert!(matches!(
+            engine.process(bid),
+            Err(EngineError::RiskError(RiskError::MaxQuantityExceeded { .. }))
+        ));
+        engine.process(good_til(4, OrderSide::Bid, DAY)).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 1);
+    }
+
+    #[rstest]
+    fn defer_large_in_scale_trades() {
+        let clock = ManualClock::new(0);
+        let deferral = TradeDeferral::new(100.into(), MINUTE);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_trade_deferral(deferral, clock.clone());
+        let market_data = engine.subscribe(Granularity::Order);
+        let trades = |market_data: &Receiver<MarketDataEvent>| {
+            market_data
+                .try_iter()
+                .filter(|event| matches!(event.event, BookEvent::Trade { .. }))
+                .count()
+        };
+
+        // 10 x 15 = 150 is large in scale, the counterparties see the trade right away
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        engine.process(good_til(2, OrderSide::Bid, DAY)).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 1);
+        assert_eq!(trades(&market_data), 0);
+        assert_eq!(engine.held_trades()[0].0, MINUTE);
+
+        clock.advance(MINUTE - 1);
+        assert_eq!(engine.publish_deferred_trades(), 0);
+        clock.advance(1);
+        assert_eq!(engine.publish_deferred_trades(), 1);
+        assert_eq!(trades(&market_data), 1);
+        assert!(engine.held_trades().is_empty());
+    }
+
+    #[rstest]
+    fn report_off_book_trades() {
+        let mut accounts = AccountManager::default();
+        accounts.deposit("1", "ETH", 10.into()).unwrap();
+        accounts.deposit("2", "USDT", 1_000.into()).unwrap();
+        let risk_limits = RiskLimits::default().with_price_collar(Decimal::new(1, 1));
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_accounts(accounts)
+            .with_risk_limits(risk_limits);
+        let market_data = engine.subscribe(Granularity::Order);
+
+        // a trade in the book sets the reference price at 15
+        engine.process(good_til(1, OrderSide::Ask, DAY)).unwrap();
+        let mut bid = good_til(2, OrderSide::Bid, DAY);
+        if let OrderRequest::Create {
+            account_id, quantity, ..
+        } = &mut bid
+        {
+            *account_id = "2".into();
+            *quantity = 1.into();
+        }
+        engine.process(bid).unwrap();
+
+        assert!(matches!(
+            engine.report_trade("2", "1", 20.into(), 5.into()),
+            Err(EngineError::RiskError(RiskError::OutsidePriceCollar { .. }))
+        ));
+        assert!(matches!(
+            engine.report_trade("2", "2", 15.into(), 5.into()),
+            Err(EngineError::InvalidOffBookTrade(_))
+        ));
+
+        assert!(matches!(
+            engine.report_trade("2", "1", 16.into(), 0.into()),
+            Err(EngineError::InvalidOffBookTrade(_))
+        ));
+
+        // the bid left 985 USDT and the ask locked the rest of the ETH, the book is untouched
+        engine.accounts_mut().unwrap().deposit("1", "ETH", 5.into()).unwrap();
+        let trade_id = engine.report_trade("2", "1", 16.into(), 5.into()).unwrap();
+        assert_eq!(engine.off_book_trades()[0].id(), trade_id);
+        assert_eq!(engine.orderbook().trade_count(), 1);
+
+        let accounts = engine.accounts().unwrap();
+        assert_eq!(accounts.balance("2", "ETH").available, Decimal::from(6));
+        assert_eq!(accounts.balance("2", "USDT").available, Decimal::from(905));
+        assert_eq!(engine.ledger().balance("1", "USDT"), Decimal::from(80));
+        assert!(market_data
+            .try_iter()
+            .any(|event| matches!(event.event, BookEvent::OffBookTrade { trade_id: id, .. } if id == trade_id)));
+    }
+
+    #[rstest]
+    fn call_auction(mut engine: Engine) {
+        let market_data = engine.subscribe(Granularity::Level);
+        assert!(matches!(
+            engine.uncross(),
+            Err(EngineError::AuctionNotStarted(SessionState::Open))
+        ));
+        engine.start_auction().unwrap();
+        assert_eq!(engine.session(), SessionState::Auction);
+
+        // crossing orders rest during the call phase, the indicative price follows them
+        let mut bid = good_til(1, OrderSide::Bid, DAY);
+        if let OrderRequest::Create { limit_price, .. } = &mut bid {
+            *limit_price = Some(16.into());
+        }
+        engine.process(bid).unwrap();
+        engine.process(good_til(2, OrderSide::Ask, DAY)).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 0);
+        let indicative = engine.indicative().unwrap();
+        assert_eq!(indicative.matched, OrderQuantity::from(10));
+        assert!(market_data
+            .try_iter()
+            .any(|event| matches!(event.event, BookEvent::Indicative { uncross: Some(_) })));
+
+        let trades = engine.uncross().unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price(), indicative.price);
+        assert_eq!(engine.session(), SessionState::Open);
+        assert_eq!(engine.indicative(), None);
+    }
+
+    #[rstest]
+    fn halt_on_price_moves() {
+        // 10% within a minute halts trading for 5 minutes
+        let clock = ManualClock::new(0);
+        let circuit_breaker = CircuitBreaker::new(Decimal::new(1, 1), MINUTE, 5 * MINUTE);
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_circuit_breaker(circuit_breaker, clock.clone())
+            .with_halt_policy(HaltPolicy::Queue);
+        let market_data = engine.subscribe(Granularity::Order);
+        let order = |order_id, side, price: u32| {
+            let mut order = good_til(order_id, side, DAY);
+            if let OrderRequest::Create {
+                limit_price, quantity, ..
+            } = &mut order
+            {
+                *limit_price = Some(price.into());
+                *quantity = 1.into();
+            }
+            order
+        };
+
+        engine.process(order(1, OrderSide::Ask, 15)).unwrap();
+        engine.process(order(2, OrderSide::Bid, 15)).unwrap();
+        engine.process(order(3, OrderSide::Ask, 17)).unwrap();
+        engine.process(order(4, OrderSide::Bid, 17)).unwrap();
+        assert_eq!(engine.session(), SessionState::Halted);
+
+        // queued until the halt is over
+        engine.process(order(5, OrderSide::Bid, 16)).unwrap();
+        assert!(engine.get_order(5.into()).is_none());
+        clock.advance(5 * MINUTE);
+        assert_eq!(engine.update_session().unwrap(), Some(SessionState::Halted));
+        assert_eq!(engine.session(), SessionState::Open);
+        assert!(engine.get_order(5.into()).is_some());
+
+        let sessions: Vec<SessionState> = market_data
+            .try_iter()
+            .filter_map(|event| match event.event {
+                BookEvent::Session { state } => Some(state),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sessions, vec![SessionState::Halted, SessionState::Open]);
+
+        // only cancels go through by hand
+        engine.cancel_only().unwrap();
+        assert!(matches!(
+            engine.process(order(6, OrderSide::Bid, 16)),
+            Err(EngineError::SessionNotOpen(SessionState::CancelOnly))
+        ));
+        engine.process(util::cancel(5)).unwrap();
+        assert!(engine.resume().unwrap().is_empty());
+        assert!(matches!(
+            engine.resume(),
+            Err(EngineError::NotHalted(SessionState::Open))
+        ));
+    }
+
+    #[rstest]
+    fn halt_on_odd_lot_price_moves() {
+        let lot_rules = LotRules::new(5.into()).unwrap().with_odd_lots(OddLotHandling::Separate);
+        let circuit_breaker = CircuitBreaker::new(Decimal::new(1, 1), MINUTE, 5 * MINUTE);
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_lot_rules(lot_rules)
+            .with_circuit_breaker(circuit_breaker, ManualClock::new(0));
+        let order = |order_id, side, price: u32| {
+            util::create(order_id, side)
+                .limit_price(Some(price.into()))
+                .quantity(1)
+                .build()
+        };
+
+        engine.process(order(1, OrderSide::Ask, 15)).unwrap();
+        engine.process(order(2, OrderSide::Bid, 15)).unwrap();
+        engine.process(order(3, OrderSide::Ask, 17)).unwrap();
+        engine.process(order(4, OrderSide::Bid, 17)).unwrap();
+        assert_eq!(engine.odd_lots().trade_count(), 2);
+        assert_eq!(engine.session(), SessionState::Halted);
+    }
+
+    #[rstest]
+    fn reopen_post_only_after_a_halt() {
+        let clock = ManualClock::new(0);
+        let circuit_breaker = CircuitBreaker::new(Decimal::new(1, 1), MINUTE, 5 * MINUTE);
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_circuit_breaker(circuit_breaker, clock.clone())
+            .with_post_only_reopen(MINUTE, clock.clone())
+            .with_halt_policy(HaltPolicy::Queue);
+        let order = |order_id, side, price: u32, post_only| {
+            let mut order = good_til(order_id, side, DAY);
+            if let OrderRequest::Create {
+                limit_price,
+                quantity,
+                time_in_force,
+                ..
+            } = &mut order
+            {
+                *limit_price = Some(price.into());
+                *quantity = 1.into();
+                *time_in_force = Some(TimeInForce::GoodTilCancel { post_only });
+            }
+            order
+        };
+
+        engine.process(order(1, OrderSide::Ask, 15, false)).unwrap();
+        engine.process(order(2, OrderSide::Bid, 15, false)).unwrap();
+        engine.process(order(3, OrderSide::Ask, 17, false)).unwrap();
+        engine.process(order(4, OrderSide::Bid, 17, false)).unwrap();
+        assert_eq!(engine.session(), SessionState::Halted);
+        engine.process(order(5, OrderSide::Ask, 18, false)).unwrap();
+
+        // the halt is over, the book rebuilds with post-only orders while the queued ones wait
+        clock.advance(5 * MINUTE);
+        assert_eq!(engine.update_session().unwrap(), Some(SessionState::Halted));
+        assert_eq!(engine.session(), SessionState::PostOnly);
+        assert!(matches!(
+            engine.process(order(6, OrderSide::Bid, 16, false)),
+            Err(EngineError::SessionNotOpen(SessionState::PostOnly))
+        ));
+        engine.process(order(7, OrderSide::Bid, 16, true)).unwrap();
+        assert!(engine.get_order(5.into()).is_none());
+
+        clock.advance(MINUTE);
+        engine.process(order(8, OrderSide::Bid, 18, false)).unwrap();
+        assert_eq!(engine.session(), SessionState::Open);
+        assert_eq!(engine.order_status(5.into()).unwrap().status, OrderStatus::Completed);
+
+        // a hand resume goes through the reopen too, a second one ends it
+        engine.halt().unwrap();
+        assert!(engine.resume().unwrap().is_empty());
+        assert_eq!(engine.session(), SessionState::PostOnly);
+        engine.resume().unwrap();
+        assert_eq!(engine.session(), SessionState::Open);
+    }
+
+    #[rstest]
+    fn bust_flagged_trades(#[values(false, true)] odd_lots: bool) {
+        let journal_path = util::TempPath::new(&format!("bust-{odd_lots}.jsonl"));
+        let clock = ManualClock::new(0);
+        let mut accounts = AccountManager::default();
+        accounts.deposit("1", "ETH", 20.into()).unwrap();
+        accounts.deposit("2", "USDT", 1_000.into()).unwrap();
+        let review = ReviewQueue::new(MINUTE).with_band(Decimal::new(1, 1));
+        let lot_rules = odd_lots.then(|| LotRules::new(5.into()).unwrap().with_odd_lots(OddLotHandling::Separate));
+        let new_engine = || {
+            let engine = Engine::new(DEFAULT_SYMBOL);
+            match lot_rules {
+                Some(lot_rules) => engine.with_lot_rules(lot_rules),
+                None => engine,
+            }
+        };
+        let mut engine = new_engine()
+            .with_accounts(accounts)
+            .with_positions()
+            .with_ticker(TickerStats::default(), clock.clone())
+            .with_journal(Journal::open(&journal_path).unwrap())
+            .with_trade_review(review, clock.clone());
+        let market_data = engine.subscribe(Granularity::Order);
+        let order = |order_id, account_id: &str, side, price: u32, quantity: u32| {
+            util::create(order_id, side)
+                .account_id(account_id)
+                .limit_price(Some(price.into()))
+                .quantity(quantity)
+                .time_in_force(Some(TimeInForce::GoodTilDate {
+                    expires_at: DAY,
+                    post_only: false,
+                }))
+                .build()
+        };
+
+        // 15 is the reference (of the round lots), 20 is far through the band
+        let first = if odd_lots { 5 } else { 1 };
+        engine.process(order(1, "1", OrderSide::Ask, 15, first)).unwrap();
+        engine.process(order(2, "2", OrderSide::Bid, 15, first)).unwrap();
+        engine.process(order(3, "1", OrderSide::Ask, 20, 1)).unwrap();
+        engine.process(order(4, "2", OrderSide::Bid, 20, 1)).unwrap();
+        let flagged: Vec<TradeId> = engine
+            .review_queue()
+            .unwrap()
+            .pending()
+            .map(|flagged| flagged.trade.id())
+            .collect();
+        assert_eq!(flagged.len(), 1);
+
+        clock.advance(MINUTE - 1);
+        engine.bust_trade(flagged[0]).unwrap();
+        let book = |engine: &Engine| match odd_lots {
+            true => engine.odd_lots().trades_from(0).next().unwrap().is_busted(),
+            false => engine.orderbook().trades_from(1).next().unwrap().is_busted(),
+        };
+        assert!(book(&engine));
+        let accounts = engine.accounts().unwrap();
+        assert_eq!(
+            accounts.balance("2", "USDT").available,
+            Decimal::from(1_000 - 15 * first)
+        );
+        assert_eq!(accounts.balance("2", "ETH").available, Decimal::from(first));
+        assert_eq!(accounts.balance("1", "USDT").available, Decimal::from(15 * first));
+        assert!(market_data
+            .try_iter()
+            .any(|event| event.event == BookEvent::Bust { trade_id: flagged[0] }));
+
+        // only the first trade is left in the positions, the fills and the ticker
+        let positions = engine.positions().unwrap();
+        assert_eq!(positions.get("2").unwrap().size, Decimal::from(first));
+        assert_eq!(positions.open_interest(), first.into());
+        positions.verify().unwrap();
+        for order_id in [3, 4] {
+            let report = engine.order_status(order_id.into()).unwrap();
+            assert_eq!(report.filled_quantity, OrderQuantity::ZERO);
+            assert!(engine.executions(order_id.into()).is_empty());
+        }
+        let ticker = engine.ticker(DEFAULT_PAIR).unwrap();
+        assert_eq!(
+            (ticker.volume, ticker.last_price, ticker.high),
+            (first.into(), Some(15.into()), Some(15.into()))
+        );
+
+        assert!(matches!(
+            engine.confirm_trade(flagged[0]),
+            Err(EngineError::ReviewError(ReviewError::NotUnderReview(_)))
+        ));
+        drop(engine);
+
+        // the replay busts it again
+        let last = Journal::read(&journal_path).unwrap().last().unwrap().unwrap();
+        assert!(matches!(last.event, JournalEvent::Busted { maker: 3, taker: 4, .. }));
+        let engine = new_engine().replay(&journal_path).unwrap();
+        assert!(book(&engine));
+    }
 }
@@ -0,0 +1,78 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    order::{Order, OrderPrice, OrderQuantity, OrderStatus},
+    orderbook::Priority,
+    trade::{Trade, TradeId},
+};
+
+// status of an order as of the last request processed, the remaining quantity is zero once the order is gone
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OrderStatusReport {
+    pub status: OrderStatus,
+    pub filled_quantity: OrderQuantity,
+    pub average_price: Option<OrderPrice>, // weighted by the quantity of every fill
+    pub remaining: OrderQuantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>, // of a resting order
+}
+
+// what is kept of every order accepted by the engine, resting or gone
+#[derive(Clone, Debug, Default)]
+pub(super) struct OrderHistory {
+    order_quantity: OrderQuantity,
+    filled_quantity: OrderQuantity,
+    notional: Decimal,
+    fills: Vec<Trade>,
+}
+
+impl OrderHistory {
+    #[inline]
+    pub(super) fn new(order_quantity: OrderQuantity) -> Self {
+        Self {
+            order_quantity,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub(super) fn fill(&mut self, trade: &Trade) {
+        self.filled_quantity += trade.quantity();
+        self.notional += trade.notional();
+        self.fills.push(*trade);
+    }
+
+    // the fill of a busted trade is taken back
+    pub(super) fn bust(&mut self, trade_id: TradeId) {
+        if let Some(index) = self.fills.iter().position(|fill| fill.id() == trade_id) {
+            let trade = self.fills.remove(index);
+            self.filled_quantity -= trade.quantity();
+            self.notional -= trade.notional();
+        }
+    }
+
+    #[inline]
+    pub(super) fn fills(&self) -> &[Trade] {
+        &self.fills
+    }
+
+    // the resting order (if any) has the status, a gone one was either filled in full or cancelled (expired, or not
+    // bookable) with or without fills
+    pub(super) fn report(&self, resting: Option<&Order>, priority: Option<Priority>) -> OrderStatusReport {
+        let (status, remaining) = match resting {
+            Some(order) => (order.status(), order.remaining()),
+            None if self.filled_quantity == self.order_quantity => (OrderStatus::Completed, OrderQuantity::ZERO),
+            None if self.filled_quantity.is_zero() => (OrderStatus::Cancelled, OrderQuantity::ZERO),
+            None => (OrderStatus::Closed, OrderQuantity::ZERO),
+        };
+        OrderStatusReport {
+            status,
+            filled_quantity: self.filled_quantity,
+            average_price: (!self.filled_quantity.is_zero())
+                .then(|| OrderPrice::from_decimal(self.notional / self.filled_quantity)),
+            remaining,
+            priority,
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::order::OrderRequest;
+
+// outcome of an order request, journaled next to it so a replay can verify it reaches the same state
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "event")]
+pub enum JournalEvent {
+    Created {
+        order_id: u64,
+        matched: bool,
+    },
+    Cancelled {
+        order_id: u64,
+    },
+    CancelledAll {
+        account_id: CompactString,
+        order_ids: Vec<u64>,
+    },
+    Expired {
+        order_id: u64,
+    },
+    Rejected {
+        order_id: u64,
+        reason: CompactString,
+    },
+    // a trade busted after review, found again on replay by its orders as the trade ids are not replayed
+    Busted {
+        trade_id: u64,
+        maker: u64,
+        taker: u64,
+    },
+    // the quotes of the account replaced by those of a mass quote
+    Quoted {
+        account_id: CompactString,
+        cancelled: Vec<u64>,
+        created: Vec<u64>,
+        matched: bool,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub order_request: OrderRequest,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+// append-only write-ahead log, one JSON entry per line
+pub struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    first_sequence: u64, // of the first entry kept, the ones before may have been compacted away
+    next_sequence: u64,
+}
+
+impl Journal {
+    // a truncated last entry (e.g. crash in the middle of a write) is discarded so appends start on a clean line
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut valid_len = 0;
+        let mut first_sequence = None;
+        let mut next_sequence = 0;
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(&path)?);
+            let mut line = String::new();
+            loop {
+                line.clear();
+                let read = reader.read_line(&mut line)?;
+                if read == 0 || !line.ends_with('\n') {
+                    break;
+                }
+                match serde_json::from_str::<JournalEntry>(&line) {
+                    Ok(entry) => {
+                        first_sequence.get_or_insert(entry.sequence);
+                        next_sequence = entry.sequence + 1;
+                    }
+                    Err(error) if error.is_eof() => break,
+                    Err(error) => return Err(error.into()),
+                }
+                valid_len += read as u64;
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.set_len(valid_len)?;
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            first_sequence: first_sequence.unwrap_or(next_sequence),
+            next_sequence,
+        })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    #[inline]
+    pub fn first_sequence(&self) -> u64 {
+        self.first_sequence
+    }
+
+    // entries kept
+    #[inline]
+    pub fn len(&self) -> usize {
+        (self.next_sequence - self.first_sequence) as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the next entry is numbered `sequence` at least, e.g. when a compaction left none to number it after
+    pub fn resume_at(&mut self, sequence: u64) {
+        if sequence > self.next_sequence {
+            if self.is_empty() {
+                self.first_sequence = sequence;
+            }
+            self.next_sequence = sequence;
+        }
+    }
+
+    // drops the entries before `sequence` (e.g. in a snapshot): the ones kept are written to a new file, synced, that
+    // then replaces the journal; returns how many were dropped
+    pub fn compact(&mut self, sequence: u64) -> Result<usize, JournalError> {
+        self.sync()?;
+        let compacted = self.path.with_extension("compacting");
+        let mut dropped = 0;
+        {
+            let mut writer = BufWriter::new(File::create(&compacted)?);
+            for entry in Self::read(&self.path)? {
+                let entry = entry?;
+                if entry.sequence < sequence {
+                    dropped += 1;
+                    continue;
+                }
+                serde_json::to_writer(&mut writer, &entry)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        std::fs::rename(&compacted, &self.path)?;
+
+        self.writer = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+        self.first_sequence = sequence.clamp(self.first_sequence, self.next_sequence);
+        Ok(dropped)
+    }
+
+    // the entry is flushed to the OS right away, use sync() to make it durable on disk
+    pub fn append(&mut self, order_request: &OrderRequest, event: &JournalEvent) -> Result<u64, JournalError> {
+        let sequence = self.next_sequence;
+        let entry = JournalEntry {
+            sequence,
+            order_request: order_request.clone(),
+            event: event.clone(),
+        };
+
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        self.next_sequence += 1;
+
+        Ok(sequence)
+    }
+
+    pub fn sync(&mut self) -> Result<(), JournalError> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        Ok(())
+    }
+
+    // a truncated last line (e.g. crash in the middle of a write) marks the end of the journal
+    pub fn read(
+        path: impl AsRef<Path>,
+    ) -> Result<impl Iterator<Item = Result<JournalEntry, JournalError>>, JournalError> {
+        let reader = BufReader::new(File::open(path)?);
+        let entries = reader
+            .lines()
+            .map(|line| -> Result<Option<JournalEntry>, JournalError> {
+                let line = line?;
+                match serde_json::from_str(&line) {
+                    Ok(entry) => Ok(Some(entry)),
+                    Err(error) if error.is_eof() => Ok(None),
+                    Err(error) => Err(error.into()),
+                }
+            })
+            .map_while(|entry| entry.transpose());
+
+        Ok(entries)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum JournalError {
+    #[error("journal io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("journal format error: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("replay diverged from the journal! sequence:{0}")]
+    Divergence(u64),
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        engine::Engine,
+        order::{
+            util::{self, TempPath, DEFAULT_SYMBOL},
+            OrderQuantity, OrderSide, TimeInForce,
+        },
+    };
+
+    #[fixture]
+    fn journal_path(#[default("journal")] name: &str) -> TempPath {
+        TempPath::new(&format!("{name}.jsonl"))
+    }
+
+    fn create(order_id: u64, side: OrderSide, quantity: u32, limit_price: u32) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(Some(limit_price.into()))
+            .quantity(quantity)
+            .build()
+    }
+
+    #[rstest]
+    fn replay_rebuilds_engine(#[with("replay")] journal_path: TempPath) {
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_journal(Journal::open(&journal_path).unwrap());
+        for order_request in [
+            create(1, OrderSide::Ask, 10, 15),
+            create(2, OrderSide::Ask, 10, 16),
+            create(3, OrderSide::Bid, 4, 15),
+            util::cancel(2),
+            util::cancel(2),
+        ] {
+            engine.process(order_request).unwrap();
+        }
+        let top_ask = *engine.orderbook().peek_top(&OrderSide::Ask).unwrap();
+        drop(engine);
+
+        let events: Vec<JournalEvent> = Journal::read(&journal_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().event)
+            .collect();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[2],
+            JournalEvent::Created {
+                order_id: 3,
+                matched: true
+            }
+        );
+        assert!(matches!(events[4], JournalEvent::Rejected { order_id: 2, .. }));
+
+        // the replayed engine has the same book and keeps appending after the last entry
+        let mut engine = Engine::new(DEFAULT_SYMBOL).replay(&journal_path).unwrap();
+        let replayed_top_ask = engine.orderbook().peek_top(&OrderSide::Ask).unwrap();
+        assert_eq!(replayed_top_ask, &top_ask);
+        assert_eq!(replayed_top_ask.remaining(), OrderQuantity::from(6));
+
+        engine.process(util::cancel(1)).unwrap();
+        let last = Journal::read(&journal_path).unwrap().last().unwrap().unwrap();
+        assert_eq!(last.sequence, 5);
+        assert_eq!(last.event, JournalEvent::Cancelled { order_id: 1 });
+    }
+
+    #[rstest]
+    fn replay_expirations(#[with("expirations")] journal_path: TempPath) {
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_journal(Journal::open(&journal_path).unwrap());
+        let mut good_til_date = create(1, OrderSide::Ask, 10, 15);
+        if let OrderRequest::Create { time_in_force, .. } = &mut good_til_date {
+            *time_in_force = Some(TimeInForce::GoodTilDate {
+                expires_at: 100,
+                post_only: false,
+            });
+        }
+        engine.process(good_til_date).unwrap();
+        engine.process(create(2, OrderSide::Ask, 10, 16)).unwrap();
+        assert_eq!(engine.expire(100).unwrap(), vec![1]);
+        drop(engine);
+
+        let last = Journal::read(&journal_path).unwrap().last().unwrap().unwrap();
+        assert_eq!(last.event, JournalEvent::Expired { order_id: 1 });
+
+        // the expiration is replayed at its place in the journal, not at the next sweep
+        let mut engine = Engine::new(DEFAULT_SYMBOL).replay(&journal_path).unwrap();
+        assert_eq!(engine.orderbook().peek_top(&OrderSide::Ask).unwrap().id(), 2.into());
+        assert!(engine.expire(100).unwrap().is_empty());
+    }
+
+    #[rstest]
+    fn ignore_truncated_entry(#[with("truncated")] journal_path: TempPath) {
+        let mut journal = Journal::open(&journal_path).unwrap();
+        let event = JournalEvent::Created {
+            order_id: 1,
+            matched: false,
+        };
+        journal.append(&create(1, OrderSide::Bid, 10, 15), &event).unwrap();
+        drop(journal);
+
+        // simulate a crash in the middle of a write
+        let mut file = OpenOptions::new().append(true).open(&journal_path).unwrap();
+        file.write_all(br#"{"sequence":1,"order_request":{"order_requ"#)
+            .unwrap();
+
+        let mut engine = Engine::new(DEFAULT_SYMBOL).replay(&journal_path).unwrap();
+        assert!(engine.orderbook().peek_top(&OrderSide::Bid).is_some());
+
+        // the truncated entry is gone and the next one is appended in its place
+        engine.process(util::cancel(1)).unwrap();
+        let sequences: Vec<u64> = Journal::read(&journal_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().sequence)
+            .collect();
+        assert_eq!(sequences, vec![0, 1]);
+    }
+
+    #[rstest]
+    fn detect_divergence(#[with("divergence")] journal_path: TempPath) {
+        // an order that cannot match recorded as matched
+        let mut journal = Journal::open(&journal_path).unwrap();
+        let event = JournalEvent::Created {
+            order_id: 1,
+            matched: true,
+        };
+        journal.append(&create(1, OrderSide::Bid, 10, 15), &event).unwrap();
+
+        let replay = Engine::new(DEFAULT_SYMBOL).replay(&journal_path);
+        assert!(matches!(
+            replay,
+            Err(crate::engine::EngineError::JournalError(JournalError::Divergence(0)))
+        ));
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{order::OrderRequest, trade::Trade};
+
+use super::journal::JournalEvent;
+
+// called synchronously by the engine while it processes, e.g. to settle, log or persist elsewhere, every method does
+// nothing unless overridden
+pub trait ExecutionListener: Send {
+    // the order made it to the book (it may have matched right away)
+    fn on_accept(&mut self, _order_request: &OrderRequest) {}
+
+    // once per trade, with its fees and the accounts of both sides
+    fn on_fill(&mut self, _trade: &Trade, _maker: &str, _taker: &str) {}
+
+    fn on_cancel(&mut self, _order_id: u64) {}
+
+    // refused by a pre-trade check or by the book
+    fn on_reject(&mut self, _order_request: &OrderRequest, _reason: &str) {}
+
+    fn on_expire(&mut self, _order_id: u64) {}
+}
+
+impl ExecutionListener for () {}
+
+// the outcome of a request as the journal has it
+pub(super) fn notify(listener: &mut dyn ExecutionListener, order_request: &OrderRequest, event: &JournalEvent) {
+    match event {
+        // one ack for the whole mass quote
+        JournalEvent::Created { .. } | JournalEvent::Quoted { .. } => listener.on_accept(order_request),
+        JournalEvent::Cancelled { order_id } => listener.on_cancel(*order_id),
+        JournalEvent::CancelledAll { order_ids, .. } => {
+            for order_id in order_ids {
+                listener.on_cancel(*order_id);
+            }
+        }
+        JournalEvent::Expired { order_id } => listener.on_expire(*order_id),
+        JournalEvent::Rejected { reason, .. } => listener.on_reject(order_request, reason),
+        // no request of the client
+        JournalEvent::Busted { .. } => {}
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    calendar::SessionState,
+    clock::{Timestamp, HOUR},
+    order::{Order, OrderId},
+    orderbook::Priority,
+    symbol::Symbol,
+};
+
+const JOURNAL_FILE: &str = "journal.jsonl";
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+const SNAPSHOT_EXTENSION: &str = "json";
+
+// an order resting in the book as of a snapshot, with what the book knows of it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RestingOrder {
+    pub order: Order,
+    pub account_id: CompactString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<CompactString>,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    pub odd_lot: bool, // in the book of the odd lots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>, // none for the auction orders
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    pub quote: bool, // replaced by the next mass quote of the account
+}
+
+// the books as of the journal entry numbered `sequence` (excluded): restoring it then replaying the entries from there
+// reaches the same books; the fills of the orders gone are not in it, nor the accounts, the positions, the ledger and
+// the circuit breaker (an engine keeping them takes no snapshot)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub pair: Symbol,
+    pub sequence: u64,
+    pub session: SessionState,
+    pub orders: Vec<RestingOrder>, // bids then asks in priority order, then the auction orders
+    pub handled: Vec<OrderId>,     // every id taken by the book, new orders cannot reuse them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub odd_lots_handled: Vec<OrderId>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotConfig {
+    pub interval: Timestamp, // between two snapshots
+    pub keep: usize,         // snapshots kept, the older ones are removed
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            interval: HOUR,
+            keep: 2,
+        }
+    }
+}
+
+// the directory of the journal and the snapshots of an engine: a snapshot is written aside, synced then renamed so a
+// crash leaves either snapshot but never a torn one, and the journal is compacted once the snapshot is in place
+#[derive(Debug)]
+pub struct Recovery {
+    dir: PathBuf,
+    last_at: Option<Timestamp>, // of the last snapshot taken in this run
+}
+
+impl Recovery {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, RecoveryError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, last_at: None })
+    }
+
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[inline]
+    pub fn journal_path(&self) -> PathBuf {
+        self.dir.join(JOURNAL_FILE)
+    }
+
+    #[inline]
+    pub fn last_at(&self) -> Option<Timestamp> {
+        self.last_at
+    }
+
+    #[inline]
+    pub(crate) fn set_last_at(&mut self, now: Timestamp) {
+        self.last_at = Some(now);
+    }
+
+    // the first snapshot of a run is due right away
+    #[inline]
+    pub fn is_due(&self, now: Timestamp, interval: Timestamp) -> bool {
+        self.last_at.is_none_or(|last_at| now >= last_at + interval)
+    }
+
+    fn snapshot_path(&self, sequence: u64) -> PathBuf {
+        self.dir
+            .join(format!("{SNAPSHOT_PREFIX}{sequence:020}.{SNAPSHOT_EXTENSION}"))
+    }
+
+    // the snapshots in the directory by sequence, oldest first
+    pub fn snapshots(&self) -> Result<Vec<(u64, PathBuf)>, RecoveryError> {
+        let mut snapshots = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != SNAPSHOT_EXTENSION) {
+                continue;
+            }
+            let sequence = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.strip_prefix(SNAPSHOT_PREFIX))
+                .and_then(|sequence| sequence.parse().ok());
+            if let Some(sequence) = sequence {
+                snapshots.push((sequence, path));
+            }
+        }
+        snapshots.sort();
+        Ok(snapshots)
+    }
+
+    pub fn latest(&self) -> Result<Option<EngineSnapshot>, RecoveryError> {
+        let Some((_, path)) = self.snapshots()?.pop() else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_reader(BufReader::new(File::open(path)?))?))
+    }
+
+    // then only the last `keep` snapshots are left (at least this one)
+    pub fn save(&self, snapshot: &EngineSnapshot, keep: usize) -> Result<PathBuf, RecoveryError> {
+        let path = self.snapshot_path(snapshot.sequence);
+        let written = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&written)?);
+            serde_json::to_writer(&mut writer, snapshot)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&written, &path)?;
+
+        let snapshots = self.snapshots()?;
+        let stale = snapshots.len().saturating_sub(keep.max(1));
+        for (_, stale) in snapshots.into_iter().take(stale) {
+            fs::remove_file(stale)?;
+        }
+        Ok(path)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RecoveryError {
+    #[error("recovery io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("snapshot format error: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("no recovery directory, see Engine::recover")]
+    NotRecovered,
+    #[error("snapshot refused, it does not hold the {0}")]
+    NotInSnapshot(&'static str),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        accounts::AccountManager,
+        clock::{Clock, ManualClock, MINUTE},
+        engine::{journal::Journal, Engine, EngineError},
+        memory::Subsystem,
+        order::{
+            util::{self, TempPath, DEFAULT_PAIR, DEFAULT_SYMBOL},
+            OrderRequest, OrderSide, TimeInForce,
+        },
+    };
+
+    #[fixture]
+    fn recovery_dir(#[default("recovery")] name: &str) -> TempPath {
+        TempPath::new(name)
+    }
+
+    fn create(order_id: u64, side: OrderSide, limit_price: u32, time_in_force: Option<TimeInForce>) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(Some(limit_price.into()))
+            .quantity(2)
+            .time_in_force(time_in_force)
+            .client_order_id(Some(format!("c{order_id}").into()))
+            .build()
+    }
+
+    fn good_til(expires_at: Timestamp) -> Option<TimeInForce> {
+        Some(TimeInForce::GoodTilDate {
+            expires_at,
+            post_only: false,
+        })
+    }
+
+    #[rstest]
+    fn recover_from_snapshot_and_journal(#[with("snapshot")] recovery_dir: TempPath) {
+        let mut engine = Engine::new(DEFAULT_SYMBOL).recover(&recovery_dir).unwrap();
+        engine.process(create(1, OrderSide::Bid, 14, None)).unwrap();
+        engine.process(create(2, OrderSide::Bid, 14, good_til(MINUTE))).unwrap();
+        engine.process(create(3, OrderSide::Ask, 16, None)).unwrap();
+        engine.process(create(4, OrderSide::Ask, 14, None)).unwrap(); // fills 1
+        assert_eq!(engine.take_snapshot().unwrap(), 4);
+
+        // the journal only keeps what comes after the snapshot
+        engine.process(create(5, OrderSide::Ask, 17, None)).unwrap();
+        engine.process(util::cancel(3)).unwrap();
+        let recovery = Recovery::open(&recovery_dir).unwrap();
+        let sequences: Vec<u64> = Journal::read(recovery.journal_path())
+            .unwrap()
+            .map(|entry| entry.unwrap().sequence)
+            .collect();
+        assert_eq!(sequences, vec![4, 5]);
+        let expected = engine.snapshot();
+        let priorities = [2, 5].map(|order_id| engine.orderbook().priority(order_id.into()));
+        drop(engine);
+
+        let mut engine = Engine::new(DEFAULT_SYMBOL).recover(&recovery_dir).unwrap();
+        let recovered = engine.snapshot();
+        assert_eq!(recovered.sequence, 6);
+        assert_eq!(
+            serde_json::to_value(&recovered.orders).unwrap(),
+            serde_json::to_value(&expected.orders).unwrap()
+        );
+        assert_eq!(engine.orderbook().client_order_id(2.into()), Some("c2"));
+        // the orders keep their place in their level, the ones replayed from the journal included
+        assert_eq!(
+            [2, 5].map(|order_id| engine.orderbook().priority(order_id.into())),
+            priorities
+        );
+        assert_eq!(engine.next_expiry(), Some(MINUTE));
+
+        // the ids taken before the snapshot stay taken, the journal goes on from where it was
+        engine.process(create(1, OrderSide::Bid, 10, None)).unwrap();
+        assert!(engine.get_order(1.into()).is_none());
+        assert_eq!(engine.expire(MINUTE).unwrap(), vec![2]);
+        assert_eq!(engine.snapshot().sequence, 8);
+    }
+
+    #[rstest]
+    fn reject_snapshot_of_another_pair(#[with("pair")] recovery_dir: TempPath) {
+        let recovery = Recovery::open(&recovery_dir).unwrap();
+        let mut snapshot = Engine::new(DEFAULT_SYMBOL).snapshot();
+        snapshot.pair = "BTC/USDT".parse().unwrap();
+        recovery.save(&snapshot, 1).unwrap();
+
+        assert!(matches!(
+            Engine::new(DEFAULT_SYMBOL).recover(&recovery_dir),
+            Err(EngineError::InvalidPair { expected, .. }) if expected == DEFAULT_PAIR
+        ));
+        assert!(matches!(
+            Engine::new(DEFAULT_SYMBOL).take_snapshot(),
+            Err(EngineError::RecoveryError(RecoveryError::NotRecovered))
+        ));
+    }
+
+    #[rstest]
+    fn snapshot_on_the_ticks(#[with("ticks")] recovery_dir: TempPath) {
+        let clock = ManualClock::new(0);
+        let config = SnapshotConfig {
+            interval: MINUTE,
+            keep: 2,
+        };
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_snapshots(config, clock.clone())
+            .recover(&recovery_dir)
+            .unwrap();
+
+        // none until the interval is over since the recovery
+        assert!(engine.tick(clock.now()).unwrap().is_idle());
+        for order_id in 1..=3 {
+            engine.process(create(order_id, OrderSide::Bid, 14, None)).unwrap();
+            clock.advance(MINUTE);
+            assert_eq!(engine.tick(clock.now()).unwrap().snapshot, Some(order_id));
+            assert!(engine.tick(clock.now()).unwrap().is_idle());
+        }
+
+        let recovery = Recovery::open(&recovery_dir).unwrap();
+        let sequences: Vec<u64> = recovery
+            .snapshots()
+            .unwrap()
+            .into_iter()
+            .map(|(sequence, _)| sequence)
+            .collect();
+        assert_eq!(sequences, vec![2, 3]);
+        assert_eq!(recovery.latest().unwrap().unwrap().orders.len(), 3);
+        assert_eq!(engine.retained()[6], (Subsystem::JournalEntries, 0));
+    }
+
+    #[rstest]
+    fn keep_the_journal_of_the_accounts(#[with("accounts")] recovery_dir: TempPath) {
+        let clock = ManualClock::new(0);
+        let accounts = || {
+            let mut accounts = AccountManager::default();
+            accounts.deposit("1", "USDT", 100.into()).unwrap();
+            accounts
+        };
+        let mut engine = Engine::new(DEFAULT_SYMBOL)
+            .with_accounts(accounts())
+            .with_snapshots(SnapshotConfig::default(), clock.clone())
+            .recover(&recovery_dir)
+            .unwrap();
+        engine.process(create(1, OrderSide::Bid, 14, None)).unwrap();
+        engine.process(create(2, OrderSide::Bid, 15, None)).unwrap();
+
+        // neither by hand nor on the ticks, the locked funds would be gone with the compacted entries
+        assert!(matches!(
+            engine.take_snapshot(),
+            Err(EngineError::RecoveryError(RecoveryError::NotInSnapshot("accounts")))
+        ));
+        clock.advance(HOUR);
+        assert!(engine.tick(clock.now()).unwrap().is_idle());
+        let recovery = Recovery::open(&recovery_dir).unwrap();
+        assert!(recovery.snapshots().unwrap().is_empty());
+        assert_eq!(Journal::read(recovery.journal_path()).unwrap().count(), 2);
+        drop(engine);
+
+        // the whole journal is replayed
+        let engine = Engine::new(DEFAULT_SYMBOL)
+            .with_accounts(accounts())
+            .recover(&recovery_dir)
+            .unwrap();
+        assert_eq!(engine.snapshot().orders.len(), 2);
+        assert_eq!(engine.snapshot().sequence, 2);
+    }
+}
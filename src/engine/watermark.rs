@@ -0,0 +1,217 @@
+use std::{
+    fs, io,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// what becomes of a new order whose id was already taken by a previous run
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CollisionPolicy {
+    #[default]
+    Reject,
+    Remap, // to the next id above the watermark, the cancels of the original id follow it
+}
+
+// highest order id taken so far, kept in a file across restarts: the ids up to the one found on open belong to previous
+// runs (whose orders may be in the audit trail or an older journal only) and new orders cannot reuse them
+#[derive(Debug)]
+pub struct IdWatermark {
+    path: PathBuf,
+    policy: CollisionPolicy,
+    floor: Option<u64>,   // as of the open
+    highest: Option<u64>, // as persisted
+    remapped: IndexMap<u64, u64>,
+}
+
+impl IdWatermark {
+    pub fn open(path: impl AsRef<Path>, policy: CollisionPolicy) -> Result<Self, WatermarkError> {
+        let path = path.as_ref().to_path_buf();
+        let floor = match fs::read_to_string(&path) {
+            Ok(content) => Some(content.trim().parse()?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Self {
+            path,
+            policy,
+            floor,
+            highest: floor,
+            remapped: IndexMap::new(),
+        })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn policy(&self) -> CollisionPolicy {
+        self.policy
+    }
+
+    #[inline]
+    pub fn floor(&self) -> Option<u64> {
+        self.floor
+    }
+
+    #[inline]
+    pub fn highest(&self) -> Option<u64> {
+        self.highest
+    }
+
+    // the id a remapped order got in this run
+    #[inline]
+    pub fn remapped(&self, order_id: u64) -> Option<u64> {
+        self.remapped.get(&order_id).copied()
+    }
+
+    // the id a new order goes on with, the watermark is persisted before it is used (an order rejected afterwards
+    // still burns its id)
+    pub fn take(&mut self, order_id: u64) -> Result<u64, WatermarkError> {
+        let order_id = match self.floor {
+            Some(floor) if order_id <= floor => match self.policy {
+                CollisionPolicy::Reject => return Err(WatermarkError::Collision { order_id, floor }),
+                CollisionPolicy::Remap => {
+                    let next = self
+                        .highest
+                        .unwrap_or(floor)
+                        .checked_add(1)
+                        .ok_or(WatermarkError::Exhausted)?;
+                    self.remapped.insert(order_id, next);
+                    next
+                }
+            },
+            _ => order_id,
+        };
+        if self.highest.is_none_or(|highest| order_id > highest) {
+            self.persist(order_id)?;
+            self.highest = Some(order_id);
+        }
+        Ok(order_id)
+    }
+
+    // the ids of orders going on together (e.g. a mass quote): all of them are taken, or none when one collides
+    pub fn take_all(&mut self, order_ids: &[u64]) -> Result<Vec<u64>, WatermarkError> {
+        if let Some(floor) = self.floor {
+            let colliding = order_ids.iter().filter(|order_id| **order_id <= floor);
+            match self.policy {
+                CollisionPolicy::Reject => {
+                    if let Some(order_id) = colliding.copied().next() {
+                        return Err(WatermarkError::Collision { order_id, floor });
+                    }
+                }
+                CollisionPolicy::Remap => {
+                    self.highest
+                        .unwrap_or(floor)
+                        .checked_add(colliding.count() as u64)
+                        .ok_or(WatermarkError::Exhausted)?;
+                }
+            }
+        }
+        order_ids.iter().map(|order_id| self.take(*order_id)).collect()
+    }
+
+    // written aside then renamed, a crash leaves either watermark but never a torn one
+    fn persist(&self, order_id: u64) -> io::Result<()> {
+        let path = self.path.with_extension("tmp");
+        fs::write(&path, order_id.to_string())?;
+        fs::rename(path, &self.path)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WatermarkError {
+    #[error("watermark io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("watermark format error: {0}")]
+    Format(#[from] ParseIntError),
+    #[error("order id taken by a previous run (order_id={}, floor={})", .order_id, .floor)]
+    Collision { order_id: u64, floor: u64 },
+    #[error("no order id left above the watermark")]
+    Exhausted,
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        engine::{Engine, EngineError},
+        order::{
+            util::{self, TempPath, DEFAULT_SYMBOL},
+            OrderRequest, OrderSide,
+        },
+    };
+
+    #[fixture]
+    fn watermark_path(#[default("watermark")] name: &str) -> TempPath {
+        TempPath::new(&format!("{name}.id"))
+    }
+
+    fn create(order_id: u64) -> OrderRequest {
+        util::create(order_id, OrderSide::Bid).quantity(1).build()
+    }
+
+    fn restart(path: &Path, policy: CollisionPolicy) -> Engine {
+        Engine::new(DEFAULT_SYMBOL).with_id_watermark(IdWatermark::open(path, policy).unwrap())
+    }
+
+    #[rstest]
+    fn reject_ids_of_a_previous_run(#[with("reject")] watermark_path: TempPath) {
+        let mut engine = restart(&watermark_path, CollisionPolicy::Reject);
+        for order_id in [1, 3, 2] {
+            engine.process(create(order_id)).unwrap();
+        }
+        assert_eq!(engine.id_watermark().unwrap().highest(), Some(3));
+
+        let mut engine = restart(&watermark_path, CollisionPolicy::Reject);
+        assert!(matches!(
+            engine.process(create(2)),
+            Err(EngineError::WatermarkError(WatermarkError::Collision {
+                order_id: 2,
+                floor: 3
+            }))
+        ));
+        engine.process(create(4)).unwrap();
+        assert_eq!(fs::read_to_string(&watermark_path).unwrap(), "4");
+    }
+
+    #[rstest]
+    fn remap_ids_of_a_previous_run(#[with("remap")] watermark_path: TempPath) {
+        restart(&watermark_path, CollisionPolicy::Remap)
+            .process(create(5))
+            .unwrap();
+
+        let mut engine = restart(&watermark_path, CollisionPolicy::Remap);
+        engine.process(create(5)).unwrap();
+        engine.process(create(1)).unwrap();
+        assert!(engine.get_order(6.into()).is_some());
+        assert_eq!(engine.id_watermark().unwrap().remapped(1), Some(7));
+
+        // the cancel of the id the client knows
+        engine.process(util::cancel(5)).unwrap();
+        assert!(engine.get_order(6.into()).is_none());
+        assert!(engine.get_order(7.into()).is_some());
+    }
+
+    #[rstest]
+    fn take_all_ids_or_none(#[with("all")] watermark_path: TempPath) {
+        fs::write(&watermark_path, "3").unwrap();
+        let mut watermark = IdWatermark::open(&watermark_path, CollisionPolicy::Reject).unwrap();
+        assert!(matches!(
+            watermark.take_all(&[4, 5, 2]),
+            Err(WatermarkError::Collision { order_id: 2, floor: 3 })
+        ));
+        assert_eq!(watermark.highest(), Some(3));
+
+        assert_eq!(watermark.take_all(&[4, 5]).unwrap(), vec![4, 5]);
+        assert_eq!(watermark.highest(), Some(5));
+    }
+}
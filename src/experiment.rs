@@ -0,0 +1,267 @@
+use std::{fmt::Display, path::Path};
+
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    engine::{
+        journal::{Journal, JournalError, JournalEvent},
+        Engine,
+    },
+    order::{OrderQuantity, OrderRequest, OrderSide},
+};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountOutcome {
+    pub maker_volume: OrderQuantity,
+    pub taker_volume: OrderQuantity,
+    pub fees: Decimal, // maker and taker fees paid
+}
+
+// outcome of one arm of the experiment
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArmMetrics {
+    pub requests: u64,
+    pub rejected: u64,
+    pub submitted: OrderQuantity, // quantity of the orders created
+    pub filled: OrderQuantity,    // both sides of every trade
+    pub trades: u64,
+    spread_paid: Decimal,         // quantity weighted, against the mid price on arrival of the taker
+    spread_volume: OrderQuantity, // traded with a mid price on arrival
+    pub accounts: IndexMap<CompactString, AccountOutcome>,
+}
+
+impl ArmMetrics {
+    #[inline]
+    pub fn fill_rate(&self) -> Decimal {
+        self.filled
+            .value()
+            .checked_div(self.submitted.value())
+            .unwrap_or_default()
+    }
+
+    // twice the distance of the trade price to the mid price on arrival, quantity weighted
+    #[inline]
+    pub fn effective_spread(&self) -> Option<Decimal> {
+        self.spread_paid.checked_div(self.spread_volume.value())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExperimentReport {
+    pub a: ArmMetrics,
+    pub b: ArmMetrics,
+}
+
+impl ExperimentReport {
+    // b less a, so positive when b fills more
+    #[inline]
+    pub fn fill_rate_change(&self) -> Decimal {
+        self.b.fill_rate() - self.a.fill_rate()
+    }
+
+    #[inline]
+    pub fn effective_spread_change(&self) -> Option<Decimal> {
+        Some(self.b.effective_spread()? - self.a.effective_spread()?)
+    }
+
+    // the accounts whose maker or taker outcome is not the same under both arms
+    pub fn account_changes(&self) -> IndexMap<CompactString, (AccountOutcome, AccountOutcome)> {
+        let account_ids = self.a.accounts.keys().chain(self.b.accounts.keys());
+        let mut changes = IndexMap::new();
+        for account_id in account_ids {
+            let a = self.a.accounts.get(account_id).copied().unwrap_or_default();
+            let b = self.b.accounts.get(account_id).copied().unwrap_or_default();
+            if a != b {
+                changes.insert(account_id.clone(), (a, b));
+            }
+        }
+        changes
+    }
+}
+
+impl Display for ExperimentReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let spread = |metrics: &ArmMetrics| {
+            metrics
+                .effective_spread()
+                .map_or("-".into(), |spread| spread.to_string())
+        };
+        for (arm, metrics) in [("a", &self.a), ("b", &self.b)] {
+            writeln!(
+                f,
+                "{arm}: requests={} rejected={} trades={} fill_rate={:.4} effective_spread={}",
+                metrics.requests,
+                metrics.rejected,
+                metrics.trades,
+                metrics.fill_rate(),
+                spread(metrics)
+            )?;
+        }
+        for (account_id, (a, b)) in self.account_changes() {
+            writeln!(
+                f,
+                "account {account_id}: maker_volume={}->{} taker_volume={}->{} fees={}->{}",
+                a.maker_volume, b.maker_volume, a.taker_volume, b.taker_volume, a.fees, b.fees
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A/B harness for market structure research: the same order requests through two engines that only differ by their
+// matching policy (queue priority, ...), both starting empty
+pub struct Experiment {
+    a: Engine,
+    b: Engine,
+    accounts: IndexMap<u64, CompactString>, // account of every order created
+    report: ExperimentReport,
+}
+
+impl Experiment {
+    #[inline]
+    pub fn new(a: Engine, b: Engine) -> Self {
+        Self {
+            a,
+            b,
+            accounts: IndexMap::new(),
+            report: ExperimentReport::default(),
+        }
+    }
+
+    pub fn process(&mut self, order_request: OrderRequest) {
+        if let OrderRequest::Create {
+            account_id, order_id, ..
+        } = &order_request
+        {
+            self.accounts.insert(*order_id, account_id.clone());
+        }
+        run(&mut self.a, &mut self.report.a, &self.accounts, order_request.clone());
+        run(&mut self.b, &mut self.report.b, &self.accounts, order_request);
+    }
+
+    // the requests of the journal in order, expirations included (they are journaled as cancels)
+    pub fn replay_journal(mut self, path: impl AsRef<Path>) -> Result<ExperimentReport, JournalError> {
+        for entry in Journal::read(path)? {
+            let entry = entry?;
+            // a bust is not a request of the client
+            if !matches!(entry.event, JournalEvent::Busted { .. }) {
+                self.process(entry.order_request);
+            }
+        }
+        Ok(self.report)
+    }
+
+    #[inline]
+    pub fn report(&self) -> &ExperimentReport {
+        &self.report
+    }
+}
+
+fn run(
+    engine: &mut Engine,
+    metrics: &mut ArmMetrics,
+    accounts: &IndexMap<u64, CompactString>,
+    order_request: OrderRequest,
+) {
+    let mid_price = engine.orderbook().mid_price();
+    let (trades_from, odd_lot_trades_from) = (engine.orderbook().trade_count(), engine.odd_lots().trade_count());
+    metrics.requests += 1;
+    if let OrderRequest::Create { quantity, .. } = &order_request {
+        metrics.submitted += *quantity;
+    }
+    if engine.process(order_request).is_err() {
+        metrics.rejected += 1;
+    }
+
+    let trades = engine
+        .orderbook()
+        .trades_from(trades_from)
+        .chain(engine.odd_lots().trades_from(odd_lot_trades_from));
+    for trade in trades {
+        metrics.trades += 1;
+        metrics.filled += trade.quantity() * Decimal::TWO;
+        if let Some(mid_price) = mid_price {
+            let distance = match trade.side() {
+                OrderSide::Bid => trade.price() - mid_price,
+                OrderSide::Ask => mid_price - trade.price(),
+            };
+            metrics.spread_paid += Decimal::TWO * distance * trade.quantity();
+            metrics.spread_volume += trade.quantity();
+        }
+
+        let account_id = |order_id: u64| accounts.get(&order_id).cloned().unwrap_or_default();
+        let maker = metrics.accounts.entry(account_id(trade.maker().into())).or_default();
+        maker.maker_volume += trade.quantity();
+        maker.fees += trade.maker_fee();
+        let taker = metrics.accounts.entry(account_id(trade.taker().into())).or_default();
+        taker.taker_volume += trade.quantity();
+        taker.fees += trade.taker_fee();
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        order::{
+            util::{self, DEFAULT_SYMBOL},
+            TimeInForce,
+        },
+        orderbook::QueuePriority,
+    };
+
+    fn create(account_id: &str, order_id: u64, side: OrderSide, limit_price: u32, priority_fee: i64) -> OrderRequest {
+        util::create(order_id, side)
+            .account_id(account_id)
+            .limit_price(Some(limit_price.into()))
+            .time_in_force(Some(TimeInForce::GoodTilCancel { post_only: false }))
+            .priority_fee(priority_fee.into())
+            .build()
+    }
+
+    #[rstest]
+    fn compare_queue_priorities() {
+        let path = util::TempPath::new("experiment.jsonl");
+        let mut journal = Journal::open(&path).unwrap();
+        for order_request in [
+            create("1", 1, OrderSide::Bid, 9, 0),
+            create("2", 2, OrderSide::Ask, 11, 0),
+            create("3", 3, OrderSide::Ask, 11, 1),
+            create("4", 4, OrderSide::Bid, 11, 0),
+        ] {
+            let event = JournalEvent::Created {
+                order_id: 0,
+                matched: false,
+            };
+            journal.append(&order_request, &event).unwrap();
+        }
+        drop(journal);
+
+        // under time priority account 2 was first at 11, account 3 paid to go ahead of it
+        let a = Engine::new(DEFAULT_SYMBOL);
+        let b = Engine::new(DEFAULT_SYMBOL).with_queue_priority(QueuePriority::PriorityFee);
+        let report = Experiment::new(a, b).replay_journal(&path).unwrap();
+
+        assert_eq!((report.a.trades, report.b.trades), (1, 1));
+        assert_eq!(report.a.fill_rate(), Decimal::new(5, 1));
+        assert_eq!(report.fill_rate_change(), Decimal::ZERO);
+        // the bid paid one above the mid of 10 on arrival
+        assert_eq!(report.a.effective_spread(), Some(2.into()));
+        assert_eq!(report.effective_spread_change(), Some(Decimal::ZERO));
+
+        let changes = report.account_changes();
+        assert_eq!(
+            changes.keys().map(|account_id| account_id.as_str()).collect::<Vec<_>>(),
+            vec!["2", "3"]
+        );
+        assert_eq!(changes["3"].0.maker_volume, OrderQuantity::ZERO);
+        assert_eq!(changes["3"].1.maker_volume, 10.into());
+        assert!(report.to_string().contains("account 2: maker_volume=10->0"));
+    }
+}
@@ -0,0 +1,183 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+// negative rates are rebates
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeRates {
+    pub maker_bps: Decimal,
+    pub taker_bps: Decimal,
+}
+
+impl FeeRates {
+    #[inline]
+    pub fn new(maker_bps: Decimal, taker_bps: Decimal) -> Self {
+        Self { maker_bps, taker_bps }
+    }
+
+    #[inline]
+    pub fn maker_fee(&self, notional: Decimal) -> Decimal {
+        notional * self.maker_bps / BPS
+    }
+
+    #[inline]
+    pub fn taker_fee(&self, notional: Decimal) -> Decimal {
+        notional * self.taker_bps / BPS
+    }
+}
+
+// the rates of an account are the ones of the highest tier its traded volume (quote notional) reached
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeTier {
+    pub min_volume: Decimal,
+    pub rates: FeeRates,
+}
+
+// per-pair overrides replace the base rates and the tiers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeeSchedule {
+    base: FeeRates,
+    tiers: Vec<FeeTier>,
+    pairs: IndexMap<CompactString, FeeRates>,
+}
+
+impl FeeSchedule {
+    #[inline]
+    pub fn builder() -> FeeScheduleBuilder {
+        FeeScheduleBuilder::default()
+    }
+
+    pub fn rates(&self, pair: &str, volume: Decimal) -> FeeRates {
+        if let Some(rates) = self.pairs.get(pair) {
+            return *rates;
+        }
+
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| volume >= tier.min_volume)
+            .map_or(self.base, |tier| tier.rates)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FeeScheduleBuilder {
+    schedule: FeeSchedule,
+}
+
+impl FeeScheduleBuilder {
+    pub fn maker_bps(mut self, maker_bps: Decimal) -> Self {
+        self.schedule.base.maker_bps = maker_bps;
+        self
+    }
+
+    pub fn taker_bps(mut self, taker_bps: Decimal) -> Self {
+        self.schedule.base.taker_bps = taker_bps;
+        self
+    }
+
+    pub fn tier(mut self, min_volume: Decimal, rates: FeeRates) -> Self {
+        self.schedule.tiers.push(FeeTier { min_volume, rates });
+        self
+    }
+
+    pub fn pair(mut self, pair: &str, rates: FeeRates) -> Self {
+        self.schedule.pairs.insert(pair.into(), rates);
+        self
+    }
+
+    pub fn build(mut self) -> Result<FeeSchedule, FeeError> {
+        self.schedule.tiers.sort_by_key(|tier| tier.min_volume);
+        if let Some(pair) = self
+            .schedule
+            .tiers
+            .windows(2)
+            .find(|pair| pair[0].min_volume == pair[1].min_volume)
+        {
+            return Err(FeeError::TierDuplicated(pair[0].min_volume));
+        }
+        Ok(self.schedule)
+    }
+}
+
+// fees and volume of an account since the engine started
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeReport {
+    pub volume: Decimal,
+    pub maker_fees: Decimal,
+    pub taker_fees: Decimal,
+    #[serde(default)]
+    pub priority_fees: Decimal, // paid to jump the queue, see QueuePriority::PriorityFee
+    #[serde(default)]
+    pub order_fees: Decimal, // per order, see ScriptRules
+}
+
+impl FeeReport {
+    #[inline]
+    pub fn total(&self) -> Decimal {
+        self.maker_fees + self.taker_fees + self.priority_fees + self.order_fees
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FeeError {
+    #[error("fee tier already exists! {0}")]
+    TierDuplicated(Decimal),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // 1/5 bps, 0/3 bps from 1M and a rebate from 10M, BTC/USDT at 0/1 bps
+    #[fixture]
+    fn schedule() -> FeeSchedule {
+        FeeSchedule::builder()
+            .maker_bps(1.into())
+            .taker_bps(5.into())
+            .tier(10_000_000.into(), FeeRates::new((-1).into(), 2.into()))
+            .tier(1_000_000.into(), FeeRates::new(0.into(), 3.into()))
+            .pair("BTC/USDT", FeeRates::new(0.into(), 1.into()))
+            .build()
+            .unwrap()
+    }
+
+    #[rstest]
+    fn tiers_and_pair_overrides(schedule: FeeSchedule) {
+        assert_eq!(schedule.rates("ETH/USDT", 0.into()), FeeRates::new(1.into(), 5.into()));
+        assert_eq!(
+            schedule.rates("ETH/USDT", 1_000_000.into()),
+            FeeRates::new(0.into(), 3.into())
+        );
+        assert_eq!(
+            schedule.rates("ETH/USDT", 20_000_000.into()),
+            FeeRates::new((-1).into(), 2.into())
+        );
+        assert_eq!(
+            schedule.rates("BTC/USDT", 20_000_000.into()),
+            FeeRates::new(0.into(), 1.into())
+        );
+
+        let rates = schedule.rates("ETH/USDT", 0.into());
+        assert_eq!(rates.taker_fee(10_000.into()), Decimal::from(5));
+        assert_eq!(rates.maker_fee(10_000.into()), Decimal::from(1));
+    }
+
+    #[rstest]
+    fn reject_duplicated_tiers() {
+        let rates = FeeRates::default();
+        assert_eq!(
+            FeeSchedule::builder()
+                .tier(1.into(), rates)
+                .tier(1.into(), rates)
+                .build(),
+            Err(FeeError::TierDuplicated(1.into()))
+        );
+    }
+}
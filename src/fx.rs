@@ -0,0 +1,192 @@
+use compact_str::{format_compact, CompactString};
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::{Timestamp, MINUTE},
+    price_feed::PriceBoard,
+};
+
+// 1 base = rate quote, kept on the ledger postings it was used for
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FxRate {
+    pub base: CompactString,
+    pub quote: CompactString,
+    pub rate: Decimal,
+    pub timestamp: Timestamp,
+    pub source: CompactString,
+}
+
+impl FxRate {
+    #[inline]
+    pub fn convert(&self, amount: Decimal) -> Decimal {
+        amount * self.rate
+    }
+
+    fn inverse(&self) -> Self {
+        Self {
+            base: self.quote.clone(),
+            quote: self.base.clone(),
+            rate: Decimal::ONE / self.rate,
+            timestamp: self.timestamp,
+            source: self.source.clone(),
+        }
+    }
+}
+
+// configured FX rates, either set by hand or loaded from the price feeds ("BASE/QUOTE" symbols)
+#[derive(Clone, Debug)]
+pub struct FxRates {
+    rates: IndexMap<(CompactString, CompactString), FxRate>,
+    max_age: Timestamp,
+}
+
+impl Default for FxRates {
+    fn default() -> Self {
+        Self {
+            rates: IndexMap::new(),
+            max_age: MINUTE,
+        }
+    }
+}
+
+impl FxRates {
+    pub fn with_max_age(mut self, max_age: Timestamp) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn set(
+        &mut self,
+        base: &str,
+        quote: &str,
+        rate: Decimal,
+        timestamp: Timestamp,
+        source: &str,
+    ) -> Result<(), FxError> {
+        if rate <= Decimal::ZERO {
+            return Err(FxError::InvalidRate(rate));
+        }
+
+        self.rates.insert(
+            (base.into(), quote.into()),
+            FxRate {
+                base: base.into(),
+                quote: quote.into(),
+                rate,
+                timestamp,
+                source: source.into(),
+            },
+        );
+        Ok(())
+    }
+
+    // fresh prices of the board for the given symbols replace the configured rates
+    pub fn load(&mut self, board: &PriceBoard, source: &str, symbols: &[&str], now: Timestamp) -> Result<(), FxError> {
+        for symbol in symbols {
+            let Some((base, quote)) = symbol.split_once('/') else {
+                return Err(FxError::InvalidSymbol((*symbol).into()));
+            };
+            if let (Some(rate), Some(metrics)) = (board.price(source, symbol, now), board.metrics(source, symbol)) {
+                self.set(base, quote, rate.value(), metrics.last_timestamp, source)?;
+            }
+        }
+        Ok(())
+    }
+
+    // direct or inverse rate, never older than the max age
+    pub fn rate(&self, from: &str, to: &str, now: Timestamp) -> Result<FxRate, FxError> {
+        let rate = match self.rates.get(&(from.into(), to.into())) {
+            Some(rate) => rate.clone(),
+            None => self
+                .rates
+                .get(&(to.into(), from.into()))
+                .map(FxRate::inverse)
+                .ok_or_else(|| FxError::RateNotFound(format_compact!("{from}/{to}")))?,
+        };
+
+        let age = now.saturating_sub(rate.timestamp);
+        if age > self.max_age {
+            return Err(FxError::StaleRate {
+                pair: format_compact!("{from}/{to}"),
+                age,
+            });
+        }
+
+        Ok(rate)
+    }
+
+    // same currency needs no rate
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        from: &str,
+        to: &str,
+        now: Timestamp,
+    ) -> Result<(Decimal, Option<FxRate>), FxError> {
+        if from == to {
+            return Ok((amount, None));
+        }
+
+        let rate = self.rate(from, to, now)?;
+        Ok((rate.convert(amount), Some(rate)))
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FxError {
+    #[error("fx rate not found! {0}")]
+    RateNotFound(CompactString),
+    #[error("fx rate too old (pair={}, age={})", .pair, .age)]
+    StaleRate { pair: CompactString, age: Timestamp },
+    #[error("fx rate must be positive! {0}")]
+    InvalidRate(Decimal),
+    #[error("fx symbol must be BASE/QUOTE! {0}")]
+    InvalidSymbol(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::clock::SECOND;
+
+    #[fixture]
+    fn rates() -> FxRates {
+        let mut rates = FxRates::default();
+        rates.set("USDT", "EUR", Decimal::new(9, 1), 0, "manual").unwrap();
+        rates
+    }
+
+    #[rstest]
+    fn direct_and_inverse(rates: FxRates) {
+        assert_eq!(
+            rates.convert(100.into(), "USDT", "EUR", SECOND).unwrap().0,
+            Decimal::from(90)
+        );
+
+        let (amount, rate) = rates.convert(90.into(), "EUR", "USDT", SECOND).unwrap();
+        assert_eq!(amount.round_dp(8), Decimal::from(100));
+        assert_eq!(rate.unwrap().base, "EUR");
+
+        assert_eq!(rates.convert(5.into(), "EUR", "EUR", SECOND), Ok((5.into(), None)));
+        assert_eq!(
+            rates.convert(5.into(), "EUR", "GBP", SECOND),
+            Err(FxError::RateNotFound("EUR/GBP".into()))
+        );
+    }
+
+    #[rstest]
+    fn reject_stale_rates(rates: FxRates) {
+        assert_eq!(
+            rates.rate("USDT", "EUR", 2 * MINUTE),
+            Err(FxError::StaleRate {
+                pair: "USDT/EUR".into(),
+                age: 2 * MINUTE
+            })
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use compact_str::CompactString;
+use crossbeam_channel::Receiver;
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use crate::{
+    engine::{Engine, EngineError},
+    market_data::{Granularity, MarketDataEvent},
+    order::OrderRequest,
+    symbol::Symbol,
+};
+
+// the engines behind an order entry server (WebSocket, gRPC, REST): they are not shared, they all live in one thread
+// fed by the connections
+pub struct Gateway {
+    engines: IndexMap<Symbol, (Engine, Receiver<MarketDataEvent>)>,
+}
+
+impl Gateway {
+    pub fn new(engines: impl IntoIterator<Item = Engine>, granularity: Granularity) -> Self {
+        let engines = engines
+            .into_iter()
+            .map(|mut engine| {
+                let market_data = engine.subscribe(granularity);
+                (engine.pair().clone(), (engine, market_data))
+            })
+            .collect();
+        Self { engines }
+    }
+
+    // the engine thread, handling the commands of the server one at a time until there is none left
+    pub fn spawn<C>(
+        mut self,
+        mut next: impl FnMut() -> Option<C> + Send + 'static,
+        mut handle: impl FnMut(&mut Self, C) + Send + 'static,
+    ) {
+        std::thread::spawn(move || {
+            while let Some(command) = next() {
+                handle(&mut self, command);
+            }
+        });
+    }
+
+    #[inline]
+    pub fn engine(&self, pair: &str) -> Option<&Engine> {
+        self.engines.get(pair).map(|(engine, _)| engine)
+    }
+
+    // nothing is kept per order: cancels do not carry the pair, they go to the engine where the account holds the
+    // order (order ids are unique per book only); the market data events of every engine involved are handed over on
+    // the way
+    pub fn process(
+        &mut self,
+        order_request: OrderRequest,
+        mut on_event: impl FnMut(&Symbol, MarketDataEvent),
+    ) -> Result<(), GatewayError> {
+        let pairs: Vec<Symbol> = match &order_request {
+            OrderRequest::Create { pair, .. } | OrderRequest::MassQuote { pair, .. } => {
+                if !self.engines.contains_key(pair) {
+                    return Err(GatewayError::UnknownPair(pair.as_str().into()));
+                }
+                vec![pair.clone()]
+            }
+            OrderRequest::Cancel { account_id, order_id } => vec![self
+                .holding(|engine| engine.owner((*order_id).into()) == Some(account_id.as_str()))
+                .ok_or(GatewayError::OrderNotFound(*order_id))?],
+            OrderRequest::CancelByClientId {
+                account_id,
+                client_order_id,
+            } => vec![self
+                .holding(|engine| engine.order_by_client_id(account_id, client_order_id).is_some())
+                .ok_or_else(|| GatewayError::ClientOrderNotFound(client_order_id.clone()))?],
+            // the account may have orders resting on every pair
+            OrderRequest::CancelAll { .. } => self.engines.keys().cloned().collect(),
+        };
+
+        let mut result = Ok(());
+        for pair in pairs {
+            let Some((engine, market_data)) = self.engines.get_mut(&pair) else {
+                continue;
+            };
+            if let Err(error) = engine.process(order_request.clone()) {
+                result = Err(error.into());
+            }
+            for event in market_data.try_iter() {
+                on_event(&pair, event);
+            }
+        }
+        result
+    }
+
+    #[inline]
+    fn holding(&self, holds: impl Fn(&Engine) -> bool) -> Option<Symbol> {
+        self.engines
+            .iter()
+            .find(|(_, (engine, _))| holds(engine))
+            .map(|(pair, _)| pair.clone())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GatewayError {
+    #[error("unknown pair! {0}")]
+    UnknownPair(CompactString),
+    #[error("order not found! {0}")]
+    OrderNotFound(u64),
+    #[error("client order id not found! {0}")]
+    ClientOrderNotFound(CompactString),
+    #[error(transparent)]
+    EngineError(#[from] EngineError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        market_data::BookEvent,
+        order::{util, OrderSide},
+    };
+
+    const PAIRS: [&str; 2] = ["BTC/USDT", "ETH/USDT"];
+
+    #[fixture]
+    fn gateway() -> Gateway {
+        Gateway::new(PAIRS.map(|pair| Engine::new(pair.parse().unwrap())), Granularity::Order)
+    }
+
+    #[rstest]
+    fn cancel_in_the_engine_holding_the_order(mut gateway: Gateway) {
+        // an id reused on another pair and filled there at once: the cancel reaches the resting one
+        let mut events = vec![];
+        let mut on_event = |pair: &Symbol, event: MarketDataEvent| events.push((pair.clone(), event.event));
+        gateway
+            .process(util::create_on(1, PAIRS[1], OrderSide::Ask).build(), &mut on_event)
+            .unwrap();
+        gateway
+            .process(util::create_on(2, PAIRS[0], OrderSide::Bid).build(), &mut on_event)
+            .unwrap();
+        gateway
+            .process(util::create_on(2, PAIRS[1], OrderSide::Bid).build(), &mut on_event)
+            .unwrap();
+        gateway.process(util::cancel(2), &mut on_event).unwrap();
+        assert!(matches!(
+            events.last(),
+            Some((pair, BookEvent::Delete { order_id: Some(order_id), .. })) if pair == PAIRS[0] && *order_id == 2.into()
+        ));
+
+        assert!(matches!(
+            gateway.process(util::cancel(2), |_, _| {}),
+            Err(GatewayError::OrderNotFound(2))
+        ));
+        assert!(matches!(
+            gateway.process(util::create_on(3, "SOL/USDT", OrderSide::Bid).build(), |_, _| {}),
+            Err(GatewayError::UnknownPair(pair)) if pair == "SOL/USDT"
+        ));
+    }
+
+    #[rstest]
+    fn cancel_in_the_engine_where_the_account_holds_the_order(mut gateway: Gateway) {
+        // the same id resting for another account on the first pair
+        let other = util::create_on(5, PAIRS[0], OrderSide::Bid).account_id("2").build();
+        gateway.process(other, |_, _| {}).unwrap();
+        let own = util::create_on(5, PAIRS[1], OrderSide::Bid).build();
+        gateway.process(own, |_, _| {}).unwrap();
+
+        gateway.process(util::cancel(5), |_, _| {}).unwrap();
+        assert!(gateway.engine(PAIRS[1]).unwrap().get_order(5.into()).is_none());
+        assert_eq!(gateway.engine(PAIRS[0]).unwrap().owner(5.into()), Some("2"));
+        assert!(matches!(
+            gateway.process(util::cancel(5), |_, _| {}),
+            Err(GatewayError::OrderNotFound(5))
+        ));
+    }
+
+    #[rstest]
+    fn cancel_all_on_every_pair(mut gateway: Gateway) {
+        for (order_id, pair) in PAIRS.iter().enumerate() {
+            gateway
+                .process(
+                    util::create_on(order_id as u64 + 1, pair, OrderSide::Bid).build(),
+                    |_, _| {},
+                )
+                .unwrap();
+        }
+        gateway
+            .process(OrderRequest::CancelAll { account_id: "1".into() }, |_, _| {})
+            .unwrap();
+        assert!(PAIRS
+            .iter()
+            .all(|pair| gateway.engine(pair).unwrap().orderbook().resting_count() == 0));
+    }
+}
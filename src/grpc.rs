@@ -0,0 +1,387 @@
+use std::{net::SocketAddr, pin::Pin};
+
+use compact_str::{format_compact, CompactString};
+use futures_util::{stream, Stream};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc, oneshot,
+    },
+};
+use tonic::{
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status,
+};
+
+use crate::{
+    engine::Engine,
+    gateway::Gateway,
+    market_data::{BookEvent, Granularity},
+    order::{OrderRequest, OrderSide},
+    orderbook::{Depth, DepthLevel},
+    symbol::{Symbol, SymbolError},
+};
+
+pub mod proto {
+    tonic::include_proto!("merx");
+}
+
+use proto::order_entry_server::{OrderEntry, OrderEntryServer};
+
+// slow trade streams beyond this are ended, the client subscribes again
+const TRADES_CAPACITY: usize = 65_536;
+
+// what the RPCs ask of the engines
+#[derive(Debug)]
+enum Command {
+    Request {
+        order_request: OrderRequest,
+        ack: oneshot::Sender<Result<(), CompactString>>,
+    },
+    Snapshot {
+        pair: CompactString,
+        levels: usize,
+        reply: oneshot::Sender<Option<Depth>>,
+    },
+}
+
+// typed order entry (see proto/merx.proto), the engines run in a thread of their own like behind the WebSocket server
+pub struct GrpcServer {
+    listener: TcpListener,
+    commands: mpsc::UnboundedSender<Command>,
+    trades: broadcast::Sender<proto::Trade>,
+}
+
+impl GrpcServer {
+    pub async fn bind(addr: impl ToSocketAddrs, pairs: &[Symbol]) -> Result<Self, GrpcError> {
+        let listener = TcpListener::bind(addr).await?;
+        let (commands, mut rx) = mpsc::unbounded_channel();
+        let (trades, _) = broadcast::channel(TRADES_CAPACITY);
+
+        let gateway = Gateway::new(pairs.iter().cloned().map(Engine::new), Granularity::Level);
+        let publisher = trades.clone();
+        gateway.spawn(
+            move || rx.blocking_recv(),
+            move |gateway, command| handle(gateway, command, &publisher),
+        );
+
+        Ok(Self {
+            listener,
+            commands,
+            trades,
+        })
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Result<SocketAddr, GrpcError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn run(self) -> Result<(), GrpcError> {
+        let service = OrderEntryService {
+            commands: self.commands,
+            trades: self.trades,
+        };
+        Server::builder()
+            .add_service(OrderEntryServer::new(service))
+            .serve_with_incoming(TcpIncoming::from(self.listener))
+            .await?;
+        Ok(())
+    }
+}
+
+fn handle(gateway: &mut Gateway, command: Command, trades: &broadcast::Sender<proto::Trade>) {
+    match command {
+        Command::Request { order_request, ack } => {
+            let result = gateway.process(order_request, |pair, event| {
+                if let BookEvent::Trade {
+                    trade_id,
+                    maker,
+                    taker,
+                    side,
+                    price,
+                    quantity,
+                } = event.event
+                {
+                    // no trade stream at all is fine
+                    let _ = trades.send(proto::Trade {
+                        pair: pair.to_string(),
+                        trade_id: trade_id.into(),
+                        maker_order_id: maker.into(),
+                        taker_order_id: taker.into(),
+                        side: side_to_proto(side) as i32,
+                        price: price.to_string(),
+                        quantity: quantity.to_string(),
+                    });
+                }
+            });
+            let _ = ack.send(result.map_err(|error| format_compact!("{error}")));
+        }
+        Command::Snapshot { pair, levels, reply } => {
+            let depth = gateway
+                .engine(pair.as_str())
+                .map(|engine| engine.orderbook().depth(levels));
+            let _ = reply.send(depth);
+        }
+    }
+}
+
+struct OrderEntryService {
+    commands: mpsc::UnboundedSender<Command>,
+    trades: broadcast::Sender<proto::Trade>,
+}
+
+impl OrderEntryService {
+    async fn send(&self, order_request: OrderRequest) -> Result<Response<proto::OrderReply>, Status> {
+        let (ack, reply) = oneshot::channel();
+        self.commands
+            .send(Command::Request { order_request, ack })
+            .map_err(|_| engine_stopped())?;
+        let reply = match reply.await.map_err(|_| engine_stopped())? {
+            Ok(()) => proto::OrderReply {
+                accepted: true,
+                reason: String::new(),
+            },
+            Err(reason) => proto::OrderReply {
+                accepted: false,
+                reason: reason.into(),
+            },
+        };
+        Ok(Response::new(reply))
+    }
+}
+
+#[tonic::async_trait]
+impl OrderEntry for OrderEntryService {
+    async fn submit_order(
+        &self,
+        request: Request<proto::SubmitOrderRequest>,
+    ) -> Result<Response<proto::OrderReply>, Status> {
+        let request = request.into_inner();
+        let side = match request.side() {
+            proto::Side::Buy => OrderSide::Bid,
+            proto::Side::Sell => OrderSide::Ask,
+            proto::Side::Unspecified => return Err(Status::invalid_argument("order side missing")),
+        };
+        let invalid = |error: crate::order::UnitError| Status::invalid_argument(error.to_string());
+        let limit_price = match request.limit_price.as_deref() {
+            Some(limit_price) => Some(limit_price.parse().map_err(invalid)?),
+            None => None,
+        };
+        self.send(OrderRequest::Create {
+            account_id: request.account_id.into(),
+            order_id: request.order_id,
+            pair: request
+                .pair
+                .parse()
+                .map_err(|error: SymbolError| Status::invalid_argument(error.to_string()))?,
+            side,
+            limit_price,
+            quantity: request.quantity.parse().map_err(invalid)?,
+            time_in_force: None,
+            short_sell: false,
+            priority_fee: Default::default(),
+            client_order_id: request.client_order_id.map(CompactString::from),
+            price_protection: None,
+            reduce_only: false,
+        })
+        .await
+    }
+
+    async fn cancel_order(
+        &self,
+        request: Request<proto::CancelOrderRequest>,
+    ) -> Result<Response<proto::OrderReply>, Status> {
+        let request = request.into_inner();
+        self.send(OrderRequest::Cancel {
+            account_id: request.account_id.into(),
+            order_id: request.order_id,
+        })
+        .await
+    }
+
+    async fn get_orderbook(
+        &self,
+        request: Request<proto::GetOrderbookRequest>,
+    ) -> Result<Response<proto::Orderbook>, Status> {
+        let request = request.into_inner();
+        let levels = match request.depth {
+            0 => usize::MAX,
+            depth => depth as usize,
+        };
+        let (reply, depth) = oneshot::channel();
+        self.commands
+            .send(Command::Snapshot {
+                pair: request.pair.as_str().into(),
+                levels,
+                reply,
+            })
+            .map_err(|_| engine_stopped())?;
+        let depth = depth
+            .await
+            .map_err(|_| engine_stopped())?
+            .ok_or_else(|| Status::not_found(GrpcError::UnknownPair(request.pair.as_str().into()).to_string()))?;
+        let levels = |levels: Vec<DepthLevel>| {
+            levels
+                .into_iter()
+                .map(|(price, quantity, orders)| proto::Level {
+                    price: price.to_string(),
+                    quantity: quantity.to_string(),
+                    orders: orders as u64,
+                })
+                .collect()
+        };
+        Ok(Response::new(proto::Orderbook {
+            pair: request.pair,
+            bids: levels(depth.bids),
+            asks: levels(depth.asks),
+        }))
+    }
+
+    type TradesStream = Pin<Box<dyn Stream<Item = Result<proto::Trade, Status>> + Send>>;
+
+    async fn trades(&self, request: Request<proto::TradesRequest>) -> Result<Response<Self::TradesStream>, Status> {
+        let pair = request.into_inner().pair;
+        let trades = stream::unfold(Some(self.trades.subscribe()), move |receiver| {
+            let pair = pair.clone();
+            async move {
+                let mut receiver = receiver?;
+                loop {
+                    match receiver.recv().await {
+                        Ok(trade) if pair.is_empty() || trade.pair == pair => return Some((Ok(trade), Some(receiver))),
+                        Ok(_) => {}
+                        // the trades skipped cannot be told, the stream ends after saying so
+                        Err(RecvError::Lagged(skipped)) => {
+                            let status = Status::data_loss(GrpcError::Lagged(skipped).to_string());
+                            return Some((Err(status), None));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(trades)))
+    }
+}
+
+fn side_to_proto(side: OrderSide) -> proto::Side {
+    match side {
+        OrderSide::Bid => proto::Side::Buy,
+        OrderSide::Ask => proto::Side::Sell,
+    }
+}
+
+fn engine_stopped() -> Status {
+    Status::unavailable(GrpcError::EngineStopped.to_string())
+}
+
+#[derive(Debug, Error)]
+pub enum GrpcError {
+    #[error("server io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("grpc transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("unknown pair! {0}")]
+    UnknownPair(CompactString),
+    #[error("trade stream too slow! skipped:{0}")]
+    Lagged(u64),
+    #[error("engine stopped!")]
+    EngineStopped,
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::order::util::{DEFAULT_PAIR, DEFAULT_SYMBOL};
+    use proto::order_entry_client::OrderEntryClient;
+
+    fn submit(order_id: u64, pair: &str, side: proto::Side) -> proto::SubmitOrderRequest {
+        proto::SubmitOrderRequest {
+            account_id: "1".into(),
+            order_id,
+            pair: pair.into(),
+            side: side as i32,
+            limit_price: Some("15".into()),
+            quantity: "10".into(),
+            client_order_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn orders_book_and_trades() {
+        let server = GrpcServer::bind("127.0.0.1:0", &[DEFAULT_SYMBOL]).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+        let mut client = OrderEntryClient::connect(format!("http://{addr}")).await.unwrap();
+
+        let mut trades = client
+            .trades(proto::TradesRequest {
+                pair: DEFAULT_PAIR.into(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let reply = client
+            .submit_order(submit(1, "XXX/YYY", proto::Side::Sell))
+            .await
+            .unwrap();
+        assert!(!reply.get_ref().accepted);
+        for order_id in [2, 3] {
+            let reply = client
+                .submit_order(submit(order_id, DEFAULT_PAIR, proto::Side::Sell))
+                .await
+                .unwrap();
+            assert!(reply.get_ref().accepted);
+        }
+        let book = client
+            .get_orderbook(proto::GetOrderbookRequest {
+                pair: DEFAULT_PAIR.into(),
+                depth: 0,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((book.asks[0].quantity.as_str(), book.asks[0].orders), ("20", 2));
+
+        let reply = client
+            .submit_order(submit(4, DEFAULT_PAIR, proto::Side::Buy))
+            .await
+            .unwrap();
+        assert!(reply.get_ref().accepted);
+        let trade = trades.next().await.unwrap().unwrap();
+        assert_eq!((trade.maker_order_id, trade.taker_order_id), (2, 4));
+        assert_eq!((trade.side(), trade.price.as_str()), (proto::Side::Buy, "15"));
+
+        let reply = client
+            .cancel_order(proto::CancelOrderRequest {
+                account_id: "1".into(),
+                order_id: 3,
+            })
+            .await
+            .unwrap();
+        assert!(reply.get_ref().accepted);
+
+        let mut invalid = submit(5, DEFAULT_PAIR, proto::Side::Buy);
+        invalid.quantity = "ten".into();
+        let status = client.submit_order(invalid).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let status = client
+            .submit_order(submit(6, "ETHUSDT", proto::Side::Buy))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let status = client
+            .get_orderbook(proto::GetOrderbookRequest {
+                pair: "XXX/YYY".into(),
+                depth: 1,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,255 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::{Timestamp, SECOND},
+    order::OrderPrice,
+};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Constituent {
+    pub weight: Decimal,
+    pub price: Option<OrderPrice>,
+    pub updated_at: Timestamp,
+}
+
+// composite price of the index with the sources that were left out of it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IndexPrice {
+    pub price: OrderPrice,
+    pub timestamp: Timestamp,
+    pub sources: Vec<CompactString>,
+    pub stale: Vec<CompactString>,
+    pub outliers: Vec<CompactString>,
+}
+
+// the index price used for marks, funding and settlements, fed by the user with the prices of each source
+#[derive(Clone, Debug)]
+pub struct IndexComposer {
+    constituents: IndexMap<CompactString, Constituent>,
+    max_age: Timestamp,
+    max_deviation: Decimal, // relative to the median of the fresh sources
+    min_sources: usize,
+}
+
+impl Default for IndexComposer {
+    fn default() -> Self {
+        Self {
+            constituents: IndexMap::new(),
+            max_age: 10 * SECOND,
+            max_deviation: Decimal::new(5, 2),
+            min_sources: 1,
+        }
+    }
+}
+
+impl IndexComposer {
+    pub fn with_max_age(mut self, max_age: Timestamp) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn with_max_deviation(mut self, max_deviation: Decimal) -> Self {
+        self.max_deviation = max_deviation;
+        self
+    }
+
+    pub fn with_min_sources(mut self, min_sources: usize) -> Self {
+        self.min_sources = min_sources;
+        self
+    }
+
+    pub fn add_source(&mut self, source: &str, weight: Decimal) -> Result<(), IndexError> {
+        if weight <= Decimal::ZERO {
+            return Err(IndexError::InvalidWeight(weight));
+        }
+        if self.constituents.contains_key(source) {
+            return Err(IndexError::SourceDuplicated(source.into()));
+        }
+
+        self.constituents.insert(
+            source.into(),
+            Constituent {
+                weight,
+                price: None,
+                updated_at: 0,
+            },
+        );
+        Ok(())
+    }
+
+    #[inline]
+    pub fn remove_source(&mut self, source: &str) -> Option<Constituent> {
+        self.constituents.shift_remove(source)
+    }
+
+    #[inline]
+    pub fn constituent(&self, source: &str) -> Option<&Constituent> {
+        self.constituents.get(source)
+    }
+
+    pub fn update(&mut self, source: &str, price: OrderPrice, now: Timestamp) -> Result<(), IndexError> {
+        if price.is_zero() {
+            return Err(IndexError::InvalidPrice(price));
+        }
+
+        let constituent = self
+            .constituents
+            .get_mut(source)
+            .ok_or_else(|| IndexError::SourceNotFound(source.into()))?;
+        constituent.price = Some(price);
+        constituent.updated_at = now;
+        Ok(())
+    }
+
+    // stale sources are dropped, then the ones too far from the median; the rest are averaged by weight
+    pub fn price(&self, now: Timestamp) -> Result<IndexPrice, IndexError> {
+        let mut stale = vec![];
+        let mut fresh = vec![];
+        for (source, constituent) in &self.constituents {
+            match constituent.price {
+                Some(price) if now.saturating_sub(constituent.updated_at) <= self.max_age => {
+                    fresh.push((source, constituent.weight, price))
+                }
+                _ => stale.push(source.clone()),
+            }
+        }
+        if fresh.is_empty() {
+            return Err(IndexError::NotEnoughSources {
+                required: self.min_sources,
+                available: 0,
+            });
+        }
+
+        let median = median(fresh.iter().map(|(_, _, price)| *price).collect());
+        let (sources, outliers): (Vec<_>, Vec<_>) = fresh
+            .into_iter()
+            .partition(|(_, _, price)| ((*price - median).abs() / median) <= self.max_deviation);
+
+        if sources.len() < self.min_sources {
+            return Err(IndexError::NotEnoughSources {
+                required: self.min_sources,
+                available: sources.len(),
+            });
+        }
+
+        let total_weight: Decimal = sources.iter().map(|(_, weight, _)| *weight).sum();
+        let notional = sources
+            .iter()
+            .map(|(_, weight, price)| *weight * *price)
+            .sum::<Decimal>();
+        let price = OrderPrice::from_decimal(notional / total_weight);
+
+        Ok(IndexPrice {
+            price,
+            timestamp: now,
+            sources: sources.into_iter().map(|(source, _, _)| source.clone()).collect(),
+            stale,
+            outliers: outliers.into_iter().map(|(source, _, _)| source.clone()).collect(),
+        })
+    }
+}
+
+fn median(mut prices: Vec<OrderPrice>) -> OrderPrice {
+    prices.sort();
+    let middle = prices.len() / 2;
+    if prices.len().is_multiple_of(2) {
+        (prices[middle - 1] + prices[middle]) / Decimal::TWO
+    } else {
+        prices[middle]
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum IndexError {
+    #[error("index source already exists! {0}")]
+    SourceDuplicated(CompactString),
+    #[error("index source not found! {0}")]
+    SourceNotFound(CompactString),
+    #[error("index weight must be positive! {0}")]
+    InvalidWeight(Decimal),
+    #[error("index price must be positive! {0}")]
+    InvalidPrice(OrderPrice),
+    #[error("not enough sources to compute the index (required={}, available={})", .required, .available)]
+    NotEnoughSources { required: usize, available: usize },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // three sources, the first one counts double
+    #[fixture]
+    fn composer() -> IndexComposer {
+        let mut composer = IndexComposer::default().with_min_sources(2);
+        composer.add_source("binance", 2.into()).unwrap();
+        composer.add_source("coinbase", 1.into()).unwrap();
+        composer.add_source("kraken", 1.into()).unwrap();
+        composer
+    }
+
+    #[rstest]
+    fn weighted_average(mut composer: IndexComposer) {
+        composer.update("binance", 100.into(), 0).unwrap();
+        composer.update("coinbase", 102.into(), 0).unwrap();
+        composer.update("kraken", 102.into(), 0).unwrap();
+
+        let index = composer.price(SECOND).unwrap();
+        assert_eq!(index.price, OrderPrice::from(101));
+        assert_eq!(index.sources.len(), 3);
+    }
+
+    #[rstest]
+    fn reject_outliers_and_stale_sources(mut composer: IndexComposer) {
+        composer.update("binance", 100.into(), 0).unwrap();
+        composer.update("coinbase", 101.into(), 20 * SECOND).unwrap();
+        composer.update("kraken", 150.into(), 20 * SECOND).unwrap();
+
+        // binance is stale and the two fresh sources are both ~20% away from their median (125.5)
+        assert_eq!(
+            composer.price(20 * SECOND),
+            Err(IndexError::NotEnoughSources {
+                required: 2,
+                available: 0
+            })
+        );
+
+        // with binance back the median is 101 and only kraken is rejected
+        composer.update("binance", 100.into(), 20 * SECOND).unwrap();
+        let index = composer.price(20 * SECOND).unwrap();
+        assert_eq!(index.outliers, vec!["kraken"]);
+        assert!(index.stale.is_empty());
+        assert_eq!(index.price.value().round_dp(4), Decimal::new(1003333, 4));
+    }
+
+    #[rstest]
+    fn validate_sources(mut composer: IndexComposer) {
+        assert_eq!(
+            composer.add_source("kraken", 1.into()),
+            Err(IndexError::SourceDuplicated("kraken".into()))
+        );
+        assert_eq!(
+            composer.add_source("okx", Decimal::ZERO),
+            Err(IndexError::InvalidWeight(Decimal::ZERO))
+        );
+        assert_eq!(
+            composer.update("okx", 100.into(), 0),
+            Err(IndexError::SourceNotFound("okx".into()))
+        );
+
+        // sources without any price yet are stale
+        composer.update("binance", 100.into(), 0).unwrap();
+        assert_eq!(
+            composer.price(0),
+            Err(IndexError::NotEnoughSources {
+                required: 2,
+                available: 1
+            })
+        );
+    }
+}
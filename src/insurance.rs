@@ -0,0 +1,143 @@
+use compact_str::CompactString;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::ledger::{Ledger, LedgerError, PostingKind};
+
+pub const INSURANCE_FUND_ACCOUNT: &str = "INSURANCE_FUND";
+
+// how a liquidation shortfall has been absorbed: first by the insurance fund, the residual goes to ADL
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Waterfall {
+    pub shortfall: Decimal,
+    pub covered_by_fund: Decimal,
+    pub residual: Decimal, // to be socialized via auto-deleveraging
+}
+
+impl Waterfall {
+    #[inline]
+    pub fn requires_deleveraging(&self) -> bool {
+        self.residual > Decimal::ZERO
+    }
+}
+
+// the balance of the fund lives in the ledger, this only knows how to move it
+#[derive(Clone, Debug)]
+pub struct InsuranceFund {
+    asset: CompactString,
+}
+
+impl InsuranceFund {
+    pub fn new(asset: &str) -> Self {
+        Self { asset: asset.into() }
+    }
+
+    #[inline]
+    pub fn asset(&self) -> &str {
+        &self.asset
+    }
+
+    #[inline]
+    pub fn balance(&self, ledger: &Ledger) -> Decimal {
+        ledger.balance(INSURANCE_FUND_ACCOUNT, &self.asset)
+    }
+
+    // contributions to the fund (e.g. the surplus of a liquidation closed better than the bankruptcy price)
+    pub fn deposit(&self, ledger: &mut Ledger, from: &str, amount: Decimal) -> Result<u64, LedgerError> {
+        ledger.transfer(
+            from,
+            INSURANCE_FUND_ACCOUNT,
+            &self.asset,
+            amount,
+            PostingKind::InsuranceDeposit,
+        )
+    }
+
+    // cover the shortfall of a bankrupt account as far as the fund allows, the fund never goes negative
+    pub fn absorb(&self, ledger: &mut Ledger, bankrupt: &str, shortfall: Decimal) -> Result<Waterfall, LedgerError> {
+        if shortfall <= Decimal::ZERO {
+            return Err(LedgerError::NonPositiveAmount(shortfall));
+        }
+
+        let covered_by_fund = self.balance(ledger).max(Decimal::ZERO).min(shortfall);
+        if covered_by_fund > Decimal::ZERO {
+            ledger.transfer(
+                INSURANCE_FUND_ACCOUNT,
+                bankrupt,
+                &self.asset,
+                covered_by_fund,
+                PostingKind::InsuranceCoverage,
+            )?;
+        }
+
+        Ok(Waterfall {
+            shortfall,
+            covered_by_fund,
+            residual: shortfall - covered_by_fund,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    #[fixture]
+    fn funded() -> (InsuranceFund, Ledger) {
+        let fund = InsuranceFund::new("USDT");
+        let mut ledger = Ledger::default();
+        fund.deposit(&mut ledger, "1", 100.into()).unwrap();
+        (fund, ledger)
+    }
+
+    #[rstest]
+    fn fund_covers_shortfall(funded: (InsuranceFund, Ledger)) {
+        let (fund, mut ledger) = funded;
+        assert_eq!(fund.balance(&ledger), Decimal::from(100));
+
+        let waterfall = fund.absorb(&mut ledger, "2", 40.into()).unwrap();
+        assert_eq!(waterfall.covered_by_fund, Decimal::from(40));
+        assert!(!waterfall.requires_deleveraging());
+
+        assert_eq!(fund.balance(&ledger), Decimal::from(60));
+        assert_eq!(ledger.balance("2", "USDT"), Decimal::from(40));
+        assert_eq!(
+            ledger.statement("2").total(PostingKind::InsuranceCoverage),
+            Decimal::from(40)
+        );
+    }
+
+    #[rstest]
+    fn exhausted_fund_goes_to_deleveraging(funded: (InsuranceFund, Ledger)) {
+        let (fund, mut ledger) = funded;
+
+        let waterfall = fund.absorb(&mut ledger, "2", 130.into()).unwrap();
+        assert_eq!(
+            waterfall,
+            Waterfall {
+                shortfall: 130.into(),
+                covered_by_fund: 100.into(),
+                residual: 30.into(),
+            }
+        );
+        assert!(waterfall.requires_deleveraging());
+        assert_eq!(fund.balance(&ledger), Decimal::ZERO);
+
+        // nothing left in the fund, everything goes to ADL (and no posting is made)
+        let postings = ledger.postings().len();
+        let waterfall = fund.absorb(&mut ledger, "3", 10.into()).unwrap();
+        assert_eq!(waterfall.residual, Decimal::from(10));
+        assert_eq!(ledger.postings().len(), postings);
+    }
+
+    #[rstest]
+    fn reject_non_positive_shortfall(funded: (InsuranceFund, Ledger)) {
+        let (fund, mut ledger) = funded;
+        assert_eq!(
+            fund.absorb(&mut ledger, "2", Decimal::ZERO),
+            Err(LedgerError::NonPositiveAmount(Decimal::ZERO))
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt::Display;
+
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    calendar::SessionState,
+    engine::{Engine, EngineError},
+};
+
+// the resting orders of every pair once the kill switch is tripped
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CancelPolicy {
+    #[default]
+    Keep,
+    CancelAll,
+}
+
+// trading is re-enabled in stages, one step at a time: cancels first, then orders that cannot take liquidity, then
+// continuous trading
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum KillSwitchStage {
+    #[default]
+    Trading,
+    Halted,
+    CancelOnly,
+    PostOnly,
+}
+
+impl Display for KillSwitchStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KillSwitchStage::Trading => write!(f, "TRADING"),
+            KillSwitchStage::Halted => write!(f, "HALTED"),
+            KillSwitchStage::CancelOnly => write!(f, "CANCELONLY"),
+            KillSwitchStage::PostOnly => write!(f, "POSTONLY"),
+        }
+    }
+}
+
+// exchange-wide halt: every pair goes through the same session states at once (see Engine::kill), the requests queued
+// by the halt policy of a pair are only processed back in continuous trading
+#[derive(Clone, Debug, Default)]
+pub struct KillSwitch {
+    cancel_policy: CancelPolicy,
+    stage: KillSwitchStage,
+}
+
+impl KillSwitch {
+    #[inline]
+    pub fn new(cancel_policy: CancelPolicy) -> Self {
+        Self {
+            cancel_policy,
+            stage: KillSwitchStage::Trading,
+        }
+    }
+
+    #[inline]
+    pub fn stage(&self) -> KillSwitchStage {
+        self.stage
+    }
+
+    // halts every pair whatever its session (even part way through the re-enable), returns the orders cancelled per
+    // pair
+    pub fn trip<'a>(
+        &mut self,
+        engines: impl IntoIterator<Item = &'a mut Engine>,
+    ) -> Result<IndexMap<CompactString, Vec<u64>>, KillSwitchError> {
+        warn!("kill switch tripped ({})", self.stage);
+        self.stage = KillSwitchStage::Halted;
+        let mut cancelled = IndexMap::new();
+        for engine in engines {
+            engine.kill();
+            if self.cancel_policy == CancelPolicy::CancelAll {
+                let order_ids = engine
+                    .mass_cancel()
+                    .map_err(|error| KillSwitchError::on(engine, error))?;
+                cancelled.insert(CompactString::from(engine.pair().as_str()), order_ids);
+            }
+        }
+        Ok(cancelled)
+    }
+
+    // the next stage of the re-enable on every pair, a pair left behind by a failure is taken along by the next call
+    pub fn advance<'a>(
+        &mut self,
+        engines: impl IntoIterator<Item = &'a mut Engine>,
+    ) -> Result<KillSwitchStage, KillSwitchError> {
+        let next = match self.stage {
+            KillSwitchStage::Trading => return Err(KillSwitchError::NotTripped),
+            KillSwitchStage::Halted => KillSwitchStage::CancelOnly,
+            KillSwitchStage::CancelOnly => KillSwitchStage::PostOnly,
+            KillSwitchStage::PostOnly => KillSwitchStage::Trading,
+        };
+        for engine in engines {
+            let advanced = match next {
+                KillSwitchStage::CancelOnly => engine.cancel_only(),
+                KillSwitchStage::PostOnly => engine.post_only(),
+                _ if matches!(
+                    engine.session(),
+                    SessionState::Halted | SessionState::CancelOnly | SessionState::PostOnly
+                ) =>
+                {
+                    engine.resume().map(|results| {
+                        for error in results.into_iter().filter_map(Result::err) {
+                            info!("queued request failed: {error} ({})", engine.pair());
+                        }
+                    })
+                }
+                _ => Ok(()),
+            };
+            advanced.map_err(|error| KillSwitchError::on(engine, error))?;
+        }
+        info!("kill switch {} -> {next}", self.stage);
+        self.stage = next;
+        Ok(next)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KillSwitchError {
+    #[error("kill switch not tripped")]
+    NotTripped,
+    #[error("engine error (pair={}): {}", .pair, .error)]
+    EngineError {
+        pair: CompactString,
+        error: Box<EngineError>,
+    },
+}
+
+impl KillSwitchError {
+    #[inline]
+    fn on(engine: &Engine, error: EngineError) -> Self {
+        Self::EngineError {
+            pair: engine.pair().as_str().into(),
+            error: Box::new(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::order::{util, OrderRequest, OrderSide, TimeInForce};
+
+    const PAIRS: [&str; 2] = ["ETH/USDT", "BTC/USDT"];
+
+    fn create(pair: &str, order_id: u64, side: OrderSide, post_only: bool) -> OrderRequest {
+        let limit_price = match side {
+            OrderSide::Bid => 10,
+            OrderSide::Ask => 11,
+        };
+        util::create(order_id, side)
+            .pair(pair.parse().unwrap())
+            .limit_price(Some(limit_price.into()))
+            .quantity(1)
+            .time_in_force(Some(TimeInForce::GoodTilCancel { post_only }))
+            .build()
+    }
+
+    #[fixture]
+    fn engines() -> IndexMap<CompactString, Engine> {
+        PAIRS
+            .into_iter()
+            .map(|pair| {
+                let mut engine = Engine::new(pair.parse().unwrap());
+                engine.process(create(pair, 1, OrderSide::Bid, false)).unwrap();
+                engine.process(create(pair, 2, OrderSide::Ask, false)).unwrap();
+                (pair.into(), engine)
+            })
+            .collect()
+    }
+
+    fn sessions(engines: &IndexMap<CompactString, Engine>) -> Vec<SessionState> {
+        engines.values().map(Engine::session).collect()
+    }
+
+    #[rstest]
+    fn halt_everything_then_re_enable_in_stages(mut engines: IndexMap<CompactString, Engine>) {
+        let mut kill_switch = KillSwitch::new(CancelPolicy::CancelAll);
+        assert!(matches!(
+            kill_switch.advance(engines.values_mut()),
+            Err(KillSwitchError::NotTripped)
+        ));
+
+        let cancelled = kill_switch.trip(engines.values_mut()).unwrap();
+        assert_eq!(cancelled["BTC/USDT"], vec![1, 2]);
+        assert_eq!(sessions(&engines), vec![SessionState::Halted; 2]);
+        assert!(engines.values().all(|engine| engine.open_orders("1").next().is_none()));
+
+        assert_eq!(
+            kill_switch.advance(engines.values_mut()).unwrap(),
+            KillSwitchStage::CancelOnly
+        );
+        assert_eq!(sessions(&engines), vec![SessionState::CancelOnly; 2]);
+
+        // only orders that cannot take liquidity
+        assert_eq!(
+            kill_switch.advance(engines.values_mut()).unwrap(),
+            KillSwitchStage::PostOnly
+        );
+        let engine = &mut engines["ETH/USDT"];
+        assert!(matches!(
+            engine.process(create("ETH/USDT", 3, OrderSide::Bid, false)),
+            Err(EngineError::SessionNotOpen(SessionState::PostOnly))
+        ));
+        engine.process(create("ETH/USDT", 4, OrderSide::Bid, true)).unwrap();
+
+        assert_eq!(
+            kill_switch.advance(engines.values_mut()).unwrap(),
+            KillSwitchStage::Trading
+        );
+        assert_eq!(sessions(&engines), vec![SessionState::Open; 2]);
+        engines["ETH/USDT"]
+            .process(create("ETH/USDT", 5, OrderSide::Ask, false))
+            .unwrap();
+    }
+}
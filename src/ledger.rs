@@ -0,0 +1,254 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::fx::FxRate;
+
+// counterparty of every fee collected by the venue
+pub const EXCHANGE_ACCOUNT: &str = "EXCHANGE";
+// intermediary of the settlements between many payers and many receivers
+pub const CLEARING_ACCOUNT: &str = "CLEARING";
+
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PostingKind {
+    MessageFee,
+    InsuranceDeposit,
+    InsuranceCoverage,
+    OptionExercise,
+    OptionAssignment,
+    OffBookTrade,
+    Allocation,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Posting {
+    pub entry_id: u64,
+    pub account_id: CompactString,
+    pub asset: CompactString,
+    pub amount: Decimal, // positive = credit, negative = debit
+    pub kind: PostingKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<FxRate>, // snapshot of the rate used when the amount was converted from another asset
+}
+
+impl Display for Posting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ENTRY[{}] {} {:+} {} ({:?})",
+            self.entry_id, self.account_id, self.amount, self.asset, self.kind
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct Ledger {
+    postings: Vec<Posting>,
+    balances: IndexMap<(CompactString, CompactString), Decimal>,
+    next_entry_id: u64,
+}
+
+impl Ledger {
+    // every entry is balanced: the amount is debited from one account and credited to the other
+    pub fn transfer(
+        &mut self,
+        from: &str,
+        to: &str,
+        asset: &str,
+        amount: Decimal,
+        kind: PostingKind,
+    ) -> Result<u64, LedgerError> {
+        self.transfer_with_fx(from, to, asset, amount, kind, None)
+    }
+
+    // the rate converted the amount into the asset, both postings of the entry keep it for auditing
+    pub fn transfer_with_fx(
+        &mut self,
+        from: &str,
+        to: &str,
+        asset: &str,
+        amount: Decimal,
+        kind: PostingKind,
+        fx: Option<FxRate>,
+    ) -> Result<u64, LedgerError> {
+        if amount <= Decimal::ZERO {
+            return Err(LedgerError::NonPositiveAmount(amount));
+        }
+        if from == to {
+            return Err(LedgerError::SameAccount(from.into()));
+        }
+
+        let entry_id = self.next_entry_id;
+        self.next_entry_id += 1;
+
+        self.post(entry_id, from, asset, -amount, kind, fx.clone());
+        self.post(entry_id, to, asset, amount, kind, fx);
+
+        Ok(entry_id)
+    }
+
+    fn post(
+        &mut self,
+        entry_id: u64,
+        account_id: &str,
+        asset: &str,
+        amount: Decimal,
+        kind: PostingKind,
+        fx: Option<FxRate>,
+    ) {
+        *self
+            .balances
+            .entry((account_id.into(), asset.into()))
+            .or_insert(Decimal::ZERO) += amount;
+        self.postings.push(Posting {
+            entry_id,
+            account_id: account_id.into(),
+            asset: asset.into(),
+            amount,
+            kind,
+            fx,
+        });
+    }
+
+    #[inline]
+    pub fn balance(&self, account_id: &str, asset: &str) -> Decimal {
+        self.balances
+            .get(&(account_id.into(), asset.into()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    pub fn statement(&self, account_id: &str) -> Statement {
+        let postings: Vec<Posting> = self
+            .postings
+            .iter()
+            .filter(|posting| posting.account_id == account_id)
+            .cloned()
+            .collect();
+
+        let mut balances = BTreeMap::new();
+        for posting in &postings {
+            *balances.entry(posting.asset.clone()).or_insert(Decimal::ZERO) += posting.amount;
+        }
+
+        Statement {
+            account_id: account_id.into(),
+            postings,
+            balances,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Statement {
+    pub account_id: CompactString,
+    pub postings: Vec<Posting>,
+    pub balances: BTreeMap<CompactString, Decimal>,
+}
+
+impl Statement {
+    pub fn total(&self, kind: PostingKind) -> Decimal {
+        self.postings
+            .iter()
+            .filter(|posting| posting.kind == kind)
+            .map(|posting| posting.amount)
+            .sum()
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "STATEMENT[{}]", self.account_id)?;
+        for posting in &self.postings {
+            writeln!(f, "  {posting}")?;
+        }
+        for (asset, balance) in &self.balances {
+            writeln!(f, "  BALANCE {balance} {asset}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LedgerError {
+    #[error("ledger amounts must be positive! {0}")]
+    NonPositiveAmount(Decimal),
+    #[error("cannot transfer to the same account! {0}")]
+    SameAccount(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    #[fixture]
+    fn ledger() -> Ledger {
+        Ledger::default()
+    }
+
+    #[rstest]
+    fn transfer_is_balanced(mut ledger: Ledger) {
+        assert_eq!(
+            ledger.transfer("1", "2", "USDT", 10.into(), PostingKind::MessageFee),
+            Ok(0)
+        );
+        assert_eq!(
+            ledger.transfer("1", "2", "USDT", 5.into(), PostingKind::MessageFee),
+            Ok(1)
+        );
+
+        assert_eq!(ledger.balance("1", "USDT"), Decimal::from(-15));
+        assert_eq!(ledger.balance("2", "USDT"), Decimal::from(15));
+        assert_eq!(ledger.balance("2", "ETH"), Decimal::ZERO);
+
+        // the sum of all the postings is always zero
+        let sum: Decimal = ledger.postings().iter().map(|posting| posting.amount).sum();
+        assert_eq!(sum, Decimal::ZERO);
+    }
+
+    #[rstest]
+    fn reject_invalid_transfers(mut ledger: Ledger) {
+        assert_eq!(
+            ledger.transfer("1", "2", "USDT", Decimal::ZERO, PostingKind::MessageFee),
+            Err(LedgerError::NonPositiveAmount(Decimal::ZERO))
+        );
+        assert_eq!(
+            ledger.transfer("1", "1", "USDT", 1.into(), PostingKind::MessageFee),
+            Err(LedgerError::SameAccount("1".into()))
+        );
+        assert!(ledger.postings().is_empty());
+    }
+
+    #[rstest]
+    fn statement_per_account(mut ledger: Ledger) {
+        ledger
+            .transfer("1", EXCHANGE_ACCOUNT, "USDT", 3.into(), PostingKind::MessageFee)
+            .unwrap();
+        ledger
+            .transfer("2", EXCHANGE_ACCOUNT, "USDT", 4.into(), PostingKind::MessageFee)
+            .unwrap();
+        ledger
+            .transfer("1", EXCHANGE_ACCOUNT, "USDT", 1.into(), PostingKind::MessageFee)
+            .unwrap();
+
+        let statement = ledger.statement("1");
+        assert_eq!(statement.postings.len(), 2);
+        assert_eq!(statement.balances.get("USDT"), Some(&Decimal::from(-4)));
+        assert_eq!(statement.total(PostingKind::MessageFee), Decimal::from(-4));
+
+        let statement = ledger.statement(EXCHANGE_ACCOUNT);
+        assert_eq!(statement.total(PostingKind::MessageFee), Decimal::from(8));
+    }
+}
@@ -1,6 +1,69 @@
+pub mod accounts;
+pub mod accrual;
+pub mod adl;
+pub mod allocation;
+pub mod analytics;
+pub mod arbiter;
+pub mod batching;
+pub mod billing;
+pub mod calendar;
+pub mod candles;
+pub mod circuit_breaker;
+pub mod clock;
+pub mod compression;
+pub mod consolidation;
+pub mod dataset;
+pub mod dead_man;
 pub mod engine;
+pub mod experiment;
+pub mod fees;
+pub mod fx;
+#[cfg(any(feature = "grpc", feature = "rest", feature = "websocket"))]
+pub mod gateway;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod index;
+pub mod insurance;
+pub mod kill_switch;
+pub mod ledger;
+pub mod line;
+pub mod locate;
+pub mod lots;
+pub mod margin;
+pub mod market_data;
+pub mod memory;
+pub mod metrics;
+pub mod options;
 pub mod order;
 pub mod orderbook;
 //pub mod policy;
+pub mod position;
+pub mod price_feed;
+#[cfg(feature = "publisher")]
+pub mod publisher;
+pub mod recording;
+pub mod replay;
+#[cfg(feature = "rest")]
+pub mod rest;
+pub mod review;
+pub mod risk;
+pub mod routing;
+pub mod runtime;
+pub mod scenario;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod session;
+pub mod shadow;
+pub mod simulation;
+pub mod speed_bump;
+#[cfg(feature = "storage")]
+pub mod storage;
 pub mod summary;
+pub mod symbol;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod throttle;
+pub mod ticker;
 pub mod trade;
+#[cfg(feature = "websocket")]
+pub mod websocket;
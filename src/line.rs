@@ -0,0 +1,174 @@
+use std::io::{self, BufRead, Write};
+#[cfg(unix)]
+use std::{io::BufReader, os::unix::net::UnixListener, path::Path};
+
+use compact_str::{format_compact, CompactString};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    engine::{Engine, EngineError},
+    order::{
+        id::IdGenerator,
+        terse::{self, TerseDefaults},
+        OrderRequest,
+    },
+};
+
+pub mod framing;
+
+// written back for every request line, in the same order
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "status")]
+pub enum LineAck {
+    Accepted {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        order_id: Option<u64>,
+    },
+    Rejected {
+        reason: CompactString,
+    },
+}
+
+// line-delimited order entry for scripting and manual testing: every line is either a JSON order request or a terse one
+// (see terse::parse) for the account of the frontend unless it says otherwise, the terse orders get generated ids
+pub struct LineFrontend {
+    engine: Engine,
+    account_id: CompactString,
+    ids: IdGenerator,
+}
+
+impl LineFrontend {
+    pub fn new(engine: Engine, account_id: &str) -> Self {
+        Self {
+            engine,
+            account_id: account_id.into(),
+            ids: IdGenerator::default(),
+        }
+    }
+
+    pub fn with_id_generator(mut self, ids: IdGenerator) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    #[inline]
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    #[inline]
+    pub fn into_engine(self) -> Engine {
+        self.engine
+    }
+
+    // blank lines get no ack, the requests for another pair than the one of the engine are rejected
+    pub fn handle_line(&mut self, line: &str) -> Option<LineAck> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+
+        let order_request = if line.starts_with('{') {
+            serde_json::from_str(line).map_err(|error| format_compact!("{error}"))
+        } else {
+            let defaults = TerseDefaults {
+                account_id: &self.account_id,
+                pair: self.engine.pair(),
+            };
+            let ids = &mut self.ids;
+            terse::parse(line, defaults, || ids.next_id().ok().map(u64::from))
+                .map_err(|error| format_compact!("{error}"))
+        };
+        let ack = match order_request {
+            Ok(order_request) => {
+                let (order_id, pair) = match &order_request {
+                    OrderRequest::Create { order_id, pair, .. } => (Some(*order_id), Some(pair.clone())),
+                    _ => (None, None),
+                };
+                let processed = match pair {
+                    Some(pair) if pair != *self.engine.pair() => Err(EngineError::InvalidPair {
+                        expected: self.engine.pair().as_str().into(),
+                        found: pair.into(),
+                    }),
+                    _ => self.engine.process(order_request),
+                };
+                match processed {
+                    Ok(()) => LineAck::Accepted { order_id },
+                    Err(error) => LineAck::Rejected {
+                        reason: format_compact!("{error}"),
+                    },
+                }
+            }
+            Err(reason) => LineAck::Rejected { reason },
+        };
+        Some(ack)
+    }
+
+    // until the end of the input
+    pub fn serve(&mut self, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        for line in reader.lines() {
+            if let Some(ack) = self.handle_line(&line?) {
+                writeln!(writer, "{}", serde_json::to_string(&ack)?)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // one connection at a time, each one served until it is closed (or gone)
+    #[cfg(unix)]
+    pub fn serve_unix(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let listener = UnixListener::bind(path)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            if let Err(error) = self.serve(BufReader::new(stream.try_clone()?), stream) {
+                warn!("line protocol connection closed with error: {error}");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        order::{id::EPOCH, util::DEFAULT_SYMBOL},
+    };
+
+    #[fixture]
+    fn frontend() -> LineFrontend {
+        let ids = IdGenerator::new(ManualClock::new(EPOCH), EPOCH, 0).unwrap();
+        LineFrontend::new(Engine::new(DEFAULT_SYMBOL), "1").with_id_generator(ids)
+    }
+
+    #[rstest]
+    fn ack_every_line(mut frontend: LineFrontend) {
+        let input = format!(
+            "S 10@15\n\n{}\nB 10@15 BTC/USDT\nB 1@14 IOC PO\n",
+            r#"{"order_request":"CREATE","account_id":"2","order_id":7,"pair":"ETH/USDT","side":"BID","limit_price":"15","quantity":"4"}"#
+        );
+        let mut output = vec![];
+        frontend.serve(input.as_bytes(), &mut output).unwrap();
+
+        let acks: Vec<LineAck> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(acks.len(), 4);
+        assert_eq!(acks[1], LineAck::Accepted { order_id: Some(7) });
+        assert!(matches!(acks[2], LineAck::Rejected { .. }));
+        assert_eq!(
+            acks[3],
+            LineAck::Rejected {
+                reason: "conflicting token! IOC".into()
+            }
+        );
+        assert_eq!(frontend.engine().orderbook().trade_count(), 1);
+    }
+}
@@ -0,0 +1,298 @@
+use std::io::{self, Read, Write};
+
+use compact_str::format_compact;
+use crc32fast::Hasher;
+use thiserror::Error;
+
+use crate::line::{LineAck, LineFrontend};
+
+const HEADER_LEN: usize = 4 + 8 + 4; // payload length, sequence, header crc
+const TRAILER_LEN: usize = 4 + 4; // rolling checksum, crc
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub sequence: u64,
+    pub payload: Vec<u8>,
+}
+
+// length-prefixed frames for links that may corrupt bytes silently, big-endian:
+// payload length (u32) | sequence (u64) | header crc (u32) | payload | rolling checksum (u32) | crc (u32)
+// the header crc covers the length and the sequence, so that a frame is never cut on a corrupted length; the crc
+// covers the frame up to itself, the rolling checksum is the CRC-32 of every payload of the session so far in
+// that direction, this one included; each side keeps one codec per session for what it sends and what it receives
+#[derive(Clone, Debug)]
+pub struct FrameCodec {
+    max_payload: usize,
+    sent: u64,
+    sent_checksum: Hasher,
+    received: u64,
+    received_checksum: Hasher,
+    buffer: Vec<u8>,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self {
+            max_payload: 64 * 1024,
+            sent: 0,
+            sent_checksum: Hasher::new(),
+            received: 0,
+            received_checksum: Hasher::new(),
+            buffer: vec![],
+        }
+    }
+}
+
+impl FrameCodec {
+    pub fn with_max_payload(mut self, max_payload: usize) -> Self {
+        self.max_payload = max_payload;
+        self
+    }
+
+    // sequence of the last frame accepted
+    #[inline]
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    pub fn encode(&mut self, payload: &[u8]) -> Vec<u8> {
+        self.sent += 1;
+        self.sent_checksum.update(payload);
+
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + TRAILER_LEN);
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&self.sent.to_be_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&frame).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame.extend_from_slice(&self.sent_checksum.clone().finalize().to_be_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&frame).to_be_bytes());
+        frame
+    }
+
+    #[inline]
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // none until a whole frame is buffered; a rejected frame is consumed and leaves the session as it was, so the same
+    // frame can be sent again, except for a corrupted header or an oversized frame: the length cannot be trusted then,
+    // so neither can the rest of the stream (see FrameError::is_fatal)
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, FrameError> {
+        let Some(header) = self.buffer.get(..HEADER_LEN) else {
+            return Ok(None);
+        };
+        let found = u32::from_be_bytes(header[12..].try_into().unwrap());
+        let expected = crc32fast::hash(&header[..12]);
+        if found != expected {
+            self.buffer.clear();
+            return Err(FrameError::HeaderCrcMismatch { expected, found });
+        }
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let sequence = u64::from_be_bytes(header[4..12].try_into().unwrap());
+        if len > self.max_payload {
+            self.buffer.clear();
+            return Err(FrameError::PayloadTooLarge {
+                len,
+                max_payload: self.max_payload,
+            });
+        }
+        let frame_len = HEADER_LEN + len + TRAILER_LEN;
+        if self.buffer.len() < frame_len {
+            return Ok(None);
+        }
+
+        let frame: Vec<u8> = self.buffer.drain(..frame_len).collect();
+        let (checked, crc) = frame.split_at(frame_len - 4);
+        let found = u32::from_be_bytes(crc.try_into().unwrap());
+        let expected = crc32fast::hash(checked);
+        if found != expected {
+            return Err(FrameError::CrcMismatch {
+                sequence,
+                expected,
+                found,
+            });
+        }
+        if sequence != self.received + 1 {
+            return Err(FrameError::SequenceMismatch {
+                expected: self.received + 1,
+                found: sequence,
+            });
+        }
+        let payload = &frame[HEADER_LEN..HEADER_LEN + len];
+        let mut checksum = self.received_checksum.clone();
+        checksum.update(payload);
+        let expected = checksum.clone().finalize();
+        let found = u32::from_be_bytes(frame[HEADER_LEN + len..frame_len - 4].try_into().unwrap());
+        if found != expected {
+            return Err(FrameError::ChecksumMismatch {
+                sequence,
+                expected,
+                found,
+            });
+        }
+
+        self.received = sequence;
+        self.received_checksum = checksum;
+        Ok(Some(Frame {
+            sequence,
+            payload: payload.to_vec(),
+        }))
+    }
+}
+
+impl LineFrontend {
+    // the line protocol over frames: every frame carries one request line and gets one framed ack (blank ones too), the
+    // rejected frames are acked as rejected and not processed; until the end of the input or a fatal frame error
+    pub fn serve_framed(&mut self, mut reader: impl Read, mut writer: impl Write) -> io::Result<()> {
+        let mut codec = FrameCodec::default();
+        let mut chunk = [0; 4096];
+        loop {
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(());
+            }
+            codec.push(&chunk[..read]);
+            loop {
+                let (ack, fatal) = match codec.next_frame() {
+                    Ok(None) => break,
+                    Ok(Some(frame)) => {
+                        let ack = match std::str::from_utf8(&frame.payload) {
+                            Ok(line) => self.handle_line(line).unwrap_or(LineAck::Accepted { order_id: None }),
+                            Err(error) => LineAck::Rejected {
+                                reason: format_compact!("{error}"),
+                            },
+                        };
+                        (ack, false)
+                    }
+                    Err(error) => {
+                        let fatal = error.is_fatal();
+                        let reason = format_compact!("{error}");
+                        (LineAck::Rejected { reason }, fatal)
+                    }
+                };
+                writer.write_all(&codec.encode(&serde_json::to_vec(&ack)?))?;
+                writer.flush()?;
+                if fatal {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FrameError {
+    #[error("frame header crc mismatch (expected={:08x}, found={:08x})", .expected, .found)]
+    HeaderCrcMismatch { expected: u32, found: u32 },
+    #[error("frame payload too large (len={}, max_payload={})", .len, .max_payload)]
+    PayloadTooLarge { len: usize, max_payload: usize },
+    #[error("frame crc mismatch (sequence={}, expected={:08x}, found={:08x})", .sequence, .expected, .found)]
+    CrcMismatch { sequence: u64, expected: u32, found: u32 },
+    #[error("frame out of sequence (expected={}, found={})", .expected, .found)]
+    SequenceMismatch { expected: u64, found: u64 },
+    #[error("session checksum mismatch (sequence={}, expected={:08x}, found={:08x})", .sequence, .expected, .found)]
+    ChecksumMismatch { sequence: u64, expected: u32, found: u32 },
+}
+
+impl FrameError {
+    // the frame boundaries are lost, the buffered bytes are dropped and the session is over
+    #[inline]
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::HeaderCrcMismatch { .. } | Self::PayloadTooLarge { .. })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{engine::Engine, order::util::DEFAULT_SYMBOL};
+
+    #[rstest]
+    fn reject_corrupted_frames() {
+        let mut sender = FrameCodec::default();
+        let mut receiver = FrameCodec::default().with_max_payload(16);
+        let first = sender.encode(b"S 10@15");
+        let mut corrupted = first.clone();
+        corrupted[HEADER_LEN] ^= 0x20;
+
+        // the frame comes through in pieces, a corrupted one can be sent again
+        receiver.push(&corrupted[..5]);
+        assert_eq!(receiver.next_frame(), Ok(None));
+        receiver.push(&corrupted[5..]);
+        assert!(matches!(
+            receiver.next_frame(),
+            Err(FrameError::CrcMismatch { sequence: 1, .. })
+        ));
+        receiver.push(&first);
+        assert_eq!(receiver.next_frame().unwrap().unwrap().payload, b"S 10@15");
+        receiver.push(&first);
+        assert_eq!(
+            receiver.next_frame(),
+            Err(FrameError::SequenceMismatch { expected: 2, found: 1 })
+        );
+
+        // a sender out of step with the session, with a valid crc
+        let mut other = FrameCodec::default();
+        other.encode(b"B 1@14");
+        receiver.push(&other.encode(b"B 1@14"));
+        assert!(matches!(
+            receiver.next_frame(),
+            Err(FrameError::ChecksumMismatch { sequence: 2, .. })
+        ));
+        receiver.push(&sender.encode(b"B 1@14"));
+        assert_eq!(receiver.next_frame().unwrap().unwrap().sequence, 2);
+        assert_eq!(receiver.received(), 2);
+
+        receiver.push(&sender.encode(&[b' '; 17]));
+        assert_eq!(
+            receiver.next_frame(),
+            Err(FrameError::PayloadTooLarge {
+                len: 17,
+                max_payload: 16
+            })
+        );
+    }
+
+    #[rstest]
+    fn reject_corrupted_headers() {
+        let mut sender = FrameCodec::default();
+        let mut receiver = FrameCodec::default();
+        // a length still under the maximum: the frame is not waited for, nor cut at the wrong place
+        let mut corrupted = sender.encode(b"S 10@15");
+        corrupted[3] ^= 0x10;
+        receiver.push(&corrupted);
+        let error = receiver.next_frame().unwrap_err();
+        assert!(matches!(error, FrameError::HeaderCrcMismatch { .. }));
+        assert!(error.is_fatal());
+        assert_eq!(receiver.next_frame(), Ok(None));
+        assert_eq!(receiver.received(), 0);
+    }
+
+    #[rstest]
+    fn serve_framed_lines() {
+        let mut client = FrameCodec::default();
+        let mut input = client.encode(b"S 10@15 #7");
+        let second = client.encode(b"B 10@15 #8");
+        let mut corrupted = second.clone();
+        corrupted[second.len() - 1] ^= 1;
+        input.extend_from_slice(&corrupted);
+        input.extend_from_slice(&second);
+
+        let mut frontend = LineFrontend::new(Engine::new(DEFAULT_SYMBOL), "1");
+        let mut output = vec![];
+        frontend.serve_framed(&input[..], &mut output).unwrap();
+
+        client.push(&output);
+        let mut acks = vec![];
+        while let Some(frame) = client.next_frame().unwrap() {
+            acks.push(serde_json::from_slice::<LineAck>(&frame.payload).unwrap());
+        }
+        assert_eq!(acks.len(), 3);
+        assert_eq!(acks[0], LineAck::Accepted { order_id: Some(7) });
+        assert!(matches!(&acks[1], LineAck::Rejected { reason } if reason.starts_with("frame crc mismatch")));
+        assert_eq!(acks[2], LineAck::Accepted { order_id: Some(8) });
+        assert_eq!(frontend.engine().orderbook().trade_count(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use crate::order::OrderQuantity;
+
+// pre-trade check of a short sale: the shares must be located (borrowable) before the order is accepted
+pub trait Locate: Send {
+    fn locate(&mut self, account_id: &str, pair: &str, quantity: OrderQuantity) -> Result<(), LocateError>;
+}
+
+// borrowable inventory by pair, each located quantity is taken out of it
+#[derive(Debug, Default)]
+pub struct BorrowInventory {
+    available: IndexMap<CompactString, OrderQuantity>,
+}
+
+impl BorrowInventory {
+    #[inline]
+    pub fn supply(&mut self, pair: &str, quantity: OrderQuantity) {
+        *self.available.entry(pair.into()).or_default() += quantity;
+    }
+
+    #[inline]
+    pub fn available(&self, pair: &str) -> OrderQuantity {
+        self.available.get(pair).copied().unwrap_or_default()
+    }
+}
+
+impl Locate for BorrowInventory {
+    fn locate(&mut self, _account_id: &str, pair: &str, quantity: OrderQuantity) -> Result<(), LocateError> {
+        let available = self.available(pair);
+        if available < quantity {
+            return Err(LocateError::NotLocated {
+                pair: pair.into(),
+                requested: quantity,
+                available,
+            });
+        }
+
+        self.available.insert(pair.into(), available - quantity);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LocateError {
+    #[error("short sale not located (pair={}, requested={}, available={})", .pair, .requested, .available)]
+    NotLocated {
+        pair: CompactString,
+        requested: OrderQuantity,
+        available: OrderQuantity,
+    },
+    #[error("short sale refused! {0}")]
+    Refused(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn locate_from_inventory() {
+        let mut inventory = BorrowInventory::default();
+        inventory.supply("AAPL/USD", 100.into());
+
+        assert_eq!(inventory.locate("1", "AAPL/USD", 60.into()), Ok(()));
+        assert_eq!(
+            inventory.locate("2", "AAPL/USD", 60.into()),
+            Err(LocateError::NotLocated {
+                pair: "AAPL/USD".into(),
+                requested: 60.into(),
+                available: 40.into()
+            })
+        );
+        assert_eq!(inventory.available("AAPL/USD"), OrderQuantity::from(40));
+    }
+}
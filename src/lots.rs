@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::order::OrderQuantity;
+
+// what becomes of the orders below one round lot
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OddLotHandling {
+    #[default]
+    Flag, // matched with the round lots, their trades are flagged
+    Separate, // matched among themselves, in a book of their own
+}
+
+// round lot of an instrument, orders from the block size on trade as blocks (held back from the feed)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LotRules {
+    round_lot: OrderQuantity,
+    odd_lots: OddLotHandling,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    block_size: Option<OrderQuantity>,
+}
+
+impl LotRules {
+    pub fn new(round_lot: OrderQuantity) -> Result<Self, LotError> {
+        if round_lot <= OrderQuantity::ZERO {
+            return Err(LotError::NonPositiveRoundLot(round_lot));
+        }
+
+        Ok(Self {
+            round_lot,
+            odd_lots: OddLotHandling::default(),
+            block_size: None,
+        })
+    }
+
+    pub fn with_odd_lots(mut self, odd_lots: OddLotHandling) -> Self {
+        self.odd_lots = odd_lots;
+        self
+    }
+
+    pub fn with_block_size(mut self, block_size: OrderQuantity) -> Result<Self, LotError> {
+        if block_size < self.round_lot {
+            return Err(LotError::BlockBelowRoundLot {
+                block_size,
+                round_lot: self.round_lot,
+            });
+        }
+
+        self.block_size = Some(block_size);
+        Ok(self)
+    }
+
+    #[inline]
+    pub fn round_lot(&self) -> OrderQuantity {
+        self.round_lot
+    }
+
+    #[inline]
+    pub fn odd_lots(&self) -> OddLotHandling {
+        self.odd_lots
+    }
+
+    #[inline]
+    pub fn is_odd_lot(&self, quantity: OrderQuantity) -> bool {
+        quantity < self.round_lot
+    }
+
+    #[inline]
+    pub fn is_block(&self, quantity: OrderQuantity) -> bool {
+        self.block_size.is_some_and(|block_size| quantity >= block_size)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LotError {
+    #[error("round lot must be positive! {0}")]
+    NonPositiveRoundLot(OrderQuantity),
+    #[error("block size below the round lot (block_size={}, round_lot={})", .block_size, .round_lot)]
+    BlockBelowRoundLot {
+        block_size: OrderQuantity,
+        round_lot: OrderQuantity,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn odd_lots_and_blocks() {
+        let rules = LotRules::new(100.into())
+            .unwrap()
+            .with_block_size(10_000.into())
+            .unwrap();
+        assert!(rules.is_odd_lot(99.into()));
+        assert!(!rules.is_odd_lot(150.into()));
+        assert!(!rules.is_block(9_999.into()));
+        assert!(rules.is_block(10_000.into()));
+
+        assert_eq!(
+            LotRules::new(100.into()).unwrap().with_block_size(50.into()),
+            Err(LotError::BlockBelowRoundLot {
+                block_size: 50.into(),
+                round_lot: 100.into()
+            })
+        );
+        assert_eq!(LotRules::new(0.into()), Err(LotError::NonPositiveRoundLot(0.into())));
+    }
+}
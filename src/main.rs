@@ -11,23 +11,45 @@ use compact_str::CompactString;
 use crossbeam_channel::unbounded;
 use merx::{
     engine::Engine,
-    order::{util::DEFAULT_PAIR, OrderRequest},
+    line::LineFrontend,
+    order::{
+        id::IdGenerator,
+        terse::{self, TerseDefaults},
+        util::DEFAULT_PAIR,
+        OrderRequest,
+    },
     summary::compute,
+    symbol::Symbol,
 };
 use tracing::{error, info};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_log::LogTracer;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter, Layer, Registry};
 
+#[cfg(feature = "csv")]
+use merx::recording::{RecordFormat, RecordReader};
+
 #[derive(Parser)]
 #[clap(author, version, about)]
 struct Args {
     #[clap(short, long, default_value = DEFAULT_PAIR, help = "Pair")]
-    pair: CompactString,
+    pair: Symbol,
     #[clap(short, long, value_parser = clap::value_parser!(Input), help = "Source of Order requests")]
     input: Option<Input>,
     #[clap(short, long, value_parser = clap::value_parser!(Output), help = "Target of Order Book events")]
     output: Option<Output>,
+    #[clap(short, long, help = "Journal of processed Order requests (replayed on start)")]
+    journal: Option<PathBuf>,
+    #[clap(
+        long,
+        conflicts_with = "input",
+        help = "Serve the line protocol on stdin, one ack per line on stdout"
+    )]
+    serve: bool,
+    #[clap(long, conflicts_with_all = ["input", "serve"], help = "Serve the line protocol on a Unix socket")]
+    socket: Option<PathBuf>,
+    #[clap(short, long, default_value = "1", help = "Account of the terse order requests")]
+    account: CompactString,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -68,20 +90,34 @@ fn main() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
-    let (tx, rx) = unbounded();
-
-    // Start reading orders in a separate thread
-    let reader = read(args.input.unwrap_or_default(), tx);
-    reader.join().expect("order reader thread panicked")?;
-
     // Create the matching engine
-    let mut engine = Engine::new(&args.pair);
+    let mut engine = match args.journal {
+        Some(path) => Engine::new(args.pair.clone()).replay(path)?,
+        None => Engine::new(args.pair.clone()),
+    };
 
-    // Process all the order requests
     let start = Instant::now();
-    while let Ok(order_request) = rx.recv() {
-        if let Err(error) = engine.process(order_request) {
-            error!("Error processing order request: {}", error);
+    if args.serve || args.socket.is_some() {
+        // Serve the line protocol until the end of the input
+        let mut frontend = LineFrontend::new(engine, &args.account);
+        match args.socket {
+            Some(path) => serve_unix(&mut frontend, path)?,
+            None => frontend.serve(std::io::stdin().lock(), std::io::stdout())?,
+        }
+        engine = frontend.into_engine();
+    } else {
+        let (tx, rx) = unbounded();
+
+        // Start reading orders in a separate thread
+        let defaults = (args.account.clone(), args.pair.clone());
+        let reader = read(args.input.unwrap_or_default(), defaults, tx);
+        reader.join().expect("order reader thread panicked")?;
+
+        // Process all the order requests
+        while let Ok(order_request) = rx.recv() {
+            if let Err(error) = engine.process(order_request) {
+                error!("Error processing order request: {}", error);
+            }
         }
     }
     let elapsed = (Instant::now() - start).as_millis();
@@ -89,17 +125,26 @@ fn main() -> Result<()> {
 
     // Report summary
     let orderbook = engine.orderbook();
+    let summary = compute(orderbook);
     match args.output.unwrap_or_default() {
-        Output::Stdout => {
-            let summary = compute(orderbook);
-            info!("{summary}");
-        }
-        Output::File(..) => unimplemented!(),
+        Output::Stdout => info!("{summary}"),
+        Output::File(path) => std::fs::write(path, format!("{summary}\n"))?,
     }
 
     Ok(())
 }
 
+#[cfg(unix)]
+fn serve_unix(frontend: &mut LineFrontend, path: PathBuf) -> Result<()> {
+    info!("Serving the line protocol on {}", path.display());
+    Ok(frontend.serve_unix(path)?)
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_: &mut LineFrontend, _: PathBuf) -> Result<()> {
+    anyhow::bail!("Unix sockets are not supported on this platform")
+}
+
 fn init_logs() -> WorkerGuard {
     LogTracer::init().expect("Unable to set up log tracer");
 
@@ -116,8 +161,32 @@ fn init_logs() -> WorkerGuard {
     guard
 }
 
-fn read(input_source: Input, tx: crossbeam_channel::Sender<OrderRequest>) -> std::thread::JoinHandle<Result<()>> {
+// JSON or terse order requests (see terse::parse), the terse ones are for the account and pair given unless they say
+// otherwise; or a CSV file of order requests (see recording::REQUEST_COLUMNS)
+fn read(
+    input_source: Input,
+    (account_id, pair): (CompactString, Symbol),
+    tx: crossbeam_channel::Sender<OrderRequest>,
+) -> std::thread::JoinHandle<Result<()>> {
     std::thread::spawn(move || -> Result<()> {
+        let defaults = TerseDefaults {
+            account_id: &account_id,
+            pair: &pair,
+        };
+        #[cfg(feature = "csv")]
+        if let Input::File(path) = &input_source {
+            if matches!(RecordFormat::from_path(path), Ok(RecordFormat::Csv)) {
+                for order in RecordReader::<_, OrderRequest>::open(path)? {
+                    match order {
+                        Err(error) => error!("Error processing source of orders: {}", error),
+                        Ok(order) => tx.send(order)?,
+                    }
+                }
+                return Ok(());
+            }
+        }
+
+        let mut ids = IdGenerator::default();
         let mut buf_read: Box<dyn BufRead> = match &input_source {
             Input::File(path) => {
                 let file = std::fs::File::open(path)?;
@@ -130,17 +199,20 @@ fn read(input_source: Input, tx: crossbeam_channel::Sender<OrderRequest>) -> std
         };
 
         let mut buf = String::with_capacity(4096);
-        while buf_read.read_line(&mut buf).is_ok() {
-            let order = serde_json::from_str(&buf);
+        while buf_read.read_line(&mut buf)? > 0 {
+            let line = buf.trim();
+            let order = if line.is_empty() {
+                None
+            } else if line.starts_with('{') {
+                Some(serde_json::from_str(line).map_err(anyhow::Error::from))
+            } else {
+                Some(terse::parse(line, defaults, || ids.next_id().ok().map(u64::from)).map_err(anyhow::Error::from))
+            };
             buf.clear();
             match order {
-                Err(error) => {
-                    if error.is_eof() {
-                        break;
-                    }
-                    error!("Error processing source of orders: {}", error);
-                }
-                Ok(order) => tx.send(order)?,
+                Some(Err(error)) => error!("Error processing source of orders: {}", error),
+                Some(Ok(order)) => tx.send(order)?,
+                None => {}
             }
         }
 
@@ -0,0 +1,391 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    order::{OrderPrice, OrderQuantity, OrderSide},
+    position::PositionBook,
+};
+
+// position of an account in one instrument, positions on the same underlying can offset each other
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Exposure {
+    pub underlying: CompactString,
+    pub size: Decimal, // signed: positive = long, negative = short
+    pub entry_price: OrderPrice,
+    pub mark_price: OrderPrice,
+}
+
+impl Exposure {
+    // the would-be position of an order, used by the pre-trade check (no pnl until the mark moves)
+    pub fn order(underlying: &str, side: OrderSide, quantity: OrderQuantity, price: OrderPrice) -> Self {
+        let size = match side {
+            OrderSide::Bid => quantity.value(),
+            OrderSide::Ask => -quantity.value(),
+        };
+        Self {
+            underlying: underlying.into(),
+            size,
+            entry_price: price,
+            mark_price: price,
+        }
+    }
+
+    pub fn from_positions(
+        underlying: &str,
+        positions: &PositionBook,
+        account_id: &str,
+        mark_price: OrderPrice,
+    ) -> Option<Self> {
+        positions
+            .get(account_id)
+            .filter(|position| !position.is_flat())
+            .map(|position| Self {
+                underlying: underlying.into(),
+                size: position.size,
+                entry_price: position.entry_price,
+                mark_price,
+            })
+    }
+
+    #[inline]
+    pub fn notional(&self) -> Decimal {
+        self.size.abs() * self.mark_price
+    }
+
+    #[inline]
+    pub fn unrealized_pnl(&self) -> Decimal {
+        self.size * (self.mark_price - self.entry_price)
+    }
+}
+
+pub trait MarginModel: Send {
+    // required to open new exposure
+    fn initial_margin(&self, exposures: &[Exposure]) -> Decimal;
+
+    // below this the account gets liquidated
+    fn maintenance_margin(&self, exposures: &[Exposure]) -> Decimal;
+}
+
+// a fixed rate of the gross notional, no offsets at all
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotionalMargin {
+    pub initial_rate: Decimal,
+    pub maintenance_rate: Decimal,
+}
+
+impl Default for NotionalMargin {
+    fn default() -> Self {
+        Self {
+            initial_rate: Decimal::new(10, 2),
+            maintenance_rate: Decimal::new(5, 2),
+        }
+    }
+}
+
+impl MarginModel for NotionalMargin {
+    fn initial_margin(&self, exposures: &[Exposure]) -> Decimal {
+        exposures.iter().map(Exposure::notional).sum::<Decimal>() * self.initial_rate
+    }
+
+    fn maintenance_margin(&self, exposures: &[Exposure]) -> Decimal {
+        exposures.iter().map(Exposure::notional).sum::<Decimal>() * self.maintenance_rate
+    }
+}
+
+// SPAN-like: every underlying is shocked with each price move and the worst loss of the group is required,
+// so long and short exposures on the same underlying offset each other (no credit across underlyings)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScenarioMargin {
+    pub price_moves: Vec<Decimal>, // relative to the mark price, e.g. -0.15
+    pub initial_multiplier: Decimal,
+}
+
+impl Default for ScenarioMargin {
+    fn default() -> Self {
+        let price_moves = [-15, -10, -5, 0, 5, 10, 15]
+            .into_iter()
+            .map(|percent| Decimal::new(percent, 2))
+            .collect();
+        Self {
+            price_moves,
+            initial_multiplier: Decimal::new(12, 1),
+        }
+    }
+}
+
+impl ScenarioMargin {
+    fn worst_loss(&self, exposures: &[Exposure]) -> Decimal {
+        let mut groups: IndexMap<&str, Vec<&Exposure>> = IndexMap::new();
+        for exposure in exposures {
+            groups.entry(exposure.underlying.as_str()).or_default().push(exposure);
+        }
+
+        groups
+            .values()
+            .map(|group| {
+                self.price_moves
+                    .iter()
+                    .map(|price_move| {
+                        let pnl: Decimal = group
+                            .iter()
+                            .map(|exposure| exposure.size * exposure.mark_price * price_move)
+                            .sum();
+                        (-pnl).max(Decimal::ZERO)
+                    })
+                    .max()
+                    .unwrap_or_default()
+            })
+            .sum()
+    }
+}
+
+impl MarginModel for ScenarioMargin {
+    fn initial_margin(&self, exposures: &[Exposure]) -> Decimal {
+        self.worst_loss(exposures) * self.initial_multiplier
+    }
+
+    fn maintenance_margin(&self, exposures: &[Exposure]) -> Decimal {
+        self.worst_loss(exposures)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Hash, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AccountType {
+    #[default]
+    Standard,
+    Portfolio,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MarginAssessment {
+    pub initial: Decimal,
+    pub maintenance: Decimal,
+    pub equity: Decimal, // collateral plus unrealized pnl
+}
+
+impl MarginAssessment {
+    #[inline]
+    pub fn excess(&self) -> Decimal {
+        self.equity - self.initial
+    }
+
+    #[inline]
+    pub fn is_liquidatable(&self) -> bool {
+        self.equity < self.maintenance
+    }
+
+    // negative equity left after a liquidation, to be absorbed by the insurance fund
+    #[inline]
+    pub fn shortfall(&self) -> Decimal {
+        (-self.equity).max(Decimal::ZERO)
+    }
+}
+
+// picks the margin model by the type of the account, accounts without a type are standard
+pub struct Margining {
+    models: IndexMap<AccountType, Box<dyn MarginModel>>,
+    account_types: IndexMap<CompactString, AccountType>,
+}
+
+impl Default for Margining {
+    fn default() -> Self {
+        Self::new()
+            .with_model(AccountType::Standard, NotionalMargin::default())
+            .with_model(AccountType::Portfolio, ScenarioMargin::default())
+    }
+}
+
+impl Margining {
+    pub fn new() -> Self {
+        Self {
+            models: IndexMap::new(),
+            account_types: IndexMap::new(),
+        }
+    }
+
+    pub fn with_model(mut self, account_type: AccountType, model: impl MarginModel + 'static) -> Self {
+        self.models.insert(account_type, Box::new(model));
+        self
+    }
+
+    pub fn set_account_type(&mut self, account_id: &str, account_type: AccountType) {
+        self.account_types.insert(account_id.into(), account_type);
+    }
+
+    #[inline]
+    pub fn account_type(&self, account_id: &str) -> AccountType {
+        self.account_types.get(account_id).copied().unwrap_or_default()
+    }
+
+    pub fn assess(
+        &self,
+        account_id: &str,
+        exposures: &[Exposure],
+        collateral: Decimal,
+    ) -> Result<MarginAssessment, MarginError> {
+        let account_type = self.account_type(account_id);
+        let model = self
+            .models
+            .get(&account_type)
+            .ok_or(MarginError::NoMarginModel(account_type))?;
+
+        Ok(MarginAssessment {
+            initial: model.initial_margin(exposures),
+            maintenance: model.maintenance_margin(exposures),
+            equity: collateral + exposures.iter().map(Exposure::unrealized_pnl).sum::<Decimal>(),
+        })
+    }
+
+    // the portfolio after the order is filled must still be covered by the initial margin
+    pub fn check_pre_trade(
+        &self,
+        account_id: &str,
+        exposures: &[Exposure],
+        order: Exposure,
+        collateral: Decimal,
+    ) -> Result<MarginAssessment, MarginError> {
+        let mut after = exposures.to_vec();
+        after.push(order);
+
+        let assessment = self.assess(account_id, &after, collateral)?;
+        if assessment.excess() < Decimal::ZERO {
+            return Err(MarginError::InsufficientMargin {
+                required: assessment.initial,
+                available: assessment.equity,
+            });
+        }
+
+        Ok(assessment)
+    }
+
+    // accounts below maintenance margin, with their assessment
+    pub fn liquidations<'a>(
+        &self,
+        accounts: impl IntoIterator<Item = (&'a str, &'a [Exposure], Decimal)>,
+    ) -> Result<Vec<(CompactString, MarginAssessment)>, MarginError> {
+        let mut liquidations = vec![];
+        for (account_id, exposures, collateral) in accounts {
+            let assessment = self.assess(account_id, exposures, collateral)?;
+            if assessment.is_liquidatable() {
+                liquidations.push((account_id.into(), assessment));
+            }
+        }
+        Ok(liquidations)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MarginError {
+    #[error("no margin model for the account type! {0:?}")]
+    NoMarginModel(AccountType),
+    #[error("insufficient margin! required:{required} available:{available}")]
+    InsufficientMargin { required: Decimal, available: Decimal },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // long 10 futures and short 10 perpetuals on ETH (a calendar spread), both marked at 100
+    #[fixture]
+    fn spread() -> Vec<Exposure> {
+        vec![
+            Exposure::order("ETH", OrderSide::Bid, 10.into(), 100.into()),
+            Exposure::order("ETH", OrderSide::Ask, 10.into(), 100.into()),
+        ]
+    }
+
+    #[fixture]
+    fn margining() -> Margining {
+        let mut margining = Margining::default();
+        margining.set_account_type("pm", AccountType::Portfolio);
+        margining
+    }
+
+    #[rstest]
+    fn notional_ignores_offsets(spread: Vec<Exposure>) {
+        let model = NotionalMargin::default();
+        assert_eq!(model.initial_margin(&spread), Decimal::from(200));
+        assert_eq!(model.maintenance_margin(&spread), Decimal::from(100));
+    }
+
+    #[rstest]
+    fn scenario_offsets_same_underlying(mut spread: Vec<Exposure>) {
+        let model = ScenarioMargin::default();
+        assert_eq!(model.maintenance_margin(&spread), Decimal::ZERO);
+
+        // an unhedged long on another underlying loses 15% in the worst scenario
+        spread.push(Exposure::order("BTC", OrderSide::Bid, 1.into(), 1000.into()));
+        assert_eq!(model.maintenance_margin(&spread), Decimal::from(150));
+        assert_eq!(model.initial_margin(&spread), Decimal::from(180));
+    }
+
+    #[rstest]
+    fn model_selected_by_account_type(margining: Margining, spread: Vec<Exposure>) {
+        assert_eq!(margining.account_type("std"), AccountType::Standard);
+        assert_eq!(
+            margining.assess("std", &spread, 0.into()).unwrap().initial,
+            Decimal::from(200)
+        );
+        assert_eq!(
+            margining.assess("pm", &spread, 0.into()).unwrap().initial,
+            Decimal::ZERO
+        );
+
+        let margining = Margining::new();
+        assert_eq!(
+            margining.assess("std", &spread, 0.into()),
+            Err(MarginError::NoMarginModel(AccountType::Standard))
+        );
+    }
+
+    #[rstest]
+    fn pre_trade_check(margining: Margining) {
+        let long = [Exposure::order("ETH", OrderSide::Bid, 10.into(), 100.into())];
+        let hedge = Exposure::order("ETH", OrderSide::Ask, 10.into(), 100.into());
+
+        // the standard account needs 10% of the gross notional, the portfolio one gets the hedge for free
+        assert_eq!(
+            margining.check_pre_trade("std", &long, hedge.clone(), 150.into()),
+            Err(MarginError::InsufficientMargin {
+                required: 200.into(),
+                available: 150.into()
+            })
+        );
+        assert!(margining.check_pre_trade("pm", &long, hedge, 150.into()).is_ok());
+    }
+
+    #[rstest]
+    fn liquidate_below_maintenance(margining: Margining) {
+        // the mark dropped from 100 to 96: 40 of loss
+        let mut long = Exposure::order("ETH", OrderSide::Bid, 10.into(), 100.into());
+        long.mark_price = 96.into();
+        let long = [long];
+
+        let liquidations = margining
+            .liquidations([
+                ("std", &long[..], Decimal::from(80)),
+                ("pm", &long[..], Decimal::from(300)),
+            ])
+            .unwrap();
+        assert_eq!(liquidations.len(), 1);
+
+        // maintenance is 5% of 960 and only 40 of equity is left
+        let (account_id, assessment) = &liquidations[0];
+        assert_eq!(account_id.as_str(), "std");
+        assert_eq!(assessment.maintenance, Decimal::new(4800, 2));
+        assert_eq!(assessment.equity, Decimal::from(40));
+        assert_eq!(assessment.shortfall(), Decimal::ZERO);
+
+        // the portfolio account covers the worst scenario (15% of 960) with 260 of equity
+        let assessment = margining.assess("pm", &long, 300.into()).unwrap();
+        assert_eq!(assessment.maintenance, Decimal::new(14400, 2));
+        assert!(!assessment.is_liquidatable());
+    }
+}
@@ -0,0 +1,660 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use compact_str::CompactString;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calendar::SessionState,
+    clock::Timestamp,
+    order::{OrderId, OrderPrice, OrderQuantity, OrderSide},
+    orderbook::Uncross,
+    trade::TradeId,
+};
+
+pub mod itch;
+
+// incremental change of the book: order_id is set for order (L3) events and empty for level (L2) events,
+// the quantity is the remaining of the order or the total of the level
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "type")]
+pub enum BookEvent {
+    Add {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        order_id: Option<OrderId>,
+        side: OrderSide,
+        price: OrderPrice,
+        quantity: OrderQuantity,
+    },
+    Modify {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        order_id: Option<OrderId>,
+        side: OrderSide,
+        price: OrderPrice,
+        quantity: OrderQuantity,
+    },
+    Delete {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        order_id: Option<OrderId>,
+        side: OrderSide,
+        price: OrderPrice,
+    },
+    Trade {
+        trade_id: TradeId,
+        maker: OrderId,
+        taker: OrderId,
+        side: OrderSide, // of the taker
+        price: OrderPrice,
+        quantity: OrderQuantity,
+    },
+    // the trade was cancelled after review
+    Bust {
+        trade_id: TradeId,
+    },
+    // reported by the counterparties of a privately negotiated trade, not from the book
+    OffBookTrade {
+        trade_id: TradeId,
+        price: OrderPrice,
+        quantity: OrderQuantity,
+    },
+    // every change of the trading session, e.g. a halt
+    Session {
+        state: SessionState,
+    },
+    // what the opening uncross would produce right now, published during the pre-open
+    Indicative {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uncross: Option<Uncross>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Granularity {
+    Order,
+    Level,
+}
+
+// classes of events generated for a pair: the order (L3) events of the order stream, the level (L2) events of the
+// level stream and the trades (with their busts) of both, the session and indicative events are always generated
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct EventVerbosity {
+    pub orders: bool,
+    pub levels: bool,
+    pub trades: bool,
+}
+
+impl Default for EventVerbosity {
+    fn default() -> Self {
+        Self {
+            orders: true,
+            levels: true,
+            trades: true,
+        }
+    }
+}
+
+impl EventVerbosity {
+    // e.g. for very active pairs: no per-order events, the order stream only carries the trades
+    pub const LEVELS_AND_TRADES: Self = Self {
+        orders: false,
+        levels: true,
+        trades: true,
+    };
+
+    #[inline]
+    fn generates(&self, granularity: Granularity, event: &BookEvent) -> bool {
+        match event {
+            BookEvent::Trade { .. } | BookEvent::Bust { .. } | BookEvent::OffBookTrade { .. } => self.trades,
+            BookEvent::Session { .. } | BookEvent::Indicative { .. } => true,
+            BookEvent::Add { .. } | BookEvent::Modify { .. } | BookEvent::Delete { .. } => match granularity {
+                Granularity::Order => self.orders,
+                Granularity::Level => self.levels,
+            },
+        }
+    }
+}
+
+// verbosity of every pair, those not listed get the default one
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct VerbosityConfig {
+    pub default: EventVerbosity,
+    pub pairs: BTreeMap<CompactString, EventVerbosity>,
+}
+
+impl VerbosityConfig {
+    #[inline]
+    pub fn of(&self, pair: &str) -> EventVerbosity {
+        self.pairs.get(pair).copied().unwrap_or(self.default)
+    }
+}
+
+// each granularity has its own sequence so a consumer can detect gaps in the stream it follows
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MarketDataEvent {
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub event: BookEvent,
+}
+
+// the best bid and ask of the round lots with the total quantity of their level, none for an empty side
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bbo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bid_price: Option<OrderPrice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bid_quantity: Option<OrderQuantity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ask_price: Option<OrderPrice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ask_quantity: Option<OrderQuantity>,
+}
+
+// published only when the best price or quantity of a side changes, with a sequence of its own
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BboUpdate {
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub bbo: Bbo,
+}
+
+#[derive(Debug, Default)]
+struct BboSlot {
+    update: Option<BboUpdate>,
+    conflated: u64,
+}
+
+// latest value wins: a slow consumer only gets the last update since it looked, never a backlog
+#[derive(Debug, Default)]
+pub struct ConflatedBbo(Arc<Mutex<BboSlot>>);
+
+impl ConflatedBbo {
+    // the last update not taken yet
+    #[inline]
+    pub fn take(&self) -> Option<BboUpdate> {
+        self.0.lock().unwrap().update.take()
+    }
+
+    // updates replaced by a newer one before they were taken
+    #[inline]
+    pub fn conflated(&self) -> u64 {
+        self.0.lock().unwrap().conflated
+    }
+}
+
+enum BboSubscriber {
+    Stream(Sender<BboUpdate>),
+    Conflated(Weak<Mutex<BboSlot>>),
+}
+
+impl BboSubscriber {
+    // false once the subscriber is gone
+    fn send(&self, update: BboUpdate) -> bool {
+        match self {
+            Self::Stream(tx) => tx.send(update).is_ok(),
+            Self::Conflated(slot) => slot.upgrade().is_some_and(|slot| {
+                let mut slot = slot.lock().unwrap();
+                slot.conflated += u64::from(slot.update.replace(update).is_some());
+                true
+            }),
+        }
+    }
+}
+
+// large in scale trades reach the public feed only once the delay is over (milliseconds)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TradeDeferral {
+    pub min_notional: Decimal,
+    pub delay: Timestamp,
+}
+
+impl TradeDeferral {
+    #[inline]
+    pub fn new(min_notional: Decimal, delay: Timestamp) -> Self {
+        Self { min_notional, delay }
+    }
+
+    #[inline]
+    pub fn is_deferred(&self, price: OrderPrice, quantity: OrderQuantity) -> bool {
+        price * quantity >= self.min_notional
+    }
+}
+
+// fans out the order events of the book and derives the level events from them
+#[derive(Default)]
+pub struct MarketData {
+    subscribers: Vec<(Granularity, Sender<MarketDataEvent>)>,
+    order_sequence: u64,
+    level_sequence: u64,
+    orders: IndexMap<OrderId, OrderQuantity>,
+    levels: IndexMap<(OrderSide, OrderPrice), OrderQuantity>,
+    verbosity: EventVerbosity,
+    bbo_subscribers: Vec<BboSubscriber>,
+    bbo_sequence: u64,
+    bbo: Bbo,
+}
+
+impl MarketData {
+    // to be set before the first event, the levels are not rebuilt from events that were not generated
+    #[inline]
+    pub fn set_verbosity(&mut self, verbosity: EventVerbosity) {
+        self.verbosity = verbosity;
+    }
+
+    #[inline]
+    pub fn verbosity(&self) -> EventVerbosity {
+        self.verbosity
+    }
+
+    pub fn subscribe(&mut self, granularity: Granularity) -> Receiver<MarketDataEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers.push((granularity, tx));
+        rx
+    }
+
+    // `bbo` is the current one of the book, sent to the new subscriber right away
+    pub fn subscribe_bbo(&mut self, bbo: Bbo) -> Receiver<BboUpdate> {
+        let (tx, rx) = unbounded();
+        self.bbo_subscribers.push(BboSubscriber::Stream(tx));
+        self.seed_bbo(bbo);
+        rx
+    }
+
+    pub fn subscribe_bbo_conflated(&mut self, bbo: Bbo) -> ConflatedBbo {
+        let conflated = ConflatedBbo::default();
+        self.bbo_subscribers
+            .push(BboSubscriber::Conflated(Arc::downgrade(&conflated.0)));
+        self.seed_bbo(bbo);
+        conflated
+    }
+
+    fn seed_bbo(&mut self, bbo: Bbo) {
+        if self.bbo != bbo || self.bbo_sequence == 0 {
+            self.publish_bbo(bbo);
+        } else if let Some(subscriber) = self.bbo_subscribers.last() {
+            subscriber.send(BboUpdate {
+                sequence: self.bbo_sequence,
+                bbo,
+            });
+        }
+    }
+
+    #[inline]
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.is_empty() || self.has_bbo_subscribers()
+    }
+
+    #[inline]
+    pub fn has_bbo_subscribers(&self) -> bool {
+        !self.bbo_subscribers.is_empty()
+    }
+
+    // the ones that dropped their receiver are only forgotten on the next publish
+    #[inline]
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len() + self.bbo_subscribers.len()
+    }
+
+    // nothing is sent unless the bbo changed since the last one
+    pub fn publish_bbo(&mut self, bbo: Bbo) {
+        if self.bbo == bbo && self.bbo_sequence > 0 {
+            return;
+        }
+        self.bbo = bbo;
+        self.bbo_sequence += 1;
+        let update = BboUpdate {
+            sequence: self.bbo_sequence,
+            bbo,
+        };
+        self.bbo_subscribers.retain(|subscriber| subscriber.send(update));
+    }
+
+    // subscribers that dropped their receiver are forgotten, the events the verbosity leaves out take no sequence
+    pub fn publish(&mut self, event: BookEvent) {
+        let order_event = self.verbosity.generates(Granularity::Order, &event).then(|| {
+            self.order_sequence += 1;
+            MarketDataEvent {
+                sequence: self.order_sequence,
+                event,
+            }
+        });
+        let level_event = self
+            .verbosity
+            .generates(Granularity::Level, &event)
+            .then(|| self.aggregate(event))
+            .flatten()
+            .map(|event| {
+                self.level_sequence += 1;
+                MarketDataEvent {
+                    sequence: self.level_sequence,
+                    event,
+                }
+            });
+
+        self.subscribers.retain(|(granularity, tx)| match granularity {
+            Granularity::Order => order_event.is_none_or(|order_event| tx.send(order_event).is_ok()),
+            Granularity::Level => level_event.is_none_or(|level_event| tx.send(level_event).is_ok()),
+        });
+    }
+
+    fn aggregate(&mut self, event: BookEvent) -> Option<BookEvent> {
+        match event {
+            BookEvent::Add {
+                order_id: Some(order_id),
+                side,
+                price,
+                quantity,
+            } => {
+                self.orders.insert(order_id, quantity);
+                let total = self.levels.entry((side, price)).or_default();
+                *total += quantity;
+                let order_id = None;
+                if *total == quantity {
+                    Some(BookEvent::Add {
+                        order_id,
+                        side,
+                        price,
+                        quantity,
+                    })
+                } else {
+                    Some(BookEvent::Modify {
+                        order_id,
+                        side,
+                        price,
+                        quantity: *total,
+                    })
+                }
+            }
+            BookEvent::Modify {
+                order_id: Some(order_id),
+                side,
+                price,
+                quantity,
+            } => {
+                let previous = self.orders.insert(order_id, quantity)?;
+                let total = self.levels.get_mut(&(side, price))?;
+                *total = *total + quantity - previous;
+                Some(BookEvent::Modify {
+                    order_id: None,
+                    side,
+                    price,
+                    quantity: *total,
+                })
+            }
+            BookEvent::Delete {
+                order_id: Some(order_id),
+                side,
+                price,
+            } => {
+                let previous = self.orders.swap_remove(&order_id)?;
+                let total = self.levels.get_mut(&(side, price))?;
+                *total -= previous;
+                if total.is_zero() {
+                    self.levels.swap_remove(&(side, price));
+                    Some(BookEvent::Delete {
+                        order_id: None,
+                        side,
+                        price,
+                    })
+                } else {
+                    Some(BookEvent::Modify {
+                        order_id: None,
+                        side,
+                        price,
+                        quantity: *total,
+                    })
+                }
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        engine::Engine,
+        order::{
+            util::{self, DEFAULT_PAIR, DEFAULT_SYMBOL},
+            OrderRequest,
+        },
+    };
+
+    fn create(order_id: u64, side: OrderSide, quantity: u32, limit_price: u32) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(Some(limit_price.into()))
+            .quantity(quantity)
+            .build()
+    }
+
+    #[fixture]
+    fn engine() -> Engine {
+        Engine::new(DEFAULT_SYMBOL)
+    }
+
+    #[rstest]
+    fn order_and_level_streams(mut engine: Engine) {
+        let orders = engine.subscribe(Granularity::Order);
+        let levels = engine.subscribe(Granularity::Level);
+
+        // two asks on the same level, a bid takes part of the first one, then the second one is cancelled
+        for order_request in [
+            create(1, OrderSide::Ask, 10, 15),
+            create(2, OrderSide::Ask, 5, 15),
+            create(3, OrderSide::Bid, 4, 15),
+            util::cancel(2),
+        ] {
+            engine.process(order_request).unwrap();
+        }
+
+        let orders: Vec<MarketDataEvent> = orders.try_iter().collect();
+        assert_eq!(
+            orders.iter().map(|event| event.sequence).collect::<Vec<u64>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert!(matches!(
+            orders[2].event,
+            BookEvent::Trade {
+                side: OrderSide::Bid,
+                ..
+            }
+        ));
+        assert_eq!(
+            orders[3].event,
+            BookEvent::Modify {
+                order_id: Some(1.into()),
+                side: OrderSide::Ask,
+                price: 15.into(),
+                quantity: 6.into()
+            }
+        );
+
+        // the level stream aggregates both asks
+        let levels: Vec<BookEvent> = levels.try_iter().map(|event| event.event).collect();
+        let level = |quantity: u32| BookEvent::Modify {
+            order_id: None,
+            side: OrderSide::Ask,
+            price: 15.into(),
+            quantity: quantity.into(),
+        };
+        assert_eq!(
+            levels[0],
+            BookEvent::Add {
+                order_id: None,
+                side: OrderSide::Ask,
+                price: 15.into(),
+                quantity: 10.into()
+            }
+        );
+        assert_eq!(levels[1], level(15));
+        assert_eq!(levels[3], level(11));
+        assert_eq!(levels[4], level(6));
+    }
+
+    #[rstest]
+    fn resting_orders_replayed_on_first_subscription(mut engine: Engine) {
+        engine.process(create(1, OrderSide::Bid, 10, 14)).unwrap();
+        engine.process(create(2, OrderSide::Bid, 10, 15)).unwrap();
+
+        let levels = engine.subscribe(Granularity::Level);
+        engine.process(util::cancel(1)).unwrap();
+
+        // the best level first, then the cancel removes the whole level
+        let levels: Vec<MarketDataEvent> = levels.try_iter().collect();
+        assert_eq!(levels.len(), 3);
+        assert!(matches!(levels[0].event, BookEvent::Add { price, .. } if price == 15.into()));
+        assert_eq!(
+            levels[2],
+            MarketDataEvent {
+                sequence: 3,
+                event: BookEvent::Delete {
+                    order_id: None,
+                    side: OrderSide::Bid,
+                    price: 14.into()
+                }
+            }
+        );
+    }
+
+    #[rstest]
+    fn only_levels_and_trades(engine: Engine) {
+        let mut engine = engine.with_verbosity(EventVerbosity::LEVELS_AND_TRADES);
+        let orders = engine.subscribe(Granularity::Order);
+        let levels = engine.subscribe(Granularity::Level);
+        for order_request in [
+            create(1, OrderSide::Ask, 10, 15),
+            create(2, OrderSide::Bid, 4, 15),
+            util::cancel(1),
+        ] {
+            engine.process(order_request).unwrap();
+        }
+
+        // the order stream keeps the trade alone, with no gap in its sequence
+        let orders: Vec<MarketDataEvent> = orders.try_iter().collect();
+        assert_eq!(orders.len(), 1);
+        assert!(matches!(
+            orders[0],
+            MarketDataEvent {
+                sequence: 1,
+                event: BookEvent::Trade { .. }
+            }
+        ));
+        assert_eq!(levels.try_iter().count(), 4);
+    }
+
+    #[rstest]
+    fn bbo_only_on_changes(mut engine: Engine) {
+        engine.process(create(1, OrderSide::Bid, 10, 14)).unwrap();
+        let bbo = engine.subscribe_bbo();
+        for order_request in [
+            create(2, OrderSide::Bid, 5, 13), // below the best bid
+            create(3, OrderSide::Ask, 10, 16),
+            create(4, OrderSide::Ask, 5, 17), // above the best ask
+            create(5, OrderSide::Bid, 4, 16), // takes part of the best ask
+            util::cancel(2),
+            util::cancel(3),
+        ] {
+            engine.process(order_request).unwrap();
+        }
+
+        let quote = |bid: Option<(u32, u32)>, ask: Option<(u32, u32)>| Bbo {
+            bid_price: bid.map(|(price, _)| price.into()),
+            bid_quantity: bid.map(|(_, quantity)| quantity.into()),
+            ask_price: ask.map(|(price, _)| price.into()),
+            ask_quantity: ask.map(|(_, quantity)| quantity.into()),
+        };
+        let updates: Vec<BboUpdate> = bbo.try_iter().collect();
+        assert_eq!(
+            updates.iter().map(|update| update.sequence).collect::<Vec<u64>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(
+            updates.into_iter().map(|update| update.bbo).collect::<Vec<Bbo>>(),
+            vec![
+                quote(Some((14, 10)), None),
+                quote(Some((14, 10)), Some((16, 10))),
+                quote(Some((14, 10)), Some((16, 6))),
+                quote(Some((14, 10)), Some((17, 5))),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn conflated_bbo_keeps_the_latest(mut engine: Engine) {
+        let bbo = engine.subscribe_bbo_conflated();
+        assert_eq!(bbo.take().unwrap().bbo, Bbo::default());
+        assert!(bbo.take().is_none());
+
+        for (order_id, limit_price) in [(1, 14), (2, 15), (3, 16)] {
+            engine
+                .process(create(order_id, OrderSide::Bid, 10, limit_price))
+                .unwrap();
+        }
+        let update = bbo.take().unwrap();
+        assert_eq!(update.sequence, 4);
+        assert_eq!(update.bbo.bid_price, Some(16.into()));
+        assert_eq!(bbo.conflated(), 2);
+
+        drop(bbo);
+        engine.process(util::cancel(3)).unwrap();
+        assert!(!engine.market_data().has_subscribers());
+    }
+
+    #[rstest]
+    fn serialize_bbo_update() {
+        let update = BboUpdate {
+            sequence: 3,
+            bbo: Bbo {
+                bid_price: Some(14.into()),
+                bid_quantity: Some(10.into()),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&update).unwrap(),
+            r#"{"sequence":3,"bid_price":"14","bid_quantity":"10"}"#
+        );
+    }
+
+    #[rstest]
+    fn verbosity_of_a_pair() {
+        let config: VerbosityConfig =
+            serde_json::from_str(r#"{"pairs":{"BTC/USDT":{"orders":false},"ETH/BTC":{"trades":false}}}"#).unwrap();
+        assert_eq!(config.of("BTC/USDT"), EventVerbosity::LEVELS_AND_TRADES);
+        assert!(!config.of("ETH/BTC").trades && config.of("ETH/BTC").orders);
+        assert_eq!(config.of(DEFAULT_PAIR), EventVerbosity::default());
+    }
+
+    #[rstest]
+    fn forget_dropped_subscribers(mut engine: Engine) {
+        drop(engine.subscribe(Granularity::Order));
+        engine.process(create(1, OrderSide::Bid, 10, 14)).unwrap();
+        assert!(!engine.market_data().has_subscribers());
+    }
+
+    #[rstest]
+    fn serialize_level_event_without_order_id() {
+        let event = MarketDataEvent {
+            sequence: 7,
+            event: BookEvent::Delete {
+                order_id: None,
+                side: OrderSide::Ask,
+                price: 15.into(),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"sequence":7,"type":"DELETE","side":"ASK","price":"15"}"#
+        );
+    }
+}
@@ -0,0 +1,647 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::{Timestamp, DAY},
+    market_data::{BookEvent, MarketDataEvent},
+    order::{OrderId, OrderPrice, OrderQuantity, OrderSide},
+};
+
+const HEADER_LEN: usize = 11; // type, stock locate, tracking number, timestamp
+const STOCK_LEN: usize = 8;
+const SESSION_LEN: usize = 10;
+const PACKET_HEADER_LEN: usize = SESSION_LEN + 8 + 2; // session, sequence of the first message, message count
+
+// implied decimals of the prices and quantities on the wire, both ends must agree on them (ITCH has 4 and whole shares)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FixedPoint {
+    pub price_decimals: u32,
+    pub quantity_decimals: u32,
+}
+
+impl Default for FixedPoint {
+    fn default() -> Self {
+        Self {
+            price_decimals: 4,
+            quantity_decimals: 0,
+        }
+    }
+}
+
+impl FixedPoint {
+    fn to_wire(decimals: u32, value: Decimal) -> Result<u64, ItchError> {
+        value
+            .checked_mul(Decimal::from(10u64.pow(decimals)))
+            .filter(|scaled| scaled.fract().is_zero())
+            .and_then(|scaled| scaled.to_u64())
+            .ok_or(ItchError::NotRepresentable(value))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "type")]
+pub enum ItchBody {
+    AddOrder {
+        order_ref: u64,
+        side: OrderSide,
+        shares: OrderQuantity,
+        stock: CompactString,
+        price: OrderPrice,
+    },
+    // at the price of the resting order
+    OrderExecuted {
+        order_ref: u64,
+        executed_shares: OrderQuantity,
+        match_number: u64,
+    },
+    // partial cancel, the order stays with the rest
+    OrderCancel {
+        order_ref: u64,
+        cancelled_shares: OrderQuantity,
+    },
+    OrderDelete {
+        order_ref: u64,
+    },
+    // a trade without a displayed order, i.e. the off-book trades
+    Trade {
+        order_ref: u64,
+        side: OrderSide,
+        shares: OrderQuantity,
+        stock: CompactString,
+        price: OrderPrice,
+        match_number: u64,
+    },
+    BrokenTrade {
+        match_number: u64,
+    },
+}
+
+impl ItchBody {
+    #[inline]
+    fn message_type(&self) -> u8 {
+        match self {
+            ItchBody::AddOrder { .. } => b'A',
+            ItchBody::OrderExecuted { .. } => b'E',
+            ItchBody::OrderCancel { .. } => b'X',
+            ItchBody::OrderDelete { .. } => b'D',
+            ItchBody::Trade { .. } => b'P',
+            ItchBody::BrokenTrade { .. } => b'B',
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ItchMessage {
+    pub stock_locate: u16,
+    pub timestamp: u64, // nanoseconds since midnight
+    #[serde(flatten)]
+    pub body: ItchBody,
+}
+
+impl ItchMessage {
+    // big-endian, the numbers are 8 bytes wide where ITCH has 4 as the quantities may be fractional
+    pub fn write(&self, fixed_point: FixedPoint, buf: &mut Vec<u8>) -> Result<(), ItchError> {
+        let price = |price: OrderPrice| FixedPoint::to_wire(fixed_point.price_decimals, price.value());
+        let shares = |shares: OrderQuantity| FixedPoint::to_wire(fixed_point.quantity_decimals, shares.value());
+
+        buf.push(self.body.message_type());
+        buf.extend_from_slice(&self.stock_locate.to_be_bytes());
+        buf.extend_from_slice(&0u16.to_be_bytes()); // tracking number
+        buf.extend_from_slice(&self.timestamp.to_be_bytes()[2..]);
+        match &self.body {
+            ItchBody::AddOrder {
+                order_ref,
+                side,
+                shares: quantity,
+                stock,
+                price: limit_price,
+            } => {
+                buf.extend_from_slice(&order_ref.to_be_bytes());
+                buf.push(write_side(*side));
+                buf.extend_from_slice(&shares(*quantity)?.to_be_bytes());
+                write_stock(stock, buf)?;
+                buf.extend_from_slice(&price(*limit_price)?.to_be_bytes());
+            }
+            ItchBody::OrderExecuted {
+                order_ref,
+                executed_shares,
+                match_number,
+            } => {
+                buf.extend_from_slice(&order_ref.to_be_bytes());
+                buf.extend_from_slice(&shares(*executed_shares)?.to_be_bytes());
+                buf.extend_from_slice(&match_number.to_be_bytes());
+            }
+            ItchBody::OrderCancel {
+                order_ref,
+                cancelled_shares,
+            } => {
+                buf.extend_from_slice(&order_ref.to_be_bytes());
+                buf.extend_from_slice(&shares(*cancelled_shares)?.to_be_bytes());
+            }
+            ItchBody::OrderDelete { order_ref } => buf.extend_from_slice(&order_ref.to_be_bytes()),
+            ItchBody::Trade {
+                order_ref,
+                side,
+                shares: quantity,
+                stock,
+                price: trade_price,
+                match_number,
+            } => {
+                buf.extend_from_slice(&order_ref.to_be_bytes());
+                buf.push(write_side(*side));
+                buf.extend_from_slice(&shares(*quantity)?.to_be_bytes());
+                write_stock(stock, buf)?;
+                buf.extend_from_slice(&price(*trade_price)?.to_be_bytes());
+                buf.extend_from_slice(&match_number.to_be_bytes());
+            }
+            ItchBody::BrokenTrade { match_number } => buf.extend_from_slice(&match_number.to_be_bytes()),
+        }
+        Ok(())
+    }
+
+    pub fn read(fixed_point: FixedPoint, bytes: &[u8]) -> Result<Self, ItchError> {
+        let mut reader = Reader { bytes, at: 0 };
+        let message_type = reader.u8()?;
+        let stock_locate = reader.u16()?;
+        reader.take(2)?; // tracking number
+        let timestamp = reader.u48()?;
+        let price = |raw: u64| OrderPrice::new(raw, fixed_point.price_decimals);
+        let shares = |raw: u64| OrderQuantity::new(raw, fixed_point.quantity_decimals);
+
+        let body = match message_type {
+            b'A' => ItchBody::AddOrder {
+                order_ref: reader.u64()?,
+                side: reader.side()?,
+                shares: shares(reader.u64()?),
+                stock: reader.stock()?,
+                price: price(reader.u64()?),
+            },
+            b'E' => ItchBody::OrderExecuted {
+                order_ref: reader.u64()?,
+                executed_shares: shares(reader.u64()?),
+                match_number: reader.u64()?,
+            },
+            b'X' => ItchBody::OrderCancel {
+                order_ref: reader.u64()?,
+                cancelled_shares: shares(reader.u64()?),
+            },
+            b'D' => ItchBody::OrderDelete {
+                order_ref: reader.u64()?,
+            },
+            b'P' => ItchBody::Trade {
+                order_ref: reader.u64()?,
+                side: reader.side()?,
+                shares: shares(reader.u64()?),
+                stock: reader.stock()?,
+                price: price(reader.u64()?),
+                match_number: reader.u64()?,
+            },
+            b'B' => ItchBody::BrokenTrade {
+                match_number: reader.u64()?,
+            },
+            message_type => return Err(ItchError::UnknownMessageType(message_type)),
+        };
+        Ok(Self {
+            stock_locate,
+            timestamp,
+            body,
+        })
+    }
+}
+
+#[inline]
+fn write_side(side: OrderSide) -> u8 {
+    match side {
+        OrderSide::Bid => b'B',
+        OrderSide::Ask => b'S',
+    }
+}
+
+// left aligned, padded with spaces
+fn write_stock(stock: &str, buf: &mut Vec<u8>) -> Result<(), ItchError> {
+    if stock.len() > STOCK_LEN {
+        return Err(ItchError::StockTooLong(stock.into()));
+    }
+    buf.extend_from_slice(stock.as_bytes());
+    buf.extend(std::iter::repeat_n(b' ', STOCK_LEN - stock.len()));
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ItchError> {
+        let bytes = self.bytes.get(self.at..self.at + len).ok_or(ItchError::Truncated {
+            expected: self.at + len,
+            found: self.bytes.len(),
+        })?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ItchError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ItchError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u48(&mut self) -> Result<u64, ItchError> {
+        let mut bytes = [0; 8];
+        bytes[2..].copy_from_slice(self.take(6)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, ItchError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn side(&mut self) -> Result<OrderSide, ItchError> {
+        match self.u8()? {
+            b'B' => Ok(OrderSide::Bid),
+            b'S' => Ok(OrderSide::Ask),
+            side => Err(ItchError::InvalidSide(side)),
+        }
+    }
+
+    fn stock(&mut self) -> Result<CompactString, ItchError> {
+        let stock = self.take(STOCK_LEN)?;
+        Ok(String::from_utf8_lossy(stock).trim_end().into())
+    }
+}
+
+// turns the order stream (Granularity::Order) of one pair into ITCH messages framed in MoldUDP64-like packets, ready to
+// be sent over multicast; the remaining quantities conveyed are tracked so that the fills are only told once, by the
+// executions, the level, session and indicative events have no message
+#[derive(Clone, Debug)]
+pub struct ItchEncoder {
+    stock: CompactString,
+    stock_locate: u16,
+    session: [u8; SESSION_LEN],
+    fixed_point: FixedPoint,
+    sequence: u64, // of the next message
+    orders: IndexMap<OrderId, (OrderSide, OrderPrice, OrderQuantity)>,
+}
+
+impl ItchEncoder {
+    pub fn new(pair: &str, stock_locate: u16) -> Result<Self, ItchError> {
+        if pair.len() > STOCK_LEN {
+            return Err(ItchError::StockTooLong(pair.into()));
+        }
+
+        Ok(Self {
+            stock: pair.into(),
+            stock_locate,
+            session: *b"MERX      ",
+            fixed_point: FixedPoint::default(),
+            sequence: 1,
+            orders: IndexMap::new(),
+        })
+    }
+
+    // the session is padded with spaces (or cut) to 10 bytes
+    pub fn with_session(mut self, session: &str) -> Self {
+        self.session = [b' '; SESSION_LEN];
+        let len = session.len().min(SESSION_LEN);
+        self.session[..len].copy_from_slice(&session.as_bytes()[..len]);
+        self
+    }
+
+    pub fn with_fixed_point(mut self, fixed_point: FixedPoint) -> Self {
+        self.fixed_point = fixed_point;
+        self
+    }
+
+    #[inline]
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    // at is when the event was published, only the time of day goes on the wire
+    pub fn encode(&mut self, event: &BookEvent, at: Timestamp) -> Vec<ItchMessage> {
+        let bodies = match *event {
+            BookEvent::Add {
+                order_id: Some(order_id),
+                side,
+                price,
+                quantity,
+            } => {
+                self.orders.insert(order_id, (side, price, quantity));
+                vec![self.add_order(order_id, side, price, quantity)]
+            }
+            BookEvent::Modify {
+                order_id: Some(order_id),
+                side,
+                price,
+                quantity,
+            } => match self.orders.insert(order_id, (side, price, quantity)) {
+                Some((_, _, previous)) if previous == quantity => vec![],
+                Some((_, _, previous)) if previous > quantity => vec![ItchBody::OrderCancel {
+                    order_ref: order_id.into(),
+                    cancelled_shares: previous - quantity,
+                }],
+                // ITCH has no way to add to an order, it is sent again
+                _ => vec![
+                    ItchBody::OrderDelete {
+                        order_ref: order_id.into(),
+                    },
+                    self.add_order(order_id, side, price, quantity),
+                ],
+            },
+            BookEvent::Delete {
+                order_id: Some(order_id),
+                ..
+            } => match self.orders.swap_remove(&order_id) {
+                Some((_, _, remaining)) if remaining.is_zero() => vec![],
+                _ => vec![ItchBody::OrderDelete {
+                    order_ref: order_id.into(),
+                }],
+            },
+            BookEvent::Trade {
+                trade_id,
+                maker,
+                quantity,
+                ..
+            } => {
+                if let Some((_, _, remaining)) = self.orders.get_mut(&maker) {
+                    *remaining = remaining.checked_sub(quantity).unwrap_or_default();
+                }
+                vec![ItchBody::OrderExecuted {
+                    order_ref: maker.into(),
+                    executed_shares: quantity,
+                    match_number: trade_id.into(),
+                }]
+            }
+            BookEvent::OffBookTrade {
+                trade_id,
+                price,
+                quantity,
+            } => vec![ItchBody::Trade {
+                order_ref: 0,
+                side: OrderSide::Bid,
+                shares: quantity,
+                stock: self.stock.clone(),
+                price,
+                match_number: trade_id.into(),
+            }],
+            BookEvent::Bust { trade_id } => vec![ItchBody::BrokenTrade {
+                match_number: trade_id.into(),
+            }],
+            _ => vec![],
+        };
+
+        let timestamp = (at % DAY) * 1_000_000;
+        bodies
+            .into_iter()
+            .map(|body| ItchMessage {
+                stock_locate: self.stock_locate,
+                timestamp,
+                body,
+            })
+            .collect()
+    }
+
+    // one packet for the messages of the events, none without any message (the sequence is only taken by messages)
+    pub fn packet(&mut self, events: &[MarketDataEvent], at: Timestamp) -> Result<Option<Vec<u8>>, ItchError> {
+        let messages: Vec<ItchMessage> = events.iter().flat_map(|event| self.encode(&event.event, at)).collect();
+        if messages.is_empty() {
+            return Ok(None);
+        }
+
+        let mut packet = Vec::with_capacity(PACKET_HEADER_LEN + (2 + HEADER_LEN + 41) * messages.len());
+        packet.extend_from_slice(&self.session);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&(messages.len() as u16).to_be_bytes());
+        let mut message = vec![];
+        for itch_message in &messages {
+            message.clear();
+            itch_message.write(self.fixed_point, &mut message)?;
+            packet.extend_from_slice(&(message.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&message);
+        }
+        self.sequence += messages.len() as u64;
+        Ok(Some(packet))
+    }
+
+    fn add_order(&self, order_id: OrderId, side: OrderSide, price: OrderPrice, quantity: OrderQuantity) -> ItchBody {
+        ItchBody::AddOrder {
+            order_ref: order_id.into(),
+            side,
+            shares: quantity,
+            stock: self.stock.clone(),
+            price,
+        }
+    }
+}
+
+// reads the packets of an encoder in sequence: the messages already seen (e.g. from the other feed of an A/B pair) are
+// dropped, a gap is an error for the consumer to recover from
+#[derive(Clone, Debug)]
+pub struct ItchDecoder {
+    fixed_point: FixedPoint,
+    sequence: u64, // of the next message
+}
+
+impl Default for ItchDecoder {
+    fn default() -> Self {
+        Self {
+            fixed_point: FixedPoint::default(),
+            sequence: 1,
+        }
+    }
+}
+
+impl ItchDecoder {
+    pub fn with_fixed_point(mut self, fixed_point: FixedPoint) -> Self {
+        self.fixed_point = fixed_point;
+        self
+    }
+
+    #[inline]
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    // e.g. once recovered from a gap
+    #[inline]
+    pub fn reset(&mut self, sequence: u64) {
+        self.sequence = sequence;
+    }
+
+    pub fn decode(&mut self, packet: &[u8]) -> Result<Vec<ItchMessage>, ItchError> {
+        let mut reader = Reader { bytes: packet, at: 0 };
+        reader.take(SESSION_LEN)?;
+        let sequence = reader.u64()?;
+        let count = reader.u16()? as u64;
+        if sequence > self.sequence {
+            return Err(ItchError::SequenceGap {
+                expected: self.sequence,
+                found: sequence,
+            });
+        }
+
+        let mut messages = vec![];
+        for message_sequence in sequence..sequence + count {
+            let len = reader.u16()? as usize;
+            let bytes = reader.take(len)?;
+            if message_sequence >= self.sequence {
+                messages.push(ItchMessage::read(self.fixed_point, bytes)?);
+            }
+        }
+        self.sequence = self.sequence.max(sequence + count);
+        Ok(messages)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ItchError {
+    #[error("stock too long for 8 bytes! {0}")]
+    StockTooLong(CompactString),
+    #[error("not representable in the fixed point of the feed! {0}")]
+    NotRepresentable(Decimal),
+    #[error("truncated message (expected={}, found={})", .expected, .found)]
+    Truncated { expected: usize, found: usize },
+    #[error("unknown message type! {0}")]
+    UnknownMessageType(u8),
+    #[error("invalid side! {0}")]
+    InvalidSide(u8),
+    #[error("sequence gap (expected={}, found={})", .expected, .found)]
+    SequenceGap { expected: u64, found: u64 },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::{
+        clock::HOUR,
+        engine::Engine,
+        market_data::Granularity,
+        order::{
+            util::{self, DEFAULT_PAIR, DEFAULT_SYMBOL},
+            OrderRequest,
+        },
+    };
+
+    fn create(order_id: u64, side: OrderSide, quantity: u32, price: u32) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(Some(price.into()))
+            .quantity(quantity)
+            .build()
+    }
+
+    #[rstest]
+    fn encode_the_order_stream() {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        let orders = engine.subscribe(Granularity::Order);
+        for order_request in [
+            create(1, OrderSide::Ask, 10, 15),
+            create(2, OrderSide::Ask, 5, 16),
+            create(3, OrderSide::Bid, 12, 16),
+            util::cancel(2),
+        ] {
+            engine.process(order_request).unwrap();
+        }
+        let events: Vec<MarketDataEvent> = orders.try_iter().collect();
+
+        let mut encoder = ItchEncoder::new(DEFAULT_PAIR, 7).unwrap();
+        let at = 3 * DAY + 9 * HOUR;
+        let packet = encoder.packet(&events, at).unwrap().unwrap();
+        assert_eq!(encoder.next_sequence(), 6);
+
+        // order 1 is filled and gone, order 2 loses 2 then is cancelled, order 3 never rests
+        let mut decoder = ItchDecoder::default();
+        let messages = decoder.decode(&packet).unwrap();
+        let bodies: Vec<&ItchBody> = messages.iter().map(|message| &message.body).collect();
+        assert!(matches!(
+            bodies[..],
+            [
+                ItchBody::AddOrder { order_ref: 1, .. },
+                ItchBody::AddOrder { order_ref: 2, .. },
+                ItchBody::OrderExecuted { order_ref: 1, .. },
+                ItchBody::OrderExecuted { order_ref: 2, .. },
+                ItchBody::OrderDelete { order_ref: 2 },
+            ]
+        ));
+        assert_eq!(
+            *bodies[1],
+            ItchBody::AddOrder {
+                order_ref: 2,
+                side: OrderSide::Ask,
+                shares: 5.into(),
+                stock: DEFAULT_PAIR.into(),
+                price: 16.into(),
+            }
+        );
+        assert_eq!(messages[0].stock_locate, 7);
+        assert_eq!(messages[0].timestamp, 9 * HOUR * 1_000_000);
+
+        // the other feed repeats it, then a packet is lost
+        assert!(decoder.decode(&packet).unwrap().is_empty());
+        let bust = MarketDataEvent {
+            sequence: 7,
+            event: BookEvent::Bust { trade_id: 1.into() },
+        };
+        encoder.packet(&[bust], at).unwrap();
+        let packet = encoder.packet(&[bust], at).unwrap().unwrap();
+        assert_eq!(
+            decoder.decode(&packet),
+            Err(ItchError::SequenceGap { expected: 6, found: 7 })
+        );
+        decoder.reset(7);
+        assert_eq!(
+            decoder.decode(&packet[..PACKET_HEADER_LEN + 4]),
+            Err(ItchError::Truncated {
+                expected: PACKET_HEADER_LEN + 2 + HEADER_LEN + 8,
+                found: PACKET_HEADER_LEN + 4
+            })
+        );
+        assert_eq!(
+            decoder.decode(&packet).unwrap()[0].body,
+            ItchBody::BrokenTrade { match_number: 1 }
+        );
+    }
+
+    #[rstest]
+    fn fixed_point_on_the_wire() {
+        let message = ItchMessage {
+            stock_locate: 1,
+            timestamp: 42,
+            body: ItchBody::Trade {
+                order_ref: 0,
+                side: OrderSide::Bid,
+                shares: OrderQuantity::new(25, 1),
+                stock: "ETH".into(),
+                price: OrderPrice::new(150_125, 4),
+                match_number: 3,
+            },
+        };
+        let mut buf = vec![];
+        assert_eq!(
+            message.write(FixedPoint::default(), &mut buf),
+            Err(ItchError::NotRepresentable(Decimal::new(25, 1)))
+        );
+
+        let fixed_point = FixedPoint {
+            price_decimals: 4,
+            quantity_decimals: 2,
+        };
+        buf.clear();
+        message.write(fixed_point, &mut buf).unwrap();
+        assert_eq!(buf.len(), 52);
+        assert_eq!(ItchMessage::read(fixed_point, &buf), Ok(message));
+        assert_eq!(
+            ItchMessage::read(fixed_point, &[b'Z'; HEADER_LEN]),
+            Err(ItchError::UnknownMessageType(b'Z'))
+        );
+    }
+}
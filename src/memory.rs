@@ -0,0 +1,239 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Timestamp;
+
+// what the engine keeps in memory, in number of objects
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Subsystem {
+    Orders,         // resting in the books, auction orders included
+    Histories,      // every order accepted, kept with its fills for the status queries
+    Trades,         // buffered by the books
+    HeldTrades,     // not on the feed yet
+    Expiries,       // good-til-date orders by expiry, gone orders until their expiry
+    Queued,         // requests received during a halt
+    JournalEntries, // in the journal file, since its last compaction
+    Subscriptions,  // market data subscribers still listening
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 8] = [
+        Subsystem::Orders,
+        Subsystem::Histories,
+        Subsystem::Trades,
+        Subsystem::HeldTrades,
+        Subsystem::Expiries,
+        Subsystem::Queued,
+        Subsystem::JournalEntries,
+        Subsystem::Subscriptions,
+    ];
+}
+
+impl Display for Subsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subsystem::Orders => write!(f, "ORDERS"),
+            Subsystem::Histories => write!(f, "HISTORIES"),
+            Subsystem::Trades => write!(f, "TRADES"),
+            Subsystem::HeldTrades => write!(f, "HELDTRADES"),
+            Subsystem::Expiries => write!(f, "EXPIRIES"),
+            Subsystem::Queued => write!(f, "QUEUED"),
+            Subsystem::JournalEntries => write!(f, "JOURNALENTRIES"),
+            Subsystem::Subscriptions => write!(f, "SUBSCRIPTIONS"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AlertKind {
+    AboveLimit { limit: usize },
+    // never went down over the whole window and grew by at least the minimum growth since `since`
+    SteadyGrowth { from: usize, since: Timestamp },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MemoryAlert {
+    pub subsystem: Subsystem,
+    pub count: usize,
+    pub at: Timestamp,
+    pub kind: AlertKind,
+}
+
+// memory stability over long runs (weeks): samples what every subsystem retains at most once per interval and raises an
+// alert when a count goes above its limit or keeps growing over the last `window` samples, the way a leak does. An alert
+// is raised once, then again only after its condition cleared
+#[derive(Clone, Debug)]
+pub struct MemoryMonitor {
+    interval: Timestamp,
+    window: usize,
+    min_growth: usize,
+    limits: IndexMap<Subsystem, usize>,
+    samples: IndexMap<Subsystem, VecDeque<(Timestamp, usize)>>,
+    last_sample: Option<Timestamp>,
+    above_limit: IndexSet<Subsystem>,
+    growing: IndexSet<Subsystem>,
+}
+
+impl MemoryMonitor {
+    // window in number of samples, e.g. a day of never shrinking with an interval of an hour and a window of 24
+    pub fn new(interval: Timestamp, window: usize, min_growth: usize) -> Self {
+        Self {
+            interval,
+            window: window.max(1),
+            min_growth: min_growth.max(1),
+            limits: IndexMap::new(),
+            samples: IndexMap::new(),
+            last_sample: None,
+            above_limit: IndexSet::new(),
+            growing: IndexSet::new(),
+        }
+    }
+
+    pub fn with_limit(mut self, subsystem: Subsystem, limit: usize) -> Self {
+        self.limits.insert(subsystem, limit);
+        self
+    }
+
+    #[inline]
+    pub fn is_due(&self, now: Timestamp) -> bool {
+        self.last_sample
+            .is_none_or(|last_sample| now >= last_sample + self.interval)
+    }
+
+    // the samples kept of a subsystem, oldest first
+    #[inline]
+    pub fn samples(&self, subsystem: Subsystem) -> impl Iterator<Item = &(Timestamp, usize)> {
+        self.samples.get(&subsystem).into_iter().flatten()
+    }
+
+    // the last count sampled of a subsystem
+    #[inline]
+    pub fn last(&self, subsystem: Subsystem) -> Option<usize> {
+        self.samples.get(&subsystem)?.back().map(|(_, count)| *count)
+    }
+
+    // returns the alerts raised by this sample, nothing when it is not due
+    pub fn sample(&mut self, now: Timestamp, counts: &[(Subsystem, usize)]) -> Vec<MemoryAlert> {
+        if !self.is_due(now) {
+            return vec![];
+        }
+        self.last_sample = Some(now);
+
+        let mut alerts = vec![];
+        for &(subsystem, count) in counts {
+            let samples = self.samples.entry(subsystem).or_default();
+            samples.push_back((now, count));
+            // one more than the window: the window is the number of steps between samples
+            if samples.len() > self.window + 1 {
+                samples.pop_front();
+            }
+
+            match self.limits.get(&subsystem) {
+                Some(&limit) if count > limit => {
+                    if self.above_limit.insert(subsystem) {
+                        alerts.push(MemoryAlert {
+                            subsystem,
+                            count,
+                            at: now,
+                            kind: AlertKind::AboveLimit { limit },
+                        });
+                    }
+                }
+                _ => {
+                    self.above_limit.shift_remove(&subsystem);
+                }
+            }
+
+            let growth = (samples.len() > self.window)
+                .then(|| samples.front().copied())
+                .flatten()
+                .filter(|&(_, from)| {
+                    samples
+                        .iter()
+                        .zip(samples.iter().skip(1))
+                        .all(|((_, before), (_, after))| after >= before)
+                        && count >= from + self.min_growth
+                });
+            match growth {
+                Some((since, from)) => {
+                    if self.growing.insert(subsystem) {
+                        alerts.push(MemoryAlert {
+                            subsystem,
+                            count,
+                            at: now,
+                            kind: AlertKind::SteadyGrowth { from, since },
+                        });
+                    }
+                }
+                None => {
+                    self.growing.shift_remove(&subsystem);
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::clock::{HOUR, MINUTE};
+
+    #[fixture]
+    fn monitor() -> MemoryMonitor {
+        MemoryMonitor::new(HOUR, 3, 10).with_limit(Subsystem::Orders, 100)
+    }
+
+    #[rstest]
+    fn alert_above_the_limit(mut monitor: MemoryMonitor) {
+        let alerts = monitor.sample(0, &[(Subsystem::Orders, 101)]);
+        assert_eq!(
+            alerts,
+            vec![MemoryAlert {
+                subsystem: Subsystem::Orders,
+                count: 101,
+                at: 0,
+                kind: AlertKind::AboveLimit { limit: 100 }
+            }]
+        );
+        // not due yet, then raised once until it clears
+        assert!(monitor.sample(30 * MINUTE, &[(Subsystem::Orders, 50)]).is_empty());
+        assert_eq!(monitor.last(Subsystem::Orders), Some(101));
+        assert!(monitor.sample(HOUR, &[(Subsystem::Orders, 120)]).is_empty());
+        assert!(monitor.sample(2 * HOUR, &[(Subsystem::Orders, 50)]).is_empty());
+        assert_eq!(monitor.sample(3 * HOUR, &[(Subsystem::Orders, 101)]).len(), 1);
+    }
+
+    #[rstest]
+    fn alert_on_steady_growth(mut monitor: MemoryMonitor) {
+        // a sawtooth is not a leak
+        for (hour, count) in [0, 20, 5, 30, 10].into_iter().enumerate() {
+            let alerts = monitor.sample(hour as u64 * HOUR, &[(Subsystem::Trades, count)]);
+            assert!(alerts.is_empty());
+        }
+
+        // never going down over the window: 10 at 4h, then 10, 15, 21
+        assert!(monitor.sample(5 * HOUR, &[(Subsystem::Trades, 10)]).is_empty());
+        assert!(monitor.sample(6 * HOUR, &[(Subsystem::Trades, 15)]).is_empty());
+        let alerts = monitor.sample(7 * HOUR, &[(Subsystem::Trades, 21)]);
+        assert_eq!(
+            alerts,
+            vec![MemoryAlert {
+                subsystem: Subsystem::Trades,
+                count: 21,
+                at: 7 * HOUR,
+                kind: AlertKind::SteadyGrowth {
+                    from: 10,
+                    since: 4 * HOUR
+                }
+            }]
+        );
+        assert!(monitor.sample(8 * HOUR, &[(Subsystem::Trades, 40)]).is_empty());
+        assert_eq!(monitor.samples(Subsystem::Trades).count(), 4);
+    }
+}
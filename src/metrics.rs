@@ -0,0 +1,283 @@
+use std::fmt::Display;
+
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::clock::{Timestamp, HOUR, MINUTE, SECOND};
+
+pub mod grafana;
+#[cfg(feature = "metrics")]
+pub mod prometheus;
+
+// 5 minutes by the second, 4 hours by the minute, 2 days by the hour
+pub const DEFAULT_RESOLUTIONS: [(Timestamp, usize); 3] = [(SECOND, 300), (MINUTE, 240), (HOUR, 48)];
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Metric {
+    Throughput, // requests processed, the sum of a bucket is its count
+    BestBid,
+    BestAsk,
+    Spread,
+    BidDepth, // quantity at the touch
+    AskDepth,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 6] = [
+        Metric::Throughput,
+        Metric::BestBid,
+        Metric::BestAsk,
+        Metric::Spread,
+        Metric::BidDepth,
+        Metric::AskDepth,
+    ];
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::Throughput => write!(f, "THROUGHPUT"),
+            Metric::BestBid => write!(f, "BESTBID"),
+            Metric::BestAsk => write!(f, "BESTASK"),
+            Metric::Spread => write!(f, "SPREAD"),
+            Metric::BidDepth => write!(f, "BIDDEPTH"),
+            Metric::AskDepth => write!(f, "ASKDEPTH"),
+        }
+    }
+}
+
+// the values recorded within one bucket of a resolution
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bucket {
+    pub start: Timestamp,
+    pub count: u64,
+    pub sum: Decimal,
+    pub min: Decimal,
+    pub max: Decimal,
+    pub last: Decimal,
+}
+
+impl Bucket {
+    fn new(start: Timestamp, value: Decimal) -> Self {
+        Self {
+            start,
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+            last: value,
+        }
+    }
+
+    fn add(&mut self, value: Decimal) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.last = value;
+    }
+
+    #[inline]
+    pub fn mean(&self) -> Decimal {
+        self.sum / Decimal::from(self.count)
+    }
+}
+
+// fixed number of buckets, allocated up front: the bucket of a time is at its slot modulo the capacity, what was there
+// before (a full turn older) is overwritten
+#[derive(Clone, Debug)]
+struct Ring {
+    resolution: Timestamp,
+    buckets: Vec<Option<Bucket>>,
+}
+
+impl Ring {
+    fn new(resolution: Timestamp, capacity: usize) -> Self {
+        Self {
+            resolution,
+            buckets: vec![None; capacity],
+        }
+    }
+
+    fn record(&mut self, at: Timestamp, value: Decimal) {
+        let start = at - at % self.resolution;
+        let slot = (at / self.resolution) as usize % self.buckets.len();
+        match &mut self.buckets[slot] {
+            Some(bucket) if bucket.start == start => bucket.add(value),
+            // a value older than the bucket there is too old to be kept
+            Some(bucket) if bucket.start > start => {}
+            bucket => *bucket = Some(Bucket::new(start, value)),
+        }
+    }
+
+    // oldest bucket start still kept, as of the last value recorded
+    fn oldest(&self) -> Option<Timestamp> {
+        let newest = self.buckets.iter().flatten().map(|bucket| bucket.start).max()?;
+        let span = self.resolution * (self.buckets.len() as u64 - 1);
+        Some(newest.saturating_sub(span))
+    }
+
+    fn range(&self, from: Timestamp, to: Timestamp) -> Vec<Bucket> {
+        let oldest = self.oldest().unwrap_or_default();
+        let mut buckets: Vec<Bucket> = self
+            .buckets
+            .iter()
+            .flatten()
+            .filter(|bucket| bucket.start >= oldest && bucket.start + self.resolution > from && bucket.start < to)
+            .copied()
+            .collect();
+        buckets.sort_unstable_by_key(|bucket| bucket.start);
+        buckets
+    }
+}
+
+// what the admin API asks for, the finest resolution still covering `from` is used unless one is given
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MetricsQuery {
+    pub metric: Metric,
+    pub from: Timestamp,
+    pub to: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Timestamp>,
+}
+
+// short-term history of the metrics of a market, every value goes to each resolution (the coarser ones keep it
+// downsampled for longer), the memory used is fixed by the resolutions
+#[derive(Clone, Debug)]
+pub struct MetricsStore {
+    resolutions: Vec<(Timestamp, usize)>,
+    series: IndexMap<Metric, Vec<Ring>>, // in the order of the resolutions
+}
+
+impl MetricsStore {
+    pub fn new(resolutions: impl IntoIterator<Item = (Timestamp, usize)>) -> Result<Self, MetricsError> {
+        let mut resolutions: Vec<(Timestamp, usize)> = resolutions.into_iter().collect();
+        if let Some((resolution, capacity)) = resolutions
+            .iter()
+            .find(|(resolution, capacity)| *resolution == 0 || *capacity == 0)
+        {
+            return Err(MetricsError::InvalidResolution {
+                resolution: *resolution,
+                capacity: *capacity,
+            });
+        }
+        resolutions.sort_unstable();
+        Ok(Self {
+            resolutions,
+            series: IndexMap::new(),
+        })
+    }
+
+    #[inline]
+    pub fn resolutions(&self) -> &[(Timestamp, usize)] {
+        &self.resolutions
+    }
+
+    pub fn record(&mut self, metric: Metric, at: Timestamp, value: Decimal) {
+        let resolutions = &self.resolutions;
+        let rings = self.series.entry(metric).or_insert_with(|| {
+            resolutions
+                .iter()
+                .map(|(resolution, capacity)| Ring::new(*resolution, *capacity))
+                .collect()
+        });
+        for ring in rings {
+            ring.record(at, value);
+        }
+    }
+
+    // the buckets overlapping [from, to), oldest first
+    pub fn query(&self, query: &MetricsQuery) -> Result<Vec<Bucket>, MetricsError> {
+        let Some(rings) = self.series.get(&query.metric) else {
+            return Ok(vec![]);
+        };
+        let ring = match query.resolution {
+            Some(resolution) => rings
+                .iter()
+                .find(|ring| ring.resolution == resolution)
+                .ok_or(MetricsError::UnknownResolution(resolution))?,
+            None => rings
+                .iter()
+                .find(|ring| ring.oldest().is_some_and(|oldest| oldest <= query.from))
+                .or(rings.last())
+                .ok_or(MetricsError::UnknownResolution(0))?,
+        };
+        Ok(ring.range(query.from, query.to))
+    }
+}
+
+impl Default for MetricsStore {
+    fn default() -> Self {
+        Self {
+            resolutions: DEFAULT_RESOLUTIONS.to_vec(),
+            series: IndexMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MetricsError {
+    #[error("invalid resolution (resolution={}, capacity={})", .resolution, .capacity)]
+    InvalidResolution { resolution: Timestamp, capacity: usize },
+    #[error("no such resolution! {0}")]
+    UnknownResolution(Timestamp),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn query(from: Timestamp, to: Timestamp, resolution: Option<Timestamp>) -> MetricsQuery {
+        MetricsQuery {
+            metric: Metric::Spread,
+            from,
+            to,
+            resolution,
+        }
+    }
+
+    #[rstest]
+    fn downsample_and_forget() {
+        let mut store = MetricsStore::new([(MINUTE, 5), (SECOND, 3)]).unwrap();
+        for (at, value) in [(0, 1), (500, 3), (SECOND, 2), (4 * SECOND, 5), (4 * SECOND + 1, 1)] {
+            store.record(Metric::Spread, at, value.into());
+        }
+
+        // by the second, only the last 3 are kept
+        let seconds = store.query(&query(0, MINUTE, Some(SECOND))).unwrap();
+        assert_eq!(seconds.len(), 1);
+        assert_eq!(
+            (seconds[0].start, seconds[0].count, seconds[0].max),
+            (4 * SECOND, 2, 5.into())
+        );
+
+        // the same values downsampled, picked as the finest resolution still covering the start
+        let minutes = store.query(&query(0, MINUTE, None)).unwrap();
+        assert_eq!(minutes.len(), 1);
+        let minute = minutes[0];
+        assert_eq!(
+            (minute.count, minute.min, minute.max, minute.last),
+            (5, 1.into(), 5.into(), 1.into())
+        );
+        assert_eq!(minute.mean(), Decimal::new(24, 1));
+        assert_eq!(store.query(&query(3 * SECOND, 5 * SECOND, None)).unwrap(), seconds);
+
+        assert_eq!(
+            store.query(&query(0, MINUTE, Some(HOUR))),
+            Err(MetricsError::UnknownResolution(HOUR))
+        );
+        assert!(store
+            .query(&MetricsQuery {
+                metric: Metric::Throughput,
+                ..query(0, MINUTE, None)
+            })
+            .unwrap()
+            .is_empty());
+        assert!(MetricsStore::new([(SECOND, 0)]).is_err());
+    }
+}
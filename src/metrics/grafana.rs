@@ -0,0 +1,312 @@
+use std::io::{self, BufRead, Write};
+
+use compact_str::{format_compact, CompactString};
+use indexmap::IndexMap;
+use num::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{Metric, MetricsError, MetricsQuery, MetricsStore};
+use crate::{
+    clock::{Timestamp, DAY, HOUR, MINUTE, SECOND},
+    engine::Engine,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: CompactString, // ISO 8601 in UTC as sent by Grafana, or millis
+    pub to: CompactString,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueryTarget {
+    pub target: CompactString, // pair and metric, e.g. "ETH/USDT:SPREAD"
+}
+
+// the body of a /query of the JSON datasource plugin, the fields it sends besides are ignored
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GrafanaQuery {
+    pub range: TimeRange,
+    pub targets: Vec<QueryTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_data_points: Option<usize>,
+}
+
+// datapoints are [value, millis], oldest first
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TimeSeries {
+    pub target: CompactString,
+    pub datapoints: Vec<(f64, Timestamp)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TargetOption {
+    pub label: CompactString,
+    pub value: CompactString,
+}
+
+// the metrics time-series of the engines (the ones keeping them) behind the endpoints of Grafana's JSON datasource:
+// `GET /` to test the connection, `POST /search` or `POST /metrics` for the targets and `POST /query` for the series
+pub struct GrafanaDatasource<'a> {
+    stores: IndexMap<&'a str, &'a MetricsStore>, // by pair
+}
+
+impl<'a> GrafanaDatasource<'a> {
+    pub fn new(engines: impl IntoIterator<Item = &'a Engine>) -> Self {
+        let stores = engines
+            .into_iter()
+            .filter_map(|engine| Some((engine.pair().as_str(), engine.metrics()?)))
+            .collect();
+        Self { stores }
+    }
+
+    pub fn targets(&self) -> Vec<CompactString> {
+        self.stores
+            .keys()
+            .flat_map(|pair| Metric::ALL.iter().map(move |metric| format_compact!("{pair}:{metric}")))
+            .collect()
+    }
+
+    // throughput is plotted as the requests of each bucket, the other metrics as their mean over it
+    pub fn query(&self, query: &GrafanaQuery) -> Result<Vec<TimeSeries>, GrafanaError> {
+        let from = parse_time(&query.range.from)?;
+        let to = parse_time(&query.range.to)?;
+        query
+            .targets
+            .iter()
+            .map(|QueryTarget { target }| {
+                let (store, metric) = self.target(target)?;
+                let buckets = store.query(&MetricsQuery {
+                    metric,
+                    from,
+                    to,
+                    resolution: None,
+                })?;
+                // the most recent ones when there are more than the panel can show
+                let skipped = query.max_data_points.map_or(0, |max| buckets.len().saturating_sub(max));
+                let datapoints = buckets[skipped..]
+                    .iter()
+                    .map(|bucket| {
+                        let value = match metric {
+                            Metric::Throughput => bucket.sum,
+                            _ => bucket.mean(),
+                        };
+                        (value.to_f64().unwrap_or_default(), bucket.start)
+                    })
+                    .collect();
+                Ok(TimeSeries {
+                    target: target.clone(),
+                    datapoints,
+                })
+            })
+            .collect()
+    }
+
+    fn target(&self, target: &str) -> Result<(&MetricsStore, Metric), GrafanaError> {
+        let unknown = || GrafanaError::UnknownTarget(target.into());
+        let (pair, name) = target.rsplit_once(':').ok_or_else(unknown)?;
+        let store = self.stores.get(pair).ok_or_else(unknown)?;
+        let metric = Metric::ALL
+            .into_iter()
+            .find(|metric| metric.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(unknown)?;
+        Ok((store, metric))
+    }
+
+    // status and JSON body of the response
+    pub fn handle(&self, method: &str, path: &str, body: &str) -> (u16, String) {
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        let response = match (method, path) {
+            ("GET", "/") => Ok(serde_json::json!({ "status": "OK" })),
+            ("POST", "/search") => Ok(serde_json::json!(self.targets())),
+            ("POST", "/metrics") => {
+                let options: Vec<TargetOption> = self
+                    .targets()
+                    .into_iter()
+                    .map(|target| TargetOption {
+                        label: target.clone(),
+                        value: target,
+                    })
+                    .collect();
+                Ok(serde_json::json!(options))
+            }
+            ("POST", "/query") => serde_json::from_str(body)
+                .map_err(GrafanaError::from)
+                .and_then(|query| self.query(&query))
+                .map(|series| serde_json::json!(series)),
+            _ => return (404, serde_json::json!({ "error": "no such endpoint" }).to_string()),
+        };
+        match response {
+            Ok(response) => (200, response.to_string()),
+            Err(error) => (400, serde_json::json!({ "error": error.to_string() }).to_string()),
+        }
+    }
+
+    // one HTTP/1.1 request and its response, the connection is closed after it
+    pub fn serve(&self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap_or_default();
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+
+        let (status, body) = self.handle(method, path, &String::from_utf8_lossy(&body));
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            _ => "Not Found",
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        writer.flush()
+    }
+}
+
+// e.g. "2024-01-31T09:30:00.250Z", Grafana sends the range in UTC
+fn parse_time(time: &str) -> Result<Timestamp, GrafanaError> {
+    if let Ok(millis) = time.parse() {
+        return Ok(millis);
+    }
+    let invalid = || GrafanaError::InvalidTime(time.into());
+    let numbers = |text: &str, separator: char| -> Option<Vec<u64>> {
+        text.split(separator).map(|number| number.parse().ok()).collect()
+    };
+
+    let (date, clock) = time
+        .strip_suffix('Z')
+        .and_then(|time| time.split_once('T'))
+        .ok_or_else(invalid)?;
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, "0"));
+    let digits = &fraction[..fraction.len().min(3)];
+    let millis = digits.parse::<u64>().map_err(|_| invalid())? * 10_u64.pow(3 - digits.len() as u32);
+    let (Some([year, month, day]), Some([hours, minutes, seconds])) = (
+        numbers(date, '-').and_then(|numbers| <[u64; 3]>::try_from(numbers).ok()),
+        numbers(clock, ':').and_then(|numbers| <[u64; 3]>::try_from(numbers).ok()),
+    ) else {
+        return Err(invalid());
+    };
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 59
+    {
+        return Err(invalid());
+    }
+
+    // days since the epoch of a proleptic Gregorian date, years starting in March (the leap day is the last one)
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year / 400, year % 400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).checked_sub(719_468).ok_or_else(invalid)?;
+    Ok(days * DAY + hours * HOUR + minutes * MINUTE + seconds * SECOND + millis)
+}
+
+#[derive(Debug, Error)]
+pub enum GrafanaError {
+    #[error("invalid query! {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid time! {0}")]
+    InvalidTime(CompactString),
+    #[error("no such target! {0}")]
+    UnknownTarget(CompactString),
+    #[error(transparent)]
+    MetricsError(#[from] MetricsError),
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        order::{
+            util::{self, DEFAULT_SYMBOL},
+            OrderSide,
+        },
+    };
+
+    const NEW_YEAR: Timestamp = 1_704_067_200_000; // 2024-01-01T00:00:00Z
+
+    #[rstest]
+    #[case("2024-01-01T00:00:00Z", Some(NEW_YEAR))]
+    #[case("2024-03-01T09:30:15.25Z", Some(NEW_YEAR + 60 * DAY + 9 * HOUR + 30 * MINUTE + 15 * SECOND + 250))]
+    #[case("1970-01-01T00:00:00.000Z", Some(0))]
+    #[case("1704067200000", Some(NEW_YEAR))]
+    #[case("2024-01-01T00:00:00+01:00", None)]
+    #[case("2024-13-01T00:00:00Z", None)]
+    #[case("now-6h", None)]
+    fn parse_the_range(#[case] time: &str, #[case] expected: Option<Timestamp>) {
+        assert_eq!(parse_time(time).ok(), expected);
+    }
+
+    #[rstest]
+    fn serve_the_json_datasource() {
+        let clock = ManualClock::new(NEW_YEAR);
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_metrics(MetricsStore::default(), clock.clone());
+        for (order_id, price) in [(1, 14), (2, 16)] {
+            engine
+                .process(
+                    util::create(order_id, OrderSide::Bid)
+                        .limit_price(Some(price.into()))
+                        .quantity(1)
+                        .build(),
+                )
+                .unwrap();
+        }
+        let datasource = GrafanaDatasource::new([&engine]);
+
+        let (status, body) = datasource.handle("POST", "/search", "{}");
+        assert_eq!(status, 200);
+        assert!(body.contains("\"ETH/USDT:BESTBID\""));
+
+        let query = r#"{"range":{"from":"2024-01-01T00:00:00Z","to":"2024-01-01T00:01:00Z"},
+            "targets":[{"target":"ETH/USDT:BESTBID","refId":"A"},{"target":"ETH/USDT:THROUGHPUT"}]}"#;
+        let request = format!(
+            "POST /query HTTP/1.1\r\nHost: merx\r\nContent-Length: {}\r\n\r\n{query}",
+            query.len()
+        );
+        let mut response = vec![];
+        datasource.serve(Cursor::new(request), &mut response).unwrap();
+        let response = String::from_utf8(response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        let series: Vec<TimeSeries> = serde_json::from_str(body).unwrap();
+        assert_eq!(series[0].datapoints, vec![(15.0, NEW_YEAR)]);
+        assert_eq!(series[1].datapoints, vec![(2.0, NEW_YEAR)]);
+
+        let (status, body) = datasource.handle(
+            "POST",
+            "/query",
+            r#"{"range":{"from":"0","to":"1"},"targets":[{"target":"BTC/USDT:SPREAD"}]}"#,
+        );
+        assert_eq!(
+            (status, body.as_str()),
+            (400, r#"{"error":"no such target! BTC/USDT:SPREAD"}"#)
+        );
+        assert_eq!(datasource.handle("GET", "/annotations", "").0, 404);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Write;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    engine::{journal::JournalEvent, Engine},
+    order::OrderSide,
+};
+
+// wide enough for the latencies (micros) as well as for the fills of an order
+pub const DEFAULT_BOUNDS: [u64; 14] = [0, 1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+// cumulative on scrape only, each observation counts once in the bucket of its upper bound
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bounds: &'static [u64],
+    counts: Vec<u64>, // the last one is past every bound (+Inf)
+    sum: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [u64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0,
+        }
+    }
+
+    #[inline]
+    pub fn observe(&mut self, value: u64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    #[inline]
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(&DEFAULT_BOUNDS)
+    }
+}
+
+// what the engine counts as it goes (see Engine::counters)
+#[derive(Clone, Debug, Default)]
+pub struct EngineCounters {
+    pub accepted: u64,
+    pub rejected: u64, // by a pre-trade check or by the book
+    pub cancelled: u64,
+    pub expired: u64,
+    pub trades: u64,
+    pub match_latency: Histogram, // micros to execute a request against the book
+}
+
+impl EngineCounters {
+    pub(crate) fn record(&mut self, event: &JournalEvent) {
+        match event {
+            JournalEvent::Created { .. } => self.accepted += 1,
+            JournalEvent::Cancelled { .. } => self.cancelled += 1,
+            JournalEvent::CancelledAll { order_ids, .. } => self.cancelled += order_ids.len() as u64,
+            JournalEvent::Expired { .. } => self.expired += 1,
+            JournalEvent::Rejected { .. } => self.rejected += 1,
+            JournalEvent::Busted { .. } => {}
+            JournalEvent::Quoted { cancelled, created, .. } => {
+                self.accepted += created.len() as u64;
+                self.cancelled += cancelled.len() as u64;
+            }
+        }
+    }
+}
+
+type Counter = fn(&EngineCounters) -> u64;
+
+// the metrics of every engine in the Prometheus text exposition format, labelled by pair
+pub fn scrape<'a>(engines: impl IntoIterator<Item = &'a Engine>) -> String {
+    let engines: Vec<&Engine> = engines.into_iter().collect();
+    let mut text = String::new();
+
+    let counters: [(&str, &str, Counter); 5] = [
+        ("merx_orders_accepted_total", "orders accepted", |counters| {
+            counters.accepted
+        }),
+        ("merx_orders_rejected_total", "orders rejected", |counters| {
+            counters.rejected
+        }),
+        ("merx_orders_cancelled_total", "orders cancelled", |counters| {
+            counters.cancelled
+        }),
+        ("merx_orders_expired_total", "orders expired", |counters| {
+            counters.expired
+        }),
+        ("merx_trades_total", "trades", |counters| counters.trades),
+    ];
+    for (name, help, value) in counters {
+        header(&mut text, name, help, "counter");
+        for engine in &engines {
+            let _ = writeln!(
+                text,
+                "{name}{{pair=\"{}\"}} {}",
+                engine.pair(),
+                value(engine.counters())
+            );
+        }
+    }
+
+    header(&mut text, "merx_resting_orders", "orders resting in the book", "gauge");
+    for engine in &engines {
+        let resting = engine.orderbook().resting_count() + engine.odd_lots().resting_count();
+        let _ = writeln!(text, "merx_resting_orders{{pair=\"{}\"}} {resting}", engine.pair());
+    }
+    header(
+        &mut text,
+        "merx_book_depth",
+        "quantity resting on each side of the book",
+        "gauge",
+    );
+    for engine in &engines {
+        let depth = engine.orderbook().depth(usize::MAX);
+        for (side, levels) in [(OrderSide::Bid, depth.bids), (OrderSide::Ask, depth.asks)] {
+            let quantity: Decimal = levels.iter().map(|(_, quantity, _)| quantity.value()).sum();
+            let _ = writeln!(
+                text,
+                "merx_book_depth{{pair=\"{}\",side=\"{side}\"}} {quantity}",
+                engine.pair()
+            );
+        }
+    }
+
+    header(
+        &mut text,
+        "merx_match_latency_micros",
+        "time to execute a request",
+        "histogram",
+    );
+    for engine in &engines {
+        histogram(
+            &mut text,
+            "merx_match_latency_micros",
+            engine.pair().as_str(),
+            &engine.counters().match_latency,
+        );
+    }
+    header(
+        &mut text,
+        "merx_fills_per_order",
+        "trades of an incoming order",
+        "histogram",
+    );
+    for engine in &engines {
+        histogram(
+            &mut text,
+            "merx_fills_per_order",
+            engine.pair().as_str(),
+            engine.orderbook().fills_per_order(),
+        );
+    }
+    text
+}
+
+fn header(text: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {kind}");
+}
+
+fn histogram(text: &mut String, name: &str, pair: &str, histogram: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        cumulative += count;
+        let _ = writeln!(text, "{name}_bucket{{pair=\"{pair}\",le=\"{bound}\"}} {cumulative}");
+    }
+    let _ = writeln!(
+        text,
+        "{name}_bucket{{pair=\"{pair}\",le=\"+Inf\"}} {}",
+        histogram.count()
+    );
+    let _ = writeln!(text, "{name}_sum{{pair=\"{pair}\"}} {}", histogram.sum);
+    let _ = writeln!(text, "{name}_count{{pair=\"{pair}\"}} {}", histogram.count());
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{
+        util::{self, DEFAULT_SYMBOL},
+        OrderRequest,
+    };
+
+    fn create(order_id: u64, side: OrderSide) -> OrderRequest {
+        util::create(order_id, side).quantity(2).build()
+    }
+
+    #[rstest]
+    fn scrape_the_engines() {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        engine.process(create(1, OrderSide::Ask)).unwrap();
+        engine.process(create(2, OrderSide::Ask)).unwrap();
+        engine.process(create(3, OrderSide::Bid)).unwrap();
+        engine.process(create(3, OrderSide::Bid)).unwrap();
+
+        let text = scrape([&engine]);
+        for line in [
+            "merx_orders_accepted_total{pair=\"ETH/USDT\"} 3",
+            "merx_orders_rejected_total{pair=\"ETH/USDT\"} 1",
+            "merx_trades_total{pair=\"ETH/USDT\"} 1",
+            "merx_resting_orders{pair=\"ETH/USDT\"} 1",
+            "merx_book_depth{pair=\"ETH/USDT\",side=\"SELL\"} 2",
+            "merx_match_latency_micros_count{pair=\"ETH/USDT\"} 4",
+            "merx_fills_per_order_bucket{pair=\"ETH/USDT\",le=\"0\"} 2",
+            "merx_fills_per_order_bucket{pair=\"ETH/USDT\",le=\"1\"} 3",
+        ] {
+            assert!(
+                text.lines().any(|candidate| candidate == line),
+                "{line} missing from\n{text}"
+            );
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::Display;
+
+use compact_str::{format_compact, CompactString};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::Timestamp,
+    engine::Engine,
+    ledger::{Ledger, LedgerError, PostingKind, CLEARING_ACCOUNT},
+    order::{OrderPrice, OrderQuantity},
+    position::PositionBook,
+    symbol::{Asset, Symbol, SymbolError},
+};
+
+pub mod vol;
+
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OptionKind {
+    Call,
+    Put,
+}
+
+// options are cash settled in the quote asset of the underlying pair
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OptionContract {
+    pub base: Asset,
+    pub quote: Asset,
+    pub strike: OrderPrice,
+    pub expiry: Timestamp,
+    pub kind: OptionKind,
+}
+
+impl OptionContract {
+    // e.g. ETH-1760000000000-2000-C/USDT, the quote asset stays at the end like in any other pair
+    // too long a strike makes too long an instrument code
+    pub fn symbol(&self) -> Result<Symbol, SymbolError> {
+        Ok(Symbol::new(self.instrument().parse()?, self.quote.clone()))
+    }
+
+    fn instrument(&self) -> CompactString {
+        let kind = match self.kind {
+            OptionKind::Call => "C",
+            OptionKind::Put => "P",
+        };
+        format_compact!("{}-{}-{}-{}", self.base, self.expiry, self.strike, kind)
+    }
+
+    // the book of an option is a regular book, only the settlement at expiry is specific
+    pub fn engine(&self) -> Result<Engine, SymbolError> {
+        Ok(Engine::new(self.symbol()?))
+    }
+
+    #[inline]
+    pub fn is_expired(&self, now: Timestamp) -> bool {
+        now >= self.expiry
+    }
+
+    #[inline]
+    pub fn intrinsic_value(&self, settlement_price: OrderPrice) -> OrderPrice {
+        let value = match self.kind {
+            OptionKind::Call => settlement_price.checked_sub(self.strike),
+            OptionKind::Put => self.strike.checked_sub(settlement_price),
+        };
+        value.unwrap_or_default()
+    }
+
+    // holders (long) are exercised and writers (short) are assigned, every position is closed at the intrinsic value
+    pub fn exercise(
+        &self,
+        positions: &mut PositionBook,
+        ledger: &mut Ledger,
+        settlement_price: OrderPrice,
+        now: Timestamp,
+    ) -> Result<Vec<Exercise>, OptionError> {
+        if !self.is_expired(now) {
+            return Err(OptionError::NotExpired {
+                expiry: self.expiry,
+                now,
+            });
+        }
+
+        let value = self.intrinsic_value(settlement_price);
+        let open: Vec<(CompactString, Decimal)> = positions
+            .iter()
+            .filter(|(_, position)| !position.is_flat())
+            .map(|(account_id, position)| (account_id.clone(), position.size))
+            .collect();
+
+        // writers pay first so the clearing account never runs short
+        let mut exercises = Vec::with_capacity(open.len());
+        for (account_id, size) in open.iter().filter(|(_, size)| *size < Decimal::ZERO) {
+            let amount = *size * value;
+            if amount < Decimal::ZERO {
+                ledger.transfer(
+                    account_id,
+                    CLEARING_ACCOUNT,
+                    self.quote.as_str(),
+                    -amount,
+                    PostingKind::OptionAssignment,
+                )?;
+            }
+            exercises.push(self.close(positions, account_id, *size, amount, value));
+        }
+        for (account_id, size) in open.iter().filter(|(_, size)| *size > Decimal::ZERO) {
+            let amount = *size * value;
+            if amount > Decimal::ZERO {
+                ledger.transfer(
+                    CLEARING_ACCOUNT,
+                    account_id,
+                    self.quote.as_str(),
+                    amount,
+                    PostingKind::OptionExercise,
+                )?;
+            }
+            exercises.push(self.close(positions, account_id, *size, amount, value));
+        }
+
+        Ok(exercises)
+    }
+
+    fn close(
+        &self,
+        positions: &mut PositionBook,
+        account_id: &str,
+        size: Decimal,
+        amount: Decimal,
+        value: OrderPrice,
+    ) -> Exercise {
+        let side = positions.get(account_id).and_then(|position| position.side()).unwrap();
+        let realized_pnl = positions.apply_fill(account_id, !side, OrderQuantity::from_decimal(size.abs()), value);
+        Exercise {
+            account_id: account_id.into(),
+            quantity: size,
+            amount,
+            realized_pnl,
+        }
+    }
+}
+
+impl Display for OptionContract {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.instrument(), self.quote)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Exercise {
+    pub account_id: CompactString,
+    pub quantity: Decimal, // signed: positive = exercised (holder), negative = assigned (writer)
+    pub amount: Decimal,   // cash received (positive) or paid (negative)
+    pub realized_pnl: Decimal, // including the premium paid or received when the position was opened
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum OptionError {
+    #[error("option cannot be exercised before expiry (expiry={}, now={})", .expiry, .now)]
+    NotExpired { expiry: Timestamp, now: Timestamp },
+    #[error("ledger error: {0}")]
+    LedgerError(#[from] LedgerError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::order::OrderSide;
+
+    const EXPIRY: Timestamp = 1_000;
+
+    #[fixture]
+    fn call_2000() -> OptionContract {
+        OptionContract {
+            base: "ETH".parse().unwrap(),
+            quote: "USDT".parse().unwrap(),
+            strike: 2000.into(),
+            expiry: EXPIRY,
+            kind: OptionKind::Call,
+        }
+    }
+
+    // account 1 bought 2 contracts from account 2 and 1 from account 3 at a premium of 50
+    #[fixture]
+    fn positions() -> PositionBook {
+        let mut positions = PositionBook::default();
+        positions.apply_fill("1", OrderSide::Bid, 3.into(), 50.into());
+        positions.apply_fill("2", OrderSide::Ask, 2.into(), 50.into());
+        positions.apply_fill("3", OrderSide::Ask, 1.into(), 50.into());
+        positions
+    }
+
+    #[rstest]
+    fn symbol_keeps_quote_at_the_end(call_2000: OptionContract) {
+        assert_eq!(call_2000.symbol().unwrap(), "ETH-1000-2000-C/USDT");
+        assert_eq!(call_2000.to_string(), "ETH-1000-2000-C/USDT");
+        assert_eq!(call_2000.engine().unwrap().orderbook().peek_top(&OrderSide::Bid), None);
+    }
+
+    #[rstest]
+    fn intrinsic_value(call_2000: OptionContract) {
+        let put_2000 = OptionContract {
+            kind: OptionKind::Put,
+            ..call_2000.clone()
+        };
+        assert_eq!(call_2000.intrinsic_value(2100.into()), OrderPrice::from(100));
+        assert_eq!(call_2000.intrinsic_value(1900.into()), OrderPrice::ZERO);
+        assert_eq!(put_2000.intrinsic_value(1900.into()), OrderPrice::from(100));
+        assert_eq!(put_2000.intrinsic_value(2100.into()), OrderPrice::ZERO);
+    }
+
+    #[rstest]
+    fn exercise_in_the_money(call_2000: OptionContract, mut positions: PositionBook) {
+        let mut ledger = Ledger::default();
+        let exercises = call_2000
+            .exercise(&mut positions, &mut ledger, 2100.into(), EXPIRY)
+            .unwrap();
+        assert_eq!(exercises.len(), 3);
+
+        // the holder receives the intrinsic value of 3 contracts, the writers pay their share
+        assert_eq!(ledger.balance("1", "USDT"), Decimal::from(300));
+        assert_eq!(ledger.balance("2", "USDT"), Decimal::from(-200));
+        assert_eq!(ledger.balance("3", "USDT"), Decimal::from(-100));
+        assert_eq!(ledger.balance(CLEARING_ACCOUNT, "USDT"), Decimal::ZERO);
+
+        // every position is closed, the pnl includes the premium
+        assert!(positions.iter().all(|(_, position)| position.is_flat()));
+        let holder = exercises.iter().find(|exercise| exercise.account_id == "1").unwrap();
+        assert_eq!(holder.realized_pnl, Decimal::from(150));
+    }
+
+    #[rstest]
+    fn expire_out_of_the_money(call_2000: OptionContract, mut positions: PositionBook) {
+        let mut ledger = Ledger::default();
+        call_2000
+            .exercise(&mut positions, &mut ledger, 1900.into(), EXPIRY)
+            .unwrap();
+
+        assert!(ledger.postings().is_empty());
+        assert!(positions.iter().all(|(_, position)| position.is_flat()));
+        assert_eq!(positions.get("2").unwrap().realized_pnl, Decimal::from(100));
+    }
+
+    #[rstest]
+    fn reject_before_expiry(call_2000: OptionContract, mut positions: PositionBook) {
+        let mut ledger = Ledger::default();
+        assert_eq!(
+            call_2000.exercise(&mut positions, &mut ledger, 2100.into(), EXPIRY - 1),
+            Err(OptionError::NotExpired {
+                expiry: EXPIRY,
+                now: EXPIRY - 1
+            })
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    clock::{Timestamp, DAY},
+    engine::{Engine, EngineError},
+    order::{
+        id::{IdError, IdGenerator},
+        OrderPrice, OrderQuantity, OrderRequest, OrderSide,
+    },
+    symbol::SymbolError,
+};
+
+use super::{OptionContract, OptionKind};
+
+const YEAR: u64 = 365 * DAY;
+
+pub trait PricingModel {
+    // option premium for the given underlying price and (annualized) volatility, None if it cannot be priced
+    fn price(
+        &self,
+        contract: &OptionContract,
+        underlying: OrderPrice,
+        volatility: Decimal,
+        now: Timestamp,
+    ) -> Option<OrderPrice>;
+}
+
+// Black-76 with no discounting, i.e. the underlying mark is taken as the forward
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Black76;
+
+impl PricingModel for Black76 {
+    fn price(
+        &self,
+        contract: &OptionContract,
+        underlying: OrderPrice,
+        volatility: Decimal,
+        now: Timestamp,
+    ) -> Option<OrderPrice> {
+        if contract.is_expired(now) {
+            return Some(contract.intrinsic_value(underlying));
+        }
+
+        let forward = f64::try_from(underlying.value()).ok()?;
+        let strike = f64::try_from(contract.strike.value()).ok()?;
+        let sigma = f64::try_from(volatility).ok()?;
+        if forward <= 0.0 || strike <= 0.0 || sigma <= 0.0 {
+            return None;
+        }
+
+        let time = (contract.expiry - now) as f64 / YEAR as f64;
+        let deviation = sigma * time.sqrt();
+        let d1 = ((forward / strike).ln() + deviation * deviation / 2.0) / deviation;
+        let d2 = d1 - deviation;
+
+        let premium = match contract.kind {
+            OptionKind::Call => forward * normal_cdf(d1) - strike * normal_cdf(d2),
+            OptionKind::Put => strike * normal_cdf(-d2) - forward * normal_cdf(-d1),
+        };
+        Decimal::try_from(premium.max(0.0)).ok().map(OrderPrice::from_decimal)
+    }
+}
+
+// Abramowitz and Stegun 7.1.26 (absolute error below 1.5e-7)
+fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * z);
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - polynomial * (-z * z).exp();
+    if x >= 0.0 {
+        (1.0 + erf) / 2.0
+    } else {
+        (1.0 - erf) / 2.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolOrder {
+    pub account_id: CompactString,
+    pub side: OrderSide,
+    pub volatility: Decimal,
+    pub quantity: OrderQuantity,
+    pub limit_price: OrderPrice,
+    pub book_order_id: u64, // the id it rests under, a new one on every re-price since the book takes an id only once
+}
+
+// option book where orders can be entered in volatility terms, the engine converts them to prices
+pub struct VolQuoting<M: PricingModel> {
+    contract: OptionContract,
+    model: M,
+    engine: Engine,
+    tick_size: OrderPrice,
+    underlying: Option<OrderPrice>,
+    orders: IndexMap<u64, VolOrder>,
+    ids: IdGenerator,
+}
+
+impl<M: PricingModel> VolQuoting<M> {
+    pub fn new(contract: OptionContract, model: M) -> Result<Self, VolError> {
+        let engine = contract.engine()?;
+        Ok(Self {
+            contract,
+            model,
+            engine,
+            tick_size: OrderPrice::new(1, 2),
+            underlying: None,
+            orders: IndexMap::new(),
+            ids: IdGenerator::default(),
+        })
+    }
+
+    pub fn with_id_generator(mut self, ids: IdGenerator) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn with_tick_size(mut self, tick_size: OrderPrice) -> Self {
+        self.tick_size = tick_size;
+        self
+    }
+
+    #[inline]
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    #[inline]
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    #[inline]
+    pub fn vol_order(&self, order_id: u64) -> Option<&VolOrder> {
+        self.orders.get(&order_id)
+    }
+
+    // bids are rounded down and asks up to the tick size so the conversion never improves the quote
+    fn to_price(&self, side: OrderSide, volatility: Decimal, now: Timestamp) -> Result<OrderPrice, VolError> {
+        let underlying = self.underlying.ok_or(VolError::NoUnderlyingMark)?;
+        let premium = self
+            .model
+            .price(&self.contract, underlying, volatility, now)
+            .ok_or(VolError::CannotPrice(volatility))?;
+
+        let ticks = premium / self.tick_size;
+        let ticks = match side {
+            OrderSide::Bid => ticks.floor(),
+            OrderSide::Ask => ticks.ceil(),
+        };
+        Ok((self.tick_size * ticks).max(self.tick_size))
+    }
+
+    pub fn submit(
+        &mut self,
+        account_id: &str,
+        order_id: u64,
+        side: OrderSide,
+        volatility: Decimal,
+        quantity: OrderQuantity,
+        now: Timestamp,
+    ) -> Result<OrderPrice, VolError> {
+        if volatility <= Decimal::ZERO {
+            return Err(VolError::CannotPrice(volatility));
+        }
+
+        let limit_price = self.to_price(side, volatility, now)?;
+        self.engine
+            .process(self.create(account_id, order_id, side, quantity, limit_price))?;
+        self.orders.insert(
+            order_id,
+            VolOrder {
+                account_id: account_id.into(),
+                side,
+                volatility,
+                quantity,
+                limit_price,
+                book_order_id: order_id,
+            },
+        );
+
+        Ok(limit_price)
+    }
+
+    pub fn cancel(&mut self, order_id: u64) -> Result<(), VolError> {
+        let vol_order = self
+            .orders
+            .shift_remove(&order_id)
+            .ok_or(VolError::VolOrderNotFound(order_id))?;
+        self.engine.process(OrderRequest::Cancel {
+            account_id: vol_order.account_id,
+            order_id: vol_order.book_order_id,
+        })?;
+        Ok(())
+    }
+
+    // re-price every resting vol order with the new mark (filled orders are forgotten), returns how many were re-priced
+    pub fn update_underlying(&mut self, mark_price: OrderPrice, now: Timestamp) -> Result<usize, VolError> {
+        self.underlying = Some(mark_price);
+
+        let mut repriced = 0;
+        for order_id in self.orders.keys().copied().collect::<Vec<u64>>() {
+            let Some(remaining) = self.remaining(self.orders[&order_id].book_order_id) else {
+                self.orders.shift_remove(&order_id);
+                continue;
+            };
+
+            let vol_order = &self.orders[&order_id];
+            let limit_price = self.to_price(vol_order.side, vol_order.volatility, now)?;
+            if limit_price == vol_order.limit_price {
+                continue;
+            }
+
+            // the order loses its time priority, same as any other amend of the price
+            let (account_id, side) = (vol_order.account_id.clone(), vol_order.side);
+            self.engine.process(OrderRequest::Cancel {
+                account_id: account_id.clone(),
+                order_id: vol_order.book_order_id,
+            })?;
+            let book_order_id = self.ids.next_id()?.into();
+            self.engine
+                .process(self.create(&account_id, book_order_id, side, remaining, limit_price))?;
+
+            let vol_order = &mut self.orders[&order_id];
+            vol_order.quantity = remaining;
+            vol_order.limit_price = limit_price;
+            vol_order.book_order_id = book_order_id;
+            repriced += 1;
+        }
+
+        Ok(repriced)
+    }
+
+    // remaining quantity of a vol order still resting in the book
+    fn remaining(&self, book_order_id: u64) -> Option<OrderQuantity> {
+        self.engine
+            .orderbook()
+            .get_order(book_order_id.into())
+            .map(|order| order.remaining())
+    }
+
+    fn create(
+        &self,
+        account_id: &str,
+        order_id: u64,
+        side: OrderSide,
+        quantity: OrderQuantity,
+        limit_price: OrderPrice,
+    ) -> OrderRequest {
+        OrderRequest::Create {
+            account_id: account_id.into(),
+            order_id,
+            pair: self.engine.pair().clone(),
+            side,
+            limit_price: Some(limit_price),
+            quantity,
+            time_in_force: None,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: None,
+            price_protection: None,
+            reduce_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VolError {
+    #[error("vol orders cannot be priced without a mark price of the underlying!")]
+    NoUnderlyingMark,
+    #[error("vol order cannot be priced! volatility:{0}")]
+    CannotPrice(Decimal),
+    #[error("vol order not found! order_id:{0}")]
+    VolOrderNotFound(u64),
+    #[error("engine error: {0}")]
+    EngineError(#[from] EngineError),
+    #[error("id error: {0}")]
+    IdError(#[from] IdError),
+    #[error("symbol error: {0}")]
+    SymbolError(#[from] SymbolError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::order::util;
+
+    const NOW: Timestamp = 0;
+
+    #[fixture]
+    fn call_100() -> OptionContract {
+        OptionContract {
+            base: "ETH".parse().unwrap(),
+            quote: "USDT".parse().unwrap(),
+            strike: 100.into(),
+            expiry: YEAR,
+            kind: OptionKind::Call,
+        }
+    }
+
+    #[fixture]
+    fn quoting(call_100: OptionContract) -> VolQuoting<Black76> {
+        VolQuoting::new(call_100, Black76).unwrap()
+    }
+
+    #[rstest]
+    fn black76_prices(call_100: OptionContract) {
+        let put_100 = OptionContract {
+            kind: OptionKind::Put,
+            ..call_100.clone()
+        };
+        let volatility = Decimal::new(5, 1);
+
+        // at the money call with 50% vol and 1 year to expiry is worth ~19.74
+        let call = Black76.price(&call_100, 100.into(), volatility, NOW).unwrap();
+        assert_eq!(call.value().round_dp(2), Decimal::new(1974, 2));
+
+        // put-call parity (no discounting): C - P = F - K
+        let call = Black76.price(&call_100, 110.into(), volatility, NOW).unwrap();
+        let put = Black76.price(&put_100, 110.into(), volatility, NOW).unwrap();
+        assert_eq!((call - put).round_dp(4), Decimal::from(10));
+
+        // at expiry the price is the intrinsic value
+        assert_eq!(Black76.price(&call_100, 110.into(), volatility, YEAR), Some(10.into()));
+    }
+
+    #[rstest]
+    fn submit_needs_underlying(mut quoting: VolQuoting<Black76>) {
+        let submit = quoting.submit("1", 1, OrderSide::Bid, Decimal::new(5, 1), 1.into(), NOW);
+        assert!(matches!(submit, Err(VolError::NoUnderlyingMark)));
+
+        quoting.update_underlying(100.into(), NOW).unwrap();
+        let limit_price = quoting
+            .submit("1", 1, OrderSide::Bid, Decimal::new(5, 1), 1.into(), NOW)
+            .unwrap();
+        assert_eq!(limit_price, OrderPrice::new(1974, 2));
+        assert_eq!(
+            quoting
+                .engine()
+                .orderbook()
+                .peek_top(&OrderSide::Bid)
+                .unwrap()
+                .limit_price(),
+            Some(limit_price)
+        );
+    }
+
+    #[rstest]
+    fn reprice_when_underlying_moves(mut quoting: VolQuoting<Black76>) {
+        quoting.update_underlying(100.into(), NOW).unwrap();
+        quoting
+            .submit("1", 1, OrderSide::Bid, Decimal::new(5, 1), 2.into(), NOW)
+            .unwrap();
+        let before = quoting.vol_order(1).unwrap().limit_price;
+
+        // the underlying goes up so the call is worth more at the same volatility
+        assert_eq!(quoting.update_underlying(110.into(), NOW).unwrap(), 1);
+        let after = quoting.vol_order(1).unwrap().limit_price;
+        assert!(after > before);
+        assert_eq!(
+            quoting
+                .engine()
+                .orderbook()
+                .peek_top(&OrderSide::Bid)
+                .unwrap()
+                .limit_price(),
+            Some(after)
+        );
+
+        // a fill against the vol order, then the filled order is not re-priced anymore
+        let ask = util::create(2, OrderSide::Ask)
+            .account_id("2")
+            .pair(quoting.engine().pair().clone())
+            .limit_price(Some(after))
+            .quantity(2)
+            .build();
+        quoting.engine_mut().process(ask).unwrap();
+        assert_eq!(quoting.update_underlying(120.into(), NOW).unwrap(), 0);
+        assert_eq!(quoting.vol_order(1), None);
+    }
+
+    #[rstest]
+    fn cancel_vol_order(mut quoting: VolQuoting<Black76>) {
+        quoting.update_underlying(100.into(), NOW).unwrap();
+        quoting
+            .submit("1", 1, OrderSide::Ask, Decimal::new(5, 1), 1.into(), NOW)
+            .unwrap();
+        quoting.cancel(1).unwrap();
+        assert_eq!(quoting.engine().orderbook().peek_top(&OrderSide::Ask), None);
+        assert!(matches!(quoting.cancel(1), Err(VolError::VolOrderNotFound(1))));
+    }
+}
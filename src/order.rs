@@ -5,6 +5,12 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{clock::Timestamp, symbol::Symbol};
+
+pub mod id;
+pub mod terse;
+pub mod units;
+
 #[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
 pub struct OrderId(u64);
 
@@ -21,15 +27,19 @@ impl From<u64> for OrderId {
     }
 }
 
+impl From<OrderId> for u64 {
+    fn from(value: OrderId) -> u64 {
+        value.0
+    }
+}
+
 impl Display for OrderId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "order_id:{}", self.0)
     }
 }
 
-// TODO use struct to give behavior (see OrderId)
-pub type OrderPrice = Decimal;
-pub type OrderQuantity = Decimal;
+pub use self::units::{OrderPrice, OrderQuantity, UnitError};
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE", tag = "order_request")]
@@ -37,36 +47,133 @@ pub enum OrderRequest {
     Create {
         account_id: CompactString,
         order_id: u64,
-        pair: CompactString,
+        pair: Symbol,
         side: OrderSide,
-        limit_price: Option<Decimal>, // for market orders use None
-        quantity: Decimal,
+        limit_price: Option<OrderPrice>, // for market orders use None
+        quantity: OrderQuantity,
+        #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+        time_in_force: Option<TimeInForce>,
+        #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+        short_sell: bool, // asks only, needs a locate (see Engine::with_locate)
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        priority_fee: Decimal, // experimental, see QueuePriority::PriorityFee
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_order_id: Option<CompactString>, // unique among the open orders of the account
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        price_protection: Option<Decimal>, // market orders only, see Engine::with_price_protection
+        #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+        reduce_only: bool, // never increases the position of the account (see Engine::with_positions)
     },
     Cancel {
+        #[serde(default)]
+        account_id: CompactString,
         order_id: u64,
     },
+    // the open order of the account with this client order id
+    CancelByClientId {
+        account_id: CompactString,
+        client_order_id: CompactString,
+    },
+    // every resting order of the account (e.g. on disconnect)
+    CancelAll {
+        account_id: CompactString,
+    },
+    // replaces every quote of the account still resting at once, no levels pulls them all
+    MassQuote {
+        account_id: CompactString,
+        pair: Symbol,
+        #[serde(default)]
+        levels: Vec<QuoteLevel>,
+    },
+}
+
+// one side of a quote rests as a good-til-cancel limit order
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuoteSide {
+    pub order_id: u64,
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+}
+
+// a level of a mass quote, one side may be left out
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuoteLevel {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bid: Option<QuoteSide>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ask: Option<QuoteSide>,
+}
+
+impl QuoteLevel {
+    #[inline]
+    pub fn new(bid: QuoteSide, ask: QuoteSide) -> Self {
+        Self {
+            bid: Some(bid),
+            ask: Some(ask),
+        }
+    }
+
+    #[inline]
+    pub fn sides(&self) -> impl Iterator<Item = (OrderSide, QuoteSide)> {
+        self.bid
+            .map(|bid| (OrderSide::Bid, bid))
+            .into_iter()
+            .chain(self.ask.map(|ask| (OrderSide::Ask, ask)))
+    }
+}
+
+impl OrderRequest {
+    #[inline]
+    pub fn account_id(&self) -> &str {
+        match self {
+            OrderRequest::Create { account_id, .. }
+            | OrderRequest::Cancel { account_id, .. }
+            | OrderRequest::CancelByClientId { account_id, .. }
+            | OrderRequest::CancelAll { account_id }
+            | OrderRequest::MassQuote { account_id, .. } => account_id,
+        }
+    }
+
+    // of the orders the request brings to the book
+    pub fn new_order_ids(&self) -> Vec<u64> {
+        match self {
+            OrderRequest::Create { order_id, .. } => vec![*order_id],
+            OrderRequest::MassQuote { levels, .. } => levels
+                .iter()
+                .flat_map(QuoteLevel::sides)
+                .map(|(_, quote)| quote.order_id)
+                .collect(),
+            _ => vec![],
+        }
+    }
 }
 
 impl Display for OrderRequest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             OrderRequest::Create {
-                account_id: _,
                 order_id,
-                pair: _,
                 side,
                 limit_price,
                 quantity,
+                ..
             } => match limit_price {
                 Some(limit_price) => write!(f, "ORDER[{order_id}] {side} {quantity}@{limit_price}"),
                 None => write!(f, "ORDER[{order_id}] {side} {quantity}@MARKET"),
             },
-            OrderRequest::Cancel { order_id } => write!(f, "[CANCEL] order_id: {order_id}"),
+            OrderRequest::Cancel { order_id, .. } => write!(f, "[CANCEL] order_id: {order_id}"),
+            OrderRequest::CancelByClientId { client_order_id, .. } => {
+                write!(f, "[CANCEL] client_order_id: {client_order_id}")
+            }
+            OrderRequest::CancelAll { account_id } => write!(f, "[CANCEL ALL] account_id: {account_id}"),
+            OrderRequest::MassQuote { account_id, levels, .. } => {
+                write!(f, "[MASS QUOTE] account_id: {account_id} levels: {}", levels.len())
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum OrderSide {
     Ask,
@@ -122,6 +229,26 @@ pub enum TimeInForce {
         #[serde(default, skip_serializing_if = "core::ops::Not::not")]
         fill_or_kill: bool,
     },
+    // auction only: trades at the uncross price against the imbalance, never shown in the book
+    #[serde(rename = "IO")]
+    ImbalanceOnly,
+    // same as IO, then whatever is left goes on as a GTC limit order once the auction is over
+    #[serde(rename = "IOF")]
+    ImbalanceOffset,
+    // rests like GTC until the engine expires it (see Engine::expire)
+    #[serde(rename = "GTD")]
+    GoodTilDate {
+        expires_at: Timestamp,
+        #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+        post_only: bool,
+    },
+    // good for the trading day: the engine turns it into a GTD expiring at the end of the session on arrival (see
+    // Engine::with_session_end)
+    #[serde(rename = "DAY")]
+    Day {
+        #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+        post_only: bool,
+    },
 }
 
 impl Default for TimeInForce {
@@ -151,6 +278,12 @@ pub struct Order {
     //#[serde(default)]
     filled_quantity: OrderQuantity,
     status: OrderStatus,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    short_sell: bool,
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    priority_fee: Decimal,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    reduce_only: bool,
 }
 
 impl Order {
@@ -166,6 +299,9 @@ impl Order {
             order_quantity: quantity,
             filled_quantity: 0.into(),
             status: OrderStatus::Open,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            reduce_only: false,
         }
     }
 
@@ -180,9 +316,57 @@ impl Order {
             order_quantity: quantity,
             filled_quantity: 0.into(),
             status: OrderStatus::Open,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            reduce_only: false,
         }
     }
 
+    // market orders only keep the fill or kill flag of an IOC
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        match &mut self.type_ {
+            OrderType::Limit {
+                time_in_force: current, ..
+            } => *current = time_in_force,
+            OrderType::Market { fill_or_kill } => {
+                *fill_or_kill = matches!(time_in_force, TimeInForce::ImmediateOrCancel { fill_or_kill: true })
+            }
+        }
+        self
+    }
+
+    // only asks can be short sales
+    pub fn with_short_sell(mut self, short_sell: bool) -> Self {
+        self.short_sell = short_sell && self.side == OrderSide::Ask;
+        self
+    }
+
+    #[inline]
+    pub fn is_short_sell(&self) -> bool {
+        self.short_sell
+    }
+
+    pub fn with_reduce_only(mut self, reduce_only: bool) -> Self {
+        self.reduce_only = reduce_only;
+        self
+    }
+
+    #[inline]
+    pub fn is_reduce_only(&self) -> bool {
+        self.reduce_only
+    }
+
+    // paid to rest ahead of the orders paying less at the same price
+    pub fn with_priority_fee(mut self, priority_fee: Decimal) -> Self {
+        self.priority_fee = priority_fee;
+        self
+    }
+
+    #[inline]
+    pub fn priority_fee(&self) -> Decimal {
+        self.priority_fee
+    }
+
     #[inline]
     pub fn id(&self) -> OrderId {
         self.id
@@ -199,7 +383,7 @@ impl Order {
     }
 
     #[inline]
-    fn status(&self) -> OrderStatus {
+    pub fn status(&self) -> OrderStatus {
         self.status
     }
 
@@ -211,6 +395,47 @@ impl Order {
         }
     }
 
+    // only limit orders have a price to change, used by the book (e.g. post-only re-pricing)
+    #[inline]
+    pub(crate) fn reprice(&mut self, price: OrderPrice) {
+        if let OrderType::Limit { limit_price, .. } = &mut self.type_ {
+            *limit_price = price;
+        }
+    }
+
+    #[inline]
+    pub fn expires_at(&self) -> Option<Timestamp> {
+        match self.type_ {
+            OrderType::Limit {
+                time_in_force: TimeInForce::GoodTilDate { expires_at, .. },
+                ..
+            } => Some(expires_at),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_auction_only(&self) -> bool {
+        matches!(
+            self.type_,
+            OrderType::Limit {
+                time_in_force: TimeInForce::ImbalanceOnly | TimeInForce::ImbalanceOffset,
+                ..
+            }
+        )
+    }
+
+    #[inline]
+    pub fn is_imbalance_offset(&self) -> bool {
+        matches!(
+            self.type_,
+            OrderType::Limit {
+                time_in_force: TimeInForce::ImbalanceOffset,
+                ..
+            }
+        )
+    }
+
     pub fn can_trade(&self, order: &Order) -> OrderQuantity {
         self.remaining().min(order.remaining())
     }
@@ -341,7 +566,9 @@ impl OrderFeatures for Order {
         matches!(
             self.type_,
             OrderType::Limit {
-                time_in_force: TimeInForce::GoodTilCancel { post_only: true },
+                time_in_force: TimeInForce::GoodTilCancel { post_only: true }
+                    | TimeInForce::GoodTilDate { post_only: true, .. }
+                    | TimeInForce::Day { post_only: true },
                 ..
             }
         )
@@ -368,45 +595,287 @@ pub enum OrderError {
 }
 
 pub mod util {
-    use compact_str::{format_compact, CompactString};
-    use rand::{rngs::ThreadRng, Rng};
+    use std::ops::Range;
+
+    use compact_str::format_compact;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use rust_decimal::Decimal;
 
     use super::{OrderRequest, OrderSide};
+    use crate::symbol::Symbol;
 
     pub const DEFAULT_PAIR: &str = "ETH/USDT";
+    pub const DEFAULT_SYMBOL: Symbol = Symbol::new_inline("ETH", "USDT", DEFAULT_PAIR);
+
+    // the create request of the tests: a limit order for 10 at 15 by account 1 on the default pair, the rest is changed
+    // where a test needs it
+    #[cfg(test)]
+    pub(crate) fn create(order_id: u64, side: OrderSide) -> CreateBuilder {
+        CreateBuilder {
+            account_id: "1".into(),
+            order_id,
+            pair: DEFAULT_SYMBOL,
+            side,
+            limit_price: Some(15.into()),
+            quantity: 10.into(),
+            time_in_force: None,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: None,
+            price_protection: None,
+            reduce_only: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn create_on(order_id: u64, pair: &str, side: OrderSide) -> CreateBuilder {
+        create(order_id, side).pair(pair.parse().unwrap())
+    }
+
+    // the cancel request of the tests, by account 1
+    #[cfg(test)]
+    pub(crate) fn cancel(order_id: u64) -> OrderRequest {
+        OrderRequest::Cancel {
+            account_id: "1".into(),
+            order_id,
+        }
+    }
+
+    #[cfg(test)]
+    #[derive(Clone, Debug)]
+    pub(crate) struct CreateBuilder {
+        account_id: compact_str::CompactString,
+        order_id: u64,
+        pair: Symbol,
+        side: OrderSide,
+        limit_price: Option<super::OrderPrice>,
+        quantity: super::OrderQuantity,
+        time_in_force: Option<super::TimeInForce>,
+        short_sell: bool,
+        priority_fee: Decimal,
+        client_order_id: Option<compact_str::CompactString>,
+        price_protection: Option<Decimal>,
+        reduce_only: bool,
+    }
+
+    #[cfg(test)]
+    impl CreateBuilder {
+        pub(crate) fn account_id(mut self, account_id: &str) -> Self {
+            self.account_id = account_id.into();
+            self
+        }
+
+        pub(crate) fn pair(mut self, pair: Symbol) -> Self {
+            self.pair = pair;
+            self
+        }
+
+        pub(crate) fn limit_price(mut self, limit_price: Option<super::OrderPrice>) -> Self {
+            self.limit_price = limit_price;
+            self
+        }
+
+        pub(crate) fn quantity(mut self, quantity: impl Into<super::OrderQuantity>) -> Self {
+            self.quantity = quantity.into();
+            self
+        }
+
+        pub(crate) fn time_in_force(mut self, time_in_force: Option<super::TimeInForce>) -> Self {
+            self.time_in_force = time_in_force;
+            self
+        }
+
+        pub(crate) fn short_sell(mut self, short_sell: bool) -> Self {
+            self.short_sell = short_sell;
+            self
+        }
+
+        pub(crate) fn priority_fee(mut self, priority_fee: Decimal) -> Self {
+            self.priority_fee = priority_fee;
+            self
+        }
+
+        pub(crate) fn client_order_id(mut self, client_order_id: Option<compact_str::CompactString>) -> Self {
+            self.client_order_id = client_order_id;
+            self
+        }
+
+        pub(crate) fn price_protection(mut self, price_protection: Option<Decimal>) -> Self {
+            self.price_protection = price_protection;
+            self
+        }
+
+        pub(crate) fn reduce_only(mut self, reduce_only: bool) -> Self {
+            self.reduce_only = reduce_only;
+            self
+        }
+
+        pub(crate) fn build(self) -> OrderRequest {
+            OrderRequest::Create {
+                account_id: self.account_id,
+                order_id: self.order_id,
+                pair: self.pair,
+                side: self.side,
+                limit_price: self.limit_price,
+                quantity: self.quantity,
+                time_in_force: self.time_in_force,
+                short_sell: self.short_sell,
+                priority_fee: self.priority_fee,
+                client_order_id: self.client_order_id,
+                price_protection: self.price_protection,
+                reduce_only: self.reduce_only,
+            }
+        }
+    }
+
+    // a path of the tests in the temp directory, cleared when taken and removed (file or directory) when dropped
+    #[cfg(test)]
+    pub(crate) struct TempPath(std::path::PathBuf);
+
+    #[cfg(test)]
+    impl TempPath {
+        pub(crate) fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("merx-{}-{name}", std::process::id()));
+            Self::clear(&path);
+            Self(path)
+        }
+
+        fn clear(path: &std::path::Path) {
+            let _ = std::fs::remove_file(path).or_else(|_| std::fs::remove_dir_all(path));
+        }
+    }
+
+    #[cfg(test)]
+    impl std::ops::Deref for TempPath {
+        type Target = std::path::Path;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    #[cfg(test)]
+    impl AsRef<std::path::Path> for TempPath {
+        fn as_ref(&self) -> &std::path::Path {
+            &self.0
+        }
+    }
+
+    #[cfg(test)]
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            Self::clear(&self.0);
+        }
+    }
+
+    // prices and quantities are expressed in hundredths (2 decimal places)
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct GeneratorConfig {
+        pub cancel_ratio: f64,
+        // when set, cancels only target one of the latest N order ids (otherwise any previous order id)
+        pub cancel_window: Option<u64>,
+        pub bid_ratio: f64,
+        pub market_ratio: f64,
+        pub price_range: Range<i64>,
+        // shift applied to the price range on every generated order (e.g. negative to simulate a crash)
+        pub price_drift: i64,
+        pub quantity_range: Range<i64>,
+        pub accounts: u64,
+        // the same flow for the same seed (e.g. reproducible benchmarks), a different one on every run without
+        pub seed: Option<u64>,
+    }
+
+    impl Default for GeneratorConfig {
+        fn default() -> Self {
+            Self {
+                cancel_ratio: 1.0 / 1000.0,
+                cancel_window: None,
+                bid_ratio: 0.5,
+                market_ratio: 0.2,
+                price_range: 10000..1_000_000,
+                price_drift: 0,
+                quantity_range: 10000..1_000_000,
+                accounts: 9,
+                seed: None,
+            }
+        }
+    }
+
+    impl GeneratorConfig {
+        fn price_range_at(&self, i: usize) -> Range<i64> {
+            let shift = self.price_drift.saturating_mul(i as i64);
+            let start = self.price_range.start.saturating_add(shift).max(1);
+            let end = self.price_range.end.saturating_add(shift).max(start + 1);
+            start..end
+        }
+    }
 
     pub fn generate(range: impl Iterator<Item = usize>) -> impl Iterator<Item = OrderRequest> {
-        let mut rng = rand::thread_rng();
+        generate_with(GeneratorConfig::default(), range)
+    }
+
+    pub fn generate_with(
+        config: GeneratorConfig,
+        range: impl Iterator<Item = usize>,
+    ) -> impl Iterator<Item = OrderRequest> {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
 
+        // the account of an order follows from its id, so that its cancels come from the account holding it
+        let account_of = move |order_id: u64| format_compact!("{}", order_id % config.accounts.max(1) + 1);
         range.map(move |i| {
-            if rng.gen_bool(1.0 / 1000.0) {
+            if rng.gen_bool(config.cancel_ratio) {
+                let latest = i as u64;
+                let earliest = match config.cancel_window {
+                    Some(window) => latest.saturating_sub(window).max(1),
+                    None => 1,
+                };
+                let order_id = rng.gen_range(earliest..=latest);
                 OrderRequest::Cancel {
-                    order_id: rng.gen_range(1..=i as u64),
+                    account_id: account_of(order_id),
+                    order_id,
                 }
             } else {
                 OrderRequest::Create {
-                    account_id: format_compact!("{}", rng.gen_range(1..10)),
+                    account_id: account_of(i as u64),
                     order_id: i as u64,
-                    pair: CompactString::new_inline(DEFAULT_PAIR),
-                    side: if rng.gen_bool(0.5) {
-                        OrderSide::Ask
-                    } else {
+                    pair: DEFAULT_SYMBOL,
+                    side: if rng.gen_bool(config.bid_ratio) {
                         OrderSide::Bid
-                    },
-                    limit_price: if rng.gen_bool(0.8) {
-                        Some(random_decimal(&mut rng))
                     } else {
+                        OrderSide::Ask
+                    },
+                    limit_price: if rng.gen_bool(config.market_ratio) {
                         None
+                    } else {
+                        Some(random_amount_in(&mut rng, config.price_range_at(i)))
                     },
-                    quantity: random_decimal(&mut rng),
+                    quantity: random_amount_in(&mut rng, config.quantity_range.clone()),
+                    time_in_force: None,
+                    short_sell: false,
+                    priority_fee: Decimal::ZERO,
+                    client_order_id: None,
+                    price_protection: None,
+                    reduce_only: false,
                 }
             }
         })
     }
 
-    pub fn random_decimal(rng: &mut ThreadRng) -> Decimal {
-        Decimal::new(rng.gen_range(10000..1_000_000), 2)
+    pub fn random_decimal(rng: &mut impl Rng) -> Decimal {
+        random_decimal_in(rng, 10000..1_000_000)
+    }
+
+    pub fn random_decimal_in(rng: &mut impl Rng, range: Range<i64>) -> Decimal {
+        Decimal::new(rng.gen_range(range), 2)
+    }
+
+    // a price or a quantity, the ranges of the generator are never negative
+    fn random_amount_in<T: TryFrom<Decimal>>(rng: &mut impl Rng, range: Range<i64>) -> T {
+        T::try_from(random_decimal_in(rng, range.start.max(0)..range.end.max(1)))
+            .unwrap_or_else(|_| unreachable!("non-negative amount with a scale of 2"))
     }
 }
 
@@ -544,7 +1013,7 @@ mod test {
 
             let fok = TimeInForce::ImmediateOrCancel { fill_or_kill: true };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: fok,
             };
             assert!(limit_order.is_fill_or_kill());
@@ -563,7 +1032,7 @@ mod test {
             // change the limit order to GTC but without enforcing post_only
             let gtc = TimeInForce::GoodTilCancel { post_only: false };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: gtc,
             };
             assert!(!limit_order.is_post_only());
@@ -571,7 +1040,7 @@ mod test {
             // change the limit order to GTC but enforcing post_only
             let gtc = TimeInForce::GoodTilCancel { post_only: true };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: gtc,
             };
             assert!(limit_order.is_post_only());
@@ -589,10 +1058,53 @@ mod test {
 
             let ioc = TimeInForce::ImmediateOrCancel { fill_or_kill: false };
             limit_order.type_ = OrderType::Limit {
-                limit_price: Decimal::ZERO,
+                limit_price: OrderPrice::ZERO,
                 time_in_force: ioc,
             };
             assert!(limit_order.is_immediate_or_cancel());
         }
+
+        #[rstest]
+        fn good_til_date(bid_040_at_013: Order, bid_040_at_market: Order) {
+            let gtd = TimeInForce::GoodTilDate {
+                expires_at: 100,
+                post_only: true,
+            };
+            let limit_order = bid_040_at_013.with_time_in_force(gtd);
+            assert_eq!(limit_order.expires_at(), Some(100));
+            assert!(limit_order.is_post_only());
+            assert!(!limit_order.is_immediate_or_cancel());
+
+            // market orders cannot rest so they never expire
+            assert_eq!(bid_040_at_market.with_time_in_force(gtd).expires_at(), None);
+        }
+
+        #[rstest]
+        fn deserialize_time_in_force() {
+            let json = r#"{"order_request":"CREATE","account_id":"1","order_id":1,"pair":"ETH/USDT","side":"BID",
+                "limit_price":"15","quantity":"10","time_in_force":"GTD","expires_at":100}"#;
+            let order_request: OrderRequest = serde_json::from_str(json).unwrap();
+            assert!(matches!(
+                order_request,
+                OrderRequest::Create {
+                    time_in_force: Some(TimeInForce::GoodTilDate {
+                        expires_at: 100,
+                        post_only: false
+                    }),
+                    ..
+                }
+            ));
+
+            // without it the order is a plain GTC
+            let json = json.replace(r#","time_in_force":"GTD","expires_at":100"#, "");
+            let order_request: OrderRequest = serde_json::from_str(&json).unwrap();
+            assert!(matches!(
+                order_request,
+                OrderRequest::Create {
+                    time_in_force: None,
+                    ..
+                }
+            ));
+        }
     }
 }
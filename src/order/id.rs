@@ -0,0 +1,144 @@
+use thiserror::Error;
+
+use crate::clock::{Clock, SystemClock, Timestamp};
+
+use super::OrderId;
+
+const SHARD_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const TIMESTAMP_BITS: u32 = 64 - 1 - SHARD_BITS - SEQUENCE_BITS; // the top bit is left clear
+
+pub const EPOCH: Timestamp = 1_672_531_200_000; // 2023-01-01T00:00:00Z
+pub const MAX_SHARD: u16 = (1 << SHARD_BITS) - 1;
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+const MAX_ELAPSED: u64 = (1 << TIMESTAMP_BITS) - 1;
+
+// snowflake-style ids: milliseconds since the epoch of the generator, then the shard, then a sequence within the
+// millisecond, so that the ids of one shard grow with time and the shards never collide
+pub struct IdGenerator {
+    clock: Box<dyn Clock>,
+    epoch: Timestamp,
+    shard: u16,
+    last: Timestamp, // elapsed of the last id given, never moves back even if the clock does
+    sequence: u64,
+}
+
+impl IdGenerator {
+    pub fn new(clock: impl Clock + 'static, epoch: Timestamp, shard: u16) -> Result<Self, IdError> {
+        if shard > MAX_SHARD {
+            return Err(IdError::ShardOutOfRange(shard));
+        }
+        Ok(Self {
+            clock: Box::new(clock),
+            epoch,
+            shard,
+            last: 0,
+            sequence: 0,
+        })
+    }
+
+    // once the sequence of a millisecond is spent, the ids borrow from the next one rather than collide
+    pub fn next_id(&mut self) -> Result<OrderId, IdError> {
+        let elapsed = self.clock.now().saturating_sub(self.epoch);
+        if elapsed > self.last {
+            self.last = elapsed;
+            self.sequence = 0;
+        } else if self.sequence < MAX_SEQUENCE {
+            self.sequence += 1;
+        } else {
+            self.last += 1;
+            self.sequence = 0;
+        }
+        if self.last > MAX_ELAPSED {
+            return Err(IdError::Exhausted);
+        }
+        Ok(OrderId::new(
+            self.last << (SHARD_BITS + SEQUENCE_BITS) | (self.shard as u64) << SEQUENCE_BITS | self.sequence,
+        ))
+    }
+
+    #[inline]
+    pub fn shard(&self) -> u16 {
+        self.shard
+    }
+}
+
+impl Default for IdGenerator {
+    fn default() -> Self {
+        Self {
+            clock: Box::new(SystemClock),
+            epoch: EPOCH,
+            shard: 0,
+            last: 0,
+            sequence: 0,
+        }
+    }
+}
+
+// the parts of an id given by an IdGenerator
+#[inline]
+pub fn decompose(order_id: OrderId, epoch: Timestamp) -> (Timestamp, u16, u64) {
+    let id = u64::from(order_id);
+    (
+        epoch + (id >> (SHARD_BITS + SEQUENCE_BITS)),
+        ((id >> SEQUENCE_BITS) & MAX_SHARD as u64) as u16,
+        id & MAX_SEQUENCE,
+    )
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum IdError {
+    #[error("shard out of range (shard={0}, max={MAX_SHARD})")]
+    ShardOutOfRange(u16),
+    #[error("no id left after the timestamp range of the generator")]
+    Exhausted,
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexSet;
+    use rstest::rstest;
+
+    use crate::clock::ManualClock;
+
+    use super::*;
+
+    #[rstest]
+    fn ids_grow_and_decompose() {
+        let clock = ManualClock::new(EPOCH + 5);
+        let mut generator = IdGenerator::new(clock.clone(), EPOCH, 7).unwrap();
+        let first = generator.next_id().unwrap();
+        let second = generator.next_id().unwrap();
+        clock.advance(1);
+        let third = generator.next_id().unwrap();
+        assert_eq!(decompose(first, EPOCH), (EPOCH + 5, 7, 0));
+        assert_eq!(decompose(second, EPOCH), (EPOCH + 5, 7, 1));
+        assert_eq!(decompose(third, EPOCH), (EPOCH + 6, 7, 0));
+        assert!(u64::from(first) < u64::from(second) && u64::from(second) < u64::from(third));
+    }
+
+    #[rstest]
+    fn no_collision_on_a_spent_sequence_or_a_clock_moving_back() {
+        let clock = ManualClock::new(EPOCH + 10);
+        let mut generator = IdGenerator::new(clock.clone(), EPOCH, 1).unwrap();
+        let mut ids = IndexSet::new();
+        for _ in 0..=MAX_SEQUENCE + 1 {
+            assert!(ids.insert(generator.next_id().unwrap()));
+        }
+        assert_eq!(decompose(*ids.last().unwrap(), EPOCH), (EPOCH + 11, 1, 0));
+        clock.set(EPOCH);
+        assert!(ids.insert(generator.next_id().unwrap()));
+    }
+
+    #[rstest]
+    fn shards_never_collide() {
+        let clock = ManualClock::new(EPOCH);
+        let mut shard_0 = IdGenerator::new(clock.clone(), EPOCH, 0).unwrap();
+        let mut shard_1 = IdGenerator::new(clock, EPOCH, 1).unwrap();
+        assert_ne!(shard_0.next_id().unwrap(), shard_1.next_id().unwrap());
+        assert!(matches!(
+            IdGenerator::new(ManualClock::default(), EPOCH, MAX_SHARD + 1),
+            Err(IdError::ShardOutOfRange(_))
+        ));
+    }
+}
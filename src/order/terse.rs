@@ -0,0 +1,323 @@
+use std::str::FromStr;
+
+use compact_str::CompactString;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use super::{OrderRequest, OrderSide, TimeInForce};
+use crate::symbol::{Symbol, SymbolError};
+
+// what a terse request may leave out
+#[derive(Clone, Copy, Debug)]
+pub struct TerseDefaults<'a> {
+    pub account_id: &'a str,
+    pub pair: &'a Symbol,
+}
+
+// compact order requests, blank separated tokens in any order after the command, keywords in any case:
+//   B|BUY|S|SELL <quantity>[@<price>] [<pair>] [GTC|DAY|IOC|FOK|IO|IOF|GTD=<expires_at>] [PO] [SHORT] [RO]
+//       [FEE=<priority_fee>] [#<order_id>] [ACCOUNT=<account_id>] [CLIENT=<client_order_id>]
+//   C|CANCEL #<order_id>|<order_id>|CLIENT=<client_order_id> [ACCOUNT=<account_id>]
+//   CA|CANCELALL [ACCOUNT=<account_id>]
+// e.g. `B 100@13.5 ETH/USDT IOC`, a create with no price is a market order and with no order id takes the next one
+pub fn parse(
+    line: &str,
+    defaults: TerseDefaults,
+    next_order_id: impl FnOnce() -> Option<u64>,
+) -> Result<OrderRequest, TerseError> {
+    let mut tokens = line.split_whitespace();
+    let command = tokens.next().ok_or(TerseError::Missing("command"))?;
+    let side = match command.to_ascii_uppercase().as_str() {
+        "B" | "BUY" => Some(OrderSide::Bid),
+        "S" | "SELL" => Some(OrderSide::Ask),
+        "C" | "CANCEL" | "CA" | "CANCELALL" => None,
+        _ => return Err(TerseError::UnknownCommand(command.into())),
+    };
+
+    let Some(side) = side else {
+        let mut fields = Fields::default();
+        for token in tokens {
+            match token.parse::<u64>() {
+                Ok(order_id) if fields.order_id.is_none() => fields.order_id = Some(order_id),
+                _ => fields.read(token)?,
+            }
+        }
+        if let Some(token) = fields.create_only() {
+            return Err(TerseError::UnexpectedToken(token));
+        }
+        let account_id = fields.account_id.unwrap_or_else(|| defaults.account_id.into());
+        let all = matches!(command.to_ascii_uppercase().as_str(), "CA" | "CANCELALL");
+        return match (all, fields.order_id, fields.client_order_id) {
+            (true, None, None) => Ok(OrderRequest::CancelAll { account_id }),
+            (false, Some(order_id), None) => Ok(OrderRequest::Cancel { account_id, order_id }),
+            (false, None, Some(client_order_id)) => Ok(OrderRequest::CancelByClientId {
+                account_id,
+                client_order_id,
+            }),
+            (false, None, None) => Err(TerseError::Missing("order id")),
+            _ => Err(TerseError::Conflicting(command.into())),
+        };
+    };
+
+    let amount = tokens.next().ok_or(TerseError::Missing("quantity"))?;
+    let (quantity, limit_price) = match amount.split_once('@') {
+        Some((quantity, limit_price)) => (amount_of(quantity)?, Some(amount_of(limit_price)?)),
+        None => (amount_of(amount)?, None),
+    };
+    let mut fields = Fields::default();
+    for token in tokens {
+        fields.read(token)?;
+    }
+    let time_in_force = fields.time_in_force()?;
+    let order_id = match fields.order_id {
+        Some(order_id) => order_id,
+        None => next_order_id().ok_or(TerseError::Missing("order id"))?,
+    };
+    Ok(OrderRequest::Create {
+        account_id: fields.account_id.unwrap_or_else(|| defaults.account_id.into()),
+        order_id,
+        pair: fields.pair.unwrap_or_else(|| defaults.pair.clone()),
+        side,
+        limit_price,
+        quantity,
+        time_in_force,
+        short_sell: fields.short_sell,
+        priority_fee: fields.priority_fee.unwrap_or_default(),
+        client_order_id: fields.client_order_id,
+        price_protection: None,
+        reduce_only: fields.reduce_only,
+    })
+}
+
+#[derive(Debug, Default)]
+struct Fields {
+    pair: Option<Symbol>,
+    order_id: Option<u64>,
+    account_id: Option<CompactString>,
+    client_order_id: Option<CompactString>,
+    time_in_force: Option<(CompactString, TimeInForce)>, // with the token it was read from
+    post_only: bool,
+    short_sell: bool,
+    reduce_only: bool,
+    priority_fee: Option<Decimal>,
+}
+
+impl Fields {
+    fn read(&mut self, token: &str) -> Result<(), TerseError> {
+        if let Some(order_id) = token.strip_prefix('#') {
+            let order_id = order_id.parse().map_err(|_| TerseError::InvalidNumber(token.into()))?;
+            return set(&mut self.order_id, order_id, token);
+        }
+        if let Some((key, value)) = token.split_once('=') {
+            return match key.to_ascii_uppercase().as_str() {
+                "ACCOUNT" => set(&mut self.account_id, value.into(), token),
+                "CLIENT" => set(&mut self.client_order_id, value.into(), token),
+                "FEE" => set(&mut self.priority_fee, decimal(value)?, token),
+                "GTD" => {
+                    let expires_at = value.parse().map_err(|_| TerseError::InvalidNumber(value.into()))?;
+                    let time_in_force = TimeInForce::GoodTilDate {
+                        expires_at,
+                        post_only: false,
+                    };
+                    set(&mut self.time_in_force, (token.into(), time_in_force), token)
+                }
+                _ => Err(TerseError::UnexpectedToken(token.into())),
+            };
+        }
+        if token.contains('/') {
+            return set(&mut self.pair, token.parse()?, token);
+        }
+
+        let time_in_force = match token.to_ascii_uppercase().as_str() {
+            "GTC" => TimeInForce::GoodTilCancel { post_only: false },
+            "DAY" => TimeInForce::Day { post_only: false },
+            "IOC" => TimeInForce::ImmediateOrCancel { fill_or_kill: false },
+            "FOK" => TimeInForce::ImmediateOrCancel { fill_or_kill: true },
+            "IO" => TimeInForce::ImbalanceOnly,
+            "IOF" => TimeInForce::ImbalanceOffset,
+            "PO" if !self.post_only => {
+                self.post_only = true;
+                return Ok(());
+            }
+            "SHORT" if !self.short_sell => {
+                self.short_sell = true;
+                return Ok(());
+            }
+            "RO" if !self.reduce_only => {
+                self.reduce_only = true;
+                return Ok(());
+            }
+            "PO" | "SHORT" | "RO" => return Err(TerseError::Conflicting(token.into())),
+            _ => return Err(TerseError::UnexpectedToken(token.into())),
+        };
+        set(&mut self.time_in_force, (token.into(), time_in_force), token)
+    }
+
+    // post only applies to the orders resting in the book (GTC by default)
+    fn time_in_force(&self) -> Result<Option<TimeInForce>, TerseError> {
+        match (self.time_in_force.clone(), self.post_only) {
+            (None, false) => Ok(None),
+            (None, true) => Ok(Some(TimeInForce::GoodTilCancel { post_only: true })),
+            (Some((_, TimeInForce::GoodTilCancel { .. })), post_only) => {
+                Ok(Some(TimeInForce::GoodTilCancel { post_only }))
+            }
+            (Some((_, TimeInForce::GoodTilDate { expires_at, .. })), post_only) => {
+                Ok(Some(TimeInForce::GoodTilDate { expires_at, post_only }))
+            }
+            (Some((_, TimeInForce::Day { .. })), post_only) => Ok(Some(TimeInForce::Day { post_only })),
+            (Some((_, time_in_force)), false) => Ok(Some(time_in_force)),
+            (Some((token, _)), true) => Err(TerseError::Conflicting(token)),
+        }
+    }
+
+    // the first token a cancel cannot have
+    fn create_only(&self) -> Option<CompactString> {
+        if let Some(pair) = &self.pair {
+            return Some(pair.as_str().into());
+        }
+        if let Some((token, _)) = &self.time_in_force {
+            return Some(token.clone());
+        }
+        [
+            (self.post_only, "PO"),
+            (self.short_sell, "SHORT"),
+            (self.reduce_only, "RO"),
+            (self.priority_fee.is_some(), "FEE"),
+        ]
+        .into_iter()
+        .find(|(set, _)| *set)
+        .map(|(_, token)| token.into())
+    }
+}
+
+fn set<T>(field: &mut Option<T>, value: T, token: &str) -> Result<(), TerseError> {
+    if field.is_some() {
+        return Err(TerseError::Conflicting(token.into()));
+    }
+    *field = Some(value);
+    Ok(())
+}
+
+fn decimal(token: &str) -> Result<Decimal, TerseError> {
+    token.parse().map_err(|_| TerseError::InvalidNumber(token.into()))
+}
+
+// a price or a quantity
+fn amount_of<T: FromStr>(token: &str) -> Result<T, TerseError> {
+    token.parse().map_err(|_| TerseError::InvalidNumber(token.into()))
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TerseError {
+    #[error("unknown command! {0}")]
+    UnknownCommand(CompactString),
+    #[error("missing {0}!")]
+    Missing(&'static str),
+    #[error("invalid number! {0}")]
+    InvalidNumber(CompactString),
+    #[error("unexpected token! {0}")]
+    UnexpectedToken(CompactString),
+    #[error("conflicting token! {0}")]
+    Conflicting(CompactString),
+    #[error("symbol error: {0}")]
+    SymbolError(#[from] SymbolError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{
+        util::{self, DEFAULT_SYMBOL},
+        OrderPrice,
+    };
+
+    const DEFAULTS: TerseDefaults = TerseDefaults {
+        account_id: "1",
+        pair: &DEFAULT_SYMBOL,
+    };
+
+    fn terse(line: &str) -> Result<OrderRequest, TerseError> {
+        parse(line, DEFAULTS, || Some(99))
+    }
+
+    #[rstest]
+    fn parse_creates() {
+        assert_eq!(
+            terse("B 100@13.5").unwrap(),
+            util::create(99, OrderSide::Bid)
+                .limit_price(Some(OrderPrice::new(135, 1)))
+                .quantity(100)
+                .build()
+        );
+        assert_eq!(
+            terse("sell 5 BTC/USDT fok #7 account=2 client=A-1 short fee=0.5 ro").unwrap(),
+            util::create(7, OrderSide::Ask)
+                .account_id("2")
+                .pair("BTC/USDT".parse().unwrap())
+                .limit_price(None)
+                .quantity(5)
+                .time_in_force(Some(TimeInForce::ImmediateOrCancel { fill_or_kill: true }))
+                .short_sell(true)
+                .priority_fee(Decimal::new(5, 1))
+                .client_order_id(Some("A-1".into()))
+                .reduce_only(true)
+                .build()
+        );
+        let time_in_force = |line: &str| match terse(line).unwrap() {
+            OrderRequest::Create { time_in_force, .. } => time_in_force,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            time_in_force("S 1@2 PO"),
+            Some(TimeInForce::GoodTilCancel { post_only: true })
+        );
+        assert_eq!(
+            time_in_force("S 1@2 GTD=60000 PO"),
+            Some(TimeInForce::GoodTilDate {
+                expires_at: 60_000,
+                post_only: true
+            })
+        );
+        assert_eq!(time_in_force("S 1@2 IO"), Some(TimeInForce::ImbalanceOnly));
+        assert_eq!(
+            time_in_force("S 1@2 po day"),
+            Some(TimeInForce::Day { post_only: true })
+        );
+    }
+
+    #[rstest]
+    fn parse_cancels() {
+        assert_eq!(terse("C 42").unwrap(), util::cancel(42));
+        assert_eq!(
+            terse("cancel client=A-1 account=2").unwrap(),
+            OrderRequest::CancelByClientId {
+                account_id: "2".into(),
+                client_order_id: "A-1".into()
+            }
+        );
+        assert_eq!(terse("CA").unwrap(), OrderRequest::CancelAll { account_id: "1".into() });
+    }
+
+    #[rstest]
+    #[case("", TerseError::Missing("command"))]
+    #[case("Q 1@2", TerseError::UnknownCommand("Q".into()))]
+    #[case("B", TerseError::Missing("quantity"))]
+    #[case("B x@1", TerseError::InvalidNumber("x".into()))]
+    #[case("B 1@-2", TerseError::InvalidNumber("-2".into()))]
+    #[case("B 1@2 IOC PO", TerseError::Conflicting("IOC".into()))]
+    #[case("B 1@2 IOC FOK", TerseError::Conflicting("FOK".into()))]
+    #[case("B 1@2 NOW", TerseError::UnexpectedToken("NOW".into()))]
+    #[case("C", TerseError::Missing("order id"))]
+    #[case("C 42 IOC", TerseError::UnexpectedToken("IOC".into()))]
+    #[case("CA 42", TerseError::Conflicting("CA".into()))]
+    fn reject_invalid_requests(#[case] line: &str, #[case] error: TerseError) {
+        assert_eq!(terse(line), Err(error));
+    }
+
+    #[rstest]
+    fn create_needs_an_order_id() {
+        assert_eq!(parse("B 1@2", DEFAULTS, || None), Err(TerseError::Missing("order id")));
+    }
+}
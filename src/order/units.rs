@@ -0,0 +1,296 @@
+use std::{
+    fmt::{Debug, Display},
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Sub, SubAssign},
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// non-negative decimals with a bounded scale, checked when built from a decimal (and so when deserialized), the
+// arithmetic between them keeps them apart: a price times a quantity is a notional, a plain decimal
+macro_rules! non_negative_decimal {
+    ($name:ident, $max_scale:expr) => {
+        #[derive(Clone, Copy, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(try_from = "Decimal", into = "Decimal")]
+        pub struct $name(Decimal);
+
+        impl $name {
+            pub const ZERO: Self = Self(Decimal::ZERO);
+            pub const MAX_SCALE: u32 = $max_scale;
+
+            // same as Decimal::new, it panics with a scale over the maximum
+            #[inline]
+            pub const fn new(num: u64, scale: u32) -> Self {
+                assert!(scale <= Self::MAX_SCALE, "scale over the maximum");
+                Self(Decimal::from_parts(
+                    num as u32,
+                    (num >> 32) as u32,
+                    0,
+                    false,
+                    scale,
+                ))
+            }
+
+            #[inline]
+            pub fn value(self) -> Decimal {
+                self.0
+            }
+
+            #[inline]
+            pub fn is_zero(self) -> bool {
+                self.0.is_zero()
+            }
+
+            // none when it would go below zero
+            #[inline]
+            pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+                (self >= rhs).then(|| Self(self.0 - rhs.0))
+            }
+
+            // for what the engine computes (e.g. averages), it must not be negative and is rounded to the maximum scale
+            #[inline]
+            pub(crate) fn from_decimal(value: Decimal) -> Self {
+                debug_assert!(
+                    !value.is_sign_negative() || value.is_zero(),
+                    "negative {}: {value}",
+                    stringify!($name)
+                );
+                Self(value.max(Decimal::ZERO).round_dp(Self::MAX_SCALE))
+            }
+        }
+
+        impl TryFrom<Decimal> for $name {
+            type Error = UnitError;
+
+            fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+                if value.is_sign_negative() && !value.is_zero() {
+                    return Err(UnitError::Negative(value));
+                }
+                if value.normalize().scale() > Self::MAX_SCALE {
+                    return Err(UnitError::ScaleTooLarge {
+                        value,
+                        max_scale: Self::MAX_SCALE,
+                    });
+                }
+                Ok(Self(value))
+            }
+        }
+
+        impl From<u32> for $name {
+            #[inline]
+            fn from(value: u32) -> Self {
+                Self(value.into())
+            }
+        }
+
+        impl From<$name> for Decimal {
+            #[inline]
+            fn from(value: $name) -> Decimal {
+                value.0
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = UnitError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let value = Decimal::from_str(s).map_err(|_| UnitError::Invalid(s.into()))?;
+                value.try_into()
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                Display::fmt(&self.0, f)
+            }
+        }
+
+        // printed as the decimal it wraps, like in the logs and digests from before the type
+        impl Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            #[inline]
+            fn add(self, rhs: Self) -> Self {
+                Self(self.0 + rhs.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            #[inline]
+            fn add_assign(&mut self, rhs: Self) {
+                self.0 += rhs.0;
+            }
+        }
+
+        // the ratio of two amounts
+        impl Div for $name {
+            type Output = Decimal;
+
+            #[inline]
+            fn div(self, rhs: Self) -> Decimal {
+                self.0 / rhs.0
+            }
+        }
+
+        // scaled by a non-negative factor
+        impl Mul<Decimal> for $name {
+            type Output = Self;
+
+            #[inline]
+            fn mul(self, rhs: Decimal) -> Self {
+                Self::from_decimal(self.0 * rhs)
+            }
+        }
+
+        impl Div<Decimal> for $name {
+            type Output = Self;
+
+            #[inline]
+            fn div(self, rhs: Decimal) -> Self {
+                Self::from_decimal(self.0 / rhs)
+            }
+        }
+
+        // a signed size or a notional against an amount, e.g. a position times a price
+        impl Mul<$name> for Decimal {
+            type Output = Decimal;
+
+            #[inline]
+            fn mul(self, rhs: $name) -> Decimal {
+                self * rhs.0
+            }
+        }
+
+        impl Div<$name> for Decimal {
+            type Output = Decimal;
+
+            #[inline]
+            fn div(self, rhs: $name) -> Decimal {
+                self / rhs.0
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold(Self::ZERO, Add::add)
+            }
+        }
+
+        impl<'a> Sum<&'a $name> for $name {
+            fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+                iter.copied().sum()
+            }
+        }
+    };
+}
+
+non_negative_decimal!(OrderPrice, 12);
+non_negative_decimal!(OrderQuantity, 12);
+
+// the difference of two prices can be negative, it is a plain decimal
+impl Sub for OrderPrice {
+    type Output = Decimal;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Decimal {
+        self.0 - rhs.0
+    }
+}
+
+// only what is there can be taken away: the quantities never go below zero
+impl Sub for OrderQuantity {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self::from_decimal(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for OrderQuantity {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+// notional
+impl Mul<OrderQuantity> for OrderPrice {
+    type Output = Decimal;
+
+    #[inline]
+    fn mul(self, rhs: OrderQuantity) -> Decimal {
+        self.0 * rhs.0
+    }
+}
+
+impl Mul<OrderPrice> for OrderQuantity {
+    type Output = Decimal;
+
+    #[inline]
+    fn mul(self, rhs: OrderPrice) -> Decimal {
+        self.0 * rhs.0
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum UnitError {
+    #[error("negative value! {0}")]
+    Negative(Decimal),
+    #[error("scale too large (value={}, max_scale={})", .value, .max_scale)]
+    ScaleTooLarge { value: Decimal, max_scale: u32 },
+    #[error("invalid decimal! {0}")]
+    Invalid(compact_str::CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn check_what_comes_in() {
+        assert_eq!(OrderPrice::try_from(Decimal::new(135, 1)), Ok(OrderPrice::new(135, 1)));
+        assert_eq!(
+            OrderPrice::try_from(Decimal::NEGATIVE_ONE),
+            Err(UnitError::Negative(Decimal::NEGATIVE_ONE))
+        );
+        assert!(matches!(
+            OrderQuantity::try_from(Decimal::new(1, 13)),
+            Err(UnitError::ScaleTooLarge { max_scale: 12, .. })
+        ));
+        // trailing zeros do not count
+        assert!(OrderQuantity::try_from(Decimal::new(10_000, 14)).is_ok());
+        assert_eq!("1.5".parse::<OrderQuantity>(), Ok(OrderQuantity::new(15, 1)));
+    }
+
+    #[rstest]
+    fn keep_prices_and_quantities_apart() {
+        let price = OrderPrice::from(15);
+        let quantity = OrderQuantity::new(25, 1);
+        assert_eq!(price * quantity, Decimal::new(375, 1));
+        assert_eq!(OrderPrice::from(14) - price, Decimal::NEGATIVE_ONE);
+        assert_eq!(quantity - OrderQuantity::from(1), OrderQuantity::new(15, 1));
+        assert_eq!(
+            [quantity, quantity].iter().sum::<OrderQuantity>(),
+            OrderQuantity::from(5)
+        );
+    }
+
+    #[rstest]
+    fn serialize_as_decimals() {
+        let price: OrderPrice = serde_json::from_str(r#""13.50""#).unwrap();
+        assert_eq!(serde_json::to_string(&price).unwrap(), r#""13.50""#);
+        assert!(serde_json::from_str::<OrderQuantity>(r#""-1""#).is_err());
+    }
+}
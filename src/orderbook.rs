@@ -1,27 +1,38 @@
 use std::{
     cmp::Reverse,
-    collections::{btree_map::Entry, BTreeMap, VecDeque},
+    collections::{btree_map::Entry, BTreeMap},
     fmt::Display,
     ops::{Deref, DerefMut},
 };
 
 use anyhow::Result;
-use indexmap::IndexMap;
-use num::Zero;
-use rust_decimal::Decimal;
+use compact_str::CompactString;
+use indexmap::{IndexMap, IndexSet};
+use num::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    order::{Order, OrderFeatures, OrderId, OrderPrice, OrderQuantity, OrderSide},
+    clock::Clock,
+    market_data::{Bbo, BookEvent},
+    order::{Order, OrderFeatures, OrderId, OrderPrice, OrderQuantity, OrderSide, TimeInForce},
     trade::{Trade, TradeError, TradeId},
 };
 
-const DEFAULT_LEVEL_SIZE: usize = 8;
+#[cfg(feature = "metrics")]
+use crate::metrics::prometheus::Histogram;
+
+pub use self::arena::Priority;
+use self::arena::{Arena, Handle, Queue};
+
+mod arena;
+pub mod microstructure;
 
 trait Ladder: Deref + DerefMut {
-    fn insert(&mut self, order: &Order) -> Result<&mut Self, OrderbookError>;
+    fn insert(&mut self, arena: &mut Arena, order: Order, ticks: i64) -> Result<Handle, OrderbookError>;
 
-    fn remove(&mut self, order: &Order) -> Result<&mut Self, OrderbookError>;
+    fn remove(&mut self, arena: &mut Arena, handle: Handle, ticks: i64) -> Result<Order, OrderbookError>;
 }
 
 #[derive(Default)]
@@ -42,128 +53,180 @@ impl<T> DerefMut for LadderWrapper<T> {
 }
 
 impl<K: Ord> LadderWrapper<BTreeMap<K, PriceLevel>> {
-    fn peek_top<'a>(&'a self, orders: &'a IndexMap<OrderId, Order>) -> Option<&'a Order> {
+    fn peek_top<'a>(&'a self, orders: &'a Arena) -> Option<&'a Order> {
         self.first_key_value()
             .map(|(_, level)| level)?
             .front()
-            .and_then(|order_id| orders.get(order_id))
+            .map(|handle| orders.order(handle))
+    }
+
+    // levels are already sorted best-first by the key of each ladder
+    fn depth(&self, levels: usize) -> Vec<DepthLevel> {
+        self.values()
+            .take(levels)
+            .map(|level| (level.price, level.quantity, level.len()))
+            .collect()
+    }
+}
+
+// the key of a level in a ladder: the ticks themselves (asks, lowest first) or reversed (bids, highest first)
+trait LadderKey: Ord {
+    fn key(ticks: Ticks) -> Self;
+}
+
+impl LadderKey for Ticks {
+    #[inline]
+    fn key(ticks: Ticks) -> Self {
+        ticks
     }
 }
 
-impl Ladder for LadderWrapper<BTreeMap<OrderPrice, PriceLevel>> {
-    fn insert(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
+impl LadderKey for Reverse<Ticks> {
+    #[inline]
+    fn key(ticks: Ticks) -> Self {
+        Reverse(ticks)
+    }
+}
+
+impl<K: LadderKey> Ladder for LadderWrapper<BTreeMap<K, PriceLevel>> {
+    fn insert(&mut self, arena: &mut Arena, order: Order, ticks: i64) -> Result<Handle, OrderbookError> {
         let limit_price = order
             .limit_price()
-            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(*order))?;
+            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(order))?;
+        // before the level is touched, a duplicate leaves the book as it was
+        let handle = arena
+            .insert(order)
+            .ok_or(OrderbookError::DuplicateOrderId(order.id()))?;
         let price_level = self
             .0
-            .entry(limit_price)
-            .or_insert_with(|| PriceLevel::new(limit_price));
+            .entry(K::key(ticks))
+            .or_insert_with(|| PriceLevel::new(limit_price, ticks));
 
         price_level.quantity += order.remaining();
-        price_level.push_back(order.id());
+        price_level.push_back(arena, handle);
 
-        Ok(self)
+        Ok(handle)
     }
 
-    fn remove(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
-        let limit_price = order
-            .limit_price()
-            .ok_or(OrderbookError::OrderToRemoveWithNoLimitPrice(*order))?;
-        let Entry::Occupied(mut price_level) = self.0.entry(limit_price) else {
+    fn remove(&mut self, arena: &mut Arena, handle: Handle, ticks: i64) -> Result<Order, OrderbookError> {
+        let order = *arena.order(handle);
+        let Entry::Occupied(mut price_level) = self.0.entry(K::key(ticks)) else {
             unreachable!();
         };
 
-        if price_level.get().len() == 1 {
+        price_level.get_mut().unlink(arena, handle);
+        if price_level.get().is_empty() {
             price_level.remove();
         } else {
-            let price_level = price_level.get_mut();
-            price_level.quantity -= order.remaining();
-            if let Some(idx) = price_level.iter().position(|&order_id| order.id() == order_id) {
-                price_level.remove(idx);
-            }
+            price_level.get_mut().quantity -= order.remaining();
         }
 
-        Ok(self)
+        Ok(arena.remove(handle))
     }
 }
 
-impl Ladder for LadderWrapper<BTreeMap<Reverse<OrderPrice>, PriceLevel>> {
-    fn insert(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
-        let limit_price = order
-            .limit_price()
-            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(*order))?;
-        let price_level = self
-            .0
-            .entry(Reverse(limit_price))
-            .or_insert_with(|| PriceLevel::new(limit_price));
+type Ticks = i64;
+type AsksLadder = LadderWrapper<BTreeMap<Ticks, PriceLevel>>;
+type BidsLadder = LadderWrapper<BTreeMap<Reverse<Ticks>, PriceLevel>>;
 
-        price_level.quantity += order.remaining();
-        price_level.push_back(order.id());
+// finest tick supported when none is given
+pub const DEFAULT_TICK_SIZE: OrderPrice = OrderPrice::new(1, 8);
+
+// the book keys and compares its prices as whole ticks, Decimal is only converted (checked) at its boundary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickSize(OrderPrice);
 
-        Ok(self)
+impl Default for TickSize {
+    fn default() -> Self {
+        Self(DEFAULT_TICK_SIZE)
     }
+}
 
-    fn remove(&mut self, order: &Order) -> Result<&mut Self, OrderbookError> {
-        let limit_price = order
-            .limit_price()
-            .ok_or(OrderbookError::OrderToRemoveWithNoLimitPrice(*order))?;
-        let Entry::Occupied(mut price_level) = self.0.entry(Reverse(limit_price)) else {
-            unreachable!();
-        };
+impl TickSize {
+    #[inline]
+    pub fn new(tick_size: OrderPrice) -> Option<Self> {
+        (!tick_size.is_zero()).then_some(Self(tick_size))
+    }
 
-        if price_level.get().len() == 1 {
-            price_level.remove();
-        } else {
-            let price_level = price_level.get_mut();
-            price_level.quantity -= order.remaining();
-            if let Some(idx) = price_level.iter().position(|&order_id| order.id() == order_id) {
-                price_level.remove(idx);
-            }
+    // none when the price is off the tick or out of range
+    #[inline]
+    pub fn to_ticks(&self, price: OrderPrice) -> Option<Ticks> {
+        let ticks = price.value().checked_div(self.0.value())?;
+        if !ticks.fract().is_zero() {
+            return None;
         }
+        ticks.to_i64()
+    }
 
-        Ok(self)
+    #[inline]
+    pub fn to_price(&self, ticks: Ticks) -> OrderPrice {
+        self.0 * Decimal::from(ticks)
+    }
+
+    // to a whole number of ticks, one at least
+    pub fn round(&self, price: OrderPrice, strategy: RoundingStrategy) -> OrderPrice {
+        let ticks = (price.value() / self.0.value()).round_dp_with_strategy(0, strategy);
+        (self.0 * ticks).max(self.0)
     }
 }
 
-type AsksLadder = LadderWrapper<BTreeMap<OrderPrice, PriceLevel>>;
-type BidsLadder = LadderWrapper<BTreeMap<Reverse<OrderPrice>, PriceLevel>>;
+// aggregated price level: (price, total quantity, order count)
+pub type DepthLevel = (OrderPrice, OrderQuantity, usize);
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Depth {
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+// auction at a single price: the one with the most volume, then the smallest imbalance, then the lowest one
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Uncross {
+    pub price: OrderPrice,
+    pub matched: OrderQuantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imbalance_side: Option<OrderSide>, // the side left with quantity at the price
+    pub imbalance: OrderQuantity,
+}
+
+// the orders of a level are queued in the arena of the book (see Queue), a level allocates nothing of its own
 #[derive(Debug)]
 pub struct PriceLevel {
-    order_ids: VecDeque<OrderId>,
+    queue: Queue,
     quantity: OrderQuantity,
     price: OrderPrice,
+    ticks: Ticks, // the price in ticks of the book
 }
 
 impl PriceLevel {
-    fn new(price: OrderPrice) -> Self {
+    fn new(price: OrderPrice, ticks: Ticks) -> Self {
         Self {
-            order_ids: VecDeque::with_capacity(DEFAULT_LEVEL_SIZE),
-            quantity: Decimal::ZERO,
+            queue: Queue::default(),
+            quantity: OrderQuantity::ZERO,
             price,
+            ticks,
         }
     }
 }
 
 impl Deref for PriceLevel {
-    type Target = VecDeque<OrderId>;
+    type Target = Queue;
 
     fn deref(&self) -> &Self::Target {
-        &self.order_ids
+        &self.queue
     }
 }
 
 impl DerefMut for PriceLevel {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.order_ids
+        &mut self.queue
     }
 }
 
 impl PriceLevel {
     #[inline]
     fn is_closed(&self) -> bool {
-        self.quantity == OrderQuantity::zero()
+        self.quantity.is_zero()
     }
 
     #[inline]
@@ -171,20 +234,20 @@ impl PriceLevel {
         self.quantity.min(order.remaining()) != OrderQuantity::ZERO
     }
 
+    // the limit price of the order in ticks, see Orderbook::ticks
     #[inline]
-    pub fn matches(&self, order: &Order) -> bool {
+    fn matches(&self, order: &Order, limit_ticks: Option<Ticks>) -> bool {
         let level = self;
 
         if level.is_closed() || order.is_closed() {
             return false;
         }
 
-        let level_limit_price = self.price;
-        match order.limit_price() {
+        match limit_ticks {
             // limit price == limit order
-            Some(order_limit_price) => match order.side() {
-                OrderSide::Ask => order_limit_price <= level_limit_price,
-                OrderSide::Bid => order_limit_price >= level_limit_price,
+            Some(limit_ticks) => match order.side() {
+                OrderSide::Ask => limit_ticks <= level.ticks,
+                OrderSide::Bid => limit_ticks >= level.ticks,
             },
             None => true, // no limit price == market order
         }
@@ -193,63 +256,217 @@ impl PriceLevel {
 
 impl Display for PriceLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} [{:?}]", self.quantity, self.order_ids)
+        write!(f, "{} @ {} [{} orders]", self.quantity, self.price, self.len())
+    }
+}
+
+// what can be bought for the budget at the price, cut to the scale of the quantity of the order
+#[inline]
+fn affordable(budget: Decimal, price: OrderPrice, scale: u32) -> OrderQuantity {
+    if budget <= Decimal::ZERO {
+        return OrderQuantity::ZERO;
     }
+    let quantity = budget.checked_div(price.value()).unwrap_or(Decimal::MAX);
+    OrderQuantity::from_decimal(quantity.round_dp_with_strategy(scale, RoundingStrategy::ToZero))
+}
+
+// share of the quantity of every order of a level (in queue order) by its remaining quantity, cut to the scale of the
+// incoming quantity, what the rounding leaves goes in time priority; with top priority the first order is served first
+fn pro_rata(
+    quantity: OrderQuantity,
+    remainings: &[OrderQuantity],
+    top_priority: bool,
+    scale: u32,
+) -> Vec<OrderQuantity> {
+    let mut allocations = vec![OrderQuantity::ZERO; remainings.len()];
+    let mut left = quantity;
+    let skip = match (top_priority, remainings.first()) {
+        (true, Some(top)) => {
+            allocations[0] = left.min(*top);
+            left -= allocations[0];
+            1
+        }
+        _ => 0,
+    };
+
+    let total: OrderQuantity = remainings[skip..].iter().sum();
+    if !total.is_zero() {
+        let share = left.value() / total.value();
+        for (allocation, remaining) in allocations[skip..].iter_mut().zip(&remainings[skip..]) {
+            let quantity = (share * remaining.value()).round_dp_with_strategy(scale, RoundingStrategy::ToZero);
+            *allocation = OrderQuantity::from_decimal(quantity).min(*remaining);
+            left -= *allocation;
+        }
+    }
+    for (allocation, remaining) in allocations.iter_mut().zip(remainings) {
+        let extra = left.min(*remaining - *allocation);
+        *allocation += extra;
+        left -= extra;
+    }
+    allocations
 }
 
 macro_rules! match_order {
-    ($incoming_order:ident, $orders:ident, $trades:ident, $order_ladder:ident, $opposite_ladder:ident) =>  {
+    ($incoming_order:ident, $limit_ticks:ident, $tick_size:ident, $orders:ident, $trades:ident, $events:ident, $post_only_tick:ident, $sweep_limits:ident, $matching_policy:ident, $yield_point:ident, $order_ladder:ident, $opposite_ladder:ident) =>  {
         'exit: {
-        // PostOnly orders should go directly to the book; otherwise, if they can be matched inmediately, then they should be canceled
-        if $incoming_order.is_post_only()
-            && $opposite_ladder
+        // PostOnly orders should go directly to the book; if they would cross the spread they are either rejected or
+        // re-priced one tick behind the best opposite price
+        if $incoming_order.is_post_only() {
+            let crossed = $opposite_ladder
                 .peek_top($orders)
-                .is_some_and(|top_order| $incoming_order.matches(top_order))
-        {
-            $incoming_order.cancel();
-            break 'exit Ok(false);
+                .filter(|top_order| $incoming_order.matches(top_order))
+                .and_then(|top_order| top_order.limit_price());
+            if let Some(best_price) = crossed {
+                let repriced = $post_only_tick.and_then(|tick_size| match $incoming_order.side() {
+                    OrderSide::Ask => Some(best_price + tick_size),
+                    OrderSide::Bid => best_price.checked_sub(tick_size).filter(|price| !price.is_zero()),
+                });
+                match repriced {
+                    Some(limit_price) => {
+                        $incoming_order.reprice(limit_price);
+                        $limit_ticks = $tick_size.to_ticks(limit_price);
+                        if $limit_ticks.is_none() {
+                            $incoming_order.cancel();
+                            break 'exit Err(OrderbookError::PriceNotOnTick {
+                                order_id: $incoming_order.id(),
+                                price: limit_price,
+                                tick_size: $tick_size.0,
+                            });
+                        }
+                    }
+                    _ => {
+                        $incoming_order.cancel();
+                        break 'exit Err(OrderbookError::PostOnlyWouldCross {
+                            order_id: $incoming_order.id(),
+                            limit_price: $incoming_order.limit_price().unwrap_or_default(),
+                            best_price,
+                        });
+                    }
+                }
+            }
         }
 
-        // FOK orders should be canceled if they cannot be fill completely
+        let max_levels = $sweep_limits.and_then(|sweep_limits| sweep_limits.max_levels);
+        let max_notional = $sweep_limits.and_then(|sweep_limits| sweep_limits.max_notional);
+        let scale = $incoming_order.remaining().value().scale();
+
+        // FOK orders are rejected before any trade if the liquidity at acceptable prices (and within the sweep limits)
+        // cannot fill them completely
         if $incoming_order.is_fill_or_kill() {
-            let mut can_be_filled = false;
-            let mut remaining = $incoming_order.remaining();
-                for (_, price_level) in $opposite_ladder.iter_mut() {
-                if $incoming_order.is_closed() || !price_level.matches(& $incoming_order) {
+            let requested = $incoming_order.remaining();
+            let mut available = OrderQuantity::ZERO;
+            let mut budget = max_notional;
+            for (levels, price_level) in $opposite_ladder.values().enumerate() {
+                if available >= requested
+                    || !price_level.matches(&$incoming_order, $limit_ticks)
+                    || max_levels.is_some_and(|max_levels| levels >= max_levels)
+                {
                     break;
                 }
-                remaining -= price_level.quantity;
-                if (remaining <= Decimal::ZERO) {
-                    can_be_filled = true;
-                    break;
+                let mut quantity = price_level.quantity;
+                if let Some(budget) = budget.as_mut() {
+                    quantity = quantity.min(affordable(*budget, price_level.price, scale));
+                    *budget -= price_level.price * quantity;
                 }
+                available += quantity;
             }
-            if !can_be_filled {
+            if available < requested {
                 $incoming_order.cancel();
-                break 'exit Ok(false);
+                break 'exit Err(OrderbookError::FillOrKillNotFillable {
+                    order_id: $incoming_order.id(),
+                    requested,
+                    available,
+                });
             }
         }
 
         let mut matched = false;
         let mut trades: Vec<Trade> = vec![];
+        let mut fills = 0;
         let mut drained_levels = 0;
+        let mut swept_levels = 0;
+        let mut swept_notional = Decimal::ZERO;
+        let mut capped = false; // by the sweep limits
 
         for (_, price_level) in $opposite_ladder.iter_mut() {
-            if $incoming_order.is_closed() || !price_level.matches(&$incoming_order) {
+            if $incoming_order.is_closed() || !price_level.matches(&$incoming_order, $limit_ticks) {
+                break;
+            }
+            if max_levels.is_some_and(|max_levels| swept_levels >= max_levels) {
+                capped = true;
                 break;
             }
+            swept_levels += 1;
+
+            // a level the order does not take entirely is shared out as the policy says
+            let allocations = match $matching_policy {
+                MatchingPolicy::Fifo => None,
+                _ if $incoming_order.remaining() >= price_level.quantity => None,
+                policy => {
+                    let remainings: Vec<OrderQuantity> =
+                        price_level.iter($orders).map(|(_, order)| order.remaining()).collect();
+                    let top_priority = policy == MatchingPolicy::ProRataTopPriority;
+                    Some(pro_rata($incoming_order.remaining(), &remainings, top_priority, scale))
+                }
+            };
+            let mut position = 0;
 
             let mut total_traded = OrderQuantity::ZERO;
             let mut orders_completed = 0;
 
-            for order_id in price_level.iter_mut() {
-                let maker = $orders
-                    .get_mut(order_id)
-                    .ok_or(OrderbookError::OrderToMatchNotFound(*order_id))?;
-                let traded = $incoming_order.can_trade(maker);
+            let mut cursor = price_level.front();
+            while let Some(handle) = cursor {
+                // no empty trades against the rest of the level once the incoming order is filled
+                if $incoming_order.is_closed() || capped {
+                    break;
+                }
+
+                cursor = $orders.next(handle);
+                let maker = $orders.order_mut(handle);
+                let mut traded = $incoming_order.can_trade(maker);
+                if let Some(allocations) = &allocations {
+                    traded = traded.min(allocations[position]);
+                    position += 1;
+                    if traded.is_zero() {
+                        continue;
+                    }
+                }
+                if let Some(max_notional) = max_notional {
+                    let budget = affordable(max_notional - swept_notional, price_level.price, scale);
+                    if budget < traded {
+                        traded = budget;
+                        capped = true;
+                    }
+                    if traded.is_zero() {
+                        break;
+                    }
+                    swept_notional += price_level.price * traded;
+                }
 
                 let trade = Trade::new(&mut $incoming_order, maker, traded).map_err(OrderbookError::TradeError)?;
                 trades.push(trade);
+                fills += 1;
+
+                if let Some(events) = $events.as_mut() {
+                    events.push(BookEvent::Trade {
+                        trade_id: trade.id(),
+                        maker: maker.id(),
+                        taker: trade.taker(),
+                        side: $incoming_order.side(),
+                        price: trade.price(),
+                        quantity: traded,
+                    });
+                    events.push(if maker.is_closed() {
+                        BookEvent::Delete { order_id: Some(maker.id()), side: maker.side(), price: trade.price() }
+                    } else {
+                        BookEvent::Modify {
+                            order_id: Some(maker.id()),
+                            side: maker.side(),
+                            price: trade.price(),
+                            quantity: maker.remaining(),
+                        }
+                    });
+                }
 
                 matched = true;
 
@@ -257,16 +474,45 @@ macro_rules! match_order {
                 if maker.is_closed() {
                     orders_completed += 1;
                 }
+
+                // the order goes on from this very fill once the handler is done
+                if let Some((fills_per_yield, handler)) = $yield_point.as_mut() {
+                    if fills % *fills_per_yield == 0 && !$incoming_order.is_closed() {
+                        handler.on_yield(YieldPoint {
+                            order_id: $incoming_order.id(),
+                            fills,
+                            levels: swept_levels,
+                        });
+                    }
+                }
             }
 
             price_level.quantity -= total_traded;
-            for _ in 0..orders_completed {
-                price_level.pop_front().and_then(|order_id| $orders.remove(& order_id));
+            if allocations.is_none() {
+                // in time priority the completed orders are the first ones
+                for _ in 0..orders_completed {
+                    if let Some(handle) = price_level.front() {
+                        price_level.unlink($orders, handle);
+                        $orders.remove(handle);
+                    }
+                }
+            } else if orders_completed > 0 {
+                let mut cursor = price_level.front();
+                while let Some(handle) = cursor {
+                    cursor = $orders.next(handle);
+                    if $orders.order(handle).is_closed() {
+                        price_level.unlink($orders, handle);
+                        $orders.remove(handle);
+                    }
+                }
             }
 
             if price_level.quantity == OrderQuantity::ZERO {
                 drained_levels += 1;
             }
+            if capped {
+                break;
+            }
         }
         for _ in 0..drained_levels {
             $opposite_ladder.pop_first();
@@ -277,86 +523,862 @@ macro_rules! match_order {
             $trades.insert(trade.id(), trade);
         }
 
+        // what a capped sweep leaves is cancelled, or rests one tick behind the best opposite price rather than cross it
+        if capped && !$incoming_order.is_closed() {
+            let best_price = $opposite_ladder
+                .peek_top($orders)
+                .filter(|top_order| $incoming_order.matches(top_order))
+                .and_then(|top_order| top_order.limit_price());
+            let resting = match ($sweep_limits.map(|sweep_limits| sweep_limits.remainder), best_price) {
+                (Some(SweepRemainder::Rest), None) => $limit_ticks.is_some(),
+                (Some(SweepRemainder::Rest), Some(best_price)) => {
+                    let repriced = match $incoming_order.side() {
+                        OrderSide::Ask => Some(best_price + $tick_size.0),
+                        OrderSide::Bid => best_price.checked_sub($tick_size.0).filter(|price| !price.is_zero()),
+                    };
+                    match (repriced, $limit_ticks) {
+                        (Some(limit_price), Some(_)) => {
+                            $incoming_order.reprice(limit_price);
+                            $limit_ticks = $tick_size.to_ticks(limit_price);
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+                _ => false,
+            };
+            if !resting {
+                $incoming_order.cancel();
+            }
+        }
+
         // IOC orders should be closed at the end of the matching phase (this is, no insertion in the book)
         if $incoming_order.is_immediate_or_cancel() {
             $incoming_order.cancel();
             break 'exit Ok(matched);
         }
 
-        // insert limit order in the book
-        if !$incoming_order.is_closed() && $incoming_order.is_bookable() {
-            $order_ladder.insert(&$incoming_order)?;
-            $orders.insert($incoming_order.id(), $incoming_order);
-        }
+        // insert limit order in the book
+        if let (false, Some(limit_ticks)) = ($incoming_order.is_closed(), $limit_ticks) {
+            $order_ladder.insert($orders, $incoming_order, limit_ticks)?;
+            if let (Some(events), Some(price)) = ($events.as_mut(), $incoming_order.limit_price()) {
+                events.push(BookEvent::Add {
+                    order_id: Some($incoming_order.id()),
+                    side: $incoming_order.side(),
+                    price,
+                    quantity: $incoming_order.remaining(),
+                });
+            }
+        }
+
+        Ok(matched)
+    }};
+}
+
+// how an aggressive order is shared out among the orders of the last level it reaches (the ones before are taken
+// entirely whatever the policy)
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MatchingPolicy {
+    #[default]
+    Fifo, // price-time
+    ProRata,            // by remaining quantity
+    ProRataTopPriority, // the first order of the level is filled first, then pro-rata
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SweepRemainder {
+    #[default]
+    Cancel,
+    Rest, // limit orders only, market orders are cancelled
+}
+
+// caps on what a single aggressive order may take in one pass, bounding the work of matching it: once one is reached,
+// the remainder is handled as configured
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SweepLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_levels: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_notional: Option<Decimal>,
+    #[serde(default)]
+    pub remainder: SweepRemainder,
+}
+
+// where a long sweep stops between two fills, the incoming order being partially filled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct YieldPoint {
+    pub order_id: OrderId,
+    pub fills: usize,  // of the incoming order so far
+    pub levels: usize, // reached so far, the current one included
+}
+
+// called from inside the matching loop of a sweep (e.g. a cascade through a thin book) so that the thread it runs on
+// can flush what it buffered or answer heartbeats before the sweep goes on; it has no access to the book, the sweep
+// then goes on exactly as without it
+pub trait YieldHandler: Send {
+    fn on_yield(&mut self, yield_point: YieldPoint);
+}
+
+// experimental: how the orders of a price level are queued
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum QueuePriority {
+    #[default]
+    Time,
+    PriorityFee, // highest priority fee first, then time
+}
+
+#[derive(Default)]
+pub struct Orderbook {
+    asks: AsksLadder,
+    bids: BidsLadder,
+    orders: Arena, // resting orders, queued by level (see PriceLevel)
+    trades: IndexMap<TradeId, Trade>,
+    events: Option<Vec<BookEvent>>, // only recorded when there is someone to publish them to
+    tick_size: TickSize,
+    post_only_tick: Option<OrderPrice>, // post-only orders crossing the spread are rejected unless set
+    auction_orders: Vec<Order>,
+    queue_priority: QueuePriority,
+    sweep_limits: Option<SweepLimits>,
+    matching_policy: MatchingPolicy,
+    yield_point: Option<(usize, Box<dyn YieldHandler>)>, // every so many fills of an aggressive order
+    accounts: IndexMap<CompactString, IndexSet<OrderId>>, // open orders of every account (see assign_account)
+    owners: IndexMap<OrderId, CompactString>,
+    // client order ids of the open orders, kept apart from the orders like their account (see assign_client_order_id)
+    client_order_ids: IndexMap<CompactString, IndexMap<CompactString, OrderId>>, // by account
+    client_ids: IndexMap<OrderId, CompactString>,
+    handled: IndexSet<OrderId>, // every order id the book took, resting or gone: none is taken twice
+    clock: Option<Box<dyn Clock>>, // times the orders joining the book, they are all at 0 without one
+    #[cfg(feature = "metrics")]
+    fills_per_order: Histogram,
+}
+
+type MatchResult = Result<bool, OrderbookError>;
+#[cfg(test)]
+const MATCHED: MatchResult = Ok(true);
+#[cfg(test)]
+const NOT_MATCHED: MatchResult = Ok(false);
+
+type CancelResult = Result<Order, OrderbookError>;
+
+impl Orderbook {
+    // limit prices off the tick are rejected, a tick size that is not positive is ignored
+    pub fn with_tick_size(mut self, tick_size: OrderPrice) -> Self {
+        if let Some(tick_size) = TickSize::new(tick_size) {
+            self.tick_size = tick_size;
+        }
+        self
+    }
+
+    #[inline]
+    pub fn tick_size(&self) -> TickSize {
+        self.tick_size
+    }
+
+    // checked conversion of the limit price into ticks, none for market orders
+    fn ticks(&self, order: &Order) -> Result<Option<Ticks>, OrderbookError> {
+        let Some(price) = order.limit_price() else {
+            return Ok(None);
+        };
+        let ticks = self.tick_size.to_ticks(price).ok_or(OrderbookError::PriceNotOnTick {
+            order_id: order.id(),
+            price,
+            tick_size: self.tick_size.0,
+        })?;
+        Ok(Some(ticks))
+    }
+
+    pub fn with_post_only_repricing(mut self, tick_size: OrderPrice) -> Self {
+        self.post_only_tick = Some(tick_size);
+        self
+    }
+
+    pub fn with_queue_priority(mut self, queue_priority: QueuePriority) -> Self {
+        self.queue_priority = queue_priority;
+        self
+    }
+
+    #[inline]
+    pub fn queue_priority(&self) -> QueuePriority {
+        self.queue_priority
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    pub fn with_sweep_limits(mut self, sweep_limits: SweepLimits) -> Self {
+        self.sweep_limits = Some(sweep_limits);
+        self
+    }
+
+    #[inline]
+    pub fn sweep_limits(&self) -> Option<SweepLimits> {
+        self.sweep_limits
+    }
+
+    pub fn with_matching_policy(mut self, matching_policy: MatchingPolicy) -> Self {
+        self.matching_policy = matching_policy;
+        self
+    }
+
+    #[inline]
+    pub fn matching_policy(&self) -> MatchingPolicy {
+        self.matching_policy
+    }
+
+    // a budget of fills per aggressive order between two calls of the handler, zero is taken as one
+    pub fn with_yield_point(mut self, fills: usize, handler: impl YieldHandler + 'static) -> Self {
+        self.yield_point = Some((fills.max(1), Box::new(handler)));
+        self
+    }
+
+    #[inline]
+    fn stamp(&mut self, order_id: OrderId) {
+        if let (Some(clock), Some(handle)) = (self.clock.as_ref(), self.orders.handle(order_id)) {
+            self.orders.set_accepted_at(handle, clock.now());
+        }
+    }
+
+    // of a resting order
+    #[inline]
+    pub fn priority(&self, order_id: OrderId) -> Option<Priority> {
+        self.orders.handle(order_id).map(|handle| self.orders.priority(handle))
+    }
+
+    // e.g. from a snapshot, the order keeps its place in its level
+    pub fn set_priority(&mut self, order_id: OrderId, priority: Priority) {
+        if let Some(handle) = self.orders.handle(order_id) {
+            self.orders.set_priority(handle, priority);
+        }
+    }
+
+    // a new order joins the back of its level, it moves ahead of the orders paying a lower priority fee
+    fn jump_queue(&mut self, order_id: OrderId) {
+        let Some(handle) = self.orders.handle(order_id) else {
+            return;
+        };
+        let order = self.orders.order(handle);
+        let (Some(price), priority_fee) = (order.limit_price(), order.priority_fee()) else {
+            return;
+        };
+        if self.queue_priority != QueuePriority::PriorityFee || priority_fee <= Decimal::ZERO {
+            return;
+        }
+        let Some(ticks) = self.tick_size.to_ticks(price) else {
+            return;
+        };
+
+        let level = match order.side() {
+            OrderSide::Ask => self.asks.0.get_mut(&ticks),
+            OrderSide::Bid => self.bids.0.get_mut(&Reverse(ticks)),
+        };
+        let Some(level) = level.filter(|level| level.back() == Some(handle)) else {
+            return;
+        };
+        let ahead = level
+            .iter(&self.orders)
+            .find(|(_, queued)| queued.priority_fee() < priority_fee)
+            .map(|(queued, _)| queued);
+        if let Some(ahead) = ahead {
+            level.unlink(&mut self.orders, handle);
+            level.insert_before(&mut self.orders, ahead, handle);
+        }
+    }
+
+    #[inline]
+    pub fn peek_top(&self, side: &OrderSide) -> Option<&Order> {
+        match side {
+            OrderSide::Ask => self.asks.peek_top(&self.orders),
+            OrderSide::Bid => self.bids.peek_top(&self.orders),
+        }
+    }
+
+    // L2 view of the book, up to `levels` price levels per side sorted best-first
+    #[inline]
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            bids: self.bids.depth(levels),
+            asks: self.asks.depth(levels),
+        }
+    }
+
+    #[inline]
+    pub fn best_bid(&self) -> Option<OrderPrice> {
+        self.bids.first_key_value().map(|(_, level)| level.price)
+    }
+
+    #[inline]
+    pub fn best_ask(&self) -> Option<OrderPrice> {
+        self.asks.first_key_value().map(|(_, level)| level.price)
+    }
+
+    #[inline]
+    pub fn bbo(&self) -> Bbo {
+        let bid = self.bids.first_key_value().map(|(_, level)| level);
+        let ask = self.asks.first_key_value().map(|(_, level)| level);
+        Bbo {
+            bid_price: bid.map(|level| level.price),
+            bid_quantity: bid.map(|level| level.quantity),
+            ask_price: ask.map(|level| level.price),
+            ask_quantity: ask.map(|level| level.quantity),
+        }
+    }
+
+    // negative while the book is crossed (pre-open)
+    #[inline]
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()? - self.best_bid()?)
+    }
+
+    #[inline]
+    pub fn mid_price(&self) -> Option<OrderPrice> {
+        Some((self.best_ask()? + self.best_bid()?) / Decimal::TWO)
+    }
+
+    // resting orders of one side in priority order: best price first, then time
+    pub fn iter_side(&self, side: OrderSide) -> impl Iterator<Item = &Order> {
+        let (asks, bids) = match side {
+            OrderSide::Ask => (Some(self.asks.values()), None),
+            OrderSide::Bid => (None, Some(self.bids.values())),
+        };
+        asks.into_iter()
+            .flatten()
+            .chain(bids.into_iter().flatten())
+            .flat_map(|level| level.iter(&self.orders))
+            .map(|(_, order)| order)
+    }
+
+    // the book may be crossed while the orders are only resting (pre-open)
+    pub fn indicative_uncross(&self) -> Option<Uncross> {
+        let prices = self.bids.values().chain(self.asks.values()).map(|level| level.price);
+        prices
+            .filter_map(|price| {
+                let bids: OrderQuantity = self
+                    .bids
+                    .values()
+                    .take_while(|level| level.price >= price)
+                    .map(|level| level.quantity)
+                    .sum();
+                let asks: OrderQuantity = self
+                    .asks
+                    .values()
+                    .take_while(|level| level.price <= price)
+                    .map(|level| level.quantity)
+                    .sum();
+                let matched = bids.min(asks);
+                let imbalance_side = match bids.cmp(&asks) {
+                    std::cmp::Ordering::Greater => Some(OrderSide::Bid),
+                    std::cmp::Ordering::Less => Some(OrderSide::Ask),
+                    std::cmp::Ordering::Equal => None,
+                };
+                (!matched.is_zero()).then_some(Uncross {
+                    price,
+                    matched,
+                    imbalance_side,
+                    imbalance: bids.max(asks) - bids.min(asks),
+                })
+            })
+            .min_by_key(|uncross| (Reverse(uncross.matched), uncross.imbalance, uncross.price))
+    }
+
+    // add a limit order to the book without matching it (pre-open), auction orders wait apart from the book
+    pub fn rest(&mut self, order: Order) -> Result<(), OrderbookError> {
+        if self.has_handled(order.id()) {
+            return Err(OrderbookError::DuplicateOrderId(order.id()));
+        }
+        if order.is_auction_only() {
+            if !order.is_bookable() {
+                return Err(OrderbookError::OrderToInsertWithNoLimitPrice(order));
+            }
+            // on the tick like the book, the imbalance-offset leftovers go there after the uncross
+            self.ticks(&order)?;
+            self.handled.insert(order.id());
+            self.auction_orders.push(order);
+            return Ok(());
+        }
+
+        let ticks = self
+            .ticks(&order)?
+            .ok_or(OrderbookError::OrderToInsertWithNoLimitPrice(order))?;
+        self.handled.insert(order.id());
+        match order.side() {
+            OrderSide::Ask => self.asks.insert(&mut self.orders, order, ticks)?,
+            OrderSide::Bid => self.bids.insert(&mut self.orders, order, ticks)?,
+        };
+        self.stamp(order.id());
+        self.jump_queue(order.id());
+
+        if let (Some(events), Some(price)) = (self.events.as_mut(), order.limit_price()) {
+            events.push(BookEvent::Add {
+                order_id: Some(order.id()),
+                side: order.side(),
+                price,
+                quantity: order.remaining(),
+            });
+        }
+        Ok(())
+    }
+
+    // trade the crossed part of the book at the indicative price, in price-time priority on both sides; then the
+    // auction orders trade against what is left on the imbalance side
+    pub fn uncross(&mut self) -> Result<Vec<Trade>, OrderbookError> {
+        let trades = self.uncross_orders();
+        // the auction orders may be gone without trading
+        let order_ids: Vec<OrderId> = self.owners.keys().copied().collect();
+        self.forget_closed(order_ids);
+        trades
+    }
+
+    fn uncross_orders(&mut self) -> Result<Vec<Trade>, OrderbookError> {
+        let auction_orders = std::mem::take(&mut self.auction_orders);
+        let Some(Uncross { price, .. }) = self.indicative_uncross() else {
+            return self.release_auction_orders(auction_orders, vec![]);
+        };
+
+        let mut trades = vec![];
+        while let (Some(&bid), Some(&ask)) = (self.peek_top(&OrderSide::Bid), self.peek_top(&OrderSide::Ask)) {
+            if !(bid.limit_price() >= Some(price) && ask.limit_price() <= Some(price)) {
+                break;
+            }
+
+            let (mut bid, mut ask) = (bid, ask);
+            let traded = bid.can_trade(&ask);
+            let trade = Trade::at_price(&mut bid, &mut ask, traded, price)?;
+            self.push_trade(trade, OrderSide::Bid);
+            self.apply_resting_fill(bid, traded);
+            self.apply_resting_fill(ask, traded);
+            trades.push(trade);
+        }
+
+        let mut auction_orders = auction_orders;
+        for auction_order in auction_orders.iter_mut() {
+            let side = !auction_order.side();
+            let acceptable = match auction_order.side() {
+                OrderSide::Ask => auction_order.limit_price() <= Some(price),
+                OrderSide::Bid => auction_order.limit_price() >= Some(price),
+            };
+            while acceptable && !auction_order.is_closed() {
+                // the resting orders of the imbalance side, still willing to trade at the uncross price
+                let Some(&resting) = self.peek_top(&side) else {
+                    break;
+                };
+                let willing = match side {
+                    OrderSide::Ask => resting.limit_price() <= Some(price),
+                    OrderSide::Bid => resting.limit_price() >= Some(price),
+                };
+                if !willing {
+                    break;
+                }
+
+                let mut resting = resting;
+                let traded = auction_order.can_trade(&resting);
+                let trade = Trade::at_price(auction_order, &mut resting, traded, price)?;
+                self.push_trade(trade, auction_order.side());
+                self.apply_resting_fill(resting, traded);
+                trades.push(trade);
+            }
+        }
+
+        self.release_auction_orders(auction_orders, trades)
+    }
+
+    // imbalance-only orders are done after the uncross, imbalance-offset orders go on as plain limit orders
+    fn release_auction_orders(
+        &mut self,
+        auction_orders: Vec<Order>,
+        mut trades: Vec<Trade>,
+    ) -> Result<Vec<Trade>, OrderbookError> {
+        for trade in &trades {
+            self.trades.insert(trade.id(), *trade);
+        }
+
+        for auction_order in auction_orders {
+            if auction_order.is_closed() || !auction_order.is_imbalance_offset() {
+                continue;
+            }
+            let trades_from = self.trades.len();
+            let order = auction_order.with_time_in_force(TimeInForce::default());
+            let limit_ticks = self.ticks(&order)?;
+            self.create(order, limit_ticks)?;
+            trades.extend(self.trades.values().skip(trades_from).copied());
+        }
+        Ok(trades)
+    }
+
+    fn push_trade(&mut self, trade: Trade, side: OrderSide) {
+        if let Some(events) = self.events.as_mut() {
+            events.push(BookEvent::Trade {
+                trade_id: trade.id(),
+                maker: trade.maker(),
+                taker: trade.taker(),
+                side,
+                price: trade.price(),
+                quantity: trade.quantity(),
+            });
+        }
+    }
+
+    // a resting order filled outside the continuous matching (auctions): its level and the events follow
+    fn apply_resting_fill(&mut self, order: Order, traded: OrderQuantity) {
+        let (Some(price), Some(handle)) = (order.limit_price(), self.orders.handle(order.id())) else {
+            return;
+        };
+        let Some(ticks) = self.tick_size.to_ticks(price) else {
+            return;
+        };
+        let level = match order.side() {
+            OrderSide::Ask => self.asks.get_mut(&ticks),
+            OrderSide::Bid => self.bids.get_mut(&Reverse(ticks)),
+        };
+        let Some(level) = level else {
+            return;
+        };
+
+        level.quantity -= traded;
+        if order.is_closed() {
+            level.unlink(&mut self.orders, handle);
+            self.orders.remove(handle);
+        } else {
+            *self.orders.order_mut(handle) = order;
+        }
+        if level.is_empty() {
+            match order.side() {
+                OrderSide::Ask => self.asks.0.remove(&ticks).map(|_| ()),
+                OrderSide::Bid => self.bids.0.remove(&Reverse(ticks)).map(|_| ()),
+            };
+        }
+
+        if let Some(events) = self.events.as_mut() {
+            events.push(if order.is_closed() {
+                BookEvent::Delete {
+                    order_id: Some(order.id()),
+                    side: order.side(),
+                    price,
+                }
+            } else {
+                BookEvent::Modify {
+                    order_id: Some(order.id()),
+                    side: order.side(),
+                    price,
+                    quantity: order.remaining(),
+                }
+            });
+        }
+    }
+
+    // the book only knows the account of the orders it is told about, and only while they are open
+    pub fn assign_account(&mut self, order_id: OrderId, account_id: &str) {
+        if self.get_order(order_id).is_none() {
+            return;
+        }
+        self.accounts.entry(account_id.into()).or_default().insert(order_id);
+        self.owners.insert(order_id, account_id.into());
+    }
+
+    // the order must be assigned to its account first, a client order id is unique among the open orders of the account
+    pub fn assign_client_order_id(&mut self, order_id: OrderId, client_order_id: &str) -> Result<(), OrderbookError> {
+        let Some(account_id) = self.owners.get(&order_id) else {
+            return Ok(());
+        };
+        let order_ids = self.client_order_ids.entry(account_id.clone()).or_default();
+        match order_ids.get(client_order_id) {
+            Some(taken) if *taken != order_id => Err(OrderbookError::DuplicateClientOrderId(client_order_id.into())),
+            _ => {
+                order_ids.insert(client_order_id.into(), order_id);
+                self.client_ids.insert(order_id, client_order_id.into());
+                Ok(())
+            }
+        }
+    }
+
+    #[inline]
+    pub fn order_by_client_id(&self, account_id: &str, client_order_id: &str) -> Option<&Order> {
+        let order_id = self.client_order_ids.get(account_id)?.get(client_order_id)?;
+        self.get_order(*order_id)
+    }
+
+    #[inline]
+    pub fn owner(&self, order_id: OrderId) -> Option<&str> {
+        self.owners.get(&order_id).map(CompactString::as_str)
+    }
+
+    #[inline]
+    pub fn client_order_id(&self, order_id: OrderId) -> Option<&str> {
+        self.client_ids.get(&order_id).map(CompactString::as_str)
+    }
+
+    // in time priority
+    pub fn open_orders(&self, account_id: &str) -> impl Iterator<Item = &Order> {
+        self.accounts
+            .get(account_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|order_id| self.get_order(*order_id))
+    }
+
+    // the accounts with open orders
+    #[inline]
+    pub fn account_ids(&self) -> impl Iterator<Item = &str> {
+        self.accounts.keys().map(CompactString::as_str)
+    }
+
+    // mass cancel, returns the cancelled orders
+    pub fn cancel_all(&mut self, account_id: &str) -> Result<Vec<Order>, OrderbookError> {
+        let order_ids: Vec<OrderId> = self.accounts.get(account_id).into_iter().flatten().copied().collect();
+        order_ids
+            .into_iter()
+            .map(|order_id| self.handle_cancel(order_id))
+            .collect()
+    }
+
+    fn forget(&mut self, order_id: OrderId) {
+        let Some(account_id) = self.owners.swap_remove(&order_id) else {
+            return;
+        };
+        if let Some(client_order_id) = self.client_ids.swap_remove(&order_id) {
+            if let Some(order_ids) = self.client_order_ids.get_mut(&account_id) {
+                order_ids.swap_remove(&client_order_id);
+                if order_ids.is_empty() {
+                    self.client_order_ids.swap_remove(&account_id);
+                }
+            }
+        }
+        if let Some(order_ids) = self.accounts.get_mut(&account_id) {
+            order_ids.shift_remove(&order_id);
+            if order_ids.is_empty() {
+                self.accounts.swap_remove(&account_id);
+            }
+        }
+    }
+
+    fn forget_closed(&mut self, order_ids: impl IntoIterator<Item = OrderId>) {
+        let closed: Vec<OrderId> = order_ids
+            .into_iter()
+            .filter(|order_id| self.get_order(*order_id).is_none())
+            .collect();
+        for order_id in closed {
+            self.forget(order_id);
+        }
+    }
+
+    // waiting for the next uncross, in time priority
+    #[inline]
+    pub fn auction_orders(&self) -> &[Order] {
+        &self.auction_orders
+    }
+
+    // start recording the changes of the book, the resting orders are recorded first as if they were just added
+    pub fn record_events(&mut self) {
+        if self.events.is_some() {
+            return;
+        }
+
+        let bids = self.bids.values().map(|level| (OrderSide::Bid, level));
+        let asks = self.asks.values().map(|level| (OrderSide::Ask, level));
+        let events = bids
+            .chain(asks)
+            .flat_map(|(side, level)| {
+                level.iter(&self.orders).map(move |(_, order)| BookEvent::Add {
+                    order_id: Some(order.id()),
+                    side,
+                    price: level.price,
+                    quantity: order.remaining(),
+                })
+            })
+            .collect();
+        self.events = Some(events);
+    }
+
+    #[inline]
+    pub fn drain_events(&mut self) -> impl Iterator<Item = BookEvent> + '_ {
+        self.events.iter_mut().flat_map(|events| events.drain(..))
+    }
+
+    #[inline]
+    pub fn trade_count(&self) -> usize {
+        self.trades.len()
+    }
+
+    // trades in execution order, starting at the given count (e.g. the trades of the last request)
+    #[inline]
+    pub fn trades_from(&self, index: usize) -> impl Iterator<Item = &Trade> {
+        self.trades.values().skip(index)
+    }
+
+    #[inline]
+    pub(crate) fn trades_from_mut(&mut self, index: usize) -> impl Iterator<Item = &mut Trade> {
+        self.trades.values_mut().skip(index)
+    }
+
+    // only orders resting in the book (or waiting for the auction), filled or cancelled orders are gone
+    #[inline]
+    pub fn get_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.orders
+            .get(order_id)
+            .or_else(|| self.auction_orders.iter().find(|order| order.id() == order_id))
+    }
 
-        Ok(matched)
-    }};
-}
+    // the orders in the book, auction orders included
+    #[inline]
+    pub fn resting_count(&self) -> usize {
+        self.orders.len() + self.auction_orders.len()
+    }
 
-#[derive(Default)]
-pub struct Orderbook {
-    asks: AsksLadder,
-    bids: BidsLadder,
-    orders: IndexMap<OrderId, Order>,
-    trades: IndexMap<TradeId, Trade>,
-}
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub fn fills_per_order(&self) -> &Histogram {
+        &self.fills_per_order
+    }
 
-type MatchResult = Result<bool, OrderbookError>;
-#[cfg(test)]
-const MATCHED: MatchResult = Ok(true);
-#[cfg(test)]
-const NOT_MATCHED: MatchResult = Ok(false);
+    // whether the id was taken by the book before, even if the order is gone since
+    #[inline]
+    pub fn has_handled(&self, order_id: OrderId) -> bool {
+        self.handled.contains(&order_id)
+    }
 
-type CancelResult = Result<Order, OrderbookError>;
+    // every id taken so far, in the order taken
+    #[inline]
+    pub fn handled(&self) -> impl Iterator<Item = OrderId> + '_ {
+        self.handled.iter().copied()
+    }
 
-impl Orderbook {
+    // an id taken before, e.g. by the book a snapshot was taken of
     #[inline]
-    pub fn peek_top(&self, side: &OrderSide) -> Option<&Order> {
-        match side {
-            OrderSide::Ask => self.asks.peek_top(&self.orders),
-            OrderSide::Bid => self.bids.peek_top(&self.orders),
-        }
+    pub fn mark_handled(&mut self, order_id: OrderId) {
+        self.handled.insert(order_id);
     }
 
     #[inline]
-    pub fn handle_create(&mut self, mut order: Order) -> MatchResult {
-        if self.orders.contains_key(&order.id()) {
-            return Err(OrderbookError::OrderDuplicated(order.id()));
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "trace", skip_all, fields(order_id = u64::from(order.id()), side = %order.side()))
+    )]
+    pub fn handle_create(&mut self, order: Order) -> MatchResult {
+        if self.has_handled(order.id()) {
+            return Err(OrderbookError::DuplicateOrderId(order.id()));
+        }
+        if order.is_auction_only() {
+            return Err(OrderbookError::AuctionOrderOutsideAuction(order.id()));
+        }
+
+        let limit_ticks = self.ticks(&order)?;
+        self.create(order, limit_ticks)
+    }
+
+    // an order rejected before any trade (e.g. a fill or kill not fillable) leaves its id free
+    fn create(&mut self, order: Order, limit_ticks: Option<Ticks>) -> MatchResult {
+        let trades_from = self.trades.len();
+        let matched = self.match_order(order, limit_ticks);
+        if matched.is_ok() || self.trades.len() > trades_from {
+            self.handled.insert(order.id());
         }
+        #[cfg(feature = "metrics")]
+        self.fills_per_order.observe((self.trades.len() - trades_from) as u64);
+        let makers: Vec<OrderId> = self.trades_from(trades_from).map(Trade::maker).collect();
+        self.forget_closed(makers);
+        self.stamp(order.id());
+        self.jump_queue(order.id());
+        matched
+    }
 
+    fn match_order(&mut self, mut order: Order, mut limit_ticks: Option<Ticks>) -> MatchResult {
+        let tick_size = self.tick_size;
         let orders = &mut self.orders;
         let trades = &mut self.trades;
+        let events = &mut self.events;
+        let post_only_tick = self.post_only_tick;
+        let sweep_limits = self.sweep_limits;
+        let matching_policy = self.matching_policy;
+        let yield_point = &mut self.yield_point;
 
         match order.side() {
             OrderSide::Ask => {
                 let order_ladder = &mut self.asks;
                 let opposite_ladder = &mut self.bids;
-                match_order!(order, orders, trades, order_ladder, opposite_ladder)
+                match_order!(
+                    order,
+                    limit_ticks,
+                    tick_size,
+                    orders,
+                    trades,
+                    events,
+                    post_only_tick,
+                    sweep_limits,
+                    matching_policy,
+                    yield_point,
+                    order_ladder,
+                    opposite_ladder
+                )
             }
             OrderSide::Bid => {
                 let order_ladder = &mut self.bids;
                 let opposite_ladder = &mut self.asks;
-                match_order!(order, orders, trades, order_ladder, opposite_ladder)
+                match_order!(
+                    order,
+                    limit_ticks,
+                    tick_size,
+                    orders,
+                    trades,
+                    events,
+                    post_only_tick,
+                    sweep_limits,
+                    matching_policy,
+                    yield_point,
+                    order_ladder,
+                    opposite_ladder
+                )
             }
         }
     }
 
     #[inline]
     pub fn handle_cancel(&mut self, order_id: OrderId) -> CancelResult {
-        let order = self
+        #[cfg(feature = "trace")]
+        let account_id = self.owners.get(&order_id).cloned().unwrap_or_default();
+        let cancelled = self.cancel(order_id);
+        #[cfg(feature = "trace")]
+        if let Ok(order) = &cancelled {
+            tracing::debug!(
+                order_id = u64::from(order.id()),
+                %account_id,
+                price = %order.limit_price().map_or(CompactString::from("MARKET"), |price| compact_str::format_compact!("{price}")),
+                quantity = %order.remaining(),
+                "cancel"
+            );
+        }
+        cancelled
+    }
+
+    fn cancel(&mut self, order_id: OrderId) -> CancelResult {
+        self.forget(order_id);
+        if let Some(index) = self.auction_orders.iter().position(|order| order.id() == order_id) {
+            return Ok(self.auction_orders.remove(index));
+        }
+
+        let handle = self
             .orders
-            .remove(&order_id)
+            .handle(order_id)
             .ok_or(OrderbookError::OrderToCancelNotFound(order_id))?;
 
-        match order.side() {
-            OrderSide::Ask => {
-                let order_ladder = &mut self.asks;
-                order_ladder.remove(&order)?;
-            }
-            OrderSide::Bid => {
-                let order_ladder = &mut self.bids;
-                order_ladder.remove(&order)?;
-            }
+        let resting = *self.orders.order(handle);
+        let ticks = self
+            .ticks(&resting)?
+            .ok_or(OrderbookError::OrderToRemoveWithNoLimitPrice(resting))?;
+        let order = match resting.side() {
+            OrderSide::Ask => self.asks.remove(&mut self.orders, handle, ticks)?,
+            OrderSide::Bid => self.bids.remove(&mut self.orders, handle, ticks)?,
+        };
+
+        if let (Some(events), Some(price)) = (self.events.as_mut(), order.limit_price()) {
+            events.push(BookEvent::Delete {
+                order_id: Some(order.id()),
+                side: order.side(),
+                price,
+            });
         }
 
         Ok(order)
@@ -366,7 +1388,9 @@ impl Orderbook {
 #[derive(Debug, Error, PartialEq)]
 pub enum OrderbookError {
     #[error("an order with the same ID has been handled before! {0}")]
-    OrderDuplicated(OrderId),
+    DuplicateOrderId(OrderId),
+    #[error("an open order of the account has the same client order ID! {0}")]
+    DuplicateClientOrderId(CompactString),
     #[error("order cannot be inserted into the book with no limit price! {0}")]
     OrderToInsertWithNoLimitPrice(Order),
     #[error("order cannot be removed from the book with no limit price! {0}")]
@@ -377,6 +1401,26 @@ pub enum OrderbookError {
     OrderToMatchNotFound(OrderId),
     #[error("trade error: {0}")]
     TradeError(#[from] TradeError),
+    #[error("auction order outside of an auction! {0}")]
+    AuctionOrderOutsideAuction(OrderId),
+    #[error("post only order would cross the spread (order={}, limit_price={}, best_price={})", .order_id, .limit_price, .best_price)]
+    PostOnlyWouldCross {
+        order_id: OrderId,
+        limit_price: OrderPrice,
+        best_price: OrderPrice,
+    },
+    #[error("price not on the tick (order={}, price={}, tick_size={})", .order_id, .price, .tick_size)]
+    PriceNotOnTick {
+        order_id: OrderId,
+        price: OrderPrice,
+        tick_size: OrderPrice,
+    },
+    #[error("fill or kill order cannot be filled completely (order={}, requested={}, available={})", .order_id, .requested, .available)]
+    FillOrKillNotFillable {
+        order_id: OrderId,
+        requested: OrderQuantity,
+        available: OrderQuantity,
+    },
 }
 
 #[cfg(test)]
@@ -384,7 +1428,10 @@ mod test {
     use rstest::{fixture, rstest};
 
     use super::*;
-    use crate::order::{Order, OrderSide};
+    use crate::{
+        clock::ManualClock,
+        order::{Order, OrderSide},
+    };
 
     // convention for order ids: 3-digit side (bid = 900, ask = 901), 3-digit quantity, 3-digit price (for market orders always 999)
 
@@ -470,7 +1517,68 @@ mod test {
             assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
             assert_eq!(
                 orderbook.handle_create(ask_100_at_015),
-                Err(OrderbookError::OrderDuplicated(ask_100_at_015.id()))
+                Err(OrderbookError::DuplicateOrderId(ask_100_at_015.id()))
+            );
+        }
+
+        #[rstest]
+        fn reject_id_of_an_order_gone(mut orderbook: Orderbook, ask_100_at_015: Order, bid_099_at_015: Order) {
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert!(orderbook.handle_cancel(ask_100_at_015.id()).is_ok());
+            assert_eq!(
+                orderbook.handle_create(ask_100_at_015),
+                Err(OrderbookError::DuplicateOrderId(ask_100_at_015.id()))
+            );
+            assert_eq!(
+                orderbook.rest(Order::limit_order(
+                    ask_100_at_015.id(),
+                    OrderSide::Bid,
+                    bid_099_at_015.remaining(),
+                    bid_099_at_015.limit_price().unwrap()
+                )),
+                Err(OrderbookError::DuplicateOrderId(ask_100_at_015.id()))
+            );
+        }
+
+        #[rstest]
+        fn recreate_with_the_id_of_a_resting_or_cancelled_order(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            ask_070_at_014: Order,
+        ) {
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            let depth = orderbook.depth(10);
+
+            // same id, another price and quantity: the resting order and its level are untouched
+            let twin = Order::limit_order(
+                ask_100_at_015.id(),
+                OrderSide::Ask,
+                ask_070_at_014.remaining(),
+                ask_070_at_014.limit_price().unwrap(),
+            );
+            assert_eq!(
+                orderbook.handle_create(twin),
+                Err(OrderbookError::DuplicateOrderId(ask_100_at_015.id()))
+            );
+            assert_eq!(orderbook.depth(10), depth);
+            assert_eq!(
+                orderbook.get_order(ask_100_at_015.id()).unwrap().limit_price(),
+                Some(15.into())
+            );
+
+            // nor can it come back once cancelled
+            assert!(orderbook.handle_cancel(ask_100_at_015.id()).is_ok());
+            for _ in 0..2 {
+                assert_eq!(
+                    orderbook.handle_create(twin),
+                    Err(OrderbookError::DuplicateOrderId(ask_100_at_015.id()))
+                );
+            }
+            assert!(orderbook.get_order(ask_100_at_015.id()).is_none());
+            assert!(orderbook.depth(10).asks.is_empty());
+            assert_eq!(
+                orderbook.handle_cancel(ask_100_at_015.id()),
+                Err(OrderbookError::OrderToCancelNotFound(ask_100_at_015.id()))
             );
         }
 
@@ -591,6 +1699,35 @@ mod test {
         }
     }
 
+    mod accounts {
+        use super::*;
+
+        #[rstest]
+        fn index_open_orders_by_account(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            ask_070_at_014: Order,
+            bid_099_at_015: Order,
+        ) {
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            orderbook.assign_account(ask_100_at_015.id(), "1");
+            assert_eq!(orderbook.handle_create(ask_070_at_014), NOT_MATCHED);
+            orderbook.assign_account(ask_070_at_014.id(), "1");
+            let open: Vec<OrderId> = orderbook.open_orders("1").map(Order::id).collect();
+            assert_eq!(open, vec![ask_100_at_015.id(), ask_070_at_014.id()]);
+
+            // filled orders leave the index, the ones gone already are not indexed
+            assert_eq!(orderbook.handle_create(bid_099_at_015), MATCHED);
+            orderbook.assign_account(bid_099_at_015.id(), "2");
+            assert_eq!(orderbook.open_orders("2").count(), 0);
+            let cancelled = orderbook.cancel_all("1").unwrap();
+            assert_eq!(cancelled.len(), 1);
+            assert_eq!(cancelled[0].remaining(), 71.into());
+            assert!(orderbook.peek_top(&OrderSide::Ask).is_none());
+            assert!(orderbook.accounts.is_empty() && orderbook.owners.is_empty());
+        }
+    }
+
     mod features {
         use crate::order::{OrderType, TimeInForce};
 
@@ -621,12 +1758,23 @@ mod test {
             // confirm it cannot be filled
             assert!(bid_099_at_015.remaining() > ask_080_at_015.remaining());
 
-            // send to the book, there should be no matching
+            // send to the book, the bid is rejected without any partial fill
             assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
-            assert_eq!(orderbook.handle_create(bid_099_at_015), NOT_MATCHED);
+            assert_eq!(
+                orderbook.handle_create(bid_099_at_015),
+                Err(OrderbookError::FillOrKillNotFillable {
+                    order_id: bid_099_at_015.id(),
+                    requested: bid_099_at_015.remaining(),
+                    available: ask_080_at_015.remaining()
+                })
+            );
 
             // ask remains untouched in the top and there's no bid in the book
             assert_eq!(orderbook.peek_top(&OrderSide::Ask), Some(&ask_080_at_015));
+            assert_eq!(
+                orderbook.peek_top(&OrderSide::Ask).unwrap().remaining(),
+                ask_080_at_015.remaining()
+            );
             assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
 
             // now confirm that with another ask then the bid could be filled
@@ -645,6 +1793,165 @@ mod test {
             assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
         }
 
+        #[rstest]
+        fn reject_fill_or_kill_beyond_limit_price(
+            mut orderbook: Orderbook,
+            ask_070_at_014: Order,
+            ask_100_at_015: Order,
+        ) {
+            // enough asks in the book but only 70 at 14 or better
+            let fill_or_kill = TimeInForce::ImmediateOrCancel { fill_or_kill: true };
+            let bid_099_at_014 = Order::limit_order(OrderId::new(900_099_014), OrderSide::Bid, 99.into(), 14.into())
+                .with_time_in_force(fill_or_kill);
+            assert_eq!(orderbook.handle_create(ask_070_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            orderbook.record_events();
+            orderbook.drain_events().for_each(drop);
+
+            assert!(matches!(
+                orderbook.handle_create(bid_099_at_014),
+                Err(OrderbookError::FillOrKillNotFillable { available, .. }) if available == 70.into()
+            ));
+
+            // atomic: no trade, no book change
+            assert_eq!(orderbook.drain_events().count(), 0);
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask).unwrap().remaining(), 70.into());
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
+        }
+
+        #[rstest]
+        #[case::fifo(MatchingPolicy::Fifo, 50, [10, 30, 10])]
+        #[case::pro_rata(MatchingPolicy::ProRata, 50, [5, 15, 30])]
+        #[case::pro_rata_rounding(MatchingPolicy::ProRata, 7, [1, 2, 4])]
+        #[case::top_priority(MatchingPolicy::ProRataTopPriority, 50, [10, 14, 26])]
+        fn share_out_the_level(
+            #[case] matching_policy: MatchingPolicy,
+            #[case] quantity: u32,
+            #[case] filled: [u32; 3],
+        ) {
+            let mut orderbook = Orderbook::default().with_matching_policy(matching_policy);
+            let asks = [10u32, 30, 60].map(|quantity| {
+                let order_id = OrderId::new(901_000_015 + u64::from(quantity) * 1_000);
+                Order::limit_order(order_id, OrderSide::Ask, quantity.into(), 15.into())
+            });
+            for ask in asks {
+                orderbook.handle_create(ask).unwrap();
+            }
+            let bid = Order::limit_order(OrderId::new(900_000_015), OrderSide::Bid, quantity.into(), 15.into());
+            assert_eq!(orderbook.handle_create(bid), MATCHED);
+
+            for (ask, filled) in asks.iter().zip(filled) {
+                let remaining = orderbook
+                    .get_order(ask.id())
+                    .map_or(OrderQuantity::ZERO, Order::remaining);
+                assert_eq!(remaining, ask.remaining() - filled.into());
+            }
+            assert_eq!(orderbook.depth(1).asks[0].1, (100 - quantity).into());
+            assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
+        }
+
+        #[rstest]
+        #[case::levels(Some(1), None, 70)]
+        #[case::notional(None, Some(1_280), 90)]
+        fn cap_the_sweep(
+            #[case] max_levels: Option<usize>,
+            #[case] max_notional: Option<u32>,
+            #[case] filled: u32,
+            ask_070_at_014: Order,
+            ask_100_at_015: Order,
+        ) {
+            let sweep = |remainder: SweepRemainder| {
+                let sweep_limits = SweepLimits {
+                    max_levels,
+                    max_notional: max_notional.map(Decimal::from),
+                    remainder,
+                };
+                let mut orderbook = Orderbook::default()
+                    .with_tick_size(1.into())
+                    .with_sweep_limits(sweep_limits);
+                orderbook.handle_create(ask_070_at_014).unwrap();
+                orderbook.handle_create(ask_100_at_015).unwrap();
+                let bid_150_at_016 =
+                    Order::limit_order(OrderId::new(900_150_016), OrderSide::Bid, 150.into(), 16.into());
+                assert_eq!(orderbook.handle_create(bid_150_at_016), MATCHED);
+                let traded: OrderQuantity = orderbook.trades_from(0).map(Trade::quantity).sum();
+                assert_eq!(traded, filled.into());
+                assert_eq!(orderbook.best_ask(), Some(15.into()));
+                orderbook
+            };
+
+            assert_eq!(sweep(SweepRemainder::Cancel).peek_top(&OrderSide::Bid), None);
+            // one tick behind the best ask rather than crossing it
+            let orderbook = sweep(SweepRemainder::Rest);
+            let bid = orderbook.peek_top(&OrderSide::Bid).unwrap();
+            assert_eq!(
+                (bid.limit_price(), bid.remaining()),
+                (Some(14.into()), (150 - filled).into())
+            );
+
+            // a fill or kill only counts what the sweep may take
+            let mut orderbook = Orderbook::default().with_sweep_limits(SweepLimits {
+                max_levels,
+                max_notional: max_notional.map(Decimal::from),
+                remainder: SweepRemainder::Cancel,
+            });
+            orderbook.handle_create(ask_070_at_014).unwrap();
+            orderbook.handle_create(ask_100_at_015).unwrap();
+            let fill_or_kill = Order::limit_order(OrderId::new(900_099_016), OrderSide::Bid, 99.into(), 16.into())
+                .with_time_in_force(TimeInForce::ImmediateOrCancel { fill_or_kill: true });
+            assert!(matches!(
+                orderbook.handle_create(fill_or_kill),
+                Err(OrderbookError::FillOrKillNotFillable { available, .. }) if available == filled.into()
+            ));
+        }
+
+        #[derive(Clone, Default)]
+        struct Yields(std::sync::Arc<std::sync::Mutex<Vec<YieldPoint>>>);
+
+        impl YieldHandler for Yields {
+            fn on_yield(&mut self, yield_point: YieldPoint) {
+                self.0.lock().unwrap().push(yield_point);
+            }
+        }
+
+        #[rstest]
+        fn yield_during_a_long_sweep() {
+            let sweep = |orderbook: &mut Orderbook| {
+                for level in 0..4u64 {
+                    for position in 0..3u64 {
+                        let order_id = OrderId::new(901_000_000 + level * 10 + position);
+                        let price = (15 + level as u32).into();
+                        orderbook
+                            .handle_create(Order::limit_order(order_id, OrderSide::Ask, 10.into(), price))
+                            .unwrap();
+                    }
+                }
+                let bid = Order::limit_order(OrderId::new(900_000_001), OrderSide::Bid, 105.into(), 18.into());
+                assert_eq!(orderbook.handle_create(bid), MATCHED);
+                orderbook
+                    .trades_from(0)
+                    .map(|trade| (trade.maker(), trade.price(), trade.quantity()))
+                    .collect::<Vec<_>>()
+            };
+
+            let yields = Yields::default();
+            let mut orderbook = Orderbook::default().with_yield_point(5, yields.clone());
+            let mut straight = Orderbook::default();
+            assert_eq!(sweep(&mut orderbook), sweep(&mut straight));
+            assert_eq!(orderbook.depth(4), straight.depth(4));
+
+            // not once the order is filled, at its eleventh fill
+            let yields = yields.0.lock().unwrap();
+            let fills: Vec<(usize, usize)> = yields
+                .iter()
+                .map(|yield_point| (yield_point.fills, yield_point.levels))
+                .collect();
+            assert_eq!(fills, vec![(5, 2), (10, 4)]);
+            assert!(yields
+                .iter()
+                .all(|yield_point| yield_point.order_id == OrderId::new(900_000_001)));
+        }
+
         #[rstest]
         fn cancel_post_only(mut orderbook: Orderbook, ask_100_at_015: Order, bid_099_at_015: Order) {
             // keep the original limit price
@@ -662,15 +1969,161 @@ mod test {
             assert!(bid_099_at_015.matches(&ask_100_at_015));
             assert!(!bid_099_at_015.is_closed());
 
-            // send to the book, there should be no matching
+            // send to the book, the bid is rejected since it would cross the spread
             assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
-            assert_eq!(orderbook.handle_create(bid_099_at_015), NOT_MATCHED);
+            assert_eq!(
+                orderbook.handle_create(bid_099_at_015),
+                Err(OrderbookError::PostOnlyWouldCross {
+                    order_id: bid_099_at_015.id(),
+                    limit_price,
+                    best_price: 15.into()
+                })
+            );
 
             // ask remains untouched in the top and there's no bid in the book
             assert_eq!(orderbook.peek_top(&OrderSide::Ask), Some(&ask_100_at_015));
             assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
         }
 
+        #[rstest]
+        fn reprice_post_only(ask_100_at_015: Order, bid_099_at_015: Order) {
+            let mut orderbook = Orderbook::default().with_post_only_repricing(OrderPrice::new(1, 2));
+            let post_only = TimeInForce::GoodTilCancel { post_only: true };
+
+            // the bid rests one tick below the best ask instead of being rejected
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(
+                orderbook.handle_create(bid_099_at_015.with_time_in_force(post_only)),
+                NOT_MATCHED
+            );
+            let top_bid = orderbook.peek_top(&OrderSide::Bid).unwrap();
+            assert_eq!(top_bid.limit_price(), Some(OrderPrice::new(1499, 2)));
+            assert_eq!(top_bid.remaining(), bid_099_at_015.remaining());
+            assert_eq!(
+                orderbook.peek_top(&OrderSide::Ask).unwrap().remaining(),
+                ask_100_at_015.remaining()
+            );
+        }
+
+        #[rstest]
+        fn jump_the_queue_with_a_priority_fee(ask_100_at_015: Order, ask_080_at_015: Order, bid_099_at_015: Order) {
+            let mut orderbook = Orderbook::default().with_queue_priority(QueuePriority::PriorityFee);
+            let ask_010_at_015 = Order::limit_order(10.into(), OrderSide::Ask, 10.into(), 15.into());
+
+            // the highest fee first, equal fees keep their time priority
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(
+                orderbook.handle_create(ask_080_at_015.with_priority_fee(Decimal::new(5, 1))),
+                NOT_MATCHED
+            );
+            assert_eq!(
+                orderbook.handle_create(ask_010_at_015.with_priority_fee(Decimal::new(5, 1))),
+                NOT_MATCHED
+            );
+            let asks: Vec<OrderId> = orderbook.iter_side(OrderSide::Ask).map(Order::id).collect();
+            assert_eq!(
+                asks,
+                vec![ask_080_at_015.id(), ask_010_at_015.id(), ask_100_at_015.id()]
+            );
+
+            assert_eq!(orderbook.handle_create(bid_099_at_015), MATCHED);
+            let makers: Vec<OrderId> = orderbook.trades_from(0).map(Trade::maker).collect();
+            assert_eq!(makers, asks);
+
+            // fees are ignored under time priority
+            let mut orderbook = Orderbook::default();
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(
+                orderbook.handle_create(ask_080_at_015.with_priority_fee(1.into())),
+                NOT_MATCHED
+            );
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask).unwrap().id(), ask_100_at_015.id());
+        }
+
+        // the orders of every level of the side, in the order they trade, must have joined the book in that order
+        fn assert_time_priority(orderbook: &Orderbook, side: OrderSide) {
+            let resting: Vec<(Option<OrderPrice>, Priority)> = orderbook
+                .iter_side(side)
+                .map(|order| (order.limit_price(), orderbook.priority(order.id()).unwrap()))
+                .collect();
+            for pair in resting.windows(2) {
+                let [(price, first), (next_price, next)] = pair else {
+                    unreachable!();
+                };
+                if price == next_price {
+                    assert!(first.sequence < next.sequence, "{first:?} ahead of {next:?}");
+                    assert!(first.accepted_at <= next.accepted_at, "{first:?} ahead of {next:?}");
+                }
+            }
+        }
+
+        #[rstest]
+        fn strict_time_priority_within_a_level(ask_100_at_015: Order, ask_080_at_015: Order, ask_070_at_014: Order) {
+            let clock = ManualClock::new(1_000);
+            let mut orderbook = Orderbook::default().with_clock(clock.clone());
+            let ask_010_at_015 = Order::limit_order(10.into(), OrderSide::Ask, 10.into(), 15.into());
+            for ask in [ask_100_at_015, ask_070_at_014, ask_080_at_015, ask_010_at_015] {
+                assert_eq!(orderbook.handle_create(ask), NOT_MATCHED);
+                clock.advance(5);
+            }
+            assert_eq!(
+                orderbook.priority(ask_080_at_015.id()),
+                Some(Priority {
+                    sequence: 3,
+                    accepted_at: 1_010
+                })
+            );
+            assert_time_priority(&orderbook, OrderSide::Ask);
+
+            // a partial fill keeps the place of the order, the ones at the same price trade by sequence
+            let bid = Order::limit_order(20.into(), OrderSide::Bid, 120.into(), 15.into());
+            assert_eq!(orderbook.handle_create(bid), MATCHED);
+            let makers: Vec<OrderId> = orderbook.trades_from(0).map(Trade::maker).collect();
+            assert_eq!(makers, vec![ask_070_at_014.id(), ask_100_at_015.id()]);
+            assert_eq!(orderbook.priority(ask_100_at_015.id()).unwrap().sequence, 1);
+            assert_time_priority(&orderbook, OrderSide::Ask);
+
+            // an order coming back goes behind the others, even with an id lower than theirs
+            assert!(orderbook.handle_cancel(ask_100_at_015.id()).is_ok());
+            let ask_005_at_015 = Order::limit_order(5.into(), OrderSide::Ask, 5.into(), 15.into());
+            assert_eq!(orderbook.handle_create(ask_005_at_015), NOT_MATCHED);
+            let asks: Vec<OrderId> = orderbook.iter_side(OrderSide::Ask).map(Order::id).collect();
+            assert_eq!(
+                asks,
+                vec![ask_080_at_015.id(), ask_010_at_015.id(), ask_005_at_015.id()]
+            );
+            assert_eq!(orderbook.priority(ask_005_at_015.id()).unwrap().sequence, 5);
+            assert_time_priority(&orderbook, OrderSide::Ask);
+            assert!(orderbook.priority(ask_100_at_015.id()).is_none());
+        }
+
+        #[rstest]
+        fn reject_prices_off_the_tick(ask_100_at_015: Order) {
+            let tick_size = TickSize::new(OrderPrice::new(5, 1)).unwrap();
+            assert_eq!(tick_size.to_ticks(OrderPrice::new(155, 1)), Some(31));
+            assert_eq!(tick_size.to_ticks(OrderPrice::new(1551, 2)), None);
+            assert_eq!(tick_size.to_price(31), OrderPrice::new(155, 1));
+            assert_eq!(TickSize::new(OrderPrice::ZERO), None);
+
+            let mut orderbook = Orderbook::default().with_tick_size(OrderPrice::new(5, 1));
+            let off_tick = Order::limit_order(10.into(), OrderSide::Bid, 10.into(), OrderPrice::new(1520, 2));
+            assert_eq!(
+                orderbook.handle_create(off_tick),
+                Err(OrderbookError::PriceNotOnTick {
+                    order_id: off_tick.id(),
+                    price: OrderPrice::new(1520, 2),
+                    tick_size: OrderPrice::new(5, 1)
+                })
+            );
+            assert!(orderbook.rest(off_tick).is_err());
+
+            // on the tick, whatever its scale
+            let bid = Order::limit_order(11.into(), OrderSide::Bid, 10.into(), OrderPrice::new(1500, 2));
+            assert_eq!(orderbook.handle_create(bid), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_100_at_015), MATCHED);
+            assert_eq!(orderbook.best_ask(), Some(15.into()));
+        }
+
         #[rstest]
         fn cancel_immediate_or_cancel(mut orderbook: Orderbook, ask_080_at_015: Order, bid_099_at_015: Order) {
             // keep the original limit price
@@ -697,4 +2150,157 @@ mod test {
             assert_eq!(orderbook.peek_top(&OrderSide::Bid), None);
         }
     }
+    mod depth {
+        use super::*;
+
+        #[rstest]
+        fn aggregate_levels_best_first(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            ask_080_at_015: Order,
+            ask_070_at_014: Order,
+            bid_025_at_014: Order,
+        ) {
+            assert_eq!(orderbook.depth(5), Depth::default());
+
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(ask_070_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_025_at_014), MATCHED);
+
+            // the bid is filled against the best ask which keeps the leftover
+            let depth = orderbook.depth(5);
+            assert_eq!(depth.bids, vec![]);
+            assert_eq!(depth.asks, vec![(14.into(), 45.into(), 1), (15.into(), 180.into(), 2)]);
+
+            // only the requested number of levels
+            assert_eq!(orderbook.depth(1).asks, vec![(14.into(), 45.into(), 1)]);
+        }
+
+        #[rstest]
+        fn bids_sorted_by_highest_price(mut orderbook: Orderbook, bid_025_at_014: Order, bid_020_at_016: Order) {
+            assert_eq!(orderbook.handle_create(bid_025_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_020_at_016), NOT_MATCHED);
+
+            assert_eq!(
+                orderbook.depth(5).bids,
+                vec![(16.into(), 20.into(), 1), (14.into(), 25.into(), 1)]
+            );
+
+            // a cancel removes the quantity and the level when it is the last order
+            assert!(orderbook.handle_cancel(bid_020_at_016.id()).is_ok());
+            assert_eq!(orderbook.depth(5).bids, vec![(14.into(), 25.into(), 1)]);
+        }
+
+        #[rstest]
+        fn query_top_of_book(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            ask_080_at_015: Order,
+            bid_025_at_014: Order,
+        ) {
+            assert_eq!(orderbook.spread(), None);
+
+            assert_eq!(orderbook.handle_create(ask_100_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.best_ask(), Some(15.into()));
+            assert_eq!(orderbook.mid_price(), None);
+
+            assert_eq!(orderbook.handle_create(ask_080_at_015), NOT_MATCHED);
+            assert_eq!(orderbook.handle_create(bid_025_at_014), NOT_MATCHED);
+            assert_eq!(orderbook.best_bid(), Some(14.into()));
+            assert_eq!(orderbook.spread(), Some(1.into()));
+            assert_eq!(orderbook.mid_price(), Some(OrderPrice::new(145, 1)));
+
+            // time priority within the level
+            let asks: Vec<OrderId> = orderbook.iter_side(OrderSide::Ask).map(Order::id).collect();
+            assert_eq!(asks, vec![ask_100_at_015.id(), ask_080_at_015.id()]);
+            assert_eq!(orderbook.iter_side(OrderSide::Bid).count(), 1);
+            assert_eq!(orderbook.get_order(ask_080_at_015.id()), Some(&ask_080_at_015));
+        }
+    }
+
+    mod auction {
+        use super::*;
+        use crate::order::TimeInForce;
+
+        fn auction_order(side: OrderSide, quantity: u32, limit_price: u32, time_in_force: TimeInForce) -> Order {
+            let order_id = OrderId::new(quantity as u64 * 1_000 + limit_price as u64);
+            Order::limit_order(order_id, side, quantity.into(), limit_price.into()).with_time_in_force(time_in_force)
+        }
+
+        #[rstest]
+        fn imbalance_orders_trade_against_the_imbalance(
+            mut orderbook: Orderbook,
+            ask_100_at_015: Order,
+            bid_020_at_016: Order,
+        ) {
+            // 20 trade at 15 and 80 are left on the ask side
+            orderbook.rest(ask_100_at_015).unwrap();
+            orderbook.rest(bid_020_at_016).unwrap();
+
+            // same side as the imbalance: nothing to trade against
+            let io_ask = auction_order(OrderSide::Ask, 30, 15, TimeInForce::ImbalanceOnly);
+            let io_bid = auction_order(OrderSide::Bid, 50, 15, TimeInForce::ImbalanceOnly);
+            let iof_bid = auction_order(OrderSide::Bid, 40, 16, TimeInForce::ImbalanceOffset);
+            for order in [io_ask, io_bid, iof_bid] {
+                orderbook.rest(order).unwrap();
+            }
+
+            // auction orders never change the uncross
+            let uncross = orderbook.indicative_uncross().unwrap();
+            assert_eq!((uncross.price, uncross.imbalance), (15.into(), 80.into()));
+            assert_eq!(
+                orderbook.handle_create(io_bid),
+                Err(OrderbookError::DuplicateOrderId(io_bid.id()))
+            );
+
+            let trades = orderbook.uncross().unwrap();
+            assert_eq!(
+                trades.iter().map(|trade| trade.quantity()).collect::<Vec<_>>(),
+                vec![20.into(), 50.into(), 30.into()]
+            );
+            assert!(trades.iter().all(|trade| trade.price() == 15.into()));
+
+            // the imbalance-offset leftover rests as a plain limit order, the others are gone
+            assert_eq!(orderbook.peek_top(&OrderSide::Ask), None);
+            let top_bid = orderbook.peek_top(&OrderSide::Bid).unwrap();
+            assert_eq!((top_bid.id(), top_bid.remaining()), (iof_bid.id(), 10.into()));
+            assert!(orderbook.auction_orders().is_empty());
+            assert!(orderbook.get_order(io_ask.id()).is_none());
+        }
+
+        #[rstest]
+        fn reject_auction_orders_off_the_tick(ask_100_at_015: Order, bid_020_at_016: Order) {
+            let mut orderbook = Orderbook::default().with_tick_size(2.into());
+            let iof_bid = auction_order(OrderSide::Bid, 40, 15, TimeInForce::ImbalanceOffset);
+            assert_eq!(
+                orderbook.rest(iof_bid),
+                Err(OrderbookError::PriceNotOnTick {
+                    order_id: iof_bid.id(),
+                    price: 15.into(),
+                    tick_size: 2.into(),
+                })
+            );
+
+            // the uncross goes through in full, its trades included
+            let ask = Order::limit_order(ask_100_at_015.id(), OrderSide::Ask, 100.into(), 14.into());
+            orderbook.rest(ask).unwrap();
+            orderbook.rest(bid_020_at_016).unwrap();
+            let trades = orderbook.uncross().unwrap();
+            assert_eq!(
+                trades.iter().map(|trade| trade.quantity()).collect::<Vec<_>>(),
+                vec![20.into()]
+            );
+            assert!(orderbook.get_order(iof_bid.id()).is_none());
+        }
+
+        #[rstest]
+        fn reject_auction_orders_outside_the_auction(mut orderbook: Orderbook) {
+            let io_bid = auction_order(OrderSide::Bid, 50, 15, TimeInForce::ImbalanceOnly);
+            assert_eq!(
+                orderbook.handle_create(io_bid),
+                Err(OrderbookError::AuctionOrderOutsideAuction(io_bid.id()))
+            );
+        }
+    }
 }
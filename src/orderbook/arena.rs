@@ -0,0 +1,295 @@
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Timestamp,
+    order::{Order, OrderId},
+};
+
+// dense index of an order in the arena, valid until the order leaves it (its slot is reused then)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handle(u32);
+
+// when the order joined the book: the sequence grows with every order resting, whatever its level, so within a level
+// the orders of a plain time priority queue come by increasing sequence
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Priority {
+    pub sequence: u64,
+    pub accepted_at: Timestamp,
+}
+
+#[derive(Debug)]
+struct Node {
+    order: Order,
+    priority: Priority,
+    prev: Option<Handle>, // in the queue of its price level
+    next: Option<Handle>,
+}
+
+#[derive(Debug)]
+enum Slot {
+    Occupied(Node),
+    Vacant { next_free: Option<Handle> },
+}
+
+// the resting orders live in one growable vector, the slots left by the orders gone are reused by the next ones: once
+// the book reached its largest size, adding an order allocates nothing (the vector and the id index only grow past it)
+#[derive(Debug, Default)]
+pub struct Arena {
+    slots: Vec<Slot>,
+    free: Option<Handle>, // head of the free list, threaded through the vacant slots
+    handles: IndexMap<OrderId, Handle>,
+    sequence: u64, // of the last order inserted
+}
+
+impl Arena {
+    // none when an order with the same id is resting already: it is left alone and the index keeps pointing at it;
+    // the order is not timed yet (see set_accepted_at)
+    pub fn insert(&mut self, order: Order) -> Option<Handle> {
+        if self.handles.contains_key(&order.id()) {
+            return None;
+        }
+        self.sequence += 1;
+        let node = Slot::Occupied(Node {
+            order,
+            priority: Priority {
+                sequence: self.sequence,
+                accepted_at: 0,
+            },
+            prev: None,
+            next: None,
+        });
+        let handle = match self.free {
+            Some(handle) => {
+                let Slot::Vacant { next_free } = self.slots[handle.0 as usize] else {
+                    unreachable!("occupied slot in the free list");
+                };
+                self.free = next_free;
+                self.slots[handle.0 as usize] = node;
+                handle
+            }
+            None => {
+                self.slots.push(node);
+                Handle(self.slots.len() as u32 - 1)
+            }
+        };
+        self.handles.insert(order.id(), handle);
+        Some(handle)
+    }
+
+    // the order must be out of its queue already (see Queue::unlink)
+    pub fn remove(&mut self, handle: Handle) -> Order {
+        let slot = std::mem::replace(
+            &mut self.slots[handle.0 as usize],
+            Slot::Vacant { next_free: self.free },
+        );
+        let Slot::Occupied(node) = slot else {
+            unreachable!("vacant slot removed");
+        };
+        self.free = Some(handle);
+        self.handles.swap_remove(&node.order.id());
+        node.order
+    }
+
+    #[inline]
+    pub fn handle(&self, order_id: OrderId) -> Option<Handle> {
+        self.handles.get(&order_id).copied()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    #[inline]
+    pub fn get(&self, order_id: OrderId) -> Option<&Order> {
+        self.handle(order_id).map(|handle| self.order(handle))
+    }
+
+    #[inline]
+    pub fn order(&self, handle: Handle) -> &Order {
+        &self.node(handle).order
+    }
+
+    #[inline]
+    pub fn order_mut(&mut self, handle: Handle) -> &mut Order {
+        &mut self.node_mut(handle).order
+    }
+
+    #[inline]
+    pub fn priority(&self, handle: Handle) -> Priority {
+        self.node(handle).priority
+    }
+
+    #[inline]
+    pub fn set_accepted_at(&mut self, handle: Handle, accepted_at: Timestamp) {
+        self.node_mut(handle).priority.accepted_at = accepted_at;
+    }
+
+    // e.g. from a snapshot, the orders inserted next come after it
+    pub fn set_priority(&mut self, handle: Handle, priority: Priority) {
+        self.node_mut(handle).priority = priority;
+        self.sequence = self.sequence.max(priority.sequence);
+    }
+
+    #[inline]
+    pub fn next(&self, handle: Handle) -> Option<Handle> {
+        self.node(handle).next
+    }
+
+    #[inline]
+    fn node(&self, handle: Handle) -> &Node {
+        match &self.slots[handle.0 as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("stale handle"),
+        }
+    }
+
+    #[inline]
+    fn node_mut(&mut self, handle: Handle) -> &mut Node {
+        match &mut self.slots[handle.0 as usize] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant { .. } => unreachable!("stale handle"),
+        }
+    }
+}
+
+// intrusive FIFO queue of the orders of a price level, linked through their nodes in the arena: no allocation of its
+// own, orders leave it from anywhere in constant time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Queue {
+    head: Option<Handle>,
+    tail: Option<Handle>,
+    len: usize,
+}
+
+impl Queue {
+    #[inline]
+    pub fn front(&self) -> Option<Handle> {
+        self.head
+    }
+
+    #[inline]
+    pub fn back(&self) -> Option<Handle> {
+        self.tail
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_back(&mut self, arena: &mut Arena, handle: Handle) {
+        let node = arena.node_mut(handle);
+        node.prev = self.tail;
+        node.next = None;
+        match self.tail {
+            Some(tail) => arena.node_mut(tail).next = Some(handle),
+            None => self.head = Some(handle),
+        }
+        self.tail = Some(handle);
+        self.len += 1;
+    }
+
+    // `at` must be in the queue, `handle` out of it
+    pub fn insert_before(&mut self, arena: &mut Arena, at: Handle, handle: Handle) {
+        let prev = arena.node(at).prev;
+        let node = arena.node_mut(handle);
+        node.prev = prev;
+        node.next = Some(at);
+        arena.node_mut(at).prev = Some(handle);
+        match prev {
+            Some(prev) => arena.node_mut(prev).next = Some(handle),
+            None => self.head = Some(handle),
+        }
+        self.len += 1;
+    }
+
+    pub fn unlink(&mut self, arena: &mut Arena, handle: Handle) {
+        let node = arena.node_mut(handle);
+        let (prev, next) = (node.prev.take(), node.next.take());
+        match prev {
+            Some(prev) => arena.node_mut(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => arena.node_mut(next).prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+    }
+
+    pub fn iter<'a>(&self, arena: &'a Arena) -> impl Iterator<Item = (Handle, &'a Order)> {
+        std::iter::successors(self.head, |handle| arena.next(*handle)).map(|handle| (handle, arena.order(handle)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::OrderSide;
+
+    fn order(order_id: u64) -> Order {
+        Order::limit_order(order_id.into(), OrderSide::Ask, 10.into(), 15.into())
+    }
+
+    fn ids(queue: &Queue, arena: &Arena) -> Vec<u64> {
+        queue.iter(arena).map(|(_, order)| u64::from(order.id())).collect()
+    }
+
+    #[rstest]
+    fn reuse_the_slots_of_the_orders_gone() {
+        let mut arena = Arena::default();
+        let mut queue = Queue::default();
+        let handles: Vec<Handle> = (1..=3).map(|order_id| arena.insert(order(order_id)).unwrap()).collect();
+        for handle in &handles {
+            queue.push_back(&mut arena, *handle);
+        }
+        assert_eq!(ids(&queue, &arena), vec![1, 2, 3]);
+
+        queue.unlink(&mut arena, handles[1]);
+        assert_eq!(arena.remove(handles[1]).id(), 2.into());
+        assert!(arena.get(2.into()).is_none());
+        assert_eq!(ids(&queue, &arena), vec![1, 3]);
+
+        // the next order takes the free slot, wherever it goes in the queue
+        let handle = arena.insert(order(4)).unwrap();
+        assert_eq!(handle, handles[1]);
+        assert_eq!(arena.slots.len(), 3);
+        queue.insert_before(&mut arena, handles[0], handle);
+        assert_eq!(ids(&queue, &arena), vec![4, 1, 3]);
+        assert_eq!(
+            (queue.front(), queue.back(), queue.len()),
+            (Some(handle), Some(handles[2]), 3)
+        );
+
+        for handle in [handles[2], handle, handles[0]] {
+            queue.unlink(&mut arena, handle);
+            arena.remove(handle);
+        }
+        assert!(queue.is_empty());
+        assert_eq!((queue.front(), queue.back()), (None, None));
+    }
+
+    #[rstest]
+    fn refuse_an_id_already_resting() {
+        let mut arena = Arena::default();
+        let handle = arena.insert(order(1)).unwrap();
+        let mut twin = order(1);
+        twin.fill(5.into()).unwrap();
+        assert_eq!(arena.insert(twin), None);
+        assert_eq!(arena.slots.len(), 1);
+        assert_eq!(arena.get(1.into()).unwrap().remaining(), 10.into());
+
+        // free again once the order is gone
+        arena.remove(handle);
+        assert!(arena.insert(order(1)).is_some());
+    }
+}
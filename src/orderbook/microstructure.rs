@@ -0,0 +1,128 @@
+use std::cmp::Reverse;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::{Orderbook, PriceLevel};
+use crate::order::{OrderId, OrderPrice, OrderQuantity, OrderSide};
+
+// where a resting order stands in its level, an estimate as far as what is ahead may still be cancelled
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueuePosition {
+    pub price: OrderPrice,
+    pub orders_ahead: usize,
+    pub quantity_ahead: OrderQuantity, // to trade before the order gets its first fill
+    pub level_quantity: OrderQuantity, // the order included
+}
+
+// values derived from the book for the strategies embedding it: read only, from the top levels (no full walk)
+impl Orderbook {
+    // (bid - ask) / (bid + ask) of the quantities over the best `levels` of each side, from -1 (asks only) to 1
+    pub fn imbalance(&self, levels: usize) -> Option<Decimal> {
+        let bid: OrderQuantity = self.bids.values().take(levels).map(|level| level.quantity).sum();
+        let ask: OrderQuantity = self.asks.values().take(levels).map(|level| level.quantity).sum();
+        let total = bid + ask;
+        if total.is_zero() {
+            return None;
+        }
+        Some((bid.value() - ask.value()) / total.value())
+    }
+
+    // the mid price leaning towards the side with less at the touch (microprice): the best bid weighted by the
+    // quantity of the best ask and the other way round
+    pub fn weighted_mid_price(&self) -> Option<OrderPrice> {
+        let (_, bid) = self.bids.first_key_value()?;
+        let (_, ask) = self.asks.first_key_value()?;
+        let total = bid.quantity + ask.quantity;
+        if total.is_zero() {
+            return None;
+        }
+        Some(OrderPrice::from_decimal(
+            (bid.price * ask.quantity + ask.price * bid.quantity) / total.value(),
+        ))
+    }
+
+    // resting quantity of the side within `bps` basis points of the mid price
+    pub fn depth_within(&self, side: OrderSide, bps: u32) -> Option<OrderQuantity> {
+        let mid_price = self.mid_price()?;
+        let band = mid_price.value() * Decimal::from(bps) / Decimal::from(10_000);
+        let within = |level: &&PriceLevel| match side {
+            OrderSide::Bid => level.price.value() >= mid_price.value() - band,
+            OrderSide::Ask => level.price.value() <= mid_price.value() + band,
+        };
+        let depth = match side {
+            OrderSide::Bid => self.bids.values().take_while(within).map(|level| level.quantity).sum(),
+            OrderSide::Ask => self.asks.values().take_while(within).map(|level| level.quantity).sum(),
+        };
+        Some(depth)
+    }
+
+    // none for an order not resting in the book
+    pub fn queue_position(&self, order_id: OrderId) -> Option<QueuePosition> {
+        let handle = self.orders.handle(order_id)?;
+        let order = self.orders.order(handle);
+        let ticks = self.tick_size.to_ticks(order.limit_price()?)?;
+        let level = match order.side() {
+            OrderSide::Ask => self.asks.get(&ticks),
+            OrderSide::Bid => self.bids.get(&Reverse(ticks)),
+        }?;
+
+        let mut quantity_ahead = OrderQuantity::ZERO;
+        for (orders_ahead, (queued, queued_order)) in level.iter(&self.orders).enumerate() {
+            if queued == handle {
+                return Some(QueuePosition {
+                    price: level.price,
+                    orders_ahead,
+                    quantity_ahead,
+                    level_quantity: level.quantity,
+                });
+            }
+            quantity_ahead += queued_order.remaining();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::Order;
+
+    #[rstest]
+    fn derive_from_the_top_of_the_book() {
+        let mut orderbook = Orderbook::default();
+        assert_eq!(orderbook.imbalance(5), None);
+        let orders = [
+            (1, OrderSide::Bid, 30, 99),
+            (2, OrderSide::Bid, 10, 98),
+            (3, OrderSide::Bid, 20, 90),
+            (4, OrderSide::Ask, 10, 101),
+            (5, OrderSide::Ask, 20, 101),
+            (6, OrderSide::Ask, 30, 103),
+        ];
+        for (order_id, side, quantity, price) in orders {
+            let order = Order::limit_order(OrderId::new(order_id), side, quantity.into(), price.into());
+            orderbook.handle_create(order).unwrap();
+        }
+
+        assert_eq!(orderbook.imbalance(1), Some(Decimal::ZERO));
+        assert_eq!(orderbook.imbalance(2), Some(Decimal::new(-2, 1)));
+        assert_eq!(orderbook.weighted_mid_price(), Some(100.into()));
+        // 1% of the mid price is 99 to 101
+        assert_eq!(orderbook.depth_within(OrderSide::Bid, 100), Some(30.into()));
+        assert_eq!(orderbook.depth_within(OrderSide::Ask, 100), Some(30.into()));
+        assert_eq!(orderbook.depth_within(OrderSide::Bid, 1_000), Some(60.into()));
+        assert_eq!(
+            orderbook.queue_position(OrderId::new(5)),
+            Some(QueuePosition {
+                price: 101.into(),
+                orders_ahead: 1,
+                quantity_ahead: 10.into(),
+                level_quantity: 30.into(),
+            })
+        );
+        assert_eq!(orderbook.queue_position(OrderId::new(7)), None);
+    }
+}
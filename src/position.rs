@@ -0,0 +1,288 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use num::Signed;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::order::{OrderPrice, OrderQuantity, OrderSide};
+
+// size is signed: positive = long, negative = short
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Position {
+    pub size: Decimal,
+    pub entry_price: OrderPrice,
+    pub margin: Decimal,
+    pub realized_pnl: Decimal,
+}
+
+impl Position {
+    #[inline]
+    pub fn is_flat(&self) -> bool {
+        self.size.is_zero()
+    }
+
+    // long positions are on the bid side, short positions on the ask side
+    #[inline]
+    pub fn side(&self) -> Option<OrderSide> {
+        if self.size > Decimal::ZERO {
+            Some(OrderSide::Bid)
+        } else if self.size < Decimal::ZERO {
+            Some(OrderSide::Ask)
+        } else {
+            None
+        }
+    }
+
+    // the size without its sign
+    #[inline]
+    pub fn quantity(&self) -> OrderQuantity {
+        OrderQuantity::from_decimal(self.size.abs())
+    }
+
+    #[inline]
+    pub fn notional(&self, mark_price: OrderPrice) -> Decimal {
+        self.size.abs() * mark_price
+    }
+
+    #[inline]
+    pub fn unrealized_pnl(&self, mark_price: OrderPrice) -> Decimal {
+        self.size * (mark_price - self.entry_price)
+    }
+
+    #[inline]
+    pub fn leverage(&self, mark_price: OrderPrice) -> Option<Decimal> {
+        if self.margin > Decimal::ZERO {
+            Some(self.notional(mark_price) / self.margin)
+        } else {
+            None
+        }
+    }
+
+    // returns the pnl realized by the fill (only when the position is reduced, closed or flipped)
+    pub fn apply_fill(&mut self, side: OrderSide, quantity: OrderQuantity, price: OrderPrice) -> Decimal {
+        let signed = match side {
+            OrderSide::Bid => quantity.value(),
+            OrderSide::Ask => -quantity.value(),
+        };
+
+        if self.size.is_zero() || self.size.is_sign_positive() == signed.is_sign_positive() {
+            // open or increase: the entry price is the average of all the fills
+            let size = self.quantity() + quantity;
+            self.entry_price = OrderPrice::from_decimal((self.size.abs() * self.entry_price + price * quantity) / size);
+            self.size += signed;
+            return Decimal::ZERO;
+        }
+
+        // reduce, close or flip
+        let closed = self.size.abs().min(quantity.value());
+        let realized = closed * (price - self.entry_price) * self.size.signum();
+        self.size += signed;
+        self.realized_pnl += realized;
+
+        if self.size.is_zero() {
+            self.entry_price = OrderPrice::ZERO;
+        } else if self.size.is_sign_positive() == signed.is_sign_positive() {
+            // flipped, the leftover opens a new position at the fill price
+            self.entry_price = price;
+        }
+
+        realized
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PositionBook {
+    positions: IndexMap<CompactString, Position>,
+    open_interest: OrderQuantity, // contracts outstanding, i.e. the sum of the long positions
+}
+
+impl PositionBook {
+    #[inline]
+    pub fn get(&self, account_id: &str) -> Option<&Position> {
+        self.positions.get(account_id)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, account_id: &str) -> Option<&mut Position> {
+        self.positions.get_mut(account_id)
+    }
+
+    #[inline]
+    pub fn open_interest(&self) -> OrderQuantity {
+        self.open_interest
+    }
+
+    // open interest goes up with new exposure and down with closes, only the long leg of each fill counts
+    #[inline]
+    pub fn apply_fill(
+        &mut self,
+        account_id: &str,
+        side: OrderSide,
+        quantity: OrderQuantity,
+        price: OrderPrice,
+    ) -> Decimal {
+        let position = self.positions.entry(account_id.into()).or_default();
+        let long_before = position.size.max(Decimal::ZERO);
+        let realized = position.apply_fill(side, quantity, price);
+        let long_after = position.size.max(Decimal::ZERO);
+        self.open_interest = OrderQuantity::from_decimal(self.open_interest.value() + long_after - long_before);
+        realized
+    }
+
+    // undoes a fill (e.g. of a busted trade) by the offsetting one at its price: the size and the open interest are as
+    // before it, a position increased by it keeps the entry price it got
+    #[inline]
+    pub fn reverse_fill(
+        &mut self,
+        account_id: &str,
+        side: OrderSide,
+        quantity: OrderQuantity,
+        price: OrderPrice,
+    ) -> Decimal {
+        let side = match side {
+            OrderSide::Bid => OrderSide::Ask,
+            OrderSide::Ask => OrderSide::Bid,
+        };
+        self.apply_fill(account_id, side, quantity, price)
+    }
+
+    #[inline]
+    pub fn add_margin(&mut self, account_id: &str, amount: Decimal) {
+        self.positions.entry(account_id.into()).or_default().margin += amount;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&CompactString, &Position)> {
+        self.positions.iter()
+    }
+
+    // integrity check: the tracked open interest matches the positions and every long has a short on the other side
+    pub fn verify(&self) -> Result<(), PositionError> {
+        let long: Decimal = self
+            .positions
+            .values()
+            .map(|position| position.size.max(Decimal::ZERO))
+            .sum();
+        let short: Decimal = self
+            .positions
+            .values()
+            .map(|position| (-position.size).max(Decimal::ZERO))
+            .sum();
+
+        let (long, short) = (OrderQuantity::from_decimal(long), OrderQuantity::from_decimal(short));
+        if long != self.open_interest {
+            return Err(PositionError::OpenInterestMismatch {
+                tracked: self.open_interest,
+                computed: long,
+            });
+        }
+        if long != short {
+            return Err(PositionError::Unbalanced { long, short });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PositionError {
+    #[error("open interest does not match the positions (tracked={}, computed={})", .tracked, .computed)]
+    OpenInterestMismatch {
+        tracked: OrderQuantity,
+        computed: OrderQuantity,
+    },
+    #[error("long and short positions do not offset (long={}, short={})", .long, .short)]
+    Unbalanced { long: OrderQuantity, short: OrderQuantity },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    #[fixture]
+    fn long_010_at_100() -> Position {
+        let mut position = Position::default();
+        position.apply_fill(OrderSide::Bid, 10.into(), 100.into());
+        position
+    }
+
+    #[rstest]
+    fn increase_averages_entry(mut long_010_at_100: Position) {
+        assert_eq!(long_010_at_100.side(), Some(OrderSide::Bid));
+        assert_eq!(
+            long_010_at_100.apply_fill(OrderSide::Bid, 10.into(), 110.into()),
+            Decimal::ZERO
+        );
+        assert_eq!(long_010_at_100.size, Decimal::from(20));
+        assert_eq!(long_010_at_100.entry_price, OrderPrice::from(105));
+    }
+
+    #[rstest]
+    fn reduce_and_close_realize_pnl(mut long_010_at_100: Position) {
+        assert_eq!(
+            long_010_at_100.apply_fill(OrderSide::Ask, 4.into(), 110.into()),
+            Decimal::from(40)
+        );
+        assert_eq!(long_010_at_100.size, Decimal::from(6));
+        assert_eq!(long_010_at_100.entry_price, OrderPrice::from(100));
+
+        assert_eq!(
+            long_010_at_100.apply_fill(OrderSide::Ask, 6.into(), 90.into()),
+            Decimal::from(-60)
+        );
+        assert!(long_010_at_100.is_flat());
+        assert_eq!(long_010_at_100.realized_pnl, Decimal::from(-20));
+    }
+
+    #[rstest]
+    fn flip_reopens_at_fill_price(mut long_010_at_100: Position) {
+        assert_eq!(
+            long_010_at_100.apply_fill(OrderSide::Ask, 15.into(), 120.into()),
+            Decimal::from(200)
+        );
+        assert_eq!(long_010_at_100.side(), Some(OrderSide::Ask));
+        assert_eq!(long_010_at_100.size, Decimal::from(-5));
+        assert_eq!(long_010_at_100.entry_price, OrderPrice::from(120));
+        assert_eq!(long_010_at_100.unrealized_pnl(110.into()), Decimal::from(50));
+    }
+
+    #[rstest]
+    fn leverage_needs_margin(mut long_010_at_100: Position) {
+        assert_eq!(long_010_at_100.leverage(100.into()), None);
+        long_010_at_100.margin = 200.into();
+        assert_eq!(long_010_at_100.leverage(100.into()), Some(5.into()));
+    }
+
+    #[rstest]
+    fn open_interest_from_fills() {
+        let mut positions = PositionBook::default();
+
+        // 1 buys 10 from 2: new exposure on both sides
+        positions.apply_fill("1", OrderSide::Bid, 10.into(), 100.into());
+        positions.apply_fill("2", OrderSide::Ask, 10.into(), 100.into());
+        assert_eq!(positions.open_interest(), OrderQuantity::from(10));
+
+        // 1 sells 4 to 3: the long is transferred, no change
+        positions.apply_fill("1", OrderSide::Ask, 4.into(), 100.into());
+        positions.apply_fill("3", OrderSide::Bid, 4.into(), 100.into());
+        assert_eq!(positions.open_interest(), OrderQuantity::from(10));
+
+        // 2 buys 6 back from 1: both close
+        positions.apply_fill("2", OrderSide::Bid, 6.into(), 100.into());
+        positions.apply_fill("1", OrderSide::Ask, 6.into(), 100.into());
+        assert_eq!(positions.open_interest(), OrderQuantity::from(4));
+        assert_eq!(positions.verify(), Ok(()));
+
+        // a fill without its counterparty breaks the integrity check
+        positions.apply_fill("4", OrderSide::Bid, 1.into(), 100.into());
+        assert_eq!(
+            positions.verify(),
+            Err(PositionError::Unbalanced {
+                long: 5.into(),
+                short: 4.into()
+            })
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{Timestamp, SECOND},
+    index::{IndexComposer, IndexError},
+    order::OrderPrice,
+};
+
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+// price of a symbol (index constituent, FX rate...) as published by the source
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PriceUpdate {
+    pub symbol: CompactString,
+    pub price: OrderPrice,
+    pub timestamp: Timestamp,
+}
+
+// external prices pushed into the engine, polled so the engine keeps control of when they are applied
+pub trait PriceFeed: Send {
+    fn source(&self) -> &str;
+
+    // updates received since the last poll
+    fn poll(&mut self) -> Vec<PriceUpdate>;
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeedMetrics {
+    pub updates: u64,
+    pub out_of_order: u64,
+    pub last_timestamp: Timestamp,
+    pub last_received: Timestamp,
+    pub max_delay: Timestamp, // between the source timestamp and the time it was applied
+}
+
+impl FeedMetrics {
+    #[inline]
+    pub fn age(&self, now: Timestamp) -> Timestamp {
+        now.saturating_sub(self.last_timestamp)
+    }
+}
+
+// latest price of every (source, symbol) with its staleness metrics
+#[derive(Clone, Debug)]
+pub struct PriceBoard {
+    prices: IndexMap<(CompactString, CompactString), (OrderPrice, FeedMetrics)>,
+    max_age: Timestamp,
+}
+
+impl Default for PriceBoard {
+    fn default() -> Self {
+        Self {
+            prices: IndexMap::new(),
+            max_age: 10 * SECOND,
+        }
+    }
+}
+
+impl PriceBoard {
+    pub fn with_max_age(mut self, max_age: Timestamp) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    // returns the updates applied, older than the latest known price are discarded
+    pub fn pull(&mut self, feed: &mut dyn PriceFeed, now: Timestamp) -> Vec<PriceUpdate> {
+        let source = CompactString::new(feed.source());
+        let mut applied = vec![];
+        for update in feed.poll() {
+            let (price, metrics) = self.prices.entry((source.clone(), update.symbol.clone())).or_default();
+            if metrics.updates > 0 && update.timestamp < metrics.last_timestamp {
+                metrics.out_of_order += 1;
+                continue;
+            }
+
+            *price = update.price;
+            metrics.updates += 1;
+            metrics.last_timestamp = update.timestamp;
+            metrics.last_received = now;
+            metrics.max_delay = metrics.max_delay.max(now.saturating_sub(update.timestamp));
+            applied.push(update);
+        }
+        applied
+    }
+
+    // only fresh prices
+    pub fn price(&self, source: &str, symbol: &str, now: Timestamp) -> Option<OrderPrice> {
+        self.prices
+            .get(&(source.into(), symbol.into()))
+            .filter(|(_, metrics)| metrics.age(now) <= self.max_age)
+            .map(|(price, _)| *price)
+    }
+
+    #[inline]
+    pub fn metrics(&self, source: &str, symbol: &str) -> Option<&FeedMetrics> {
+        self.prices
+            .get(&(source.into(), symbol.into()))
+            .map(|(_, metrics)| metrics)
+    }
+
+    pub fn stale(&self, now: Timestamp) -> Vec<(&str, &str, Timestamp)> {
+        self.prices
+            .iter()
+            .filter(|(_, (_, metrics))| metrics.age(now) > self.max_age)
+            .map(|((source, symbol), (_, metrics))| (source.as_str(), symbol.as_str(), metrics.age(now)))
+            .collect()
+    }
+
+    // the source of each price is the index constituent, the composer decides on staleness by itself
+    pub fn feed_index(&self, symbol: &str, composer: &mut IndexComposer) -> Result<(), IndexError> {
+        for ((source, _), (price, metrics)) in self.prices.iter().filter(|((_, s), _)| s.as_str() == symbol) {
+            if composer.constituent(source).is_some() {
+                composer.update(source, *price, metrics.last_timestamp)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    struct ManualFeed(&'static str, Vec<PriceUpdate>);
+
+    impl PriceFeed for ManualFeed {
+        fn source(&self) -> &str {
+            self.0
+        }
+
+        fn poll(&mut self) -> Vec<PriceUpdate> {
+            std::mem::take(&mut self.1)
+        }
+    }
+
+    fn update(symbol: &str, price: u32, timestamp: Timestamp) -> PriceUpdate {
+        PriceUpdate {
+            symbol: symbol.into(),
+            price: price.into(),
+            timestamp,
+        }
+    }
+
+    #[rstest]
+    fn staleness_metrics() {
+        let mut board = PriceBoard::default();
+        let mut feed = ManualFeed(
+            "binance",
+            vec![update("ETH", 100, SECOND), update("ETH", 99, 0), update("BTC", 1000, 0)],
+        );
+
+        // the late ETH update is discarded
+        assert_eq!(board.pull(&mut feed, 2 * SECOND).len(), 2);
+        let metrics = board.metrics("binance", "ETH").unwrap();
+        assert_eq!((metrics.updates, metrics.out_of_order), (1, 1));
+        assert_eq!(metrics.max_delay, SECOND);
+        assert_eq!(board.metrics("binance", "BTC").unwrap().max_delay, 2 * SECOND);
+
+        // BTC goes stale first
+        assert_eq!(board.price("binance", "ETH", 11 * SECOND), Some(100.into()));
+        assert_eq!(board.price("binance", "BTC", 11 * SECOND), None);
+        assert_eq!(board.stale(11 * SECOND), vec![("binance", "BTC", 11 * SECOND)]);
+    }
+
+    #[rstest]
+    fn push_into_index() {
+        let mut board = PriceBoard::default();
+        board.pull(&mut ManualFeed("binance", vec![update("ETH", 100, 0)]), 0);
+        board.pull(&mut ManualFeed("kraken", vec![update("ETH", 102, 0)]), 0);
+
+        let mut composer = IndexComposer::default();
+        composer.add_source("binance", 1.into()).unwrap();
+        composer.add_source("kraken", 1.into()).unwrap();
+        board.feed_index("ETH", &mut composer).unwrap();
+        assert_eq!(composer.price(0).unwrap().price, OrderPrice::from(101));
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+    Arc,
+};
+
+use compact_str::CompactString;
+use crossbeam_channel::{unbounded, Receiver};
+use futures_util::StreamExt;
+use thiserror::Error;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, Message},
+};
+
+use super::{PriceFeed, PriceUpdate};
+
+// reference feed: one JSON PriceUpdate per text message, read in the background by a tokio task
+pub struct WebSocketPriceFeed {
+    source: CompactString,
+    updates: Receiver<PriceUpdate>,
+    malformed: Arc<AtomicU64>,
+    connected: Arc<AtomicBool>,
+}
+
+impl WebSocketPriceFeed {
+    pub async fn connect(source: &str, url: &str) -> Result<Self, PriceFeedError> {
+        let (mut socket, _) = connect_async(url).await?;
+        let (tx, updates) = unbounded();
+        let malformed = Arc::new(AtomicU64::new(0));
+        let connected = Arc::new(AtomicBool::new(true));
+
+        let (task_malformed, task_connected) = (malformed.clone(), connected.clone());
+        tokio::spawn(async move {
+            while let Some(Ok(message)) = socket.next().await {
+                let text = match message {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                match serde_json::from_str::<PriceUpdate>(&text) {
+                    Ok(update) => {
+                        if tx.send(update).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => {
+                        task_malformed.fetch_add(1, Relaxed);
+                    }
+                }
+            }
+            task_connected.store(false, Relaxed);
+        });
+
+        Ok(Self {
+            source: source.into(),
+            updates,
+            malformed,
+            connected,
+        })
+    }
+
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Relaxed)
+    }
+
+    #[inline]
+    pub fn malformed(&self) -> u64 {
+        self.malformed.load(Relaxed)
+    }
+}
+
+impl PriceFeed for WebSocketPriceFeed {
+    fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn poll(&mut self) -> Vec<PriceUpdate> {
+        self.updates.try_iter().collect()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PriceFeedError {
+    #[error("price feed websocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+}
+
+impl From<tungstenite::Error> for PriceFeedError {
+    fn from(error: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    use super::*;
+    use crate::{clock::SECOND, price_feed::PriceBoard};
+
+    #[tokio::test]
+    async fn consume_json_stream() {
+        // a provider sending one malformed message, one price and then closing
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            for text in [
+                "not a price",
+                r#"{"symbol":"ETH/USDT","price":"2000.5","timestamp":1000}"#,
+            ] {
+                socket.send(Message::Text(text.to_owned())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+        });
+
+        let mut feed = WebSocketPriceFeed::connect("provider", &format!("ws://{addr}"))
+            .await
+            .unwrap();
+        while feed.is_connected() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(feed.malformed(), 1);
+
+        let mut board = PriceBoard::default();
+        assert_eq!(board.pull(&mut feed, 2 * SECOND).len(), 1);
+        assert_eq!(
+            board.price("provider", "ETH/USDT", 2 * SECOND),
+            Some("2000.5".parse().unwrap())
+        );
+    }
+}
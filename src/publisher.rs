@@ -0,0 +1,402 @@
+use std::collections::VecDeque;
+
+use compact_str::CompactString;
+use crossbeam_channel::Receiver;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+    engine::listener::ExecutionListener,
+    market_data::{BookEvent, MarketDataEvent},
+    order::OrderRequest,
+    trade::Trade,
+};
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod nats;
+
+// an engine event as published, one JSON message each
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE", tag = "type")]
+pub enum PublishedEvent {
+    Accepted {
+        pair: CompactString,
+        order_request: OrderRequest,
+    },
+    Filled {
+        pair: CompactString,
+        trade: Trade,
+        maker: CompactString, // accounts of both sides
+        taker: CompactString,
+    },
+    Cancelled {
+        pair: CompactString,
+        order_id: u64,
+    },
+    Rejected {
+        pair: CompactString,
+        order_request: OrderRequest,
+        reason: CompactString,
+    },
+    Expired {
+        pair: CompactString,
+        order_id: u64,
+    },
+    Book {
+        pair: CompactString,
+        sequence: u64,
+        event: BookEvent,
+    },
+}
+
+// what goes to the broker: the topic (Kafka) or subject (NATS) and the key the messages are partitioned by, if any
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub topic: CompactString,
+    pub key: Option<CompactString>,
+    pub payload: Vec<u8>,
+}
+
+// a broker confirms the message is stored (or fails) before returning, which is what makes the delivery at-least-once
+pub trait Broker: Send {
+    fn publish(&mut self, message: &Message) -> Result<(), BrokerError>;
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Partitioning {
+    Single, // one stream for all the pairs
+    #[default]
+    ByPair, // keyed by pair: a Kafka partition or a NATS subject of its own, in order within the pair
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PublisherConfig {
+    pub partitioning: Partitioning,
+    pub retries: usize,        // right away, before the message is spilled
+    pub spill_capacity: usize, // messages kept while the broker is down, the oldest are dropped beyond
+}
+
+impl Default for PublisherConfig {
+    fn default() -> Self {
+        Self {
+            partitioning: Partitioning::default(),
+            retries: 3,
+            spill_capacity: 100_000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PublisherStats {
+    pub published: u64,
+    pub retried: u64,
+    pub spilled: u64, // every message that went through the spill buffer
+    pub dropped: u64, // lost to a full spill buffer
+}
+
+// publishes the events of an engine to a broker as its execution listener, and the book deltas of a market data
+// subscription along with them; a message the broker does not take after the retries waits in the spill buffer, the
+// buffer is sent again first (in order) on the next event or on a flush
+pub struct EventPublisher<B: Broker> {
+    broker: B,
+    pair: CompactString,
+    topic: CompactString,
+    config: PublisherConfig,
+    market_data: Option<Receiver<MarketDataEvent>>,
+    spill: VecDeque<Message>,
+    stats: PublisherStats,
+}
+
+impl<B: Broker> EventPublisher<B> {
+    pub fn new(broker: B, pair: &str, topic: &str, config: PublisherConfig) -> Self {
+        Self {
+            broker,
+            pair: pair.into(),
+            topic: topic.into(),
+            config,
+            market_data: None,
+            spill: VecDeque::new(),
+            stats: PublisherStats::default(),
+        }
+    }
+
+    // book deltas, from Engine::subscribe at the granularity wanted
+    pub fn with_market_data(mut self, market_data: Receiver<MarketDataEvent>) -> Self {
+        self.market_data = Some(market_data);
+        self
+    }
+
+    #[inline]
+    pub fn broker(&self) -> &B {
+        &self.broker
+    }
+
+    #[inline]
+    pub fn stats(&self) -> PublisherStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn spilled(&self) -> usize {
+        self.spill.len()
+    }
+
+    pub fn publish(&mut self, event: &PublishedEvent) {
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => payload,
+            Err(error) => {
+                warn!("event not serialized: {error} ({})", self.pair);
+                return;
+            }
+        };
+        let key = (self.config.partitioning == Partitioning::ByPair).then(|| self.pair.clone());
+        let message = Message {
+            topic: self.topic.clone(),
+            key,
+            payload,
+        };
+
+        // behind the spilled ones, to keep the order
+        if !self.flush() || !self.send(&message) {
+            self.spill(message);
+        }
+    }
+
+    // tick driven too: the book deltas received so far, then the spill buffer; returns whether the buffer is empty
+    pub fn flush(&mut self) -> bool {
+        let book_events: Vec<MarketDataEvent> = self
+            .market_data
+            .as_ref()
+            .map(|market_data| market_data.try_iter().collect())
+            .unwrap_or_default();
+        let spilled = self.drain_spill();
+        for MarketDataEvent { sequence, event } in book_events {
+            self.publish(&PublishedEvent::Book {
+                pair: self.pair.clone(),
+                sequence,
+                event,
+            });
+        }
+        spilled && self.spill.is_empty()
+    }
+
+    fn drain_spill(&mut self) -> bool {
+        while let Some(message) = self.spill.pop_front() {
+            if !self.send(&message) {
+                self.spill.push_front(message);
+                return false;
+            }
+        }
+        true
+    }
+
+    fn send(&mut self, message: &Message) -> bool {
+        for attempt in 0..=self.config.retries {
+            if attempt > 0 {
+                self.stats.retried += 1;
+            }
+            match self.broker.publish(message) {
+                Ok(()) => {
+                    self.stats.published += 1;
+                    return true;
+                }
+                Err(error) if attempt == self.config.retries => {
+                    warn!("message not published: {error} ({})", self.pair);
+                }
+                Err(_) => {}
+            }
+        }
+        false
+    }
+
+    fn spill(&mut self, message: Message) {
+        if self.config.spill_capacity == 0 {
+            self.stats.dropped += 1;
+            return;
+        }
+        if self.spill.len() >= self.config.spill_capacity {
+            self.spill.pop_front();
+            self.stats.dropped += 1;
+        }
+        self.spill.push_back(message);
+        self.stats.spilled += 1;
+    }
+}
+
+impl<B: Broker> ExecutionListener for EventPublisher<B> {
+    fn on_accept(&mut self, order_request: &OrderRequest) {
+        self.publish(&PublishedEvent::Accepted {
+            pair: self.pair.clone(),
+            order_request: order_request.clone(),
+        });
+    }
+
+    fn on_fill(&mut self, trade: &Trade, maker: &str, taker: &str) {
+        self.publish(&PublishedEvent::Filled {
+            pair: self.pair.clone(),
+            trade: *trade,
+            maker: maker.into(),
+            taker: taker.into(),
+        });
+    }
+
+    fn on_cancel(&mut self, order_id: u64) {
+        self.publish(&PublishedEvent::Cancelled {
+            pair: self.pair.clone(),
+            order_id,
+        });
+    }
+
+    fn on_reject(&mut self, order_request: &OrderRequest, reason: &str) {
+        self.publish(&PublishedEvent::Rejected {
+            pair: self.pair.clone(),
+            order_request: order_request.clone(),
+            reason: reason.into(),
+        });
+    }
+
+    fn on_expire(&mut self, order_id: u64) {
+        self.publish(&PublishedEvent::Expired {
+            pair: self.pair.clone(),
+            order_id,
+        });
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BrokerError {
+    #[error("broker io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("broker refused the message! {0}")]
+    Refused(CompactString),
+    #[cfg(feature = "kafka")]
+    #[error("kafka error: {0}")]
+    Kafka(#[from] rdkafka::error::KafkaError),
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        engine::Engine,
+        market_data::Granularity,
+        order::{
+            util::{self, DEFAULT_PAIR, DEFAULT_SYMBOL},
+            OrderSide,
+        },
+    };
+
+    type Published = Vec<(Option<CompactString>, PublishedEvent)>;
+
+    // down until told otherwise, keeps the keys and events of the messages it took
+    #[derive(Clone, Default)]
+    struct FlakyBroker {
+        down: Arc<Mutex<bool>>,
+        published: Arc<Mutex<Published>>,
+    }
+
+    impl Broker for FlakyBroker {
+        fn publish(&mut self, message: &Message) -> Result<(), BrokerError> {
+            if *self.down.lock().unwrap() {
+                return Err(BrokerError::Refused("down".into()));
+            }
+            let event = serde_json::from_slice(&message.payload).unwrap();
+            self.published.lock().unwrap().push((message.key.clone(), event));
+            Ok(())
+        }
+    }
+
+    fn create(order_id: u64, side: OrderSide) -> OrderRequest {
+        util::create(order_id, side).account_id(&format!("{order_id}")).build()
+    }
+
+    fn types(broker: &FlakyBroker) -> Vec<&'static str> {
+        broker
+            .published
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, event)| match event {
+                PublishedEvent::Accepted { .. } => "accepted",
+                PublishedEvent::Filled { .. } => "filled",
+                PublishedEvent::Cancelled { .. } => "cancelled",
+                PublishedEvent::Rejected { .. } => "rejected",
+                PublishedEvent::Expired { .. } => "expired",
+                PublishedEvent::Book { .. } => "book",
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn publish_the_engine_events() {
+        let broker = FlakyBroker::default();
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        let market_data = engine.subscribe(Granularity::Level);
+        let publisher = EventPublisher::new(broker.clone(), DEFAULT_PAIR, "merx", PublisherConfig::default())
+            .with_market_data(market_data);
+        let mut engine = engine.with_execution_listener(publisher);
+
+        engine.process(create(1, OrderSide::Ask)).unwrap();
+        engine.process(create(2, OrderSide::Bid)).unwrap();
+        // the book deltas received so far go ahead of the next event
+        assert_eq!(
+            types(&broker),
+            vec!["book", "accepted", "book", "book", "accepted", "filled"]
+        );
+        let published = broker.published.lock().unwrap();
+        assert!(published.iter().all(|(key, _)| key.as_deref() == Some(DEFAULT_PAIR)));
+        assert!(matches!(
+            &published[5].1,
+            PublishedEvent::Filled { maker, taker, .. } if maker.as_str() == "1" && taker.as_str() == "2"
+        ));
+    }
+
+    #[rstest]
+    fn spill_while_the_broker_is_down() {
+        let broker = FlakyBroker::default();
+        let config = PublisherConfig {
+            partitioning: Partitioning::Single,
+            retries: 2,
+            spill_capacity: 2,
+        };
+        let mut publisher = EventPublisher::new(broker.clone(), DEFAULT_PAIR, "merx", config);
+        *broker.down.lock().unwrap() = true;
+        for order_id in 1..=3 {
+            publisher.on_cancel(order_id);
+        }
+        assert_eq!(publisher.spilled(), 2);
+        assert!(!publisher.flush());
+
+        // the oldest was dropped, the others go first and in order
+        *broker.down.lock().unwrap() = false;
+        publisher.on_expire(4);
+        let published = broker.published.lock().unwrap();
+        let order_ids: Vec<u64> = published
+            .iter()
+            .map(|(key, event)| {
+                assert!(key.is_none());
+                match event {
+                    PublishedEvent::Cancelled { order_id, .. } | PublishedEvent::Expired { order_id, .. } => *order_id,
+                    _ => unreachable!(),
+                }
+            })
+            .collect();
+        assert_eq!(order_ids, vec![2, 3, 4]);
+        assert_eq!(
+            publisher.stats(),
+            PublisherStats {
+                published: 3,
+                retried: 8, // 2 for each of the 4 failed sends
+                spilled: 3,
+                dropped: 1,
+            }
+        );
+    }
+}
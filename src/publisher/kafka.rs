@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use rdkafka::{
+    config::ClientConfig,
+    error::KafkaError,
+    producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext},
+    ClientContext,
+};
+
+use super::{Broker, BrokerError, Message};
+
+// remembers whether a message of the last flush failed, the producer only reports it through its context
+#[derive(Default)]
+struct DeliveryContext {
+    failed: std::sync::Mutex<Option<KafkaError>>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((error, _)) = delivery_result {
+            if let Ok(mut failed) = self.failed.lock() {
+                *failed = Some(error.clone());
+            }
+        }
+    }
+}
+
+// Kafka through librdkafka with acks from all the in-sync replicas: a message is published once its delivery is
+// confirmed, within the timeout; the key (the pair) picks the partition
+pub struct KafkaBroker {
+    producer: BaseProducer<DeliveryContext>,
+    timeout: Duration,
+}
+
+impl KafkaBroker {
+    // the brokers as bootstrap.servers, e.g. "localhost:9092"
+    pub fn new(brokers: &str, timeout: Duration) -> Result<Self, BrokerError> {
+        Self::with_config(ClientConfig::new().set("bootstrap.servers", brokers), timeout)
+    }
+
+    // any other producer setting, the acks and idempotence are set on top
+    pub fn with_config(config: &ClientConfig, timeout: Duration) -> Result<Self, BrokerError> {
+        let producer = config
+            .clone()
+            .set("acks", "all")
+            .set("enable.idempotence", "true")
+            .create_with_context(DeliveryContext::default())?;
+        Ok(Self { producer, timeout })
+    }
+}
+
+impl Broker for KafkaBroker {
+    fn publish(&mut self, message: &Message) -> Result<(), BrokerError> {
+        let mut record = BaseRecord::to(&message.topic).payload(&message.payload);
+        if let Some(key) = message.key.as_deref() {
+            record = record.key(key);
+        }
+        self.producer.send(record).map_err(|(error, _)| error)?;
+        self.producer.flush(self.timeout)?;
+
+        let failed = self
+            .producer
+            .context()
+            .failed
+            .lock()
+            .ok()
+            .and_then(|mut failed| failed.take());
+        match failed {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+}
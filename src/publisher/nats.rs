@@ -0,0 +1,182 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use compact_str::{format_compact, CompactString};
+
+use super::{Broker, BrokerError, Message};
+
+// core NATS over a plain TCP connection: every PUB is followed by a PING and the PONG of the server confirms it got the
+// message (nothing more durable without JetStream); the connection is made again on the next message after a failure
+pub struct NatsBroker {
+    address: CompactString,
+    timeout: Duration,
+    connection: Option<(BufReader<TcpStream>, TcpStream)>,
+}
+
+impl NatsBroker {
+    // not connected before the first message
+    pub fn new(address: &str, timeout: Duration) -> Self {
+        Self {
+            address: address.into(),
+            timeout,
+            connection: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    fn connect(&self) -> Result<(BufReader<TcpStream>, TcpStream), BrokerError> {
+        let address = self
+            .address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| BrokerError::Refused(format_compact!("no address for {}", self.address)))?;
+        let stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let info = read_line(&mut reader)?;
+        if !info.starts_with("INFO") {
+            return Err(BrokerError::Refused(info));
+        }
+        writer.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"merx\"}\r\n")?;
+        Ok((reader, writer))
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), BrokerError> {
+        let connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+        let (reader, writer) = self.connection.insert(connection);
+
+        let subject = subject(message);
+        let mut frame = format!("PUB {subject} {}\r\n", message.payload.len()).into_bytes();
+        frame.extend_from_slice(&message.payload);
+        frame.extend_from_slice(b"\r\nPING\r\n");
+        writer.write_all(&frame)?;
+
+        loop {
+            let line = read_line(reader)?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                "PING" => writer.write_all(b"PONG\r\n")?,
+                line if line.starts_with("-ERR") => return Err(BrokerError::Refused(line.into())),
+                _ => {} // +OK, INFO updates
+            }
+        }
+    }
+}
+
+impl Broker for NatsBroker {
+    fn publish(&mut self, message: &Message) -> Result<(), BrokerError> {
+        let result = self.send(message);
+        if result.is_err() {
+            self.connection = None;
+        }
+        result
+    }
+}
+
+// the key is the last token of the subject, without the characters NATS gives a meaning to
+fn subject(message: &Message) -> CompactString {
+    match message.key.as_deref() {
+        Some(key) => {
+            let key: CompactString = key
+                .chars()
+                .map(|c| match c {
+                    '.' | '*' | '>' | '/' => '-',
+                    c if c.is_whitespace() => '-',
+                    c => c,
+                })
+                .collect();
+            format_compact!("{}.{key}", message.topic)
+        }
+        None => message.topic.clone(),
+    }
+}
+
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<CompactString, BrokerError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(BrokerError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(line.trim_end().into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::TcpListener, thread};
+
+    use rstest::rstest;
+
+    use super::*;
+
+    // accepts one connection and answers like a NATS server, returns the subjects and payloads received
+    fn server(listener: TcpListener, messages: usize) -> thread::JoinHandle<Vec<(String, String)>> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"INFO {\"server_id\":\"test\"}\r\n").unwrap();
+            let mut received = vec![];
+            let mut line = String::new();
+            while received.len() < messages {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                match parts.as_slice() {
+                    ["PUB", subject, _] => {
+                        let mut payload = String::new();
+                        reader.read_line(&mut payload).unwrap();
+                        received.push((subject.to_string(), payload.trim_end().to_string()));
+                    }
+                    ["PING"] => writer.write_all(b"PONG\r\n").unwrap(),
+                    _ => {}
+                }
+            }
+            // the PING of the last message
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            writer.write_all(b"PONG\r\n").unwrap();
+            received
+        })
+    }
+
+    #[rstest]
+    fn publish_by_subject() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = server(listener, 2);
+
+        let mut broker = NatsBroker::new(&address, Duration::from_secs(5));
+        let message = |key: Option<&str>, payload: &str| Message {
+            topic: "merx.events".into(),
+            key: key.map(Into::into),
+            payload: payload.as_bytes().to_vec(),
+        };
+        broker.publish(&message(Some("ETH/USDT"), "{}")).unwrap();
+        broker.publish(&message(None, "[]")).unwrap();
+        assert!(broker.is_connected());
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                ("merx.events.ETH-USDT".to_string(), "{}".to_string()),
+                ("merx.events".to_string(), "[]".to_string())
+            ]
+        );
+
+        // the server is gone
+        assert!(broker.publish(&message(None, "{}")).is_err());
+        assert!(!broker.is_connected());
+    }
+}
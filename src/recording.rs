@@ -0,0 +1,481 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    marker::PhantomData,
+    path::Path,
+    str::FromStr,
+};
+
+use compact_str::CompactString;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(feature = "csv")]
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::{
+    clock::Timestamp,
+    order::{OrderPrice, OrderQuantity, OrderSide},
+    orderbook::Depth,
+};
+
+// columns of the CSV files, named like the fields of the JSON ones; a request may come with the time it was recorded at
+// (see TimedRequest), the columns not known are ignored
+pub const REQUEST_COLUMNS: [&str; 17] = [
+    "at",
+    "order_request",
+    "account_id",
+    "order_id",
+    "pair",
+    "side",
+    "limit_price",
+    "quantity",
+    "time_in_force",
+    "expires_at",
+    "post_only",
+    "fill_or_kill",
+    "short_sell",
+    "priority_fee",
+    "client_order_id",
+    "price_protection",
+    "reduce_only",
+];
+pub const TRADE_COLUMNS: [&str; 11] = [
+    "id",
+    "taker",
+    "maker",
+    "side",
+    "price",
+    "quantity",
+    "maker_fee",
+    "taker_fee",
+    "short_sell",
+    "odd_lot",
+    "busted",
+];
+pub const BOOK_COLUMNS: [&str; 6] = ["at", "side", "level", "price", "quantity", "orders"];
+
+// the CSV cells given to the JSON parser as numbers or booleans, the others as strings
+#[cfg(feature = "csv")]
+const NUMBER_COLUMNS: [&str; 8] = [
+    "at",
+    "order_id",
+    "expires_at",
+    "id",
+    "taker",
+    "maker",
+    "level",
+    "orders",
+];
+#[cfg(feature = "csv")]
+const BOOL_COLUMNS: [&str; 6] = [
+    "post_only",
+    "fill_or_kill",
+    "short_sell",
+    "reduce_only",
+    "odd_lot",
+    "busted",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    JsonLines,
+    #[cfg(feature = "csv")]
+    Csv, // with a header row
+}
+
+impl RecordFormat {
+    // by the extension of the file: .csv, or .jsonl (.ndjson, .json)
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        let extension = path.as_ref().extension().and_then(|extension| extension.to_str());
+        extension.unwrap_or_default().parse()
+    }
+}
+
+impl Display for RecordFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordFormat::JsonLines => write!(f, "jsonl"),
+            #[cfg(feature = "csv")]
+            RecordFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = RecordingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" | "ndjson" | "json" => Ok(RecordFormat::JsonLines),
+            #[cfg(feature = "csv")]
+            "csv" => Ok(RecordFormat::Csv),
+            _ => Err(RecordingError::UnknownFormat(s.into())),
+        }
+    }
+}
+
+// one price level of a book snapshot, best first
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BookLevel {
+    pub at: Timestamp,
+    pub side: OrderSide,
+    pub level: usize,
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+    pub orders: usize,
+}
+
+impl BookLevel {
+    // bids then asks
+    pub fn from_depth(at: Timestamp, depth: &Depth) -> impl Iterator<Item = BookLevel> + '_ {
+        [(OrderSide::Bid, &depth.bids), (OrderSide::Ask, &depth.asks)]
+            .into_iter()
+            .flat_map(move |(side, levels)| {
+                levels
+                    .iter()
+                    .enumerate()
+                    .map(move |(level, (price, quantity, orders))| BookLevel {
+                        at,
+                        side,
+                        level,
+                        price: *price,
+                        quantity: *quantity,
+                        orders: *orders,
+                    })
+            })
+    }
+}
+
+enum Source<R: BufRead> {
+    JsonLines {
+        reader: R,
+        line: String,
+    },
+    #[cfg(feature = "csv")]
+    Csv {
+        records: csv::StringRecordsIntoIter<R>,
+        header: csv::StringRecord,
+    },
+}
+
+// the records of a file one at a time, e.g. OrderRequest or TimedRequest; blank lines are skipped
+pub struct RecordReader<R: BufRead, T> {
+    source: Source<R>,
+    record: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> RecordReader<BufReader<File>, T> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        let format = RecordFormat::from_path(&path)?;
+        Self::new(BufReader::new(File::open(path)?), format)
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> RecordReader<R, T> {
+    pub fn new(reader: R, format: RecordFormat) -> Result<Self, RecordingError> {
+        let source = match format {
+            RecordFormat::JsonLines => Source::JsonLines {
+                reader,
+                line: String::new(),
+            },
+            #[cfg(feature = "csv")]
+            RecordFormat::Csv => {
+                let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+                let header = reader.headers()?.clone();
+                Source::Csv {
+                    records: reader.into_records(),
+                    header,
+                }
+            }
+        };
+        Ok(Self {
+            source,
+            record: PhantomData,
+        })
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for RecordReader<R, T> {
+    type Item = Result<T, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            Source::JsonLines { reader, line } => loop {
+                line.clear();
+                match reader.read_line(line) {
+                    Ok(0) => return None,
+                    Ok(_) if line.trim().is_empty() => continue,
+                    Ok(_) => return Some(serde_json::from_str(line).map_err(RecordingError::from)),
+                    Err(error) => return Some(Err(error.into())),
+                }
+            },
+            #[cfg(feature = "csv")]
+            Source::Csv { records, header } => {
+                let record = match records.next()? {
+                    Ok(record) => record,
+                    Err(error) => return Some(Err(error.into())),
+                };
+                Some(from_row(header, &record))
+            }
+        }
+    }
+}
+
+// the non-empty cells as a JSON object, parsed like a JSON line
+#[cfg(feature = "csv")]
+fn from_row<T: DeserializeOwned>(header: &csv::StringRecord, record: &csv::StringRecord) -> Result<T, RecordingError> {
+    let mut object = Map::new();
+    for (column, cell) in header.iter().zip(record.iter()).filter(|(_, cell)| !cell.is_empty()) {
+        let value = if NUMBER_COLUMNS.contains(&column) || BOOL_COLUMNS.contains(&column) {
+            serde_json::from_str(cell).map_err(|_| RecordingError::InvalidCell {
+                column: column.into(),
+                cell: cell.into(),
+            })?
+        } else {
+            Value::String(cell.into())
+        };
+        object.insert(column.into(), value);
+    }
+    Ok(serde_json::from_value(Value::Object(object))?)
+}
+
+#[cfg(feature = "csv")]
+fn to_row(columns: &[&str], record: &impl Serialize) -> Result<Vec<String>, RecordingError> {
+    let Value::Object(object) = serde_json::to_value(record)? else {
+        return Err(RecordingError::NotARecord);
+    };
+    let cell = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+    Ok(columns
+        .iter()
+        .map(|column| object.get(*column).map(cell).unwrap_or_default())
+        .collect())
+}
+
+enum Sink<W: Write> {
+    JsonLines(BufWriter<W>),
+    #[cfg(feature = "csv")]
+    Csv(Box<csv::Writer<W>>),
+}
+
+// records written one at a time, the CSV header first; the columns of a CSV file are fixed (e.g. TRADE_COLUMNS), the
+// fields of a record missing from them are left out
+pub struct RecordWriter<W: Write> {
+    sink: Sink<W>,
+    columns: &'static [&'static str],
+}
+
+impl RecordWriter<File> {
+    pub fn create(path: impl AsRef<Path>, columns: &'static [&'static str]) -> Result<Self, RecordingError> {
+        let format = RecordFormat::from_path(&path)?;
+        Self::new(File::create(path)?, format, columns)
+    }
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: RecordFormat, columns: &'static [&'static str]) -> Result<Self, RecordingError> {
+        let sink = match format {
+            RecordFormat::JsonLines => Sink::JsonLines(BufWriter::new(writer)),
+            #[cfg(feature = "csv")]
+            RecordFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                writer.write_record(columns)?;
+                Sink::Csv(Box::new(writer))
+            }
+        };
+        Ok(Self { sink, columns })
+    }
+
+    pub fn write(&mut self, record: &impl Serialize) -> Result<(), RecordingError> {
+        match &mut self.sink {
+            Sink::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+            #[cfg(feature = "csv")]
+            Sink::Csv(writer) => writer.write_record(to_row(self.columns, record)?)?,
+        }
+        Ok(())
+    }
+
+    // one record per level, see BookLevel
+    pub fn write_book(&mut self, at: Timestamp, depth: &Depth) -> Result<(), RecordingError> {
+        for level in BookLevel::from_depth(at, depth) {
+            self.write(&level)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn columns(&self) -> &[&str] {
+        self.columns
+    }
+
+    pub fn flush(&mut self) -> Result<(), RecordingError> {
+        match &mut self.sink {
+            Sink::JsonLines(writer) => writer.flush()?,
+            #[cfg(feature = "csv")]
+            Sink::Csv(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("recording io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("recording format error: {0}")]
+    Format(#[from] serde_json::Error),
+    #[cfg(feature = "csv")]
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("unknown recording format! {0}")]
+    UnknownFormat(CompactString),
+    #[error("invalid cell (column={}, cell={})", .column, .cell)]
+    InvalidCell { column: CompactString, cell: CompactString },
+    #[error("not a record, records are serialized as objects")]
+    NotARecord,
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        engine::Engine,
+        order::{
+            util::{self, DEFAULT_SYMBOL},
+            OrderRequest, TimeInForce,
+        },
+        simulation::TimedRequest,
+        trade::Trade,
+    };
+
+    fn requests() -> Vec<OrderRequest> {
+        let create = |order_id, side, limit_price: Option<u32>, time_in_force| {
+            util::create(order_id, side)
+                .limit_price(limit_price.map(OrderPrice::from))
+                .quantity(OrderQuantity::new(25, 1))
+                .time_in_force(time_in_force)
+                .client_order_id((order_id == 2).then(|| "a,\"b\"".into())) // quoted in CSV
+                .build()
+        };
+        vec![
+            create(1, OrderSide::Bid, Some(14), None),
+            create(
+                2,
+                OrderSide::Ask,
+                Some(16),
+                Some(TimeInForce::GoodTilDate {
+                    expires_at: 100,
+                    post_only: true,
+                }),
+            ),
+            create(3, OrderSide::Ask, None, None),
+            util::cancel(2),
+        ]
+    }
+
+    fn round_trip<T: Serialize + DeserializeOwned>(
+        format: RecordFormat,
+        columns: &'static [&'static str],
+        records: &[T],
+    ) -> Vec<T> {
+        let mut writer = RecordWriter::new(vec![], format, columns).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+        let written = match writer.sink {
+            Sink::JsonLines(writer) => writer.into_inner().unwrap(),
+            #[cfg(feature = "csv")]
+            Sink::Csv(writer) => writer.into_inner().unwrap(),
+        };
+        RecordReader::new(Cursor::new(written), format)
+            .unwrap()
+            .collect::<Result<Vec<T>, RecordingError>>()
+            .unwrap()
+    }
+
+    #[rstest]
+    #[case::json_lines(RecordFormat::JsonLines)]
+    #[cfg_attr(feature = "csv", case::csv(RecordFormat::Csv))]
+    fn round_trip_requests(#[case] format: RecordFormat) {
+        assert_eq!(round_trip(format, &REQUEST_COLUMNS, &requests()), requests());
+
+        let timed: Vec<TimedRequest> = requests()
+            .into_iter()
+            .map(|order_request| TimedRequest { at: 5, order_request })
+            .collect();
+        assert_eq!(round_trip(format, &REQUEST_COLUMNS, &timed), timed);
+    }
+
+    #[rstest]
+    #[case::json_lines(RecordFormat::JsonLines)]
+    #[cfg_attr(feature = "csv", case::csv(RecordFormat::Csv))]
+    fn export_trades_and_book(#[case] format: RecordFormat) {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        // the ask of 2 is left
+        for order_request in requests().into_iter().take(3) {
+            engine.process(order_request).unwrap();
+        }
+        let trades: Vec<Trade> = engine.orderbook().trades_from(0).copied().collect();
+        let exported = round_trip(format, &TRADE_COLUMNS, &trades);
+        assert_eq!(exported.len(), 1);
+        assert_eq!(
+            (exported[0].maker(), exported[0].price(), exported[0].quantity()),
+            (1.into(), 14.into(), OrderQuantity::new(25, 1))
+        );
+
+        let book: Vec<BookLevel> = BookLevel::from_depth(7, &engine.orderbook().depth(10)).collect();
+        assert_eq!(book.len(), 1);
+        assert_eq!(round_trip(format, &BOOK_COLUMNS, &book), book);
+    }
+
+    #[rstest]
+    fn skip_blank_lines() {
+        let input = "\n{\"order_request\":\"CANCEL\",\"order_id\":1}\n  \n";
+        let requests: Vec<OrderRequest> = RecordReader::new(input.as_bytes(), RecordFormat::JsonLines)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(matches!(
+            "parquet".parse::<RecordFormat>(),
+            Err(RecordingError::UnknownFormat(_))
+        ));
+    }
+
+    // recorded by hand: the columns in any order, some missing, others unknown
+    #[cfg(feature = "csv")]
+    #[rstest]
+    fn read_a_recorded_csv() {
+        let input = "\
+order_request,order_id,side,limit_price,quantity,account_id,pair,venue
+CREATE,1,BID,15,2,1,ETH/USDT,X
+CREATE,2,ASK, 15 ,1,2,ETH/USDT,X
+CANCEL,1,,,,1,,X
+";
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        for order_request in RecordReader::<_, OrderRequest>::new(input.as_bytes(), RecordFormat::Csv).unwrap() {
+            engine.process(order_request.unwrap()).unwrap();
+        }
+        assert_eq!(engine.orderbook().trade_count(), 1);
+        assert!(engine.get_order(1.into()).is_none());
+
+        let input = "order_request,order_id\nCANCEL,one\n";
+        let mut reader = RecordReader::<_, OrderRequest>::new(input.as_bytes(), RecordFormat::Csv).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(RecordingError::InvalidCell { column, .. })) if column == "order_id"
+        ));
+    }
+}
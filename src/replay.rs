@@ -0,0 +1,181 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    engine::Engine,
+    order::{
+        terse::{self, TerseDefaults, TerseError},
+        util::DEFAULT_SYMBOL,
+        OrderRequest, OrderSide,
+    },
+};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// FNV-1a, stable across platforms and toolchains unlike the std hasher
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Fnv(pub(crate) u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl Fnv {
+    pub(crate) fn write(&mut self, value: impl Display) {
+        for byte in value.to_string().bytes().chain([b'\n']) {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+// fingerprint of a run: two runs of the same requests must give the same digest, whatever the matching core looks like
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReplayDigest {
+    pub requests: u64,
+    pub rejected: u64,
+    pub trades: u64,
+    pub book: u64,         // resting orders in priority order
+    pub trade_stream: u64, // trades in execution order, their ids left out (they are global to the process)
+}
+
+impl Display for ReplayDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "requests={} rejected={} trades={} book={:016x} trade_stream={:016x}",
+            self.requests, self.rejected, self.trades, self.book, self.trade_stream
+        )
+    }
+}
+
+// one JSON or terse order request per line, blank lines are skipped (the binary format is not supported yet): the
+// terse ones are for account 1 on the default pair unless they say otherwise, and must carry their order id
+pub fn read_requests(
+    path: impl AsRef<Path>,
+) -> Result<impl Iterator<Item = Result<OrderRequest, ReplayError>>, ReplayError> {
+    let reader = BufReader::new(File::open(path)?);
+    let pair = DEFAULT_SYMBOL;
+    let requests = reader
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(move |line| {
+            let line = line?;
+            if line.trim_start().starts_with('{') {
+                Ok(serde_json::from_str(&line)?)
+            } else {
+                let defaults = TerseDefaults {
+                    account_id: "1",
+                    pair: &pair,
+                };
+                Ok(terse::parse(&line, defaults, || None)?)
+            }
+        });
+    Ok(requests)
+}
+
+// rejected requests are part of the run, they are counted rather than stopping it
+pub fn replay(engine: &mut Engine, order_requests: impl IntoIterator<Item = OrderRequest>) -> ReplayDigest {
+    let trades_from = engine.orderbook().trade_count();
+    let mut digest = ReplayDigest::default();
+    for order_request in order_requests {
+        digest.requests += 1;
+        if engine.process(order_request).is_err() {
+            digest.rejected += 1;
+        }
+    }
+
+    let mut book = Fnv::default();
+    for side in [OrderSide::Bid, OrderSide::Ask] {
+        for order in engine.orderbook().iter_side(side) {
+            book.write(format_args!(
+                "{}|{side}|{:?}|{}",
+                u64::from(order.id()),
+                order.limit_price(),
+                order.remaining()
+            ));
+        }
+    }
+    let mut trade_stream = Fnv::default();
+    for trade in engine.orderbook().trades_from(trades_from) {
+        digest.trades += 1;
+        trade_stream.write(format_args!(
+            "{}|{}|{}|{}|{}",
+            u64::from(trade.taker()),
+            u64::from(trade.maker()),
+            trade.side(),
+            trade.price(),
+            trade.quantity()
+        ));
+    }
+    digest.book = book.0;
+    digest.trade_stream = trade_stream.0;
+    digest
+}
+
+pub fn replay_file(engine: &mut Engine, path: impl AsRef<Path>) -> Result<ReplayDigest, ReplayError> {
+    let order_requests = read_requests(path)?.collect::<Result<Vec<_>, _>>()?;
+    Ok(replay(engine, order_requests))
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("replay io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("replay format error: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("replay terse format error: {0}")]
+    Terse(#[from] TerseError),
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    #[fixture]
+    fn fixture_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/replay.jsonl")
+    }
+
+    #[rstest]
+    fn replay_recorded_requests(fixture_path: PathBuf) {
+        let digest = replay_file(&mut Engine::new(DEFAULT_SYMBOL), &fixture_path).unwrap();
+        // recorded once, a change in the matching core that changes the outcome breaks it
+        assert_eq!(
+            digest.to_string(),
+            "requests=8 rejected=1 trades=3 book=654d1d811f9b45f3 trade_stream=b3802c36f7287f1e"
+        );
+        assert_eq!(
+            replay_file(&mut Engine::new(DEFAULT_SYMBOL), &fixture_path).unwrap(),
+            digest
+        );
+
+        // any change to the outcome shows in the digest
+        let mut order_requests: Vec<OrderRequest> =
+            read_requests(&fixture_path).unwrap().collect::<Result<_, _>>().unwrap();
+        order_requests.pop();
+        let shorter = replay(&mut Engine::new(DEFAULT_SYMBOL), order_requests);
+        assert_ne!(shorter.book, digest.book);
+    }
+
+    #[rstest]
+    fn terse_requests_replay_the_same(fixture_path: PathBuf) {
+        let terse_path = fixture_path.with_extension("terse");
+        let json: Vec<OrderRequest> = read_requests(&fixture_path).unwrap().collect::<Result<_, _>>().unwrap();
+        let terse: Vec<OrderRequest> = read_requests(&terse_path).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(terse, json);
+    }
+}
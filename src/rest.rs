@@ -0,0 +1,463 @@
+use std::{collections::VecDeque, net::SocketAddr};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use compact_str::{format_compact, CompactString};
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    engine::Engine,
+    gateway::{Gateway, GatewayError},
+    market_data::{BookEvent, Granularity, MarketDataEvent},
+    order::{id::IdGenerator, OrderId, OrderPrice, OrderQuantity, OrderRequest, OrderSide, TimeInForce},
+    orderbook::{Depth, DepthLevel},
+    symbol::Symbol,
+    trade::TradeId,
+};
+
+// the last trades of every pair kept for GET /trades
+const TRADES_CAPACITY: usize = 1_000;
+// client order ids remembered for the retries of their order, the oldest ones are forgotten first
+const IDEMPOTENCY_CAPACITY: usize = 100_000;
+const DEFAULT_TRADES_LIMIT: usize = 100;
+
+// the body of POST /orders, the order id is generated when none is given
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NewOrder {
+    pub account_id: CompactString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<u64>,
+    pub pair: Symbol,
+    pub side: OrderSide,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<OrderPrice>, // for market orders use None
+    pub quantity: OrderQuantity,
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub time_in_force: Option<TimeInForce>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<CompactString>, // makes the request idempotent for the account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_protection: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    pub reduce_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OrderAck {
+    pub order_id: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub pair: CompactString,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TradeReport {
+    pub pair: CompactString,
+    pub trade_id: TradeId,
+    pub maker: OrderId,
+    pub taker: OrderId,
+    pub side: OrderSide, // of the taker
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DepthQuery {
+    pub depth: Option<usize>, // levels per side, all of them by default
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CancelQuery {
+    #[serde(default)]
+    pub account_id: CompactString,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TradesQuery {
+    pub pair: Option<CompactString>,
+    pub limit: Option<usize>,
+}
+
+enum Submitted {
+    Created(u64),
+    Replayed(u64), // the retry of an order already accepted
+}
+
+// what the handlers ask of the engines
+enum Command {
+    Submit {
+        order: NewOrder,
+        reply: oneshot::Sender<Result<Submitted, RestError>>,
+    },
+    Cancel {
+        account_id: CompactString,
+        order_id: u64,
+        reply: oneshot::Sender<Result<(), RestError>>,
+    },
+    Snapshot {
+        pair: CompactString,
+        levels: usize,
+        reply: oneshot::Sender<Option<Depth>>,
+    },
+    Trades {
+        query: TradesQuery,
+        reply: oneshot::Sender<Vec<TradeReport>>,
+    },
+}
+
+type Commands = mpsc::UnboundedSender<Command>;
+
+// plain HTTP/JSON order entry and book queries, the engines run in a thread of their own like behind the WebSocket
+// server: POST /orders, DELETE /orders/{order_id}, GET /orderbook/{pair}?depth=N (the pair url-encoded, e.g.
+// ETH%2FUSDT) and GET /trades?pair=P&limit=N
+pub struct RestServer {
+    listener: TcpListener,
+    commands: Commands,
+}
+
+impl RestServer {
+    pub async fn bind(addr: impl ToSocketAddrs, pairs: &[Symbol]) -> Result<Self, RestError> {
+        let listener = TcpListener::bind(addr).await?;
+        let (commands, mut rx) = mpsc::unbounded_channel();
+
+        let mut entry = OrderEntry::default();
+        Gateway::new(pairs.iter().cloned().map(Engine::new), Granularity::Level).spawn(
+            move || rx.blocking_recv(),
+            move |gateway, command| entry.handle(gateway, command),
+        );
+
+        Ok(Self { listener, commands })
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Result<SocketAddr, RestError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    fn router(commands: Commands) -> Router {
+        Router::new()
+            .route("/orders", post(submit_order))
+            .route("/orders/{order_id}", delete(cancel_order))
+            .route("/orderbook/{pair}", get(orderbook))
+            .route("/trades", get(trades))
+            .with_state(commands)
+    }
+
+    pub async fn run(self) -> Result<(), RestError> {
+        axum::serve(self.listener, Self::router(self.commands)).await?;
+        Ok(())
+    }
+}
+
+// the state of the engine thread besides the engines
+#[derive(Default)]
+struct OrderEntry {
+    ids: IdGenerator,
+    accepted: IndexMap<(CompactString, CompactString), u64>, // by account and client order id
+    accepted_order: VecDeque<(CompactString, CompactString)>,
+    trades: VecDeque<TradeReport>,
+}
+
+impl OrderEntry {
+    fn handle(&mut self, gateway: &mut Gateway, command: Command) {
+        match command {
+            Command::Submit { order, reply } => {
+                let _ = reply.send(self.submit(gateway, order));
+            }
+            Command::Cancel {
+                account_id,
+                order_id,
+                reply,
+            } => {
+                let result = gateway.process(OrderRequest::Cancel { account_id, order_id }, |pair, event| {
+                    self.record_trade(pair, event)
+                });
+                let _ = reply.send(result.map_err(RestError::from));
+            }
+            Command::Snapshot { pair, levels, reply } => {
+                let depth = gateway
+                    .engine(pair.as_str())
+                    .map(|engine| engine.orderbook().depth(levels));
+                let _ = reply.send(depth);
+            }
+            Command::Trades { query, reply } => {
+                let _ = reply.send(self.trades(&query));
+            }
+        }
+    }
+
+    // a rejected order leaves nothing behind, its retry is taken as a new request
+    fn submit(&mut self, gateway: &mut Gateway, order: NewOrder) -> Result<Submitted, RestError> {
+        let key = order
+            .client_order_id
+            .clone()
+            .map(|client_order_id| (order.account_id.clone(), client_order_id));
+        if let Some(order_id) = key.as_ref().and_then(|key| self.accepted.get(key)) {
+            return Ok(Submitted::Replayed(*order_id));
+        }
+        let order_id = match order.order_id {
+            Some(order_id) => order_id,
+            None => self
+                .ids
+                .next_id()
+                .map_err(|error| RestError::Rejected(format_compact!("{error}")))?
+                .into(),
+        };
+        let create = OrderRequest::Create {
+            account_id: order.account_id,
+            order_id,
+            pair: order.pair,
+            side: order.side,
+            limit_price: order.limit_price,
+            quantity: order.quantity,
+            time_in_force: order.time_in_force,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: order.client_order_id,
+            price_protection: order.price_protection,
+            reduce_only: order.reduce_only,
+        };
+        gateway.process(create, |pair, event| self.record_trade(pair, event))?;
+
+        if let Some(key) = key {
+            self.accepted.insert(key.clone(), order_id);
+            self.accepted_order.push_back(key);
+            if self.accepted_order.len() > IDEMPOTENCY_CAPACITY {
+                if let Some(oldest) = self.accepted_order.pop_front() {
+                    self.accepted.swap_remove(&oldest);
+                }
+            }
+        }
+        Ok(Submitted::Created(order_id))
+    }
+
+    fn record_trade(&mut self, pair: &Symbol, event: MarketDataEvent) {
+        if let BookEvent::Trade {
+            trade_id,
+            maker,
+            taker,
+            side,
+            price,
+            quantity,
+        } = event.event
+        {
+            self.trades.push_back(TradeReport {
+                pair: pair.as_str().into(),
+                trade_id,
+                maker,
+                taker,
+                side,
+                price,
+                quantity,
+            });
+            if self.trades.len() > TRADES_CAPACITY {
+                self.trades.pop_front();
+            }
+        }
+    }
+
+    // the most recent ones, oldest first
+    fn trades(&self, query: &TradesQuery) -> Vec<TradeReport> {
+        let mut trades: Vec<TradeReport> = self
+            .trades
+            .iter()
+            .rev()
+            .filter(|trade| query.pair.as_ref().is_none_or(|pair| trade.pair == *pair))
+            .take(query.limit.unwrap_or(DEFAULT_TRADES_LIMIT))
+            .cloned()
+            .collect();
+        trades.reverse();
+        trades
+    }
+}
+
+async fn ask<T>(commands: &Commands, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T, RestError> {
+    let (reply, replied) = oneshot::channel();
+    commands.send(command(reply)).map_err(|_| RestError::EngineStopped)?;
+    replied.await.map_err(|_| RestError::EngineStopped)
+}
+
+// 201 for a new order, 200 with the same body for the retry of one already accepted
+async fn submit_order(
+    State(commands): State<Commands>,
+    Json(order): Json<NewOrder>,
+) -> Result<(StatusCode, Json<OrderAck>), RestError> {
+    match ask(&commands, |reply| Command::Submit { order, reply }).await?? {
+        Submitted::Created(order_id) => Ok((StatusCode::CREATED, Json(OrderAck { order_id }))),
+        Submitted::Replayed(order_id) => Ok((StatusCode::OK, Json(OrderAck { order_id }))),
+    }
+}
+
+async fn cancel_order(
+    State(commands): State<Commands>,
+    Path(order_id): Path<u64>,
+    Query(query): Query<CancelQuery>,
+) -> Result<Json<OrderAck>, RestError> {
+    ask(&commands, |reply| Command::Cancel {
+        account_id: query.account_id,
+        order_id,
+        reply,
+    })
+    .await??;
+    Ok(Json(OrderAck { order_id }))
+}
+
+async fn orderbook(
+    State(commands): State<Commands>,
+    Path(pair): Path<CompactString>,
+    Query(query): Query<DepthQuery>,
+) -> Result<Json<BookSnapshot>, RestError> {
+    let depth = ask(&commands, |reply| Command::Snapshot {
+        pair: pair.clone(),
+        levels: query.depth.unwrap_or(usize::MAX),
+        reply,
+    })
+    .await?
+    .ok_or_else(|| RestError::UnknownPair(pair.clone()))?;
+    Ok(Json(BookSnapshot {
+        pair,
+        bids: depth.bids,
+        asks: depth.asks,
+    }))
+}
+
+async fn trades(
+    State(commands): State<Commands>,
+    Query(query): Query<TradesQuery>,
+) -> Result<Json<Vec<TradeReport>>, RestError> {
+    Ok(Json(ask(&commands, |reply| Command::Trades { query, reply }).await?))
+}
+
+#[derive(Debug, Error)]
+pub enum RestError {
+    #[error("server io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unknown pair! {0}")]
+    UnknownPair(CompactString),
+    #[error("order not found! {0}")]
+    OrderNotFound(u64),
+    #[error("order rejected! {0}")]
+    Rejected(CompactString),
+    #[error("engine stopped!")]
+    EngineStopped,
+}
+
+impl From<GatewayError> for RestError {
+    fn from(error: GatewayError) -> Self {
+        match error {
+            GatewayError::UnknownPair(pair) => RestError::UnknownPair(pair),
+            GatewayError::OrderNotFound(order_id) => RestError::OrderNotFound(order_id),
+            error => RestError::Rejected(format_compact!("{error}")),
+        }
+    }
+}
+
+impl IntoResponse for RestError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            RestError::UnknownPair(_) | RestError::OrderNotFound(_) => StatusCode::NOT_FOUND,
+            RestError::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            RestError::Io(_) | RestError::EngineStopped => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, Json(serde_json::json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::*;
+    use crate::order::util::{DEFAULT_PAIR, DEFAULT_SYMBOL};
+
+    // one request per connection, the server closes it after the response
+    async fn request(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> (u16, serde_json::Value) {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: merx\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap_or_default())
+    }
+
+    fn order(side: &str, client_order_id: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "account_id": "1",
+            "pair": DEFAULT_PAIR,
+            "side": side,
+            "limit_price": "15",
+            "quantity": "10",
+            "client_order_id": client_order_id,
+        })
+    }
+
+    #[tokio::test]
+    async fn orders_book_and_trades() {
+        let server = RestServer::bind("127.0.0.1:0", &[DEFAULT_SYMBOL]).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        // the retry of an accepted order gets the same id back, without a second order
+        let (status, created) = request(addr, "POST", "/orders", Some(order("ASK", Some("a")))).await;
+        assert_eq!(status, 201);
+        let (status, replayed) = request(addr, "POST", "/orders", Some(order("ASK", Some("a")))).await;
+        assert_eq!((status, &replayed), (200, &created));
+        let (status, resting) = request(addr, "POST", "/orders", Some(order("ASK", None))).await;
+        assert_eq!(status, 201);
+
+        let (status, book) = request(addr, "GET", "/orderbook/ETH%2FUSDT?depth=1", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(book["asks"], serde_json::json!([["15", "20", 2]]));
+        assert_eq!(request(addr, "GET", "/orderbook/XXX%2FYYY", None).await.0, 404);
+
+        let (status, _) = request(addr, "POST", "/orders", Some(order("BID", None))).await;
+        assert_eq!(status, 201);
+        let (_, trades) = request(addr, "GET", "/trades?pair=ETH%2FUSDT&limit=10", None).await;
+        assert_eq!(trades.as_array().unwrap().len(), 1);
+        assert_eq!(trades[0]["maker"], created["order_id"]);
+
+        // a post-only order crossing the ask left is rejected by the engine
+        let mut post_only = order("BID", None);
+        post_only["time_in_force"] = "GTC".into();
+        post_only["post_only"] = true.into();
+        let (status, body) = request(addr, "POST", "/orders", Some(post_only)).await;
+        assert_eq!(status, 422);
+        assert!(body["error"].as_str().unwrap().starts_with("order rejected!"));
+
+        for (order_id, expected) in [(&resting, 200), (&created, 404)] {
+            let path = format!("/orders/{}?account_id=1", order_id["order_id"]);
+            assert_eq!(request(addr, "DELETE", &path, None).await.0, expected);
+        }
+    }
+}
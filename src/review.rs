@@ -0,0 +1,276 @@
+use std::fmt::Display;
+
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::Timestamp,
+    order::OrderPrice,
+    trade::{Trade, TradeId},
+};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ReviewDecision {
+    Confirmed,
+    Busted,
+    Expired, // nobody decided within the window, the trade stands
+}
+
+// from this reference price on, trades further than the threshold (relative, e.g. 0.1) are clearly erroneous
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Guideline {
+    pub min_price: OrderPrice,
+    pub threshold: Decimal,
+}
+
+// numerical guidelines of an instrument, the tier of the reference price applies
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Guidelines(Vec<Guideline>);
+
+impl Guidelines {
+    pub fn with_tier(mut self, min_price: OrderPrice, threshold: Decimal) -> Self {
+        self.0.push(Guideline { min_price, threshold });
+        self.0.sort_by_key(|guideline| guideline.min_price);
+        self
+    }
+
+    pub fn evaluate(&self, price: OrderPrice, reference_price: OrderPrice) -> Option<Evaluation> {
+        if reference_price <= OrderPrice::ZERO {
+            return None;
+        }
+
+        let guideline = *self
+            .0
+            .iter()
+            .rev()
+            .find(|guideline| reference_price >= guideline.min_price)?;
+        Some(Evaluation {
+            price,
+            reference_price,
+            deviation: (price - reference_price).abs() / reference_price,
+            guideline,
+        })
+    }
+}
+
+// report of the rules about a trade, attached to its flag
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Evaluation {
+    pub price: OrderPrice,
+    pub reference_price: OrderPrice,
+    pub deviation: Decimal, // relative to the reference price
+    pub guideline: Guideline,
+}
+
+impl Evaluation {
+    #[inline]
+    pub fn is_erroneous(&self) -> bool {
+        self.deviation > self.guideline.threshold
+    }
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} deviates {} from {} (threshold={} from {})",
+            self.price,
+            self.deviation.round_dp(4),
+            self.reference_price,
+            self.guideline.threshold,
+            self.guideline.min_price
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlaggedTrade {
+    pub trade: Trade,
+    pub buyer: CompactString,
+    pub seller: CompactString,
+    pub flagged_at: Timestamp,
+    pub evaluation: Evaluation,
+}
+
+// flagged trades wait here for an admin to confirm or bust them, until the busting window closes
+#[derive(Clone, Debug)]
+pub struct ReviewQueue {
+    window: Timestamp,
+    guidelines: Guidelines,
+    pending: IndexMap<TradeId, FlaggedTrade>,
+    decisions: Vec<(TradeId, ReviewDecision)>,
+}
+
+impl ReviewQueue {
+    #[inline]
+    pub fn new(window: Timestamp) -> Self {
+        Self {
+            window,
+            guidelines: Guidelines::default(),
+            pending: IndexMap::new(),
+            decisions: vec![],
+        }
+    }
+
+    // a single band (relative to the reference price, e.g. 0.1) whatever the price
+    pub fn with_band(self, band: Decimal) -> Self {
+        self.with_guidelines(Guidelines::default().with_tier(OrderPrice::ZERO, band))
+    }
+
+    pub fn with_guidelines(mut self, guidelines: Guidelines) -> Self {
+        self.guidelines = guidelines;
+        self
+    }
+
+    // the evaluation of a trade to flag, if any
+    pub fn check(&self, price: OrderPrice, reference_price: Option<OrderPrice>) -> Option<Evaluation> {
+        self.guidelines
+            .evaluate(price, reference_price?)
+            .filter(Evaluation::is_erroneous)
+    }
+
+    #[inline]
+    pub fn flag(&mut self, flagged: FlaggedTrade) {
+        self.pending.insert(flagged.trade.id(), flagged);
+    }
+
+    #[inline]
+    pub fn pending(&self) -> impl Iterator<Item = &FlaggedTrade> {
+        self.pending.values()
+    }
+
+    #[inline]
+    pub fn decisions(&self) -> &[(TradeId, ReviewDecision)] {
+        &self.decisions
+    }
+
+    // confirms or busts a pending trade while the window is open, returns it so that a bust can be undone
+    pub fn decide(
+        &mut self,
+        trade_id: TradeId,
+        decision: ReviewDecision,
+        now: Timestamp,
+    ) -> Result<FlaggedTrade, ReviewError> {
+        let flagged = self
+            .pending
+            .get(&trade_id)
+            .ok_or(ReviewError::NotUnderReview(trade_id))?;
+        if flagged.flagged_at + self.window <= now {
+            self.expire(now);
+            return Err(ReviewError::WindowClosed(trade_id));
+        }
+
+        let flagged = self.pending.shift_remove(&trade_id).unwrap();
+        self.decisions.push((trade_id, decision));
+        Ok(flagged)
+    }
+
+    // the trades whose window closed stand, returns them
+    pub fn expire(&mut self, now: Timestamp) -> Vec<FlaggedTrade> {
+        let mut expired = vec![];
+        while let Some((_, flagged)) = self.pending.first() {
+            if flagged.flagged_at + self.window > now {
+                break;
+            }
+            let (trade_id, flagged) = self.pending.shift_remove_index(0).unwrap();
+            self.decisions.push((trade_id, ReviewDecision::Expired));
+            expired.push(flagged);
+        }
+        expired
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ReviewError {
+    #[error("trade not under review! {0:?}")]
+    NotUnderReview(TradeId),
+    #[error("busting window closed! {0:?}")]
+    WindowClosed(TradeId),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::{
+        clock::{MINUTE, SECOND},
+        order::{Order, OrderSide},
+    };
+
+    fn flagged(order_id: u64, flagged_at: Timestamp) -> FlaggedTrade {
+        let mut taker = Order::limit_order((order_id + 1).into(), OrderSide::Bid, 10.into(), 120.into());
+        let mut maker = Order::limit_order(order_id.into(), OrderSide::Ask, 10.into(), 120.into());
+        FlaggedTrade {
+            trade: Trade::new(&mut taker, &mut maker, 10.into()).unwrap(),
+            buyer: "1".into(),
+            seller: "2".into(),
+            flagged_at,
+            evaluation: Guidelines::default()
+                .with_tier(0.into(), Decimal::new(1, 1))
+                .evaluate(120.into(), 100.into())
+                .unwrap(),
+        }
+    }
+
+    // 10% around the reference price, a minute to decide
+    #[fixture]
+    fn queue() -> ReviewQueue {
+        ReviewQueue::new(MINUTE).with_band(Decimal::new(1, 1))
+    }
+
+    #[rstest]
+    fn confirm_or_bust_within_the_window(mut queue: ReviewQueue) {
+        assert!(queue.check(120.into(), Some(100.into())).is_some());
+        assert!(queue.check(105.into(), Some(100.into())).is_none());
+        assert!(queue.check(120.into(), None).is_none());
+
+        let first = flagged(1, 0);
+        let second = flagged(3, 30 * SECOND);
+        let (first_id, second_id) = (first.trade.id(), second.trade.id());
+        queue.flag(first);
+        queue.flag(second);
+
+        assert!(queue.decide(second_id, ReviewDecision::Busted, MINUTE).is_ok());
+        assert_eq!(
+            queue.decide(first_id, ReviewDecision::Confirmed, MINUTE).unwrap_err(),
+            ReviewError::WindowClosed(first_id)
+        );
+        assert_eq!(
+            queue.decisions(),
+            &[(second_id, ReviewDecision::Busted), (first_id, ReviewDecision::Expired)]
+        );
+        assert_eq!(queue.pending().count(), 0);
+        assert_eq!(
+            queue.decide(first_id, ReviewDecision::Busted, MINUTE).unwrap_err(),
+            ReviewError::NotUnderReview(first_id)
+        );
+    }
+
+    #[rstest]
+    fn evaluate_against_the_guidelines() {
+        // 10% up to 25, 5% up to 50, 3% above
+        let guidelines = Guidelines::default()
+            .with_tier(50.into(), Decimal::new(3, 2))
+            .with_tier(0.into(), Decimal::new(1, 1))
+            .with_tier(25.into(), Decimal::new(5, 2));
+        let evaluation = guidelines.evaluate(42.into(), 40.into()).unwrap();
+        assert_eq!(evaluation.deviation, Decimal::new(5, 2));
+        assert!(!evaluation.is_erroneous());
+
+        let queue = ReviewQueue::new(MINUTE).with_guidelines(guidelines);
+        assert!(queue.check(21.into(), Some(20.into())).is_none());
+        assert!(queue.check(43.into(), Some(40.into())).is_some());
+
+        let evaluation = queue.check(96.into(), Some(100.into())).unwrap();
+        assert_eq!(evaluation.guideline.min_price, OrderPrice::from(50));
+        assert_eq!(
+            evaluation.to_string(),
+            "96 deviates 0.04 from 100 (threshold=0.03 from 50)"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::order::{OrderPrice, OrderQuantity};
+
+// pre-trade limits of an instrument, no limit unless set
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RiskLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_quantity: Option<OrderQuantity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_notional: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    price_collar: Option<Decimal>, // relative to the reference price, e.g. 0.1
+}
+
+impl RiskLimits {
+    pub fn with_max_quantity(mut self, max_quantity: OrderQuantity) -> Self {
+        self.max_quantity = Some(max_quantity);
+        self
+    }
+
+    pub fn with_max_notional(mut self, max_notional: Decimal) -> Self {
+        self.max_notional = Some(max_notional);
+        self
+    }
+
+    pub fn with_price_collar(mut self, price_collar: Decimal) -> Self {
+        self.price_collar = Some(price_collar);
+        self
+    }
+
+    // the reference price is the last trade (or the best opposite price), market orders are valued at it and no
+    // price is checked without one
+    pub fn check(
+        &self,
+        limit_price: Option<OrderPrice>,
+        quantity: OrderQuantity,
+        reference_price: Option<OrderPrice>,
+    ) -> Result<(), RiskError> {
+        if let Some(max_quantity) = self.max_quantity.filter(|max_quantity| quantity > *max_quantity) {
+            return Err(RiskError::MaxQuantityExceeded { quantity, max_quantity });
+        }
+
+        if let Some((max_notional, price)) = self.max_notional.zip(limit_price.or(reference_price)) {
+            let notional = quantity * price;
+            if notional > max_notional {
+                return Err(RiskError::MaxNotionalExceeded { notional, max_notional });
+            }
+        }
+
+        match limit_price {
+            Some(limit_price) => self.check_price(limit_price, reference_price),
+            None => Ok(()),
+        }
+    }
+
+    // off-book trades are only checked against the price collar
+    pub fn check_price(&self, price: OrderPrice, reference_price: Option<OrderPrice>) -> Result<(), RiskError> {
+        if let Some((price_collar, reference_price)) = self.price_collar.zip(reference_price) {
+            let band = reference_price * price_collar;
+            if (price - reference_price).abs() > band.value() {
+                return Err(RiskError::OutsidePriceCollar {
+                    limit_price: price,
+                    low: reference_price.checked_sub(band).unwrap_or_default(),
+                    high: reference_price + band,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RiskError {
+    #[error("max order quantity exceeded (quantity={}, max_quantity={})", .quantity, .max_quantity)]
+    MaxQuantityExceeded {
+        quantity: OrderQuantity,
+        max_quantity: OrderQuantity,
+    },
+    #[error("max order notional exceeded (notional={}, max_notional={})", .notional, .max_notional)]
+    MaxNotionalExceeded { notional: Decimal, max_notional: Decimal },
+    #[error("limit price outside the price collar (limit_price={}, low={}, high={})", .limit_price, .low, .high)]
+    OutsidePriceCollar {
+        limit_price: OrderPrice,
+        low: OrderPrice,
+        high: OrderPrice,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+
+    // 100 at most, 10k notional at most, 10% around the reference price
+    #[fixture]
+    fn limits() -> RiskLimits {
+        RiskLimits::default()
+            .with_max_quantity(100.into())
+            .with_max_notional(10_000.into())
+            .with_price_collar(Decimal::new(1, 1))
+    }
+
+    #[rstest]
+    fn check_limits(limits: RiskLimits) {
+        assert_eq!(limits.check(Some(105.into()), 90.into(), Some(100.into())), Ok(()));
+        assert_eq!(
+            limits.check(Some(100.into()), 101.into(), None),
+            Err(RiskError::MaxQuantityExceeded {
+                quantity: 101.into(),
+                max_quantity: 100.into()
+            })
+        );
+        assert_eq!(
+            limits.check(None, 90.into(), Some(120.into())),
+            Err(RiskError::MaxNotionalExceeded {
+                notional: 10_800.into(),
+                max_notional: 10_000.into()
+            })
+        );
+        assert_eq!(
+            limits.check(Some(89.into()), 10.into(), Some(100.into())),
+            Err(RiskError::OutsidePriceCollar {
+                limit_price: 89.into(),
+                low: 90.into(),
+                high: 110.into()
+            })
+        );
+
+        // no reference price, nothing to collar
+        assert_eq!(limits.check(Some(1.into()), 10.into(), None), Ok(()));
+    }
+}
@@ -0,0 +1,376 @@
+use compact_str::{format_compact, CompactString};
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    engine::Engine,
+    order::{
+        id::{IdError, IdGenerator},
+        OrderId, OrderPrice, OrderQuantity, OrderRequest, OrderSide, TimeInForce,
+    },
+};
+
+pub const DEFAULT_MAX_ROUNDS: usize = 3;
+
+const IOC: TimeInForce = TimeInForce::ImmediateOrCancel { fill_or_kill: false };
+
+// what the router is asked to get done, across every venue
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ParentOrder {
+    pub account_id: CompactString,
+    pub side: OrderSide,
+    pub quantity: OrderQuantity,
+    pub limit_price: Option<OrderPrice>, // none for a market order
+}
+
+// one order sent to a venue on behalf of the parent
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChildOrder {
+    pub venue: CompactString,
+    pub order_id: u64,
+    pub round: usize,
+    pub quantity: OrderQuantity,
+    pub limit_price: OrderPrice,
+    pub filled: OrderQuantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<CompactString>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoutingReport {
+    pub filled: OrderQuantity,
+    pub notional: Decimal,
+    pub remaining: OrderQuantity,
+    pub children: Vec<ChildOrder>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resting: Option<ChildOrder>, // the remainder left on the venue of the first priority
+}
+
+impl RoutingReport {
+    #[inline]
+    pub fn average_price(&self) -> Option<OrderPrice> {
+        (!self.filled.is_zero()).then(|| OrderPrice::from_decimal(self.notional / self.filled))
+    }
+}
+
+// splits a parent order across the engines of several venues (or pairs standing for them) by the liquidity they
+// display: the best prices first, the venue of the lower priority number on a tie; every round sends one immediate or
+// cancel child per venue, a venue that fills less than it displayed is left out of the next rounds
+pub struct SmartOrderRouter {
+    priorities: IndexMap<CompactString, u32>, // by venue
+    ids: IdGenerator,
+    max_rounds: usize,
+    rest_remainder: bool,
+}
+
+impl Default for SmartOrderRouter {
+    fn default() -> Self {
+        Self {
+            priorities: IndexMap::new(),
+            ids: IdGenerator::default(),
+            max_rounds: DEFAULT_MAX_ROUNDS,
+            rest_remainder: false,
+        }
+    }
+}
+
+impl SmartOrderRouter {
+    pub fn with_id_generator(mut self, ids: IdGenerator) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    // a limit parent not filled by the end of the rounds rests its remainder on the venue of the first priority
+    pub fn with_rest_remainder(mut self, rest_remainder: bool) -> Self {
+        self.rest_remainder = rest_remainder;
+        self
+    }
+
+    pub fn add_venue(&mut self, venue: &str, priority: u32) -> Result<(), RoutingError> {
+        if self.priorities.contains_key(venue) {
+            return Err(RoutingError::VenueDuplicated(venue.into()));
+        }
+        self.priorities.insert(venue.into(), priority);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn remove_venue(&mut self, venue: &str) -> Option<u32> {
+        self.priorities.shift_remove(venue)
+    }
+
+    pub fn route(
+        &mut self,
+        parent: &ParentOrder,
+        engines: &mut IndexMap<CompactString, Engine>,
+    ) -> Result<RoutingReport, RoutingError> {
+        if let Some(venue) = self.priorities.keys().find(|venue| !engines.contains_key(*venue)) {
+            return Err(RoutingError::VenueNotFound(venue.clone()));
+        }
+
+        let mut report = RoutingReport {
+            remaining: parent.quantity,
+            ..RoutingReport::default()
+        };
+        let mut excluded: Vec<CompactString> = vec![];
+        for round in 0..self.max_rounds {
+            let allocations = self.allocate(parent, report.remaining, engines, &excluded);
+            if allocations.is_empty() {
+                break;
+            }
+            for (venue, quantity, limit_price) in allocations {
+                let child = self.send(parent, round, &venue, (quantity, limit_price), Some(IOC), engines)?;
+                let fills = engines[&venue].executions(child.order_id.into());
+                report.notional += fills.iter().map(|trade| trade.notional()).sum::<Decimal>();
+                report.filled += child.filled;
+                report.remaining -= child.filled;
+                if child.filled < child.quantity {
+                    excluded.push(venue);
+                }
+                report.children.push(child);
+            }
+            if report.remaining.is_zero() {
+                break;
+            }
+        }
+
+        if let (true, false, Some(limit_price)) = (self.rest_remainder, report.remaining.is_zero(), parent.limit_price)
+        {
+            if let Some(venue) = self.venues().into_iter().next() {
+                let child = self.send(
+                    parent,
+                    self.max_rounds,
+                    &venue,
+                    (report.remaining, limit_price),
+                    None,
+                    engines,
+                )?;
+                report.resting = Some(child);
+            }
+        }
+        Ok(report)
+    }
+
+    // venues by priority, the first added first on a tie
+    fn venues(&self) -> Vec<CompactString> {
+        let mut venues: Vec<(&CompactString, &u32)> = self.priorities.iter().collect();
+        venues.sort_by_key(|(_, priority)| **priority);
+        venues.into_iter().map(|(venue, _)| venue.clone()).collect()
+    }
+
+    // quantity and worst price to take at each venue, in the order they were allocated
+    fn allocate(
+        &self,
+        parent: &ParentOrder,
+        quantity: OrderQuantity,
+        engines: &IndexMap<CompactString, Engine>,
+        excluded: &[CompactString],
+    ) -> Vec<(CompactString, OrderQuantity, OrderPrice)> {
+        let acceptable = |price: OrderPrice| match (parent.side, parent.limit_price) {
+            (_, None) => true,
+            (OrderSide::Bid, Some(limit_price)) => price <= limit_price,
+            (OrderSide::Ask, Some(limit_price)) => price >= limit_price,
+        };
+        let mut levels = vec![];
+        for (rank, venue) in self.venues().into_iter().enumerate() {
+            if excluded.contains(&venue) {
+                continue;
+            }
+            let depth = engines[&venue].orderbook().depth(usize::MAX);
+            let opposite = match parent.side {
+                OrderSide::Bid => depth.asks,
+                OrderSide::Ask => depth.bids,
+            };
+            levels.extend(
+                opposite
+                    .into_iter()
+                    .take_while(|(price, _, _)| acceptable(*price))
+                    .map(|(price, displayed, _)| (price, rank, venue.clone(), displayed)),
+            );
+        }
+        levels.sort_by(|(price, rank, ..), (other_price, other_rank, ..)| {
+            let by_price = match parent.side {
+                OrderSide::Bid => price.cmp(other_price),
+                OrderSide::Ask => other_price.cmp(price),
+            };
+            by_price.then(rank.cmp(other_rank))
+        });
+
+        let mut remaining = quantity;
+        let mut allocations: IndexMap<CompactString, (OrderQuantity, OrderPrice)> = IndexMap::new();
+        for (price, _, venue, displayed) in levels {
+            if remaining.is_zero() {
+                break;
+            }
+            let taken = displayed.min(remaining);
+            remaining -= taken;
+            let allocation = allocations.entry(venue).or_insert((OrderQuantity::ZERO, price));
+            allocation.0 += taken;
+            allocation.1 = price;
+        }
+        allocations
+            .into_iter()
+            .map(|(venue, (quantity, price))| (venue, quantity, price))
+            .collect()
+    }
+
+    // a rejected child is reported as such, the other venues go on
+    fn send(
+        &mut self,
+        parent: &ParentOrder,
+        round: usize,
+        venue: &CompactString,
+        (quantity, limit_price): (OrderQuantity, OrderPrice),
+        time_in_force: Option<TimeInForce>,
+        engines: &mut IndexMap<CompactString, Engine>,
+    ) -> Result<ChildOrder, RoutingError> {
+        let order_id = u64::from(self.ids.next_id()?);
+        let engine = &mut engines[venue];
+        let result = engine.process(OrderRequest::Create {
+            account_id: parent.account_id.clone(),
+            order_id,
+            pair: engine.pair().clone(),
+            side: parent.side,
+            limit_price: Some(limit_price),
+            quantity,
+            time_in_force,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: None,
+            price_protection: None,
+            reduce_only: false,
+        });
+        let filled = engine
+            .executions(OrderId::new(order_id))
+            .iter()
+            .map(|trade| trade.quantity())
+            .sum();
+        Ok(ChildOrder {
+            venue: venue.clone(),
+            order_id,
+            round,
+            quantity,
+            limit_price,
+            filled,
+            rejected: result.err().map(|error| format_compact!("{error}")),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RoutingError {
+    #[error("routing venue already exists! {0}")]
+    VenueDuplicated(CompactString),
+    #[error("no engine for the routing venue! {0}")]
+    VenueNotFound(CompactString),
+    #[error("child order id error: {0}")]
+    IdError(#[from] IdError),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::order::util::{self, DEFAULT_SYMBOL};
+
+    fn ask(engine: &mut Engine, order_id: u64, price: u32, quantity: u32) {
+        engine
+            .process(
+                util::create(order_id, OrderSide::Ask)
+                    .account_id("maker")
+                    .limit_price(Some(price.into()))
+                    .quantity(quantity)
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    // the same price on east and west, west first by priority, a worse one on north
+    #[fixture]
+    fn engines() -> IndexMap<CompactString, Engine> {
+        let mut engines: IndexMap<CompactString, Engine> = ["east", "west", "north"]
+            .into_iter()
+            .map(|venue| (venue.into(), Engine::new(DEFAULT_SYMBOL)))
+            .collect();
+        ask(&mut engines["east"], 1, 10, 5);
+        ask(&mut engines["west"], 1, 10, 5);
+        ask(&mut engines["north"], 1, 11, 10);
+        engines
+    }
+
+    #[fixture]
+    fn router() -> SmartOrderRouter {
+        let mut router = SmartOrderRouter::default();
+        for (venue, priority) in [("east", 1), ("west", 0), ("north", 2)] {
+            router.add_venue(venue, priority).unwrap();
+        }
+        router
+    }
+
+    fn buy(quantity: u32, limit_price: Option<u32>) -> ParentOrder {
+        ParentOrder {
+            account_id: "taker".into(),
+            side: OrderSide::Bid,
+            quantity: quantity.into(),
+            limit_price: limit_price.map(OrderPrice::from),
+        }
+    }
+
+    #[rstest]
+    fn split_by_price_then_priority(mut router: SmartOrderRouter, mut engines: IndexMap<CompactString, Engine>) {
+        let report = router.route(&buy(12, Some(11)), &mut engines).unwrap();
+        let children: Vec<(&str, OrderQuantity, OrderQuantity)> = report
+            .children
+            .iter()
+            .map(|child| (child.venue.as_str(), child.quantity, child.filled))
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                ("west", 5.into(), 5.into()),
+                ("east", 5.into(), 5.into()),
+                ("north", 2.into(), 2.into())
+            ]
+        );
+        assert_eq!((report.filled, report.remaining), (12.into(), OrderQuantity::ZERO));
+        assert_eq!(
+            report.average_price(),
+            Some(OrderPrice::from_decimal(Decimal::from(122) / Decimal::from(12)))
+        );
+        assert_eq!(engines["north"].orderbook().best_ask(), Some(11.into()));
+        assert!(router.add_venue("east", 3).is_err());
+    }
+
+    #[rstest]
+    fn reroute_and_rest_the_remainder(router: SmartOrderRouter, mut engines: IndexMap<CompactString, Engine>) {
+        // west takes no new order: its child is rejected and what it displayed goes to the others next round
+        engines["west"].cancel_only().unwrap();
+        let mut router = router.with_rest_remainder(true);
+        let report = router.route(&buy(12, Some(10)), &mut engines).unwrap();
+
+        assert!(report.children[0].rejected.is_some());
+        assert_eq!(
+            (report.children[1].venue.as_str(), report.children[1].filled),
+            ("east", 5.into())
+        );
+        assert_eq!((report.filled, report.remaining), (5.into(), 7.into()));
+        let resting = report.resting.unwrap();
+        assert_eq!(resting.venue, "west");
+        assert!(resting.rejected.is_some());
+
+        engines["west"].resume().unwrap();
+        let report = router.route(&buy(7, Some(10)), &mut engines).unwrap();
+        assert_eq!((report.filled, report.children.len()), (5.into(), 1));
+        let resting = report.resting.unwrap();
+        assert_eq!((resting.venue.as_str(), resting.quantity), ("west", 2.into()));
+        assert!(engines["west"].get_order(resting.order_id.into()).is_some());
+    }
+}
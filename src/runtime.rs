@@ -0,0 +1,332 @@
+use std::{
+    cell::Cell,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use core_affinity::CoreId;
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use thiserror::Error;
+use tracing::{error, warn};
+
+use crate::{engine::Engine, order::OrderRequest};
+
+mod ring;
+pub mod shard;
+
+use ring::Ring;
+
+pub const DEFAULT_CAPACITY: usize = 4_096;
+
+// an idle engine thread spins, then yields, then naps this long between two looks at its rings
+const IDLE_PARK: Duration = Duration::from_micros(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    pub capacity: usize,     // of the ring of every producer, rounded up to a power of two
+    pub core: Option<usize>, // the engine thread is pinned to it
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            core: None,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SendError {
+    #[error("ingestion ring full! {0}")]
+    Full(OrderRequest), // backpressure: the engine is behind, send it again later
+    #[error("runtime shut down! {0}")]
+    Closed(OrderRequest),
+    #[error("unknown pair! {0}")]
+    UnknownPair(OrderRequest),
+}
+
+impl SendError {
+    #[inline]
+    pub fn into_inner(self) -> OrderRequest {
+        match self {
+            Self::Full(order_request) | Self::Closed(order_request) | Self::UnknownPair(order_request) => order_request,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    closing: AtomicBool,
+}
+
+type Registry = Sender<Arc<Ring<OrderRequest>>>;
+
+// the gateway end: every clone gets a ring of its own, so each ring keeps a single producer (and the engine thread is
+// its single consumer); sending never blocks nor locks, a full ring is reported back instead; it moves to another
+// thread but is never shared by two, that would make two producers of its ring
+pub struct Producer {
+    ring: Arc<Ring<OrderRequest>>,
+    shared: Arc<Shared>,
+    registry: Registry,
+    _not_sync: PhantomData<Cell<()>>, // clone it instead, see Clone
+}
+
+impl Producer {
+    fn register(capacity: usize, shared: Arc<Shared>, registry: Registry) -> Self {
+        let ring = Arc::new(Ring::new(capacity));
+        // the engine thread is gone when nobody takes it, the closing flag says so on send
+        let _ = registry.send(ring.clone());
+        Self {
+            ring,
+            shared,
+            registry,
+            _not_sync: PhantomData,
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&self, order_request: OrderRequest) -> Result<(), SendError> {
+        // announced before the closing flag is read, so that the engine thread drains it on shutdown
+        self.ring.set_writing(true);
+        let result = if self.shared.closing.load(Ordering::SeqCst) {
+            Err(SendError::Closed(order_request))
+        } else {
+            self.ring.push(order_request).map_err(SendError::Full)
+        };
+        self.ring.set_writing(false);
+        result
+    }
+
+    // waits for room in the ring (spinning, then yielding): for threads of their own, not for async tasks
+    #[allow(clippy::result_large_err)]
+    pub fn send(&self, order_request: OrderRequest) -> Result<(), SendError> {
+        let mut order_request = order_request;
+        let mut attempts = 0u32;
+        loop {
+            match self.try_send(order_request) {
+                Err(SendError::Full(back)) => order_request = back,
+                result => return result,
+            }
+            attempts += 1;
+            if attempts < 64 {
+                std::hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+    }
+
+    // requests of this producer not processed yet
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.ring.len()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+}
+
+impl Clone for Producer {
+    fn clone(&self) -> Self {
+        Self::register(self.capacity(), self.shared.clone(), self.registry.clone())
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        self.ring.disconnect();
+    }
+}
+
+// what the runtime thread runs the requests through: one engine, or a shard of them (see ShardedRuntime)
+pub trait Worker: Send + 'static {
+    fn handle(&mut self, order_request: OrderRequest);
+}
+
+impl Worker for Engine {
+    #[inline]
+    fn handle(&mut self, order_request: OrderRequest) {
+        if let Err(error) = self.process(order_request) {
+            error!("Error processing order request: {}", error);
+        }
+    }
+}
+
+// the matching core stays single threaded: it runs alone on its (pinned) thread, taking the requests of the producers
+// in turns; what it publishes is read through the subscriptions made before it is spawned
+pub struct Runtime<W: Worker = Engine> {
+    shared: Arc<Shared>,
+    thread: JoinHandle<W>,
+}
+
+impl<W: Worker> Runtime<W> {
+    pub fn spawn(worker: W, config: RuntimeConfig) -> std::io::Result<(Self, Producer)> {
+        let shared = Arc::new(Shared::default());
+        let (registry, rings) = unbounded();
+        let producer = Producer::register(config.capacity, shared.clone(), registry);
+
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("engine".into())
+                .spawn(move || run(worker, rings, &shared, config.core))?
+        };
+        Ok((Self { shared, thread }, producer))
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // no request is taken from then on, the ones already in the rings are processed before the engine is handed back
+    pub fn shutdown(self) -> W {
+        self.shared.closing.store(true, Ordering::SeqCst);
+        self.thread.thread().unpark();
+        self.join()
+    }
+
+    // once every producer is dropped and its requests processed
+    pub fn join(self) -> W {
+        self.thread.join().expect("engine thread panicked")
+    }
+}
+
+fn run<W: Worker>(
+    mut worker: W,
+    registry: Receiver<Arc<Ring<OrderRequest>>>,
+    shared: &Shared,
+    core: Option<usize>,
+) -> W {
+    if let Some(core) = core {
+        if !core_affinity::set_for_current(CoreId { id: core }) {
+            warn!("Engine thread not pinned to core {core}");
+        }
+    }
+
+    let mut rings: Vec<Arc<Ring<OrderRequest>>> = Vec::new();
+    let mut idle = 0u32;
+    while !shared.closing.load(Ordering::SeqCst) {
+        rings.extend(registry.try_iter());
+        let mut busy = false;
+        for ring in &rings {
+            if let Some(order_request) = ring.pop() {
+                worker.handle(order_request);
+                busy = true;
+            }
+        }
+        // disconnected first: nothing was pushed after it, empty is then for good
+        rings.retain(|ring| !(ring.is_disconnected() && ring.is_empty()));
+        if rings.is_empty() {
+            match registry.try_recv() {
+                Ok(ring) => rings.push(ring),
+                Err(TryRecvError::Disconnected) => {
+                    shared.closing.store(true, Ordering::SeqCst);
+                    return worker;
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        idle = if busy { 0 } else { idle.saturating_add(1) };
+        match idle {
+            0 => {}
+            1..=63 => std::hint::spin_loop(),
+            64..=1_023 => thread::yield_now(),
+            _ => thread::park_timeout(IDLE_PARK),
+        }
+    }
+
+    // drain: a producer past the closing flag check finishes its push first
+    rings.extend(registry.try_iter());
+    for ring in &rings {
+        while ring.is_writing() {
+            thread::yield_now();
+        }
+        while let Some(order_request) = ring.pop() {
+            worker.handle(order_request);
+        }
+    }
+    worker
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{
+        util::{self, DEFAULT_SYMBOL},
+        OrderSide,
+    };
+
+    fn create(order_id: u64) -> OrderRequest {
+        util::create(order_id, OrderSide::Bid)
+            .limit_price(Some(((order_id % 100) as u32 + 1).into()))
+            .build()
+    }
+
+    #[rstest]
+    fn producer_is_not_sync() {
+        // ambiguous, so not compiling, once Producer is Sync
+        trait AmbiguousIfSync<A> {
+            fn some_item() {}
+        }
+        impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+        impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+        let _ = <Producer as AmbiguousIfSync<_>>::some_item;
+    }
+
+    #[rstest]
+    fn process_the_requests_of_every_producer() {
+        let config = RuntimeConfig {
+            capacity: 8,
+            ..Default::default()
+        };
+        let (runtime, producer) = Runtime::spawn(Engine::new(DEFAULT_SYMBOL), config).unwrap();
+        assert_eq!(producer.capacity(), 8);
+
+        let gateways: Vec<_> = (0..4u64)
+            .map(|gateway| {
+                let producer = producer.clone();
+                thread::spawn(move || {
+                    for order_id in 1..=1_000 {
+                        producer.send(create(gateway * 1_000 + order_id)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for gateway in gateways {
+            gateway.join().unwrap();
+        }
+        drop(producer);
+
+        // the gateways are gone: the engine thread stops once it processed all they sent
+        let engine = runtime.join();
+        assert_eq!(engine.orderbook().iter_side(OrderSide::Bid).count(), 4_000);
+    }
+
+    #[rstest]
+    fn drain_on_shutdown() {
+        let (runtime, producer) = Runtime::spawn(Engine::new(DEFAULT_SYMBOL), RuntimeConfig::default()).unwrap();
+        for order_id in 1..=100 {
+            producer.try_send(create(order_id)).unwrap();
+        }
+
+        let engine = runtime.shutdown();
+        assert_eq!(engine.orderbook().iter_side(OrderSide::Bid).count(), 100);
+        assert_eq!(producer.try_send(create(101)), Err(SendError::Closed(create(101))));
+        assert_eq!(
+            producer.clone().try_send(create(101)).unwrap_err().into_inner(),
+            create(101)
+        );
+    }
+}
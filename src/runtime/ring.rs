@@ -0,0 +1,160 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+// keeps the two ends of the ring on their own cache lines, the producer and the consumer do not share one
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+// bounded single producer single consumer queue: the producer only writes the tail, the consumer only the head, each
+// reads the other with acquire ordering (no lock, no read-modify-write on the hot path)
+pub struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    mask: usize,
+    head: CachePadded<AtomicUsize>, // next slot to read
+    tail: CachePadded<AtomicUsize>, // next slot to write
+    writing: AtomicBool,            // a push is in progress (see Runtime::shutdown)
+    disconnected: AtomicBool,       // the producer is gone, nothing more will be pushed
+}
+
+// the slots are only touched by the end that owns them at the time: written by the producer before the tail moves past
+// them, read by the consumer before the head does; sound as long as there is one producer (Producer is not Sync)
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    // rounded up to a power of two
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        Self {
+            slots: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+            mask: capacity - 1,
+            head: CachePadded::default(),
+            tail: CachePadded::default(),
+            writing: AtomicBool::new(false),
+            disconnected: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        let tail = self.tail.0.load(Ordering::Acquire);
+        tail.wrapping_sub(self.head.0.load(Ordering::Acquire))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // producer side only, the value is handed back when the ring is full
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.0.load(Ordering::Acquire)) == self.capacity() {
+            return Err(value);
+        }
+        unsafe { (*self.slots[tail & self.mask].get()).write(value) };
+        self.tail.0.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    // consumer side only
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.0.load(Ordering::Relaxed);
+        if head == self.tail.0.load(Ordering::Acquire) {
+            return None;
+        }
+        let value = unsafe { (*self.slots[head & self.mask].get()).assume_init_read() };
+        self.head.0.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    #[inline]
+    pub fn set_writing(&self, writing: bool) {
+        self.writing.store(writing, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn is_writing(&self) -> bool {
+        self.writing.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn disconnect(&self) {
+        self.disconnected.store(true, Ordering::Release);
+    }
+
+    #[inline]
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn hand_back_what_does_not_fit() {
+        let ring = Ring::new(3);
+        assert_eq!(ring.capacity(), 4);
+        for value in 0..4 {
+            ring.push(value).unwrap();
+        }
+        assert_eq!(ring.push(4), Err(4));
+        assert_eq!(ring.pop(), Some(0));
+        ring.push(4).unwrap();
+        assert_eq!(ring.len(), 4);
+        assert_eq!(std::iter::from_fn(|| ring.pop()).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert!(ring.is_empty());
+    }
+
+    #[rstest]
+    fn keep_the_order_across_threads() {
+        let ring = Arc::new(Ring::new(16));
+        let producer = {
+            let ring = ring.clone();
+            std::thread::spawn(move || {
+                for value in 0..100_000u64 {
+                    let mut value = value;
+                    while let Err(back) = ring.push(value) {
+                        value = back;
+                        std::thread::yield_now();
+                    }
+                }
+                ring.disconnect();
+            })
+        };
+
+        let mut expected = 0;
+        while !(ring.is_disconnected() && ring.is_empty()) {
+            match ring.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(expected, 100_000);
+    }
+}
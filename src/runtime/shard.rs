@@ -0,0 +1,378 @@
+use std::{
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+};
+
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::{Producer, Runtime, RuntimeConfig, SendError, Worker};
+use crate::{
+    engine::Engine,
+    market_data::{Granularity, MarketDataEvent},
+    order::OrderRequest,
+    symbol::Symbol,
+};
+
+// an event of one of the engines, sequenced across all of them
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShardEvent {
+    pub sequence: u64,
+    pub pair: Symbol,
+    #[serde(flatten)]
+    pub event: MarketDataEvent, // its own sequence is the one of its pair
+}
+
+// the engines of the pairs assigned to one worker thread
+pub struct Shard {
+    engines: IndexMap<Symbol, (Engine, Receiver<MarketDataEvent>)>,
+    events: Sender<ShardEvent>,
+    sequence: Arc<AtomicU64>,
+}
+
+impl Worker for Shard {
+    fn handle(&mut self, order_request: OrderRequest) {
+        let pairs: Vec<Symbol> = match &order_request {
+            OrderRequest::Create { pair, .. } | OrderRequest::MassQuote { pair, .. } => vec![pair.clone()],
+            // cancels do not carry the pair: broadcast by the router, only the engine where the account holds the order
+            // takes it (order ids are unique per book only)
+            OrderRequest::Cancel { account_id, order_id } => match self
+                .engines
+                .iter()
+                .find(|(_, (engine, _))| engine.owner((*order_id).into()) == Some(account_id.as_str()))
+            {
+                Some((pair, _)) => vec![pair.clone()],
+                None => return,
+            },
+            OrderRequest::CancelByClientId {
+                account_id,
+                client_order_id,
+            } => match self
+                .engines
+                .iter()
+                .find(|(_, (engine, _))| engine.order_by_client_id(account_id, client_order_id).is_some())
+            {
+                Some((pair, _)) => vec![pair.clone()],
+                None => return,
+            },
+            OrderRequest::CancelAll { .. } => self.engines.keys().cloned().collect(),
+        };
+
+        for pair in pairs {
+            let Some((engine, market_data)) = self.engines.get_mut(&pair) else {
+                error!("Error processing order request: unknown pair {pair}");
+                continue;
+            };
+            engine.handle(order_request.clone());
+            for event in market_data.try_iter() {
+                // the stream is ordered back by sequence on the way out (see EventStream)
+                let sequence = self.sequence.fetch_add(1, Relaxed) + 1;
+                let _ = self.events.send(ShardEvent {
+                    sequence,
+                    pair: pair.clone(),
+                    event,
+                });
+            }
+        }
+    }
+}
+
+// the events of every shard merged in global sequence
+pub struct EventStream {
+    events: Receiver<ShardEvent>,
+    next: u64,
+    pending: BTreeMap<u64, ShardEvent>, // ahead of a sequence some other shard has not sent yet
+}
+
+impl EventStream {
+    // none once every shard is gone and the stream consumed
+    pub fn recv(&mut self) -> Option<ShardEvent> {
+        loop {
+            if let Some(event) = self.next_pending() {
+                return Some(event);
+            }
+            match self.events.recv() {
+                Ok(event) => {
+                    self.pending.insert(event.sequence, event);
+                }
+                Err(_) => return self.pending.pop_first().map(|(_, event)| event),
+            }
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<ShardEvent> {
+        loop {
+            if let Some(event) = self.next_pending() {
+                return Some(event);
+            }
+            match self.events.try_recv() {
+                Ok(event) => {
+                    self.pending.insert(event.sequence, event);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return self.pending.pop_first().map(|(_, event)| event),
+            }
+        }
+    }
+
+    #[inline]
+    fn next_pending(&mut self) -> Option<ShardEvent> {
+        let event = self.pending.remove(&self.next)?;
+        self.next += 1;
+        Some(event)
+    }
+}
+
+// the gateway end of the sharded runtime, cloned per gateway like a Producer
+#[derive(Clone)]
+pub struct Router {
+    producers: Vec<Producer>, // one per shard
+    pairs: Arc<IndexSet<Symbol>>,
+}
+
+impl Router {
+    #[inline]
+    pub fn shard_of(&self, pair: &Symbol) -> usize {
+        shard_of(pair, self.producers.len())
+    }
+
+    // nothing is kept per order: the cancels go to every shard and only the one holding the order takes it, a retry
+    // after a full ring is harmless then
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&mut self, order_request: OrderRequest) -> Result<(), SendError> {
+        let shard = match &order_request {
+            OrderRequest::Create { pair, .. } | OrderRequest::MassQuote { pair, .. } => {
+                if !self.pairs.contains(pair) {
+                    return Err(SendError::UnknownPair(order_request));
+                }
+                Some(self.shard_of(pair))
+            }
+            OrderRequest::Cancel { .. } | OrderRequest::CancelByClientId { .. } | OrderRequest::CancelAll { .. } => {
+                None
+            }
+        };
+
+        match shard {
+            Some(shard) => self.producers[shard].try_send(order_request),
+            None => {
+                let mut result = Ok(());
+                for producer in &self.producers {
+                    if let Err(error) = producer.try_send(order_request.clone()) {
+                        result = Err(error);
+                    }
+                }
+                result.map_err(|error| match error {
+                    SendError::Full(_) => SendError::Full(order_request),
+                    _ => SendError::Closed(order_request),
+                })
+            }
+        }
+    }
+
+    // waits for room in the ring of the shard (see Producer::send)
+    #[allow(clippy::result_large_err)]
+    pub fn send(&mut self, order_request: OrderRequest) -> Result<(), SendError> {
+        let mut order_request = order_request;
+        loop {
+            match self.try_send(order_request) {
+                Err(SendError::Full(back)) => order_request = back,
+                result => return result,
+            }
+            std::thread::yield_now();
+        }
+    }
+}
+
+// the pairs are spread over the shards by hash, each shard is a runtime of its own (pinned to the core of the config
+// plus its index, if any) running the engines of its pairs one request at a time
+pub struct ShardedRuntime {
+    shards: Vec<Runtime<Shard>>,
+}
+
+impl ShardedRuntime {
+    pub fn spawn(
+        pairs: &[Symbol],
+        shards: usize,
+        engine: impl Fn(Symbol) -> Engine,
+        config: RuntimeConfig,
+    ) -> std::io::Result<(Self, Router, EventStream)> {
+        let shards = shards.max(1);
+        let (events, rx) = unbounded();
+        let sequence = Arc::new(AtomicU64::new(0));
+
+        let mut workers: Vec<Shard> = (0..shards)
+            .map(|_| Shard {
+                engines: IndexMap::new(),
+                events: events.clone(),
+                sequence: sequence.clone(),
+            })
+            .collect();
+        for pair in pairs {
+            let mut engine = engine(pair.clone());
+            let market_data = engine.subscribe(Granularity::Order);
+            workers[shard_of(pair, shards)]
+                .engines
+                .insert(pair.clone(), (engine, market_data));
+        }
+
+        let mut runtimes = Vec::with_capacity(shards);
+        let mut producers = Vec::with_capacity(shards);
+        for (index, worker) in workers.into_iter().enumerate() {
+            let config = RuntimeConfig {
+                core: config.core.map(|core| core + index),
+                ..config
+            };
+            let (runtime, producer) = Runtime::spawn(worker, config)?;
+            runtimes.push(runtime);
+            producers.push(producer);
+        }
+
+        let router = Router {
+            producers,
+            pairs: Arc::new(pairs.iter().cloned().collect()),
+        };
+        let stream = EventStream {
+            events: rx,
+            next: 1,
+            pending: BTreeMap::new(),
+        };
+        Ok((Self { shards: runtimes }, router, stream))
+    }
+
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    // the requests already routed are processed first (see Runtime::shutdown)
+    pub fn shutdown(self) -> IndexMap<Symbol, Engine> {
+        let shards: Vec<Shard> = self.shards.into_iter().map(Runtime::shutdown).collect();
+        engines(shards)
+    }
+
+    // once every router is dropped and its requests processed
+    pub fn join(self) -> IndexMap<Symbol, Engine> {
+        let shards: Vec<Shard> = self.shards.into_iter().map(Runtime::join).collect();
+        engines(shards)
+    }
+}
+
+fn engines(shards: Vec<Shard>) -> IndexMap<Symbol, Engine> {
+    shards
+        .into_iter()
+        .flat_map(|shard| shard.engines)
+        .map(|(pair, (engine, _))| (pair, engine))
+        .collect()
+}
+
+#[inline]
+fn shard_of(pair: &Symbol, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    pair.hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        market_data::BookEvent,
+        order::{util, OrderSide},
+    };
+
+    const PAIRS: [&str; 3] = ["BTC/USDT", "ETH/USDT", "SOL/USDT"];
+
+    #[rstest]
+    fn route_by_pair_and_merge_the_events() {
+        let pairs: Vec<Symbol> = PAIRS.iter().map(|pair| pair.parse().unwrap()).collect();
+        let (runtime, mut router, mut stream) =
+            ShardedRuntime::spawn(&pairs, 2, Engine::new, RuntimeConfig::default()).unwrap();
+        assert_eq!(runtime.shards(), 2);
+
+        // an ask then a bid trading with it on every pair, then a second ask cancelled
+        let mut order_id = 0;
+        for pair in PAIRS {
+            for side in [OrderSide::Ask, OrderSide::Bid, OrderSide::Ask] {
+                order_id += 1;
+                router.send(util::create_on(order_id, pair, side).build()).unwrap();
+            }
+            router.send(util::cancel(order_id)).unwrap();
+        }
+        let request = util::create_on(10, "DOGE/USDT", OrderSide::Bid).build();
+        assert_eq!(router.try_send(request.clone()), Err(SendError::UnknownPair(request)));
+        drop(router);
+
+        let engines = runtime.join();
+        assert_eq!(engines.len(), 3);
+        assert!(engines
+            .values()
+            .all(|engine| engine.orderbook().iter_side(OrderSide::Ask).count() == 0));
+
+        // add, trade, delete, add, delete on every pair: globally in sequence, and in order within a pair
+        let events: Vec<ShardEvent> = std::iter::from_fn(|| stream.recv()).collect();
+        assert_eq!(
+            events.iter().map(|event| event.sequence).collect::<Vec<_>>(),
+            (1..=15).collect::<Vec<_>>()
+        );
+        for pair in PAIRS {
+            let pair_events: Vec<&ShardEvent> = events.iter().filter(|event| event.pair == pair).collect();
+            assert_eq!(
+                pair_events.iter().map(|event| event.event.sequence).collect::<Vec<_>>(),
+                vec![1, 2, 3, 4, 5]
+            );
+            assert!(matches!(pair_events[1].event.event, BookEvent::Trade { .. }));
+        }
+    }
+
+    #[rstest]
+    fn cancel_in_the_engine_holding_the_order() {
+        let pairs: Vec<Symbol> = PAIRS.iter().map(|pair| pair.parse().unwrap()).collect();
+        let (runtime, mut router, _stream) =
+            ShardedRuntime::spawn(&pairs, 2, Engine::new, RuntimeConfig::default()).unwrap();
+
+        // create/cancel cycles over every pair leave nothing behind
+        for order_id in 1..=3_000 {
+            router
+                .send(util::create_on(order_id, PAIRS[order_id as usize % 3], OrderSide::Bid).build())
+                .unwrap();
+            router.send(util::cancel(order_id)).unwrap();
+        }
+        // an id reused on another pair, filled there at once, then cancelled: the cancel reaches the resting one
+        router
+            .send(util::create_on(3_001, PAIRS[1], OrderSide::Ask).build())
+            .unwrap();
+        router
+            .send(util::create_on(3_002, PAIRS[0], OrderSide::Bid).build())
+            .unwrap();
+        router
+            .send(util::create_on(3_002, PAIRS[1], OrderSide::Bid).build())
+            .unwrap();
+        router.send(util::cancel(3_002)).unwrap();
+        // an id resting for another account on another pair is left alone
+        router
+            .send(util::create_on(3_003, PAIRS[0], OrderSide::Bid).account_id("2").build())
+            .unwrap();
+        router
+            .send(util::create_on(3_003, PAIRS[2], OrderSide::Bid).build())
+            .unwrap();
+        router.send(util::cancel(3_003)).unwrap();
+        drop(router);
+
+        let engines = runtime.join();
+        let resting: Vec<(&str, usize)> = engines
+            .iter()
+            .map(|(pair, engine)| (pair.as_str(), engine.orderbook().resting_count()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        assert_eq!(resting, [(PAIRS[0], 1)]);
+        assert_eq!(engines[PAIRS[0]].owner(3_003.into()), Some("2"));
+    }
+}
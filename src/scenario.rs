@@ -0,0 +1,203 @@
+use std::{fmt::Display, str::FromStr};
+
+use compact_str::CompactString;
+use thiserror::Error;
+
+use crate::order::{
+    util::{generate_with, GeneratorConfig},
+    OrderRequest,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scenario {
+    FlashCrash,
+    OneSidedFlow,
+    CancelStorm,
+    QuoteStuffing,
+    ThinBook,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 5] = [
+        Scenario::FlashCrash,
+        Scenario::OneSidedFlow,
+        Scenario::CancelStorm,
+        Scenario::QuoteStuffing,
+        Scenario::ThinBook,
+    ];
+
+    pub fn config(&self) -> GeneratorConfig {
+        let default = GeneratorConfig::default();
+        match self {
+            // sellers take over: mostly asks, lots of market orders and prices sliding down on every order
+            Scenario::FlashCrash => GeneratorConfig {
+                bid_ratio: 0.2,
+                market_ratio: 0.5,
+                price_range: 490_000..510_000,
+                price_drift: -1,
+                ..default
+            },
+            // (almost) all the flow is on the bid side, the ask side of the book is quickly drained
+            Scenario::OneSidedFlow => GeneratorConfig {
+                bid_ratio: 0.95,
+                ..default
+            },
+            // half of the requests are cancels of any previous order
+            Scenario::CancelStorm => GeneratorConfig {
+                cancel_ratio: 0.5,
+                ..default
+            },
+            // tiny orders cancelled right after being sent, flooding the book with short-lived quotes
+            Scenario::QuoteStuffing => GeneratorConfig {
+                cancel_ratio: 0.5,
+                cancel_window: Some(2),
+                market_ratio: 0.0,
+                quantity_range: 1..100,
+                ..default
+            },
+            // few and small orders spread over a wide price range, market orders sweep many levels
+            Scenario::ThinBook => GeneratorConfig {
+                market_ratio: 0.3,
+                price_range: 100..10_000_000,
+                quantity_range: 1..500,
+                ..default
+            },
+        }
+    }
+
+    pub fn generate(&self, range: impl Iterator<Item = usize>) -> impl Iterator<Item = OrderRequest> {
+        generate_with(self.config(), range)
+    }
+}
+
+impl Display for Scenario {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scenario::FlashCrash => write!(f, "flash-crash"),
+            Scenario::OneSidedFlow => write!(f, "one-sided-flow"),
+            Scenario::CancelStorm => write!(f, "cancel-storm"),
+            Scenario::QuoteStuffing => write!(f, "quote-stuffing"),
+            Scenario::ThinBook => write!(f, "thin-book"),
+        }
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = ScenarioError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scenario::ALL
+            .into_iter()
+            .find(|scenario| scenario.to_string() == s)
+            .ok_or_else(|| ScenarioError::UnknownScenario(s.into()))
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ScenarioError {
+    #[error("unknown scenario! {0}")]
+    UnknownScenario(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{OrderPrice, OrderQuantity, OrderSide};
+
+    const SAMPLES: usize = 10_000;
+
+    fn creates(scenario: Scenario) -> Vec<(OrderSide, Option<OrderPrice>, OrderQuantity)> {
+        scenario
+            .generate(1..=SAMPLES)
+            .filter_map(|order_request| match order_request {
+                OrderRequest::Create {
+                    side,
+                    limit_price,
+                    quantity,
+                    ..
+                } => Some((side, limit_price, quantity)),
+                OrderRequest::Cancel { .. }
+                | OrderRequest::CancelByClientId { .. }
+                | OrderRequest::CancelAll { .. }
+                | OrderRequest::MassQuote { .. } => None,
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn round_trip_names() {
+        for scenario in Scenario::ALL {
+            assert_eq!(scenario.to_string().parse::<Scenario>(), Ok(scenario));
+        }
+        assert_eq!(
+            "unknown".parse::<Scenario>(),
+            Err(ScenarioError::UnknownScenario("unknown".into()))
+        );
+    }
+
+    #[rstest]
+    fn flash_crash_prices_slide_down() {
+        let prices: Vec<OrderPrice> = creates(Scenario::FlashCrash)
+            .into_iter()
+            .filter_map(|(_, limit_price, _)| limit_price)
+            .collect();
+
+        // compare the average of the first and the last chunk of limit prices
+        let chunk = prices.len() / 10;
+        let first: OrderPrice = prices[..chunk].iter().sum();
+        let last: OrderPrice = prices[prices.len() - chunk..].iter().sum();
+        assert!(last < first);
+    }
+
+    #[rstest]
+    fn one_sided_flow_is_mostly_bids() {
+        let creates = creates(Scenario::OneSidedFlow);
+        let bids = creates.iter().filter(|(side, ..)| *side == OrderSide::Bid).count();
+        assert!(bids * 10 > creates.len() * 9);
+    }
+
+    #[rstest]
+    fn cancel_storm_is_mostly_cancels() {
+        let cancels = Scenario::CancelStorm
+            .generate(1..=SAMPLES)
+            .filter(|order_request| matches!(order_request, OrderRequest::Cancel { .. }))
+            .count();
+        assert!(cancels * 3 > SAMPLES);
+    }
+
+    #[rstest]
+    fn quote_stuffing_cancels_recent_orders() {
+        for (i, order_request) in (1..=SAMPLES).zip(Scenario::QuoteStuffing.generate(1..=SAMPLES)) {
+            match order_request {
+                OrderRequest::Cancel { order_id, .. } => assert!(order_id + 2 >= i as u64 && order_id <= i as u64),
+                OrderRequest::Create { limit_price, .. } => assert!(limit_price.is_some()),
+                OrderRequest::CancelByClientId { .. }
+                | OrderRequest::CancelAll { .. }
+                | OrderRequest::MassQuote { .. } => unreachable!(),
+            }
+        }
+    }
+
+    #[rstest]
+    fn reproduce_a_seeded_flow() {
+        let seeded = |seed| {
+            let config = GeneratorConfig {
+                seed: Some(seed),
+                ..Scenario::CancelStorm.config()
+            };
+            generate_with(config, 1..=100).collect::<Vec<OrderRequest>>()
+        };
+        assert_eq!(seeded(7), seeded(7));
+        assert_ne!(seeded(7), seeded(8));
+    }
+
+    #[rstest]
+    fn thin_book_has_small_quantities() {
+        let max = OrderQuantity::new(500, 2);
+        assert!(creates(Scenario::ThinBook)
+            .iter()
+            .all(|(_, _, quantity)| *quantity < max));
+    }
+}
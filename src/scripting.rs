@@ -0,0 +1,258 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use compact_str::{format_compact, CompactString, ToCompactString};
+use rhai::{module_resolvers::DummyModuleResolver, Dynamic, Map, Scope, AST};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use thiserror::Error;
+
+use crate::order::OrderRequest;
+
+// what a rule may spend on one order before it is stopped (and the order rejected)
+const MAX_OPERATIONS: u64 = 10_000;
+
+// operator rules too niche to hardcode, as a rhai script evaluated for every new order in the pre-trade checks:
+//   fn validate(order) { if order.quantity > 1000 && order.market { "no large market orders" } }
+//   fn fee(order) { if order.account_id == "retail" { 0.1 } else { 0 } }
+// validate (required) accepts the order with nothing (or true), rejects it with false or a reason; fee (optional) is
+// charged to the account once the order is accepted; the order is a map of account_id, order_id, side ("BUY" or
+// "SELL"), limit_price (unit for market orders), market, quantity and short_sell
+// sandboxed: no modules, no eval, nothing printed, a bounded number of operations per call
+pub struct ScriptRules {
+    engine: rhai::Engine,
+    ast: AST,
+    has_fee: bool,
+    source: Option<(PathBuf, Option<SystemTime>)>, // the file it was loaded from, as modified then
+}
+
+impl ScriptRules {
+    pub fn compile(script: &str) -> Result<Self, ScriptError> {
+        let engine = sandbox();
+        let ast = compile(&engine, script)?;
+        Ok(Self {
+            has_fee: has_fn(&ast, "fee"),
+            engine,
+            ast,
+            source: None,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let path = path.as_ref();
+        let modified = modified(path)?;
+        let script = fs::read_to_string(path).map_err(|error| ScriptError::Read(format_compact!("{error}")))?;
+        let mut rules = Self::compile(&script)?;
+        rules.source = Some((path.to_path_buf(), modified));
+        Ok(rules)
+    }
+
+    // hot reload: once the file it was loaded from changed, returns whether it did; a script that does not compile
+    // leaves the rules as they were
+    pub fn reload(&mut self) -> Result<bool, ScriptError> {
+        let Some((path, loaded)) = self.source.as_ref() else {
+            return Ok(false);
+        };
+        if modified(path)? == *loaded {
+            return Ok(false);
+        }
+
+        *self = Self::load(path.clone())?;
+        Ok(true)
+    }
+
+    // none for an accepted order
+    pub fn validate(&self, order_request: &OrderRequest) -> Result<Option<CompactString>, ScriptError> {
+        let Some(order) = order_map(order_request) else {
+            return Ok(None);
+        };
+        let verdict = self.call("validate", order)?;
+        if verdict.is_unit() {
+            return Ok(None);
+        }
+        if let Ok(accepted) = verdict.as_bool() {
+            return Ok((!accepted).then(|| "rejected by script".into()));
+        }
+        match verdict.into_immutable_string() {
+            Ok(reason) => Ok(Some(reason.as_str().into())),
+            Err(type_name) => Err(ScriptError::Eval(format_compact!("validate returned {type_name}"))),
+        }
+    }
+
+    // zero without a fee rule
+    pub fn fee(&self, order_request: &OrderRequest) -> Result<Decimal, ScriptError> {
+        let Some(order) = order_map(order_request).filter(|_| self.has_fee) else {
+            return Ok(Decimal::ZERO);
+        };
+        let fee = self.call("fee", order)?;
+        let fee = if let Ok(fee) = fee.as_decimal() {
+            fee
+        } else if let Ok(fee) = fee.as_int() {
+            fee.into()
+        } else if let Some(fee) = fee.as_float().ok().and_then(Decimal::from_f64) {
+            fee
+        } else {
+            return Err(ScriptError::Eval(format_compact!("fee returned {}", fee.type_name())));
+        };
+        if fee.is_sign_negative() {
+            return Err(ScriptError::Eval(format_compact!("negative fee {fee}")));
+        }
+        Ok(fee)
+    }
+
+    fn call(&self, name: &str, order: Map) -> Result<Dynamic, ScriptError> {
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, (order,))
+            .map_err(|error| ScriptError::Eval(format_compact!("{name}: {error}")))
+    }
+}
+
+fn sandbox() -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .disable_symbol("eval")
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {})
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(16)
+        .set_max_expr_depths(32, 32)
+        .set_max_string_size(1_024)
+        .set_max_array_size(1_024)
+        .set_max_map_size(256);
+    engine
+}
+
+fn compile(engine: &rhai::Engine, script: &str) -> Result<AST, ScriptError> {
+    let ast = engine
+        .compile(script)
+        .map_err(|error| ScriptError::Parse(format_compact!("{error}")))?;
+    if !has_fn(&ast, "validate") {
+        return Err(ScriptError::Parse("no validate function".into()));
+    }
+    Ok(ast)
+}
+
+#[inline]
+fn has_fn(ast: &AST, name: &str) -> bool {
+    ast.iter_functions()
+        .any(|function| function.name == name && function.params.len() == 1)
+}
+
+#[inline]
+fn modified(path: &Path) -> Result<Option<SystemTime>, ScriptError> {
+    let metadata = fs::metadata(path).map_err(|error| ScriptError::Read(format_compact!("{error}")))?;
+    Ok(metadata.modified().ok())
+}
+
+fn order_map(order_request: &OrderRequest) -> Option<Map> {
+    let OrderRequest::Create {
+        account_id,
+        order_id,
+        side,
+        limit_price,
+        quantity,
+        short_sell,
+        ..
+    } = order_request
+    else {
+        return None;
+    };
+
+    let mut order = Map::new();
+    order.insert("account_id".into(), account_id.as_str().into());
+    order.insert("order_id".into(), Dynamic::from_int(*order_id as i64));
+    order.insert("side".into(), side.to_compact_string().as_str().into());
+    order.insert(
+        "limit_price".into(),
+        limit_price.map_or(Dynamic::UNIT, |limit_price| Dynamic::from_decimal(limit_price.value())),
+    );
+    order.insert("market".into(), limit_price.is_none().into());
+    order.insert("quantity".into(), Dynamic::from_decimal(quantity.value()));
+    order.insert("short_sell".into(), (*short_sell).into());
+    Some(order)
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ScriptError {
+    #[error("script not read! {0}")]
+    Read(CompactString),
+    #[error("script not compiled! {0}")]
+    Parse(CompactString),
+    #[error("script failed! {0}")]
+    Eval(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{util, OrderSide};
+
+    fn create(account_id: &str, limit_price: Option<u32>, quantity: u32) -> OrderRequest {
+        util::create(1, OrderSide::Bid)
+            .account_id(account_id)
+            .limit_price(limit_price.map(Into::into))
+            .quantity(quantity)
+            .build()
+    }
+
+    const RULES: &str = r#"
+        fn validate(order) {
+            if order.market && order.quantity > 100 { return "no large market orders"; }
+            order.side == "BUY" || order.limit_price >= 10
+        }
+        fn fee(order) { if order.account_id == "retail" { order.quantity / 100 } else { 0 } }
+    "#;
+
+    #[rstest]
+    fn validate_and_charge() {
+        let rules = ScriptRules::compile(RULES).unwrap();
+        assert_eq!(rules.validate(&create("1", Some(15), 500)), Ok(None));
+        assert_eq!(
+            rules.validate(&create("1", None, 500)),
+            Ok(Some("no large market orders".into()))
+        );
+        assert_eq!(rules.fee(&create("retail", Some(15), 50)), Ok(Decimal::new(5, 1)));
+        assert_eq!(rules.fee(&create("1", Some(15), 50)), Ok(Decimal::ZERO));
+
+        // the sandbox stops a runaway rule
+        let rules = ScriptRules::compile("fn validate(order) { loop {} }").unwrap();
+        assert!(matches!(
+            rules.validate(&create("1", None, 1)),
+            Err(ScriptError::Eval(_))
+        ));
+        assert_eq!(
+            ScriptRules::compile("fn check(order) {}").err(),
+            Some(ScriptError::Parse("no validate function".into()))
+        );
+    }
+
+    #[rstest]
+    fn reload_when_the_file_changes() {
+        let path = util::TempPath::new("rules.rhai");
+        fs::write(&path, "fn validate(order) { true }").unwrap();
+        let mut rules = ScriptRules::load(&path).unwrap();
+        assert_eq!(rules.reload(), Ok(false));
+
+        fs::write(&path, "fn validate(order) { false }").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(rules.reload(), Ok(true));
+        assert_eq!(
+            rules.validate(&create("1", Some(15), 1)),
+            Ok(Some("rejected by script".into()))
+        );
+
+        // a broken script keeps the rules in place
+        fs::write(&path, "fn validate(order) {").unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(2))
+            .unwrap();
+        assert!(matches!(rules.reload(), Err(ScriptError::Parse(_))));
+        assert!(rules.validate(&create("1", Some(15), 1)).unwrap().is_some());
+    }
+}
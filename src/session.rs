@@ -0,0 +1,287 @@
+use compact_str::CompactString;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{clock::Timestamp, order::OrderRequest};
+
+// connection of a gateway client, bound to one account for its whole life
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Session {
+    pub session_id: u64,
+    pub account_id: CompactString,
+    pub last_heartbeat: Timestamp,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DisconnectReason {
+    Closed,
+    TimedOut,
+}
+
+// lifecycle of a session as recorded in the audit trail
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "event")]
+pub enum SessionEvent {
+    Authenticated { account_id: CompactString }, // the session is open once bound to its account
+    Subscribed { pair: CompactString },
+    Submitted { order_request: OrderRequest },
+    Disconnected { reason: DisconnectReason },
+    CancelledOnDisconnect { order_ids: Vec<u64> }, // the last session of the account is gone
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub at: Timestamp,
+    pub session_id: u64,
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+// sessions silent for longer than the heartbeat timeout are considered gone, who did what through which session is
+// kept in the audit trail
+#[derive(Clone, Debug)]
+pub struct SessionManager {
+    heartbeat_timeout: Timestamp,
+    sessions: IndexMap<u64, Session>,
+    audit: Vec<AuditEntry>,
+    submitted_by: IndexMap<u64, u64>, // session of every order created through one
+}
+
+impl SessionManager {
+    #[inline]
+    pub fn new(heartbeat_timeout: Timestamp) -> Self {
+        Self {
+            heartbeat_timeout,
+            sessions: IndexMap::new(),
+            audit: vec![],
+            submitted_by: IndexMap::new(),
+        }
+    }
+
+    pub fn open(&mut self, session_id: u64, account_id: &str, now: Timestamp) -> Result<(), SessionError> {
+        if self.sessions.contains_key(&session_id) {
+            return Err(SessionError::DuplicateSession(session_id));
+        }
+
+        self.sessions.insert(
+            session_id,
+            Session {
+                session_id,
+                account_id: account_id.into(),
+                last_heartbeat: now,
+            },
+        );
+        self.record(
+            now,
+            session_id,
+            SessionEvent::Authenticated {
+                account_id: account_id.into(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn heartbeat(&mut self, session_id: u64, now: Timestamp) -> Result<(), SessionError> {
+        let session = self
+            .sessions
+            .get_mut(&session_id)
+            .ok_or(SessionError::UnknownSession(session_id))?;
+        session.last_heartbeat = session.last_heartbeat.max(now);
+        Ok(())
+    }
+
+    pub fn close(&mut self, session_id: u64, now: Timestamp) -> Result<Session, SessionError> {
+        let session = self
+            .sessions
+            .shift_remove(&session_id)
+            .ok_or(SessionError::UnknownSession(session_id))?;
+        self.record(
+            now,
+            session_id,
+            SessionEvent::Disconnected {
+                reason: DisconnectReason::Closed,
+            },
+        );
+        Ok(session)
+    }
+
+    // removes the sessions past their heartbeat timeout, returns them
+    pub fn time_out(&mut self, now: Timestamp) -> Vec<Session> {
+        let timed_out: Vec<u64> = self
+            .sessions
+            .values()
+            .filter(|session| session.last_heartbeat + self.heartbeat_timeout <= now)
+            .map(|session| session.session_id)
+            .collect();
+        let timed_out: Vec<Session> = timed_out
+            .into_iter()
+            .filter_map(|session_id| self.sessions.shift_remove(&session_id))
+            .collect();
+        for session in &timed_out {
+            self.record(
+                now,
+                session.session_id,
+                SessionEvent::Disconnected {
+                    reason: DisconnectReason::TimedOut,
+                },
+            );
+        }
+        timed_out
+    }
+
+    // a session only sends requests for its own account
+    pub fn submit(
+        &mut self,
+        session_id: u64,
+        order_request: &OrderRequest,
+        now: Timestamp,
+    ) -> Result<(), SessionError> {
+        let session = self
+            .sessions
+            .get(&session_id)
+            .ok_or(SessionError::UnknownSession(session_id))?;
+        let account_id = order_request.account_id();
+        if account_id != session.account_id {
+            return Err(SessionError::AccountMismatch {
+                session_id,
+                account_id: account_id.into(),
+            });
+        }
+
+        for order_id in order_request.new_order_ids() {
+            self.submitted_by.insert(order_id, session_id);
+        }
+        self.record(
+            now,
+            session_id,
+            SessionEvent::Submitted {
+                order_request: order_request.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn subscribe(&mut self, session_id: u64, pair: &str, now: Timestamp) -> Result<(), SessionError> {
+        if !self.sessions.contains_key(&session_id) {
+            return Err(SessionError::UnknownSession(session_id));
+        }
+        self.record(now, session_id, SessionEvent::Subscribed { pair: pair.into() });
+        Ok(())
+    }
+
+    #[inline]
+    pub fn record(&mut self, at: Timestamp, session_id: u64, event: SessionEvent) {
+        self.audit.push(AuditEntry { at, session_id, event });
+    }
+
+    #[inline]
+    pub fn audit(&self) -> &[AuditEntry] {
+        &self.audit
+    }
+
+    // the session the order was created through, if any
+    #[inline]
+    pub fn submitted_by(&self, order_id: u64) -> Option<u64> {
+        self.submitted_by.get(&order_id).copied()
+    }
+
+    #[inline]
+    pub fn get(&self, session_id: u64) -> Option<&Session> {
+        self.sessions.get(&session_id)
+    }
+
+    #[inline]
+    pub fn is_connected(&self, account_id: &str) -> bool {
+        self.sessions.values().any(|session| session.account_id == account_id)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SessionError {
+    #[error("session already open! {0}")]
+    DuplicateSession(u64),
+    #[error("unknown session! {0}")]
+    UnknownSession(u64),
+    #[error("request for another account (session_id={}, account_id={})", .session_id, .account_id)]
+    AccountMismatch { session_id: u64, account_id: CompactString },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::clock::SECOND;
+
+    #[rstest]
+    fn time_out_silent_sessions() {
+        let mut sessions = SessionManager::new(30 * SECOND);
+        sessions.open(1, "1", 0).unwrap();
+        sessions.open(2, "1", 0).unwrap();
+        assert_eq!(sessions.open(2, "2", 0), Err(SessionError::DuplicateSession(2)));
+
+        sessions.heartbeat(2, 20 * SECOND).unwrap();
+        let timed_out = sessions.time_out(30 * SECOND);
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].session_id, 1);
+        assert!(sessions.is_connected("1"));
+
+        assert_eq!(sessions.close(2, 30 * SECOND).unwrap().account_id, "1");
+        assert!(!sessions.is_connected("1"));
+        assert_eq!(sessions.heartbeat(2, 40 * SECOND), Err(SessionError::UnknownSession(2)));
+    }
+
+    #[rstest]
+    fn audit_the_lifecycle_of_sessions() {
+        let mut sessions = SessionManager::new(30 * SECOND);
+        sessions.open(1, "1", 0).unwrap();
+        sessions.subscribe(1, "ETH/USDT", 1).unwrap();
+        let cancel = |account_id: &str| OrderRequest::Cancel {
+            account_id: account_id.into(),
+            order_id: 1,
+        };
+        sessions.submit(1, &cancel("1"), 2).unwrap();
+        assert_eq!(
+            sessions.submit(1, &cancel("2"), 3),
+            Err(SessionError::AccountMismatch {
+                session_id: 1,
+                account_id: "2".into()
+            })
+        );
+        // only the orders created through a session are linked to it
+        assert_eq!(sessions.submitted_by(1), None);
+        sessions.close(1, 4).unwrap();
+
+        let events: Vec<(Timestamp, SessionEvent)> = sessions
+            .audit()
+            .iter()
+            .map(|entry| (entry.at, entry.event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (0, SessionEvent::Authenticated { account_id: "1".into() }),
+                (
+                    1,
+                    SessionEvent::Subscribed {
+                        pair: "ETH/USDT".into()
+                    }
+                ),
+                (
+                    2,
+                    SessionEvent::Submitted {
+                        order_request: cancel("1")
+                    }
+                ),
+                (
+                    4,
+                    SessionEvent::Disconnected {
+                        reason: DisconnectReason::Closed
+                    }
+                ),
+            ]
+        );
+    }
+}
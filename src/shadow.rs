@@ -0,0 +1,197 @@
+use compact_str::{format_compact, CompactString};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    engine::{Engine, EngineError},
+    order::{OrderPrice, OrderQuantity, OrderRequest},
+    trade::Trade,
+};
+
+// a trade as seen by the two engines, its id left out (ids are global to the process)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Fill {
+    pub taker: u64,
+    pub maker: u64,
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+    pub fees: Decimal, // maker and taker fees together
+}
+
+impl From<&Trade> for Fill {
+    fn from(trade: &Trade) -> Self {
+        Self {
+            taker: trade.taker().into(),
+            maker: trade.maker().into(),
+            price: trade.price(),
+            quantity: trade.quantity(),
+            fees: trade.maker_fee() + trade.taker_fee(),
+        }
+    }
+}
+
+// what one request did to one engine
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Outcome {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<CompactString>,
+    pub fills: Vec<Fill>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Divergence {
+    pub sequence: u64, // of the request in the input stream
+    pub order_request: OrderRequest,
+    pub live: Outcome,
+    pub shadow: Outcome,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Totals {
+    pub rejected: u64,
+    pub trades: u64,
+    pub volume: OrderQuantity,
+    pub fees: Decimal,
+}
+
+impl Totals {
+    fn add(&mut self, outcome: &Outcome) {
+        self.rejected += u64::from(outcome.rejected.is_some());
+        for fill in &outcome.fills {
+            self.trades += 1;
+            self.volume += fill.quantity;
+            self.fees += fill.fees;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShadowReport {
+    pub requests: u64,
+    pub live: Totals,
+    pub shadow: Totals,
+    pub divergences: Vec<Divergence>,
+}
+
+// the shadow engine runs the proposed configuration (fees, bands, matching...) on the same input as the live one, every
+// request whose outcome differs between the two is reported; only the live engine answers
+pub struct ShadowEngine {
+    live: Engine,
+    shadow: Engine,
+    report: ShadowReport,
+}
+
+impl ShadowEngine {
+    #[inline]
+    pub fn new(live: Engine, shadow: Engine) -> Self {
+        Self {
+            live,
+            shadow,
+            report: ShadowReport::default(),
+        }
+    }
+
+    pub fn process(&mut self, order_request: OrderRequest) -> Result<(), EngineError> {
+        let (live_result, live) = run(&mut self.live, order_request.clone());
+        let (_, shadow) = run(&mut self.shadow, order_request.clone());
+
+        self.report.live.add(&live);
+        self.report.shadow.add(&shadow);
+        if live != shadow {
+            self.report.divergences.push(Divergence {
+                sequence: self.report.requests,
+                order_request,
+                live,
+                shadow,
+            });
+        }
+        self.report.requests += 1;
+        live_result
+    }
+
+    #[inline]
+    pub fn live(&self) -> &Engine {
+        &self.live
+    }
+
+    #[inline]
+    pub fn shadow(&self) -> &Engine {
+        &self.shadow
+    }
+
+    #[inline]
+    pub fn report(&self) -> &ShadowReport {
+        &self.report
+    }
+
+    // e.g. once the proposed configuration is approved, the shadow engine can take over
+    #[inline]
+    pub fn into_engines(self) -> (Engine, Engine, ShadowReport) {
+        (self.live, self.shadow, self.report)
+    }
+}
+
+fn run(engine: &mut Engine, order_request: OrderRequest) -> (Result<(), EngineError>, Outcome) {
+    let (trades_from, odd_lot_trades_from) = (engine.orderbook().trade_count(), engine.odd_lots().trade_count());
+    let result = engine.process(order_request);
+    let fills = engine
+        .orderbook()
+        .trades_from(trades_from)
+        .chain(engine.odd_lots().trades_from(odd_lot_trades_from))
+        .map(Fill::from)
+        .collect();
+    let rejected = result.as_ref().err().map(|error| format_compact!("{error}"));
+    (result, Outcome { rejected, fills })
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::{
+        util::{self, DEFAULT_SYMBOL},
+        OrderSide, TimeInForce,
+    };
+
+    fn create(order_id: u64, side: OrderSide, limit_price: u32, post_only: bool) -> OrderRequest {
+        util::create(order_id, side)
+            .limit_price(Some(limit_price.into()))
+            .time_in_force(Some(TimeInForce::GoodTilCancel { post_only }))
+            .build()
+    }
+
+    #[rstest]
+    fn report_the_outcomes_that_differ() {
+        // proposed: crossing post-only orders are re-priced rather than rejected
+        let shadow = Engine::new(DEFAULT_SYMBOL).with_post_only_repricing(1.into());
+        let mut engine = ShadowEngine::new(Engine::new(DEFAULT_SYMBOL), shadow);
+
+        engine.process(create(1, OrderSide::Ask, 15, false)).unwrap();
+        assert!(engine.process(create(2, OrderSide::Bid, 16, true)).is_err());
+        // only the shadow engine has the re-priced bid to trade against
+        engine.process(create(3, OrderSide::Ask, 14, false)).unwrap();
+
+        let report = engine.report();
+        assert_eq!(report.requests, 3);
+        assert_eq!((report.live.rejected, report.live.trades), (1, 0));
+        assert_eq!((report.shadow.rejected, report.shadow.trades), (0, 1));
+        let sequences: Vec<u64> = report
+            .divergences
+            .iter()
+            .map(|divergence| divergence.sequence)
+            .collect();
+        assert_eq!(sequences, vec![1, 2]);
+        assert_eq!(
+            report.divergences[1].shadow.fills,
+            vec![Fill {
+                taker: 3,
+                maker: 2,
+                price: 14.into(),
+                quantity: 10.into(),
+                fees: Decimal::ZERO,
+            }]
+        );
+        assert!(engine.live().orderbook().get_order(3.into()).is_some());
+    }
+}
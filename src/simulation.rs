@@ -0,0 +1,523 @@
+use crossbeam_channel::Receiver;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Timestamp,
+    engine::{Engine, EngineError},
+    market_data::{BookEvent, Granularity, MarketDataEvent},
+    order::{OrderId, OrderPrice, OrderQuantity, OrderRequest, OrderSide},
+};
+
+pub mod flow;
+
+// a request of the flow replayed, historical or synthetic, at the time it reached the engine
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimedRequest {
+    pub at: Timestamp,
+    #[serde(flatten)]
+    pub order_request: OrderRequest,
+}
+
+// timestamps for a synthetic flow (e.g. util::generate), one request every `step` from `start`
+pub fn timed(
+    order_requests: impl IntoIterator<Item = OrderRequest>,
+    start: Timestamp,
+    step: Timestamp,
+) -> impl Iterator<Item = TimedRequest> {
+    order_requests
+        .into_iter()
+        .enumerate()
+        .map(move |(i, order_request)| TimedRequest {
+            at: start + step * i as Timestamp,
+            order_request,
+        })
+}
+
+// an order of the strategy under study, overlaid on the replayed book: it never reaches the engine so the flow replayed
+// is not affected by it, its fills are modeled from the book instead
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StrategyOrder {
+    pub submit_at: Timestamp, // when the strategy decides, it reaches the book after the latency
+    pub side: OrderSide,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<OrderPrice>, // none for a market order, what it does not take is cancelled
+    pub quantity: OrderQuantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_in_book: Option<Timestamp>, // cancelled that long after reaching the book, otherwise kept to the end
+}
+
+impl StrategyOrder {
+    #[inline]
+    fn crosses(&self, price: OrderPrice) -> bool {
+        match (self.side, self.limit_price) {
+            (_, None) => true,
+            (OrderSide::Bid, Some(limit_price)) => price <= limit_price,
+            (OrderSide::Ask, Some(limit_price)) => price >= limit_price,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SimulationConfig {
+    pub latency: Timestamp, // from the decision of the strategy to its order reaching the book
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StrategyFill {
+    pub at: Timestamp,
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+    pub maker: bool,
+}
+
+// what became of one strategy order
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StrategyOutcome {
+    pub order: StrategyOrder,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision_mid: Option<OrderPrice>, // of the book when the strategy decided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arrived_at: Option<Timestamp>, // none when the flow ended before
+    pub queue_ahead: OrderQuantity, // resting at its price on arrival, to trade before it
+    pub fills: Vec<StrategyFill>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled_at: Option<Timestamp>,
+}
+
+impl StrategyOutcome {
+    #[inline]
+    pub fn filled(&self) -> OrderQuantity {
+        self.fills.iter().map(|fill| fill.quantity).sum()
+    }
+
+    #[inline]
+    pub fn remaining(&self) -> OrderQuantity {
+        self.order.quantity - self.filled()
+    }
+
+    // weighted by the quantity of every fill
+    pub fn average_price(&self) -> Option<OrderPrice> {
+        let notional: Decimal = self.fills.iter().map(|fill| fill.price * fill.quantity).sum();
+        Some(OrderPrice::from_decimal(notional.checked_div(self.filled().value())?))
+    }
+
+    // against the mid price of the decision, positive when it cost the strategy (e.g. a bid filled above the mid)
+    pub fn slippage(&self) -> Option<Decimal> {
+        let slippage = self.average_price()? - self.decision_mid?;
+        Some(match self.order.side {
+            OrderSide::Bid => slippage,
+            OrderSide::Ask => -slippage,
+        })
+    }
+
+    // from the arrival in the book to the first fill
+    pub fn time_to_fill(&self) -> Option<Timestamp> {
+        Some(self.fills.first()?.at - self.arrived_at?)
+    }
+}
+
+// fill statistics of the strategy orders over the flow replayed
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FillReport {
+    pub requests: u64, // of the flow
+    pub rejected: u64, // of the flow, by the engine
+    pub orders: u64,
+    pub arrived: u64,
+    pub filled: u64,    // with at least one fill
+    pub completed: u64, // filled in full
+    pub submitted: OrderQuantity,
+    pub maker_volume: OrderQuantity,
+    pub taker_volume: OrderQuantity,
+    pub outcomes: Vec<StrategyOutcome>, // in the order submitted
+}
+
+impl FillReport {
+    fn new(requests: u64, rejected: u64, outcomes: Vec<StrategyOutcome>) -> Self {
+        let mut report = Self {
+            requests,
+            rejected,
+            ..Default::default()
+        };
+        for outcome in &outcomes {
+            report.orders += 1;
+            report.arrived += u64::from(outcome.arrived_at.is_some());
+            report.filled += u64::from(!outcome.fills.is_empty());
+            report.completed += u64::from(outcome.remaining().is_zero());
+            report.submitted += outcome.order.quantity;
+            for fill in &outcome.fills {
+                match fill.maker {
+                    true => report.maker_volume += fill.quantity,
+                    false => report.taker_volume += fill.quantity,
+                }
+            }
+        }
+        report.outcomes = outcomes;
+        report
+    }
+
+    // of the orders that reached the book, the share filled at least in part
+    #[inline]
+    pub fn fill_probability(&self) -> Decimal {
+        Decimal::from(self.filled)
+            .checked_div(self.arrived.into())
+            .unwrap_or_default()
+    }
+
+    // of the quantity submitted, the share filled
+    #[inline]
+    pub fn fill_rate(&self) -> Decimal {
+        (self.maker_volume + self.taker_volume)
+            .value()
+            .checked_div(self.submitted.value())
+            .unwrap_or_default()
+    }
+
+    // weighted by the quantity filled
+    pub fn slippage(&self) -> Option<Decimal> {
+        let (total, quantity) = self
+            .outcomes
+            .iter()
+            .filter_map(|outcome| Some((outcome.slippage()?, outcome.filled())))
+            .fold(
+                (Decimal::ZERO, Decimal::ZERO),
+                |(total, weight), (slippage, quantity)| (total + slippage * quantity, weight + quantity.value()),
+            );
+        total.checked_div(quantity)
+    }
+
+    pub fn mean_time_to_fill(&self) -> Option<Timestamp> {
+        let times: Vec<Timestamp> = self.outcomes.iter().filter_map(StrategyOutcome::time_to_fill).collect();
+        (!times.is_empty()).then(|| times.iter().sum::<Timestamp>() / times.len() as Timestamp)
+    }
+}
+
+// a strategy order in the book: its queue is tracked order by order from the L3 stream, the orders resting at its
+// price on arrival trade before it, the ones arriving after trade behind it
+#[derive(Debug)]
+struct Working {
+    index: usize,
+    ahead: IndexMap<OrderId, OrderQuantity>,
+}
+
+// replays a flow of timed requests into the engine and models the fills the strategy orders would have got: on
+// arrival an order takes what crosses in the book at that time (walking the levels like the engine would), then what
+// is left rests behind the queue at its price and fills on the trades reaching it
+pub struct Simulator {
+    engine: Engine,
+    market_data: Receiver<MarketDataEvent>,
+    config: SimulationConfig,
+    outcomes: Vec<StrategyOutcome>,
+    pending: Vec<usize>, // not in the book yet, by submission
+    working: Vec<Working>,
+    requests: u64,
+    rejected: u64,
+}
+
+impl Simulator {
+    pub fn new(mut engine: Engine, config: SimulationConfig) -> Self {
+        let market_data = engine.subscribe(Granularity::Order);
+        Self {
+            engine,
+            market_data,
+            config,
+            outcomes: vec![],
+            pending: vec![],
+            working: vec![],
+            requests: 0,
+            rejected: 0,
+        }
+    }
+
+    #[inline]
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    // orders submitted before the time of the flow already replayed are decided on the next request
+    pub fn submit(&mut self, order: StrategyOrder) {
+        self.pending.push(self.outcomes.len());
+        self.outcomes.push(StrategyOutcome {
+            order,
+            decision_mid: None,
+            arrived_at: None,
+            queue_ahead: OrderQuantity::ZERO,
+            fills: vec![],
+            cancelled_at: None,
+        });
+    }
+
+    // the strategy orders due by the time of the request act on the book as it was before it, rejected requests
+    // are counted and the flow goes on
+    pub fn step(&mut self, request: TimedRequest) -> Result<(), EngineError> {
+        self.advance(request.at);
+        self.engine.tick(request.at)?;
+        self.requests += 1;
+        if self.engine.process(request.order_request).is_err() {
+            self.rejected += 1;
+        }
+        self.observe(request.at);
+        Ok(())
+    }
+
+    pub fn run(mut self, requests: impl IntoIterator<Item = TimedRequest>) -> Result<FillReport, EngineError> {
+        for request in requests {
+            self.step(request)?;
+        }
+        Ok(self.report())
+    }
+
+    // the strategy orders still working keep what they got so far
+    pub fn report(self) -> FillReport {
+        FillReport::new(self.requests, self.rejected, self.outcomes)
+    }
+
+    fn advance(&mut self, now: Timestamp) {
+        let mid_price = self.engine.orderbook().mid_price();
+        for index in &self.pending {
+            let outcome = &mut self.outcomes[*index];
+            if outcome.order.submit_at <= now && outcome.decision_mid.is_none() {
+                outcome.decision_mid = mid_price;
+            }
+        }
+
+        let (due, pending) = self
+            .pending
+            .iter()
+            .partition(|index| self.outcomes[**index].order.submit_at + self.config.latency <= now);
+        self.pending = pending;
+        for index in due {
+            self.arrive(index, now);
+        }
+
+        let outcomes = &mut self.outcomes;
+        self.working.retain(|working| {
+            let outcome = &mut outcomes[working.index];
+            let expired = match (outcome.arrived_at, outcome.order.time_in_book) {
+                (Some(arrived_at), Some(time_in_book)) => arrived_at + time_in_book <= now,
+                _ => false,
+            };
+            if expired {
+                outcome.cancelled_at = Some(now);
+            }
+            !expired
+        });
+    }
+
+    fn arrive(&mut self, index: usize, now: Timestamp) {
+        let book = self.engine.orderbook();
+        let outcome = &mut self.outcomes[index];
+        outcome.arrived_at = Some(now);
+
+        let mut remaining = outcome.order.quantity;
+        for resting in book.iter_side(!outcome.order.side) {
+            let Some(price) = resting.limit_price().filter(|price| outcome.order.crosses(*price)) else {
+                break;
+            };
+            let quantity = remaining.min(resting.remaining());
+            outcome.fills.push(StrategyFill {
+                at: now,
+                price,
+                quantity,
+                maker: false,
+            });
+            remaining -= quantity;
+            if remaining.is_zero() {
+                return;
+            }
+        }
+
+        let Some(limit_price) = outcome.order.limit_price else {
+            outcome.cancelled_at = Some(now);
+            return;
+        };
+        let ahead: IndexMap<OrderId, OrderQuantity> = book
+            .iter_side(outcome.order.side)
+            .filter(|resting| resting.limit_price() == Some(limit_price))
+            .map(|resting| (resting.id(), resting.remaining()))
+            .collect();
+        outcome.queue_ahead = ahead.values().sum();
+        self.working.push(Working { index, ahead });
+    }
+
+    fn observe(&mut self, now: Timestamp) {
+        for event in self.market_data.try_iter() {
+            for working in &mut self.working {
+                let outcome = &mut self.outcomes[working.index];
+                match event.event {
+                    BookEvent::Trade {
+                        maker,
+                        side,
+                        price,
+                        quantity,
+                        ..
+                    } if side != outcome.order.side => {
+                        let limit_price = outcome.order.limit_price.unwrap_or(price);
+                        // a trade at a better price than the order does not reach it
+                        if price != limit_price && !outcome.order.crosses(price) {
+                            continue;
+                        }
+                        // the orders ahead at its price trade first, through its price it was taken in any case
+                        if price == limit_price {
+                            if let Some(ahead) = working.ahead.get_mut(&maker) {
+                                *ahead = ahead.checked_sub(quantity).unwrap_or_default();
+                                continue;
+                            }
+                        }
+                        let quantity = quantity.min(outcome.remaining());
+                        if !quantity.is_zero() {
+                            outcome.fills.push(StrategyFill {
+                                at: now,
+                                price: limit_price,
+                                quantity,
+                                maker: true,
+                            });
+                        }
+                    }
+                    BookEvent::Modify {
+                        order_id: Some(order_id),
+                        quantity,
+                        ..
+                    } => {
+                        if let Some(ahead) = working.ahead.get_mut(&order_id) {
+                            *ahead = quantity;
+                        }
+                    }
+                    BookEvent::Delete {
+                        order_id: Some(order_id),
+                        ..
+                    } => {
+                        working.ahead.swap_remove(&order_id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let outcomes = &self.outcomes;
+        self.working
+            .retain(|working| !outcomes[working.index].remaining().is_zero());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use compact_str::format_compact;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::util::{self, generate, DEFAULT_SYMBOL};
+
+    fn create(at: Timestamp, order_id: u64, side: OrderSide, limit_price: u32, quantity: u32) -> TimedRequest {
+        TimedRequest {
+            at,
+            order_request: util::create(order_id, side)
+                .account_id(&format_compact!("{order_id}"))
+                .limit_price(Some(limit_price.into()))
+                .quantity(quantity)
+                .build(),
+        }
+    }
+
+    fn strategy(submit_at: Timestamp, side: OrderSide, limit_price: Option<u32>, quantity: u32) -> StrategyOrder {
+        StrategyOrder {
+            submit_at,
+            side,
+            limit_price: limit_price.map(OrderPrice::from),
+            quantity: quantity.into(),
+            time_in_book: None,
+        }
+    }
+
+    #[rstest]
+    fn fill_behind_the_queue() {
+        let mut simulator = Simulator::new(Engine::new(DEFAULT_SYMBOL), SimulationConfig::default());
+        simulator.submit(strategy(5, OrderSide::Bid, Some(14), 2));
+        let report = simulator
+            .run([
+                create(0, 1, OrderSide::Bid, 14, 2),
+                create(10, 2, OrderSide::Bid, 14, 3), // behind the strategy order
+                create(20, 3, OrderSide::Ask, 14, 1),
+                create(30, 4, OrderSide::Ask, 14, 3),
+            ])
+            .unwrap();
+
+        // 1 trades first, then the strategy order takes what would have gone to 2
+        let outcome = &report.outcomes[0];
+        assert_eq!(outcome.queue_ahead, 2.into());
+        assert_eq!(
+            outcome.fills,
+            vec![StrategyFill {
+                at: 30,
+                price: 14.into(),
+                quantity: 2.into(),
+                maker: true,
+            }]
+        );
+        assert_eq!(outcome.time_to_fill(), Some(20));
+        assert_eq!((report.requests, report.completed), (4, 1));
+        assert_eq!(report.fill_probability(), Decimal::ONE);
+        assert_eq!(report.maker_volume, 2.into());
+    }
+
+    #[rstest]
+    #[case::no_latency(0, "3", 2)]
+    #[case::book_moved_away(10, "3.5", 1)]
+    fn slippage_of_the_latency(#[case] latency: Timestamp, #[case] slippage: Decimal, #[case] levels: usize) {
+        let mut simulator = Simulator::new(Engine::new(DEFAULT_SYMBOL), SimulationConfig { latency });
+        simulator.submit(strategy(5, OrderSide::Bid, None, 2));
+        let report = simulator
+            .run([
+                create(0, 1, OrderSide::Ask, 15, 1),
+                create(1, 2, OrderSide::Ask, 16, 2),
+                create(2, 3, OrderSide::Bid, 10, 1),
+                create(10, 4, OrderSide::Bid, 15, 1), // takes the best ask
+                create(20, 5, OrderSide::Bid, 9, 1),
+            ])
+            .unwrap();
+
+        let outcome = &report.outcomes[0];
+        assert_eq!(outcome.decision_mid, Some(OrderPrice::new(125, 1)));
+        assert_eq!(outcome.fills.len(), levels);
+        assert_eq!(report.taker_volume, 2.into());
+        assert_eq!(report.slippage(), Some(slippage));
+    }
+
+    #[rstest]
+    fn fill_on_a_trade_through_then_cancel() {
+        let mut simulator = Simulator::new(Engine::new(DEFAULT_SYMBOL), SimulationConfig::default());
+        simulator.submit(StrategyOrder {
+            time_in_book: Some(10),
+            ..strategy(0, OrderSide::Ask, Some(15), 2)
+        });
+        simulator.submit(strategy(100, OrderSide::Ask, Some(15), 1)); // the flow ends before
+        let report = simulator
+            .run([
+                create(0, 1, OrderSide::Ask, 16, 1),
+                create(5, 2, OrderSide::Bid, 16, 1),
+                create(20, 3, OrderSide::Bid, 16, 1),
+            ])
+            .unwrap();
+
+        let outcome = &report.outcomes[0];
+        assert_eq!(outcome.fills[0].price, 15.into());
+        assert_eq!(outcome.remaining(), 1.into());
+        assert_eq!(outcome.cancelled_at, Some(20));
+        assert_eq!((report.orders, report.arrived, report.filled), (2, 1, 1));
+        assert_eq!(report.fill_rate(), Decimal::ONE / Decimal::from(3));
+    }
+
+    #[rstest]
+    fn run_a_synthetic_flow() {
+        let mut simulator = Simulator::new(Engine::new(DEFAULT_SYMBOL), SimulationConfig { latency: 3 });
+        for i in 0..10 {
+            simulator.submit(strategy(i * 100, OrderSide::Bid, Some(5_000), 10));
+        }
+        let report = simulator.run(timed(generate(1..=1_000), 0, 1)).unwrap();
+
+        assert_eq!((report.requests, report.orders, report.arrived), (1_000, 10, 10));
+        assert!(report.filled <= report.arrived);
+        assert!(report.fill_rate() <= Decimal::ONE);
+    }
+}
@@ -0,0 +1,414 @@
+use std::{collections::VecDeque, fmt::Display, str::FromStr};
+
+use compact_str::{format_compact, CompactString};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::TimedRequest;
+use crate::{
+    clock::Timestamp,
+    order::{util::DEFAULT_SYMBOL, OrderPrice, OrderQuantity, OrderRequest, OrderSide},
+};
+
+// sizes of the orders, rounded down to the lot size (one lot at least)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "UPPERCASE", tag = "kind")]
+pub enum SizeDistribution {
+    Uniform { min: OrderQuantity, max: OrderQuantity },
+    // most orders are small and a few are large, like in real flow
+    LogNormal { median: OrderQuantity, sigma: f64 },
+}
+
+// spells of requests arriving faster than usual, e.g. around news or a large order being worked
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Burstiness {
+    pub probability: f64, // of a burst starting after a request
+    pub length: u32,      // requests in a burst
+    pub speedup: u32,     // how many times faster they come during a burst
+}
+
+// prices and times of the flow are driven by the random walk of the mid price; limit orders rest around it (the closer
+// the more likely), market orders take what is there
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SimConfig {
+    pub seed: u64, // the same flow for the same seed
+    pub start: Timestamp,
+    pub mid_price: OrderPrice, // at the start
+    pub tick_size: OrderPrice,
+    pub volatility: f64, // standard deviation of the relative move of the mid price per request
+    pub drift: f64,      // mean of the relative move of the mid price per request, e.g. negative for a crash
+    pub depth: f64,      // mean distance of the limit orders to the mid price, in ticks
+    pub lot_size: OrderQuantity,
+    pub sizes: SizeDistribution,
+    pub cancel_ratio: f64,
+    pub cancel_window: usize, // cancels target one of the last orders created
+    pub market_ratio: f64,    // of the orders created
+    pub bid_ratio: f64,
+    pub accounts: u64,
+    pub mean_interval: Timestamp, // between two requests, exponentially distributed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burstiness: Option<Burstiness>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            start: 0,
+            mid_price: 2_000.into(),
+            tick_size: OrderPrice::new(1, 2),
+            volatility: 0.000_2,
+            drift: 0.0,
+            depth: 5.0,
+            lot_size: OrderQuantity::new(1, 2),
+            sizes: SizeDistribution::LogNormal {
+                median: 1.into(),
+                sigma: 1.0,
+            },
+            cancel_ratio: 0.3,
+            cancel_window: 1_000,
+            market_ratio: 0.1,
+            bid_ratio: 0.5,
+            accounts: 9,
+            mean_interval: 10,
+            burstiness: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketRegime {
+    Calm,
+    Trending,
+    Volatile,
+    Bursty,
+}
+
+impl MarketRegime {
+    pub const ALL: [MarketRegime; 4] = [
+        MarketRegime::Calm,
+        MarketRegime::Trending,
+        MarketRegime::Volatile,
+        MarketRegime::Bursty,
+    ];
+
+    pub fn config(&self, seed: u64) -> SimConfig {
+        let default = SimConfig {
+            seed,
+            ..Default::default()
+        };
+        match self {
+            // a tight book refilled as fast as it trades, the mid price hardly moves
+            MarketRegime::Calm => SimConfig {
+                volatility: 0.000_05,
+                depth: 3.0,
+                market_ratio: 0.05,
+                ..default
+            },
+            // buyers keep coming and the mid price climbs
+            MarketRegime::Trending => SimConfig {
+                drift: 0.000_05,
+                bid_ratio: 0.6,
+                market_ratio: 0.2,
+                ..default
+            },
+            // large moves, a book spread thin and more aggressive flow with larger orders
+            MarketRegime::Volatile => SimConfig {
+                volatility: 0.002,
+                depth: 20.0,
+                market_ratio: 0.25,
+                sizes: SizeDistribution::LogNormal {
+                    median: 2.into(),
+                    sigma: 1.5,
+                },
+                ..default
+            },
+            // quiet most of the time then flurries of requests, with many cancels
+            MarketRegime::Bursty => SimConfig {
+                cancel_ratio: 0.45,
+                burstiness: Some(Burstiness {
+                    probability: 0.01,
+                    length: 200,
+                    speedup: 50,
+                }),
+                ..default
+            },
+        }
+    }
+
+    pub fn generate(&self, seed: u64) -> FlowGenerator {
+        FlowGenerator::new(self.config(seed))
+    }
+}
+
+impl Display for MarketRegime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarketRegime::Calm => write!(f, "calm"),
+            MarketRegime::Trending => write!(f, "trending"),
+            MarketRegime::Volatile => write!(f, "volatile"),
+            MarketRegime::Bursty => write!(f, "bursty"),
+        }
+    }
+}
+
+impl FromStr for MarketRegime {
+    type Err = FlowError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MarketRegime::ALL
+            .into_iter()
+            .find(|regime| regime.to_string() == s)
+            .ok_or_else(|| FlowError::UnknownRegime(s.into()))
+    }
+}
+
+// an endless flow of timed requests (take what is needed), ids start at 1
+pub struct FlowGenerator {
+    config: SimConfig,
+    rng: StdRng,
+    mid_price: f64,
+    now: Timestamp,
+    next_id: u64,
+    live: VecDeque<(u64, CompactString)>, // the last orders created, with their account
+    burst: u32,                           // requests left in the current burst
+}
+
+impl FlowGenerator {
+    pub fn new(config: SimConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            mid_price: config.mid_price.value().to_f64().unwrap_or_default(),
+            now: config.start,
+            next_id: 1,
+            live: VecDeque::new(),
+            burst: 0,
+            config,
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    // on the ticks
+    #[inline]
+    pub fn mid_price(&self) -> OrderPrice {
+        self.round_to_tick(self.mid_price)
+    }
+
+    #[inline]
+    pub fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    // Box-Muller, one of the pair is enough
+    fn normal(&mut self) -> f64 {
+        let u: f64 = 1.0 - self.rng.gen::<f64>();
+        let v: f64 = self.rng.gen();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    fn exponential(&mut self, mean: f64) -> f64 {
+        -mean * (1.0 - self.rng.gen::<f64>()).ln()
+    }
+
+    fn round_to_tick(&self, price: f64) -> OrderPrice {
+        let tick_size = self.config.tick_size.value().to_f64().unwrap_or(1.0);
+        let ticks = (price / tick_size).round().max(1.0) as i64;
+        self.config.tick_size * Decimal::from(ticks)
+    }
+
+    fn advance(&mut self) {
+        self.mid_price *= 1.0 + self.config.drift + self.config.volatility * self.normal();
+        // never below a tick
+        self.mid_price = self
+            .mid_price
+            .max(self.config.tick_size.value().to_f64().unwrap_or_default());
+
+        let mut mean_interval = self.config.mean_interval as f64;
+        if let Some(burstiness) = self.config.burstiness {
+            if self.burst == 0 && self.rng.gen_bool(burstiness.probability) {
+                self.burst = burstiness.length;
+            }
+            if self.burst > 0 {
+                self.burst -= 1;
+                mean_interval /= burstiness.speedup.max(1) as f64;
+            }
+        }
+        self.now += self.exponential(mean_interval).round() as Timestamp;
+    }
+
+    fn size(&mut self) -> OrderQuantity {
+        let size = match self.config.sizes {
+            SizeDistribution::Uniform { min, max } => {
+                let (min, max) = (min.value().to_f64(), max.value().to_f64());
+                let (min, max) = (min.unwrap_or_default(), max.unwrap_or_default());
+                min + (max - min).max(0.0) * self.rng.gen::<f64>()
+            }
+            SizeDistribution::LogNormal { median, sigma } => {
+                median.value().to_f64().unwrap_or_default() * (sigma * self.normal()).exp()
+            }
+        };
+        let lot_size = self.config.lot_size.value().to_f64().unwrap_or(1.0);
+        let lots = (size / lot_size).floor().max(1.0) as i64;
+        self.config.lot_size * Decimal::from(lots)
+    }
+
+    fn create(&mut self) -> OrderRequest {
+        let order_id = self.next_id;
+        self.next_id += 1;
+        let account_id = format_compact!("{}", self.rng.gen_range(1..=self.config.accounts.max(1)));
+        let side = match self.rng.gen_bool(self.config.bid_ratio) {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        let limit_price = (!self.rng.gen_bool(self.config.market_ratio)).then(|| {
+            let tick_size = self.config.tick_size.value().to_f64().unwrap_or(1.0);
+            // half a tick at least, so that the bids and the asks do not meet at the mid price
+            let distance = (0.5 + self.exponential(self.config.depth)) * tick_size;
+            match side {
+                OrderSide::Bid => self.round_to_tick(self.mid_price - distance),
+                OrderSide::Ask => self.round_to_tick(self.mid_price + distance),
+            }
+        });
+        if limit_price.is_some() {
+            self.live.push_back((order_id, account_id.clone()));
+            if self.live.len() > self.config.cancel_window {
+                self.live.pop_front();
+            }
+        }
+
+        OrderRequest::Create {
+            account_id,
+            order_id,
+            pair: DEFAULT_SYMBOL,
+            side,
+            limit_price,
+            quantity: self.size(),
+            time_in_force: None,
+            short_sell: false,
+            priority_fee: Decimal::ZERO,
+            client_order_id: None,
+            price_protection: None,
+            reduce_only: false,
+        }
+    }
+}
+
+impl Iterator for FlowGenerator {
+    type Item = TimedRequest;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.advance();
+        let order_request = match self.live.is_empty() || !self.rng.gen_bool(self.config.cancel_ratio) {
+            true => self.create(),
+            false => {
+                let index = self.rng.gen_range(0..self.live.len());
+                let (order_id, account_id) = self.live.swap_remove_back(index).unwrap();
+                OrderRequest::Cancel { account_id, order_id }
+            }
+        };
+        Some(TimedRequest {
+            at: self.now,
+            order_request,
+        })
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FlowError {
+    #[error("unknown market regime! {0}")]
+    UnknownRegime(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+    use rstest::rstest;
+
+    use super::*;
+
+    const SAMPLES: usize = 10_000;
+
+    fn intervals(regime: MarketRegime) -> Vec<Timestamp> {
+        let requests: Vec<TimedRequest> = regime.generate(1).take(SAMPLES).collect();
+        requests.windows(2).map(|pair| pair[1].at - pair[0].at).collect()
+    }
+
+    #[rstest]
+    fn round_trip_names() {
+        for regime in MarketRegime::ALL {
+            assert_eq!(regime.to_string().parse::<MarketRegime>(), Ok(regime));
+        }
+        assert_eq!(
+            "unknown".parse::<MarketRegime>(),
+            Err(FlowError::UnknownRegime("unknown".into()))
+        );
+    }
+
+    #[rstest]
+    fn reproduce_a_seeded_flow() {
+        let flow = |seed| {
+            MarketRegime::Volatile
+                .generate(seed)
+                .take(100)
+                .collect::<Vec<TimedRequest>>()
+        };
+        assert_eq!(flow(7), flow(7));
+        assert_ne!(flow(7), flow(8));
+    }
+
+    #[rstest]
+    fn orders_follow_the_config() {
+        let config = SimConfig::default();
+        let mut owners = IndexMap::new();
+        let mut at = 0;
+        for request in FlowGenerator::new(config.clone()).take(SAMPLES) {
+            assert!(request.at >= at);
+            at = request.at;
+            match request.order_request {
+                OrderRequest::Create {
+                    account_id,
+                    order_id,
+                    limit_price,
+                    quantity,
+                    ..
+                } => {
+                    assert!(quantity >= config.lot_size);
+                    assert!((quantity / config.lot_size).is_integer());
+                    if let Some(limit_price) = limit_price {
+                        assert!((limit_price / config.tick_size).is_integer());
+                    }
+                    owners.insert(order_id, account_id);
+                }
+                // the cancels target orders created before, in the name of their account
+                OrderRequest::Cancel { account_id, order_id } => {
+                    assert_eq!(owners.get(&order_id), Some(&account_id))
+                }
+                OrderRequest::CancelByClientId { .. }
+                | OrderRequest::CancelAll { .. }
+                | OrderRequest::MassQuote { .. } => unreachable!(),
+            }
+        }
+    }
+
+    #[rstest]
+    fn trending_mid_price_climbs() {
+        let mut generator = MarketRegime::Trending.generate(1);
+        let start = generator.mid_price();
+        generator.nth(SAMPLES);
+        assert!(generator.mid_price() > start);
+    }
+
+    #[rstest]
+    fn bursty_flow_comes_in_flurries() {
+        let calm = intervals(MarketRegime::Calm);
+        let bursty = intervals(MarketRegime::Bursty);
+        let quick = |intervals: &[Timestamp]| intervals.iter().filter(|interval| **interval == 0).count();
+        assert!(quick(&bursty) > 2 * quick(&calm));
+    }
+}
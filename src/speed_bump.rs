@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+
+use crate::{clock::Timestamp, order::OrderRequest};
+
+// asymmetric speed bump: only the orders taking liquidity are delayed, so that the resting orders get a head start to
+// cancel or reprice
+#[derive(Clone, Debug)]
+pub struct SpeedBump {
+    delay: Timestamp,
+    delayed: VecDeque<(Timestamp, OrderRequest)>, // by release time, the delay being fixed
+}
+
+impl SpeedBump {
+    #[inline]
+    pub fn new(delay: Timestamp) -> Self {
+        Self {
+            delay,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub fn hold(&mut self, now: Timestamp, order_request: OrderRequest) {
+        self.delayed.push_back((now + self.delay, order_request));
+    }
+
+    // the requests whose delay is over, in arrival order
+    pub fn release(&mut self, now: Timestamp) -> Vec<OrderRequest> {
+        let due = self
+            .delayed
+            .iter()
+            .take_while(|(release_at, _)| *release_at <= now)
+            .count();
+        self.delayed
+            .drain(..due)
+            .map(|(_, order_request)| order_request)
+            .collect()
+    }
+
+    #[inline]
+    pub fn delayed(&self) -> impl Iterator<Item = &OrderRequest> {
+        self.delayed.iter().map(|(_, order_request)| order_request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::order::util;
+
+    #[rstest]
+    fn release_after_the_delay() {
+        let mut speed_bump = SpeedBump::new(3);
+        speed_bump.hold(0, util::cancel(1));
+        speed_bump.hold(2, util::cancel(2));
+        assert!(speed_bump.release(2).is_empty());
+        assert_eq!(speed_bump.release(4), vec![util::cancel(1)]);
+        assert_eq!(speed_bump.delayed().count(), 1);
+        assert_eq!(speed_bump.release(5), vec![util::cancel(2)]);
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use compact_str::CompactString;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    clock::Timestamp,
+    trade::{Trade, TradeId},
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS trades (
+        sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+        trade_id INTEGER NOT NULL,
+        pair TEXT NOT NULL,
+        executed_at INTEGER NOT NULL,
+        maker TEXT NOT NULL,
+        taker TEXT NOT NULL,
+        busted INTEGER NOT NULL DEFAULT 0,
+        trade TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS trades_by_trade_id ON trades (trade_id);
+    CREATE INDEX IF NOT EXISTS trades_by_pair ON trades (pair, executed_at);
+    CREATE INDEX IF NOT EXISTS trades_by_maker ON trades (maker, executed_at);
+    CREATE INDEX IF NOT EXISTS trades_by_taker ON trades (taker, executed_at);
+    CREATE INDEX IF NOT EXISTS trades_by_time ON trades (executed_at);
+";
+
+// a trade as persisted, with what the book does not know of it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredTrade {
+    pub pair: CompactString,
+    pub executed_at: Timestamp,
+    pub maker: CompactString, // accounts of both sides
+    pub taker: CompactString,
+    pub trade: Trade,
+}
+
+// every trade of the engine in an embedded SQLite database, indexed by pair, account and time; the trade itself is kept
+// as JSON like in the journal. Trade ids restart with the process so they are not unique across restarts, trades come
+// back in the order they were stored
+pub struct TradeStore {
+    connection: Connection,
+}
+
+impl TradeStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    // gone with the store, e.g. for tests
+    pub fn in_memory() -> Result<Self, StorageError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, StorageError> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    // all or nothing
+    pub fn insert(&mut self, trades: &[StoredTrade]) -> Result<(), StorageError> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO trades (trade_id, pair, executed_at, maker, taker, busted, trade)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for stored in trades {
+                statement.execute(params![
+                    u64::from(stored.trade.id()) as i64,
+                    stored.pair.as_str(),
+                    stored.executed_at as i64,
+                    stored.maker.as_str(),
+                    stored.taker.as_str(),
+                    stored.trade.is_busted(),
+                    serde_json::to_string(&stored.trade)?,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    // the last trade stored with the id, returns whether there was one
+    pub fn mark_busted(&mut self, trade_id: TradeId) -> Result<bool, StorageError> {
+        let updated = self.connection.execute(
+            "UPDATE trades SET busted = 1
+             WHERE sequence = (SELECT MAX(sequence) FROM trades WHERE trade_id = ?1)",
+            params![u64::from(trade_id) as i64],
+        )?;
+        Ok(updated > 0)
+    }
+
+    // the account on either side, all pairs
+    pub fn trades_by_account(&self, account_id: &str) -> Result<Vec<StoredTrade>, StorageError> {
+        self.query(
+            "SELECT pair, executed_at, maker, taker, busted, trade FROM trades
+             WHERE maker = ?1 OR taker = ?1 ORDER BY sequence",
+            params![account_id],
+        )
+    }
+
+    // executed from `from` (included) to `to` (excluded)
+    pub fn trades_in_range(
+        &self,
+        pair: &str,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<StoredTrade>, StorageError> {
+        self.query(
+            "SELECT pair, executed_at, maker, taker, busted, trade FROM trades
+             WHERE pair = ?1 AND executed_at >= ?2 AND executed_at < ?3 ORDER BY sequence",
+            params![pair, from as i64, to.min(i64::MAX as u64) as i64],
+        )
+    }
+
+    pub fn count(&self) -> Result<u64, StorageError> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM trades", [], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<StoredTrade>, StorageError> {
+        let mut statement = self.connection.prepare_cached(sql)?;
+        let rows = statement.query_map(params, |row| Ok((from_row(row)?, row.get::<_, String>(5)?)))?;
+        rows.map(|row| {
+            let ((pair, executed_at, maker, taker, busted), trade) = row?;
+            let mut trade: Trade = serde_json::from_str(&trade)?;
+            if busted {
+                trade.bust();
+            }
+            Ok(StoredTrade {
+                pair,
+                executed_at,
+                maker,
+                taker,
+                trade,
+            })
+        })
+        .collect()
+    }
+}
+
+type Columns = (CompactString, Timestamp, CompactString, CompactString, bool);
+
+fn from_row(row: &Row) -> rusqlite::Result<Columns> {
+    Ok((
+        row.get::<_, String>(0)?.into(),
+        row.get::<_, i64>(1)? as Timestamp,
+        row.get::<_, String>(2)?.into(),
+        row.get::<_, String>(3)?.into(),
+        row.get(4)?,
+    ))
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("storage database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("storage format error: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::{
+        clock::{HOUR, MINUTE},
+        order::{util::DEFAULT_PAIR, Order, OrderSide},
+    };
+
+    fn stored(executed_at: Timestamp, maker: &str, taker: &str) -> StoredTrade {
+        let mut taker_order = Order::limit_order(2.into(), OrderSide::Bid, 10.into(), 15.into());
+        let mut maker_order = Order::limit_order(1.into(), OrderSide::Ask, 10.into(), 15.into());
+        StoredTrade {
+            pair: DEFAULT_PAIR.into(),
+            executed_at,
+            maker: maker.into(),
+            taker: taker.into(),
+            trade: Trade::new(&mut taker_order, &mut maker_order, 5.into()).unwrap(),
+        }
+    }
+
+    #[rstest]
+    fn query_by_account_and_time() {
+        let mut store = TradeStore::in_memory().unwrap();
+        store
+            .insert(&[stored(HOUR, "1", "2"), stored(HOUR + MINUTE, "2", "3")])
+            .unwrap();
+        store.insert(&[stored(2 * HOUR, "3", "1")]).unwrap();
+        assert_eq!(store.count().unwrap(), 3);
+
+        let times = |trades: Vec<StoredTrade>| trades.iter().map(|stored| stored.executed_at).collect::<Vec<_>>();
+        assert_eq!(times(store.trades_by_account("1").unwrap()), vec![HOUR, 2 * HOUR]);
+        assert_eq!(
+            times(store.trades_by_account("3").unwrap()),
+            vec![HOUR + MINUTE, 2 * HOUR]
+        );
+        assert_eq!(
+            times(store.trades_in_range(DEFAULT_PAIR, HOUR + 1, 2 * HOUR).unwrap()),
+            vec![HOUR + MINUTE]
+        );
+        assert_eq!(store.trades_in_range(DEFAULT_PAIR, 0, Timestamp::MAX).unwrap().len(), 3);
+    }
+
+    #[rstest]
+    fn bust_the_last_trade_with_the_id() {
+        let mut store = TradeStore::in_memory().unwrap();
+        let first = stored(HOUR, "1", "2");
+        let trade_id = first.trade.id();
+        // the same id again after a restart
+        let mut second = stored(2 * HOUR, "1", "2");
+        second.trade = first.trade;
+        store.insert(&[first, second]).unwrap();
+
+        assert!(store.mark_busted(trade_id).unwrap());
+        let busted: Vec<bool> = store
+            .trades_by_account("1")
+            .unwrap()
+            .iter()
+            .map(|stored| stored.trade.is_busted())
+            .collect();
+        assert_eq!(busted, vec![false, true]);
+        assert!(!store.mark_busted(u64::MAX.into()).unwrap());
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+use rust_decimal::Decimal;
+
 use crate::{
     order::{OrderPrice, OrderSide},
     orderbook::Orderbook,
@@ -11,7 +13,7 @@ pub struct Summary {
 }
 
 impl Summary {
-    fn spread(&self) -> Option<OrderPrice> {
+    fn spread(&self) -> Option<Decimal> {
         match (self.best_bid, self.best_ask) {
             (Some(bid_price), Some(ask_price)) => Some(ask_price - bid_price),
             _ => None,
@@ -0,0 +1,302 @@
+use std::{
+    borrow::Borrow,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use compact_str::{format_compact, CompactString};
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const MAX_ASSET_LEN: usize = 32;
+
+// an asset code: ASCII letters and digits in upper case (parsed in any case), with dashes, dots or underscores in
+// between for instrument codes (e.g. ETH-1760000000000-2000-C)
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "CompactString", into = "CompactString")]
+pub struct Asset(CompactString);
+
+impl Asset {
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Asset {
+    type Err = SymbolError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let valid = code.len() <= MAX_ASSET_LEN
+            && code
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_'))
+            && code.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && code.ends_with(|c: char| c.is_ascii_alphanumeric());
+        if !valid {
+            return Err(SymbolError::InvalidAsset(code.into()));
+        }
+        Ok(Self(code.to_ascii_uppercase().into()))
+    }
+}
+
+impl TryFrom<CompactString> for Asset {
+    type Error = SymbolError;
+
+    fn try_from(code: CompactString) -> Result<Self, Self::Error> {
+        code.parse()
+    }
+}
+
+impl From<Asset> for CompactString {
+    fn from(asset: Asset) -> Self {
+        asset.0
+    }
+}
+
+impl Display for Asset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl PartialEq<str> for Asset {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Asset {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+// a pair of assets, BASE/QUOTE on the wire (e.g. ETH/USDT): parsed once where it comes in, the engines, books and
+// shards are keyed by it. Compares and hashes like its name so that maps keyed by symbol can be looked up by name
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "CompactString", into = "CompactString")]
+pub struct Symbol {
+    base: Asset,
+    quote: Asset,
+    name: CompactString,
+}
+
+impl Symbol {
+    #[inline]
+    pub fn new(base: Asset, quote: Asset) -> Self {
+        let name = format_compact!("{base}/{quote}");
+        Self { base, quote, name }
+    }
+
+    // for constants, nothing is checked
+    pub(crate) const fn new_inline(base: &str, quote: &str, name: &str) -> Self {
+        Self {
+            base: Asset(CompactString::new_inline(base)),
+            quote: Asset(CompactString::new_inline(quote)),
+            name: CompactString::new_inline(name),
+        }
+    }
+
+    #[inline]
+    pub fn base(&self) -> &Asset {
+        &self.base
+    }
+
+    #[inline]
+    pub fn quote(&self) -> &Asset {
+        &self.quote
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = SymbolError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let (base, quote) = name
+            .split_once('/')
+            .ok_or_else(|| SymbolError::InvalidSymbol(name.into()))?;
+        let (base, quote): (Asset, Asset) = (base.parse()?, quote.parse()?);
+        if base == quote {
+            return Err(SymbolError::InvalidSymbol(name.into()));
+        }
+        Ok(Self::new(base, quote))
+    }
+}
+
+impl TryFrom<CompactString> for Symbol {
+    type Error = SymbolError;
+
+    fn try_from(name: CompactString) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<Symbol> for CompactString {
+    fn from(symbol: Symbol) -> Self {
+        symbol.name
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.name == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.name == *other
+    }
+}
+
+// the symbols listed on the exchange, a name is resolved to one of them or refused
+#[derive(Clone, Debug, Default)]
+pub struct SymbolRegistry {
+    symbols: IndexSet<Symbol>,
+}
+
+impl SymbolRegistry {
+    // returns the symbol as listed
+    pub fn register(&mut self, name: &str) -> Result<Symbol, SymbolError> {
+        let symbol: Symbol = name.parse()?;
+        if !self.symbols.insert(symbol.clone()) {
+            return Err(SymbolError::SymbolDuplicated(symbol.name));
+        }
+        Ok(symbol)
+    }
+
+    // the name as listed, in any case
+    pub fn resolve(&self, name: &str) -> Result<&Symbol, SymbolError> {
+        let symbol: Symbol = name.parse()?;
+        self.symbols
+            .get(&symbol)
+            .ok_or_else(|| SymbolError::UnknownSymbol(name.into()))
+    }
+
+    #[inline]
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    // base and quote, in order of listing
+    pub fn assets(&self) -> IndexSet<&Asset> {
+        self.symbols
+            .iter()
+            .flat_map(|symbol| [symbol.base(), symbol.quote()])
+            .collect()
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SymbolError {
+    #[error("invalid asset! {0}")]
+    InvalidAsset(CompactString),
+    #[error("invalid symbol, expected BASE/QUOTE! {0}")]
+    InvalidSymbol(CompactString),
+    #[error("unknown symbol! {0}")]
+    UnknownSymbol(CompactString),
+    #[error("symbol already registered! {0}")]
+    SymbolDuplicated(CompactString),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("ETH/USDT", Ok(("ETH", "USDT")))]
+    #[case("eth/usdt", Ok(("ETH", "USDT")))]
+    #[case("AAPL/USD", Ok(("AAPL", "USD")))]
+    #[case("ETHUSDT", Err(SymbolError::InvalidSymbol("ETHUSDT".into())))]
+    #[case("ETH/ETH", Err(SymbolError::InvalidSymbol("ETH/ETH".into())))]
+    #[case("ETH/", Err(SymbolError::InvalidAsset("".into())))]
+    #[case("ETH-1000-2000-C/USDT", Ok(("ETH-1000-2000-C", "USDT")))]
+    #[case("ETH/US D", Err(SymbolError::InvalidAsset("US D".into())))]
+    #[case("-ETH/USD", Err(SymbolError::InvalidAsset("-ETH".into())))]
+    #[case("ETH/USDT/BTC", Err(SymbolError::InvalidAsset("USDT/BTC".into())))]
+    fn parse_symbols(#[case] name: &str, #[case] expected: Result<(&str, &str), SymbolError>) {
+        let parsed = name.parse::<Symbol>();
+        match expected {
+            Ok((base, quote)) => {
+                let symbol = parsed.unwrap();
+                assert_eq!((symbol.base().as_str(), symbol.quote().as_str()), (base, quote));
+                assert_eq!(symbol.to_string(), format!("{base}/{quote}"));
+            }
+            Err(error) => assert_eq!(parsed.unwrap_err(), error),
+        }
+    }
+
+    #[rstest]
+    fn round_trip_as_a_string() {
+        let symbol: Symbol = serde_json::from_str("\"btc/usd\"").unwrap();
+        assert_eq!(serde_json::to_string(&symbol).unwrap(), "\"BTC/USD\"");
+        assert!(serde_json::from_str::<Symbol>("\"BTCUSD\"").is_err());
+    }
+
+    #[rstest]
+    fn resolve_listed_symbols() {
+        let mut registry = SymbolRegistry::default();
+        registry.register("ETH/USDT").unwrap();
+        registry.register("BTC/USDT").unwrap();
+        assert_eq!(
+            registry.register("eth/usdt"),
+            Err(SymbolError::SymbolDuplicated("ETH/USDT".into()))
+        );
+
+        assert_eq!(registry.resolve("btc/usdt").unwrap(), "BTC/USDT");
+        assert_eq!(
+            registry.resolve("SOL/USDT"),
+            Err(SymbolError::UnknownSymbol("SOL/USDT".into()))
+        );
+        let assets: Vec<&str> = registry.assets().into_iter().map(Asset::as_str).collect();
+        assert_eq!(assets, vec!["ETH", "USDT", "BTC"]);
+    }
+}
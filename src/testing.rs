@@ -0,0 +1,326 @@
+use compact_str::format_compact;
+use indexmap::IndexMap;
+use proptest::{prelude::*, sample::Index};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    clock::{Clock, ManualClock, Timestamp},
+    engine::{Engine, EngineError, Tick},
+    order::{util::DEFAULT_SYMBOL, OrderId, OrderPrice, OrderQuantity, OrderRequest, OrderSide, TimeInForce},
+    orderbook::Orderbook,
+    trade::TradeId,
+};
+
+// the book as left by a match loop: not crossed, every level adds up to its orders, nothing negative
+pub fn check_book(orderbook: &Orderbook) -> Result<(), InvariantViolation> {
+    if let (Some(best_bid), Some(best_ask)) = (orderbook.best_bid(), orderbook.best_ask()) {
+        if best_bid >= best_ask {
+            return Err(InvariantViolation::CrossedBook { best_bid, best_ask });
+        }
+    }
+
+    let depth = orderbook.depth(usize::MAX);
+    for (side, levels) in [(OrderSide::Bid, depth.bids), (OrderSide::Ask, depth.asks)] {
+        let mut orders = orderbook.iter_side(side).peekable();
+        for (price, quantity, _) in levels {
+            let mut resting = OrderQuantity::ZERO;
+            while let Some(order) = orders.next_if(|order| order.limit_price() == Some(price)) {
+                if order.remaining() <= OrderQuantity::ZERO {
+                    return Err(InvariantViolation::NonPositiveRemaining(order.id()));
+                }
+                resting += order.remaining();
+            }
+            if resting != quantity {
+                return Err(InvariantViolation::LevelMismatch {
+                    price,
+                    quantity,
+                    resting,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// feeds an engine and checks it after every request: the book (see check_book), and the fills of every order against
+// its trades
+#[derive(Debug, Default)]
+pub struct InvariantChecker {
+    quantities: IndexMap<OrderId, OrderQuantity>, // as sent
+    traded: IndexMap<OrderId, OrderQuantity>,
+    trades_seen: usize,
+}
+
+impl InvariantChecker {
+    pub fn process(
+        &mut self,
+        engine: &mut Engine,
+        order_request: OrderRequest,
+    ) -> Result<Result<(), EngineError>, InvariantViolation> {
+        if let OrderRequest::Create { order_id, quantity, .. } = &order_request {
+            self.quantities.entry(OrderId::new(*order_id)).or_insert(*quantity);
+        }
+        let result = engine.process(order_request);
+        self.check(engine)?;
+        Ok(result)
+    }
+
+    pub fn check(&mut self, engine: &Engine) -> Result<(), InvariantViolation> {
+        // the book is only crossed while orders rest without matching
+        if !engine.session().is_call_phase() {
+            check_book(engine.orderbook())?;
+            check_book(engine.odd_lots())?;
+        }
+
+        let trades: Vec<_> = engine.orderbook().trades_from(self.trades_seen).copied().collect();
+        self.trades_seen += trades.len();
+        for trade in &trades {
+            if trade.quantity() <= OrderQuantity::ZERO {
+                return Err(InvariantViolation::EmptyTrade(trade.id()));
+            }
+            for order_id in [trade.taker(), trade.maker()] {
+                *self.traded.entry(order_id).or_default() += trade.quantity();
+            }
+        }
+
+        for (order_id, traded) in &self.traded {
+            let Some(quantity) = self.quantities.get(order_id) else {
+                continue;
+            };
+            // what is left in the book plus what traded is the quantity sent
+            let remaining = engine
+                .get_order(*order_id)
+                .map_or(OrderQuantity::ZERO, |order| order.remaining());
+            let overfilled = traded > quantity;
+            if overfilled || (engine.get_order(*order_id).is_some() && remaining + *traded != *quantity) {
+                return Err(InvariantViolation::FillMismatch {
+                    order_id: *order_id,
+                    quantity: *quantity,
+                    traded: *traded,
+                    remaining,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InvariantViolation {
+    #[error("crossed book (best_bid={}, best_ask={})", .best_bid, .best_ask)]
+    CrossedBook { best_bid: OrderPrice, best_ask: OrderPrice },
+    #[error("level quantity does not add up (price={}, quantity={}, resting={})", .price, .quantity, .resting)]
+    LevelMismatch {
+        price: OrderPrice,
+        quantity: OrderQuantity,
+        resting: OrderQuantity,
+    },
+    #[error("resting order with nothing remaining! {0}")]
+    NonPositiveRemaining(OrderId),
+    #[error("empty trade! {0:?}")]
+    EmptyTrade(TradeId),
+    #[error("fills do not add up (order_id={}, quantity={}, traded={}, remaining={})", .order_id, .quantity, .traded, .remaining)]
+    FillMismatch {
+        order_id: OrderId,
+        quantity: OrderQuantity,
+        traded: OrderQuantity,
+        remaining: OrderQuantity,
+    },
+}
+
+// a clock that drives the engine it is given to: moving the time ticks the engine (see Engine::tick) so that expirations,
+// timeouts and session transitions happen deterministically, without sleeping. The ticks run every subsystem at the
+// time travelled to; clones share the same time like the ManualClock, give one to every component timed by the engine
+// (calendar, dead man's switch, ...) so that they agree with it between the ticks too
+#[derive(Clone, Debug, Default)]
+pub struct TestClock(ManualClock);
+
+impl TestClock {
+    pub fn new(now: Timestamp) -> Self {
+        Self(ManualClock::new(now))
+    }
+
+    // moves the time without ticking, e.g. to set up the engine
+    #[inline]
+    pub fn set(&self, now: Timestamp) {
+        self.0.set(now);
+    }
+
+    #[inline]
+    pub fn advance(&self, engine: &mut Engine, elapsed: u64) -> Result<Tick, EngineError> {
+        self.travel_to(engine, self.now() + elapsed)
+    }
+
+    // forward only, the engine never sees the time going back
+    pub fn travel_to(&self, engine: &mut Engine, to: Timestamp) -> Result<Tick, EngineError> {
+        assert!(
+            to >= self.now(),
+            "travelling back in time (now={}, to={to})",
+            self.now()
+        );
+        self.0.set(to);
+        engine.tick(to)
+    }
+
+    // ticks every step on the way and at `to`, so that what falls in between happens in its own tick and in order
+    // (e.g. the opening uncross before the orders of the day expire), returns the ticks that did something
+    pub fn travel_by_steps(&self, engine: &mut Engine, to: Timestamp, step: u64) -> Result<Vec<Tick>, EngineError> {
+        assert!(step > 0, "travelling by empty steps");
+        let mut ticks = vec![];
+        while self.now() < to {
+            let tick = self.travel_to(engine, (self.now() + step).min(to))?;
+            if !tick.is_idle() {
+                ticks.push(tick);
+            }
+        }
+        Ok(ticks)
+    }
+
+    // straight to the next good-til-date expiry of the engine, None when there is none left
+    pub fn travel_to_next_expiry(&self, engine: &mut Engine) -> Result<Option<Tick>, EngineError> {
+        let Some(expires_at) = engine.next_expiry() else {
+            return Ok(None);
+        };
+        self.travel_to(engine, expires_at.max(self.now())).map(Some)
+    }
+}
+
+impl Clock for TestClock {
+    #[inline]
+    fn now(&self) -> Timestamp {
+        self.0.now()
+    }
+}
+
+// generators of random order requests, around 100 with a 0.5 tick so that levels get shared
+pub fn time_in_force() -> impl Strategy<Value = Option<TimeInForce>> {
+    prop_oneof![
+        4 => Just(None),
+        1 => any::<bool>().prop_map(|post_only| Some(TimeInForce::GoodTilCancel { post_only })),
+        1 => any::<bool>().prop_map(|fill_or_kill| Some(TimeInForce::ImmediateOrCancel { fill_or_kill })),
+    ]
+}
+
+pub fn create(order_id: u64) -> impl Strategy<Value = OrderRequest> {
+    (
+        1..=5u8,
+        prop_oneof![Just(OrderSide::Bid), Just(OrderSide::Ask)],
+        prop::option::weighted(0.9, 180..=220u64),
+        1..=100u32,
+        time_in_force(),
+    )
+        .prop_map(
+            move |(account, side, ticks, quantity, time_in_force)| OrderRequest::Create {
+                account_id: format_compact!("{account}"),
+                order_id,
+                pair: DEFAULT_SYMBOL,
+                side,
+                limit_price: ticks.map(|ticks| OrderPrice::new(ticks * 5, 1)),
+                quantity: quantity.into(),
+                time_in_force,
+                short_sell: false,
+                priority_fee: Decimal::ZERO,
+                client_order_id: None,
+                price_protection: None,
+                reduce_only: false,
+            },
+        )
+}
+
+// order ids follow the position in the sequence, cancels target one of the orders sent before them
+pub fn order_requests(max_len: usize) -> impl Strategy<Value = Vec<OrderRequest>> {
+    prop::collection::vec((create(0), prop::option::weighted(0.2, any::<Index>())), 1..=max_len).prop_map(|requests| {
+        requests
+            .into_iter()
+            .enumerate()
+            .map(|(position, (mut create, cancel))| match cancel {
+                Some(index) if position > 0 => OrderRequest::Cancel {
+                    account_id: Default::default(),
+                    order_id: index.index(position) as u64 + 1,
+                },
+                _ => {
+                    if let OrderRequest::Create { order_id, .. } = &mut create {
+                        *order_id = position as u64 + 1;
+                    }
+                    create
+                }
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::proptest;
+
+    use super::*;
+    use crate::{
+        calendar::{SessionState, TradingCalendar},
+        clock::{DAY, HOUR, MINUTE},
+        order::util,
+    };
+
+    proptest! {
+        #[test]
+        fn random_requests_keep_the_invariants(order_requests in order_requests(200)) {
+            let mut engine = Engine::new(DEFAULT_SYMBOL);
+            let mut checker = InvariantChecker::default();
+            for order_request in order_requests {
+                let checked = checker.process(&mut engine, order_request);
+                prop_assert!(checked.is_ok(), "{checked:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn travel_through_a_trading_day() {
+        let day = |order_id: u64, side: OrderSide| {
+            util::create(order_id, side)
+                .limit_price(Some(100.into()))
+                .time_in_force(Some(TimeInForce::Day { post_only: false }))
+                .build()
+        };
+        let clock = TestClock::new(7 * HOUR + 30 * MINUTE);
+        let calendar = TradingCalendar::new(8 * HOUR, 16 * HOUR)
+            .unwrap()
+            .with_pre_open(HOUR)
+            .unwrap();
+        let mut engine = Engine::new(DEFAULT_SYMBOL).with_calendar(calendar, clock.clone());
+        engine.process(day(1, OrderSide::Bid)).unwrap();
+        engine.process(day(2, OrderSide::Ask)).unwrap();
+        engine.process(day(3, OrderSide::Ask)).unwrap();
+        assert_eq!(engine.orderbook().trade_count(), 0);
+
+        // the opening uncross, then the close where the day orders left expire
+        let ticks = clock.travel_by_steps(&mut engine, DAY, MINUTE).unwrap();
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[0].session, Some(SessionState::PreOpen));
+        assert_eq!(engine.orderbook().trade_count(), 1);
+        assert_eq!(ticks[1].session, Some(SessionState::Open));
+        assert_eq!(ticks[1].expired, vec![3]);
+        assert_eq!(engine.session(), SessionState::Closed);
+
+        assert!(clock.travel_to_next_expiry(&mut engine).unwrap().is_none());
+        assert!(clock.advance(&mut engine, HOUR).unwrap().is_idle());
+        assert_eq!(clock.now(), DAY + HOUR);
+    }
+
+    #[test]
+    fn catch_crossed_books() {
+        let mut engine = Engine::new(DEFAULT_SYMBOL);
+        engine.start_auction().unwrap();
+        let mut checker = InvariantChecker::default();
+        for (order_id, side) in [(1, OrderSide::Bid), (2, OrderSide::Ask)] {
+            let order_request = util::create(order_id, side).limit_price(Some(100.into())).build();
+            assert!(matches!(checker.process(&mut engine, order_request), Ok(Ok(()))));
+        }
+        assert_eq!(
+            check_book(engine.orderbook()),
+            Err(InvariantViolation::CrossedBook {
+                best_bid: 100.into(),
+                best_ask: 100.into()
+            })
+        );
+    }
+}
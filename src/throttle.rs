@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{Timestamp, SECOND};
+
+// messages per second on average, up to burst at once, then up to queue more are smoothed out (delayed) rather than
+// refused
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ThrottleConfig {
+    pub rate: u64,
+    pub burst: u64,
+    #[serde(default)]
+    pub queue: u64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "admission")]
+pub enum Admission {
+    Now,
+    Delayed { release_at: Timestamp },
+    Throttled { resumes_at: Timestamp }, // the message is dropped, capacity is back from then on
+}
+
+// token bucket of one connection, as a virtual scheduling clock (GCRA): each message pushes the theoretical arrival
+// time one emission interval further, the burst is how far ahead of the wall clock it may go
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    interval: u64,  // micros between two messages at the average rate
+    tolerance: u64, // micros ahead of the clock a burst may take
+    max_delay: u64, // micros a smoothed message may wait
+    theoretical_arrival: u64,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        let interval = SECOND * 1_000 / config.rate.max(1);
+        Self {
+            interval,
+            tolerance: interval * config.burst.saturating_sub(1),
+            max_delay: interval * config.queue,
+            theoretical_arrival: 0,
+        }
+    }
+
+    pub fn admit(&mut self, now: Timestamp) -> Admission {
+        let now = now * 1_000;
+        let arrival = self.theoretical_arrival.max(now);
+        let allowed_at = arrival.saturating_sub(self.tolerance);
+        if allowed_at <= now {
+            self.theoretical_arrival = arrival + self.interval;
+            return Admission::Now;
+        }
+
+        if allowed_at - now <= self.max_delay {
+            self.theoretical_arrival = arrival + self.interval;
+            return Admission::Delayed {
+                release_at: allowed_at.div_ceil(1_000),
+            };
+        }
+        // the queue is full until its first message is released
+        Admission::Throttled {
+            resumes_at: (allowed_at - self.max_delay).div_ceil(1_000),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    fn burst_then_smooth_then_throttle() {
+        // 10 per second: 100ms apart, 3 at once, 2 more delayed
+        let mut throttle = Throttle::new(ThrottleConfig {
+            rate: 10,
+            burst: 3,
+            queue: 2,
+        });
+        for _ in 0..3 {
+            assert_eq!(throttle.admit(1_000), Admission::Now);
+        }
+        assert_eq!(throttle.admit(1_000), Admission::Delayed { release_at: 1_100 });
+        assert_eq!(throttle.admit(1_000), Admission::Delayed { release_at: 1_200 });
+        assert_eq!(throttle.admit(1_050), Admission::Throttled { resumes_at: 1_100 });
+
+        // capacity comes back at the average rate
+        assert_eq!(throttle.admit(1_100), Admission::Delayed { release_at: 1_300 });
+        assert_eq!(throttle.admit(2_000), Admission::Now);
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{Timestamp, DAY},
+    order::{OrderPrice, OrderQuantity},
+};
+
+// the market over the rolling window as of the time asked, the last price is the last one whenever it traded
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Ticker {
+    pub last_price: Option<OrderPrice>,
+    pub best_bid: Option<OrderPrice>,
+    pub best_ask: Option<OrderPrice>,
+    pub volume: OrderQuantity,
+    pub notional: Decimal,
+    pub vwap: Option<OrderPrice>,
+    pub high: Option<OrderPrice>,
+    pub low: Option<OrderPrice>,
+    pub trades: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Print {
+    at: Timestamp,
+    price: OrderPrice,
+    quantity: OrderQuantity,
+}
+
+// rolling statistics of the trades, the ones older than the window are evicted as time goes: the sums go down with
+// them and the high and low come from monotonic queues (each print is in them until a better one or its eviction)
+#[derive(Clone, Debug)]
+pub struct TickerStats {
+    window: Timestamp,
+    prints: VecDeque<Print>,
+    volume: OrderQuantity, // of the prints in the window
+    notional: Decimal,
+    highs: VecDeque<Print>, // decreasing prices
+    lows: VecDeque<Print>,  // increasing prices
+    last_price: Option<OrderPrice>,
+}
+
+impl TickerStats {
+    pub fn new(window: Timestamp) -> Self {
+        Self {
+            window,
+            prints: VecDeque::new(),
+            volume: OrderQuantity::ZERO,
+            notional: Decimal::ZERO,
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+            last_price: None,
+        }
+    }
+
+    #[inline]
+    pub fn window(&self) -> Timestamp {
+        self.window
+    }
+
+    pub fn record(&mut self, at: Timestamp, price: OrderPrice, quantity: OrderQuantity) {
+        self.evict(at);
+        let print = Print { at, price, quantity };
+        self.prints.push_back(print);
+        self.volume += quantity;
+        self.notional += price * quantity;
+        self.push_extremes(print);
+        self.last_price = Some(price);
+    }
+
+    // the print of a busted trade is taken back (the latest one with its price and quantity if several), the queues are
+    // rebuilt from the prints left and the last price goes back to the print before (if still in the window) when it
+    // was the last one
+    pub fn bust(&mut self, price: OrderPrice, quantity: OrderQuantity) {
+        let Some(index) = self
+            .prints
+            .iter()
+            .rposition(|print| print.price == price && print.quantity == quantity)
+        else {
+            return;
+        };
+        self.prints.remove(index);
+        self.volume -= quantity;
+        self.notional -= price * quantity;
+        if index == self.prints.len() {
+            self.last_price = self.prints.back().map(|print| print.price);
+        }
+        self.highs.clear();
+        self.lows.clear();
+        for index in 0..self.prints.len() {
+            self.push_extremes(self.prints[index]);
+        }
+    }
+
+    pub fn evict(&mut self, now: Timestamp) {
+        let window = self.window;
+        let expired = |print: &mut Print| print.at + window <= now;
+        while let Some(print) = self.prints.pop_front_if(expired) {
+            self.volume -= print.quantity;
+            self.notional -= print.price * print.quantity;
+        }
+        // the queues are in time order too
+        while self.highs.pop_front_if(expired).is_some() {}
+        while self.lows.pop_front_if(expired).is_some() {}
+    }
+
+    // the prints gone out of the window since the last trade are left out without being evicted
+    pub fn ticker(&self, now: Timestamp, best_bid: Option<OrderPrice>, best_ask: Option<OrderPrice>) -> Ticker {
+        let expired = self.prints.iter().take_while(|print| !self.in_window(print, now));
+        let (mut volume, mut notional, mut trades) = (self.volume, self.notional, self.prints.len() as u64);
+        for print in expired {
+            volume -= print.quantity;
+            notional -= print.price * print.quantity;
+            trades -= 1;
+        }
+        let first = |prints: &VecDeque<Print>| {
+            prints
+                .iter()
+                .find(|print| self.in_window(print, now))
+                .map(|print| print.price)
+        };
+        Ticker {
+            last_price: self.last_price,
+            best_bid,
+            best_ask,
+            volume,
+            notional,
+            vwap: (!volume.is_zero()).then(|| OrderPrice::from_decimal(notional / volume)),
+            high: first(&self.highs),
+            low: first(&self.lows),
+            trades,
+        }
+    }
+
+    // a print drops the ones it beats from the back of the queues
+    fn push_extremes(&mut self, print: Print) {
+        while self.highs.back().is_some_and(|high| high.price <= print.price) {
+            self.highs.pop_back();
+        }
+        self.highs.push_back(print);
+        while self.lows.back().is_some_and(|low| low.price >= print.price) {
+            self.lows.pop_back();
+        }
+        self.lows.push_back(print);
+    }
+
+    #[inline]
+    fn in_window(&self, print: &Print, now: Timestamp) -> bool {
+        print.at + self.window > now
+    }
+}
+
+impl Default for TickerStats {
+    fn default() -> Self {
+        Self::new(DAY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::clock::HOUR;
+
+    #[rstest]
+    fn roll_the_window() {
+        let mut stats = TickerStats::new(2 * HOUR);
+        stats.record(0, 20.into(), 1.into());
+        stats.record(HOUR, 10.into(), 2.into());
+        stats.record(HOUR + 1, 15.into(), 1.into());
+
+        let ticker = stats.ticker(HOUR + 1, Some(14.into()), Some(16.into()));
+        assert_eq!((ticker.high, ticker.low), (Some(20.into()), Some(10.into())));
+        assert_eq!((ticker.volume, ticker.trades), (4.into(), 3));
+        assert_eq!(ticker.vwap, Some(OrderPrice::new(1375, 2)));
+
+        // the first trade is out of the window, with or without a trade since
+        let ticker = stats.ticker(2 * HOUR, None, None);
+        assert_eq!((ticker.high, ticker.low), (Some(15.into()), Some(10.into())));
+        assert_eq!((ticker.volume, ticker.trades), (3.into(), 2));
+        stats.record(2 * HOUR, 12.into(), 1.into());
+        assert_eq!(stats.ticker(2 * HOUR, None, None).high, Some(15.into()));
+
+        let ticker = stats.ticker(10 * HOUR, None, None);
+        assert_eq!(ticker.last_price, Some(12.into()));
+        assert_eq!((ticker.high, ticker.vwap, ticker.trades), (None, None, 0));
+    }
+
+    #[rstest]
+    fn bust_a_print() {
+        let mut stats = TickerStats::new(2 * HOUR);
+        stats.record(0, 20.into(), 1.into());
+        stats.record(HOUR, 10.into(), 2.into());
+        stats.record(HOUR + 1, 15.into(), 1.into());
+
+        stats.bust(20.into(), 1.into());
+        let ticker = stats.ticker(HOUR + 1, None, None);
+        assert_eq!((ticker.high, ticker.low), (Some(15.into()), Some(10.into())));
+        assert_eq!(
+            (ticker.volume, ticker.trades, ticker.last_price),
+            (3.into(), 2, Some(15.into()))
+        );
+
+        // the last one, the price goes back to the one before
+        stats.bust(15.into(), 1.into());
+        let ticker = stats.ticker(HOUR + 1, None, None);
+        assert_eq!((ticker.high, ticker.last_price), (Some(10.into()), Some(10.into())));
+        stats.bust(15.into(), 1.into());
+        assert_eq!(stats.ticker(HOUR + 1, None, None).trades, 1);
+    }
+}
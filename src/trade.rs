@@ -4,10 +4,12 @@ use std::{
 };
 
 use anyhow::Result;
+use compact_str::CompactString;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::order::{Order, OrderError, OrderId, OrderPrice, OrderQuantity};
+use crate::order::{Order, OrderError, OrderId, OrderPrice, OrderQuantity, OrderSide};
 
 #[derive(Clone, Copy, Debug, Hash, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TradeId(u64);
@@ -17,6 +19,13 @@ impl TradeId {
     pub fn new(trade_id: u64) -> Self {
         Self(trade_id)
     }
+
+    // trades from the book and off-book trades share the sequence
+    #[inline]
+    fn next() -> Self {
+        static TRADE_ID_GENERATOR: AtomicU64 = AtomicU64::new(0);
+        Self(TRADE_ID_GENERATOR.fetch_add(1, Relaxed))
+    }
 }
 
 impl From<u64> for TradeId {
@@ -25,13 +34,30 @@ impl From<u64> for TradeId {
     }
 }
 
+impl From<TradeId> for u64 {
+    fn from(value: TradeId) -> u64 {
+        value.0
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Trade {
     id: TradeId,
     taker: OrderId,
     maker: OrderId,
+    side: OrderSide, // of the taker
     price: OrderPrice,
     quantity: OrderQuantity,
+    #[serde(default)]
+    maker_fee: Decimal,
+    #[serde(default)]
+    taker_fee: Decimal,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    short_sell: bool, // the seller sold short
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    odd_lot: bool, // below the round lot of the instrument
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    busted: bool, // cancelled after review
 }
 
 impl Trade {
@@ -40,19 +66,32 @@ impl Trade {
         let price = maker
             .limit_price()
             .ok_or(TradeError::MakerWithoutLimitPrice(maker.id()))?;
+        Self::at_price(taker, maker, traded, price)
+    }
 
+    // auctions trade every order at the uncross price instead of the price of the maker
+    #[inline]
+    pub fn at_price(
+        taker: &mut Order,
+        maker: &mut Order,
+        traded: OrderQuantity,
+        price: OrderPrice,
+    ) -> Result<Trade, TradeError> {
         taker.fill(traded).map_err(TradeError::OrderError)?;
         maker.fill(traded).map_err(TradeError::OrderError)?;
 
-        static TRADE_ID_GENERATOR: AtomicU64 = AtomicU64::new(0);
-        let trade_id = TRADE_ID_GENERATOR.fetch_add(1, Relaxed);
-
         Ok(Trade {
-            id: trade_id.into(),
+            id: TradeId::next(),
             taker: taker.id(),
             maker: maker.id(),
+            side: taker.side(),
             price,
             quantity: traded,
+            maker_fee: Decimal::ZERO,
+            taker_fee: Decimal::ZERO,
+            short_sell: taker.is_short_sell() || maker.is_short_sell(),
+            odd_lot: false,
+            busted: false,
         })
     }
 
@@ -61,10 +100,78 @@ impl Trade {
         self.id
     }
 
+    #[inline]
+    pub fn taker(&self) -> OrderId {
+        self.taker
+    }
+
+    #[inline]
+    pub fn maker(&self) -> OrderId {
+        self.maker
+    }
+
+    #[inline]
+    pub fn side(&self) -> OrderSide {
+        self.side
+    }
+
     #[inline]
     pub fn price(&self) -> OrderPrice {
         self.price
     }
+
+    #[inline]
+    pub fn quantity(&self) -> OrderQuantity {
+        self.quantity
+    }
+
+    #[inline]
+    pub fn notional(&self) -> Decimal {
+        self.quantity * self.price
+    }
+
+    #[inline]
+    pub fn is_short_sell(&self) -> bool {
+        self.short_sell
+    }
+
+    #[inline]
+    pub fn is_odd_lot(&self) -> bool {
+        self.odd_lot
+    }
+
+    #[inline]
+    pub fn is_busted(&self) -> bool {
+        self.busted
+    }
+
+    #[inline]
+    pub fn maker_fee(&self) -> Decimal {
+        self.maker_fee
+    }
+
+    #[inline]
+    pub fn taker_fee(&self) -> Decimal {
+        self.taker_fee
+    }
+
+    // fees are set by the engine once it knows the accounts of both sides
+    #[inline]
+    pub(crate) fn set_fees(&mut self, maker_fee: Decimal, taker_fee: Decimal) {
+        self.maker_fee = maker_fee;
+        self.taker_fee = taker_fee;
+    }
+
+    // so are the lot rules of the instrument
+    #[inline]
+    pub(crate) fn set_odd_lot(&mut self, odd_lot: bool) {
+        self.odd_lot = odd_lot;
+    }
+
+    #[inline]
+    pub(crate) fn bust(&mut self) {
+        self.busted = true;
+    }
 }
 
 impl Display for Trade {
@@ -77,6 +184,49 @@ impl Display for Trade {
     }
 }
 
+// privately negotiated between two accounts and reported to the venue, it never goes through the book
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OffBookTrade {
+    id: TradeId,
+    pub buyer: CompactString,
+    pub seller: CompactString,
+    pub price: OrderPrice,
+    pub quantity: OrderQuantity,
+}
+
+impl OffBookTrade {
+    #[inline]
+    pub fn new(buyer: &str, seller: &str, price: OrderPrice, quantity: OrderQuantity) -> Self {
+        Self {
+            id: TradeId::next(),
+            buyer: buyer.into(),
+            seller: seller.into(),
+            price,
+            quantity,
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> TradeId {
+        self.id
+    }
+
+    #[inline]
+    pub fn notional(&self) -> Decimal {
+        self.quantity * self.price
+    }
+}
+
+impl Display for OffBookTrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "OFFBOOK[{:?}] [buyer:{}|seller:{}] {}@{}",
+            self.id, self.buyer, self.seller, self.quantity, self.price
+        )
+    }
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum TradeError {
     #[error("maker should be a limit order, always with a limit price! {0}")]
@@ -0,0 +1,470 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+    time::Duration,
+};
+
+use compact_str::{format_compact, CompactString};
+use futures_util::{SinkExt, StreamExt};
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, error::TryRecvError},
+        oneshot,
+    },
+};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        self,
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        Message,
+    },
+    WebSocketStream,
+};
+use tracing::{info, warn};
+
+use crate::{
+    arbiter::Arbiter,
+    clock::{Clock, SystemClock, Timestamp},
+    engine::Engine,
+    gateway::Gateway,
+    market_data::{Granularity, MarketDataEvent, VerbosityConfig},
+    order::OrderRequest,
+    symbol::Symbol,
+    throttle::{Admission, Throttle, ThrottleConfig},
+};
+
+pub const ORDERS_PATH: &str = "/orders";
+pub const MARKET_DATA_PATH: &str = "/market-data";
+
+// slow market data consumers beyond this are disconnected, they have to resync from a fresh connection
+const EVENTS_CAPACITY: usize = 65_536;
+
+type Ack = oneshot::Sender<Reply>;
+
+// what the order connections send to the engines, each one as a session of its own
+#[derive(Debug)]
+enum Inbound {
+    Request {
+        session_id: u64,
+        order_request: Box<OrderRequest>,
+        ack: Ack,
+    },
+    Closed {
+        session_id: u64,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "status")]
+pub enum Reply {
+    Accepted,
+    Rejected { reason: CompactString },
+    Throttled { resumes_at: Timestamp }, // the request was dropped, send it again from then on
+    Subscribed { pair: Symbol },
+    Unsubscribed { pair: Symbol },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE", tag = "action")]
+pub enum Subscription {
+    Subscribe { pair: Symbol },
+    Unsubscribe { pair: Symbol },
+}
+
+#[derive(Serialize)]
+struct PairEvent<'a> {
+    pair: &'a str,
+    #[serde(flatten)]
+    event: &'a MarketDataEvent,
+}
+
+// serialized once, shared by every connection subscribed to the pair
+#[derive(Debug)]
+struct Published {
+    pair: Symbol,
+    json: String,
+}
+
+// order requests on ORDERS_PATH, trades and incremental book updates (by pair) on MARKET_DATA_PATH
+pub struct WebSocketServer {
+    listener: TcpListener,
+    requests: mpsc::UnboundedSender<Inbound>,
+    events: broadcast::Sender<Arc<Published>>,
+    throttle: Option<ThrottleConfig>,
+    sessions: AtomicU64,
+}
+
+impl WebSocketServer {
+    pub async fn bind(addr: impl ToSocketAddrs, pairs: &[Symbol]) -> Result<Self, ServerError> {
+        Self::bind_with_verbosity(addr, pairs, &VerbosityConfig::default()).await
+    }
+
+    pub async fn bind_with_verbosity(
+        addr: impl ToSocketAddrs,
+        pairs: &[Symbol],
+        verbosity: &VerbosityConfig,
+    ) -> Result<Self, ServerError> {
+        let listener = TcpListener::bind(addr).await?;
+        let (requests, mut rx) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        let engines = pairs
+            .iter()
+            .map(|pair| Engine::new(pair.clone()).with_verbosity(verbosity.of(pair.as_str())));
+        let publisher = events.clone();
+        let mut arbiter = Arbiter::default();
+        Gateway::new(engines, Granularity::Order).spawn(
+            move || next_request(&mut arbiter, &mut rx),
+            move |gateway, (order_request, ack): (OrderRequest, Ack)| {
+                let _ = ack.send(dispatch(gateway, &publisher, order_request));
+            },
+        );
+
+        Ok(Self {
+            listener,
+            requests,
+            events,
+            throttle: None,
+            sessions: AtomicU64::new(0),
+        })
+    }
+
+    // every order connection gets a throttle of its own, whatever the accounts it sends requests for
+    pub fn with_throttle(mut self, throttle: ThrottleConfig) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Result<SocketAddr, ServerError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn run(self) -> Result<(), ServerError> {
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            let requests = self.requests.clone();
+            let events = self.events.subscribe();
+            let throttle = self.throttle.map(Throttle::new);
+            let session_id = self.sessions.fetch_add(1, Relaxed) + 1;
+            tokio::spawn(async move {
+                match handle(stream, session_id, requests, events, throttle).await {
+                    Ok(()) => info!("WebSocket connection closed: {peer}"),
+                    Err(error) => warn!("WebSocket connection closed with error: {peer} {error}"),
+                }
+            });
+        }
+    }
+}
+
+// the requests of the connections are taken in turns rather than in arrival order (see Arbiter)
+fn next_request(
+    arbiter: &mut Arbiter<(OrderRequest, Ack)>,
+    requests: &mut mpsc::UnboundedReceiver<Inbound>,
+) -> Option<(OrderRequest, Ack)> {
+    loop {
+        if arbiter.is_empty() {
+            arbitrate(arbiter, requests.blocking_recv()?);
+        }
+        loop {
+            match requests.try_recv() {
+                Ok(inbound) => arbitrate(arbiter, inbound),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) if arbiter.is_empty() => return None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        if let Some((_, request)) = arbiter.pop(SystemClock.now()) {
+            return Some(request);
+        }
+    }
+}
+
+fn arbitrate(arbiter: &mut Arbiter<(OrderRequest, Ack)>, inbound: Inbound) {
+    match inbound {
+        Inbound::Request {
+            session_id,
+            order_request,
+            ack,
+        } => arbiter.push(session_id, SystemClock.now(), (*order_request, ack)),
+        Inbound::Closed { session_id } => {
+            if let Some((_, latency)) = arbiter.remove(session_id) {
+                info!(
+                    "session {session_id} closed: {} requests, queued {}ms on average, {}ms at most",
+                    latency.messages,
+                    latency.mean(),
+                    latency.max
+                );
+            }
+        }
+    }
+}
+
+fn dispatch(gateway: &mut Gateway, events: &broadcast::Sender<Arc<Published>>, order_request: OrderRequest) -> Reply {
+    let result = gateway.process(order_request, |pair, event| {
+        let json = serde_json::to_string(&PairEvent {
+            pair: pair.as_str(),
+            event: &event,
+        })
+        .unwrap();
+        // no market data connection at all is fine
+        let _ = events.send(Arc::new(Published {
+            pair: pair.clone(),
+            json,
+        }));
+    });
+    match result {
+        Ok(()) => Reply::Accepted,
+        Err(error) => Reply::Rejected {
+            reason: format_compact!("{error}"),
+        },
+    }
+}
+
+// the handshake callback returns a whole http response as error, that is tungstenite's signature
+#[allow(clippy::result_large_err)]
+async fn handle(
+    stream: TcpStream,
+    session_id: u64,
+    requests: mpsc::UnboundedSender<Inbound>,
+    events: broadcast::Receiver<Arc<Published>>,
+    throttle: Option<Throttle>,
+) -> Result<(), ServerError> {
+    let mut path = String::new();
+    let socket = accept_hdr_async(stream, |request: &Request, response: Response| {
+        path = request.uri().path().to_owned();
+        if path == ORDERS_PATH || path == MARKET_DATA_PATH {
+            Ok(response)
+        } else {
+            let mut not_found = ErrorResponse::new(Some(format!("unknown endpoint! {path}")));
+            *not_found.status_mut() = StatusCode::NOT_FOUND;
+            Err(not_found)
+        }
+    })
+    .await?;
+
+    if path == ORDERS_PATH {
+        let result = orders(socket, session_id, &requests, throttle).await;
+        let _ = requests.send(Inbound::Closed { session_id });
+        result
+    } else {
+        market_data(socket, events).await
+    }
+}
+
+// one reply per order request, in the same order: requests over the burst of the throttle wait for their turn (which
+// holds the following ones back too), the ones beyond its queue are dropped
+async fn orders(
+    mut socket: WebSocketStream<TcpStream>,
+    session_id: u64,
+    requests: &mpsc::UnboundedSender<Inbound>,
+    mut throttle: Option<Throttle>,
+) -> Result<(), ServerError> {
+    while let Some(message) = socket.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let now = SystemClock.now();
+        let admission = throttle.as_mut().map_or(Admission::Now, |throttle| throttle.admit(now));
+        let reply = match (serde_json::from_str::<OrderRequest>(&text), admission) {
+            (_, Admission::Throttled { resumes_at }) => Reply::Throttled { resumes_at },
+            (Ok(order_request), admission) => {
+                if let Admission::Delayed { release_at } = admission {
+                    tokio::time::sleep(Duration::from_millis(release_at.saturating_sub(now))).await;
+                }
+                let (ack, reply) = oneshot::channel();
+                requests
+                    .send(Inbound::Request {
+                        session_id,
+                        order_request: Box::new(order_request),
+                        ack,
+                    })
+                    .map_err(|_| ServerError::EngineStopped)?;
+                reply.await.map_err(|_| ServerError::EngineStopped)?
+            }
+            (Err(error), _) => Reply::Rejected {
+                reason: format_compact!("{error}"),
+            },
+        };
+        socket
+            .send(Message::Text(serde_json::to_string(&reply).unwrap()))
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn market_data(
+    mut socket: WebSocketStream<TcpStream>,
+    mut events: broadcast::Receiver<Arc<Published>>,
+) -> Result<(), ServerError> {
+    let mut pairs: IndexSet<Symbol> = IndexSet::new();
+    loop {
+        tokio::select! {
+            message = socket.next() => {
+                let text = match message.transpose()? {
+                    Some(Message::Text(text)) => text,
+                    Some(Message::Close(_)) | None => break,
+                    Some(_) => continue,
+                };
+
+                let reply = match serde_json::from_str::<Subscription>(&text) {
+                    Ok(Subscription::Subscribe { pair }) => {
+                        pairs.insert(pair.clone());
+                        Reply::Subscribed { pair }
+                    }
+                    Ok(Subscription::Unsubscribe { pair }) => {
+                        pairs.shift_remove(&pair);
+                        Reply::Unsubscribed { pair }
+                    }
+                    Err(error) => Reply::Rejected {
+                        reason: format_compact!("{error}"),
+                    },
+                };
+                socket.send(Message::Text(serde_json::to_string(&reply).unwrap())).await?;
+            }
+            event = events.recv() => match event {
+                Ok(published) if pairs.contains(&published.pair) => {
+                    socket.send(Message::Text(published.json.clone())).await?;
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => return Err(ServerError::Lagged(skipped)),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("server io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("websocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    #[error("market data connection too slow! skipped:{0}")]
+    Lagged(u64),
+    #[error("engine stopped!")]
+    EngineStopped,
+}
+
+impl From<tungstenite::Error> for ServerError {
+    fn from(error: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tokio_tungstenite::{connect_async, MaybeTlsStream};
+
+    use super::*;
+    use crate::order::{
+        util::{self, DEFAULT_PAIR, DEFAULT_SYMBOL},
+        OrderSide,
+    };
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn connect(addr: SocketAddr, path: &str) -> Client {
+        connect_async(format!("ws://{addr}{path}")).await.unwrap().0
+    }
+
+    async fn send<T: Serialize>(client: &mut Client, message: &T) -> serde_json::Value {
+        let text = serde_json::to_string(message).unwrap();
+        client.send(Message::Text(text)).await.unwrap();
+        receive(client).await
+    }
+
+    async fn receive(client: &mut Client) -> serde_json::Value {
+        match client.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("unexpected message {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn orders_and_market_data() {
+        let server = WebSocketServer::bind("127.0.0.1:0", &[DEFAULT_SYMBOL]).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let mut market_data = connect(addr, MARKET_DATA_PATH).await;
+        let subscribe = Subscription::Subscribe { pair: DEFAULT_SYMBOL };
+        let invalid = serde_json::json!({ "action": "SUBSCRIBE", "pair": "ETHUSDT" });
+        assert_eq!(send(&mut market_data, &invalid).await["status"], "REJECTED");
+        assert_eq!(send(&mut market_data, &subscribe).await["status"], "SUBSCRIBED");
+
+        // an order for an unknown pair is rejected, then a trade on the known one
+        let mut orders = connect(addr, ORDERS_PATH).await;
+        let reply = send(&mut orders, &util::create_on(1, "XXX/YYY", OrderSide::Ask).build()).await;
+        assert_eq!(reply["status"], "REJECTED");
+        assert_eq!(
+            send(&mut orders, &util::create_on(2, DEFAULT_PAIR, OrderSide::Ask).build()).await["status"],
+            "ACCEPTED"
+        );
+        assert_eq!(
+            send(&mut orders, &util::create_on(3, DEFAULT_PAIR, OrderSide::Bid).build()).await["status"],
+            "ACCEPTED"
+        );
+
+        let events = [
+            receive(&mut market_data).await,
+            receive(&mut market_data).await,
+            receive(&mut market_data).await,
+        ];
+        assert_eq!(events[0]["pair"], DEFAULT_PAIR);
+        assert_eq!(events.map(|event| event["type"].clone()), ["ADD", "TRADE", "DELETE"]);
+
+        // unknown endpoints are refused during the handshake
+        assert!(connect_async(format!("ws://{addr}/unknown")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn throttle_connections() {
+        let server = WebSocketServer::bind("127.0.0.1:0", &[DEFAULT_SYMBOL])
+            .await
+            .unwrap()
+            .with_throttle(ThrottleConfig {
+                rate: 1,
+                burst: 2,
+                queue: 0,
+            });
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let mut orders = connect(addr, ORDERS_PATH).await;
+        for order_id in 1..=2 {
+            let reply = send(
+                &mut orders,
+                &util::create_on(order_id, DEFAULT_PAIR, OrderSide::Ask).build(),
+            )
+            .await;
+            assert_eq!(reply["status"], "ACCEPTED");
+        }
+        let reply = send(&mut orders, &util::create_on(3, DEFAULT_PAIR, OrderSide::Ask).build()).await;
+        assert_eq!(reply["status"], "THROTTLED");
+        assert!(reply["resumes_at"].as_u64().unwrap() > SystemClock.now());
+
+        // the other connections have a throttle of their own
+        let mut other = connect(addr, ORDERS_PATH).await;
+        let reply = send(&mut other, &util::create_on(4, DEFAULT_PAIR, OrderSide::Ask).build()).await;
+        assert_eq!(reply["status"], "ACCEPTED");
+    }
+}